@@ -1,5 +1,5 @@
 use bazaar::{
-    apis::{Api, Ftx},
+    apis::{Api, Binance},
     strategies::{Settings, Strategy},
     AnyError, Bazaar, Exchange, Position, Symbol,
 };
@@ -32,6 +32,12 @@ impl<const FAST: usize, const SLOW: usize> MaCrossoverStrategy<FAST, SLOW> {
 // This strategy is applicable for all APIs that allow futures trading.
 impl<A: Api, const FAST: usize, const SLOW: usize> Strategy<A> for MaCrossoverStrategy<FAST, SLOW> {
     const NAME: &'static str = "MA Crossover Strategy";
+    // The periods of the moving averages, recorded with the report.
+    type Parameters = (usize, usize);
+
+    fn parameters(&self) -> Option<(usize, usize)> {
+        Some((FAST, SLOW))
+    }
 
     // Inititalize the strategy.
     fn init(&mut self, exchange: &mut Exchange<A>) -> Result<Settings, AnyError> {
@@ -40,7 +46,7 @@ impl<A: Api, const FAST: usize, const SLOW: usize> Strategy<A> for MaCrossoverSt
 
         Ok(Settings {
             // We trade on the one minute interval.
-            interval: Duration::minutes(1),
+            interval: Duration::minutes(1).into(),
             ..Default::default()
         })
     }
@@ -79,19 +85,23 @@ impl<A: Api, const FAST: usize, const SLOW: usize> Strategy<A> for MaCrossoverSt
 
 #[tokio::main]
 async fn main() -> Result<(), AnyError> {
+    // Tracing events are emitted as log records, all levels are logged by default.
     simple_logger::SimpleLogger::new()
-        .with_level(log::LevelFilter::Trace)
         .with_utc_timestamps()
         .init()
         .unwrap();
 
-    Bazaar {
-        start_time: Utc.ymd(2022, 1, 10).and_hms(0, 0, 0),
+    let report = Bazaar {
+        start_time: Utc.with_ymd_and_hms(2022, 1, 10, 0, 0, 0).unwrap(),
         ..Default::default()
     }
     .run(
-        Ftx::from_env(),
+        Binance::from_env(),
         MaCrossoverStrategy::<20, 40>::new(Symbol::perp("BTC")),
     )
-    .await
+    .await?;
+
+    tracing::info!("{:?}", report);
+
+    Ok(())
 }
@@ -0,0 +1,97 @@
+-- The tables the monitor writes to. Tables created before the migrations existed are kept.
+
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM pg_type WHERE typname = 'side') THEN
+        CREATE TYPE side AS ENUM ('BUY', 'SELL');
+    END IF;
+END
+$$;
+
+CREATE TABLE IF NOT EXISTS sessions (
+    session_id UUID PRIMARY KEY,
+    name TEXT NOT NULL,
+    exchange TEXT NOT NULL,
+    live_trading BOOLEAN NOT NULL,
+    kind TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE TABLE IF NOT EXISTS backtest_parameters (
+    session_id UUID NOT NULL,
+    name TEXT NOT NULL,
+    value TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS backtests (
+    session_id UUID NOT NULL,
+    version TEXT,
+    library_version TEXT NOT NULL,
+    start_time TIMESTAMPTZ NOT NULL,
+    end_time TIMESTAMPTZ NOT NULL,
+    start_total NUMERIC NOT NULL,
+    end_total NUMERIC NOT NULL,
+    total_return NUMERIC NOT NULL,
+    max_drawdown NUMERIC NOT NULL,
+    steps BIGINT NOT NULL,
+    closed_positions BIGINT NOT NULL,
+    report JSONB NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS equities (
+    session_id UUID NOT NULL,
+    total NUMERIC NOT NULL,
+    asset TEXT NOT NULL,
+    time TIMESTAMPTZ NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS positions (
+    position_id UUID NOT NULL,
+    session_id UUID NOT NULL,
+    symbols TEXT NOT NULL,
+    value NUMERIC NOT NULL,
+    pnl NUMERIC NOT NULL,
+    mae NUMERIC NOT NULL,
+    mfe NUMERIC NOT NULL,
+    tags JSONB NOT NULL,
+    closed BOOLEAN NOT NULL,
+    time TIMESTAMPTZ NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS orders (
+    order_id UUID NOT NULL,
+    session_id UUID NOT NULL,
+    market TEXT NOT NULL,
+    side side NOT NULL,
+    ordered_size NUMERIC NOT NULL,
+    ordered_price NUMERIC NOT NULL,
+    ordered_time TIMESTAMPTZ NOT NULL,
+    executed_size NUMERIC,
+    executed_price NUMERIC,
+    executed_time TIMESTAMPTZ
+);
+
+CREATE TABLE IF NOT EXISTS execution_gaps (
+    order_id UUID NOT NULL,
+    session_id UUID NOT NULL,
+    expected_price NUMERIC NOT NULL,
+    executed_price NUMERIC NOT NULL,
+    gap NUMERIC NOT NULL,
+    time TIMESTAMPTZ NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS strategy_states (
+    name TEXT PRIMARY KEY,
+    session_id UUID NOT NULL,
+    state JSONB NOT NULL,
+    time TIMESTAMPTZ NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS commands (
+    command_id BIGSERIAL PRIMARY KEY,
+    session_id UUID NOT NULL,
+    command TEXT NOT NULL,
+    value NUMERIC,
+    consumed BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
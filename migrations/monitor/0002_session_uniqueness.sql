@@ -0,0 +1,19 @@
+-- Rows are unique per session, so that retried writes update rows instead of duplicating them,
+-- and rows of concurrent sessions can be read in order per session.
+
+ALTER TABLE positions ADD COLUMN IF NOT EXISTS legs JSONB NOT NULL DEFAULT '{}';
+
+CREATE UNIQUE INDEX IF NOT EXISTS sessions_session_id ON sessions (session_id);
+CREATE UNIQUE INDEX IF NOT EXISTS backtest_parameters_session_id_name
+    ON backtest_parameters (session_id, name);
+CREATE UNIQUE INDEX IF NOT EXISTS backtests_session_id ON backtests (session_id);
+CREATE UNIQUE INDEX IF NOT EXISTS equities_session_id_time ON equities (session_id, time);
+CREATE UNIQUE INDEX IF NOT EXISTS positions_position_id ON positions (position_id);
+CREATE INDEX IF NOT EXISTS positions_session_id_time ON positions (session_id, time);
+CREATE UNIQUE INDEX IF NOT EXISTS orders_session_id_order_id ON orders (session_id, order_id);
+CREATE INDEX IF NOT EXISTS orders_session_id_ordered_time ON orders (session_id, ordered_time);
+CREATE UNIQUE INDEX IF NOT EXISTS execution_gaps_session_id_order_id
+    ON execution_gaps (session_id, order_id);
+CREATE INDEX IF NOT EXISTS execution_gaps_session_id_time ON execution_gaps (session_id, time);
+CREATE UNIQUE INDEX IF NOT EXISTS strategy_states_name ON strategy_states (name);
+CREATE INDEX IF NOT EXISTS commands_session_id_pending ON commands (session_id) WHERE NOT consumed;
@@ -0,0 +1,6 @@
+-- Realized and unrealized profit and loss, next to their sum in pnl.
+
+ALTER TABLE positions ADD COLUMN IF NOT EXISTS realized_pnl NUMERIC NOT NULL DEFAULT 0;
+ALTER TABLE positions ADD COLUMN IF NOT EXISTS unrealized_pnl NUMERIC NOT NULL DEFAULT 0;
+ALTER TABLE backtests ADD COLUMN IF NOT EXISTS realized_pnl NUMERIC NOT NULL DEFAULT 0;
+ALTER TABLE backtests ADD COLUMN IF NOT EXISTS unrealized_pnl NUMERIC NOT NULL DEFAULT 0;
@@ -0,0 +1,3 @@
+-- The lifecycle status of orders, e.g. Submitted until their fill is confirmed.
+
+ALTER TABLE orders ADD COLUMN IF NOT EXISTS status TEXT;
@@ -0,0 +1,6 @@
+-- The drawdown from the highest total and the gross and net exposure of the open positions,
+-- logged with the equity.
+
+ALTER TABLE equities ADD COLUMN IF NOT EXISTS drawdown NUMERIC NOT NULL DEFAULT 0;
+ALTER TABLE equities ADD COLUMN IF NOT EXISTS exposure NUMERIC NOT NULL DEFAULT 0;
+ALTER TABLE equities ADD COLUMN IF NOT EXISTS net_exposure NUMERIC NOT NULL DEFAULT 0;
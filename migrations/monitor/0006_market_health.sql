@@ -0,0 +1,10 @@
+-- Whether the candles of the markets watched by a session are stale, updated when it changes.
+
+CREATE TABLE IF NOT EXISTS market_health (
+    session_id UUID NOT NULL,
+    market TEXT NOT NULL,
+    stale BOOLEAN NOT NULL,
+    last_candle TIMESTAMPTZ,
+    time TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (session_id, market)
+);
@@ -0,0 +1,9 @@
+-- Custom series logged by strategies, e.g. the strength of a signal, one row per name and time.
+
+CREATE TABLE IF NOT EXISTS metrics (
+    session_id UUID NOT NULL,
+    name TEXT NOT NULL,
+    value NUMERIC NOT NULL,
+    time TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (session_id, name, time)
+);
@@ -0,0 +1,3 @@
+-- The serialized parameters of the strategy, to compare sessions and run them again.
+
+ALTER TABLE sessions ADD COLUMN IF NOT EXISTS parameters JSONB;
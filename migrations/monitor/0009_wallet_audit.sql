@@ -0,0 +1,11 @@
+-- Balances the wallet of the venue held differently than booked by a session, one row per asset and time.
+
+CREATE TABLE IF NOT EXISTS wallet_audit (
+    session_id UUID NOT NULL,
+    asset TEXT NOT NULL,
+    local NUMERIC NOT NULL,
+    venue NUMERIC NOT NULL,
+    corrected BOOLEAN NOT NULL,
+    time TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (session_id, asset, time)
+);
@@ -0,0 +1,216 @@
+#[cfg(feature = "alerts")]
+mod webhook;
+
+#[cfg(feature = "alerts")]
+pub use webhook::*;
+
+use crate::Symbol;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::{fmt, sync::Arc};
+use thiserror::Error;
+use tokio::sync::{
+    mpsc::{unbounded_channel, UnboundedSender},
+    oneshot,
+};
+use uuid::Uuid;
+
+/// Pushes notifications about a running strategy, e.g. to a chat.
+#[async_trait]
+pub trait Alerter: Send + Sync {
+    async fn alert(&self, alert: &Alert) -> Result<(), AlertError>;
+}
+
+#[derive(Error, Debug)]
+pub enum AlertError {
+    #[error("Could not send the alert: {0}")]
+    Send(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Alert {
+    /// A position was opened.
+    Opened {
+        time: DateTime<Utc>,
+        position: Uuid,
+        symbols: Vec<Symbol>,
+        value: Decimal,
+    },
+    /// A position was closed.
+    Closed {
+        time: DateTime<Utc>,
+        position: Uuid,
+        symbols: Vec<Symbol>,
+        pnl: Decimal,
+    },
+    /// A stop, take profit or liquidation triggered the close of a position.
+    Triggered {
+        time: DateTime<Utc>,
+        position: Uuid,
+        reason: String,
+    },
+    /// The strategy failed.
+    Error { time: DateTime<Utc>, error: String },
+}
+
+impl Alert {
+    pub fn time(&self) -> DateTime<Utc> {
+        match self {
+            Alert::Opened { time, .. }
+            | Alert::Closed { time, .. }
+            | Alert::Triggered { time, .. }
+            | Alert::Error { time, .. } => *time,
+        }
+    }
+}
+
+impl fmt::Display for Alert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |symbols: &[Symbol]| {
+            symbols
+                .iter()
+                .map(|symbol| symbol.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match self {
+            Alert::Opened { symbols, value, .. } => {
+                write!(f, "Opened {} worth {}.", join(symbols), value.round_dp(2))
+            }
+            Alert::Closed { symbols, pnl, .. } => {
+                write!(f, "Closed {} with PnL {}.", join(symbols), pnl.round_dp(2))
+            }
+            Alert::Triggered {
+                position, reason, ..
+            } => write!(f, "Position {} triggered: {}.", position, reason),
+            Alert::Error { error, .. } => write!(f, "Strategy failed: {}", error),
+        }
+    }
+}
+
+/// Which alerts are sent, all by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AlertFilter {
+    pub opened: bool,
+    pub closed: bool,
+    pub triggered: bool,
+    pub errors: bool,
+}
+
+impl Default for AlertFilter {
+    fn default() -> Self {
+        AlertFilter {
+            opened: true,
+            closed: true,
+            triggered: true,
+            errors: true,
+        }
+    }
+}
+
+impl AlertFilter {
+    pub fn allows(&self, alert: &Alert) -> bool {
+        match alert {
+            Alert::Opened { .. } => self.opened,
+            Alert::Closed { .. } => self.closed,
+            Alert::Triggered { .. } => self.triggered,
+            Alert::Error { .. } => self.errors,
+        }
+    }
+}
+
+enum Message {
+    Alert(Alert),
+    Flush(oneshot::Sender<()>),
+}
+
+// Sends the alerts in the background, so slow alerters do not hold up the strategy.
+pub(crate) struct Alerts {
+    tx: UnboundedSender<Message>,
+    filter: AlertFilter,
+}
+
+impl Alerts {
+    pub(crate) fn new(alerter: Arc<dyn Alerter>, filter: AlertFilter) -> Self {
+        let (tx, mut rx) = unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(message) = rx.recv().await {
+                match message {
+                    Message::Alert(alert) => {
+                        if let Err(err) = alerter.alert(&alert).await {
+                            tracing::error!("{}", err);
+                        }
+                    }
+                    Message::Flush(tx) => {
+                        tx.send(()).ok();
+                    }
+                }
+            }
+        });
+        Alerts { tx, filter }
+    }
+
+    pub(crate) fn send(&self, alert: Alert) {
+        if self.filter.allows(&alert) {
+            self.tx.send(Message::Alert(alert)).ok();
+        }
+    }
+
+    // Wait until all alerts sent before are delivered.
+    pub(crate) async fn flush(&self) {
+        let (tx, rx) = oneshot::channel();
+        if self.tx.send(Message::Flush(tx)).is_ok() {
+            rx.await.ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Collect(Mutex<Vec<Alert>>);
+
+    #[async_trait]
+    impl Alerter for Collect {
+        async fn alert(&self, alert: &Alert) -> Result<(), AlertError> {
+            self.0.lock().unwrap().push(alert.clone());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn filter_alerts() {
+        let collect = Arc::new(Collect::default());
+        let alerts = Alerts::new(
+            collect.clone(),
+            AlertFilter {
+                opened: false,
+                ..AlertFilter::default()
+            },
+        );
+        let time = Utc::now();
+        let position = Uuid::new_v4();
+        alerts.send(Alert::Opened {
+            time,
+            position,
+            symbols: vec![Symbol::perp("BTC")],
+            value: Decimal::ONE_HUNDRED,
+        });
+        alerts.send(Alert::Closed {
+            time,
+            position,
+            symbols: vec![Symbol::perp("BTC")],
+            pnl: Decimal::TEN,
+        });
+        alerts.flush().await;
+
+        let sent = collect.0.lock().unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].to_string(), "Closed BTC-PERP with PnL 10.");
+    }
+}
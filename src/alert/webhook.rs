@@ -0,0 +1,104 @@
+use super::{Alert, AlertError, Alerter};
+use async_trait::async_trait;
+use reqwest::Client;
+use serde_json::{json, Value};
+
+/// Posts alerts as JSON to a URL.
+pub struct Webhook {
+    client: Client,
+    url: String,
+    format: WebhookFormat,
+}
+
+/// The payload of a webhook.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WebhookFormat {
+    /// The serialized alert together with its text, `{"text": ..., "alert": ...}`.
+    Json,
+    /// A Slack incoming webhook, `{"text": ...}`.
+    Slack,
+    /// The `sendMessage` method of the Telegram bot API, posting to the chat.
+    Telegram { chat_id: String },
+}
+
+impl Webhook {
+    pub fn new(url: impl Into<String>, format: WebhookFormat) -> Self {
+        Webhook {
+            client: Client::new(),
+            url: url.into(),
+            format,
+        }
+    }
+
+    /// A Slack incoming webhook.
+    pub fn slack(url: impl Into<String>) -> Self {
+        Webhook::new(url, WebhookFormat::Slack)
+    }
+
+    /// A Telegram bot posting to a chat.
+    pub fn telegram(token: &str, chat_id: impl Into<String>) -> Self {
+        Webhook::new(
+            format!("https://api.telegram.org/bot{}/sendMessage", token),
+            WebhookFormat::Telegram {
+                chat_id: chat_id.into(),
+            },
+        )
+    }
+
+    fn payload(&self, alert: &Alert) -> Value {
+        let text = alert.to_string();
+        match &self.format {
+            WebhookFormat::Json => json!({ "text": text, "alert": alert }),
+            WebhookFormat::Slack => json!({ "text": text }),
+            WebhookFormat::Telegram { chat_id } => json!({ "chat_id": chat_id, "text": text }),
+        }
+    }
+}
+
+#[async_trait]
+impl Alerter for Webhook {
+    async fn alert(&self, alert: &Alert) -> Result<(), AlertError> {
+        self.client
+            .post(&self.url)
+            .json(&self.payload(alert))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| AlertError::Send(err.to_string()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn payloads() {
+        let alert = Alert::Error {
+            time: Utc::now(),
+            error: "Could not connect to the API.".to_owned(),
+        };
+        let text = "Strategy failed: Could not connect to the API.";
+
+        let slack = Webhook::slack("https://hooks.slack.com/services/x");
+        assert_eq!(slack.payload(&alert), json!({ "text": text }));
+
+        let telegram = Webhook::telegram("token", "42");
+        assert_eq!(
+            telegram.url,
+            "https://api.telegram.org/bottoken/sendMessage"
+        );
+        assert_eq!(
+            telegram.payload(&alert),
+            json!({ "chat_id": "42", "text": text })
+        );
+
+        let generic = Webhook::new("https://example.com", WebhookFormat::Json);
+        assert_eq!(
+            generic.payload(&alert)["alert"]["Error"]["error"],
+            "Could not connect to the API."
+        );
+    }
+}
@@ -0,0 +1,221 @@
+use super::Api;
+use crate::{
+    apis::{ApiError, ExecutionGap, FeeSchedule, Margin, Order, OrderInfo},
+    Asset, Candle, CandleKey, Command, Interval, Markets, Orderbook, PriceSource, Symbol, Trade,
+    Transfer, Wallet,
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use rust_decimal::Decimal;
+use std::ops::Range;
+use uuid::Uuid;
+
+/// The Aggregate API builds candles of multiples of a base interval from base candles,
+/// e.g. 5 minute and 1 hour candles from 1 minute candles.
+/// Wrap a Store to download and store only the base candles, whatever intervals are requested.
+/// Other intervals are requested from the API as they are.
+pub struct Aggregate<A>
+where
+    A: Api,
+{
+    api: A,
+    base: Duration,
+}
+
+impl<A> Aggregate<A>
+where
+    A: Api,
+{
+    pub fn new(api: A, base: Duration) -> Self {
+        assert!(base > Duration::zero());
+        Aggregate { api, base }
+    }
+
+    // The number of base candles in a candle of the interval, if it is a larger multiple.
+    fn factor(&self, interval: Duration) -> Option<i64> {
+        let (interval, base) = (interval.num_seconds(), self.base.num_seconds());
+        (interval > base && interval % base == 0).then(|| interval / base)
+    }
+}
+
+// Merge base candles into candles of the interval, only complete candles are returned.
+fn aggregate(
+    candles: Vec<(CandleKey, Option<Candle>)>,
+    interval: Duration,
+    factor: i64,
+) -> Vec<(CandleKey, Option<Candle>)> {
+    let mut out: Vec<(CandleKey, Option<Candle>, i64)> = Vec::new();
+    for (key, candle) in candles {
+        let start = Interval::Fixed(interval).align(key.time);
+        match out.last_mut() {
+            Some((last, merged, count)) if last.time == start => {
+                *merged = match (*merged, candle) {
+                    (Some(merged), Some(candle)) => Some(merged.merge(candle)),
+                    (merged, candle) => merged.or(candle),
+                };
+                *count += 1;
+            }
+            _ => out.push((
+                CandleKey {
+                    market: key.market,
+                    time: start,
+                    interval,
+                },
+                candle,
+                1,
+            )),
+        }
+    }
+
+    out.into_iter()
+        .filter(|(_, _, count)| *count == factor)
+        .map(|(key, candle, _)| (key, candle))
+        .collect()
+}
+
+#[async_trait]
+impl<A: Api> Api for Aggregate<A> {
+    const NAME: &'static str = A::NAME;
+    const LIVE_TRADING_ENABLED: bool = A::LIVE_TRADING_ENABLED;
+
+    async fn get_candles(
+        &self,
+        key: CandleKey,
+    ) -> Result<Vec<(CandleKey, Option<Candle>)>, ApiError> {
+        let Some(factor) = self.factor(key.interval) else {
+            return self.api.get_candles(key).await;
+        };
+
+        let candles = self
+            .api
+            .get_candles(CandleKey {
+                interval: self.base,
+                ..key
+            })
+            .await?;
+        Ok(aggregate(candles, key.interval, factor))
+    }
+
+    async fn place_order(&self, order: Order) -> Result<OrderInfo, ApiError> {
+        self.api.place_order(order).await
+    }
+
+    async fn place_orders(&self, orders: Vec<Order>) -> Vec<Result<OrderInfo, ApiError>> {
+        self.api.place_orders(orders).await
+    }
+
+    async fn order_status(&self, market: Symbol, order_id: Uuid) -> Result<OrderInfo, ApiError> {
+        self.api.order_status(market, order_id).await
+    }
+
+    async fn cancel_order(&self, market: Symbol, order_id: Uuid) -> Result<OrderInfo, ApiError> {
+        self.api.cancel_order(market, order_id).await
+    }
+
+    async fn amend_order(
+        &self,
+        market: Symbol,
+        order_id: Uuid,
+        price: Decimal,
+        size: Decimal,
+    ) -> Result<OrderInfo, ApiError> {
+        self.api.amend_order(market, order_id, price, size).await
+    }
+
+    fn format_market(&self, market: Symbol) -> String {
+        self.api.format_market(market)
+    }
+
+    async fn update_wallet(&self, wallet: &mut Wallet) -> Result<(), ApiError> {
+        self.api.update_wallet(wallet).await
+    }
+
+    async fn update_markets(&self, markets: &mut Markets) -> Result<(), ApiError> {
+        self.api.update_markets(markets).await
+    }
+
+    fn quote_asset(&self) -> Asset {
+        self.api.quote_asset()
+    }
+
+    async fn order_fee(&self) -> Decimal {
+        self.api.order_fee().await
+    }
+
+    async fn get_orderbook(
+        &self,
+        market: Symbol,
+        time: DateTime<Utc>,
+    ) -> Result<Option<Orderbook>, ApiError> {
+        self.api.get_orderbook(market, time).await
+    }
+
+    async fn get_trades(
+        &self,
+        market: Symbol,
+        range: Range<DateTime<Utc>>,
+    ) -> Result<Option<Vec<Trade>>, ApiError> {
+        self.api.get_trades(market, range).await
+    }
+
+    async fn get_price(
+        &self,
+        key: CandleKey,
+        source: PriceSource,
+    ) -> Result<Option<Decimal>, ApiError> {
+        self.api.get_price(key, source).await
+    }
+
+    async fn get_prices(
+        &self,
+        key: CandleKey,
+        source: PriceSource,
+    ) -> Result<Vec<(CandleKey, Option<Decimal>)>, ApiError> {
+        self.api.get_prices(key, source).await
+    }
+
+    async fn fee_schedule(&self) -> FeeSchedule {
+        self.api.fee_schedule().await
+    }
+
+    async fn borrow_rate(&self, market: Symbol) -> Option<Decimal> {
+        self.api.borrow_rate(market).await
+    }
+
+    async fn borrow_costs(&self, time: DateTime<Utc>) -> Result<Decimal, ApiError> {
+        self.api.borrow_costs(time).await
+    }
+
+    async fn convert_dust(&self, assets: Vec<Asset>) -> Result<(), ApiError> {
+        self.api.convert_dust(assets).await
+    }
+
+    async fn transfer(&self, transfer: &Transfer) -> Result<(), ApiError> {
+        self.api.transfer(transfer).await
+    }
+
+    async fn save_state(&self, strategy_name: &str, state: String) -> Result<(), ApiError> {
+        self.api.save_state(strategy_name, state).await
+    }
+
+    async fn load_state(&self, strategy_name: &str) -> Result<Option<String>, ApiError> {
+        self.api.load_state(strategy_name).await
+    }
+
+    fn margin(&self) -> Option<Margin> {
+        self.api.margin()
+    }
+
+    async fn shutdown(&self) {
+        self.api.shutdown().await
+    }
+
+    fn execution_gap(&self) -> Option<ExecutionGap> {
+        self.api.execution_gap()
+    }
+
+    async fn commands(&self) -> Vec<Command> {
+        self.api.commands().await
+    }
+}
@@ -1 +1,1008 @@
+use super::{
+    rest::{fill_gaps, sign, RestClient},
+    Order, OrderInfo,
+};
+use crate::{
+    apis::{Api, ApiError, FeeSchedule},
+    Asset, Candle, CandleKey, MarketInfo, MarketType, Markets, OrderStatus, OrderType,
+    PositionSide, PriceSource, Side, Symbol, TimeInForce, Trade, Wallet,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use reqwest::Method;
+use rust_decimal::prelude::*;
+use serde::{de::IgnoredAny, Deserialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    env,
+    ops::Range,
+};
+use uuid::Uuid;
 
+// Maximum number of klines per request.
+const LIMIT: i32 = 1500;
+// Maximum number of aggregate trades per request.
+const TRADE_LIMIT: usize = 1000;
+// Maximum number of orders per batch request.
+const BATCH_LIMIT: usize = 5;
+
+/// USDⓈ-M perpetual futures on Binance.
+pub struct Binance {
+    rest: RestClient,
+    key: Option<String>,
+    secret: Option<String>,
+    quote_asset: Asset,
+}
+
+impl Binance {
+    /// Reads the credentials from `BINANCE_API_KEY` and `BINANCE_API_SECRET`.
+    /// Set `BINANCE_ENDPOINT` to `testnet` to trade on the futures testnet.
+    pub fn from_env() -> Self {
+        let base_url = match env::var("BINANCE_ENDPOINT") {
+            Ok(endpoint) => match endpoint.to_ascii_lowercase().as_str() {
+                "testnet" => "https://testnet.binancefuture.com",
+                "com" => "https://fapi.binance.com",
+                _ => panic!("Invalid Binance endpoint specified."),
+            },
+            Err(_) => "https://fapi.binance.com",
+        };
+
+        Binance {
+            rest: RestClient::new(base_url),
+            key: env::var("BINANCE_API_KEY").ok(),
+            secret: env::var("BINANCE_API_SECRET").ok(),
+            quote_asset: Asset::new("USDT"),
+        }
+    }
+
+    /// Trade the perpetuals margined in another asset than USDT, e.g. USDC.
+    pub fn with_quote_asset(mut self, asset: Asset) -> Self {
+        self.quote_asset = asset;
+        self
+    }
+
+    /// Send the requests with another client, e.g. one replaying recorded responses.
+    pub fn with_rest(mut self, rest: RestClient) -> Self {
+        self.rest = rest;
+        self
+    }
+
+    /// The client sending the requests, e.g. to save the interactions it recorded.
+    pub fn rest(&self) -> &RestClient {
+        &self.rest
+    }
+
+    async fn signed<T: serde::de::DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        mut query: Vec<(&str, String)>,
+    ) -> Result<T, ApiError> {
+        let (key, secret) = match (&self.key, &self.secret) {
+            (Some(key), Some(secret)) => (key, secret),
+            _ => {
+                tracing::error!("Binance API key and secret are required for {}", path);
+                return Err(ApiError::Api);
+            }
+        };
+
+        query.push(("timestamp", Utc::now().timestamp_millis().to_string()));
+        let signature = sign(
+            secret,
+            &serde_urlencoded::to_string(&query).map_err(|_| ApiError::Api)?,
+        );
+        query.push(("signature", signature));
+
+        self.rest
+            .request(method, path, &query, &[("X-MBX-APIKEY", key.clone())])
+            .await
+    }
+
+    // Identifies an order placed before by its client order id.
+    fn order_query(&self, market: Symbol, order_id: Uuid) -> Vec<(&'static str, String)> {
+        vec![
+            ("symbol", self.format_market(market)),
+            ("origClientOrderId", order_id.to_simple().to_string()),
+        ]
+    }
+
+    // The parameters of an order shared by single and batch requests.
+    fn order_params(&self, order: &Order) -> Vec<(&'static str, String)> {
+        let mut params = vec![
+            ("symbol", self.format_market(order.market)),
+            (
+                "side",
+                match order.side {
+                    Side::Buy => "BUY",
+                    Side::Sell => "SELL",
+                }
+                .to_owned(),
+            ),
+            ("quantity", order.size.normalize().to_string()),
+            ("newClientOrderId", order.order_id.to_simple().to_string()),
+            ("newOrderRespType", "RESULT".to_owned()),
+        ];
+        // In hedge mode, orders name their leg and may not be reduce only.
+        match order.position_side {
+            Some(PositionSide::Long) => params.push(("positionSide", "LONG".to_owned())),
+            Some(PositionSide::Short) => params.push(("positionSide", "SHORT".to_owned())),
+            None => params.push(("reduceOnly", order.reduce_only.to_string())),
+        }
+        match order.order_type {
+            OrderType::Market => params.push(("type", "MARKET".to_owned())),
+            OrderType::Limit(price) => {
+                params.push(("type", "LIMIT".to_owned()));
+                params.push(("price", price.normalize().to_string()));
+                params.push((
+                    "timeInForce",
+                    match order.time_in_force {
+                        // Good til crossing, the post-only time in force of Binance.
+                        _ if order.post_only => "GTX",
+                        TimeInForce::GoodTilCancelled => "GTC",
+                        TimeInForce::ImmediateOrCancel => "IOC",
+                        TimeInForce::FillOrKill => "FOK",
+                    }
+                    .to_owned(),
+                ));
+            }
+        }
+
+        params
+    }
+}
+
+fn format_interval(interval: Duration) -> Option<&'static str> {
+    Some(match interval.num_minutes() {
+        1 => "1m",
+        3 => "3m",
+        5 => "5m",
+        15 => "15m",
+        30 => "30m",
+        60 => "1h",
+        120 => "2h",
+        240 => "4h",
+        360 => "6h",
+        480 => "8h",
+        720 => "12h",
+        1440 => "1d",
+        4320 => "3d",
+        10080 => "1w",
+        _ => return None,
+    })
+}
+
+#[derive(Deserialize)]
+struct Kline(
+    i64,
+    Decimal,
+    Decimal,
+    Decimal,
+    Decimal,
+    Decimal,
+    IgnoredAny,
+    IgnoredAny,
+    IgnoredAny,
+    IgnoredAny,
+    IgnoredAny,
+    IgnoredAny,
+);
+
+#[derive(Deserialize)]
+struct ExchangeInfo {
+    symbols: Vec<SymbolInfo>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SymbolInfo {
+    symbol: String,
+    contract_type: String,
+    // Milliseconds since the epoch, far in the future for perpetuals.
+    #[serde(default)]
+    delivery_date: Option<i64>,
+    // Only coin margined futures have a contract size.
+    #[serde(default)]
+    contract_size: Option<Decimal>,
+    base_asset: String,
+    quote_asset: String,
+    status: String,
+    filters: Vec<Filter>,
+}
+
+impl SymbolInfo {
+    fn market_type(&self) -> MarketType {
+        match self.contract_type.as_str() {
+            "PERPETUAL" => MarketType::Perpetual,
+            _ => MarketType::Future,
+        }
+    }
+
+    fn expiry(&self) -> Option<DateTime<Utc>> {
+        match self.market_type() {
+            MarketType::Future => Utc.timestamp_millis_opt(self.delivery_date?).single(),
+            MarketType::Perpetual | MarketType::Spot => None,
+        }
+    }
+
+    // Quarterly futures are named by their expiry, other contract types are not supported.
+    fn symbol(&self) -> Option<Symbol> {
+        match self.contract_type.as_str() {
+            "PERPETUAL" => Some(Symbol::perp(&self.base_asset)),
+            "CURRENT_QUARTER" | "NEXT_QUARTER" => Some(Symbol::future(
+                &self.base_asset,
+                self.expiry()?.date_naive(),
+            )),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "filterType")]
+enum Filter {
+    #[serde(rename = "PRICE_FILTER", rename_all = "camelCase")]
+    Price { tick_size: Decimal },
+    #[serde(rename = "LOT_SIZE", rename_all = "camelCase")]
+    LotSize {
+        min_qty: Decimal,
+        step_size: Decimal,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct AggTrade {
+    #[serde(rename = "a")]
+    id: u64,
+    #[serde(rename = "p")]
+    price: Decimal,
+    #[serde(rename = "q")]
+    size: Decimal,
+    #[serde(rename = "T")]
+    time: i64,
+    // The buyer was the maker, i.e. the taker sold.
+    #[serde(rename = "m")]
+    buyer_maker: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Ticker {
+    symbol: String,
+    quote_volume: Decimal,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Balance {
+    asset: String,
+    balance: Decimal,
+    available_balance: Decimal,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OrderResponse {
+    avg_price: Decimal,
+    executed_qty: Decimal,
+    update_time: i64,
+    status: String,
+    side: String,
+}
+
+impl OrderResponse {
+    // The info of a placed order, priced at the current price until it is filled.
+    fn placed(&self, order: &Order) -> OrderInfo {
+        OrderInfo {
+            order_id: order.order_id,
+            market: order.market,
+            size: self.executed_qty,
+            price: if self.executed_qty.is_zero() {
+                order.current_price
+            } else {
+                self.avg_price
+            },
+            time: Utc.timestamp_millis_opt(self.update_time).unwrap(),
+            side: order.side,
+            status: self.status(),
+        }
+    }
+
+    fn status(&self) -> OrderStatus {
+        match self.status.as_str() {
+            "NEW" => OrderStatus::Submitted,
+            "PARTIALLY_FILLED" => OrderStatus::PartiallyFilled,
+            "FILLED" => OrderStatus::Filled,
+            "REJECTED" => OrderStatus::Rejected,
+            // Cancelled and expired, e.g. the rest of immediate or cancel orders.
+            _ => OrderStatus::Cancelled,
+        }
+    }
+
+    fn info(&self, order_id: Uuid, market: Symbol) -> OrderInfo {
+        OrderInfo {
+            order_id,
+            market,
+            size: self.executed_qty,
+            price: self.avg_price,
+            time: Utc.timestamp_millis_opt(self.update_time).unwrap(),
+            side: if self.side == "BUY" {
+                Side::Buy
+            } else {
+                Side::Sell
+            },
+            status: self.status(),
+        }
+    }
+}
+
+#[async_trait]
+impl Api for Binance {
+    const NAME: &'static str = "Binance";
+    const LIVE_TRADING_ENABLED: bool = true;
+
+    async fn get_candles(
+        &self,
+        key: CandleKey,
+    ) -> Result<Vec<(CandleKey, Option<Candle>)>, ApiError> {
+        let interval = format_interval(key.interval).ok_or_else(|| {
+            tracing::error!("Binance does not support the interval {}", key.interval);
+            ApiError::Api
+        })?;
+
+        let klines: Vec<Kline> = self
+            .rest
+            .request(
+                Method::GET,
+                "/fapi/v1/klines",
+                &[
+                    ("symbol", self.format_market(key.market)),
+                    ("interval", interval.to_owned()),
+                    ("startTime", key.time.timestamp_millis().to_string()),
+                    (
+                        "endTime",
+                        (key.time + key.interval * (LIMIT - 1))
+                            .timestamp_millis()
+                            .to_string(),
+                    ),
+                    ("limit", LIMIT.to_string()),
+                ],
+                &[],
+            )
+            .await?;
+
+        let candles = klines
+            .into_iter()
+            // The last kline is still open.
+            .filter(|kline| Utc.timestamp_millis_opt(kline.0).unwrap() + key.interval <= Utc::now())
+            .map(|Kline(time, open, high, low, close, volume, ..)| {
+                (
+                    CandleKey {
+                        time: Utc.timestamp_millis_opt(time).unwrap(),
+                        ..key
+                    },
+                    Candle {
+                        open,
+                        high,
+                        low,
+                        close,
+                        volume,
+                    },
+                )
+            });
+
+        Ok(fill_gaps(key, candles, LIMIT))
+    }
+
+    // Aggregate trades can only be requested for less than an hour at once,
+    // and are paged by the time of the last trade of a full page.
+    async fn get_trades(
+        &self,
+        market: Symbol,
+        range: Range<DateTime<Utc>>,
+    ) -> Result<Option<Vec<Trade>>, ApiError> {
+        let mut trades = Vec::new();
+        let mut last_id = None;
+        let mut start = range.start;
+        while start < range.end {
+            let end = (start + Duration::hours(1)).min(range.end) - Duration::milliseconds(1);
+            let page: Vec<AggTrade> = self
+                .rest
+                .request(
+                    Method::GET,
+                    "/fapi/v1/aggTrades",
+                    &[
+                        ("symbol", self.format_market(market)),
+                        ("startTime", start.timestamp_millis().to_string()),
+                        ("endTime", end.timestamp_millis().to_string()),
+                        ("limit", TRADE_LIMIT.to_string()),
+                    ],
+                    &[],
+                )
+                .await?;
+
+            let full = page.len() >= TRADE_LIMIT;
+            let count = trades.len();
+            for trade in page {
+                if last_id.is_some_and(|id| trade.id <= id) {
+                    continue;
+                }
+                last_id = Some(trade.id);
+                trades.push(Trade {
+                    time: Utc.timestamp_millis_opt(trade.time).unwrap(),
+                    price: trade.price,
+                    size: trade.size,
+                    side: if trade.buyer_maker {
+                        Side::Sell
+                    } else {
+                        Side::Buy
+                    },
+                });
+            }
+
+            start = match trades.last() {
+                // The rest of a full page starts at its last trade, unless the page had no new trades.
+                Some(last) if full && trades.len() > count => last.time,
+                _ => end + Duration::milliseconds(1),
+            };
+        }
+
+        Ok(Some(trades))
+    }
+
+    async fn get_price(
+        &self,
+        key: CandleKey,
+        source: PriceSource,
+    ) -> Result<Option<Decimal>, ApiError> {
+        Ok(self
+            .get_prices(key, source)
+            .await?
+            .into_iter()
+            .find(|(curr_key, _)| *curr_key == key)
+            .and_then(|(_, price)| price))
+    }
+
+    async fn get_prices(
+        &self,
+        key: CandleKey,
+        source: PriceSource,
+    ) -> Result<Vec<(CandleKey, Option<Decimal>)>, ApiError> {
+        let (path, market) = match source {
+            PriceSource::Mark => ("/fapi/v1/markPriceKlines", "symbol"),
+            PriceSource::Index => ("/fapi/v1/indexPriceKlines", "pair"),
+            PriceSource::Close | PriceSource::Mid => return Ok(vec![(key, None)]),
+        };
+        let interval = format_interval(key.interval).ok_or_else(|| {
+            tracing::error!("Binance does not support the interval {}", key.interval);
+            ApiError::Api
+        })?;
+
+        let klines: Vec<Kline> = self
+            .rest
+            .request(
+                Method::GET,
+                path,
+                &[
+                    (market, self.format_market(key.market)),
+                    ("interval", interval.to_owned()),
+                    ("startTime", key.time.timestamp_millis().to_string()),
+                    (
+                        "endTime",
+                        (key.time + key.interval * (LIMIT - 1))
+                            .timestamp_millis()
+                            .to_string(),
+                    ),
+                    ("limit", LIMIT.to_string()),
+                ],
+                &[],
+            )
+            .await?;
+
+        // Price klines are candles of the price without volume.
+        let candles = klines
+            .into_iter()
+            // The last kline is still open.
+            .filter(|kline| Utc.timestamp_millis_opt(kline.0).unwrap() + key.interval <= Utc::now())
+            .map(|Kline(time, open, high, low, close, ..)| {
+                (
+                    CandleKey {
+                        time: Utc.timestamp_millis_opt(time).unwrap(),
+                        ..key
+                    },
+                    Candle {
+                        open,
+                        high,
+                        low,
+                        close,
+                        volume: Decimal::ZERO,
+                    },
+                )
+            });
+
+        Ok(fill_gaps(key, candles, LIMIT)
+            .into_iter()
+            .map(|(key, candle)| (key, candle.map(|candle| candle.close)))
+            .collect())
+    }
+
+    async fn place_order(&self, order: Order) -> Result<OrderInfo, ApiError> {
+        tracing::trace!("place order binance");
+
+        let response: OrderResponse = self
+            .signed(Method::POST, "/fapi/v1/order", self.order_params(&order))
+            .await
+            .map_err(|err| match err {
+                ApiError::Api => ApiError::Rejected,
+                err => err,
+            })?;
+
+        Ok(response.placed(&order))
+    }
+
+    async fn place_orders(&self, orders: Vec<Order>) -> Vec<Result<OrderInfo, ApiError>> {
+        let mut results = Vec::with_capacity(orders.len());
+        for batch in orders.chunks(BATCH_LIMIT) {
+            let batch_orders: Vec<BTreeMap<_, _>> = batch
+                .iter()
+                .map(|order| self.order_params(order).into_iter().collect())
+                .collect();
+            let query = match serde_json::to_string(&batch_orders) {
+                Ok(batch_orders) => vec![("batchOrders", batch_orders)],
+                Err(_) => {
+                    results.extend(batch.iter().map(|_| Err(ApiError::Api)));
+                    continue;
+                }
+            };
+
+            match self
+                .signed::<Vec<serde_json::Value>>(Method::POST, "/fapi/v1/batchOrders", query)
+                .await
+            {
+                // Every order of the batch is accepted or rejected on its own.
+                Ok(responses) => results.extend(batch.iter().enumerate().map(|(i, order)| {
+                    let response = responses.get(i).cloned().unwrap_or_default();
+                    serde_json::from_value::<OrderResponse>(response.clone())
+                        .map(|response| response.placed(order))
+                        .map_err(|_| {
+                            tracing::warn!(
+                                market = %order.market,
+                                code = %response["code"],
+                                message = %response["msg"],
+                                "Order of the batch rejected."
+                            );
+                            ApiError::Rejected
+                        })
+                })),
+                Err(err) => results.extend(batch.iter().map(|_| match &err {
+                    ApiError::Api => Err(ApiError::Rejected),
+                    err => Err(err.clone()),
+                })),
+            }
+        }
+
+        results
+    }
+
+    async fn order_status(&self, market: Symbol, order_id: Uuid) -> Result<OrderInfo, ApiError> {
+        let response: OrderResponse = self
+            .signed(
+                Method::GET,
+                "/fapi/v1/order",
+                self.order_query(market, order_id),
+            )
+            .await?;
+        Ok(response.info(order_id, market))
+    }
+
+    async fn cancel_order(&self, market: Symbol, order_id: Uuid) -> Result<OrderInfo, ApiError> {
+        let response: OrderResponse = self
+            .signed(
+                Method::DELETE,
+                "/fapi/v1/order",
+                self.order_query(market, order_id),
+            )
+            .await?;
+        Ok(response.info(order_id, market))
+    }
+
+    async fn amend_order(
+        &self,
+        market: Symbol,
+        order_id: Uuid,
+        price: Decimal,
+        size: Decimal,
+    ) -> Result<OrderInfo, ApiError> {
+        // Binance requires the side of the order to modify it.
+        let side = self.order_status(market, order_id).await?.side;
+        let mut query = self.order_query(market, order_id);
+        query.push((
+            "side",
+            match side {
+                Side::Buy => "BUY",
+                Side::Sell => "SELL",
+            }
+            .to_owned(),
+        ));
+        query.push(("quantity", size.normalize().to_string()));
+        query.push(("price", price.normalize().to_string()));
+        let response: OrderResponse = self
+            .signed(Method::PUT, "/fapi/v1/order", query)
+            .await
+            .map_err(|err| match err {
+                ApiError::Api => ApiError::Rejected,
+                err => err,
+            })?;
+        Ok(response.info(order_id, market))
+    }
+
+    fn format_market(&self, market: Symbol) -> String {
+        match market {
+            Symbol::Perp(asset) => format!("{}{}", asset, self.quote_asset()),
+            Symbol::Future(asset, expiry) => {
+                format!(
+                    "{}{}_{}",
+                    asset,
+                    self.quote_asset(),
+                    expiry.format("%y%m%d")
+                )
+            }
+            // Spot markets are not traded on the futures API, their name is rejected.
+            Symbol::Spot(_) => market.to_string(),
+        }
+    }
+
+    async fn update_wallet(&self, wallet: &mut Wallet) -> Result<(), ApiError> {
+        let balances: Vec<Balance> = self
+            .signed(Method::GET, "/fapi/v2/balance", Vec::new())
+            .await?;
+
+        *wallet = Wallet {
+            free: balances
+                .iter()
+                .map(|balance| (Asset::new(&balance.asset), balance.available_balance))
+                .collect(),
+            total: balances
+                .iter()
+                .map(|balance| (Asset::new(&balance.asset), balance.balance))
+                .collect(),
+        };
+
+        Ok(())
+    }
+
+    async fn update_markets(&self, markets: &mut Markets) -> Result<(), ApiError> {
+        let info: ExchangeInfo = self
+            .rest
+            .request(Method::GET, "/fapi/v1/exchangeInfo", &[], &[])
+            .await?;
+        let volumes: HashMap<String, Decimal> = self
+            .rest
+            .request::<Vec<Ticker>>(Method::GET, "/fapi/v1/ticker/24hr", &[], &[])
+            .await?
+            .into_iter()
+            .map(|ticker| (ticker.symbol, ticker.quote_volume))
+            .collect();
+
+        let quote_asset = self.quote_asset();
+        markets.markets = info
+            .symbols
+            .into_iter()
+            .filter(|info| info.status == "TRADING" && Asset::new(&info.quote_asset) == quote_asset)
+            .filter_map(|info| {
+                let symbol = info.symbol()?;
+                let mut market = MarketInfo {
+                    symbol,
+                    min_size: Decimal::ZERO,
+                    size_increment: Decimal::ZERO,
+                    price_increment: Decimal::ZERO,
+                    daily_quote_volume: volumes.get(&info.symbol).cloned().unwrap_or_default(),
+                    market_type: info.market_type(),
+                    contract_multiplier: info.contract_size.unwrap_or(Decimal::ONE),
+                    expiry: info.expiry(),
+                };
+                for filter in info.filters {
+                    match filter {
+                        Filter::Price { tick_size } => market.price_increment = tick_size,
+                        Filter::LotSize { min_qty, step_size } => {
+                            market.min_size = min_qty;
+                            market.size_increment = step_size;
+                        }
+                        Filter::Other => {}
+                    }
+                }
+                Some((symbol, market))
+            })
+            .collect();
+
+        Ok(())
+    }
+
+    fn quote_asset(&self) -> Asset {
+        self.quote_asset
+    }
+
+    async fn order_fee(&self) -> Decimal {
+        // 0.0004 = 0.04%, the taker fee of the lowest tier.
+        Decimal::new(4, 4)
+    }
+
+    async fn fee_schedule(&self) -> FeeSchedule {
+        FeeSchedule::new(Decimal::new(2, 4), self.order_fee().await)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::rest::Cassette;
+    use rust_decimal_macros::dec;
+
+    // Recorded responses of the futures API, replayed without credentials.
+    fn replay() -> Binance {
+        let cassette = Cassette::from_file(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/fixtures/binance/cassette.json"
+        ))
+        .unwrap();
+        Binance {
+            rest: RestClient::replay(cassette),
+            key: Some("key".to_owned()),
+            secret: Some("secret".to_owned()),
+            quote_asset: Asset::new("USDT"),
+        }
+    }
+
+    #[tokio::test]
+    async fn replay_markets_and_wallet() {
+        let api = replay();
+        let mut markets = Markets::default();
+        api.update_markets(&mut markets).await.unwrap();
+
+        // Other quote assets and markets that are not trading are skipped.
+        assert_eq!(markets.markets().count(), 3);
+        let btc = markets.market(Symbol::perp("BTC")).unwrap();
+        assert_eq!(btc.min_size, dec!(0.001));
+        assert_eq!(btc.size_increment, dec!(0.001));
+        assert_eq!(btc.price_increment, dec!(0.10));
+        assert_eq!(btc.daily_quote_volume, dec!(11831290232.41));
+        let expiry = chrono::NaiveDate::from_ymd_opt(2024, 3, 29).unwrap();
+        let future = markets.market(Symbol::future("BTC", expiry)).unwrap();
+        assert_eq!(future.market_type, MarketType::Future);
+        assert_eq!(future.daily_quote_volume, Decimal::ZERO);
+
+        let mut wallet = Wallet::new();
+        api.update_wallet(&mut wallet).await.unwrap();
+        assert_eq!(wallet.total[&Asset::new("USDT")], dec!(10122.43150121));
+        assert_eq!(wallet.free[&Asset::new("USDT")], dec!(8531.97622811));
+
+        let requests = api.rest().interactions();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[2].path, "/fapi/v2/balance");
+        assert!(requests[2].param("signature").is_some());
+    }
+
+    #[tokio::test]
+    async fn replay_candles() {
+        let api = replay();
+        let key = CandleKey {
+            market: Symbol::perp("BTC"),
+            time: Utc.with_ymd_and_hms(2021, 8, 1, 0, 0, 0).unwrap(),
+            interval: Duration::minutes(1),
+        };
+        let candles = api.get_candles(key).await.unwrap();
+
+        assert_eq!(candles.len(), LIMIT as usize);
+        assert_eq!(candles[0].0, key);
+        assert_eq!(candles[1].1.unwrap().close, dec!(41500.03));
+        // Gaps are filled with none.
+        assert_eq!(candles[2].1, None);
+        assert_eq!(candles[3].1.unwrap().volume, dec!(120.734));
+        assert_eq!(candles[3].0.time, key.time + Duration::minutes(3));
+
+        let request = &api.rest().interactions()[0];
+        assert_eq!(request.param("symbol"), Some("BTCUSDT"));
+        assert_eq!(request.param("interval"), Some("1m"));
+        assert_eq!(request.param("startTime"), Some("1627776000000"));
+    }
+
+    #[tokio::test]
+    async fn replay_mark_prices() {
+        let api = replay();
+        let key = CandleKey {
+            market: Symbol::perp("BTC"),
+            time: Utc.with_ymd_and_hms(2021, 8, 1, 0, 0, 0).unwrap(),
+            interval: Duration::minutes(1),
+        };
+        let prices = api.get_prices(key, PriceSource::Mark).await.unwrap();
+
+        // A whole page of prices is fetched at once.
+        assert_eq!(prices.len(), LIMIT as usize);
+        assert_eq!(
+            prices[1],
+            (
+                CandleKey {
+                    time: key.time + key.interval,
+                    ..key
+                },
+                Some(dec!(41502.31))
+            )
+        );
+        assert_eq!(prices[2].1, None);
+
+        let request = &api.rest().interactions()[0];
+        assert_eq!(request.path, "/fapi/v1/markPriceKlines");
+        assert_eq!(request.param("symbol"), Some("BTCUSDT"));
+        assert_eq!(request.param("limit"), Some("1500"));
+    }
+
+    #[tokio::test]
+    async fn replay_trades() {
+        let api = replay();
+        let start = Utc.with_ymd_and_hms(2021, 8, 1, 0, 0, 0).unwrap();
+        let trades = api
+            .get_trades(Symbol::perp("BTC"), start..start + Duration::minutes(1))
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(trades.len(), 3);
+        assert_eq!(trades[0].time, start + Duration::milliseconds(112));
+        assert_eq!(trades[0].side, Side::Buy);
+        assert_eq!(trades[1].price, dec!(41461.20));
+        assert_eq!(trades[1].side, Side::Sell);
+        assert_eq!(trades[2].size, dec!(0.250));
+
+        let request = &api.rest().interactions()[0];
+        assert_eq!(request.param("symbol"), Some("BTCUSDT"));
+        assert_eq!(request.param("startTime"), Some("1627776000000"));
+        assert_eq!(request.param("endTime"), Some("1627776059999"));
+    }
+
+    #[tokio::test]
+    async fn replay_orders() {
+        let api = replay();
+        let order = Order {
+            order_id: Uuid::parse_str("67e55044-10b1-426f-9247-bb680e5fe0c8").unwrap(),
+            market: Symbol::perp("BTC"),
+            side: Side::Buy,
+            size: dec!(0.500),
+            order_type: OrderType::Limit(dec!(41500.00)),
+            reduce_only: false,
+            time_in_force: TimeInForce::GoodTilCancelled,
+            post_only: false,
+            position_side: None,
+            time: Utc.with_ymd_and_hms(2021, 8, 1, 0, 1, 0).unwrap(),
+            current_price: dec!(41470.72),
+        };
+        let info = api.place_order(order.clone()).await.unwrap();
+        assert_eq!(info.size, dec!(0.5));
+        assert_eq!(info.price, dec!(41480.70));
+        assert_eq!(info.status, OrderStatus::Filled);
+
+        let request = &api.rest().interactions()[0];
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.param("side"), Some("BUY"));
+        assert_eq!(request.param("type"), Some("LIMIT"));
+        assert_eq!(request.param("quantity"), Some("0.5"));
+        assert_eq!(request.param("price"), Some("41500"));
+        assert_eq!(request.param("timeInForce"), Some("GTC"));
+        assert_eq!(request.param("reduceOnly"), Some("false"));
+        assert_eq!(
+            request.param("newClientOrderId"),
+            Some("67e5504410b1426f9247bb680e5fe0c8")
+        );
+
+        let eth = Symbol::perp("ETH");
+        let status = api.order_status(eth, order.order_id).await.unwrap();
+        assert_eq!(status.side, Side::Sell);
+        assert_eq!(status.size, dec!(1));
+        assert_eq!(status.status, OrderStatus::PartiallyFilled);
+
+        // Errors of the venue are mapped.
+        assert!(matches!(
+            api.cancel_order(eth, order.order_id).await,
+            Err(ApiError::Api)
+        ));
+    }
+
+    #[tokio::test]
+    async fn replay_batch_orders() {
+        let api = replay();
+        let order = |order_id: &str, market: Symbol| Order {
+            order_id: Uuid::parse_str(order_id).unwrap(),
+            market,
+            side: Side::Buy,
+            size: dec!(0.500),
+            order_type: OrderType::Market,
+            reduce_only: false,
+            time_in_force: TimeInForce::GoodTilCancelled,
+            post_only: false,
+            position_side: None,
+            time: Utc.with_ymd_and_hms(2021, 8, 1, 0, 1, 0).unwrap(),
+            current_price: dec!(41470.72),
+        };
+        let results = api
+            .place_orders(vec![
+                order("67e55044-10b1-426f-9247-bb680e5fe0c8", Symbol::perp("BTC")),
+                order("1f0e6b0c-8b54-4b2c-a4c5-0c1a4c2e8f3d", Symbol::perp("ETH")),
+            ])
+            .await;
+
+        // Orders of a batch are rejected on their own.
+        assert_eq!(results.len(), 2);
+        let info = results[0].as_ref().unwrap();
+        assert_eq!(info.size, dec!(0.5));
+        assert_eq!(info.price, dec!(41480.70));
+        assert_eq!(info.status, OrderStatus::Filled);
+        assert!(matches!(results[1], Err(ApiError::Rejected)));
+
+        let interactions = api.rest().interactions();
+        assert_eq!(interactions.len(), 1);
+        assert_eq!(interactions[0].path, "/fapi/v1/batchOrders");
+        let batch: Vec<HashMap<String, String>> =
+            serde_json::from_str(interactions[0].param("batchOrders").unwrap()).unwrap();
+        assert_eq!(batch[0]["symbol"], "BTCUSDT");
+        assert_eq!(batch[1]["symbol"], "ETHUSDT");
+        assert_eq!(batch[1]["type"], "MARKET");
+        assert_eq!(
+            batch[1]["newClientOrderId"],
+            "1f0e6b0c8b544b2ca4c50c1a4c2e8f3d"
+        );
+    }
+
+    #[test]
+    fn parse_exchange_info() {
+        let info: ExchangeInfo = serde_json::from_str(
+            r#"{"symbols": [{
+                "symbol": "BTCUSDT",
+                "contractType": "PERPETUAL",
+                "deliveryDate": 4133404800000,
+                "baseAsset": "BTC",
+                "quoteAsset": "USDT",
+                "status": "TRADING",
+                "filters": [
+                    {"filterType": "PRICE_FILTER", "tickSize": "0.10", "minPrice": "556.80"},
+                    {"filterType": "LOT_SIZE", "minQty": "0.001", "stepSize": "0.001", "maxQty": "1000"},
+                    {"filterType": "PERCENT_PRICE", "multiplierUp": "1.0500"}
+                ]
+            }]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(info.symbols[0].filters.len(), 3);
+        assert_eq!(info.symbols[0].market_type(), MarketType::Perpetual);
+        assert_eq!(info.symbols[0].contract_size, None);
+        assert_eq!(info.symbols[0].symbol(), Some(Symbol::perp("BTC")));
+
+        let quarterly: SymbolInfo = serde_json::from_str(
+            r#"{
+                "symbol": "BTCUSDT_240329",
+                "contractType": "CURRENT_QUARTER",
+                "deliveryDate": 1711699200000,
+                "baseAsset": "BTC",
+                "quoteAsset": "USDT",
+                "status": "TRADING",
+                "filters": []
+            }"#,
+        )
+        .unwrap();
+        let expiry = chrono::NaiveDate::from_ymd_opt(2024, 3, 29).unwrap();
+        assert_eq!(quarterly.symbol(), Some(Symbol::future("BTC", expiry)));
+        assert_eq!(quarterly.expiry().unwrap().date_naive(), expiry);
+        assert!(matches!(
+            info.symbols[0].filters[1],
+            Filter::LotSize { min_qty, .. } if min_qty == Decimal::new(1, 3)
+        ));
+
+        let klines: Vec<Kline> = serde_json::from_str(
+            r#"[[1499040000000, "0.01634790", "0.80000000", "0.01575800", "0.01577100",
+                "148976.11427815", 1499644799999, "2434.19055334", 308, "1756.87402397",
+                "28.46694368", "17928899.62484339"]]"#,
+        )
+        .unwrap();
+
+        assert_eq!(klines[0].4, Decimal::new(1577100, 8));
+    }
+}
@@ -0,0 +1,711 @@
+use super::{
+    rest::{fill_gaps, sign, RestClient},
+    Order, OrderInfo,
+};
+use crate::{
+    apis::{Api, ApiError, FeeSchedule},
+    Asset, Candle, CandleKey, MarketInfo, MarketType, Markets, OrderStatus, OrderType, Side,
+    Symbol, TimeInForce, Wallet,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use reqwest::Method;
+use rust_decimal::prelude::*;
+use serde::{Deserialize, Deserializer};
+use serde_json::{json, Value};
+use std::{collections::HashMap, env, sync::Mutex};
+use uuid::Uuid;
+
+// Maximum number of candles per request.
+const LIMIT: i32 = 350;
+
+/// Spot markets on Coinbase, traded with the Advanced Trade API.
+/// Spot markets can only be held long, see `Direction::LongOnly`.
+/// The base assets in the wallet are valued by the positions holding them.
+pub struct Coinbase {
+    rest: RestClient,
+    key: Option<String>,
+    secret: Option<String>,
+    quote_asset: Asset,
+    // The ids Coinbase assigned to the orders placed in this session.
+    order_ids: Mutex<HashMap<Uuid, String>>,
+}
+
+impl Coinbase {
+    /// Reads the credentials of an API key signed with HMAC from `COINBASE_API_KEY`
+    /// and `COINBASE_API_SECRET`. Set `COINBASE_ENDPOINT` to `sandbox` to use the sandbox.
+    pub fn from_env() -> Self {
+        let base_url = match env::var("COINBASE_ENDPOINT") {
+            Ok(endpoint) => match endpoint.to_ascii_lowercase().as_str() {
+                "sandbox" => "https://api-sandbox.coinbase.com",
+                "com" => "https://api.coinbase.com",
+                _ => panic!("Invalid Coinbase endpoint specified."),
+            },
+            Err(_) => "https://api.coinbase.com",
+        };
+
+        Coinbase {
+            rest: RestClient::new(base_url),
+            key: env::var("COINBASE_API_KEY").ok(),
+            secret: env::var("COINBASE_API_SECRET").ok(),
+            quote_asset: Asset::new("USD"),
+            order_ids: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Trade the markets quoted in another asset than USD, e.g. USDC.
+    pub fn with_quote_asset(mut self, asset: Asset) -> Self {
+        self.quote_asset = asset;
+        self
+    }
+
+    /// Send the requests with another client, e.g. one replaying recorded responses.
+    pub fn with_rest(mut self, rest: RestClient) -> Self {
+        self.rest = rest;
+        self
+    }
+
+    /// The client sending the requests, e.g. to save the interactions it recorded.
+    pub fn rest(&self) -> &RestClient {
+        &self.rest
+    }
+
+    async fn signed<T: serde::de::DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        query: &[(&str, String)],
+        body: Option<Value>,
+    ) -> Result<T, ApiError> {
+        let (key, secret) = match (&self.key, &self.secret) {
+            (Some(key), Some(secret)) => (key, secret),
+            _ => {
+                tracing::error!("Coinbase API key and secret are required for {}", path);
+                return Err(ApiError::Api);
+            }
+        };
+
+        // The path is signed without the query string.
+        let timestamp = Utc::now().timestamp().to_string();
+        let body = body.map(|body| body.to_string());
+        let signature = sign(
+            secret,
+            &format!(
+                "{}{}{}{}",
+                timestamp,
+                method,
+                path,
+                body.as_deref().unwrap_or_default()
+            ),
+        );
+        let headers = [
+            ("CB-ACCESS-KEY", key.clone()),
+            ("CB-ACCESS-SIGN", signature),
+            ("CB-ACCESS-TIMESTAMP", timestamp),
+        ];
+
+        match body {
+            Some(body) => self.rest.request_body(method, path, body, &headers).await,
+            None => self.rest.request(method, path, query, &headers).await,
+        }
+    }
+
+    fn order_id(&self, order_id: Uuid) -> Result<String, ApiError> {
+        self.order_ids
+            .lock()
+            .unwrap()
+            .get(&order_id)
+            .cloned()
+            .ok_or_else(|| {
+                tracing::warn!("Coinbase does not know the order {}", order_id);
+                ApiError::Api
+            })
+    }
+}
+
+// The granularity of the candles of an interval, Coinbase only provides some intervals.
+// Other multiples of them can be built with the `Aggregate` API.
+fn granularity(interval: Duration) -> Option<&'static str> {
+    Some(match interval.num_seconds() {
+        60 => "ONE_MINUTE",
+        300 => "FIVE_MINUTE",
+        900 => "FIFTEEN_MINUTE",
+        1800 => "THIRTY_MINUTE",
+        3600 => "ONE_HOUR",
+        7200 => "TWO_HOUR",
+        14400 => "FOUR_HOUR",
+        21600 => "SIX_HOUR",
+        86400 => "ONE_DAY",
+        _ => return None,
+    })
+}
+
+// Coinbase returns empty strings instead of zero for some amounts.
+fn zero_if_empty<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Decimal, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    if value.is_empty() {
+        Ok(Decimal::ZERO)
+    } else {
+        Decimal::from_str(&value).map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Deserialize)]
+struct Candles {
+    candles: Vec<CandleResponse>,
+}
+
+#[derive(Deserialize)]
+struct CandleResponse {
+    // Seconds since the epoch.
+    start: String,
+    open: Decimal,
+    high: Decimal,
+    low: Decimal,
+    close: Decimal,
+    volume: Decimal,
+}
+
+#[derive(Deserialize)]
+struct Products {
+    products: Vec<Product>,
+}
+
+#[derive(Deserialize)]
+struct Product {
+    product_type: String,
+    status: String,
+    #[serde(default)]
+    trading_disabled: bool,
+    base_currency_id: String,
+    quote_currency_id: String,
+    base_min_size: Decimal,
+    base_increment: Decimal,
+    price_increment: Decimal,
+    #[serde(default, deserialize_with = "zero_if_empty")]
+    approximate_quote_24h_volume: Decimal,
+}
+
+#[derive(Deserialize)]
+struct Accounts {
+    accounts: Vec<Account>,
+}
+
+#[derive(Deserialize)]
+struct Account {
+    currency: String,
+    available_balance: Amount,
+    hold: Amount,
+}
+
+#[derive(Deserialize)]
+struct Amount {
+    value: Decimal,
+}
+
+#[derive(Deserialize)]
+struct CreateOrderResponse {
+    success: bool,
+    #[serde(default)]
+    success_response: Option<CreatedOrder>,
+    #[serde(default)]
+    error_response: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct CreatedOrder {
+    order_id: String,
+}
+
+#[derive(Deserialize)]
+struct HistoricalOrder {
+    order: OrderResponse,
+}
+
+#[derive(Deserialize)]
+struct OrderResponse {
+    side: String,
+    status: String,
+    #[serde(deserialize_with = "zero_if_empty")]
+    filled_size: Decimal,
+    #[serde(deserialize_with = "zero_if_empty")]
+    average_filled_price: Decimal,
+    created_time: DateTime<Utc>,
+    #[serde(default)]
+    last_fill_time: Option<DateTime<Utc>>,
+}
+
+impl OrderResponse {
+    fn status(&self) -> OrderStatus {
+        match self.status.as_str() {
+            "FILLED" => OrderStatus::Filled,
+            "FAILED" => OrderStatus::Rejected,
+            "CANCELLED" | "EXPIRED" => OrderStatus::Cancelled,
+            // Pending, queued and open orders, also while they are being cancelled.
+            _ if self.filled_size.is_zero() => OrderStatus::Submitted,
+            _ => OrderStatus::PartiallyFilled,
+        }
+    }
+
+    fn info(&self, order_id: Uuid, market: Symbol) -> OrderInfo {
+        OrderInfo {
+            order_id,
+            market,
+            size: self.filled_size,
+            price: self.average_filled_price,
+            time: self.last_fill_time.unwrap_or(self.created_time),
+            side: if self.side == "BUY" {
+                Side::Buy
+            } else {
+                Side::Sell
+            },
+            status: self.status(),
+        }
+    }
+}
+
+#[async_trait]
+impl Api for Coinbase {
+    const NAME: &'static str = "Coinbase";
+    const LIVE_TRADING_ENABLED: bool = true;
+
+    async fn get_candles(
+        &self,
+        key: CandleKey,
+    ) -> Result<Vec<(CandleKey, Option<Candle>)>, ApiError> {
+        let granularity = granularity(key.interval).ok_or_else(|| {
+            tracing::error!("Coinbase does not support the interval {}", key.interval);
+            ApiError::Api
+        })?;
+
+        let response: Candles = self
+            .rest
+            .request(
+                Method::GET,
+                &format!(
+                    "/api/v3/brokerage/market/products/{}/candles",
+                    self.format_market(key.market)
+                ),
+                &[
+                    ("start", key.time.timestamp().to_string()),
+                    (
+                        "end",
+                        (key.time + key.interval * (LIMIT - 1))
+                            .timestamp()
+                            .to_string(),
+                    ),
+                    ("granularity", granularity.to_owned()),
+                ],
+                &[],
+            )
+            .await?;
+
+        let candles = response
+            .candles
+            .into_iter()
+            // Candles are returned with the latest first.
+            .rev()
+            .filter_map(|candle| {
+                let time = Utc.timestamp_opt(candle.start.parse().ok()?, 0).single()?;
+                // The last candle is still open.
+                if time + key.interval > Utc::now() {
+                    return None;
+                }
+                Some((
+                    CandleKey { time, ..key },
+                    Candle {
+                        open: candle.open,
+                        high: candle.high,
+                        low: candle.low,
+                        close: candle.close,
+                        volume: candle.volume,
+                    },
+                ))
+            });
+
+        Ok(fill_gaps(key, candles, LIMIT))
+    }
+
+    async fn place_order(&self, order: Order) -> Result<OrderInfo, ApiError> {
+        tracing::trace!("place order coinbase");
+
+        let size = order.size.normalize().to_string();
+        let configuration = match order.order_type {
+            OrderType::Market => json!({ "market_market_ioc": { "base_size": size } }),
+            OrderType::Limit(price) => {
+                let price = price.normalize().to_string();
+                match order.time_in_force {
+                    _ if order.post_only => json!({ "limit_limit_gtc": {
+                        "base_size": size,
+                        "limit_price": price,
+                        "post_only": true,
+                    }}),
+                    TimeInForce::GoodTilCancelled => json!({ "limit_limit_gtc": {
+                        "base_size": size,
+                        "limit_price": price,
+                        "post_only": false,
+                    }}),
+                    TimeInForce::ImmediateOrCancel => json!({ "sor_limit_ioc": {
+                        "base_size": size,
+                        "limit_price": price,
+                    }}),
+                    TimeInForce::FillOrKill => json!({ "limit_limit_fok": {
+                        "base_size": size,
+                        "limit_price": price,
+                    }}),
+                }
+            }
+        };
+
+        let response: CreateOrderResponse = self
+            .signed(
+                Method::POST,
+                "/api/v3/brokerage/orders",
+                &[],
+                Some(json!({
+                    "client_order_id": order.order_id.to_string(),
+                    "product_id": self.format_market(order.market),
+                    "side": match order.side {
+                        Side::Buy => "BUY",
+                        Side::Sell => "SELL",
+                    },
+                    "order_configuration": configuration,
+                })),
+            )
+            .await
+            .map_err(|err| match err {
+                ApiError::Api => ApiError::Rejected,
+                err => err,
+            })?;
+        let created = match response.success_response {
+            Some(created) if response.success => created,
+            _ => {
+                tracing::warn!(error = ?response.error_response, "Coinbase rejected the order.");
+                return Err(ApiError::Rejected);
+            }
+        };
+        self.order_ids
+            .lock()
+            .unwrap()
+            .insert(order.order_id, created.order_id);
+
+        match self.order_status(order.market, order.order_id).await {
+            Ok(info) if info.size.is_zero() => Ok(OrderInfo {
+                price: order.current_price,
+                ..info
+            }),
+            Ok(info) => Ok(info),
+            // Placed, but not queryable yet, the fill is confirmed with the order status later.
+            Err(_) => Ok(OrderInfo {
+                order_id: order.order_id,
+                market: order.market,
+                size: Decimal::ZERO,
+                price: order.current_price,
+                time: order.time,
+                side: order.side,
+                status: OrderStatus::Submitted,
+            }),
+        }
+    }
+
+    async fn order_status(&self, market: Symbol, order_id: Uuid) -> Result<OrderInfo, ApiError> {
+        let response: HistoricalOrder = self
+            .signed(
+                Method::GET,
+                &format!(
+                    "/api/v3/brokerage/orders/historical/{}",
+                    self.order_id(order_id)?
+                ),
+                &[],
+                None,
+            )
+            .await?;
+        Ok(response.order.info(order_id, market))
+    }
+
+    async fn cancel_order(&self, market: Symbol, order_id: Uuid) -> Result<OrderInfo, ApiError> {
+        // Cancelling only acknowledges the request, the final fill is queried afterwards.
+        self.signed::<Value>(
+            Method::POST,
+            "/api/v3/brokerage/orders/batch_cancel",
+            &[],
+            Some(json!({ "order_ids": [self.order_id(order_id)?] })),
+        )
+        .await?;
+        self.order_status(market, order_id).await
+    }
+
+    async fn amend_order(
+        &self,
+        market: Symbol,
+        order_id: Uuid,
+        price: Decimal,
+        size: Decimal,
+    ) -> Result<OrderInfo, ApiError> {
+        // Only good til cancelled limit orders can be edited.
+        let response: Value = self
+            .signed(
+                Method::POST,
+                "/api/v3/brokerage/orders/edit",
+                &[],
+                Some(json!({
+                    "order_id": self.order_id(order_id)?,
+                    "price": price.normalize().to_string(),
+                    "size": size.normalize().to_string(),
+                })),
+            )
+            .await?;
+        if response["success"] != Value::Bool(true) {
+            tracing::warn!(error = %response, "Coinbase rejected the edit.");
+            return Err(ApiError::Rejected);
+        }
+        self.order_status(market, order_id).await
+    }
+
+    fn format_market(&self, market: Symbol) -> String {
+        match market {
+            Symbol::Spot(asset) => format!("{}-{}", asset, self.quote_asset()),
+            // Derivatives are not traded on Coinbase, their name is rejected.
+            Symbol::Perp(_) | Symbol::Future(..) => market.to_string(),
+        }
+    }
+
+    async fn update_wallet(&self, wallet: &mut Wallet) -> Result<(), ApiError> {
+        let response: Accounts = self
+            .signed(
+                Method::GET,
+                "/api/v3/brokerage/accounts",
+                &[("limit", "250".to_owned())],
+                None,
+            )
+            .await?;
+
+        *wallet = Wallet {
+            free: response
+                .accounts
+                .iter()
+                .map(|account| {
+                    (
+                        Asset::new(&account.currency),
+                        account.available_balance.value,
+                    )
+                })
+                .collect(),
+            total: response
+                .accounts
+                .iter()
+                .map(|account| {
+                    (
+                        Asset::new(&account.currency),
+                        account.available_balance.value + account.hold.value,
+                    )
+                })
+                .collect(),
+        };
+
+        Ok(())
+    }
+
+    async fn update_markets(&self, markets: &mut Markets) -> Result<(), ApiError> {
+        let response: Products = self
+            .rest
+            .request(
+                Method::GET,
+                "/api/v3/brokerage/market/products",
+                &[("product_type", "SPOT".to_owned())],
+                &[],
+            )
+            .await?;
+
+        let quote_asset = self.quote_asset();
+        markets.markets = response
+            .products
+            .into_iter()
+            .filter(|product| {
+                product.product_type == "SPOT"
+                    && product.status == "online"
+                    && !product.trading_disabled
+                    && Asset::new(&product.quote_currency_id) == quote_asset
+            })
+            .map(|product| {
+                let symbol = Symbol::spot(&product.base_currency_id);
+                let market = MarketInfo {
+                    symbol,
+                    min_size: product.base_min_size,
+                    size_increment: product.base_increment,
+                    price_increment: product.price_increment,
+                    daily_quote_volume: product.approximate_quote_24h_volume,
+                    market_type: MarketType::Spot,
+                    contract_multiplier: Decimal::ONE,
+                    expiry: None,
+                };
+                (symbol, market)
+            })
+            .collect();
+
+        Ok(())
+    }
+
+    fn quote_asset(&self) -> Asset {
+        self.quote_asset
+    }
+
+    async fn order_fee(&self) -> Decimal {
+        // 0.006 = 0.6%, the taker fee of the lowest tier.
+        Decimal::new(6, 3)
+    }
+
+    async fn fee_schedule(&self) -> FeeSchedule {
+        FeeSchedule::new(Decimal::new(4, 3), self.order_fee().await)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::rest::Cassette;
+    use rust_decimal_macros::dec;
+
+    // Recorded responses of the Advanced Trade API, replayed without credentials.
+    fn replay() -> Coinbase {
+        let cassette = Cassette::from_file(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/fixtures/coinbase/cassette.json"
+        ))
+        .unwrap();
+        Coinbase {
+            rest: RestClient::replay(cassette),
+            key: Some("key".to_owned()),
+            secret: Some("secret".to_owned()),
+            quote_asset: Asset::new("USD"),
+            order_ids: Mutex::new(HashMap::new()),
+        }
+    }
+
+    #[test]
+    fn granularities() {
+        assert_eq!(granularity(Duration::minutes(1)), Some("ONE_MINUTE"));
+        assert_eq!(granularity(Duration::hours(6)), Some("SIX_HOUR"));
+        assert_eq!(granularity(Duration::days(1)), Some("ONE_DAY"));
+        assert_eq!(granularity(Duration::minutes(3)), None);
+        assert_eq!(granularity(Duration::seconds(90)), None);
+    }
+
+    #[tokio::test]
+    async fn replay_markets_and_wallet() {
+        let api = replay();
+        let mut markets = Markets::default();
+        api.update_markets(&mut markets).await.unwrap();
+
+        // Other quote assets and markets that are not trading are skipped.
+        assert_eq!(markets.markets().count(), 2);
+        let btc = markets.market(Symbol::spot("BTC")).unwrap();
+        assert_eq!(btc.min_size, dec!(0.00000001));
+        assert_eq!(btc.size_increment, dec!(0.00000001));
+        assert_eq!(btc.price_increment, dec!(0.01));
+        assert_eq!(btc.daily_quote_volume, dec!(412366210.26));
+        assert_eq!(btc.market_type, MarketType::Spot);
+        assert_eq!(api.format_market(btc.symbol), "BTC-USD");
+
+        let mut wallet = Wallet::new();
+        api.update_wallet(&mut wallet).await.unwrap();
+        assert_eq!(wallet.total[&Asset::new("USD")], dec!(10250.5));
+        assert_eq!(wallet.free[&Asset::new("USD")], dec!(9250.5));
+        assert_eq!(wallet.total[&Asset::new("BTC")], dec!(0.0421));
+    }
+
+    #[tokio::test]
+    async fn replay_candles() {
+        let api = replay();
+        let key = CandleKey {
+            market: Symbol::spot("BTC"),
+            time: Utc.with_ymd_and_hms(2023, 8, 1, 0, 0, 0).unwrap(),
+            interval: Duration::hours(1),
+        };
+        let candles = api.get_candles(key).await.unwrap();
+
+        assert_eq!(candles.len(), LIMIT as usize);
+        assert_eq!(candles[0].0, key);
+        assert_eq!(candles[0].1.unwrap().open, dec!(29230.01));
+        // Gaps are filled with none.
+        assert_eq!(candles[1].1, None);
+        assert_eq!(candles[2].1.unwrap().close, dec!(29301.99));
+        assert_eq!(candles[2].0.time, key.time + Duration::hours(2));
+
+        let request = &api.rest().interactions()[0];
+        assert_eq!(
+            request.path,
+            "/api/v3/brokerage/market/products/BTC-USD/candles"
+        );
+        assert_eq!(request.param("granularity"), Some("ONE_HOUR"));
+        assert_eq!(request.param("start"), Some("1690848000"));
+        assert!(api
+            .get_candles(CandleKey {
+                interval: Duration::minutes(3),
+                ..key
+            })
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn replay_orders() {
+        let api = replay();
+        let order = Order {
+            order_id: Uuid::parse_str("67e55044-10b1-426f-9247-bb680e5fe0c8").unwrap(),
+            market: Symbol::spot("BTC"),
+            side: Side::Buy,
+            size: dec!(0.0100),
+            order_type: OrderType::Limit(dec!(29300.00)),
+            reduce_only: false,
+            time_in_force: TimeInForce::GoodTilCancelled,
+            post_only: true,
+            position_side: None,
+            time: Utc.with_ymd_and_hms(2023, 8, 1, 3, 0, 0).unwrap(),
+            current_price: dec!(29301.99),
+        };
+        let info = api.place_order(order.clone()).await.unwrap();
+        // Resting orders are filled later.
+        assert_eq!(info.size, dec!(0));
+        assert_eq!(info.price, dec!(29301.99));
+        assert_eq!(info.status, OrderStatus::Submitted);
+
+        let interactions = api.rest().interactions();
+        let body = interactions[0].body.as_ref().unwrap();
+        assert_eq!(body["product_id"], "BTC-USD");
+        assert_eq!(body["side"], "BUY");
+        assert_eq!(body["client_order_id"], order.order_id.to_string());
+        let limit = &body["order_configuration"]["limit_limit_gtc"];
+        assert_eq!(limit["base_size"], "0.01");
+        assert_eq!(limit["limit_price"], "29300");
+        assert_eq!(limit["post_only"], true);
+        assert_eq!(
+            interactions[1].path,
+            "/api/v3/brokerage/orders/historical/0d2b3a6e-6b5f-4e3b-a5a0-8c62e5cde6e1"
+        );
+
+        let filled = api
+            .order_status(order.market, order.order_id)
+            .await
+            .unwrap();
+        assert_eq!(filled.size, dec!(0.01));
+        assert_eq!(filled.price, dec!(29300));
+        assert_eq!(filled.status, OrderStatus::Filled);
+
+        // Orders placed before are unknown.
+        assert!(api
+            .order_status(order.market, Uuid::new_v4())
+            .await
+            .is_err());
+
+        // Rejections of the venue are mapped.
+        let rejected = api
+            .place_order(Order {
+                order_id: Uuid::new_v4(),
+                order_type: OrderType::Market,
+                ..order
+            })
+            .await;
+        assert!(matches!(rejected, Err(ApiError::Rejected)));
+    }
+}
@@ -0,0 +1,204 @@
+use super::Api;
+use crate::{
+    apis::{ApiError, ExecutionGap, FeeSchedule, Margin, Order, OrderInfo},
+    Asset, Candle, CandleKey, Command, Markets, Orderbook, PriceSource, Symbol, Trade, Transfer,
+    Wallet,
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use std::{collections::HashMap, ops::Range, sync::Arc};
+use uuid::Uuid;
+
+/// A source of candles, implemented for every API.
+#[async_trait]
+pub trait CandleSource: Send + Sync {
+    async fn candles(&self, key: CandleKey) -> Result<Vec<(CandleKey, Option<Candle>)>, ApiError>;
+}
+
+#[async_trait]
+impl<A: Api> CandleSource for A {
+    async fn candles(&self, key: CandleKey) -> Result<Vec<(CandleKey, Option<Candle>)>, ApiError> {
+        self.get_candles(key).await
+    }
+}
+
+/// The Composite API fetches the candles of some symbols from other sources,
+/// e.g. an illiquid alt from a vendor CSV with `FileData`, and everything else from the API.
+/// Orders, the wallet and the markets are always handled by the API.
+pub struct Composite<A>
+where
+    A: Api,
+{
+    api: A,
+    routes: HashMap<Symbol, Arc<dyn CandleSource>>,
+}
+
+impl<A> Composite<A>
+where
+    A: Api,
+{
+    pub fn new(api: A) -> Self {
+        Composite {
+            api,
+            routes: HashMap::new(),
+        }
+    }
+
+    /// Fetch the candles of the symbol from the source.
+    pub fn with_route(mut self, symbol: Symbol, source: Arc<dyn CandleSource>) -> Self {
+        self.routes.insert(symbol, source);
+        self
+    }
+
+    /// Fetch the candles of all the symbols from the source.
+    pub fn with_routes(
+        mut self,
+        symbols: impl IntoIterator<Item = Symbol>,
+        source: Arc<dyn CandleSource>,
+    ) -> Self {
+        for symbol in symbols {
+            self.routes.insert(symbol, source.clone());
+        }
+        self
+    }
+}
+
+#[async_trait]
+impl<A: Api> Api for Composite<A> {
+    const NAME: &'static str = A::NAME;
+    const LIVE_TRADING_ENABLED: bool = A::LIVE_TRADING_ENABLED;
+
+    async fn get_candles(
+        &self,
+        key: CandleKey,
+    ) -> Result<Vec<(CandleKey, Option<Candle>)>, ApiError> {
+        match self.routes.get(&key.market) {
+            Some(source) => source.candles(key).await,
+            None => self.api.get_candles(key).await,
+        }
+    }
+
+    async fn place_order(&self, order: Order) -> Result<OrderInfo, ApiError> {
+        self.api.place_order(order).await
+    }
+
+    async fn place_orders(&self, orders: Vec<Order>) -> Vec<Result<OrderInfo, ApiError>> {
+        self.api.place_orders(orders).await
+    }
+
+    async fn order_status(&self, market: Symbol, order_id: Uuid) -> Result<OrderInfo, ApiError> {
+        self.api.order_status(market, order_id).await
+    }
+
+    async fn cancel_order(&self, market: Symbol, order_id: Uuid) -> Result<OrderInfo, ApiError> {
+        self.api.cancel_order(market, order_id).await
+    }
+
+    async fn amend_order(
+        &self,
+        market: Symbol,
+        order_id: Uuid,
+        price: Decimal,
+        size: Decimal,
+    ) -> Result<OrderInfo, ApiError> {
+        self.api.amend_order(market, order_id, price, size).await
+    }
+
+    fn format_market(&self, market: Symbol) -> String {
+        self.api.format_market(market)
+    }
+
+    async fn update_wallet(&self, wallet: &mut Wallet) -> Result<(), ApiError> {
+        self.api.update_wallet(wallet).await
+    }
+
+    async fn update_markets(&self, markets: &mut Markets) -> Result<(), ApiError> {
+        self.api.update_markets(markets).await
+    }
+
+    fn quote_asset(&self) -> Asset {
+        self.api.quote_asset()
+    }
+
+    async fn order_fee(&self) -> Decimal {
+        self.api.order_fee().await
+    }
+
+    async fn get_orderbook(
+        &self,
+        market: Symbol,
+        time: DateTime<Utc>,
+    ) -> Result<Option<Orderbook>, ApiError> {
+        self.api.get_orderbook(market, time).await
+    }
+
+    async fn get_trades(
+        &self,
+        market: Symbol,
+        range: Range<DateTime<Utc>>,
+    ) -> Result<Option<Vec<Trade>>, ApiError> {
+        self.api.get_trades(market, range).await
+    }
+
+    async fn get_price(
+        &self,
+        key: CandleKey,
+        source: PriceSource,
+    ) -> Result<Option<Decimal>, ApiError> {
+        self.api.get_price(key, source).await
+    }
+
+    async fn get_prices(
+        &self,
+        key: CandleKey,
+        source: PriceSource,
+    ) -> Result<Vec<(CandleKey, Option<Decimal>)>, ApiError> {
+        self.api.get_prices(key, source).await
+    }
+
+    async fn fee_schedule(&self) -> FeeSchedule {
+        self.api.fee_schedule().await
+    }
+
+    async fn borrow_rate(&self, market: Symbol) -> Option<Decimal> {
+        self.api.borrow_rate(market).await
+    }
+
+    async fn borrow_costs(&self, time: DateTime<Utc>) -> Result<Decimal, ApiError> {
+        self.api.borrow_costs(time).await
+    }
+
+    async fn convert_dust(&self, assets: Vec<Asset>) -> Result<(), ApiError> {
+        self.api.convert_dust(assets).await
+    }
+
+    async fn transfer(&self, transfer: &Transfer) -> Result<(), ApiError> {
+        self.api.transfer(transfer).await
+    }
+
+    async fn save_state(&self, strategy_name: &str, state: String) -> Result<(), ApiError> {
+        self.api.save_state(strategy_name, state).await
+    }
+
+    async fn load_state(&self, strategy_name: &str) -> Result<Option<String>, ApiError> {
+        self.api.load_state(strategy_name).await
+    }
+
+    fn margin(&self) -> Option<Margin> {
+        self.api.margin()
+    }
+
+    async fn shutdown(&self) {
+        self.api.shutdown().await
+    }
+
+    fn execution_gap(&self) -> Option<ExecutionGap> {
+        self.api.execution_gap()
+    }
+
+    async fn commands(&self) -> Vec<Command> {
+        self.api.commands().await
+    }
+}
@@ -0,0 +1,98 @@
+use crate::Symbol;
+use rust_decimal::prelude::*;
+use std::collections::HashMap;
+
+/// The fee rates of a market, as fractions of the order value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fees {
+    /// Charged for orders adding liquidity to the book.
+    pub maker: Decimal,
+    /// Charged for orders taking liquidity from the book.
+    pub taker: Decimal,
+}
+
+/// The fees of a venue, with per-market overrides and tiers by traded volume.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeeSchedule {
+    base: Fees,
+    markets: HashMap<Symbol, Fees>,
+    // Sorted by the minimum volume of the tier.
+    tiers: Vec<(Decimal, Fees)>,
+    // The schedules of the venues of a `Router`.
+    venues: HashMap<&'static str, FeeSchedule>,
+}
+
+impl FeeSchedule {
+    pub fn new(maker: Decimal, taker: Decimal) -> Self {
+        FeeSchedule {
+            base: Fees { maker, taker },
+            markets: HashMap::new(),
+            tiers: Vec::new(),
+            venues: HashMap::new(),
+        }
+    }
+
+    /// The same fee for makers and takers.
+    pub fn flat(fee: Decimal) -> Self {
+        Self::new(fee, fee)
+    }
+
+    /// Use other fees for a market, regardless of the traded volume.
+    pub fn with_market(mut self, symbol: Symbol, maker: Decimal, taker: Decimal) -> Self {
+        self.markets.insert(symbol, Fees { maker, taker });
+        self
+    }
+
+    /// Use other fees once the traded quote volume of the last 30 days reaches `min_volume`.
+    pub fn with_tier(mut self, min_volume: Decimal, maker: Decimal, taker: Decimal) -> Self {
+        self.tiers.push((min_volume, Fees { maker, taker }));
+        self.tiers.sort_by_key(|(min_volume, _)| *min_volume);
+        self
+    }
+
+    // Use the schedule of a venue for its markets, namespaced by a `Router`.
+    pub(crate) fn with_venue(mut self, venue: &'static str, schedule: FeeSchedule) -> Self {
+        self.venues.insert(venue, schedule);
+        self
+    }
+
+    /// The fees of a market given the traded quote volume of the last 30 days.
+    pub fn fees(&self, symbol: Symbol, volume: Decimal) -> Fees {
+        if let Some(schedule) = symbol.venue().and_then(|venue| self.venues.get(venue)) {
+            return schedule.fees(symbol.local(), volume);
+        }
+        if let Some(fees) = self.markets.get(&symbol) {
+            return *fees;
+        }
+
+        self.tiers
+            .iter()
+            .rev()
+            .find(|(min_volume, _)| volume >= *min_volume)
+            .map(|(_, fees)| *fees)
+            .unwrap_or(self.base)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn tiers_and_overrides() {
+        let schedule = FeeSchedule::new(dec!(0.0002), dec!(0.0005))
+            .with_tier(dec!(50_000_000), dec!(0.00014), dec!(0.00035))
+            .with_tier(dec!(15_000_000), dec!(0.00016), dec!(0.0004))
+            .with_market(Symbol::perp("ETH"), dec!(0), dec!(0.0003));
+
+        let btc = Symbol::perp("BTC");
+        assert_eq!(schedule.fees(btc, dec!(1000)).taker, dec!(0.0005));
+        assert_eq!(schedule.fees(btc, dec!(20_000_000)).taker, dec!(0.0004));
+        assert_eq!(schedule.fees(btc, dec!(50_000_000)).maker, dec!(0.00014));
+        assert_eq!(
+            schedule.fees(Symbol::perp("ETH"), dec!(50_000_000)).maker,
+            dec!(0)
+        );
+    }
+}
@@ -0,0 +1,143 @@
+use super::Api;
+use crate::{
+    apis::{ApiError, Order, OrderInfo},
+    Asset, Candle, CandleKey, Markets, Symbol, Wallet,
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use rust_decimal::Decimal;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs, io,
+    path::Path,
+    str::FromStr,
+};
+
+// Maximum number of candles returned per request, as by most exchanges.
+const PAGE_SIZE: usize = 5000;
+
+/// The FileData API reads candles from CSV files, e.g. data bought from a vendor.
+/// It only provides candles, route symbols to it with `Composite` to trade them.
+///
+/// Every file holds the candles of one symbol and interval, with the header
+/// `time,open,high,low,close,volume`. Times are RFC 3339 or Unix timestamps in seconds.
+#[derive(Default)]
+pub struct FileData {
+    #[allow(clippy::type_complexity)]
+    candles: HashMap<(Symbol, Duration), BTreeMap<DateTime<Utc>, Candle>>,
+}
+
+impl FileData {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Read the candles of the symbol from a CSV file.
+    pub fn with_file<P: AsRef<Path>>(
+        mut self,
+        symbol: Symbol,
+        interval: Duration,
+        path: P,
+    ) -> io::Result<Self> {
+        let candles = parse(&fs::read_to_string(path)?)?;
+        self.candles
+            .entry((symbol, interval))
+            .or_default()
+            .extend(candles);
+        Ok(self)
+    }
+}
+
+fn parse(csv: &str) -> io::Result<Vec<(DateTime<Utc>, Candle)>> {
+    let invalid = |line: usize, what: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} in line {}", what, line + 1),
+        )
+    };
+
+    let mut candles = Vec::new();
+    for (i, line) in csv.lines().enumerate().skip(1) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields: Vec<_> = line.split(',').map(str::trim).collect();
+        let [time, open, high, low, close, volume] = fields[..] else {
+            return Err(invalid(i, "Expected 6 fields"));
+        };
+        let time = match time.parse::<i64>() {
+            Ok(seconds) => Utc.timestamp_opt(seconds, 0).single(),
+            Err(_) => DateTime::parse_from_rfc3339(time)
+                .ok()
+                .map(|time| time.with_timezone(&Utc)),
+        }
+        .ok_or_else(|| invalid(i, "Invalid time"))?;
+        let decimal =
+            |field: &str| Decimal::from_str(field).map_err(|_| invalid(i, "Invalid number"));
+        candles.push((
+            time,
+            Candle {
+                open: decimal(open)?,
+                high: decimal(high)?,
+                low: decimal(low)?,
+                close: decimal(close)?,
+                volume: decimal(volume)?,
+            },
+        ));
+    }
+    Ok(candles)
+}
+
+#[async_trait]
+impl Api for FileData {
+    const NAME: &'static str = "FileData";
+    const LIVE_TRADING_ENABLED: bool = false;
+
+    /// Missing candles between stored ones are returned as None.
+    async fn get_candles(
+        &self,
+        key: CandleKey,
+    ) -> Result<Vec<(CandleKey, Option<Candle>)>, ApiError> {
+        let candles = self
+            .candles
+            .get(&(key.market, key.interval))
+            .ok_or(ApiError::Unsupported)?;
+        let last = match candles.range(key.time..).take(PAGE_SIZE).last() {
+            Some((&last, _)) => last,
+            None => return Ok(Vec::new()),
+        };
+
+        let mut out = Vec::new();
+        let mut time = key.time;
+        while time <= last && out.len() < PAGE_SIZE {
+            out.push((CandleKey { time, ..key }, candles.get(&time).copied()));
+            time += key.interval;
+        }
+        Ok(out)
+    }
+
+    async fn place_order(&self, _order: Order) -> Result<OrderInfo, ApiError> {
+        Err(ApiError::Unsupported)
+    }
+
+    fn format_market(&self, market: Symbol) -> String {
+        market.to_string()
+    }
+
+    async fn update_wallet(&self, _wallet: &mut Wallet) -> Result<(), ApiError> {
+        Ok(())
+    }
+
+    async fn update_markets(&self, _markets: &mut Markets) -> Result<(), ApiError> {
+        Ok(())
+    }
+
+    fn quote_asset(&self) -> Asset {
+        Asset::new("USD")
+    }
+
+    async fn order_fee(&self) -> Decimal {
+        Decimal::ZERO
+    }
+}
@@ -1,14 +1,33 @@
 use super::Api;
 use crate::{
-    apis::{ApiError, Order, OrderInfo},
-    Asset, Candle, CandleKey, Markets, Symbol, Wallet,
+    apis::{ApiError, ExecutionGap, FeeSchedule, Margin, Order, OrderInfo},
+    Asset, Candle, CandleKey, Command, Markets, Orderbook, PriceSource, Symbol, Trade, Transfer,
+    Wallet,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
 };
-use std::collections::HashMap;
 
 use async_trait::async_trait;
 use chrono::{DateTime, Duration, Utc};
 use futures_util::lock::Mutex;
 use rust_decimal::Decimal;
+use uuid::Uuid;
+
+/// How the Forward Fill API handles gaps longer than the maximum forward fill duration.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum OnGap {
+    /// Fail with `ApiError::GapTooLarge`.
+    #[default]
+    Error,
+    /// Stop forward filling the market, its unknown candles stay unknown from then on.
+    SkipSymbol,
+    /// Forward fill the candle regardless of the length of the gap.
+    FillAnyway,
+    /// Leave the candle unknown, forward filling resumes after the next known candle.
+    DropCandle,
+}
 
 /// The Forward Fill API forward fills unknown candles using the last known candle data.
 /// This is useful in case your strategy does not support undefined candles.
@@ -18,19 +37,71 @@ where
 {
     #[allow(clippy::type_complexity)]
     cache: Mutex<HashMap<(Symbol, Duration), (DateTime<Utc>, Candle)>>,
+    // Markets that are not forward filled anymore, with `OnGap::SkipSymbol`.
+    skipped: Mutex<HashSet<Symbol>>,
     api: A,
     max_duration: Duration,
+    on_gap: OnGap,
 }
 
 impl<A> ForwardFill<A>
 where
     A: Api,
 {
-    pub fn new(api: A, max_duration: Duration) -> Self {
+    pub fn new(api: A, max_duration: Duration, on_gap: OnGap) -> Self {
         ForwardFill {
             cache: Mutex::new(HashMap::new()),
+            skipped: Mutex::new(HashSet::new()),
             api,
             max_duration,
+            on_gap,
+        }
+    }
+
+    // The candle to fill in for the unknown candle of the key, if any.
+    async fn fill(
+        &self,
+        key: CandleKey,
+        last: Option<(DateTime<Utc>, Candle)>,
+    ) -> Result<Option<Candle>, ApiError> {
+        let Some((time, candle)) = last else {
+            return Ok(None);
+        };
+        if self.skipped.lock().await.contains(&key.market) {
+            return Ok(None);
+        }
+        if key.time.signed_duration_since(time) <= self.max_duration {
+            tracing::warn!(market = %key.market, time = %key.time, "Forward filling candle.");
+            return Ok(Some(candle));
+        }
+
+        match self.on_gap {
+            OnGap::Error => Err(ApiError::GapTooLarge(key.market)),
+            OnGap::SkipSymbol => {
+                tracing::warn!(
+                    market = %key.market,
+                    time = %key.time,
+                    "Gap too large to forward fill, not forward filling the market anymore."
+                );
+                self.skipped.lock().await.insert(key.market);
+                Ok(None)
+            }
+            OnGap::FillAnyway => {
+                tracing::warn!(
+                    market = %key.market,
+                    time = %key.time,
+                    "Forward filling candle over a large gap."
+                );
+                Ok(Some(candle))
+            }
+            OnGap::DropCandle => {
+                tracing::warn!(
+                    market = %key.market,
+                    time = %key.time,
+                    "Gap too large to forward fill, dropping the candle."
+                );
+                Ok(None)
+            }
         }
     }
 }
@@ -51,15 +122,9 @@ impl<A: Api> Api for ForwardFill<A> {
             if key.time >= Utc::now() - key.interval * 2 {
                 // Do not forward fill candles in the future.
                 Ok(Vec::new())
-            } else if let Some((time, candle)) = cache.get(&(key.market, key.interval)) {
-                if key.time.signed_duration_since(*time) <= self.max_duration {
-                    log::warn!("Forward filling candle for time {}.", key.time);
-                    Ok(vec![(key, Some(*candle))])
-                } else {
-                    panic!("Gap too large to forward fill.");
-                }
             } else {
-                Ok(vec![(key, None)])
+                let last = cache.get(&(key.market, key.interval)).copied();
+                Ok(vec![(key, self.fill(key, last).await?)])
             }
         } else {
             for (key, maybe_candle) in candles.iter_mut() {
@@ -68,13 +133,9 @@ impl<A: Api> Api for ForwardFill<A> {
                 } else if key.time >= Utc::now() - key.interval * 2 {
                     // Do not forward fill candles in the future.
                     break;
-                } else if let Some((time, candle)) = cache.get(&(key.market, key.interval)) {
-                    if key.time.signed_duration_since(*time) <= self.max_duration {
-                        log::warn!("Forward filling candle for time {}.", key.time);
-                        *maybe_candle = Some(*candle);
-                    } else {
-                        panic!("Gap too large forward fill.");
-                    }
+                } else {
+                    let last = cache.get(&(key.market, key.interval)).copied();
+                    *maybe_candle = self.fill(*key, last).await?;
                 }
             }
 
@@ -86,6 +147,28 @@ impl<A: Api> Api for ForwardFill<A> {
         self.api.place_order(order).await
     }
 
+    async fn place_orders(&self, orders: Vec<Order>) -> Vec<Result<OrderInfo, ApiError>> {
+        self.api.place_orders(orders).await
+    }
+
+    async fn order_status(&self, market: Symbol, order_id: Uuid) -> Result<OrderInfo, ApiError> {
+        self.api.order_status(market, order_id).await
+    }
+
+    async fn cancel_order(&self, market: Symbol, order_id: Uuid) -> Result<OrderInfo, ApiError> {
+        self.api.cancel_order(market, order_id).await
+    }
+
+    async fn amend_order(
+        &self,
+        market: Symbol,
+        order_id: Uuid,
+        price: Decimal,
+        size: Decimal,
+    ) -> Result<OrderInfo, ApiError> {
+        self.api.amend_order(market, order_id, price, size).await
+    }
+
     fn format_market(&self, market: Symbol) -> String {
         self.api.format_market(market)
     }
@@ -105,4 +188,80 @@ impl<A: Api> Api for ForwardFill<A> {
     async fn order_fee(&self) -> Decimal {
         self.api.order_fee().await
     }
+
+    async fn get_orderbook(
+        &self,
+        market: Symbol,
+        time: DateTime<Utc>,
+    ) -> Result<Option<Orderbook>, ApiError> {
+        self.api.get_orderbook(market, time).await
+    }
+
+    async fn get_trades(
+        &self,
+        market: Symbol,
+        range: Range<DateTime<Utc>>,
+    ) -> Result<Option<Vec<Trade>>, ApiError> {
+        self.api.get_trades(market, range).await
+    }
+
+    async fn get_price(
+        &self,
+        key: CandleKey,
+        source: PriceSource,
+    ) -> Result<Option<Decimal>, ApiError> {
+        self.api.get_price(key, source).await
+    }
+
+    async fn get_prices(
+        &self,
+        key: CandleKey,
+        source: PriceSource,
+    ) -> Result<Vec<(CandleKey, Option<Decimal>)>, ApiError> {
+        self.api.get_prices(key, source).await
+    }
+
+    async fn fee_schedule(&self) -> FeeSchedule {
+        self.api.fee_schedule().await
+    }
+
+    async fn borrow_rate(&self, market: Symbol) -> Option<Decimal> {
+        self.api.borrow_rate(market).await
+    }
+
+    async fn borrow_costs(&self, time: DateTime<Utc>) -> Result<Decimal, ApiError> {
+        self.api.borrow_costs(time).await
+    }
+
+    async fn convert_dust(&self, assets: Vec<Asset>) -> Result<(), ApiError> {
+        self.api.convert_dust(assets).await
+    }
+
+    async fn transfer(&self, transfer: &Transfer) -> Result<(), ApiError> {
+        self.api.transfer(transfer).await
+    }
+
+    async fn save_state(&self, strategy_name: &str, state: String) -> Result<(), ApiError> {
+        self.api.save_state(strategy_name, state).await
+    }
+
+    async fn load_state(&self, strategy_name: &str) -> Result<Option<String>, ApiError> {
+        self.api.load_state(strategy_name).await
+    }
+
+    fn margin(&self) -> Option<Margin> {
+        self.api.margin()
+    }
+
+    async fn shutdown(&self) {
+        self.api.shutdown().await
+    }
+
+    fn execution_gap(&self) -> Option<ExecutionGap> {
+        self.api.execution_gap()
+    }
+
+    async fn commands(&self) -> Vec<Command> {
+        self.api.commands().await
+    }
 }
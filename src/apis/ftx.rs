@@ -1,21 +1,33 @@
 use super::{Order, OrderInfo};
 use crate::{
-    apis::{Api, ApiError},
-    Asset, Candle, CandleKey, MarketInfo, Markets, OrderType, Side, Symbol, Wallet,
+    apis::{Api, ApiError, FeeSchedule},
+    Account, Asset, Candle, CandleKey, MarketInfo, Markets, OrderStatus, OrderType, Side, Symbol,
+    TimeInForce, Transfer, Wallet,
 };
 use async_trait::async_trait;
 use chrono::Utc;
 use ftx::{
     options::{Endpoint, Options},
-    rest::{GetHistoricalPrices, GetWalletBalances, PlaceOrder, Rest},
+    rest::{
+        CancelOrderByClientId, GetHistoricalPrices, GetOrderByClientId, GetWalletBalances,
+        ModifyOrderByClientId, PlaceOrder, Rest, TransferBetweenSubaccounts,
+    },
     ws::MarketType,
 };
 use rust_decimal::prelude::*;
 use std::env;
+use uuid::Uuid;
 
+/// The FTX exchange, which shut down in November 2022.
+/// Only kept behind the `ftx-legacy` feature to backtest on previously stored data.
+/// Requests are sent by the client of the `ftx` crate, not a `rest::RestClient`,
+/// so they cannot be replayed from a cassette; only the conversion of responses is tested.
 pub struct Ftx {
     rest: Rest,
     //options: Options,
+    quote_asset: Asset,
+    // The subaccount traded with, None for the main account.
+    subaccount: Option<String>,
 }
 
 impl Ftx {
@@ -34,10 +46,18 @@ impl Ftx {
         };
 
         Ftx {
+            subaccount: options.subaccount.clone(),
             rest: Rest::new(options),
             //options,
+            quote_asset: Asset::new("USD"),
         }
     }
+
+    /// Account in another quote asset than USD, e.g. USDT.
+    pub fn with_quote_asset(mut self, asset: Asset) -> Self {
+        self.quote_asset = asset;
+        self
+    }
 }
 
 #[async_trait]
@@ -76,18 +96,7 @@ impl Api for Ftx {
             .await
             .unwrap_or_else(|err| panic!("Request failed for: {:?}\nError: {:?}", req, err))
             .into_iter()
-            .map(|candle| {
-                (
-                    CandleKey {
-                        time: candle.start_time,
-                        ..key
-                    },
-                    Candle {
-                        close: candle.close,
-                        volume: candle.volume,
-                    },
-                )
-            })
+            .map(|candle| candle_entry(key, candle))
             .collect();
 
         let mut out = Vec::new();
@@ -107,17 +116,17 @@ impl Api for Ftx {
 
         'result_loop: for (curr_key, candle) in candles {
             while next_key != curr_key {
-                log::trace!("Got NO candle for time {}", next_key.time);
+                tracing::trace!("Got NO candle for time {}", next_key.time);
                 out.push((next_key, None));
-                next_key.time = next_key.time + next_key.interval;
+                next_key.time += next_key.interval;
                 if next_key.time >= key.time + key.interval * 5000 {
                     break 'result_loop;
                 }
             }
             assert_eq!(next_key, curr_key);
-            log::trace!("Got candle for time {}", next_key.time);
+            tracing::trace!("Got candle for time {}", next_key.time);
             out.push((curr_key, Some(candle)));
-            next_key.time = next_key.time + next_key.interval;
+            next_key.time += next_key.interval;
         }
         for _ in out.len()..5000 {
             // Do not fill candles in the future with none.
@@ -125,7 +134,7 @@ impl Api for Ftx {
                 break;
             }
             out.push((next_key, None));
-            next_key.time = next_key.time + next_key.interval;
+            next_key.time += next_key.interval;
         }
 
         Ok(out)
@@ -143,9 +152,14 @@ impl Api for Ftx {
     */
 
     async fn place_order(&self, order: Order) -> Result<OrderInfo, ApiError> {
-        log::trace!("place order ftx");
+        tracing::trace!("place order ftx");
+
+        // Ftx has no fill or kill orders.
+        if order.time_in_force == TimeInForce::FillOrKill {
+            tracing::warn!("Ftx does not support fill or kill orders");
+            return Err(ApiError::Rejected);
+        }
 
-        let is_market_order = order.order_type == OrderType::Market;
         self.rest
             .request(PlaceOrder {
                 market: self.format_market(order.market),
@@ -163,28 +177,50 @@ impl Api for Ftx {
                 },
                 size: order.size,
                 reduce_only: order.reduce_only,
-                ioc: is_market_order,
-                post_only: !is_market_order,
+                ioc: order.time_in_force == TimeInForce::ImmediateOrCancel,
+                post_only: order.post_only,
                 client_id: Some(order.order_id.to_string()),
                 ..Default::default()
             })
             .await
-            .map(|info| OrderInfo {
-                order_id: order.order_id,
-                price: info.avg_fill_price.unwrap(),
-                size: info.filled_size.unwrap_or(Decimal::ZERO),
-                time: info.created_at,
-                market: order.market,
-                side: order.side,
-            })
-            .map_err(|err| match err {
-                ftx::rest::Error::Api(_) => ApiError::Api,
-                ftx::rest::Error::PlacingLimitOrderRequiresPrice => ApiError::Api,
-                ftx::rest::Error::NoSecretConfigured => ApiError::Api,
-                ftx::rest::Error::SerdeQs(_) => ApiError::Api,
-                ftx::rest::Error::Reqwest(_) => ApiError::Network,
-                ftx::rest::Error::Json(_) => ApiError::Api,
+            .map(|info| placed_order_info(&order, info))
+            .map_err(api_error)
+    }
+
+    async fn order_status(&self, market: Symbol, order_id: Uuid) -> Result<OrderInfo, ApiError> {
+        self.rest
+            .request(GetOrderByClientId::new(&order_id.to_string()))
+            .await
+            .map(|info| order_info(order_id, market, info))
+            .map_err(api_error)
+    }
+
+    async fn cancel_order(&self, market: Symbol, order_id: Uuid) -> Result<OrderInfo, ApiError> {
+        // Cancelling only acknowledges the request, the final fill is queried afterwards.
+        self.rest
+            .request(CancelOrderByClientId::new(&order_id.to_string()))
+            .await
+            .map_err(api_error)?;
+        self.order_status(market, order_id).await
+    }
+
+    async fn amend_order(
+        &self,
+        market: Symbol,
+        order_id: Uuid,
+        price: Decimal,
+        size: Decimal,
+    ) -> Result<OrderInfo, ApiError> {
+        // Ftx replaces the order by a new one, which keeps the client id.
+        self.rest
+            .request(ModifyOrderByClientId {
+                client_id: order_id.to_string(),
+                price: Some(price),
+                size: Some(size),
             })
+            .await
+            .map(|info| order_info(order_id, market, info))
+            .map_err(api_error)
     }
     /*
     async fn order_update(&self, asset: Asset) -> Pin<Box<dyn Stream<Item = OrderUpdate>>> {
@@ -213,10 +249,29 @@ impl Api for Ftx {
             .boxed()
     }
     */
+    async fn transfer(&self, transfer: &Transfer) -> Result<(), ApiError> {
+        let name = |account: &Account| match account {
+            Account::Trading => self.subaccount.clone().unwrap_or_else(|| "main".to_owned()),
+            Account::Main => "main".to_owned(),
+            Account::Subaccount(name) => name.clone(),
+        };
+        self.rest
+            .request(TransferBetweenSubaccounts {
+                coin: transfer.asset.to_string(),
+                size: transfer.amount,
+                source: name(&transfer.from),
+                destination: name(&transfer.to),
+            })
+            .await
+            .map(|_| ())
+            .map_err(api_error)
+    }
+
     fn format_market(&self, market: Symbol) -> String {
         match market {
-            //Symbol::Spot(base, quote) => format!("{}/{}", base, quote),
+            Symbol::Spot(asset) => format!("{}/{}", asset, self.quote_asset),
             Symbol::Perp(asset) => format!("{}-PERP", asset),
+            Symbol::Future(asset, expiry) => format!("{}-{}", asset, expiry.format("%m%d")),
         }
     }
 
@@ -249,33 +304,188 @@ impl Api for Ftx {
             .await
             .map_err(|_| ApiError::Network)?
             .into_iter()
-            .filter_map(|market| {
-                if market.market_type != MarketType::Future || !market.name.ends_with("PERP") {
-                    return None;
-                }
-                let symbol = Symbol::perp(market.underlying?);
-                Some((
-                    symbol,
-                    MarketInfo {
-                        symbol,
-                        min_size: market.min_provide_size,
-                        size_increment: market.size_increment,
-                        price_increment: market.price_increment,
-                        daily_quote_volume: market.quote_volume24h,
-                    },
-                ))
-            })
+            .filter_map(market_info)
             .collect();
 
         Ok(())
     }
 
     fn quote_asset(&self) -> Asset {
-        Asset::new("USD")
+        self.quote_asset
     }
 
     async fn order_fee(&self) -> Decimal {
         // 0.0007 = 0.07%
         Decimal::new(7, 4)
     }
+
+    async fn fee_schedule(&self) -> FeeSchedule {
+        FeeSchedule::new(Decimal::new(2, 4), self.order_fee().await)
+    }
+}
+
+fn api_error(err: ftx::rest::Error) -> ApiError {
+    match err {
+        ftx::rest::Error::Api(_) => ApiError::Api,
+        ftx::rest::Error::PlacingLimitOrderRequiresPrice => ApiError::Api,
+        ftx::rest::Error::NoSecretConfigured => ApiError::Api,
+        ftx::rest::Error::SerdeQs(_) => ApiError::Api,
+        ftx::rest::Error::Reqwest(_) => ApiError::Network,
+        ftx::rest::Error::Json(_) => ApiError::Api,
+    }
+}
+
+fn candle_entry(key: CandleKey, candle: ftx::rest::Candle) -> (CandleKey, Candle) {
+    (
+        CandleKey {
+            time: candle.start_time,
+            ..key
+        },
+        Candle {
+            open: candle.open,
+            high: candle.high,
+            low: candle.low,
+            close: candle.close,
+            volume: candle.volume,
+        },
+    )
+}
+
+// Only perpetual futures are traded.
+fn market_info(market: ftx::rest::Market) -> Option<(Symbol, MarketInfo)> {
+    if market.market_type != MarketType::Future || !market.name.ends_with("PERP") {
+        return None;
+    }
+    let symbol = Symbol::perp(market.underlying?);
+    Some((
+        symbol,
+        MarketInfo {
+            symbol,
+            min_size: market.min_provide_size,
+            size_increment: market.size_increment,
+            price_increment: market.price_increment,
+            daily_quote_volume: market.quote_volume24h,
+            market_type: crate::MarketType::Perpetual,
+            contract_multiplier: Decimal::ONE,
+            expiry: None,
+        },
+    ))
+}
+
+// The response to a placed order, which has no fill price until it is filled.
+fn placed_order_info(order: &Order, info: ftx::rest::OrderInfo) -> OrderInfo {
+    OrderInfo {
+        price: info.avg_fill_price.unwrap_or(order.current_price),
+        ..order_info(order.order_id, order.market, info)
+    }
+}
+
+fn order_info(order_id: Uuid, market: Symbol, info: ftx::rest::OrderInfo) -> OrderInfo {
+    let filled = info.filled_size.unwrap_or(Decimal::ZERO);
+    OrderInfo {
+        order_id,
+        market,
+        size: filled,
+        price: info.avg_fill_price.or(info.price).unwrap_or(Decimal::ZERO),
+        time: info.created_at,
+        side: match info.side {
+            ftx::rest::Side::Buy => Side::Buy,
+            ftx::rest::Side::Sell => Side::Sell,
+        },
+        status: match info.status {
+            ftx::rest::OrderStatus::Closed => OrderStatus::settled(info.size, filled),
+            _ if filled.is_zero() => OrderStatus::Submitted,
+            _ => OrderStatus::PartiallyFilled,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+    use rust_decimal_macros::dec;
+    use serde::{de::DeserializeOwned, Deserialize};
+
+    // The envelope of all responses of the FTX REST API.
+    #[derive(Deserialize)]
+    struct Response<T> {
+        success: bool,
+        result: T,
+    }
+
+    // The result of a response recorded before FTX shut down. The `ftx` client has its own
+    // transport, so the recorded results are passed to the conversions instead of the client.
+    fn fixture<T: DeserializeOwned>(name: &str) -> T {
+        let path = format!("{}/fixtures/ftx/{}.json", env!("CARGO_MANIFEST_DIR"), name);
+        let response: Response<T> =
+            serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        assert!(response.success);
+        response.result
+    }
+
+    #[test]
+    fn recorded_markets() {
+        let markets: Vec<(Symbol, MarketInfo)> = fixture::<Vec<ftx::rest::Market>>("markets")
+            .into_iter()
+            .filter_map(market_info)
+            .collect();
+
+        // Spot markets and dated futures are skipped.
+        assert_eq!(markets.len(), 1);
+        let (symbol, info) = markets[0];
+        assert_eq!(symbol, Symbol::perp("BTC"));
+        assert_eq!(info.min_size, dec!(0.001));
+        assert_eq!(info.price_increment, dec!(1));
+        assert_eq!(info.daily_quote_volume, dec!(1946414316.8956));
+    }
+
+    #[test]
+    fn recorded_candles() {
+        let key = CandleKey {
+            market: Symbol::perp("BTC"),
+            time: Utc.with_ymd_and_hms(2021, 8, 1, 0, 0, 0).unwrap(),
+            interval: Duration::minutes(1),
+        };
+        let candles: Vec<(CandleKey, Candle)> =
+            fixture::<Vec<ftx::rest::Candle>>("historical_prices")
+                .into_iter()
+                .map(|candle| candle_entry(key, candle))
+                .collect();
+
+        assert_eq!(candles.len(), 2);
+        assert_eq!(candles[0].0, key);
+        assert_eq!(candles[1].0.time, key.time + Duration::minutes(1));
+        assert_eq!(candles[1].1.close, dec!(41512));
+        assert_eq!(candles[1].1.volume, dec!(3871245.1052));
+    }
+
+    #[test]
+    fn recorded_orders() {
+        let order = Order {
+            order_id: Uuid::parse_str("67e55044-10b1-426f-9247-bb680e5fe0c8").unwrap(),
+            market: Symbol::perp("BTC"),
+            side: Side::Sell,
+            size: dec!(0.5),
+            order_type: OrderType::Limit(dec!(41600)),
+            reduce_only: false,
+            time_in_force: TimeInForce::GoodTilCancelled,
+            post_only: true,
+            position_side: None,
+            time: Utc.with_ymd_and_hms(2021, 8, 1, 0, 1, 0).unwrap(),
+            current_price: dec!(41512),
+        };
+
+        // Resting orders have no fill price yet.
+        let placed = placed_order_info(&order, fixture("place_order"));
+        assert_eq!(placed.price, dec!(41512));
+        assert_eq!(placed.size, dec!(0));
+        assert_eq!(placed.status, OrderStatus::Submitted);
+
+        let status = order_info(order.order_id, order.market, fixture("order_status"));
+        assert_eq!(status.side, Side::Sell);
+        assert_eq!(status.price, dec!(41600));
+        assert_eq!(status.size, dec!(0.2));
+        assert_eq!(status.status, OrderStatus::Cancelled);
+    }
 }
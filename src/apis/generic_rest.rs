@@ -0,0 +1,612 @@
+use super::{
+    rest::{fill_gaps, sign, RestClient},
+    Order, OrderInfo,
+};
+use crate::{
+    apis::{Api, ApiError},
+    Asset, Candle, CandleKey, MarketInfo, MarketType, Markets, Symbol, Wallet,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use reqwest::Method;
+use rust_decimal::prelude::*;
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap},
+    env, fs, io,
+    path::Path,
+};
+use thiserror::Error;
+
+/// Describes a venue with a JSON REST API, so it can be used without writing an adapter.
+/// Fields of responses are named by dot separated paths, where numbers index arrays,
+/// e.g. `data.0.close`, and the empty path is the whole response.
+/// Paths and query parameters can contain `{name}` placeholders that are filled per request.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Descriptor {
+    /// The name of the venue, used in logs.
+    pub name: String,
+    pub base_url: String,
+    pub quote_asset: Asset,
+    /// The kind of all markets of the venue, e.g. `Spot`.
+    #[serde(default)]
+    pub market_type: MarketType,
+    /// The name of a market on the venue with the `{base}` and `{quote}` assets, e.g. `{base}{quote}`.
+    pub market_name: String,
+    /// The taker fee of an order.
+    #[serde(default)]
+    pub fee: Decimal,
+    #[serde(default)]
+    pub auth: Auth,
+    pub candles: CandlesEndpoint,
+    pub markets: MarketsEndpoint,
+    /// Without it, the wallet is not supported, e.g. for venues only used for data.
+    pub balances: Option<BalancesEndpoint>,
+}
+
+/// How requests to private endpoints are authenticated.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(tag = "scheme", rename_all = "kebab-case")]
+pub enum Auth {
+    #[default]
+    None,
+    /// The key, read from the environment variable, is sent in the header.
+    ApiKey { key_env: String, header: String },
+    /// The key is sent in the header, and the query with a timestamp in milliseconds
+    /// is signed with HMAC-SHA256 using the secret, like on Binance.
+    HmacQuery {
+        key_env: String,
+        secret_env: String,
+        header: String,
+        #[serde(default = "timestamp_param")]
+        timestamp_param: String,
+        #[serde(default = "signature_param")]
+        signature_param: String,
+    },
+}
+
+fn timestamp_param() -> String {
+    "timestamp".to_owned()
+}
+
+fn signature_param() -> String {
+    "signature".to_owned()
+}
+
+/// The unit of times in requests and responses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeUnit {
+    #[default]
+    Milliseconds,
+    Seconds,
+}
+
+impl TimeUnit {
+    fn format(&self, time: DateTime<Utc>) -> String {
+        match self {
+            TimeUnit::Milliseconds => time.timestamp_millis().to_string(),
+            TimeUnit::Seconds => time.timestamp().to_string(),
+        }
+    }
+
+    fn parse(&self, time: i64) -> Option<DateTime<Utc>> {
+        match self {
+            TimeUnit::Milliseconds => Utc.timestamp_millis_opt(time).single(),
+            TimeUnit::Seconds => Utc.timestamp_opt(time, 0).single(),
+        }
+    }
+}
+
+/// The candles of a market, with the placeholders `{market}`, `{interval}`, `{start}`, `{end}`
+/// and `{limit}`. Candles may be returned in any order.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CandlesEndpoint {
+    pub path: String,
+    #[serde(default)]
+    pub query: BTreeMap<String, String>,
+    /// The names of the intervals on the venue by their length, e.g. `"1h" = "60"`.
+    pub intervals: BTreeMap<String, String>,
+    /// Maximum number of candles per request.
+    pub limit: i32,
+    #[serde(default)]
+    pub time_unit: TimeUnit,
+    /// The path of the list of candles in the response.
+    #[serde(default)]
+    pub list: String,
+    /// The start time of a candle.
+    pub time: String,
+    pub open: String,
+    pub high: String,
+    pub low: String,
+    pub close: String,
+    pub volume: String,
+}
+
+/// The markets of the venue.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MarketsEndpoint {
+    pub path: String,
+    #[serde(default)]
+    pub query: BTreeMap<String, String>,
+    #[serde(default)]
+    pub list: String,
+    pub base: String,
+    /// Only markets quoted in the quote asset are kept, if given.
+    pub quote: Option<String>,
+    /// Only markets with these values are kept, e.g. `{ status = "TRADING" }`.
+    #[serde(default)]
+    pub filter: BTreeMap<String, String>,
+    pub min_size: Option<String>,
+    pub size_increment: Option<String>,
+    pub price_increment: Option<String>,
+    pub daily_quote_volume: Option<String>,
+}
+
+/// The balances of the account.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BalancesEndpoint {
+    pub path: String,
+    #[serde(default)]
+    pub query: BTreeMap<String, String>,
+    #[serde(default)]
+    pub list: String,
+    pub asset: String,
+    pub free: String,
+    /// The total balance, or the free balance plus the locked balance.
+    pub total: Option<String>,
+    pub locked: Option<String>,
+}
+
+#[derive(Error, Debug)]
+pub enum DescriptorError {
+    #[error("Could not read the descriptor.")]
+    Io(#[from] io::Error),
+    #[error("Invalid descriptor: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("Invalid interval {0}, expected e.g. 30s, 15m, 4h, 1d or 1w.")]
+    Interval(String),
+}
+
+/// A venue implemented from a [Descriptor], for candles, markets and balances.
+/// Orders are not supported, so it is meant for data and backtests.
+pub struct GenericRest {
+    descriptor: Descriptor,
+    rest: RestClient,
+    // The names of the candle intervals on the venue by their length in seconds.
+    intervals: HashMap<i64, String>,
+    key: Option<String>,
+    secret: Option<String>,
+}
+
+impl GenericRest {
+    pub fn new(descriptor: Descriptor) -> Result<Self, DescriptorError> {
+        let intervals = descriptor
+            .candles
+            .intervals
+            .iter()
+            .map(|(length, name)| Ok((parse_interval(length)?.num_seconds(), name.clone())))
+            .collect::<Result<_, DescriptorError>>()?;
+        let (key, secret) = match &descriptor.auth {
+            Auth::None => (None, None),
+            Auth::ApiKey { key_env, .. } => (env::var(key_env).ok(), None),
+            Auth::HmacQuery {
+                key_env,
+                secret_env,
+                ..
+            } => (env::var(key_env).ok(), env::var(secret_env).ok()),
+        };
+
+        Ok(GenericRest {
+            rest: RestClient::new(&descriptor.base_url),
+            descriptor,
+            intervals,
+            key,
+            secret,
+        })
+    }
+
+    /// Parse the descriptor from TOML.
+    pub fn from_toml(toml: &str) -> Result<Self, DescriptorError> {
+        GenericRest::new(toml::from_str(toml)?)
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, DescriptorError> {
+        GenericRest::from_toml(&fs::read_to_string(path)?)
+    }
+
+    /// Send the requests with another client, e.g. one replaying recorded responses.
+    pub fn with_rest(mut self, rest: RestClient) -> Self {
+        self.rest = rest;
+        self
+    }
+
+    /// The client sending the requests, e.g. to save the interactions it recorded.
+    pub fn rest(&self) -> &RestClient {
+        &self.rest
+    }
+
+    pub fn descriptor(&self) -> &Descriptor {
+        &self.descriptor
+    }
+
+    async fn get(
+        &self,
+        path: &str,
+        query: &BTreeMap<String, String>,
+        vars: &[(&str, String)],
+        signed: bool,
+    ) -> Result<Value, ApiError> {
+        let path = fill(path, vars);
+        let mut query: Vec<(&str, String)> = query
+            .iter()
+            .map(|(name, value)| (name.as_str(), fill(value, vars)))
+            .collect();
+        let mut headers = Vec::new();
+
+        if signed {
+            let Some(key) = &self.key else {
+                tracing::error!("{} API key is required for {}", self.descriptor.name, path);
+                return Err(ApiError::Api);
+            };
+            match &self.descriptor.auth {
+                Auth::None => {}
+                Auth::ApiKey { header, .. } => headers.push((header.as_str(), key.clone())),
+                Auth::HmacQuery {
+                    header,
+                    timestamp_param,
+                    signature_param,
+                    ..
+                } => {
+                    let Some(secret) = &self.secret else {
+                        tracing::error!(
+                            "{} API secret is required for {}",
+                            self.descriptor.name,
+                            path
+                        );
+                        return Err(ApiError::Api);
+                    };
+                    query.push((timestamp_param, Utc::now().timestamp_millis().to_string()));
+                    let signature = sign(
+                        secret,
+                        &serde_urlencoded::to_string(&query).map_err(|_| ApiError::Api)?,
+                    );
+                    query.push((signature_param, signature));
+                    headers.push((header.as_str(), key.clone()));
+                }
+            }
+        }
+
+        self.rest
+            .request(Method::GET, &path, &query, &headers)
+            .await
+    }
+
+    // The items of the list at the path, an error if the response does not match the descriptor.
+    fn list<'a>(&self, response: &'a Value, path: &str) -> Result<&'a Vec<Value>, ApiError> {
+        field(response, path)
+            .and_then(Value::as_array)
+            .ok_or_else(|| self.mismatch(path))
+    }
+
+    fn mismatch(&self, path: &str) -> ApiError {
+        tracing::warn!(
+            path,
+            "The response of {} does not match the descriptor.",
+            self.descriptor.name
+        );
+        ApiError::Api
+    }
+}
+
+// Parse the length of an interval, e.g. 15m.
+fn parse_interval(length: &str) -> Result<Duration, DescriptorError> {
+    let invalid = || DescriptorError::Interval(length.to_owned());
+    let unit = length.chars().last().ok_or_else(invalid)?;
+    let count: i64 = length[..length.len() - unit.len_utf8()]
+        .parse()
+        .map_err(|_| invalid())?;
+    Ok(match unit {
+        's' => Duration::seconds(count),
+        'm' => Duration::minutes(count),
+        'h' => Duration::hours(count),
+        'd' => Duration::days(count),
+        'w' => Duration::weeks(count),
+        _ => return Err(invalid()),
+    })
+}
+
+// Replace the placeholders of the template.
+fn fill(template: &str, vars: &[(&str, String)]) -> String {
+    vars.iter()
+        .fold(template.to_owned(), |filled, (name, value)| {
+            filled.replace(&format!("{{{}}}", name), value)
+        })
+}
+
+// The value at a dot separated path, e.g. data.0.close.
+fn field<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    if path.is_empty() {
+        return Some(value);
+    }
+    path.split('.').try_fold(value, |value, key| match value {
+        Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+        value => value.get(key),
+    })
+}
+
+// A number at the path, venues send them as JSON numbers or strings.
+fn decimal(value: &Value, path: &str) -> Option<Decimal> {
+    let number = match field(value, path)? {
+        Value::String(number) => number.clone(),
+        Value::Number(number) => number.to_string(),
+        _ => return None,
+    };
+    Decimal::from_str(&number)
+        .or_else(|_| Decimal::from_scientific(&number))
+        .ok()
+}
+
+fn string(value: &Value, path: &str) -> Option<String> {
+    match field(value, path)? {
+        Value::String(string) => Some(string.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        Value::Bool(bool) => Some(bool.to_string()),
+        _ => None,
+    }
+}
+
+#[async_trait]
+impl Api for GenericRest {
+    const NAME: &'static str = "GenericRest";
+    const LIVE_TRADING_ENABLED: bool = false;
+
+    async fn get_candles(
+        &self,
+        key: CandleKey,
+    ) -> Result<Vec<(CandleKey, Option<Candle>)>, ApiError> {
+        let endpoint = &self.descriptor.candles;
+        let interval = self
+            .intervals
+            .get(&key.interval.num_seconds())
+            .ok_or_else(|| {
+                tracing::error!(
+                    "{} does not support the interval {}",
+                    self.descriptor.name,
+                    key.interval
+                );
+                ApiError::Api
+            })?;
+
+        let vars = [
+            ("market", self.format_market(key.market)),
+            ("interval", interval.clone()),
+            ("start", endpoint.time_unit.format(key.time)),
+            (
+                "end",
+                endpoint
+                    .time_unit
+                    .format(key.time + key.interval * (endpoint.limit - 1)),
+            ),
+            ("limit", endpoint.limit.to_string()),
+        ];
+        let response = self
+            .get(&endpoint.path, &endpoint.query, &vars, false)
+            .await?;
+
+        let mut candles = self
+            .list(&response, &endpoint.list)?
+            .iter()
+            .map(|row| {
+                let time = endpoint
+                    .time_unit
+                    .parse(decimal(row, &endpoint.time)?.to_i64()?)?;
+                let candle = Candle {
+                    open: decimal(row, &endpoint.open)?,
+                    high: decimal(row, &endpoint.high)?,
+                    low: decimal(row, &endpoint.low)?,
+                    close: decimal(row, &endpoint.close)?,
+                    volume: decimal(row, &endpoint.volume)?,
+                };
+                Some((CandleKey { time, ..key }, candle))
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| self.mismatch(&endpoint.path))?;
+        candles.sort_by_key(|(key, _)| key.time);
+        // The last candle is still open.
+        candles.retain(|(key, _)| key.time + key.interval <= Utc::now());
+
+        Ok(fill_gaps(key, candles, endpoint.limit))
+    }
+
+    async fn place_order(&self, _order: Order) -> Result<OrderInfo, ApiError> {
+        Err(ApiError::Unsupported)
+    }
+
+    fn format_market(&self, market: Symbol) -> String {
+        fill(
+            &self.descriptor.market_name,
+            &[
+                ("base", market.underlying().to_string()),
+                ("quote", self.quote_asset().to_string()),
+            ],
+        )
+    }
+
+    async fn update_wallet(&self, wallet: &mut Wallet) -> Result<(), ApiError> {
+        let Some(endpoint) = &self.descriptor.balances else {
+            return Err(ApiError::Unsupported);
+        };
+        let response = self.get(&endpoint.path, &endpoint.query, &[], true).await?;
+
+        let balances = self
+            .list(&response, &endpoint.list)?
+            .iter()
+            .map(|balance| {
+                let asset = Asset::new(string(balance, &endpoint.asset)?);
+                let free = decimal(balance, &endpoint.free)?;
+                let total = match (&endpoint.total, &endpoint.locked) {
+                    (Some(total), _) => decimal(balance, total)?,
+                    (None, Some(locked)) => free + decimal(balance, locked)?,
+                    (None, None) => free,
+                };
+                Some((asset, free, total))
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| self.mismatch(&endpoint.path))?;
+
+        *wallet = Wallet {
+            free: balances
+                .iter()
+                .map(|&(asset, free, _)| (asset, free))
+                .collect(),
+            total: balances
+                .iter()
+                .map(|&(asset, _, total)| (asset, total))
+                .collect(),
+        };
+
+        Ok(())
+    }
+
+    async fn update_markets(&self, markets: &mut Markets) -> Result<(), ApiError> {
+        let endpoint = &self.descriptor.markets;
+        let response = self
+            .get(&endpoint.path, &endpoint.query, &[], false)
+            .await?;
+
+        let quote_asset = self.quote_asset();
+        let optional = |market: &Value, path: &Option<String>| match path {
+            Some(path) => decimal(market, path),
+            None => Some(Decimal::ZERO),
+        };
+        markets.markets = self
+            .list(&response, &endpoint.list)?
+            .iter()
+            .filter(|market| {
+                endpoint
+                    .filter
+                    .iter()
+                    .all(|(path, value)| string(market, path).as_ref() == Some(value))
+            })
+            .filter(|market| match &endpoint.quote {
+                Some(quote) => string(market, quote).map(Asset::new) == Some(quote_asset),
+                None => true,
+            })
+            .map(|market| {
+                let base = string(market, &endpoint.base)?;
+                let symbol = match self.descriptor.market_type {
+                    MarketType::Spot => Symbol::spot(base),
+                    MarketType::Perpetual | MarketType::Future => Symbol::perp(base),
+                };
+                let info = MarketInfo {
+                    symbol,
+                    min_size: optional(market, &endpoint.min_size)?,
+                    size_increment: optional(market, &endpoint.size_increment)?,
+                    price_increment: optional(market, &endpoint.price_increment)?,
+                    daily_quote_volume: optional(market, &endpoint.daily_quote_volume)?,
+                    market_type: self.descriptor.market_type,
+                    contract_multiplier: Decimal::ONE,
+                    expiry: None,
+                };
+                Some((symbol, info))
+            })
+            .collect::<Option<_>>()
+            .ok_or_else(|| self.mismatch(&endpoint.path))?;
+
+        Ok(())
+    }
+
+    fn quote_asset(&self) -> Asset {
+        self.descriptor.quote_asset
+    }
+
+    async fn order_fee(&self) -> Decimal {
+        self.descriptor.fee
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::rest::Cassette;
+    use rust_decimal_macros::dec;
+
+    // Binance spot described by a descriptor, replaying recorded responses.
+    fn replay() -> GenericRest {
+        let cassette = Cassette::from_file(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/fixtures/generic_rest/cassette.json"
+        ))
+        .unwrap();
+        let mut api = GenericRest::from_file(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/fixtures/generic_rest/binance_spot.toml"
+        ))
+        .unwrap()
+        .with_rest(RestClient::replay(cassette));
+        api.key = Some("key".to_owned());
+        api.secret = Some("secret".to_owned());
+        api
+    }
+
+    #[test]
+    fn paths_and_intervals() {
+        let response: Value =
+            serde_json::from_str(r#"{"data": [[1, "2.5"], {"a": 3e-2}]}"#).unwrap();
+        assert_eq!(decimal(&response, "data.0.1"), Some(dec!(2.5)));
+        assert_eq!(decimal(&response, "data.1.a"), Some(dec!(0.03)));
+        assert_eq!(field(&response, "data.2"), None);
+        assert_eq!(field(&response, ""), Some(&response));
+
+        assert_eq!(parse_interval("15m").unwrap(), Duration::minutes(15));
+        assert_eq!(parse_interval("1w").unwrap(), Duration::weeks(1));
+        assert!(parse_interval("1y").is_err());
+        assert!(parse_interval("").is_err());
+    }
+
+    #[tokio::test]
+    async fn replay_descriptor() {
+        let api = replay();
+        let mut markets = Markets::default();
+        api.update_markets(&mut markets).await.unwrap();
+
+        // Other quote assets and markets that are not trading are skipped.
+        assert_eq!(markets.markets().count(), 2);
+        let btc = markets.market(Symbol::spot("BTC")).unwrap();
+        assert_eq!(btc.market_type, MarketType::Spot);
+        assert_eq!(btc.min_size, dec!(0.00001));
+        assert_eq!(btc.size_increment, dec!(0.00001));
+        assert_eq!(btc.price_increment, dec!(0.01));
+
+        let key = CandleKey {
+            market: Symbol::spot("BTC"),
+            time: Utc.with_ymd_and_hms(2023, 8, 1, 0, 0, 0).unwrap(),
+            interval: Duration::hours(1),
+        };
+        let candles = api.get_candles(key).await.unwrap();
+        assert_eq!(candles.len(), 1000);
+        assert_eq!(candles[0].1.unwrap().open, dec!(29232.25));
+        assert_eq!(candles[1].1, None);
+        assert_eq!(candles[2].1.unwrap().volume, dec!(1213.80441));
+        assert!(api
+            .get_candles(CandleKey {
+                interval: Duration::minutes(3),
+                ..key
+            })
+            .await
+            .is_err());
+
+        let mut wallet = Wallet::new();
+        api.update_wallet(&mut wallet).await.unwrap();
+        assert_eq!(wallet.free[&Asset::new("USDT")], dec!(1000));
+        assert_eq!(wallet.total[&Asset::new("USDT")], dec!(1250.5));
+
+        let interactions = api.rest().interactions();
+        assert_eq!(interactions[1].param("symbol"), Some("BTCUSDT"));
+        assert_eq!(interactions[1].param("interval"), Some("1h"));
+        assert_eq!(interactions[1].param("startTime"), Some("1690848000000"));
+        assert!(interactions[2].param("signature").is_some());
+    }
+}
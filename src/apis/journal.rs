@@ -0,0 +1,639 @@
+use super::{replay::RecordedCandle, Api};
+use crate::{
+    apis::{ApiError, ExecutionGap, FeeSchedule, Margin, Order, OrderInfo},
+    Asset, Candle, CandleKey, Command, MarketInfo, Markets, Orderbook, PriceSource, Symbol, Trade,
+    Transfer, Wallet,
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{self, File},
+    io::{self, Write},
+    ops::Range,
+    path::Path,
+    sync::Mutex,
+};
+use uuid::Uuid;
+
+/// A response of the journaled API, with the time it was received.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub time: DateTime<Utc>,
+    response: Response,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum Response {
+    /// The first response of every recording, describing the recorded API.
+    Hello {
+        name: String,
+        quote_asset: Asset,
+    },
+    Candles {
+        market: Symbol,
+        time: DateTime<Utc>,
+        // Interval in seconds.
+        interval: i64,
+        result: Result<Vec<RecordedCandle>, ApiError>,
+    },
+    Wallet(Result<Wallet, ApiError>),
+    Markets(Result<Vec<MarketInfo>, ApiError>),
+    Order {
+        order: Order,
+        result: Result<OrderInfo, ApiError>,
+    },
+    OrderStatus {
+        market: Symbol,
+        result: Result<OrderInfo, ApiError>,
+    },
+    CancelOrder {
+        market: Symbol,
+        result: Result<OrderInfo, ApiError>,
+    },
+    AmendOrder {
+        market: Symbol,
+        price: Decimal,
+        size: Decimal,
+        result: Result<OrderInfo, ApiError>,
+    },
+    OrderFee(Decimal),
+    Orderbook {
+        market: Symbol,
+        time: DateTime<Utc>,
+        result: Result<Option<Orderbook>, ApiError>,
+    },
+    Price {
+        market: Symbol,
+        time: DateTime<Utc>,
+        // Interval in seconds.
+        interval: i64,
+        source: PriceSource,
+        result: Result<Option<Decimal>, ApiError>,
+    },
+    Trades {
+        market: Symbol,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        result: Result<Option<Vec<Trade>>, ApiError>,
+    },
+}
+
+/// The Journal API is a middleware that archives every response of the API with the time
+/// it was received, one JSON line per response, so a live run can be replayed with `JournalReplay`.
+/// Lines are written as the responses arrive, so the journal survives a crash.
+pub struct Journal<A>
+where
+    A: Api,
+{
+    api: A,
+    file: Mutex<File>,
+}
+
+impl<A> Journal<A>
+where
+    A: Api,
+{
+    /// Write the journal to the file, replacing it if it exists.
+    pub fn new<P: AsRef<Path>>(api: A, path: P) -> io::Result<Self> {
+        let journal = Journal {
+            file: Mutex::new(File::create(path)?),
+            api,
+        };
+        journal.write(Response::Hello {
+            name: A::NAME.to_owned(),
+            quote_asset: journal.api.quote_asset(),
+        });
+        Ok(journal)
+    }
+
+    // Append a response, failing to write is logged but does not stop the strategy.
+    fn write(&self, response: Response) {
+        let entry = JournalEntry {
+            time: Utc::now(),
+            response,
+        };
+        let line = serde_json::to_string(&entry).expect("responses are serializable");
+        if let Err(err) = writeln!(self.file.lock().unwrap(), "{}", line) {
+            tracing::error!("Could not journal the response: {}", err);
+        }
+    }
+}
+
+#[async_trait]
+impl<A: Api> Api for Journal<A> {
+    const NAME: &'static str = A::NAME;
+    const LIVE_TRADING_ENABLED: bool = A::LIVE_TRADING_ENABLED;
+
+    async fn get_candles(
+        &self,
+        key: CandleKey,
+    ) -> Result<Vec<(CandleKey, Option<Candle>)>, ApiError> {
+        let result = self.api.get_candles(key).await;
+        self.write(Response::Candles {
+            market: key.market,
+            time: key.time,
+            interval: key.interval.num_seconds(),
+            result: result
+                .as_ref()
+                .map(|candles| candles.iter().copied().map(RecordedCandle::from).collect())
+                .map_err(Clone::clone),
+        });
+        result
+    }
+
+    async fn place_order(&self, order: Order) -> Result<OrderInfo, ApiError> {
+        let result = self.api.place_order(order.clone()).await;
+        self.write(Response::Order {
+            order,
+            result: result.clone(),
+        });
+        result
+    }
+
+    async fn order_status(&self, market: Symbol, order_id: Uuid) -> Result<OrderInfo, ApiError> {
+        let result = self.api.order_status(market, order_id).await;
+        self.write(Response::OrderStatus {
+            market,
+            result: result.clone(),
+        });
+        result
+    }
+
+    async fn cancel_order(&self, market: Symbol, order_id: Uuid) -> Result<OrderInfo, ApiError> {
+        let result = self.api.cancel_order(market, order_id).await;
+        self.write(Response::CancelOrder {
+            market,
+            result: result.clone(),
+        });
+        result
+    }
+
+    async fn amend_order(
+        &self,
+        market: Symbol,
+        order_id: Uuid,
+        price: Decimal,
+        size: Decimal,
+    ) -> Result<OrderInfo, ApiError> {
+        let result = self.api.amend_order(market, order_id, price, size).await;
+        self.write(Response::AmendOrder {
+            market,
+            price,
+            size,
+            result: result.clone(),
+        });
+        result
+    }
+
+    fn format_market(&self, market: Symbol) -> String {
+        self.api.format_market(market)
+    }
+
+    async fn update_wallet(&self, wallet: &mut Wallet) -> Result<(), ApiError> {
+        let result = self.api.update_wallet(wallet).await;
+        self.write(Response::Wallet(result.clone().map(|_| wallet.clone())));
+        result
+    }
+
+    async fn update_markets(&self, markets: &mut Markets) -> Result<(), ApiError> {
+        let result = self.api.update_markets(markets).await;
+        self.write(Response::Markets(
+            result
+                .clone()
+                .map(|_| markets.markets().map(|(_, info)| *info).collect()),
+        ));
+        result
+    }
+
+    fn quote_asset(&self) -> Asset {
+        self.api.quote_asset()
+    }
+
+    async fn order_fee(&self) -> Decimal {
+        let fee = self.api.order_fee().await;
+        self.write(Response::OrderFee(fee));
+        fee
+    }
+
+    async fn get_orderbook(
+        &self,
+        market: Symbol,
+        time: DateTime<Utc>,
+    ) -> Result<Option<Orderbook>, ApiError> {
+        let result = self.api.get_orderbook(market, time).await;
+        self.write(Response::Orderbook {
+            market,
+            time,
+            result: result.clone(),
+        });
+        result
+    }
+
+    async fn get_trades(
+        &self,
+        market: Symbol,
+        range: Range<DateTime<Utc>>,
+    ) -> Result<Option<Vec<Trade>>, ApiError> {
+        let result = self.api.get_trades(market, range.clone()).await;
+        self.write(Response::Trades {
+            market,
+            start: range.start,
+            end: range.end,
+            result: result.clone(),
+        });
+        result
+    }
+
+    async fn get_price(
+        &self,
+        key: CandleKey,
+        source: PriceSource,
+    ) -> Result<Option<Decimal>, ApiError> {
+        let result = self.api.get_price(key, source).await;
+        self.write(Response::Price {
+            market: key.market,
+            time: key.time,
+            interval: key.interval.num_seconds(),
+            source,
+            result: result.clone(),
+        });
+        result
+    }
+
+    async fn fee_schedule(&self) -> FeeSchedule {
+        self.api.fee_schedule().await
+    }
+
+    async fn borrow_rate(&self, market: Symbol) -> Option<Decimal> {
+        self.api.borrow_rate(market).await
+    }
+
+    async fn borrow_costs(&self, time: DateTime<Utc>) -> Result<Decimal, ApiError> {
+        self.api.borrow_costs(time).await
+    }
+
+    async fn convert_dust(&self, assets: Vec<Asset>) -> Result<(), ApiError> {
+        self.api.convert_dust(assets).await
+    }
+
+    async fn transfer(&self, transfer: &Transfer) -> Result<(), ApiError> {
+        self.api.transfer(transfer).await
+    }
+
+    async fn save_state(&self, strategy_name: &str, state: String) -> Result<(), ApiError> {
+        self.api.save_state(strategy_name, state).await
+    }
+
+    async fn load_state(&self, strategy_name: &str) -> Result<Option<String>, ApiError> {
+        self.api.load_state(strategy_name).await
+    }
+
+    fn margin(&self) -> Option<Margin> {
+        self.api.margin()
+    }
+
+    async fn shutdown(&self) {
+        self.api.shutdown().await
+    }
+
+    fn execution_gap(&self) -> Option<ExecutionGap> {
+        self.api.execution_gap()
+    }
+
+    async fn commands(&self) -> Vec<Command> {
+        self.api.commands().await
+    }
+}
+
+type Responses<T> = Mutex<VecDeque<Result<T, ApiError>>>;
+type KeyedResponses<K, T> = Mutex<HashMap<K, VecDeque<Result<T, ApiError>>>>;
+
+/// Feeds the responses archived by `Journal` back in the order they were received,
+/// to reproduce a live run deterministically, e.g. in a debugger. Unlike `ReplayApi`,
+/// which simulates fills on a fixture of markets and candles, every response is replayed.
+/// Candles are answered by key, everything else in sequence.
+/// Once the responses of a kind are used up, requests fail with `ApiError::Api`.
+pub struct JournalReplay {
+    quote_asset: Asset,
+    candles: KeyedResponses<(Symbol, DateTime<Utc>, i64), Vec<RecordedCandle>>,
+    wallets: Responses<Wallet>,
+    markets: Responses<Vec<MarketInfo>>,
+    orders: Mutex<VecDeque<(Order, Result<OrderInfo, ApiError>)>>,
+    statuses: Responses<OrderInfo>,
+    cancels: Responses<OrderInfo>,
+    amends: Responses<OrderInfo>,
+    fees: Mutex<VecDeque<Decimal>>,
+    orderbooks: KeyedResponses<(Symbol, DateTime<Utc>), Option<Orderbook>>,
+    prices: KeyedResponses<(Symbol, DateTime<Utc>, i64, PriceSource), Option<Decimal>>,
+    #[allow(clippy::type_complexity)]
+    trades: KeyedResponses<(Symbol, DateTime<Utc>, DateTime<Utc>), Option<Vec<Trade>>>,
+}
+
+impl JournalReplay {
+    pub fn new(entries: impl IntoIterator<Item = JournalEntry>) -> Self {
+        let mut replay = JournalReplay {
+            quote_asset: Asset::new("USD"),
+            candles: Mutex::default(),
+            wallets: Mutex::default(),
+            markets: Mutex::default(),
+            orders: Mutex::default(),
+            statuses: Mutex::default(),
+            cancels: Mutex::default(),
+            amends: Mutex::default(),
+            fees: Mutex::default(),
+            orderbooks: Mutex::default(),
+            prices: Mutex::default(),
+            trades: Mutex::default(),
+        };
+        for entry in entries {
+            match entry.response {
+                Response::Hello { quote_asset, .. } => replay.quote_asset = quote_asset,
+                Response::Candles {
+                    market,
+                    time,
+                    interval,
+                    result,
+                } => replay
+                    .candles
+                    .get_mut()
+                    .unwrap()
+                    .entry((market, time, interval))
+                    .or_default()
+                    .push_back(result),
+                Response::Wallet(result) => replay.wallets.get_mut().unwrap().push_back(result),
+                Response::Markets(result) => replay.markets.get_mut().unwrap().push_back(result),
+                Response::Order { order, result } => {
+                    replay.orders.get_mut().unwrap().push_back((order, result))
+                }
+                Response::OrderStatus { result, .. } => {
+                    replay.statuses.get_mut().unwrap().push_back(result)
+                }
+                Response::CancelOrder { result, .. } => {
+                    replay.cancels.get_mut().unwrap().push_back(result)
+                }
+                Response::AmendOrder { result, .. } => {
+                    replay.amends.get_mut().unwrap().push_back(result)
+                }
+                Response::OrderFee(fee) => replay.fees.get_mut().unwrap().push_back(fee),
+                Response::Orderbook {
+                    market,
+                    time,
+                    result,
+                } => replay
+                    .orderbooks
+                    .get_mut()
+                    .unwrap()
+                    .entry((market, time))
+                    .or_default()
+                    .push_back(result),
+                Response::Price {
+                    market,
+                    time,
+                    interval,
+                    source,
+                    result,
+                } => replay
+                    .prices
+                    .get_mut()
+                    .unwrap()
+                    .entry((market, time, interval, source))
+                    .or_default()
+                    .push_back(result),
+                Response::Trades {
+                    market,
+                    start,
+                    end,
+                    result,
+                } => replay
+                    .trades
+                    .get_mut()
+                    .unwrap()
+                    .entry((market, start, end))
+                    .or_default()
+                    .push_back(result),
+            }
+        }
+        replay
+    }
+
+    /// Read a journal written by `Journal`.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let entries = fs::read_to_string(path)?
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<Result<Vec<JournalEntry>, _>>()?;
+        Ok(JournalReplay::new(entries))
+    }
+}
+
+// The next recorded response, an error once they are used up.
+fn next<T>(
+    responses: Option<&mut VecDeque<Result<T, ApiError>>>,
+    what: &str,
+) -> Result<T, ApiError> {
+    responses
+        .and_then(|responses| responses.pop_front())
+        .unwrap_or_else(|| {
+            tracing::error!("No more recorded responses for {}.", what);
+            Err(ApiError::Api)
+        })
+}
+
+#[async_trait]
+impl Api for JournalReplay {
+    const NAME: &'static str = "JournalReplay";
+    const LIVE_TRADING_ENABLED: bool = false;
+
+    async fn get_candles(
+        &self,
+        key: CandleKey,
+    ) -> Result<Vec<(CandleKey, Option<Candle>)>, ApiError> {
+        let mut candles = self.candles.lock().unwrap();
+        let responses = candles.get_mut(&(key.market, key.time, key.interval.num_seconds()));
+        Ok(next(responses, &format!("{:?}", key))?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    async fn place_order(&self, order: Order) -> Result<OrderInfo, ApiError> {
+        let (recorded, result) = self.orders.lock().unwrap().pop_front().ok_or_else(|| {
+            tracing::error!("No more recorded responses for orders.");
+            ApiError::Api
+        })?;
+        if recorded.market != order.market || recorded.side != order.side {
+            tracing::warn!(
+                "The replay diverged, ordered {:?} {} instead of {:?} {}.",
+                order.side,
+                order.market,
+                recorded.side,
+                recorded.market
+            );
+        }
+        // Order ids are random, answer with the id of the replayed order.
+        result.map(|info| OrderInfo {
+            order_id: order.order_id,
+            ..info
+        })
+    }
+
+    async fn order_status(&self, _market: Symbol, order_id: Uuid) -> Result<OrderInfo, ApiError> {
+        let info = next(Some(&mut self.statuses.lock().unwrap()), "order statuses")?;
+        Ok(OrderInfo { order_id, ..info })
+    }
+
+    async fn cancel_order(&self, _market: Symbol, order_id: Uuid) -> Result<OrderInfo, ApiError> {
+        let info = next(Some(&mut self.cancels.lock().unwrap()), "cancels")?;
+        Ok(OrderInfo { order_id, ..info })
+    }
+
+    async fn amend_order(
+        &self,
+        _market: Symbol,
+        order_id: Uuid,
+        _price: Decimal,
+        _size: Decimal,
+    ) -> Result<OrderInfo, ApiError> {
+        let info = next(Some(&mut self.amends.lock().unwrap()), "amends")?;
+        Ok(OrderInfo { order_id, ..info })
+    }
+
+    fn format_market(&self, market: Symbol) -> String {
+        market.to_string()
+    }
+
+    async fn update_wallet(&self, wallet: &mut Wallet) -> Result<(), ApiError> {
+        *wallet = next(Some(&mut self.wallets.lock().unwrap()), "the wallet")?;
+        Ok(())
+    }
+
+    async fn update_markets(&self, markets: &mut Markets) -> Result<(), ApiError> {
+        let recorded = next(Some(&mut self.markets.lock().unwrap()), "the markets")?;
+        markets.markets = recorded
+            .into_iter()
+            .map(|info| (info.symbol, info))
+            .collect();
+        Ok(())
+    }
+
+    fn quote_asset(&self) -> Asset {
+        self.quote_asset
+    }
+
+    async fn order_fee(&self) -> Decimal {
+        // The last fee stays in effect once the recorded fees are used up.
+        let mut fees = self.fees.lock().unwrap();
+        if fees.len() > 1 {
+            fees.pop_front().unwrap()
+        } else {
+            fees.front().copied().unwrap_or_default()
+        }
+    }
+
+    async fn get_orderbook(
+        &self,
+        market: Symbol,
+        time: DateTime<Utc>,
+    ) -> Result<Option<Orderbook>, ApiError> {
+        let mut orderbooks = self.orderbooks.lock().unwrap();
+        next(orderbooks.get_mut(&(market, time)), "the orderbook")
+    }
+
+    async fn get_trades(
+        &self,
+        market: Symbol,
+        range: Range<DateTime<Utc>>,
+    ) -> Result<Option<Vec<Trade>>, ApiError> {
+        let mut trades = self.trades.lock().unwrap();
+        next(
+            trades.get_mut(&(market, range.start, range.end)),
+            "the trades",
+        )
+    }
+
+    async fn get_price(
+        &self,
+        key: CandleKey,
+        source: PriceSource,
+    ) -> Result<Option<Decimal>, ApiError> {
+        let mut prices = self.prices.lock().unwrap();
+        let responses = prices.get_mut(&(key.market, key.time, key.interval.num_seconds(), source));
+        next(responses, "the price")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        apis::{FillBehavior, Mock, MockSettings},
+        OrderType, Side, TimeInForce,
+    };
+    use chrono::{Duration, TimeZone};
+    use rust_decimal_macros::dec;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn journal_and_replay() {
+        let mut settings = MockSettings::new(|key: CandleKey| Candle {
+            open: dec!(100),
+            high: dec!(110),
+            low: dec!(90),
+            close: Decimal::from(key.time.timestamp() % 1000),
+            volume: dec!(1),
+        });
+        settings.fills = FillBehavior::RejectEveryNth(2);
+        settings.wallet.deposit(dec!(1000), Asset::new("USD"));
+        let path = std::env::temp_dir().join(format!("journal-{}.jsonl", Uuid::new_v4()));
+        let api = Journal::new(Mock::new(settings), &path).unwrap();
+
+        let key = CandleKey {
+            market: Symbol::perp("BTC"),
+            time: Utc.with_ymd_and_hms(2021, 8, 1, 0, 0, 0).unwrap(),
+            interval: Duration::minutes(1),
+        };
+        let order = || Order {
+            order_id: Uuid::new_v4(),
+            market: key.market,
+            side: Side::Buy,
+            size: dec!(1),
+            order_type: OrderType::Market,
+            reduce_only: false,
+            time_in_force: TimeInForce::ImmediateOrCancel,
+            post_only: false,
+            position_side: None,
+            time: key.time,
+            current_price: dec!(100),
+        };
+        let mut wallet = Wallet::new();
+        api.update_wallet(&mut wallet).await.unwrap();
+        let candles = api.get_candles(key).await.unwrap();
+        let filled = api.place_order(order()).await.unwrap();
+        assert!(api.place_order(order()).await.is_err());
+
+        let replay = JournalReplay::from_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let mut replayed = Wallet::new();
+        replay.update_wallet(&mut replayed).await.unwrap();
+        assert_eq!(replayed.total, wallet.total);
+        assert_eq!(replay.get_candles(key).await.unwrap(), candles);
+        let next_order = order();
+        let info = replay.place_order(next_order.clone()).await.unwrap();
+        assert_eq!(info.order_id, next_order.order_id);
+        assert_eq!(info.price, filled.price);
+        assert!(matches!(
+            replay.place_order(order()).await,
+            Err(ApiError::Rejected)
+        ));
+
+        // The journal is used up.
+        assert!(replay.get_candles(key).await.is_err());
+        assert!(replay.update_wallet(&mut replayed).await.is_err());
+    }
+}
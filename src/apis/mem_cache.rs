@@ -0,0 +1,312 @@
+use super::Api;
+use crate::{
+    apis::{ApiError, ExecutionGap, FeeSchedule, Margin, Order, OrderInfo},
+    Asset, Candle, CandleKey, Command, Markets, Orderbook, PriceSource, Symbol, Trade, Transfer,
+    Wallet,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    mem::size_of,
+    ops::Range,
+    sync::Mutex,
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use uuid::Uuid;
+
+type Page = Vec<(CandleKey, Option<Candle>)>;
+
+/// Hits and misses of the Mem Cache API since it was created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+    /// Number of cached pages.
+    pub pages: usize,
+    /// Estimated memory used by the cached pages, in bytes.
+    pub bytes: usize,
+}
+
+#[derive(Default)]
+struct Lru {
+    // The cached pages with the tick they were last used at.
+    pages: HashMap<CandleKey, (Page, u64)>,
+    // The keys of the cached pages by the tick they were last used at.
+    used: BTreeMap<u64, CandleKey>,
+    tick: u64,
+    stats: CacheStats,
+}
+
+impl Lru {
+    fn get(&mut self, key: &CandleKey) -> Option<Page> {
+        self.tick += 1;
+        let (page, used) = self.pages.get_mut(key)?;
+        self.used.remove(used);
+        self.used.insert(self.tick, *key);
+        *used = self.tick;
+        Some(page.clone())
+    }
+
+    fn insert(&mut self, key: CandleKey, page: Page, budget: usize) {
+        let bytes = Self::bytes(&page);
+        if bytes > budget || self.pages.contains_key(&key) {
+            return;
+        }
+        while self.stats.bytes + bytes > budget {
+            let Some((_, evicted)) = self.used.pop_first() else {
+                break;
+            };
+            if let Some((page, _)) = self.pages.remove(&evicted) {
+                self.stats.bytes -= Self::bytes(&page);
+            }
+        }
+
+        self.tick += 1;
+        self.used.insert(self.tick, key);
+        self.pages.insert(key, (page, self.tick));
+        self.stats.bytes += bytes;
+    }
+
+    fn bytes(page: &Page) -> usize {
+        size_of::<(CandleKey, (Page, u64))>()
+            + page.len() * size_of::<(CandleKey, Option<Candle>)>()
+    }
+}
+
+/// The Mem Cache API keeps the most recently used pages of candles in memory,
+/// evicting the least recently used ones once the memory budget is exceeded.
+/// This is useful in front of the Store API if the same candles are read many times,
+/// e.g. by a `GridSearch`. Share the cache between backtests by wrapping it in an `Arc`.
+pub struct MemCache<A>
+where
+    A: Api,
+{
+    api: A,
+    cache: Mutex<Lru>,
+    budget: usize,
+}
+
+impl<A> MemCache<A>
+where
+    A: Api,
+{
+    /// Cache candles using at most about `budget` bytes of memory.
+    pub fn new(api: A, budget: usize) -> Self {
+        MemCache {
+            api,
+            cache: Mutex::new(Lru::default()),
+            budget,
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let cache = self.cache.lock().unwrap();
+        CacheStats {
+            pages: cache.pages.len(),
+            ..cache.stats
+        }
+    }
+}
+
+#[async_trait]
+impl<A: Api> Api for MemCache<A> {
+    const NAME: &'static str = A::NAME;
+    const LIVE_TRADING_ENABLED: bool = A::LIVE_TRADING_ENABLED;
+
+    async fn get_candles(&self, key: CandleKey) -> Result<Page, ApiError> {
+        if let Some(page) = {
+            let mut cache = self.cache.lock().unwrap();
+            let page = cache.get(&key);
+            if page.is_some() {
+                cache.stats.hits += 1;
+            } else {
+                cache.stats.misses += 1;
+            }
+            page
+        } {
+            return Ok(page);
+        }
+
+        let page = self.api.get_candles(key).await?;
+        // Empty pages are not cached, as the candles may become available later.
+        if !page.is_empty() {
+            self.cache
+                .lock()
+                .unwrap()
+                .insert(key, page.clone(), self.budget);
+        }
+
+        Ok(page)
+    }
+
+    async fn place_order(&self, order: Order) -> Result<OrderInfo, ApiError> {
+        self.api.place_order(order).await
+    }
+
+    async fn place_orders(&self, orders: Vec<Order>) -> Vec<Result<OrderInfo, ApiError>> {
+        self.api.place_orders(orders).await
+    }
+
+    async fn order_status(&self, market: Symbol, order_id: Uuid) -> Result<OrderInfo, ApiError> {
+        self.api.order_status(market, order_id).await
+    }
+
+    async fn cancel_order(&self, market: Symbol, order_id: Uuid) -> Result<OrderInfo, ApiError> {
+        self.api.cancel_order(market, order_id).await
+    }
+
+    async fn amend_order(
+        &self,
+        market: Symbol,
+        order_id: Uuid,
+        price: Decimal,
+        size: Decimal,
+    ) -> Result<OrderInfo, ApiError> {
+        self.api.amend_order(market, order_id, price, size).await
+    }
+
+    fn format_market(&self, market: Symbol) -> String {
+        self.api.format_market(market)
+    }
+
+    async fn update_wallet(&self, wallet: &mut Wallet) -> Result<(), ApiError> {
+        self.api.update_wallet(wallet).await
+    }
+
+    async fn update_markets(&self, markets: &mut Markets) -> Result<(), ApiError> {
+        self.api.update_markets(markets).await
+    }
+
+    fn quote_asset(&self) -> Asset {
+        self.api.quote_asset()
+    }
+
+    async fn order_fee(&self) -> Decimal {
+        self.api.order_fee().await
+    }
+
+    async fn get_orderbook(
+        &self,
+        market: Symbol,
+        time: DateTime<Utc>,
+    ) -> Result<Option<Orderbook>, ApiError> {
+        self.api.get_orderbook(market, time).await
+    }
+
+    async fn get_trades(
+        &self,
+        market: Symbol,
+        range: Range<DateTime<Utc>>,
+    ) -> Result<Option<Vec<Trade>>, ApiError> {
+        self.api.get_trades(market, range).await
+    }
+
+    async fn get_price(
+        &self,
+        key: CandleKey,
+        source: PriceSource,
+    ) -> Result<Option<Decimal>, ApiError> {
+        self.api.get_price(key, source).await
+    }
+
+    async fn get_prices(
+        &self,
+        key: CandleKey,
+        source: PriceSource,
+    ) -> Result<Vec<(CandleKey, Option<Decimal>)>, ApiError> {
+        self.api.get_prices(key, source).await
+    }
+
+    async fn fee_schedule(&self) -> FeeSchedule {
+        self.api.fee_schedule().await
+    }
+
+    async fn borrow_rate(&self, market: Symbol) -> Option<Decimal> {
+        self.api.borrow_rate(market).await
+    }
+
+    async fn borrow_costs(&self, time: DateTime<Utc>) -> Result<Decimal, ApiError> {
+        self.api.borrow_costs(time).await
+    }
+
+    async fn convert_dust(&self, assets: Vec<Asset>) -> Result<(), ApiError> {
+        self.api.convert_dust(assets).await
+    }
+
+    async fn transfer(&self, transfer: &Transfer) -> Result<(), ApiError> {
+        self.api.transfer(transfer).await
+    }
+
+    async fn save_state(&self, strategy_name: &str, state: String) -> Result<(), ApiError> {
+        self.api.save_state(strategy_name, state).await
+    }
+
+    async fn load_state(&self, strategy_name: &str) -> Result<Option<String>, ApiError> {
+        self.api.load_state(strategy_name).await
+    }
+
+    fn margin(&self) -> Option<Margin> {
+        self.api.margin()
+    }
+
+    async fn shutdown(&self) {
+        self.api.shutdown().await
+    }
+
+    fn execution_gap(&self) -> Option<ExecutionGap> {
+        self.api.execution_gap()
+    }
+
+    async fn commands(&self) -> Vec<Command> {
+        self.api.commands().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::{Mock, MockSettings};
+    use chrono::{Duration, TimeZone};
+    use rust_decimal_macros::dec;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    #[tokio::test]
+    async fn evict_least_recently_used() {
+        let fetched = Arc::new(AtomicUsize::new(0));
+        let api = Mock::new(MockSettings::new({
+            let fetched = fetched.clone();
+            move |_key: CandleKey| {
+                fetched.fetch_add(1, Ordering::Relaxed);
+                Candle {
+                    open: dec!(1),
+                    high: dec!(1),
+                    low: dec!(1),
+                    close: dec!(1),
+                    volume: dec!(1),
+                }
+            }
+        }));
+        let key = |i: i64| CandleKey {
+            market: Symbol::perp("BTC"),
+            time: Utc.with_ymd_and_hms(2021, 8, 1, 0, 0, 0).unwrap() + Duration::minutes(i),
+            interval: Duration::minutes(1),
+        };
+        // Room for two pages of a single candle.
+        let cache = MemCache::new(api, Lru::bytes(&vec![(key(0), None)]) * 2);
+
+        for i in [0, 1, 0, 0, 2, 0, 1] {
+            assert_eq!(cache.get_candles(key(i)).await.unwrap()[0].0, key(i));
+        }
+
+        // The first page stays cached as it is used more recently than the second one.
+        assert_eq!(fetched.load(Ordering::Relaxed), 4);
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.pages), (3, 4, 2));
+        assert!(stats.bytes <= Lru::bytes(&vec![(key(0), None)]) * 2);
+    }
+}
@@ -0,0 +1,218 @@
+use super::CandleGen;
+use crate::{Candle, CandleKey, Symbol};
+
+use chrono::{DateTime, Duration, Utc};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rust_decimal::prelude::*;
+use std::{collections::HashMap, sync::Mutex};
+
+/// How prices evolve from one candle to the next.
+/// Volatilities and drifts are relative changes per candle.
+#[derive(Debug, Clone, Copy)]
+pub enum CandleModel {
+    /// A geometric random walk.
+    RandomWalk { volatility: f64 },
+    /// A geometric random walk with a constant drift.
+    Trending { drift: f64, volatility: f64 },
+    /// Prices are pulled back towards the start price, the reversion is the fraction
+    /// of the deviation that is corrected per candle.
+    MeanReverting { reversion: f64, volatility: f64 },
+    /// A geometric random walk that jumps up or down by the gap size with the given probability.
+    Gaps {
+        volatility: f64,
+        probability: f64,
+        gap: f64,
+    },
+}
+
+/// Generates reproducible random candles.
+/// The same seed, model and origin always generate the same candles,
+/// independent of the order in which they are requested.
+/// Each market and interval follows its own path, starting at the origin.
+pub struct SeededCandles {
+    seed: u64,
+    model: CandleModel,
+    origin: DateTime<Utc>,
+    start_price: f64,
+    #[allow(clippy::type_complexity)]
+    paths: Mutex<HashMap<(Symbol, Duration), (ChaCha8Rng, Vec<Candle>)>>,
+}
+
+impl SeededCandles {
+    /// Paths start at a price of 100 at the origin.
+    pub fn new(seed: u64, model: CandleModel, origin: DateTime<Utc>) -> Self {
+        SeededCandles {
+            seed,
+            model,
+            origin,
+            start_price: 100.0,
+            paths: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn random_walk(seed: u64, origin: DateTime<Utc>) -> Self {
+        Self::new(seed, CandleModel::RandomWalk { volatility: 0.01 }, origin)
+    }
+
+    pub fn trending(seed: u64, origin: DateTime<Utc>) -> Self {
+        Self::new(
+            seed,
+            CandleModel::Trending {
+                drift: 0.001,
+                volatility: 0.01,
+            },
+            origin,
+        )
+    }
+
+    pub fn mean_reverting(seed: u64, origin: DateTime<Utc>) -> Self {
+        Self::new(
+            seed,
+            CandleModel::MeanReverting {
+                reversion: 0.1,
+                volatility: 0.01,
+            },
+            origin,
+        )
+    }
+
+    pub fn gaps(seed: u64, origin: DateTime<Utc>) -> Self {
+        Self::new(
+            seed,
+            CandleModel::Gaps {
+                volatility: 0.01,
+                probability: 0.01,
+                gap: 0.1,
+            },
+            origin,
+        )
+    }
+
+    pub fn with_start_price(mut self, price: f64) -> Self {
+        assert!(price > 0.0);
+        self.start_price = price;
+        self
+    }
+
+    fn next(&self, rng: &mut ChaCha8Rng, open: f64) -> Candle {
+        let change = match self.model {
+            CandleModel::RandomWalk { volatility } => volatility * normal(rng),
+            CandleModel::Trending { drift, volatility } => drift + volatility * normal(rng),
+            CandleModel::MeanReverting {
+                reversion,
+                volatility,
+            } => reversion * (self.start_price / open).ln() + volatility * normal(rng),
+            CandleModel::Gaps {
+                volatility,
+                probability,
+                gap,
+            } => {
+                let jump = if rng.gen_bool(probability) {
+                    if rng.gen_bool(0.5) {
+                        gap
+                    } else {
+                        -gap
+                    }
+                } else {
+                    0.0
+                };
+                jump + volatility * normal(rng)
+            }
+        };
+        let close = open * change.exp();
+        let wick = |rng: &mut ChaCha8Rng| 1.0 + rng.gen::<f64>() * change.abs().max(0.001);
+        let high = open.max(close) * wick(rng);
+        let low = open.min(close) / wick(rng);
+        let volume = rng.gen_range(1.0..100.0);
+
+        let decimal = |value: f64| Decimal::from_f64(value).unwrap_or_default().round_dp(8);
+        Candle {
+            open: decimal(open),
+            high: decimal(high),
+            low: decimal(low),
+            close: decimal(close),
+            volume: decimal(volume),
+        }
+    }
+}
+
+impl CandleGen for SeededCandles {
+    /// Panics if the key is before the origin.
+    fn candle(&self, key: CandleKey) -> Candle {
+        assert!(key.time >= self.origin, "Candle before the origin.");
+        let index = ((key.time - self.origin).num_seconds() / key.interval.num_seconds()) as usize;
+
+        let mut paths = self.paths.lock().unwrap();
+        let (rng, candles) = paths.entry((key.market, key.interval)).or_insert_with(|| {
+            let seed = fxhash::hash64(&(self.seed, key.market, key.interval.num_seconds()));
+            (ChaCha8Rng::seed_from_u64(seed), Vec::new())
+        });
+        while candles.len() <= index {
+            let open = candles
+                .last()
+                .map(|candle| candle.close.to_f64().unwrap())
+                .unwrap_or(self.start_price);
+            let candle = self.next(rng, open);
+            candles.push(candle);
+        }
+
+        candles[index]
+    }
+}
+
+// Sample from the standard normal distribution using the Box-Muller transform.
+fn normal(rng: &mut ChaCha8Rng) -> f64 {
+    let u: f64 = 1.0 - rng.gen::<f64>();
+    let v: f64 = rng.gen();
+    (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn reproducible() {
+        let origin = Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap();
+        let key = |market: &str, i: i32| CandleKey {
+            market: Symbol::perp(market),
+            time: origin + Duration::minutes(1) * i,
+            interval: Duration::minutes(1),
+        };
+
+        let a = SeededCandles::gaps(42, origin);
+        let b = SeededCandles::gaps(42, origin);
+        // Request in a different order.
+        let late = b.candle(key("BTC", 500));
+        let path: Vec<Candle> = (0..=500).map(|i| a.candle(key("BTC", i))).collect();
+        assert_eq!(path[500], late);
+        assert_eq!(path[0].open, Decimal::from(100));
+        for window in path.windows(2) {
+            assert_eq!(window[0].close, window[1].open);
+            assert!(window[1].low <= window[1].open.min(window[1].close));
+            assert!(window[1].high >= window[1].open.max(window[1].close));
+        }
+
+        assert_ne!(a.candle(key("ETH", 500)), late);
+        assert_ne!(
+            SeededCandles::gaps(43, origin).candle(key("BTC", 500)),
+            late
+        );
+    }
+
+    #[test]
+    fn mean_reverting_stays_close() {
+        let origin = Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap();
+        let candles = SeededCandles::mean_reverting(7, origin);
+        for i in 0..1000 {
+            let candle = candles.candle(CandleKey {
+                market: Symbol::perp("BTC"),
+                time: origin + Duration::minutes(i),
+                interval: Duration::minutes(1),
+            });
+            assert!(candle.close > Decimal::from(80) && candle.close < Decimal::from(125));
+        }
+    }
+}
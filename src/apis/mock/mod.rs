@@ -0,0 +1,339 @@
+mod generators;
+
+pub use generators::*;
+
+use super::Api;
+use crate::{
+    apis::{ApiError, FeeSchedule, Margin, Order, OrderInfo},
+    Asset, Candle, CandleKey, MarketInfo, Markets, OrderStatus, Orderbook, PriceSource, Side,
+    Symbol, TimeInForce, Trade, Transfer, Wallet,
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use rust_decimal::prelude::*;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use uuid::Uuid;
+
+/// Generates the candle for a key.
+/// Implemented for closures and the seeded generators.
+pub trait CandleGen: Send + Sync {
+    fn candle(&self, key: CandleKey) -> Candle;
+}
+
+impl<F> CandleGen for F
+where
+    F: Fn(CandleKey) -> Candle + Send + Sync,
+{
+    fn candle(&self, key: CandleKey) -> Candle {
+        self(key)
+    }
+}
+
+/// How the Mock API responds to orders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FillBehavior {
+    /// Fill all orders completely at the current price.
+    Fill,
+    /// Fill the given fraction of every order.
+    PartialFill(Decimal),
+    /// Accept all orders without filling them, they are filled completely
+    /// once their status was queried this many times.
+    Confirm(usize),
+    /// Reject every nth order with `ApiError::Rejected` and fill the others.
+    RejectEveryNth(usize),
+    /// Fail every nth order with `ApiError::Network` and fill the others.
+    NetworkErrorEveryNth(usize),
+    /// Accept the orders of a market without filling them and fill the others.
+    Illiquid(Symbol),
+    /// Reject the orders of a market with `ApiError::Rejected` and fill the others.
+    RejectMarket(Symbol),
+}
+
+pub struct MockSettings<F>
+where
+    F: CandleGen,
+{
+    /// The taker fee.
+    pub fee: Decimal,
+    pub maker_fee: Decimal,
+    pub candles: F,
+    pub markets: Vec<MarketInfo>,
+    /// The wallet the exchange starts with.
+    pub wallet: Wallet,
+    /// The wallet held at the venue, which replaces the wallet on every update like venues do.
+    /// If None, only the wallet the exchange starts with is provided.
+    pub venue_wallet: Option<Wallet>,
+    pub fills: FillBehavior,
+    /// The order books of the markets, the same at every time.
+    pub orderbooks: HashMap<Symbol, Orderbook>,
+    /// The mark and index prices of the markets, the same at every time.
+    pub prices: HashMap<(Symbol, PriceSource), Decimal>,
+    /// The trades of the markets, markets without trades provide none.
+    pub trades: HashMap<Symbol, Vec<Trade>>,
+    /// The number of market updates failing with `ApiError::Network` before they succeed.
+    pub market_failures: usize,
+    /// The margin requirements, none by default.
+    pub margin: Option<Margin>,
+}
+
+impl<F> MockSettings<F>
+where
+    F: CandleGen,
+{
+    /// Settings without fees, markets and funds, filling all orders.
+    pub fn new(candles: F) -> Self {
+        MockSettings {
+            fee: Decimal::ZERO,
+            maker_fee: Decimal::ZERO,
+            candles,
+            markets: Vec::new(),
+            wallet: Wallet::new(),
+            venue_wallet: None,
+            fills: FillBehavior::Fill,
+            orderbooks: HashMap::new(),
+            prices: HashMap::new(),
+            trades: HashMap::new(),
+            market_failures: 0,
+            margin: None,
+        }
+    }
+}
+
+/// The Mock API generates candles instead of fetching them,
+/// which is useful for reproducible tests without network access.
+pub struct Mock<F>
+where
+    F: CandleGen,
+{
+    //orderbooks: HashMap<Symbol, Orderbook>,
+    settings: MockSettings<F>,
+    // Number of orders placed so far.
+    orders: AtomicUsize,
+    // Number of market updates requested so far.
+    market_updates: AtomicUsize,
+    // The fills of orders not confirmed yet, with the number of queries until they are.
+    unconfirmed: Mutex<HashMap<Uuid, (OrderInfo, usize)>>,
+}
+
+impl<F> Mock<F>
+where
+    F: CandleGen,
+{
+    pub fn new(settings: MockSettings<F>) -> Self {
+        Mock {
+            //orderbooks: HashMap::new(),
+            settings,
+            orders: AtomicUsize::new(0),
+            market_updates: AtomicUsize::new(0),
+            unconfirmed: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl<F> Api for Mock<F>
+where
+    F: CandleGen,
+{
+    const NAME: &'static str = "Mock";
+    const LIVE_TRADING_ENABLED: bool = false;
+
+    async fn get_candles(
+        &self,
+        key: CandleKey,
+    ) -> Result<Vec<(CandleKey, Option<Candle>)>, ApiError> {
+        Ok(vec![(key, Some(self.settings.candles.candle(key)))])
+    }
+
+    async fn update_markets(&self, markets: &mut Markets) -> Result<(), ApiError> {
+        if self.market_updates.fetch_add(1, Ordering::SeqCst) < self.settings.market_failures {
+            return Err(ApiError::Network);
+        }
+        *markets = Markets {
+            markets: self
+                .settings
+                .markets
+                .iter()
+                .map(|market_info| (market_info.symbol, *market_info))
+                .collect(),
+        };
+
+        Ok(())
+    }
+
+    async fn get_trades(
+        &self,
+        market: Symbol,
+        range: Range<DateTime<Utc>>,
+    ) -> Result<Option<Vec<Trade>>, ApiError> {
+        Ok(self.settings.trades.get(&market).map(|trades| {
+            trades
+                .iter()
+                .filter(|trade| range.contains(&trade.time))
+                .copied()
+                .collect()
+        }))
+    }
+
+    async fn get_orderbook(
+        &self,
+        market: Symbol,
+        _time: DateTime<Utc>,
+    ) -> Result<Option<Orderbook>, ApiError> {
+        Ok(self.settings.orderbooks.get(&market).cloned())
+    }
+
+    async fn get_price(
+        &self,
+        key: CandleKey,
+        source: PriceSource,
+    ) -> Result<Option<Decimal>, ApiError> {
+        match source {
+            PriceSource::Mid => Ok(self
+                .settings
+                .orderbooks
+                .get(&key.market)
+                .and_then(|orderbook| orderbook.mid_price())),
+            _ => Ok(self.settings.prices.get(&(key.market, source)).copied()),
+        }
+    }
+
+    async fn place_order(&self, order: Order) -> Result<OrderInfo, ApiError> {
+        let n = self.orders.fetch_add(1, Ordering::SeqCst) + 1;
+        let size = match self.settings.fills {
+            FillBehavior::Fill => order.size,
+            FillBehavior::PartialFill(_) if order.time_in_force == TimeInForce::FillOrKill => {
+                return Err(ApiError::Rejected);
+            }
+            FillBehavior::PartialFill(fraction) => order.size * fraction,
+            FillBehavior::RejectEveryNth(nth) if n.is_multiple_of(nth) => {
+                return Err(ApiError::Rejected);
+            }
+            FillBehavior::NetworkErrorEveryNth(nth) if n.is_multiple_of(nth) => {
+                return Err(ApiError::Network);
+            }
+            FillBehavior::RejectEveryNth(_) | FillBehavior::NetworkErrorEveryNth(_) => order.size,
+            FillBehavior::Confirm(_) => order.size,
+            FillBehavior::Illiquid(market) if market == order.market => Decimal::ZERO,
+            FillBehavior::Illiquid(_) => order.size,
+            FillBehavior::RejectMarket(market) if market == order.market => {
+                return Err(ApiError::Rejected);
+            }
+            FillBehavior::RejectMarket(_) => order.size,
+        };
+
+        let fee = self.settings.fee;
+        let info = OrderInfo {
+            order_id: order.order_id,
+            market: order.market,
+            size,
+            price: if order.side == Side::Buy {
+                order.current_price * (Decimal::ONE + fee)
+            } else {
+                order.current_price * (Decimal::ONE - fee)
+            }
+            .round_dp(8),
+            time: order.time,
+            side: order.side,
+            status: OrderStatus::settled(order.size, size),
+        };
+        if let FillBehavior::Confirm(queries) = self.settings.fills {
+            self.unconfirmed
+                .lock()
+                .unwrap()
+                .insert(order.order_id, (info.clone(), queries));
+            return Ok(OrderInfo {
+                size: Decimal::ZERO,
+                price: order.current_price,
+                status: OrderStatus::Submitted,
+                ..info
+            });
+        }
+        Ok(info)
+    }
+
+    async fn order_status(&self, _market: Symbol, order_id: Uuid) -> Result<OrderInfo, ApiError> {
+        let mut unconfirmed = self.unconfirmed.lock().unwrap();
+        let (info, queries) = unconfirmed.get_mut(&order_id).ok_or(ApiError::Api)?;
+        if *queries > 0 {
+            *queries -= 1;
+            return Ok(OrderInfo {
+                size: Decimal::ZERO,
+                status: OrderStatus::Submitted,
+                ..info.clone()
+            });
+        }
+        Ok(info.clone())
+    }
+
+    async fn cancel_order(&self, _market: Symbol, order_id: Uuid) -> Result<OrderInfo, ApiError> {
+        let mut unconfirmed = self.unconfirmed.lock().unwrap();
+        let (info, queries) = unconfirmed.get_mut(&order_id).ok_or(ApiError::Api)?;
+        if *queries > 0 {
+            *queries = 0;
+            info.size = Decimal::ZERO;
+            info.status = OrderStatus::Cancelled;
+        }
+        Ok(info.clone())
+    }
+
+    async fn amend_order(
+        &self,
+        _market: Symbol,
+        order_id: Uuid,
+        price: Decimal,
+        size: Decimal,
+    ) -> Result<OrderInfo, ApiError> {
+        let mut unconfirmed = self.unconfirmed.lock().unwrap();
+        let (info, queries) = unconfirmed.get_mut(&order_id).ok_or(ApiError::Api)?;
+        if *queries == 0 {
+            return Err(ApiError::Rejected);
+        }
+        // The order is filled completely at the new price and size once confirmed.
+        info.price = price;
+        info.size = size;
+        Ok(OrderInfo {
+            size: Decimal::ZERO,
+            status: OrderStatus::Submitted,
+            ..info.clone()
+        })
+    }
+
+    async fn transfer(&self, _transfer: &Transfer) -> Result<(), ApiError> {
+        Ok(())
+    }
+
+    fn format_market(&self, market: Symbol) -> String {
+        market.to_string()
+    }
+
+    async fn update_wallet(&self, wallet: &mut Wallet) -> Result<(), ApiError> {
+        if let Some(venue_wallet) = &self.settings.venue_wallet {
+            *wallet = venue_wallet.clone();
+        } else if wallet.is_fresh() {
+            *wallet = self.settings.wallet.clone();
+        }
+
+        Ok(())
+    }
+
+    fn margin(&self) -> Option<Margin> {
+        self.settings.margin.clone()
+    }
+
+    fn quote_asset(&self) -> Asset {
+        Asset::new("USD")
+    }
+
+    async fn order_fee(&self) -> Decimal {
+        self.settings.fee
+    }
+
+    async fn fee_schedule(&self) -> FeeSchedule {
+        FeeSchedule::new(self.settings.maker_fee, self.settings.fee)
+    }
+}
@@ -1,28 +1,84 @@
+//! Venues and middlewares implementing the [`Api`] trait.
+//!
+//! Each venue lives in its own module behind a feature of the same name,
+//! so only the venues in use are compiled. A venue implements [`Api`]:
+//!
+//! - `get_candles` returns the closed candles starting at the key, with `None` for missing candles,
+//!   see `rest::fill_gaps`.
+//! - `place_order` maps the order type, time in force and post-only flag of an [`Order`],
+//!   and returns the filled size and average price.
+//! - `get_orderbook` optionally returns the book depth, used to simulate fills of larger orders.
+//! - `get_trades` optionally returns historical trades, used to simulate fills within candles.
+//! - `update_wallet` and `update_markets` fetch the balances and the tradable markets.
+//! - `format_market`, `quote_asset`, `order_fee` and `fee_schedule` describe the venue.
+//!
+//! Venues with a JSON REST API can share the client and signing helpers of the `rest` feature.
+//! Everything else, storing candles, simulating orders and monitoring, is provided by the
+//! middlewares and works with any venue.
+
+mod aggregate;
 #[cfg(feature = "binance")]
 mod binance;
+#[cfg(feature = "bybit")]
+mod bybit;
+#[cfg(feature = "coinbase")]
+mod coinbase;
+mod composite;
+mod fees;
+mod file_data;
 mod forward_fill;
-#[cfg(feature = "ftx")]
+#[cfg(feature = "ftx-legacy")]
 mod ftx;
-#[cfg(test)]
+#[cfg(feature = "generic-rest")]
+mod generic_rest;
+mod journal;
+mod mem_cache;
 mod mock;
 mod monitor;
+mod replay;
+#[cfg(feature = "rest")]
+pub mod rest;
+mod router;
+mod shared;
 mod simulate;
 mod store;
 
 #[cfg(feature = "binance")]
 pub use self::binance::*;
-#[cfg(feature = "ftx")]
+#[cfg(feature = "bybit")]
+pub use self::bybit::*;
+#[cfg(feature = "coinbase")]
+pub use self::coinbase::*;
+#[cfg(feature = "ftx-legacy")]
 pub use self::ftx::*;
+#[cfg(feature = "generic-rest")]
+pub use self::generic_rest::*;
+pub use aggregate::*;
+pub use composite::*;
+pub use fees::*;
+pub use file_data::*;
 pub use forward_fill::*;
+pub use journal::*;
+pub use mem_cache::*;
+pub use mock::*;
 pub use monitor::*;
+pub use replay::*;
+pub use router::*;
 pub use simulate::*;
 pub use store::*;
 
 use chrono::{DateTime, Utc};
 use rust_decimal::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
 use thiserror::Error;
+use uuid::Uuid;
 
-use crate::{Asset, Candle, CandleKey, Markets, Order, OrderInfo, Symbol, Wallet};
+use crate::{
+    Asset, Candle, CandleKey, CollateralWeights, Command, Exposure, MarketHealth, Markets, Order,
+    OrderInfo, Orderbook, Position, PriceSource, Report, Symbol, Trade, Transfer, Wallet,
+    WalletDiscrepancy,
+};
 use async_trait::async_trait;
 
 #[async_trait]
@@ -38,26 +94,200 @@ pub trait Api: Send + Sync {
         &self,
         key: CandleKey,
     ) -> Result<Vec<(CandleKey, Option<Candle>)>, ApiError>;
+    /// Get the order book of a market as of a time, if the API provides book depth.
+    async fn get_orderbook(
+        &self,
+        _market: Symbol,
+        _time: DateTime<Utc>,
+    ) -> Result<Option<Orderbook>, ApiError> {
+        Ok(None)
+    }
+
+    /// Get the price of a market from the source as of the close of the candle of the key,
+    /// if the API provides it. By default, only the mid price of the order book is provided.
+    async fn get_price(
+        &self,
+        key: CandleKey,
+        source: PriceSource,
+    ) -> Result<Option<Decimal>, ApiError> {
+        match source {
+            PriceSource::Mid => Ok(self
+                .get_orderbook(key.market, key.time + key.interval)
+                .await?
+                .and_then(|orderbook| orderbook.mid_price())),
+            _ => Ok(None),
+        }
+    }
+    /// Get the prices of a market from the source as of the close of the candles starting at the key,
+    /// as many as the API provides at once. By default, only the price of the key is requested.
+    async fn get_prices(
+        &self,
+        key: CandleKey,
+        source: PriceSource,
+    ) -> Result<Vec<(CandleKey, Option<Decimal>)>, ApiError> {
+        Ok(vec![(key, self.get_price(key, source).await?)])
+    }
+    /// Get the trades of a market in the time range in the order they happened,
+    /// if the API provides historical trades.
+    async fn get_trades(
+        &self,
+        _market: Symbol,
+        _range: Range<DateTime<Utc>>,
+    ) -> Result<Option<Vec<Trade>>, ApiError> {
+        Ok(None)
+    }
     /// Place order using this API.
+    /// Orders that are not settled immediately are returned with a status that is not final,
+    /// their fills are confirmed with `order_status`.
     async fn place_order(&self, order: Order) -> Result<OrderInfo, ApiError>;
+    /// Place multiple orders at once, returns the result of every order in the same order.
+    /// Venues with batch order endpoints place them with as few requests as possible,
+    /// by default every order is placed on its own, concurrently.
+    async fn place_orders(&self, orders: Vec<Order>) -> Vec<Result<OrderInfo, ApiError>> {
+        futures_util::future::join_all(orders.into_iter().map(|order| self.place_order(order)))
+            .await
+    }
+    /// The current fill and status of an order placed before.
+    async fn order_status(&self, _market: Symbol, _order_id: Uuid) -> Result<OrderInfo, ApiError> {
+        Err(ApiError::Unsupported)
+    }
+    /// Cancel the rest of an order that is not settled yet, returns its final fill and status.
+    async fn cancel_order(&self, _market: Symbol, _order_id: Uuid) -> Result<OrderInfo, ApiError> {
+        Err(ApiError::Unsupported)
+    }
+    /// Change the limit price and the total size of an order that is not settled yet.
+    async fn amend_order(
+        &self,
+        _market: Symbol,
+        _order_id: Uuid,
+        _price: Decimal,
+        _size: Decimal,
+    ) -> Result<OrderInfo, ApiError> {
+        Err(ApiError::Unsupported)
+    }
     /// Custom formatting for each API.
     fn format_market(&self, market: Symbol) -> String;
     /// Update the current state of the user wallet.
     async fn update_wallet(&self, wallet: &mut Wallet) -> Result<(), ApiError>;
     /// Update the current state of the markets.
     async fn update_markets(&self, market: &mut Markets) -> Result<(), ApiError>;
+    /// The taker fee of an order.
     async fn order_fee(&self) -> Decimal;
+    /// The maker and taker fees of every market.
+    async fn fee_schedule(&self) -> FeeSchedule {
+        FeeSchedule::flat(self.order_fee().await)
+    }
+    /// The annual interest rate for borrowing the market to short it, if the API knows it.
+    async fn borrow_rate(&self, _market: Symbol) -> Option<Decimal> {
+        None
+    }
+    /// The interest for borrowing since the last call until the time, in the quote asset,
+    /// called once per step. Venues charging the interest themselves report it in the wallet.
+    async fn borrow_costs(&self, _time: DateTime<Utc>) -> Result<Decimal, ApiError> {
+        Ok(Decimal::ZERO)
+    }
+    /// Margin requirements, if margin trading is simulated by this API.
+    fn margin(&self) -> Option<Margin> {
+        None
+    }
     fn quote_asset(&self) -> Asset;
+    /// Statistics of the gap between fill prices and the simulated fill prices.
+    fn execution_gap(&self) -> Option<ExecutionGap> {
+        None
+    }
+    /// Operator commands received since the last call.
+    async fn commands(&self) -> Vec<Command> {
+        Vec::new()
+    }
+    /// Convert small balances of the assets to the quote asset.
+    async fn convert_dust(&self, _assets: Vec<Asset>) -> Result<(), ApiError> {
+        Err(ApiError::Unsupported)
+    }
+    /// Move funds between accounts at the venue, see `Exchange::transfer`.
+    async fn transfer(&self, _transfer: &Transfer) -> Result<(), ApiError> {
+        Err(ApiError::Unsupported)
+    }
+    /// Store the state of a strategy as JSON, to resume it after a restart.
+    async fn save_state(&self, _strategy_name: &str, _state: String) -> Result<(), ApiError> {
+        Err(ApiError::Unsupported)
+    }
+    /// The state of a strategy stored before, if any.
+    async fn load_state(&self, _strategy_name: &str) -> Result<Option<String>, ApiError> {
+        Err(ApiError::Unsupported)
+    }
+    /// Called once when the strategy stops, e.g. to flush pending logs.
+    async fn shutdown(&self) {}
     fn hello(&self, _strategy_name: &'static str) {}
-    fn status(&self, _time: DateTime<Utc>, _total: Decimal) {}
+    /// Called once after `hello` with the serialized parameters of the strategy, if it has any.
+    fn parameters(&self, _parameters: &serde_json::Value) {}
+    /// Called after every step with the total value and the exposure of the open positions.
+    fn status(&self, _time: DateTime<Utc>, _total: Decimal, _exposure: &Exposure) {}
+    /// Called whenever a watched market turns stale or healthy again.
+    fn health(&self, _time: DateTime<Utc>, _health: &MarketHealth) {}
+    /// Called for every balance the wallet of the venue holds differently than booked locally,
+    /// see `Settings::reconciliation`.
+    fn reconciled(&self, _discrepancy: &WalletDiscrepancy) {}
+    /// Called whenever the strategy logs a value of a custom series, see `Exchange::metric`.
+    fn metric(&self, _time: DateTime<Utc>, _name: &str, _value: Decimal) {}
+    /// Called after every step with the open positions.
+    fn positions(&self, _time: DateTime<Utc>, _positions: &[Position]) {}
+    /// Called once for every position that got closed.
+    fn closed(&self, _time: DateTime<Utc>, _position: &Position) {}
+    /// Called once with the report when the strategy stopped without an error.
+    fn finished(&self, _report: &Report) {}
+    /// The id of the session the strategy is logged in, added to the spans of its steps.
+    fn session_id(&self) -> Option<Uuid> {
+        None
+    }
 }
 
-#[derive(Error, Debug)]
+/// Margin requirements for leveraged positions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Margin {
+    /// The maximum leverage of a position.
+    pub max_leverage: Decimal,
+    /// The fraction of the position value that needs to be covered by its margin,
+    /// otherwise the position gets liquidated.
+    pub maintenance_margin: Decimal,
+    /// Assets other than the quote asset backing positions, none by default.
+    /// With collateral, the quote asset can be borrowed against it.
+    pub collateral: CollateralWeights,
+}
+
+impl Margin {
+    pub fn new(max_leverage: Decimal, maintenance_margin: Decimal) -> Self {
+        Margin {
+            max_leverage,
+            maintenance_margin,
+            collateral: CollateralWeights::default(),
+        }
+    }
+
+    /// Let other assets back positions, see [CollateralWeights].
+    pub fn with_collateral(mut self, collateral: CollateralWeights) -> Self {
+        self.collateral = collateral;
+        self
+    }
+}
+
+#[derive(Error, Debug, Clone, Serialize, Deserialize)]
 pub enum ApiError {
     #[error("Could not connect to the API.")]
     Network,
     #[error("Internal API error.")]
     Api,
+    #[error("Order rejected.")]
+    Rejected,
+    #[error("Not supported by the API.")]
+    Unsupported,
+    #[error("The candle store is not available.")]
+    Store,
+    #[error("Fetched candles conflict with the stored candles.")]
+    CandleConflict,
+    #[error("The gap in the candles of {0} is too large to forward fill.")]
+    GapTooLarge(Symbol),
+    #[error("The market {0} is not on a venue of the router.")]
+    UnknownVenue(Symbol),
 }
 
 #[cfg(test)]
@@ -66,47 +296,211 @@ mod tests {
 
     use super::*;
 
+    // Recorded candles of BTC-PERP from 2021-08-01 00:00 to 05:00 in one minute intervals.
+    fn replay() -> ReplayApi {
+        ReplayApi::from_file(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/fixtures/btc_perp_1m.json"
+        ))
+        .unwrap()
+    }
+
     #[tokio::test]
     async fn store_api() {
-        let ftx_api = Ftx::from_env();
-        let store_api = Store::new(Ftx::from_env()).await;
+        let replay_api = replay();
+        let store_api = Store::with_store(replay(), MemoryCandleStore::new());
 
         let key = CandleKey {
             market: Symbol::new("BTC-PERP"),
-            time: Utc.ymd(2021, 8, 1).and_hms(0, 0, 0),
+            time: Utc.with_ymd_and_hms(2021, 8, 1, 0, 0, 0).unwrap(),
             interval: Duration::minutes(1),
         };
 
-        let ftx_candles = ftx_api.get_candles(key).await.unwrap();
+        let replay_candles = replay_api.get_candles(key).await.unwrap();
         let store_candles = store_api.get_candles(key).await.unwrap();
+        // The second request is served from the store.
+        let stored_candles = store_api.get_candles(key).await.unwrap();
 
-        assert!(ftx_candles.len() > 100);
+        assert!(replay_candles.len() > 100);
         assert!(store_candles.len() > 100);
 
-        assert!(ftx_candles
-            .into_iter()
-            .zip(store_candles)
-            .all(|(a, b)| a == b));
+        assert_eq!(replay_candles, store_candles);
+        assert_eq!(replay_candles, stored_candles);
+    }
+
+    #[tokio::test]
+    async fn aggregate_api() {
+        let replay_api = replay();
+        let aggregate_api = Aggregate::new(replay(), Duration::minutes(1));
+
+        let key = CandleKey {
+            market: Symbol::new("BTC-PERP"),
+            time: Utc.with_ymd_and_hms(2021, 8, 1, 0, 0, 0).unwrap(),
+            interval: Duration::minutes(5),
+        };
+
+        let replay_candles = replay_api
+            .get_candles(CandleKey {
+                interval: Duration::minutes(1),
+                ..key
+            })
+            .await
+            .unwrap();
+        let aggregate_candles = aggregate_api.get_candles(key).await.unwrap();
+
+        assert!(replay_candles.len() > 100);
+        assert_eq!(aggregate_candles.len(), replay_candles.len() / 5);
+        let first = replay_candles[..5]
+            .iter()
+            .filter_map(|(_, candle)| *candle)
+            .reduce(Candle::merge);
+        assert_eq!(aggregate_candles[0], (key, first));
+        assert_eq!(aggregate_candles[1].0.time, key.time + Duration::minutes(5));
+    }
+
+    #[tokio::test]
+    async fn forward_fill_gaps() {
+        let alt = Symbol::perp("ALT");
+        let path = std::env::temp_dir().join(format!("gaps-{}.csv", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            "time,open,high,low,close,volume\n\
+            2021-08-01T00:00:00Z,1,1,1,1,1\n\
+            2021-08-01T00:05:00Z,2,2,2,2,1\n\
+            2021-08-01T00:06:00Z,3,3,3,3,1\n\
+            2021-08-01T00:08:00Z,4,4,4,4,1\n",
+        )
+        .unwrap();
+        let file_data = || {
+            FileData::new()
+                .with_file(alt, Duration::minutes(1), &path)
+                .unwrap()
+        };
+        let key = CandleKey {
+            market: alt,
+            time: Utc.with_ymd_and_hms(2021, 8, 1, 0, 0, 0).unwrap(),
+            interval: Duration::minutes(1),
+        };
+        let closes = |on_gap| async move {
+            ForwardFill::new(file_data(), Duration::minutes(2), on_gap)
+                .get_candles(key)
+                .await
+                .map(|candles| {
+                    candles
+                        .into_iter()
+                        .map(|(_, candle)| candle.map(|candle| candle.close.to_u8().unwrap()))
+                        .collect::<Vec<_>>()
+                })
+        };
+
+        assert!(matches!(
+            closes(OnGap::Error).await,
+            Err(ApiError::GapTooLarge(market)) if market == alt
+        ));
+        let (some, none) = (Some, None);
+        assert_eq!(
+            closes(OnGap::FillAnyway).await.unwrap(),
+            vec![
+                some(1),
+                some(1),
+                some(1),
+                some(1),
+                some(1),
+                some(2),
+                some(3),
+                some(3),
+                some(4)
+            ]
+        );
+        assert_eq!(
+            closes(OnGap::DropCandle).await.unwrap(),
+            vec![
+                some(1),
+                some(1),
+                some(1),
+                none,
+                none,
+                some(2),
+                some(3),
+                some(3),
+                some(4)
+            ]
+        );
+        assert_eq!(
+            closes(OnGap::SkipSymbol).await.unwrap(),
+            vec![
+                some(1),
+                some(1),
+                some(1),
+                none,
+                none,
+                some(2),
+                some(3),
+                none,
+                some(4)
+            ]
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn composite_api() {
+        let alt = Symbol::perp("ALT");
+        let path = std::env::temp_dir().join(format!("alt-{}.csv", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            "time,open,high,low,close,volume\n\
+            2021-08-01T00:00:00Z,1,2,1,2,10\n\
+            1627776120,2,3,2,3,20\n",
+        )
+        .unwrap();
+        let file_data = FileData::new()
+            .with_file(alt, Duration::minutes(1), &path)
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let composite_api =
+            Composite::new(replay()).with_route(alt, std::sync::Arc::new(file_data));
+
+        let key = CandleKey {
+            market: Symbol::new("BTC-PERP"),
+            time: Utc.with_ymd_and_hms(2021, 8, 1, 0, 0, 0).unwrap(),
+            interval: Duration::minutes(1),
+        };
+        assert_eq!(
+            composite_api.get_candles(key).await.unwrap(),
+            replay().get_candles(key).await.unwrap()
+        );
+
+        // Missing candles of the file are None.
+        let alt_candles = composite_api
+            .get_candles(CandleKey { market: alt, ..key })
+            .await
+            .unwrap();
+        assert_eq!(alt_candles.len(), 3);
+        assert_eq!(alt_candles[0].1.unwrap().close, Decimal::TWO);
+        assert_eq!(alt_candles[1].1, None);
+        assert_eq!(alt_candles[2].0.time, key.time + Duration::minutes(2));
+        assert_eq!(alt_candles[2].1.unwrap().volume, Decimal::from(20));
     }
 
     #[tokio::test]
     async fn simulate_api() {
-        let ftx_api = Ftx::from_env();
-        let simulate_api = Simulate::new(Ftx::from_env(), Wallet::new());
+        let replay_api = replay();
+        let simulate_api = Simulate::new(replay(), Wallet::new());
 
         let key = CandleKey {
             market: Symbol::new("BTC-PERP"),
-            time: Utc.ymd(2021, 8, 1).and_hms(0, 0, 0),
+            time: Utc.with_ymd_and_hms(2021, 8, 1, 0, 0, 0).unwrap(),
             interval: Duration::minutes(1),
         };
 
-        let ftx_candles = ftx_api.get_candles(key).await.unwrap();
+        let replay_candles = replay_api.get_candles(key).await.unwrap();
         let simulate_candles = simulate_api.get_candles(key).await.unwrap();
 
-        assert!(ftx_candles.len() > 100);
+        assert!(replay_candles.len() > 100);
         assert!(simulate_candles.len() > 100);
 
-        assert!(ftx_candles
+        assert!(replay_candles
             .into_iter()
             .zip(simulate_candles)
             .all(|(a, b)| a == b));
@@ -114,24 +508,67 @@ mod tests {
 
     #[tokio::test]
     async fn forward_fill_api() {
-        let ftx_api = Ftx::from_env();
-        let forward_fill_api = ForwardFill::new(Ftx::from_env(), Duration::hours(1));
+        let replay_api = replay();
+        let forward_fill_api = ForwardFill::new(replay(), Duration::hours(1), OnGap::Error);
 
         let key = CandleKey {
             market: Symbol::new("BTC-PERP"),
-            time: Utc.ymd(2021, 8, 1).and_hms(0, 0, 0),
+            time: Utc.with_ymd_and_hms(2021, 8, 1, 0, 0, 0).unwrap(),
             interval: Duration::minutes(1),
         };
 
-        let ftx_candles = ftx_api.get_candles(key).await.unwrap();
+        let replay_candles = replay_api.get_candles(key).await.unwrap();
         let forward_fill_candles = forward_fill_api.get_candles(key).await.unwrap();
 
-        assert!(ftx_candles.len() > 100);
+        assert!(replay_candles.len() > 100);
         assert!(forward_fill_candles.len() > 100);
 
-        assert!(ftx_candles
+        assert!(replay_candles
             .into_iter()
             .zip(forward_fill_candles)
             .all(|(a, b)| a == b));
     }
+
+    // The candles of a key have to start at its time, anything else would release
+    // a newer candle as the candle of the step.
+    async fn assert_no_look_ahead<A: Api>(api: &A, key: CandleKey) {
+        let candles = api.get_candles(key).await.unwrap();
+        assert_eq!(candles.first().map(|(first, _)| *first), Some(key));
+        for (i, (candle_key, _)) in candles.iter().enumerate() {
+            assert_eq!(candle_key.time, key.time + key.interval * i as i32);
+        }
+    }
+
+    #[tokio::test]
+    async fn no_look_ahead() {
+        let start = Utc.with_ymd_and_hms(2021, 8, 1, 0, 0, 0).unwrap();
+        let key = |minutes: i64, interval: i64| CandleKey {
+            market: Symbol::new("BTC-PERP"),
+            time: start + Duration::minutes(minutes),
+            interval: Duration::minutes(interval),
+        };
+        // Requested out of order, so cached pages are requested again from within.
+        let keys = [key(137, 1), key(3, 1), key(138, 1), key(4, 1)];
+
+        let store = Store::with_store(replay(), MemoryCandleStore::new());
+        let mem_cache = MemCache::new(replay(), 1 << 20);
+        let forward_fill = ForwardFill::new(replay(), Duration::minutes(5), OnGap::FillAnyway);
+        let composite = Composite::new(replay());
+        let simulate = Simulate::new(
+            Store::with_store(replay(), MemoryCandleStore::new()),
+            Wallet::new(),
+        );
+        for key in keys {
+            assert_no_look_ahead(&store, key).await;
+            assert_no_look_ahead(&mem_cache, key).await;
+            assert_no_look_ahead(&forward_fill, key).await;
+            assert_no_look_ahead(&composite, key).await;
+            assert_no_look_ahead(&simulate, key).await;
+        }
+
+        let aggregate = Aggregate::new(replay(), Duration::minutes(1));
+        for key in [key(135, 5), key(5, 5), key(140, 5)] {
+            assert_no_look_ahead(&aggregate, key).await;
+        }
+    }
 }
@@ -1,15 +1,25 @@
 use super::Api;
 use crate::{
-    apis::{ApiError, Order, OrderInfo},
-    Asset, Candle, CandleKey, Markets, Symbol, Wallet,
+    apis::{simulate::simulated_price, ApiError, FeeSchedule, Margin, Order, OrderInfo},
+    Asset, Candle, CandleKey, Command, Exposure, MarketHealth, Markets, Orderbook, Position,
+    PriceSource, Report, Side, Symbol, Trade, Transfer, Wallet, WalletDiscrepancy,
 };
 use async_trait::async_trait;
-use chrono::{DateTime, Timelike, Utc};
+use chrono::{DateTime, Duration, Timelike, Utc};
 use rust_decimal::Decimal;
-use serde::{Deserialize, Serialize};
-use sqlx::{postgres::PgPoolOptions, PgPool};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{postgres::PgPoolOptions, Executor, PgPool};
 use std::env;
-use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use std::{
+    collections::{HashMap, VecDeque},
+    ops::Range,
+    sync::Mutex,
+};
+use tokio::sync::{
+    mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    oneshot,
+};
 use uuid::Uuid;
 
 pub struct Monitor<A>
@@ -19,6 +29,154 @@ where
     api: A,
     tx: UnboundedSender<Box<dyn Log>>,
     session_id: Uuid,
+    // Relative gaps of the most recent fills.
+    gaps: Mutex<VecDeque<Decimal>>,
+    commands: Mutex<UnboundedReceiver<Command>>,
+    kind: SessionKind,
+    version: Option<String>,
+    equity_cadence: EquityCadence,
+    equity: Mutex<EquityState>,
+}
+
+/// How often the equity is logged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EquityCadence {
+    /// After every step.
+    EveryStep,
+    /// At the first step and then once at least the duration has passed since the last log.
+    Every(Duration),
+    /// At the first step and then whenever the total changed by more than the fraction
+    /// since the last log, e.g. 0.01 for 1%.
+    OnChange(Decimal),
+}
+
+impl Default for EquityCadence {
+    fn default() -> Self {
+        EquityCadence::Every(Duration::hours(1))
+    }
+}
+
+impl EquityCadence {
+    // Whether the equity is logged, given the time and total of the last log.
+    fn due(
+        &self,
+        last: Option<(DateTime<Utc>, Decimal)>,
+        time: DateTime<Utc>,
+        total: Decimal,
+    ) -> bool {
+        let (last_time, last_total) = match last {
+            Some(last) => last,
+            None => return true,
+        };
+        match *self {
+            EquityCadence::EveryStep => true,
+            EquityCadence::Every(duration) => time - last_time >= duration,
+            EquityCadence::OnChange(_) if last_total.is_zero() => total != last_total,
+            EquityCadence::OnChange(fraction) => {
+                ((total - last_total) / last_total).abs() > fraction
+            }
+        }
+    }
+}
+
+// The highest total so far for the drawdown, and the time and total of the last logged equity.
+#[derive(Default)]
+struct EquityState {
+    peak: Decimal,
+    last: Option<(DateTime<Utc>, Decimal)>,
+}
+
+/// What a session is run for, so experiments can be told apart from production runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionKind {
+    /// Simulated on historical data.
+    Backtest,
+    /// Simulated on live data.
+    Paper,
+    /// Trading on the real exchange.
+    Live,
+}
+
+impl SessionKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SessionKind::Backtest => "backtest",
+            SessionKind::Paper => "paper",
+            SessionKind::Live => "live",
+        }
+    }
+}
+
+// How often the commands table is polled.
+const COMMAND_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+// Migrations of the monitor schema, applied in order and once per database.
+// Released migrations must not be changed, changes to the schema are new migrations.
+const MIGRATIONS: &[(i64, &str, &str)] = &[
+    (
+        1,
+        "create tables",
+        include_str!("../../migrations/monitor/0001_create_tables.sql"),
+    ),
+    (
+        2,
+        "session uniqueness",
+        include_str!("../../migrations/monitor/0002_session_uniqueness.sql"),
+    ),
+    (
+        3,
+        "realized pnl",
+        include_str!("../../migrations/monitor/0003_realized_pnl.sql"),
+    ),
+    (
+        4,
+        "order status",
+        include_str!("../../migrations/monitor/0004_order_status.sql"),
+    ),
+    (
+        5,
+        "equity drawdown and exposure",
+        include_str!("../../migrations/monitor/0005_equity_drawdown_exposure.sql"),
+    ),
+    (
+        6,
+        "market health",
+        include_str!("../../migrations/monitor/0006_market_health.sql"),
+    ),
+    (
+        7,
+        "metrics",
+        include_str!("../../migrations/monitor/0007_metrics.sql"),
+    ),
+    (
+        8,
+        "session parameters",
+        include_str!("../../migrations/monitor/0008_session_parameters.sql"),
+    ),
+    (
+        9,
+        "wallet audit",
+        include_str!("../../migrations/monitor/0009_wallet_audit.sql"),
+    ),
+];
+
+// Key of the advisory lock that serializes migrations of sessions starting at the same time.
+const MIGRATION_LOCK: i64 = 0x6d6f6e69746f72;
+
+// Number of fills the execution gap statistics are computed from.
+const GAP_WINDOW: usize = 100;
+
+/// Rolling statistics of how much worse fills were than the simulation predicted,
+/// relative to the predicted price. Positive gaps mean paying more when buying
+/// or receiving less when selling than in backtests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExecutionGap {
+    /// Number of fills in the window.
+    pub fills: usize,
+    pub mean: Decimal,
+    pub last: Decimal,
+    pub worst: Decimal,
 }
 
 impl<A> Monitor<A>
@@ -27,23 +185,29 @@ where
 {
     pub fn new(api: A) -> Self {
         let (tx, mut rx) = unbounded_channel::<Box<dyn Log>>();
+        let (commands_tx, commands_rx) = unbounded_channel();
         let session_id = Uuid::new_v4();
 
         tokio::spawn(async move {
-            match PgPoolOptions::new()
-                .connect(&env::var("DATABASE_URL").unwrap())
-                .await
-            {
-                Ok(pool) => {
+            let pool = match env::var("DATABASE_URL") {
+                Ok(url) => PgPoolOptions::new().connect(&url).await.ok(),
+                Err(_) => None,
+            };
+            match pool {
+                Some(pool) => {
+                    if let Err(err) = migrate(&pool).await {
+                        tracing::error!("Failed to migrate the monitor database: {}", err);
+                    }
+                    tokio::spawn(poll_commands(pool.clone(), session_id, commands_tx));
                     while let Some(log) = rx.recv().await {
-                        log::trace!("monitor update");
+                        tracing::trace!("monitor update");
                         if let Err(err) = log.update(&pool, session_id).await {
-                            log::error!("A database error occurred: {}", err);
+                            tracing::error!("A database error occurred: {}", err);
                         }
                     }
                 }
-                Err(_) => {
-                    log::error!("Failed to connect to monitor database.");
+                None => {
+                    tracing::error!("Failed to connect to monitor database.");
                     while let Some(_log) = rx.recv().await {
                         // Discard log.
                     }
@@ -55,6 +219,100 @@ where
             api,
             tx,
             session_id,
+            gaps: Mutex::new(VecDeque::new()),
+            commands: Mutex::new(commands_rx),
+            kind: if A::LIVE_TRADING_ENABLED {
+                SessionKind::Live
+            } else {
+                SessionKind::Paper
+            },
+            version: None,
+            equity_cadence: EquityCadence::default(),
+            equity: Mutex::new(EquityState::default()),
+        }
+    }
+
+    /// Defaults to live if the API trades live and paper otherwise.
+    pub fn with_kind(mut self, kind: SessionKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// How often the equity is logged, hourly by default.
+    pub fn with_equity_cadence(mut self, cadence: EquityCadence) -> Self {
+        self.equity_cadence = cadence;
+        self
+    }
+
+    /// Record the version of the strategy code with backtest sessions, e.g. a commit hash.
+    pub fn with_version(mut self, version: impl Into<String>) -> Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    /// The parameters the strategy of a session ran with, to run it again.
+    /// Returns None if the session is unknown or the strategy did not provide parameters.
+    pub async fn session_parameters<P: DeserializeOwned>(
+        &self,
+        session_id: Uuid,
+    ) -> Result<Option<P>, ApiError> {
+        let (tx, rx) = oneshot::channel();
+        self.tx
+            .send(
+                LoadParameters {
+                    session_id,
+                    tx: Mutex::new(Some(tx)),
+                }
+                .boxed(),
+            )
+            .ok();
+        // The request is dropped without a database connection or if the query fails.
+        rx.await
+            .map_err(|_| ApiError::Api)?
+            .map(|parameters| serde_json::from_str(&parameters))
+            .transpose()
+            .map_err(|err| {
+                tracing::error!("Could not parse the session parameters: {}", err);
+                ApiError::Api
+            })
+    }
+
+    // Log an order before it is placed, returns the price a backtest would have assumed.
+    async fn submitted(&self, order: &Order) -> Decimal {
+        self.tx.send(order.clone().boxed()).ok();
+        simulated_price(order, self.api.order_fee().await, Decimal::ZERO)
+    }
+
+    // Log a placed order and the gap between its fill price and the expected price.
+    fn placed(&self, expected_price: Decimal, order_info: &OrderInfo) {
+        self.tx.send(order_info.clone().boxed()).ok();
+
+        if !order_info.size.is_zero() && !expected_price.is_zero() {
+            let gap = if order_info.side == Side::Buy {
+                order_info.price - expected_price
+            } else {
+                expected_price - order_info.price
+            } / expected_price;
+            let gap = gap.round_dp(8);
+
+            let mut gaps = self.gaps.lock().unwrap();
+            if gaps.len() == GAP_WINDOW {
+                gaps.pop_front();
+            }
+            gaps.push_back(gap);
+
+            self.tx
+                .send(
+                    Fill {
+                        order_id: order_info.order_id,
+                        expected_price,
+                        executed_price: order_info.price,
+                        gap,
+                        time: order_info.time,
+                    }
+                    .boxed(),
+                )
+                .ok();
         }
     }
 }
@@ -72,14 +330,51 @@ impl<A: Api> Api for Monitor<A> {
     }
 
     async fn place_order(&self, order: Order) -> Result<OrderInfo, ApiError> {
-        log::trace!("place order monitor");
-
-        self.tx.send(order.clone().boxed()).ok();
+        tracing::trace!("place order monitor");
 
+        let expected_price = self.submitted(&order).await;
         let order_info = self.api.place_order(order).await?;
+        self.placed(expected_price, &order_info);
+
+        Ok(order_info)
+    }
+
+    async fn place_orders(&self, orders: Vec<Order>) -> Vec<Result<OrderInfo, ApiError>> {
+        let mut expected_prices = Vec::with_capacity(orders.len());
+        for order in &orders {
+            expected_prices.push(self.submitted(order).await);
+        }
+        let results = self.api.place_orders(orders).await;
+        for (expected_price, order_info) in expected_prices.into_iter().zip(&results) {
+            if let Ok(order_info) = order_info {
+                self.placed(expected_price, order_info);
+            }
+        }
 
+        results
+    }
+
+    async fn order_status(&self, market: Symbol, order_id: Uuid) -> Result<OrderInfo, ApiError> {
+        let order_info = self.api.order_status(market, order_id).await?;
         self.tx.send(order_info.clone().boxed()).ok();
+        Ok(order_info)
+    }
 
+    async fn cancel_order(&self, market: Symbol, order_id: Uuid) -> Result<OrderInfo, ApiError> {
+        let order_info = self.api.cancel_order(market, order_id).await?;
+        self.tx.send(order_info.clone().boxed()).ok();
+        Ok(order_info)
+    }
+
+    async fn amend_order(
+        &self,
+        market: Symbol,
+        order_id: Uuid,
+        price: Decimal,
+        size: Decimal,
+    ) -> Result<OrderInfo, ApiError> {
+        let order_info = self.api.amend_order(market, order_id, price, size).await?;
+        self.tx.send(order_info.clone().boxed()).ok();
         Ok(order_info)
     }
 
@@ -103,6 +398,121 @@ impl<A: Api> Api for Monitor<A> {
         self.api.order_fee().await
     }
 
+    async fn get_orderbook(
+        &self,
+        market: Symbol,
+        time: DateTime<Utc>,
+    ) -> Result<Option<Orderbook>, ApiError> {
+        self.api.get_orderbook(market, time).await
+    }
+
+    async fn get_trades(
+        &self,
+        market: Symbol,
+        range: Range<DateTime<Utc>>,
+    ) -> Result<Option<Vec<Trade>>, ApiError> {
+        self.api.get_trades(market, range).await
+    }
+
+    async fn get_price(
+        &self,
+        key: CandleKey,
+        source: PriceSource,
+    ) -> Result<Option<Decimal>, ApiError> {
+        self.api.get_price(key, source).await
+    }
+
+    async fn get_prices(
+        &self,
+        key: CandleKey,
+        source: PriceSource,
+    ) -> Result<Vec<(CandleKey, Option<Decimal>)>, ApiError> {
+        self.api.get_prices(key, source).await
+    }
+
+    async fn fee_schedule(&self) -> FeeSchedule {
+        self.api.fee_schedule().await
+    }
+
+    async fn borrow_rate(&self, market: Symbol) -> Option<Decimal> {
+        self.api.borrow_rate(market).await
+    }
+
+    async fn borrow_costs(&self, time: DateTime<Utc>) -> Result<Decimal, ApiError> {
+        self.api.borrow_costs(time).await
+    }
+
+    async fn convert_dust(&self, assets: Vec<Asset>) -> Result<(), ApiError> {
+        self.api.convert_dust(assets).await
+    }
+
+    async fn transfer(&self, transfer: &Transfer) -> Result<(), ApiError> {
+        self.api.transfer(transfer).await
+    }
+
+    async fn save_state(&self, strategy_name: &str, state: String) -> Result<(), ApiError> {
+        self.tx
+            .send(
+                StrategyState {
+                    name: strategy_name.to_owned(),
+                    state,
+                    time: Utc::now(),
+                }
+                .boxed(),
+            )
+            .ok();
+        Ok(())
+    }
+
+    async fn load_state(&self, strategy_name: &str) -> Result<Option<String>, ApiError> {
+        let (tx, rx) = oneshot::channel();
+        self.tx
+            .send(
+                LoadState {
+                    name: strategy_name.to_owned(),
+                    tx: Mutex::new(Some(tx)),
+                }
+                .boxed(),
+            )
+            .ok();
+        // The request is dropped without a database connection or if the query fails.
+        rx.await.map_err(|_| ApiError::Api)
+    }
+
+    fn margin(&self) -> Option<Margin> {
+        self.api.margin()
+    }
+
+    fn execution_gap(&self) -> Option<ExecutionGap> {
+        let gaps = self.gaps.lock().unwrap();
+        let last = *gaps.back()?;
+        Some(ExecutionGap {
+            fills: gaps.len(),
+            mean: (gaps.iter().sum::<Decimal>() / Decimal::from(gaps.len())).round_dp(8),
+            last,
+            worst: gaps.iter().cloned().max().unwrap_or(last),
+        })
+    }
+
+    async fn commands(&self) -> Vec<Command> {
+        let mut commands = self.api.commands().await;
+        let mut receiver = self.commands.lock().unwrap();
+        while let Ok(command) = receiver.try_recv() {
+            commands.push(command);
+        }
+        commands
+    }
+
+    async fn shutdown(&self) {
+        self.api.shutdown().await;
+
+        // Wait until all logs sent before are written.
+        let (tx, rx) = oneshot::channel();
+        if self.tx.send(Flush(Mutex::new(Some(tx))).boxed()).is_ok() {
+            rx.await.ok();
+        }
+    }
+
     fn hello(&self, strategy_name: &'static str) {
         self.tx
             .send(
@@ -110,6 +520,7 @@ impl<A: Api> Api for Monitor<A> {
                     name: strategy_name.to_owned(),
                     exchange: A::NAME.to_owned(),
                     live_trading: A::LIVE_TRADING_ENABLED,
+                    kind: self.kind,
                     id: self.session_id,
                 }
                 .boxed(),
@@ -117,13 +528,206 @@ impl<A: Api> Api for Monitor<A> {
             .ok();
     }
 
-    fn status(&self, time: DateTime<Utc>, total: Decimal) {
+    fn parameters(&self, parameters: &Value) {
+        self.tx
+            .send(
+                SessionParameters {
+                    parameters: parameters.to_string(),
+                }
+                .boxed(),
+            )
+            .ok();
+
+        // Backtests also get one row per parameter, to compare them over time.
+        if self.kind == SessionKind::Backtest {
+            for (name, value) in backtest_parameters(parameters) {
+                self.tx.send(BacktestParameter { name, value }.boxed()).ok();
+            }
+        }
+    }
+
+    fn status(&self, time: DateTime<Utc>, total: Decimal, exposure: &Exposure) {
+        let mut equity = self.equity.lock().unwrap();
+        // The peak is tracked at every step, so drawdowns between logs are not missed.
+        equity.peak = equity.peak.max(total);
+        if self.equity_cadence.due(equity.last, time, total) {
+            equity.last = Some((time, total));
+            let drawdown = if equity.peak.is_zero() {
+                Decimal::ZERO
+            } else {
+                (equity.peak - total) / equity.peak
+            };
+            let asset = self.api.quote_asset().to_string();
+            self.tx
+                .send(
+                    Equity {
+                        total,
+                        asset,
+                        drawdown,
+                        exposure: exposure.gross_total(),
+                        net_exposure: exposure.net_total(),
+                        time,
+                    }
+                    .boxed(),
+                )
+                .ok();
+        }
+    }
+
+    fn health(&self, time: DateTime<Utc>, health: &MarketHealth) {
+        self.tx
+            .send(
+                MarketStatus {
+                    market: self.api.format_market(health.market),
+                    stale: health.stale,
+                    last_candle: health.last_candle,
+                    time,
+                }
+                .boxed(),
+            )
+            .ok();
+    }
+
+    fn reconciled(&self, discrepancy: &WalletDiscrepancy) {
+        self.tx
+            .send(
+                WalletAudit {
+                    asset: discrepancy.asset.to_string(),
+                    local: discrepancy.local,
+                    venue: discrepancy.venue,
+                    corrected: discrepancy.corrected,
+                    time: discrepancy.time,
+                }
+                .boxed(),
+            )
+            .ok();
+    }
+
+    fn metric(&self, time: DateTime<Utc>, name: &str, value: Decimal) {
+        self.tx
+            .send(
+                MetricValue {
+                    name: name.to_owned(),
+                    value,
+                    time,
+                }
+                .boxed(),
+            )
+            .ok();
+    }
+
+    fn positions(&self, time: DateTime<Utc>, positions: &[Position]) {
         if time.minute() == 0 {
-            self.tx.send(Equity { total, time }.boxed()).ok();
+            for position in positions {
+                self.tx
+                    .send(PositionSnapshot::new(position, time).boxed())
+                    .ok();
+            }
+        }
+    }
+
+    fn closed(&self, time: DateTime<Utc>, position: &Position) {
+        self.tx
+            .send(PositionSnapshot::new(position, time).boxed())
+            .ok();
+    }
+
+    fn session_id(&self) -> Option<Uuid> {
+        Some(self.session_id)
+    }
+
+    fn finished(&self, report: &Report) {
+        if self.kind == SessionKind::Backtest {
+            self.tx
+                .send(
+                    BacktestResult {
+                        version: self.version.clone(),
+                        library_version: env!("CARGO_PKG_VERSION").to_owned(),
+                        report: report.clone(),
+                    }
+                    .boxed(),
+                )
+                .ok();
         }
     }
 }
 
+// Create or update the tables of the monitor to the latest migration.
+async fn migrate(pool: &PgPool) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("SELECT pg_advisory_xact_lock($1)")
+        .bind(MIGRATION_LOCK)
+        .execute(&mut tx)
+        .await?;
+    sqlx::query(
+        "
+            CREATE TABLE IF NOT EXISTS monitor_migrations (
+                version BIGINT PRIMARY KEY,
+                description TEXT NOT NULL,
+                applied_at TIMESTAMPTZ NOT NULL DEFAULT now()
+            )
+        ",
+    )
+    .execute(&mut tx)
+    .await?;
+    let applied: Vec<(i64,)> = sqlx::query_as("SELECT version FROM monitor_migrations")
+        .fetch_all(&mut tx)
+        .await?;
+
+    for &(version, description, sql) in MIGRATIONS {
+        if applied.contains(&(version,)) {
+            continue;
+        }
+        tracing::info!(version, "Migrating the monitor database: {}.", description);
+        (&mut tx).execute(sql).await?;
+        sqlx::query("INSERT INTO monitor_migrations (version, description) VALUES ($1, $2)")
+            .bind(version)
+            .bind(description)
+            .execute(&mut tx)
+            .await?;
+    }
+
+    tx.commit().await
+}
+
+// Consume the commands for this session from the commands table.
+async fn poll_commands(pool: PgPool, session_id: Uuid, tx: UnboundedSender<Command>) {
+    loop {
+        let rows: Result<Vec<(String, Option<Decimal>)>, _> = sqlx::query_as(
+            "
+                UPDATE commands
+                SET consumed = TRUE
+                WHERE session_id = $1 AND NOT consumed
+                RETURNING command, value
+            ",
+        )
+        .bind(session_id)
+        .fetch_all(&pool)
+        .await;
+
+        match rows {
+            Ok(rows) => {
+                for (name, value) in rows {
+                    match Command::parse(&name, value) {
+                        Some(command) => {
+                            if tx.send(command).is_err() {
+                                return;
+                            }
+                        }
+                        None => tracing::error!("Invalid command {}.", name),
+                    }
+                }
+            }
+            Err(err) => tracing::error!("A database error occurred: {}", err),
+        }
+
+        if tx.is_closed() {
+            return;
+        }
+        tokio::time::sleep(COMMAND_POLL_INTERVAL).await;
+    }
+}
+
 #[async_trait]
 pub trait Log: Send + Sync {
     async fn update(&self, pool: &PgPool, session_id: Uuid) -> Result<(), sqlx::Error>;
@@ -135,12 +739,80 @@ pub trait Log: Send + Sync {
     }
 }
 
+// Signals that all previous logs have been written.
+struct Flush(Mutex<Option<oneshot::Sender<()>>>);
+
+#[async_trait]
+impl Log for Flush {
+    async fn update(&self, _pool: &PgPool, _session_id: Uuid) -> Result<(), sqlx::Error> {
+        if let Some(tx) = self.0.lock().unwrap().take() {
+            tx.send(()).ok();
+        }
+        Ok(())
+    }
+}
+
+// The latest state of a strategy, one row per strategy name so a new session can resume it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StrategyState {
+    name: String,
+    // JSON of the state.
+    state: String,
+    time: DateTime<Utc>,
+}
+
+#[async_trait]
+impl Log for StrategyState {
+    async fn update(&self, pool: &PgPool, session_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "
+                INSERT INTO strategy_states (name, session_id, state, time)
+                VALUES ($1, $2, $3::jsonb, $4)
+                ON CONFLICT (name) DO UPDATE
+                SET session_id = EXCLUDED.session_id,
+                    state = EXCLUDED.state,
+                    time = EXCLUDED.time
+            ",
+        )
+        .bind(&self.name)
+        .bind(session_id)
+        .bind(&self.state)
+        .bind(self.time)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+// Reads the latest state of a strategy, after all previous logs have been written.
+struct LoadState {
+    name: String,
+    tx: Mutex<Option<oneshot::Sender<Option<String>>>>,
+}
+
+#[async_trait]
+impl Log for LoadState {
+    async fn update(&self, pool: &PgPool, _session_id: Uuid) -> Result<(), sqlx::Error> {
+        let state: Option<(String,)> =
+            sqlx::query_as("SELECT state::text FROM strategy_states WHERE name = $1")
+                .bind(&self.name)
+                .fetch_optional(pool)
+                .await?;
+        if let Some(tx) = self.tx.lock().unwrap().take() {
+            tx.send(state.map(|(state,)| state)).ok();
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Session {
     id: Uuid,
     name: String,
     exchange: String,
     live_trading: bool,
+    kind: SessionKind,
 }
 
 #[async_trait]
@@ -150,14 +822,193 @@ impl Log for Session {
 
         sqlx::query(
             "
-                INSERT INTO sessions (session_id, name, exchange, live_trading)
-                VALUES ($1, $2, $3, $4)
+                INSERT INTO sessions (session_id, name, exchange, live_trading, kind)
+                VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT (session_id) DO UPDATE
+                SET name = EXCLUDED.name,
+                    exchange = EXCLUDED.exchange,
+                    live_trading = EXCLUDED.live_trading,
+                    kind = EXCLUDED.kind
             ",
         )
         .bind(self.id)
         .bind(&self.name)
         .bind(&self.exchange)
         .bind(self.live_trading)
+        .bind(self.kind.as_str())
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+// The parameters of the strategy of the session, logged after the session.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SessionParameters {
+    // JSON of the parameters.
+    parameters: String,
+}
+
+#[async_trait]
+impl Log for SessionParameters {
+    async fn update(&self, pool: &PgPool, session_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE sessions SET parameters = $1::jsonb WHERE session_id = $2")
+            .bind(&self.parameters)
+            .bind(session_id)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+}
+
+// Reads the parameters of a session, after all previous logs have been written.
+struct LoadParameters {
+    session_id: Uuid,
+    tx: Mutex<Option<oneshot::Sender<Option<String>>>>,
+}
+
+#[async_trait]
+impl Log for LoadParameters {
+    async fn update(&self, pool: &PgPool, _session_id: Uuid) -> Result<(), sqlx::Error> {
+        let parameters: Option<(Option<String>,)> =
+            sqlx::query_as("SELECT parameters::text FROM sessions WHERE session_id = $1")
+                .bind(self.session_id)
+                .fetch_optional(pool)
+                .await?;
+        if let Some(tx) = self.tx.lock().unwrap().take() {
+            tx.send(parameters.and_then(|(parameters,)| parameters))
+                .ok();
+        }
+        Ok(())
+    }
+}
+
+// The parameters flattened into names and values, nested parameters are named by their path
+// like "fast.period", and parameters that are not in an object are named "parameters".
+fn backtest_parameters(parameters: &Value) -> Vec<(String, String)> {
+    fn flatten(name: String, value: &Value, rows: &mut Vec<(String, String)>) {
+        let path = |key: &dyn std::fmt::Display| match name.as_str() {
+            "" => key.to_string(),
+            _ => format!("{}.{}", name, key),
+        };
+        match value {
+            Value::Null => {}
+            Value::Object(fields) => {
+                for (key, value) in fields {
+                    flatten(path(key), value, rows);
+                }
+            }
+            Value::Array(values) => {
+                for (index, value) in values.iter().enumerate() {
+                    flatten(path(&index), value, rows);
+                }
+            }
+            Value::String(value) => rows.push((name, value.clone())),
+            value => rows.push((name, value.to_string())),
+        }
+    }
+
+    let mut rows = Vec::new();
+    match parameters {
+        Value::Object(_) | Value::Array(_) => flatten(String::new(), parameters, &mut rows),
+        value => flatten("parameters".to_owned(), value, &mut rows),
+    }
+    rows
+}
+
+// A parameter of a backtest session, one row per parameter.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BacktestParameter {
+    name: String,
+    value: String,
+}
+
+#[async_trait]
+impl Log for BacktestParameter {
+    async fn update(&self, pool: &PgPool, session_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "
+                INSERT INTO backtest_parameters (session_id, name, value)
+                VALUES ($1, $2, $3)
+                ON CONFLICT (session_id, name) DO UPDATE
+                SET value = EXCLUDED.value
+            ",
+        )
+        .bind(session_id)
+        .bind(&self.name)
+        .bind(&self.value)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+// The final statistics of a backtest session.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BacktestResult {
+    // The version of the strategy code.
+    version: Option<String>,
+    // The version of this library.
+    library_version: String,
+    report: Report,
+}
+
+#[async_trait]
+impl Log for BacktestResult {
+    async fn update(&self, pool: &PgPool, session_id: Uuid) -> Result<(), sqlx::Error> {
+        let report = &self.report;
+        sqlx::query(
+            "
+                INSERT INTO backtests (
+                    session_id,
+                    version,
+                    library_version,
+                    start_time,
+                    end_time,
+                    start_total,
+                    end_total,
+                    total_return,
+                    max_drawdown,
+                    steps,
+                    closed_positions,
+                    realized_pnl,
+                    unrealized_pnl,
+                    report
+                )
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14::jsonb)
+                ON CONFLICT (session_id) DO UPDATE
+                SET version = EXCLUDED.version,
+                    library_version = EXCLUDED.library_version,
+                    start_time = EXCLUDED.start_time,
+                    end_time = EXCLUDED.end_time,
+                    start_total = EXCLUDED.start_total,
+                    end_total = EXCLUDED.end_total,
+                    total_return = EXCLUDED.total_return,
+                    max_drawdown = EXCLUDED.max_drawdown,
+                    steps = EXCLUDED.steps,
+                    closed_positions = EXCLUDED.closed_positions,
+                    realized_pnl = EXCLUDED.realized_pnl,
+                    unrealized_pnl = EXCLUDED.unrealized_pnl,
+                    report = EXCLUDED.report
+            ",
+        )
+        .bind(session_id)
+        .bind(&self.version)
+        .bind(&self.library_version)
+        .bind(report.start_time)
+        .bind(report.end_time)
+        .bind(report.start_total)
+        .bind(report.end_total)
+        .bind(report.total_return())
+        .bind(report.max_drawdown)
+        .bind(report.steps as i64)
+        .bind(report.closed_positions.len() as i64)
+        .bind(report.realized_pnl)
+        .bind(report.unrealized_pnl)
+        .bind(serde_json::to_string(report).expect("reports are serializable"))
         .execute(pool)
         .await?;
 
@@ -168,6 +1019,13 @@ impl Log for Session {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Equity {
     total: Decimal,
+    // The quote asset the total is denominated in.
+    asset: String,
+    // Relative to the highest total so far.
+    drawdown: Decimal,
+    // Gross and net exposure of the open positions, see `Exposure`.
+    exposure: Decimal,
+    net_exposure: Decimal,
     time: DateTime<Utc>,
 }
 
@@ -176,12 +1034,293 @@ impl Log for Equity {
     async fn update(&self, pool: &PgPool, session_id: Uuid) -> Result<(), sqlx::Error> {
         sqlx::query(
             "
-                INSERT INTO equities (session_id, total, time)
-                VALUES ($1, $2, $3)
+                INSERT INTO equities (
+                    session_id,
+                    total,
+                    asset,
+                    drawdown,
+                    exposure,
+                    net_exposure,
+                    time
+                )
+                VALUES ($1, $2, $3, $4, $5, $6, $7)
+                ON CONFLICT (session_id, time) DO UPDATE
+                SET total = EXCLUDED.total,
+                    asset = EXCLUDED.asset,
+                    drawdown = EXCLUDED.drawdown,
+                    exposure = EXCLUDED.exposure,
+                    net_exposure = EXCLUDED.net_exposure
             ",
         )
         .bind(session_id)
         .bind(self.total)
+        .bind(&self.asset)
+        .bind(self.drawdown)
+        .bind(self.exposure)
+        .bind(self.net_exposure)
+        .bind(self.time)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+// A value of a custom series of the strategy.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct MetricValue {
+    name: String,
+    value: Decimal,
+    time: DateTime<Utc>,
+}
+
+#[async_trait]
+impl Log for MetricValue {
+    async fn update(&self, pool: &PgPool, session_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "
+                INSERT INTO metrics (
+                    session_id,
+                    name,
+                    value,
+                    time
+                )
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (session_id, name, time) DO UPDATE
+                SET value = EXCLUDED.value
+            ",
+        )
+        .bind(session_id)
+        .bind(&self.name)
+        .bind(self.value)
+        .bind(self.time)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+// Whether the data of a market is stale, one row per market.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct MarketStatus {
+    market: String,
+    stale: bool,
+    last_candle: Option<DateTime<Utc>>,
+    time: DateTime<Utc>,
+}
+
+#[async_trait]
+impl Log for MarketStatus {
+    async fn update(&self, pool: &PgPool, session_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "
+                INSERT INTO market_health (
+                    session_id,
+                    market,
+                    stale,
+                    last_candle,
+                    time
+                )
+                VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT (session_id, market) DO UPDATE
+                SET stale = EXCLUDED.stale,
+                    last_candle = EXCLUDED.last_candle,
+                    time = EXCLUDED.time
+            ",
+        )
+        .bind(session_id)
+        .bind(&self.market)
+        .bind(self.stale)
+        .bind(self.last_candle)
+        .bind(self.time)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+// A balance the wallet of the venue held differently than booked.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct WalletAudit {
+    asset: String,
+    local: Decimal,
+    venue: Decimal,
+    corrected: bool,
+    time: DateTime<Utc>,
+}
+
+#[async_trait]
+impl Log for WalletAudit {
+    async fn update(&self, pool: &PgPool, session_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "
+                INSERT INTO wallet_audit (
+                    session_id,
+                    asset,
+                    local,
+                    venue,
+                    corrected,
+                    time
+                )
+                VALUES ($1, $2, $3, $4, $5, $6)
+                ON CONFLICT (session_id, asset, time) DO NOTHING
+            ",
+        )
+        .bind(session_id)
+        .bind(&self.asset)
+        .bind(self.local)
+        .bind(self.venue)
+        .bind(self.corrected)
+        .bind(self.time)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+// The latest state of a position, one row per position.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PositionSnapshot {
+    id: Uuid,
+    symbols: String,
+    value: Decimal,
+    pnl: Decimal,
+    realized_pnl: Decimal,
+    unrealized_pnl: Decimal,
+    mae: Decimal,
+    mfe: Decimal,
+    // JSON object of the tags.
+    tags: String,
+    // JSON object of the leg each symbol is held in, "Long" or "Short". Positions in opposite
+    // legs of a symbol offset each other in netting account mode, but not in hedged mode.
+    legs: String,
+    closed: bool,
+    time: DateTime<Utc>,
+}
+
+impl PositionSnapshot {
+    fn new(position: &Position, time: DateTime<Utc>) -> Self {
+        PositionSnapshot {
+            id: position.id(),
+            symbols: position
+                .symbols()
+                .map(|symbol| symbol.to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+            value: position.value(),
+            pnl: position.pnl(),
+            realized_pnl: position.realized_pnl(),
+            unrealized_pnl: position.unrealized_pnl(),
+            mae: position.mae(),
+            mfe: position.mfe(),
+            tags: serde_json::to_string(position.tags()).expect("tags are serializable"),
+            legs: serde_json::to_string(
+                &position
+                    .legs()
+                    .into_iter()
+                    .map(|(symbol, leg)| (symbol.to_string(), leg))
+                    .collect::<HashMap<_, _>>(),
+            )
+            .expect("legs are serializable"),
+            closed: position.close.is_some(),
+            time,
+        }
+    }
+}
+
+#[async_trait]
+impl Log for PositionSnapshot {
+    async fn update(&self, pool: &PgPool, session_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "
+                INSERT INTO positions (
+                    position_id,
+                    session_id,
+                    symbols,
+                    value,
+                    pnl,
+                    realized_pnl,
+                    unrealized_pnl,
+                    mae,
+                    mfe,
+                    tags,
+                    legs,
+                    closed,
+                    time
+                )
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10::jsonb, $11::jsonb, $12, $13)
+                ON CONFLICT (position_id) DO UPDATE
+                SET value = EXCLUDED.value,
+                    pnl = EXCLUDED.pnl,
+                    realized_pnl = EXCLUDED.realized_pnl,
+                    unrealized_pnl = EXCLUDED.unrealized_pnl,
+                    mae = EXCLUDED.mae,
+                    mfe = EXCLUDED.mfe,
+                    tags = EXCLUDED.tags,
+                    legs = EXCLUDED.legs,
+                    closed = EXCLUDED.closed,
+                    time = EXCLUDED.time
+            ",
+        )
+        .bind(self.id)
+        .bind(session_id)
+        .bind(&self.symbols)
+        .bind(self.value)
+        .bind(self.pnl)
+        .bind(self.realized_pnl)
+        .bind(self.unrealized_pnl)
+        .bind(self.mae)
+        .bind(self.mfe)
+        .bind(&self.tags)
+        .bind(&self.legs)
+        .bind(self.closed)
+        .bind(self.time)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+// A fill together with the price the simulation predicted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Fill {
+    order_id: Uuid,
+    expected_price: Decimal,
+    executed_price: Decimal,
+    gap: Decimal,
+    time: DateTime<Utc>,
+}
+
+#[async_trait]
+impl Log for Fill {
+    async fn update(&self, pool: &PgPool, session_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "
+                INSERT INTO execution_gaps (
+                    order_id,
+                    session_id,
+                    expected_price,
+                    executed_price,
+                    gap,
+                    time
+                )
+                VALUES ($1, $2, $3, $4, $5, $6)
+                ON CONFLICT (session_id, order_id) DO UPDATE
+                SET expected_price = EXCLUDED.expected_price,
+                    executed_price = EXCLUDED.executed_price,
+                    gap = EXCLUDED.gap,
+                    time = EXCLUDED.time
+            ",
+        )
+        .bind(self.order_id)
+        .bind(session_id)
+        .bind(self.expected_price)
+        .bind(self.executed_price)
+        .bind(self.gap)
         .bind(self.time)
         .execute(pool)
         .await?;
@@ -219,6 +1358,12 @@ impl Log for Order {
                     NULL,
                     NULL
                 )
+                ON CONFLICT (session_id, order_id) DO UPDATE
+                SET market = EXCLUDED.market,
+                    side = EXCLUDED.side,
+                    ordered_size = EXCLUDED.ordered_size,
+                    ordered_price = EXCLUDED.ordered_price,
+                    ordered_time = EXCLUDED.ordered_time
             ",
         )
         .bind(self.order_id)
@@ -237,26 +1382,30 @@ impl Log for Order {
 
 #[async_trait]
 impl Log for OrderInfo {
-    async fn update(&self, pool: &PgPool, _session_id: Uuid) -> Result<(), sqlx::Error> {
+    async fn update(&self, pool: &PgPool, session_id: Uuid) -> Result<(), sqlx::Error> {
         sqlx::query(
             "
                 UPDATE orders 
                 SET (
                     executed_size,
                     executed_price,
-                    executed_time
+                    executed_time,
+                    status
                 ) = (
                     $2,
                     $3,
-                    $4
+                    $4,
+                    $6
                 ) 
-                WHERE order_id = $1
+                WHERE order_id = $1 AND session_id = $5
             ",
         )
         .bind(self.order_id)
         .bind(self.size)
         .bind(self.price)
         .bind(self.time)
+        .bind(session_id)
+        .bind(format!("{:?}", self.status))
         .execute(pool)
         .await?;
 
@@ -265,4 +1414,119 @@ impl Log for OrderInfo {
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use super::*;
+    use crate::{
+        apis::{Mock, MockSettings},
+        OrderType, TimeInForce,
+    };
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn migrations_are_ordered() {
+        for (i, (version, _, sql)) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(*version, i as i64 + 1);
+            assert!(!sql.trim().is_empty());
+        }
+    }
+
+    #[test]
+    fn equity_cadence() {
+        let time = Utc::now();
+        let last = Some((time, dec!(100)));
+        let later = time + Duration::minutes(5);
+
+        assert!(EquityCadence::default().due(None, time, dec!(100)));
+        assert!(EquityCadence::EveryStep.due(last, later, dec!(100)));
+        assert!(!EquityCadence::Every(Duration::minutes(10)).due(last, later, dec!(100)));
+        assert!(EquityCadence::Every(Duration::minutes(5)).due(last, later, dec!(100)));
+        assert!(!EquityCadence::OnChange(dec!(0.01)).due(last, later, dec!(100.5)));
+        assert!(EquityCadence::OnChange(dec!(0.01)).due(last, later, dec!(98.5)));
+    }
+
+    #[test]
+    fn flatten_backtest_parameters() {
+        let parameters = serde_json::json!({
+            "fast": 10,
+            "exit": { "kind": "trailing", "stop": "0.05" },
+            "markets": ["BTC-PERP"],
+            "filter": null,
+        });
+        assert_eq!(
+            backtest_parameters(&parameters),
+            vec![
+                ("exit.kind".to_owned(), "trailing".to_owned()),
+                ("exit.stop".to_owned(), "0.05".to_owned()),
+                ("fast".to_owned(), "10".to_owned()),
+                ("markets.0".to_owned(), "BTC-PERP".to_owned()),
+            ]
+        );
+        assert_eq!(
+            backtest_parameters(&serde_json::json!(20)),
+            vec![("parameters".to_owned(), "20".to_owned())]
+        );
+    }
+
+    #[tokio::test]
+    async fn session_kind() {
+        let candle = |_key: CandleKey| Candle {
+            open: dec!(100),
+            high: dec!(100),
+            low: dec!(100),
+            close: dec!(100),
+            volume: dec!(1),
+        };
+        let api = Monitor::new(Mock::new(MockSettings::new(candle)));
+        assert_eq!(api.kind, SessionKind::Paper);
+
+        let api = api.with_kind(SessionKind::Backtest).with_version("abc123");
+        assert_eq!(api.kind, SessionKind::Backtest);
+        assert_eq!(
+            serde_json::to_string(&SessionKind::Backtest).unwrap(),
+            format!("\"{}\"", SessionKind::Backtest.as_str())
+        );
+    }
+
+    #[tokio::test]
+    async fn execution_gap() {
+        let mut settings = MockSettings::new(|_key: CandleKey| Candle {
+            open: dec!(100),
+            high: dec!(100),
+            low: dec!(100),
+            close: dec!(100),
+            volume: dec!(1),
+        });
+        settings.fee = dec!(0.001);
+        let api = Monitor::new(Mock::new(settings));
+        assert_eq!(api.execution_gap(), None);
+
+        for side in [Side::Buy, Side::Sell] {
+            api.place_order(Order {
+                order_id: Uuid::new_v4(),
+                market: Symbol::perp("BTC"),
+                side,
+                size: dec!(1),
+                order_type: OrderType::Market,
+                reduce_only: false,
+                time_in_force: TimeInForce::ImmediateOrCancel,
+                post_only: false,
+                position_side: None,
+                time: Utc::now(),
+                current_price: dec!(100),
+            })
+            .await
+            .unwrap();
+        }
+
+        // The mock fills exactly as simulated.
+        assert_eq!(
+            api.execution_gap(),
+            Some(ExecutionGap {
+                fills: 2,
+                mean: dec!(0),
+                last: dec!(0),
+                worst: dec!(0),
+            })
+        );
+    }
+}
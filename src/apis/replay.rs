@@ -0,0 +1,241 @@
+use super::Api;
+use crate::{
+    apis::{ApiError, Order, OrderInfo},
+    Asset, Candle, CandleKey, MarketInfo, Markets, OrderStatus, Symbol, Wallet,
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use rust_decimal::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs, io,
+    path::Path,
+};
+
+// Maximum number of candles returned per request, as by most exchanges.
+const PAGE_SIZE: usize = 5000;
+
+/// Recorded API responses, serialized as JSON so they can be committed as test data.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Fixture {
+    pub name: String,
+    pub quote_asset: Asset,
+    pub fee: Decimal,
+    pub markets: Vec<MarketInfo>,
+    pub wallet: HashMap<Asset, Decimal>,
+    candles: Vec<RecordedCandle>,
+}
+
+/// A recorded candle with its key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedCandle {
+    market: Symbol,
+    time: DateTime<Utc>,
+    // Interval in seconds.
+    interval: i64,
+    candle: Option<Candle>,
+}
+
+impl From<(CandleKey, Option<Candle>)> for RecordedCandle {
+    fn from((key, candle): (CandleKey, Option<Candle>)) -> Self {
+        RecordedCandle {
+            market: key.market,
+            time: key.time,
+            interval: key.interval.num_seconds(),
+            candle,
+        }
+    }
+}
+
+impl From<RecordedCandle> for (CandleKey, Option<Candle>) {
+    fn from(recorded: RecordedCandle) -> Self {
+        (
+            CandleKey {
+                market: recorded.market,
+                time: recorded.time,
+                interval: Duration::seconds(recorded.interval),
+            },
+            recorded.candle,
+        )
+    }
+}
+
+impl Fixture {
+    /// Record the markets, wallet and fee of an API.
+    pub async fn record<A: Api>(api: &A) -> Result<Self, ApiError> {
+        let mut markets = Markets::default();
+        api.update_markets(&mut markets).await?;
+        let mut wallet = Wallet::new();
+        api.update_wallet(&mut wallet).await?;
+
+        Ok(Fixture {
+            name: A::NAME.to_owned(),
+            quote_asset: api.quote_asset(),
+            fee: api.order_fee().await,
+            markets: markets.markets().map(|(_, info)| *info).collect(),
+            wallet: wallet.total,
+            candles: Vec::new(),
+        })
+    }
+
+    /// Record the candles returned by the API for the key.
+    pub async fn record_candles<A: Api>(
+        &mut self,
+        api: &A,
+        key: CandleKey,
+    ) -> Result<(), ApiError> {
+        let candles = api.get_candles(key).await?;
+        self.candles
+            .extend(candles.into_iter().map(RecordedCandle::from));
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+}
+
+/// The Replay API answers requests with recorded responses instead of accessing the network,
+/// which makes tests hermetic. Orders are filled at their current price plus the recorded fee.
+/// Requesting candles that were not recorded returns `ApiError::Api`.
+pub struct ReplayApi {
+    fixture: Fixture,
+    #[allow(clippy::type_complexity)]
+    candles: HashMap<(Symbol, Duration), BTreeMap<DateTime<Utc>, Option<Candle>>>,
+}
+
+impl ReplayApi {
+    pub fn new(fixture: Fixture) -> Self {
+        let mut candles: HashMap<_, BTreeMap<_, _>> = HashMap::new();
+        for recorded in &fixture.candles {
+            candles
+                .entry((recorded.market, Duration::seconds(recorded.interval)))
+                .or_default()
+                .insert(recorded.time, recorded.candle);
+        }
+
+        ReplayApi { fixture, candles }
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(ReplayApi::new(Fixture::load(path)?))
+    }
+}
+
+#[async_trait]
+impl Api for ReplayApi {
+    const NAME: &'static str = "Replay";
+    const LIVE_TRADING_ENABLED: bool = false;
+
+    async fn get_candles(
+        &self,
+        key: CandleKey,
+    ) -> Result<Vec<(CandleKey, Option<Candle>)>, ApiError> {
+        let candles = self
+            .candles
+            .get(&(key.market, key.interval))
+            .filter(|candles| candles.contains_key(&key.time))
+            .ok_or_else(|| {
+                tracing::error!("No recorded candles for {:?}.", key);
+                ApiError::Api
+            })?;
+
+        Ok(candles
+            .range(key.time..)
+            .take(PAGE_SIZE)
+            .map(|(&time, &candle)| (CandleKey { time, ..key }, candle))
+            .collect())
+    }
+
+    async fn place_order(&self, order: Order) -> Result<OrderInfo, ApiError> {
+        let fee = self.fixture.fee;
+        Ok(OrderInfo {
+            order_id: order.order_id,
+            market: order.market,
+            size: order.size,
+            price: if order.side == crate::Side::Buy {
+                order.current_price * (Decimal::ONE + fee)
+            } else {
+                order.current_price * (Decimal::ONE - fee)
+            }
+            .round_dp(8),
+            time: order.time,
+            side: order.side,
+            status: OrderStatus::Filled,
+        })
+    }
+
+    fn format_market(&self, market: Symbol) -> String {
+        market.to_string()
+    }
+
+    async fn update_wallet(&self, wallet: &mut Wallet) -> Result<(), ApiError> {
+        if wallet.is_fresh() {
+            wallet.total = self.fixture.wallet.clone();
+            wallet.free = self.fixture.wallet.clone();
+        }
+
+        Ok(())
+    }
+
+    async fn update_markets(&self, markets: &mut Markets) -> Result<(), ApiError> {
+        markets.markets = self
+            .fixture
+            .markets
+            .iter()
+            .map(|info| (info.symbol, *info))
+            .collect();
+
+        Ok(())
+    }
+
+    fn quote_asset(&self) -> Asset {
+        self.fixture.quote_asset
+    }
+
+    async fn order_fee(&self) -> Decimal {
+        self.fixture.fee
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[tokio::test]
+    async fn unrecorded_candles() {
+        let api = ReplayApi::from_file(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/fixtures/btc_perp_1m.json"
+        ))
+        .unwrap();
+        let key = CandleKey {
+            market: Symbol::perp("BTC"),
+            time: Utc.with_ymd_and_hms(2021, 8, 1, 4, 0, 0).unwrap(),
+            interval: Duration::minutes(1),
+        };
+
+        assert_eq!(api.get_candles(key).await.unwrap().len(), 60);
+        assert!(api
+            .get_candles(CandleKey {
+                market: Symbol::perp("ETH"),
+                ..key
+            })
+            .await
+            .is_err());
+        assert!(api
+            .get_candles(CandleKey {
+                interval: Duration::minutes(5),
+                ..key
+            })
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,317 @@
+use crate::{apis::ApiError, Candle, CandleKey};
+use chrono::Utc;
+use reqwest::{header::CONTENT_TYPE, Client, Method, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+/// A JSON REST client shared by the venues, it maps transport and status
+/// errors to `ApiError`s while signing is left to the venue.
+#[derive(Clone)]
+pub struct RestClient {
+    transport: Transport,
+    base_url: String,
+    // The requests sent and their responses, if recorded.
+    log: Option<Arc<Mutex<Vec<Interaction>>>>,
+}
+
+#[derive(Clone)]
+enum Transport {
+    Http(Client),
+    Replay(Arc<Cassette>),
+}
+
+impl RestClient {
+    pub fn new(base_url: impl Into<String>) -> Self {
+        RestClient {
+            transport: Transport::Http(Client::new()),
+            base_url: base_url.into(),
+            log: None,
+        }
+    }
+
+    /// A client that answers requests with the recorded responses instead of sending them,
+    /// to test the mappings of a venue without network access or credentials.
+    /// The requests are recorded, so they can be asserted with [RestClient::interactions].
+    pub fn replay(cassette: Cassette) -> Self {
+        RestClient {
+            transport: Transport::Replay(Arc::new(cassette)),
+            base_url: String::new(),
+            log: Some(Default::default()),
+        }
+    }
+
+    /// Record the requests sent by this client and their responses, e.g. to save them as a
+    /// [Cassette] for tests.
+    pub fn record(mut self) -> Self {
+        self.log = Some(Default::default());
+        self
+    }
+
+    /// The recorded requests and responses, empty if nothing is recorded.
+    pub fn interactions(&self) -> Vec<Interaction> {
+        self.log
+            .as_ref()
+            .map(|log| log.lock().unwrap().clone())
+            .unwrap_or_default()
+    }
+
+    /// Send a request with the query parameters and headers,
+    /// and deserialize the JSON response.
+    pub async fn request<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        query: &[(&str, String)],
+        headers: &[(&str, String)],
+    ) -> Result<T, ApiError> {
+        self.send(method, path, query, None, headers).await
+    }
+
+    /// Send a request with the serialized JSON body and headers,
+    /// and deserialize the JSON response.
+    pub async fn request_body<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: String,
+        headers: &[(&str, String)],
+    ) -> Result<T, ApiError> {
+        self.send(method, path, &[], Some(body), headers).await
+    }
+
+    async fn send<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        query: &[(&str, String)],
+        request_body: Option<String>,
+        headers: &[(&str, String)],
+    ) -> Result<T, ApiError> {
+        let (status, body) = match &self.transport {
+            Transport::Http(client) => {
+                let mut request = client
+                    .request(method.clone(), format!("{}{}", self.base_url, path))
+                    .query(query);
+                for (name, value) in headers {
+                    request = request.header(*name, value);
+                }
+                if let Some(body) = &request_body {
+                    request = request
+                        .header(CONTENT_TYPE, "application/json")
+                        .body(body.clone());
+                }
+
+                let response = request.send().await.map_err(|err| {
+                    tracing::warn!(path, error = %err, "Request failed.");
+                    ApiError::Network
+                })?;
+                let status = response.status();
+                let body = response.text().await.map_err(|err| {
+                    tracing::warn!(path, error = %err, "Could not read the response.");
+                    ApiError::Network
+                })?;
+                (status, body)
+            }
+            Transport::Replay(cassette) => cassette.response(&method, path).ok_or_else(|| {
+                tracing::warn!(path, %method, "No recorded response for the request.");
+                ApiError::Api
+            })?,
+        };
+
+        if let Some(log) = &self.log {
+            log.lock().unwrap().push(Interaction {
+                method: method.to_string(),
+                path: path.to_owned(),
+                query: query
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.clone()))
+                    .collect(),
+                body: request_body.and_then(|body| serde_json::from_str(&body).ok()),
+                status: status.as_u16(),
+                response: serde_json::from_str(&body).unwrap_or(Value::String(body.clone())),
+            });
+        }
+
+        if !status.is_success() {
+            tracing::warn!(path, %status, body, "Request returned an error.");
+            return Err(match status {
+                // Rate limits and server errors are worth retrying.
+                StatusCode::TOO_MANY_REQUESTS => ApiError::Network,
+                status if status.is_server_error() => ApiError::Network,
+                _ => ApiError::Api,
+            });
+        }
+
+        serde_json::from_str(&body).map_err(|err| {
+            tracing::warn!(path, error = %err, "Could not parse the response.");
+            ApiError::Api
+        })
+    }
+}
+
+/// A request sent by a `RestClient` and the response it got.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Interaction {
+    pub method: String,
+    pub path: String,
+    #[serde(default)]
+    pub query: Vec<(String, String)>,
+    /// The JSON body of the request, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<Value>,
+    pub status: u16,
+    /// The JSON body of the response, or a string if it was no JSON.
+    pub response: Value,
+}
+
+impl Interaction {
+    /// The value of a query parameter of the request.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Recorded interactions with a venue, serialized as JSON so they can be committed as test data.
+/// Requests are answered with the responses recorded for the same method and path in order,
+/// repeating the last one once all were replayed.
+#[derive(Debug, Default)]
+pub struct Cassette {
+    interactions: Vec<Interaction>,
+    // How many responses were replayed per method and path.
+    replayed: Mutex<HashMap<(String, String), usize>>,
+}
+
+impl Cassette {
+    pub fn new(interactions: Vec<Interaction>) -> Self {
+        Cassette {
+            interactions,
+            replayed: Default::default(),
+        }
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Cassette::new(serde_json::from_str(&fs::read_to_string(
+            path,
+        )?)?))
+    }
+
+    /// Save the interactions recorded by a client, which are replayed when loaded again.
+    pub fn save(interactions: &[Interaction], path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, serde_json::to_string_pretty(interactions)?)
+    }
+
+    fn response(&self, method: &Method, path: &str) -> Option<(StatusCode, String)> {
+        let recorded: Vec<&Interaction> = self
+            .interactions
+            .iter()
+            .filter(|interaction| interaction.method == method.as_str() && interaction.path == path)
+            .collect();
+        let mut replayed = self.replayed.lock().unwrap();
+        let index = replayed
+            .entry((method.to_string(), path.to_owned()))
+            .or_default();
+        let interaction = recorded.get(*index).or(recorded.last())?;
+        *index += 1;
+
+        let body = match &interaction.response {
+            Value::String(body) => body.clone(),
+            response => response.to_string(),
+        };
+        Some((StatusCode::from_u16(interaction.status).ok()?, body))
+    }
+}
+
+/// Hex encoded HMAC-SHA256 signature of a message, used by most venues to sign requests.
+pub fn sign(secret: &str, message: &str) -> String {
+    hex::encode(hmac_sha256::HMAC::mac(
+        message.as_bytes(),
+        secret.as_bytes(),
+    ))
+}
+
+/// Turn the candles returned by a venue into the response expected by `Api::get_candles`,
+/// starting at the key and with `None` for every missing candle up to `limit` candles.
+pub fn fill_gaps(
+    key: CandleKey,
+    candles: impl IntoIterator<Item = (CandleKey, Candle)>,
+    limit: i32,
+) -> Vec<(CandleKey, Option<Candle>)> {
+    let mut out = Vec::new();
+    let mut next_key = key;
+
+    'result_loop: for (curr_key, candle) in candles {
+        if curr_key.time < next_key.time {
+            continue;
+        }
+        while next_key != curr_key {
+            tracing::trace!("Got NO candle for time {}", next_key.time);
+            out.push((next_key, None));
+            next_key.time += next_key.interval;
+            if next_key.time >= key.time + key.interval * limit {
+                break 'result_loop;
+            }
+        }
+        tracing::trace!("Got candle for time {}", next_key.time);
+        out.push((curr_key, Some(candle)));
+        next_key.time += next_key.interval;
+    }
+    for _ in out.len()..limit as usize {
+        // Do not fill candles in the future with none.
+        if next_key.time >= Utc::now() - next_key.interval * 2 {
+            break;
+        }
+        out.push((next_key, None));
+        next_key.time += next_key.interval;
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Symbol;
+    use chrono::{Duration, TimeZone};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn fill_missing_candles() {
+        let key = CandleKey {
+            market: Symbol::perp("BTC"),
+            time: Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap(),
+            interval: Duration::minutes(1),
+        };
+        let candle = Candle {
+            open: dec!(1),
+            high: dec!(1),
+            low: dec!(1),
+            close: dec!(1),
+            volume: dec!(1),
+        };
+        let at = |minutes| CandleKey {
+            time: key.time + Duration::minutes(minutes),
+            ..key
+        };
+
+        let candles = fill_gaps(key, vec![(at(0), candle), (at(2), candle)], 4);
+
+        assert_eq!(
+            candles,
+            vec![
+                (at(0), Some(candle)),
+                (at(1), None),
+                (at(2), Some(candle)),
+                (at(3), None)
+            ]
+        );
+    }
+}
@@ -0,0 +1,527 @@
+use super::Api;
+use crate::{
+    apis::{ApiError, ExecutionGap, FeeSchedule, Margin, Order, OrderInfo},
+    Asset, Candle, CandleKey, CollateralWeights, Command, Exposure, MarketHealth, MarketInfo,
+    Markets, Orderbook, Position, PriceSource, Report, Symbol, Trade, Wallet, WalletDiscrepancy,
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures_util::join;
+use rust_decimal::Decimal;
+use std::ops::Range;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+/// The Router API trades on two venues at once, e.g. to trade a market on the venue
+/// with the better price. Markets are namespaced by venue, e.g. "binance:BTC-PERP",
+/// see `Symbol::on`, and requests for a market are routed to its venue.
+/// The wallet sums the balances of both venues, which should share the quote asset.
+/// Nest routers to trade on more than two venues.
+pub struct Router<A, B>
+where
+    A: Api,
+    B: Api,
+{
+    a: A,
+    b: B,
+    venues: (&'static str, &'static str),
+    // The wallets and markets of the venues, updated by the venues themselves.
+    wallets: Mutex<(Wallet, Wallet)>,
+    markets: Mutex<(Markets, Markets)>,
+}
+
+enum Venue<'a, A, B> {
+    A(&'a A),
+    B(&'a B),
+}
+
+impl<A, B> Router<A, B>
+where
+    A: Api,
+    B: Api,
+{
+    /// Route to the venues by their names in lower case, e.g. "binance".
+    pub fn new(a: A, b: B) -> Self {
+        let venues = (A::NAME.to_lowercase(), B::NAME.to_lowercase());
+        Self::with_venues(a, &venues.0, b, &venues.1)
+    }
+
+    /// Route to the venues by other names, e.g. to trade on two accounts of the same venue.
+    pub fn with_venues(a: A, venue_a: &str, b: B, venue_b: &str) -> Self {
+        assert_ne!(venue_a, venue_b, "The venues need different names.");
+        // Validate the names.
+        Symbol::perp("BTC").on(venue_a);
+        Symbol::perp("BTC").on(venue_b);
+        Router {
+            a,
+            b,
+            venues: (Asset::new(venue_a).name(), Asset::new(venue_b).name()),
+            wallets: Mutex::new((Wallet::new(), Wallet::new())),
+            markets: Mutex::new((Markets::default(), Markets::default())),
+        }
+    }
+
+    /// The names of the venues.
+    pub fn venues(&self) -> (&'static str, &'static str) {
+        self.venues
+    }
+
+    // The venue of a market, which must be namespaced.
+    fn route(&self, market: Symbol) -> Result<Venue<'_, A, B>, ApiError> {
+        match market.venue() {
+            Some(venue) if venue == self.venues.0 => Ok(Venue::A(&self.a)),
+            Some(venue) if venue == self.venues.1 => Ok(Venue::B(&self.b)),
+            _ => Err(ApiError::UnknownVenue(market)),
+        }
+    }
+
+    fn namespace(&self, venue: &Venue<'_, A, B>, market: Symbol) -> Symbol {
+        match venue {
+            Venue::A(_) => market.on(self.venues.0),
+            Venue::B(_) => market.on(self.venues.1),
+        }
+    }
+}
+
+// Sum the balances of the wallets.
+fn merge(a: &Wallet, b: &Wallet) -> Wallet {
+    let mut wallet = a.clone();
+    for (asset, qty) in &b.total {
+        *wallet.total.entry(*asset).or_default() += qty;
+    }
+    for (asset, qty) in &b.free {
+        *wallet.free.entry(*asset).or_default() += qty;
+    }
+    wallet
+}
+
+#[async_trait]
+impl<A, B> Api for Router<A, B>
+where
+    A: Api,
+    B: Api,
+{
+    const NAME: &'static str = "Router";
+    const LIVE_TRADING_ENABLED: bool = A::LIVE_TRADING_ENABLED || B::LIVE_TRADING_ENABLED;
+
+    async fn get_candles(
+        &self,
+        key: CandleKey,
+    ) -> Result<Vec<(CandleKey, Option<Candle>)>, ApiError> {
+        let venue = self.route(key.market)?;
+        let local = CandleKey {
+            market: key.market.local(),
+            ..key
+        };
+        let candles = match &venue {
+            Venue::A(a) => a.get_candles(local).await?,
+            Venue::B(b) => b.get_candles(local).await?,
+        };
+        Ok(candles
+            .into_iter()
+            .map(|(local, candle)| {
+                let key = CandleKey {
+                    market: self.namespace(&venue, local.market),
+                    ..local
+                };
+                (key, candle)
+            })
+            .collect())
+    }
+
+    async fn get_orderbook(
+        &self,
+        market: Symbol,
+        time: DateTime<Utc>,
+    ) -> Result<Option<Orderbook>, ApiError> {
+        match self.route(market)? {
+            Venue::A(a) => a.get_orderbook(market.local(), time).await,
+            Venue::B(b) => b.get_orderbook(market.local(), time).await,
+        }
+    }
+
+    async fn get_trades(
+        &self,
+        market: Symbol,
+        range: Range<DateTime<Utc>>,
+    ) -> Result<Option<Vec<Trade>>, ApiError> {
+        match self.route(market)? {
+            Venue::A(a) => a.get_trades(market.local(), range).await,
+            Venue::B(b) => b.get_trades(market.local(), range).await,
+        }
+    }
+
+    async fn get_price(
+        &self,
+        key: CandleKey,
+        source: PriceSource,
+    ) -> Result<Option<Decimal>, ApiError> {
+        let local = CandleKey {
+            market: key.market.local(),
+            ..key
+        };
+        match self.route(key.market)? {
+            Venue::A(a) => a.get_price(local, source).await,
+            Venue::B(b) => b.get_price(local, source).await,
+        }
+    }
+
+    async fn get_prices(
+        &self,
+        key: CandleKey,
+        source: PriceSource,
+    ) -> Result<Vec<(CandleKey, Option<Decimal>)>, ApiError> {
+        let venue = self.route(key.market)?;
+        let local = CandleKey {
+            market: key.market.local(),
+            ..key
+        };
+        let prices = match &venue {
+            Venue::A(a) => a.get_prices(local, source).await?,
+            Venue::B(b) => b.get_prices(local, source).await?,
+        };
+        Ok(prices
+            .into_iter()
+            .map(|(local, price)| {
+                let key = CandleKey {
+                    market: self.namespace(&venue, local.market),
+                    ..local
+                };
+                (key, price)
+            })
+            .collect())
+    }
+
+    async fn place_order(&self, order: Order) -> Result<OrderInfo, ApiError> {
+        let venue = self.route(order.market)?;
+        let local = Order {
+            market: order.market.local(),
+            ..order
+        };
+        let info = match &venue {
+            Venue::A(a) => a.place_order(local).await?,
+            Venue::B(b) => b.place_order(local).await?,
+        };
+        Ok(OrderInfo {
+            market: self.namespace(&venue, info.market),
+            ..info
+        })
+    }
+
+    async fn order_status(&self, market: Symbol, order_id: Uuid) -> Result<OrderInfo, ApiError> {
+        let venue = self.route(market)?;
+        let info = match &venue {
+            Venue::A(a) => a.order_status(market.local(), order_id).await?,
+            Venue::B(b) => b.order_status(market.local(), order_id).await?,
+        };
+        Ok(OrderInfo {
+            market: self.namespace(&venue, info.market),
+            ..info
+        })
+    }
+
+    async fn cancel_order(&self, market: Symbol, order_id: Uuid) -> Result<OrderInfo, ApiError> {
+        let venue = self.route(market)?;
+        let info = match &venue {
+            Venue::A(a) => a.cancel_order(market.local(), order_id).await?,
+            Venue::B(b) => b.cancel_order(market.local(), order_id).await?,
+        };
+        Ok(OrderInfo {
+            market: self.namespace(&venue, info.market),
+            ..info
+        })
+    }
+
+    async fn amend_order(
+        &self,
+        market: Symbol,
+        order_id: Uuid,
+        price: Decimal,
+        size: Decimal,
+    ) -> Result<OrderInfo, ApiError> {
+        let venue = self.route(market)?;
+        let local = market.local();
+        let info = match &venue {
+            Venue::A(a) => a.amend_order(local, order_id, price, size).await?,
+            Venue::B(b) => b.amend_order(local, order_id, price, size).await?,
+        };
+        Ok(OrderInfo {
+            market: self.namespace(&venue, info.market),
+            ..info
+        })
+    }
+
+    fn format_market(&self, market: Symbol) -> String {
+        match self.route(market) {
+            Ok(Venue::A(a)) => format!("{}:{}", self.venues.0, a.format_market(market.local())),
+            Ok(Venue::B(b)) => format!("{}:{}", self.venues.1, b.format_market(market.local())),
+            Err(_) => market.to_string(),
+        }
+    }
+
+    async fn update_wallet(&self, wallet: &mut Wallet) -> Result<(), ApiError> {
+        let mut wallets = self.wallets.lock().await;
+        let (wallet_a, wallet_b) = &mut *wallets;
+        let (a, b) = join!(
+            self.a.update_wallet(wallet_a),
+            self.b.update_wallet(wallet_b)
+        );
+        a?;
+        b?;
+        *wallet = merge(wallet_a, wallet_b);
+        Ok(())
+    }
+
+    async fn update_markets(&self, markets: &mut Markets) -> Result<(), ApiError> {
+        let mut venues = self.markets.lock().await;
+        let (markets_a, markets_b) = &mut *venues;
+        let (a, b) = join!(
+            self.a.update_markets(markets_a),
+            self.b.update_markets(markets_b)
+        );
+        a?;
+        b?;
+        let namespaced = |venue: &str, markets: &Markets| {
+            markets
+                .markets()
+                .map(|(symbol, info)| {
+                    let symbol = symbol.on(venue);
+                    (symbol, MarketInfo { symbol, ..*info })
+                })
+                .collect::<Vec<_>>()
+        };
+        markets.markets = namespaced(self.venues.0, markets_a)
+            .into_iter()
+            .chain(namespaced(self.venues.1, markets_b))
+            .collect();
+        Ok(())
+    }
+
+    async fn order_fee(&self) -> Decimal {
+        let (a, b) = join!(self.a.order_fee(), self.b.order_fee());
+        a.max(b)
+    }
+
+    async fn fee_schedule(&self) -> FeeSchedule {
+        let (a, b) = join!(self.a.fee_schedule(), self.b.fee_schedule());
+        FeeSchedule::flat(self.order_fee().await)
+            .with_venue(self.venues.0, a)
+            .with_venue(self.venues.1, b)
+    }
+
+    async fn borrow_rate(&self, market: Symbol) -> Option<Decimal> {
+        match self.route(market).ok()? {
+            Venue::A(a) => a.borrow_rate(market.local()).await,
+            Venue::B(b) => b.borrow_rate(market.local()).await,
+        }
+    }
+
+    async fn borrow_costs(&self, time: DateTime<Utc>) -> Result<Decimal, ApiError> {
+        let (a, b) = join!(self.a.borrow_costs(time), self.b.borrow_costs(time));
+        Ok(a? + b?)
+    }
+
+    /// The stricter requirements of both venues.
+    fn margin(&self) -> Option<Margin> {
+        match (self.a.margin(), self.b.margin()) {
+            (Some(a), Some(b)) => Some(Margin {
+                max_leverage: a.max_leverage.min(b.max_leverage),
+                maintenance_margin: a.maintenance_margin.max(b.maintenance_margin),
+                collateral: CollateralWeights {
+                    weights: a
+                        .collateral
+                        .weights
+                        .iter()
+                        .map(|(&asset, &weight)| (asset, weight.min(b.collateral.weight(asset))))
+                        .collect(),
+                },
+            }),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// The quote asset of the first venue.
+    fn quote_asset(&self) -> Asset {
+        self.a.quote_asset()
+    }
+
+    fn execution_gap(&self) -> Option<ExecutionGap> {
+        self.a.execution_gap().or_else(|| self.b.execution_gap())
+    }
+
+    async fn commands(&self) -> Vec<Command> {
+        let (mut a, b) = join!(self.a.commands(), self.b.commands());
+        a.extend(b);
+        a
+    }
+
+    /// The state is stored by the first venue.
+    async fn save_state(&self, strategy_name: &str, state: String) -> Result<(), ApiError> {
+        self.a.save_state(strategy_name, state).await
+    }
+
+    async fn load_state(&self, strategy_name: &str) -> Result<Option<String>, ApiError> {
+        self.a.load_state(strategy_name).await
+    }
+
+    async fn shutdown(&self) {
+        join!(self.a.shutdown(), self.b.shutdown());
+    }
+
+    fn hello(&self, strategy_name: &'static str) {
+        self.a.hello(strategy_name);
+        self.b.hello(strategy_name);
+    }
+
+    fn parameters(&self, parameters: &serde_json::Value) {
+        self.a.parameters(parameters);
+        self.b.parameters(parameters);
+    }
+
+    fn status(&self, time: DateTime<Utc>, total: Decimal, exposure: &Exposure) {
+        self.a.status(time, total, exposure);
+        self.b.status(time, total, exposure);
+    }
+
+    fn health(&self, time: DateTime<Utc>, health: &MarketHealth) {
+        self.a.health(time, health);
+        self.b.health(time, health);
+    }
+
+    fn reconciled(&self, discrepancy: &WalletDiscrepancy) {
+        self.a.reconciled(discrepancy);
+        self.b.reconciled(discrepancy);
+    }
+
+    fn metric(&self, time: DateTime<Utc>, name: &str, value: Decimal) {
+        self.a.metric(time, name, value);
+        self.b.metric(time, name, value);
+    }
+
+    fn positions(&self, time: DateTime<Utc>, positions: &[Position]) {
+        self.a.positions(time, positions);
+        self.b.positions(time, positions);
+    }
+
+    fn closed(&self, time: DateTime<Utc>, position: &Position) {
+        self.a.closed(time, position);
+        self.b.closed(time, position);
+    }
+
+    fn finished(&self, report: &Report) {
+        self.a.finished(report);
+        self.b.finished(report);
+    }
+
+    fn session_id(&self) -> Option<Uuid> {
+        self.a.session_id().or_else(|| self.b.session_id())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        apis::{Mock, MockSettings},
+        MarketType, OrderType, Side, TimeInForce,
+    };
+    use chrono::{Duration, TimeZone};
+    use rust_decimal_macros::dec;
+
+    type FlatMock = Mock<fn(CandleKey) -> Candle>;
+
+    fn flat(_key: CandleKey) -> Candle {
+        Candle {
+            open: dec!(100),
+            high: dec!(100),
+            low: dec!(100),
+            close: dec!(100),
+            volume: dec!(1),
+        }
+    }
+
+    fn venue(capital: Decimal, fee: Decimal) -> FlatMock {
+        let mut settings = MockSettings::new(flat as fn(CandleKey) -> Candle);
+        settings.fee = fee;
+        settings.wallet = Wallet::new();
+        settings.wallet.deposit(capital, Asset::new("USD"));
+        settings.markets = vec![MarketInfo {
+            symbol: Symbol::perp("BTC"),
+            min_size: Decimal::ZERO,
+            size_increment: Decimal::ZERO,
+            price_increment: Decimal::ZERO,
+            daily_quote_volume: Decimal::ZERO,
+            market_type: MarketType::Perpetual,
+            contract_multiplier: Decimal::ONE,
+            expiry: None,
+        }];
+        Mock::new(settings)
+    }
+
+    fn buy(market: Symbol) -> Order {
+        Order {
+            order_id: Uuid::new_v4(),
+            market,
+            side: Side::Buy,
+            size: dec!(1),
+            order_type: OrderType::Market,
+            reduce_only: false,
+            time_in_force: TimeInForce::ImmediateOrCancel,
+            post_only: false,
+            position_side: None,
+            time: Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap(),
+            current_price: dec!(100),
+        }
+    }
+
+    #[tokio::test]
+    async fn routes_by_venue() {
+        let router = Router::with_venues(
+            venue(dec!(100), dec!(0.001)),
+            "main",
+            venue(dec!(50), dec!(0.002)),
+            "hedge",
+        );
+        let btc = Symbol::perp("BTC");
+        let (main, hedge) = (btc.on("main"), btc.on("hedge"));
+        assert_eq!(main.to_string(), "main:BTC-PERP");
+        assert_eq!(main.local(), btc);
+        assert_eq!(hedge.venue(), Some("hedge"));
+        assert_eq!(hedge.underlying(), btc.underlying());
+
+        let mut markets = Markets::default();
+        router.update_markets(&mut markets).await.unwrap();
+        let mut symbols: Vec<Symbol> = markets.markets().map(|(symbol, _)| *symbol).collect();
+        symbols.sort_by_key(|symbol| symbol.to_string());
+        assert_eq!(symbols, vec![hedge, main]);
+        assert_eq!(markets.market(main).unwrap().symbol, main);
+
+        let mut wallet = Wallet::new();
+        router.update_wallet(&mut wallet).await.unwrap();
+        assert_eq!(wallet.total(Asset::new("USD")), dec!(150));
+
+        let schedule = router.fee_schedule().await;
+        assert_eq!(schedule.fees(main, Decimal::ZERO).taker, dec!(0.001));
+        assert_eq!(schedule.fees(hedge, Decimal::ZERO).taker, dec!(0.002));
+
+        let time = Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap();
+        let candles = router
+            .get_candles(CandleKey {
+                market: hedge,
+                time,
+                interval: Duration::minutes(1),
+            })
+            .await
+            .unwrap();
+        assert!(candles.iter().all(|(key, _)| key.market == hedge));
+
+        let info = router.place_order(buy(hedge)).await.unwrap();
+        assert_eq!(info.market, hedge);
+
+        assert!(matches!(
+            router.place_order(buy(btc)).await,
+            Err(ApiError::UnknownVenue(_))
+        ));
+    }
+}
@@ -0,0 +1,187 @@
+use super::Api;
+use crate::{
+    apis::{ApiError, ExecutionGap, FeeSchedule, Margin, Order, OrderInfo},
+    Asset, Candle, CandleKey, Command, Exposure, MarketHealth, Markets, Orderbook, Position,
+    PriceSource, Report, Symbol, Trade, Transfer, Wallet, WalletDiscrepancy,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use std::{ops::Range, sync::Arc};
+use uuid::Uuid;
+
+// Shares an API between multiple exchanges, e.g. one store for concurrent backtests.
+#[async_trait]
+impl<A: Api> Api for Arc<A> {
+    const NAME: &'static str = A::NAME;
+    const LIVE_TRADING_ENABLED: bool = A::LIVE_TRADING_ENABLED;
+
+    async fn get_candles(
+        &self,
+        key: CandleKey,
+    ) -> Result<Vec<(CandleKey, Option<Candle>)>, ApiError> {
+        A::get_candles(self, key).await
+    }
+
+    async fn get_orderbook(
+        &self,
+        market: Symbol,
+        time: DateTime<Utc>,
+    ) -> Result<Option<Orderbook>, ApiError> {
+        A::get_orderbook(self, market, time).await
+    }
+
+    async fn get_trades(
+        &self,
+        market: Symbol,
+        range: Range<DateTime<Utc>>,
+    ) -> Result<Option<Vec<Trade>>, ApiError> {
+        A::get_trades(self, market, range).await
+    }
+
+    async fn get_price(
+        &self,
+        key: CandleKey,
+        source: PriceSource,
+    ) -> Result<Option<Decimal>, ApiError> {
+        A::get_price(self, key, source).await
+    }
+
+    async fn get_prices(
+        &self,
+        key: CandleKey,
+        source: PriceSource,
+    ) -> Result<Vec<(CandleKey, Option<Decimal>)>, ApiError> {
+        A::get_prices(self, key, source).await
+    }
+
+    async fn place_order(&self, order: Order) -> Result<OrderInfo, ApiError> {
+        A::place_order(self, order).await
+    }
+
+    async fn place_orders(&self, orders: Vec<Order>) -> Vec<Result<OrderInfo, ApiError>> {
+        A::place_orders(self, orders).await
+    }
+
+    async fn order_status(&self, market: Symbol, order_id: Uuid) -> Result<OrderInfo, ApiError> {
+        A::order_status(self, market, order_id).await
+    }
+
+    async fn cancel_order(&self, market: Symbol, order_id: Uuid) -> Result<OrderInfo, ApiError> {
+        A::cancel_order(self, market, order_id).await
+    }
+
+    async fn amend_order(
+        &self,
+        market: Symbol,
+        order_id: Uuid,
+        price: Decimal,
+        size: Decimal,
+    ) -> Result<OrderInfo, ApiError> {
+        A::amend_order(self, market, order_id, price, size).await
+    }
+
+    fn format_market(&self, market: Symbol) -> String {
+        A::format_market(self, market)
+    }
+
+    async fn update_wallet(&self, wallet: &mut Wallet) -> Result<(), ApiError> {
+        A::update_wallet(self, wallet).await
+    }
+
+    async fn update_markets(&self, markets: &mut Markets) -> Result<(), ApiError> {
+        A::update_markets(self, markets).await
+    }
+
+    async fn order_fee(&self) -> Decimal {
+        A::order_fee(self).await
+    }
+
+    async fn fee_schedule(&self) -> FeeSchedule {
+        A::fee_schedule(self).await
+    }
+
+    async fn borrow_rate(&self, market: Symbol) -> Option<Decimal> {
+        A::borrow_rate(self, market).await
+    }
+
+    async fn borrow_costs(&self, time: DateTime<Utc>) -> Result<Decimal, ApiError> {
+        A::borrow_costs(self, time).await
+    }
+
+    fn margin(&self) -> Option<Margin> {
+        A::margin(self)
+    }
+
+    fn quote_asset(&self) -> Asset {
+        A::quote_asset(self)
+    }
+
+    fn execution_gap(&self) -> Option<ExecutionGap> {
+        A::execution_gap(self)
+    }
+
+    async fn commands(&self) -> Vec<Command> {
+        A::commands(self).await
+    }
+
+    async fn convert_dust(&self, assets: Vec<Asset>) -> Result<(), ApiError> {
+        A::convert_dust(self, assets).await
+    }
+
+    async fn transfer(&self, transfer: &Transfer) -> Result<(), ApiError> {
+        A::transfer(self, transfer).await
+    }
+
+    async fn save_state(&self, strategy_name: &str, state: String) -> Result<(), ApiError> {
+        A::save_state(self, strategy_name, state).await
+    }
+
+    async fn load_state(&self, strategy_name: &str) -> Result<Option<String>, ApiError> {
+        A::load_state(self, strategy_name).await
+    }
+
+    async fn shutdown(&self) {
+        A::shutdown(self).await
+    }
+
+    fn hello(&self, strategy_name: &'static str) {
+        A::hello(self, strategy_name)
+    }
+
+    fn parameters(&self, parameters: &serde_json::Value) {
+        A::parameters(self, parameters)
+    }
+
+    fn status(&self, time: DateTime<Utc>, total: Decimal, exposure: &Exposure) {
+        A::status(self, time, total, exposure)
+    }
+
+    fn health(&self, time: DateTime<Utc>, health: &MarketHealth) {
+        A::health(self, time, health)
+    }
+
+    fn reconciled(&self, discrepancy: &WalletDiscrepancy) {
+        A::reconciled(self, discrepancy)
+    }
+
+    fn metric(&self, time: DateTime<Utc>, name: &str, value: Decimal) {
+        A::metric(self, time, name, value)
+    }
+
+    fn positions(&self, time: DateTime<Utc>, positions: &[Position]) {
+        A::positions(self, time, positions)
+    }
+
+    fn closed(&self, time: DateTime<Utc>, position: &Position) {
+        A::closed(self, time, position)
+    }
+
+    fn finished(&self, report: &Report) {
+        A::finished(self, report)
+    }
+
+    fn session_id(&self) -> Option<Uuid> {
+        A::session_id(self)
+    }
+}
@@ -1,12 +1,60 @@
 use super::Api;
 use crate::{
-    apis::{ApiError, Order, OrderInfo},
-    Asset, Candle, CandleKey, Markets, Side, Symbol, Wallet,
+    apis::{ApiError, ExecutionGap, FeeSchedule, Margin, Order, OrderInfo},
+    Asset, Candle, CandleKey, Command, Direction, ImpactModel, Markets, OrderStatus, OrderType,
+    Orderbook, PositionSide, PriceSource, Side, SpreadEstimator, SpreadModel, Symbol, TimeInForce,
+    Trade, Transfer, Wallet, DEFAULT_DECIMALS,
+};
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    ops::Range,
 };
 
 use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
 use futures_util::lock::Mutex;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 use rust_decimal::prelude::*;
+use uuid::Uuid;
+
+type CandleHistory = HashMap<Symbol, BTreeMap<DateTime<Utc>, Candle>>;
+
+// Number of candles the volatility for the market impact is estimated from.
+const IMPACT_WINDOW: usize = 100;
+
+/// The delay between deciding on an order and its execution, to test how sensitive a strategy
+/// is to latency. Orders are decided at the close of the candle of the step, and executed
+/// after the delay at the price interpolated between the open and close of the candle the
+/// execution falls into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Latency {
+    /// The delay of every order.
+    pub delay: Duration,
+    /// Up to this much is added to the delay of each order, uniformly at random.
+    pub jitter: Duration,
+    /// Seeds the jitter, so backtests are reproducible.
+    pub seed: u64,
+}
+
+impl Latency {
+    /// A delay without jitter.
+    pub fn fixed(delay: Duration) -> Self {
+        Latency {
+            delay,
+            jitter: Duration::zero(),
+            seed: 0,
+        }
+    }
+
+    fn sample(&self, rng: &mut ChaCha8Rng) -> Duration {
+        let jitter = self.jitter.num_milliseconds();
+        if jitter <= 0 {
+            return self.delay;
+        }
+        self.delay + Duration::milliseconds(rng.gen_range(0..=jitter))
+    }
+}
 
 /// The Simulate API is a middleware that does not actually execute orders,
 /// and instead simulates the orders.
@@ -17,7 +65,41 @@ where
 {
     wallet: Mutex<Wallet>,
     api: A,
-    //orderbooks: HashMap<Symbol, Orderbook>,
+    // Window of the spread estimator.
+    spread_window: Option<usize>,
+    // Spreads of all markets, and of single markets, instead of estimating them.
+    spread: Option<SpreadModel>,
+    market_spreads: HashMap<Symbol, SpreadModel>,
+    impact: Option<ImpactModel>,
+    // Recent candles to estimate spreads and volatility from.
+    history: Mutex<CandleHistory>,
+    daily_volumes: Mutex<HashMap<Symbol, Decimal>>,
+    // Contract multipliers of the markets, to value fills.
+    multipliers: Mutex<HashMap<Symbol, Decimal>>,
+    margin: Option<Margin>,
+    // Quote volume of the fills, to find the fee tier.
+    volume: Mutex<VecDeque<(DateTime<Utc>, Decimal)>>,
+    // Fill orders against the order books of the API instead of the candles.
+    book_depth: bool,
+    quote_asset: Option<Asset>,
+    direction: Direction,
+    // Net filled size per symbol and leg, to enforce the direction.
+    // Orders without a leg are netted per symbol.
+    net_sizes: Mutex<HashMap<(Symbol, Option<PositionSide>), Decimal>>,
+    // Annual interest rate for borrowing, if the API does not know it.
+    borrow_rate: Decimal,
+    // Recent closes per symbol, to value the borrowed sizes.
+    closes: Mutex<HashMap<Symbol, BTreeMap<DateTime<Utc>, Decimal>>>,
+    // Until when the borrowing costs were charged.
+    borrowed_until: Mutex<Option<DateTime<Utc>>>,
+    // Orders are settled immediately, their fills are kept to answer status and cancel requests.
+    settled: Mutex<HashMap<Uuid, OrderInfo>>,
+    latency: Option<Latency>,
+    latency_rng: Mutex<ChaCha8Rng>,
+    // The candle intervals requested per symbol, to find the candle an order executes in.
+    intervals: Mutex<HashMap<Symbol, Duration>>,
+    // Fill orders against the trades of the API within the candle they execute in.
+    tick_replay: bool,
 }
 
 impl<A> Simulate<A>
@@ -30,9 +112,238 @@ where
         Simulate {
             wallet: Mutex::new(wallet),
             api,
-            //orderbooks: HashMap::new(),
+            spread_window: None,
+            spread: None,
+            market_spreads: HashMap::new(),
+            impact: None,
+            history: Mutex::new(HashMap::new()),
+            daily_volumes: Mutex::new(HashMap::new()),
+            multipliers: Mutex::new(HashMap::new()),
+            margin: None,
+            volume: Mutex::new(VecDeque::new()),
+            book_depth: false,
+            quote_asset: None,
+            direction: Direction::Both,
+            net_sizes: Mutex::new(HashMap::new()),
+            borrow_rate: Decimal::ZERO,
+            closes: Mutex::new(HashMap::new()),
+            borrowed_until: Mutex::new(None),
+            settled: Mutex::new(HashMap::new()),
+            latency: None,
+            latency_rng: Mutex::new(ChaCha8Rng::seed_from_u64(0)),
+            intervals: Mutex::new(HashMap::new()),
+            tick_replay: false,
         }
     }
+
+    /// Charge interest on short sizes at this annual rate, e.g. 0.05 for 5%,
+    /// for markets the API does not know the borrow rate of.
+    pub fn with_borrow_rate(mut self, rate: Decimal) -> Self {
+        assert!(rate >= Decimal::ZERO);
+        self.borrow_rate = rate;
+        self
+    }
+
+    /// Reject orders that would leave the net size of a symbol in a forbidden direction,
+    /// like venues that disallow shorting.
+    pub fn with_direction(mut self, direction: Direction) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    /// Account in another quote asset than the one of the API, e.g. USDT instead of USD.
+    /// The wallet should hold the deposit in this asset.
+    pub fn with_quote_asset(mut self, asset: Asset) -> Self {
+        self.quote_asset = Some(asset);
+        self
+    }
+
+    /// Simulate slippage by filling orders at the estimated ask for buys and bid for sells.
+    /// The spread is estimated from the last `window` candles before the order.
+    pub fn with_spread_estimation(mut self, window: usize) -> Self {
+        self.spread_window = Some(window);
+        self
+    }
+
+    /// Simulate the spread with a model instead of estimating it, for markets without
+    /// a model of their own.
+    pub fn with_spread(mut self, model: SpreadModel) -> Self {
+        self.spread = Some(model);
+        self
+    }
+
+    /// Simulate the spread of a market with a model, e.g. a wider one for an illiquid market.
+    pub fn with_market_spread(mut self, market: Symbol, model: SpreadModel) -> Self {
+        self.market_spreads.insert(market, model);
+        self
+    }
+
+    /// Simulate the market impact of orders, moving the fill price by the impact the model
+    /// expects from the order value, the daily volume and the recent volatility of the market.
+    pub fn with_market_impact(mut self, model: ImpactModel) -> Self {
+        self.impact = Some(model);
+        self
+    }
+
+    /// Simulate margin trading, allowing leveraged positions which get liquidated
+    /// if their value falls below the maintenance margin.
+    pub fn with_margin(mut self, margin: Margin) -> Self {
+        assert!(margin.max_leverage >= Decimal::ONE);
+        self.margin = Some(margin);
+        self
+    }
+
+    /// Fill orders by walking the order book of the API, so large orders get worse prices
+    /// and may only be filled partially. Falls back to the candles if the API has no book.
+    pub fn with_book_depth(mut self) -> Self {
+        self.book_depth = true;
+        self
+    }
+
+    /// Execute orders after a delay instead of at the close of the step, see [Latency].
+    /// Orders execute at the close if the candle after the delay is not known yet,
+    /// e.g. when paper trading.
+    pub fn with_latency(mut self, latency: Latency) -> Self {
+        assert!(latency.delay >= Duration::zero() && latency.jitter >= Duration::zero());
+        self.latency_rng = Mutex::new(ChaCha8Rng::seed_from_u64(latency.seed));
+        self.latency = Some(latency);
+        self
+    }
+
+    /// Replay the trades of the API within the candle after each step to fill orders.
+    /// Orders execute at the price of the first trade after the delay, and good til cancelled
+    /// limit orders that don't cross fill up to the size traded by takers at or through their price.
+    /// Falls back to the candles for markets the API has no trades of.
+    pub fn with_tick_replay(mut self) -> Self {
+        self.tick_replay = true;
+        self
+    }
+
+    // The trades within one candle after an order placed at the close of its step executes,
+    // None if the API has no trades or the candles of the market are not known.
+    async fn replayed_trades(&self, order: &Order, delay: Duration) -> Option<Vec<Trade>> {
+        let interval = *self.intervals.lock().await.get(&order.market)?;
+        let start = order.time + interval + delay;
+        self.api
+            .get_trades(order.market, start..start + interval)
+            .await
+            .ok()?
+    }
+
+    // The price an order placed at the close of its step executes at after the delay,
+    // None if the candle the execution falls into is not known.
+    async fn delayed_price(&self, order: &Order, delay: Duration) -> Option<Decimal> {
+        let interval = *self.intervals.lock().await.get(&order.market)?;
+        let length = interval.num_milliseconds();
+        if length <= 0 {
+            return None;
+        }
+        let periods = delay.num_milliseconds() / length;
+        let time = order.time + interval * (periods as i32 + 1);
+        let elapsed = delay.num_milliseconds() - periods * length;
+        let key = CandleKey {
+            market: order.market,
+            time,
+            interval,
+        };
+        let candle = self
+            .api
+            .get_candles(key)
+            .await
+            .ok()?
+            .into_iter()
+            .find(|(key, _)| key.time == time)?
+            .1?;
+        let fraction = Decimal::from(elapsed) / Decimal::from(length);
+        Some((candle.open + (candle.close - candle.open) * fraction).round_dp(DEFAULT_DECIMALS))
+    }
+
+    // The traded quote volume of the last 30 days.
+    async fn traded_volume(&self, time: DateTime<Utc>) -> Decimal {
+        let mut volume = self.volume.lock().await;
+        while matches!(volume.front(), Some((fill_time, _)) if *fill_time < time - Duration::days(30))
+        {
+            volume.pop_front();
+        }
+        volume.iter().map(|(_, volume)| volume).sum()
+    }
+
+    // The spread of the market from its model, or estimated from the candles, zero without either.
+    async fn spread(&self, market: Symbol, time: DateTime<Utc>) -> Decimal {
+        match self.market_spreads.get(&market).or(self.spread.as_ref()) {
+            Some(model) => {
+                let daily_volume = self.daily_volumes.lock().await.get(&market).cloned();
+                model.spread(daily_volume)
+            }
+            None => self.estimate_spread(market, time).await.unwrap_or_default(),
+        }
+    }
+
+    async fn estimate_spread(&self, market: Symbol, time: DateTime<Utc>) -> Option<Decimal> {
+        let window = self.spread_window.as_ref()?;
+        let history = self.history.lock().await;
+        let mut candles: Vec<&Candle> = history
+            .get(&market)?
+            .range(..=time)
+            .rev()
+            .take(window + 1)
+            .map(|(_, candle)| candle)
+            .collect();
+        candles.reverse();
+
+        let mut estimator = SpreadEstimator::new(*window);
+        for candle in candles {
+            estimator.update(candle);
+        }
+        estimator.spread()
+    }
+
+    // The value of a size of a market at a price.
+    async fn value(&self, market: Symbol, size: Decimal, price: Decimal) -> Decimal {
+        let multiplier = self.multipliers.lock().await.get(&market).cloned();
+        size * price * multiplier.unwrap_or(Decimal::ONE)
+    }
+
+    // The expected impact of an order relative to the price, zero without enough data.
+    async fn estimate_impact(&self, order: &Order) -> Decimal {
+        let Some(model) = &self.impact else {
+            return Decimal::ZERO;
+        };
+        let Some(&daily_volume) = self.daily_volumes.lock().await.get(&order.market) else {
+            return Decimal::ZERO;
+        };
+        let history = self.history.lock().await;
+        let candles: Vec<(&DateTime<Utc>, &Candle)> = match history.get(&order.market) {
+            Some(candles) => candles
+                .range(..=order.time)
+                .rev()
+                .take(IMPACT_WINDOW + 1)
+                .collect(),
+            None => return Decimal::ZERO,
+        };
+        if candles.len() < 3 {
+            return Decimal::ZERO;
+        }
+
+        let returns: Vec<f64> = candles
+            .windows(2)
+            .filter_map(|pair| (pair[0].1.close / pair[1].1.close).to_f64())
+            .filter(|ratio| *ratio > 0.0)
+            .map(f64::ln)
+            .collect();
+        let mean = returns.iter().sum::<f64>() / returns.len() as f64;
+        let variance = returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>()
+            / (returns.len() - 1).max(1) as f64;
+        let interval = (*candles[0].0 - *candles[1].0).num_seconds().max(1) as f64;
+        let daily_volatility = variance.sqrt() * (86_400.0 / interval).sqrt();
+
+        let value = self
+            .value(order.market, order.size, order.current_price)
+            .await;
+        model
+            .impact(value, daily_volume, daily_volatility)
+            .unwrap_or_default()
+    }
 }
 
 #[async_trait]
@@ -44,11 +355,91 @@ impl<A: Api> Api for Simulate<A> {
         &self,
         key: CandleKey,
     ) -> Result<Vec<(CandleKey, Option<Candle>)>, ApiError> {
-        self.api.get_candles(key).await
+        let candles = self.api.get_candles(key).await?;
+        self.intervals.lock().await.insert(key.market, key.interval);
+
+        {
+            let mut closes = self.closes.lock().await;
+            let closes = closes.entry(key.market).or_default();
+            for (key, candle) in &candles {
+                if let Some(candle) = candle {
+                    closes.insert(key.time, candle.close);
+                }
+            }
+        }
+
+        let window = match (self.spread_window, self.impact) {
+            (Some(window), None) => Some(window),
+            (window, Some(_)) => Some(window.unwrap_or_default().max(IMPACT_WINDOW)),
+            (None, None) => None,
+        };
+        if let Some(window) = window {
+            let mut history = self.history.lock().await;
+            let history = history.entry(key.market).or_default();
+            // Candles before this are not needed for the estimates from now on.
+            let oldest = key.time - key.interval * (window as i32 + 1);
+            *history = history.split_off(&oldest);
+            for (key, candle) in &candles {
+                if let Some(candle) = candle {
+                    history.insert(key.time, *candle);
+                }
+            }
+        }
+
+        Ok(candles)
     }
 
-    async fn place_order(&self, order: Order) -> Result<OrderInfo, ApiError> {
-        log::trace!("place order simulate");
+    async fn get_orderbook(
+        &self,
+        market: Symbol,
+        time: DateTime<Utc>,
+    ) -> Result<Option<Orderbook>, ApiError> {
+        self.api.get_orderbook(market, time).await
+    }
+
+    async fn get_trades(
+        &self,
+        market: Symbol,
+        range: Range<DateTime<Utc>>,
+    ) -> Result<Option<Vec<Trade>>, ApiError> {
+        self.api.get_trades(market, range).await
+    }
+
+    async fn get_price(
+        &self,
+        key: CandleKey,
+        source: PriceSource,
+    ) -> Result<Option<Decimal>, ApiError> {
+        self.api.get_price(key, source).await
+    }
+
+    async fn get_prices(
+        &self,
+        key: CandleKey,
+        source: PriceSource,
+    ) -> Result<Vec<(CandleKey, Option<Decimal>)>, ApiError> {
+        self.api.get_prices(key, source).await
+    }
+
+    async fn place_order(&self, mut order: Order) -> Result<OrderInfo, ApiError> {
+        tracing::trace!("place order simulate");
+
+        let delay = match &self.latency {
+            Some(latency) => latency.sample(&mut *self.latency_rng.lock().await),
+            None => Duration::zero(),
+        };
+        let trades = if self.tick_replay {
+            self.replayed_trades(&order, delay).await
+        } else {
+            None
+        };
+        if let Some(trade) = trades.as_ref().and_then(|trades| trades.first()) {
+            order.current_price = trade.price;
+        } else if self.latency.is_some() {
+            if let Some(price) = self.delayed_price(&order, delay).await {
+                order.current_price = price;
+            }
+        }
 
         //let quote_size = order.size * order.price;
         //let wallet = self.wallet.lock().await;
@@ -56,19 +447,171 @@ impl<A: Api> Api for Simulate<A> {
         //wallet.reserve(quote_size, self.quote_asset()).unwrap();
         //wallet.withdraw(quote_size, self.quote_asset()).unwrap();
 
-        Ok(OrderInfo {
-            order_id: order.order_id,
-            size: order.size,
-            price: if order.side == Side::Buy {
-                order.current_price * (Decimal::one() + self.api.order_fee().await)
-            } else {
-                order.current_price * (Decimal::one() - self.api.order_fee().await)
+        let book = if self.book_depth {
+            self.api.get_orderbook(order.market, order.time).await?
+        } else {
+            None
+        };
+        let half_spread = self.spread(order.market, order.time).await / Decimal::TWO;
+        let (bid, ask) = match book
+            .as_ref()
+            .and_then(|book| Some((book.bid_price()?, book.ask_price()?)))
+        {
+            Some(touch) => touch,
+            None => (
+                order.current_price * (Decimal::ONE - half_spread),
+                order.current_price * (Decimal::ONE + half_spread),
+            ),
+        };
+        let crosses = order.crosses(bid, ask);
+
+        let net_size = self
+            .net_sizes
+            .lock()
+            .await
+            .get(&(order.market, order.position_side))
+            .cloned()
+            .unwrap_or_default();
+        // Like on a venue, reduce only orders are rejected if there is nothing to reduce,
+        // and are reduced to the net size if they are larger.
+        if order.reduce_only {
+            let reduces = match order.side {
+                Side::Buy => net_size < Decimal::ZERO,
+                Side::Sell => net_size > Decimal::ZERO,
+            };
+            if !reduces {
+                return Err(ApiError::Rejected);
+            }
+            order.size = order.size.min(net_size.abs());
+        }
+
+        let signed = |size: Decimal| match order.side {
+            Side::Buy => size,
+            Side::Sell => -size,
+        };
+        if !self.direction.allows(net_size + signed(order.size)) {
+            return Err(ApiError::Rejected);
+        }
+
+        // Post-only orders are rejected instead of taking liquidity.
+        if order.post_only && crosses {
+            return Err(ApiError::Rejected);
+        }
+
+        let fees = self
+            .api
+            .fee_schedule()
+            .await
+            .fees(order.market, self.traded_volume(order.time).await);
+
+        let (size, price) = match order.order_type {
+            _ if crosses => match &book {
+                Some(book) => book_fill(&order, book, fees.taker)?,
+                None => {
+                    let slippage = half_spread + self.estimate_impact(&order).await;
+                    (order.size, simulated_price(&order, fees.taker, slippage))
+                }
+            },
+            // With the trades of the candle, good til cancelled limit orders are filled by the takers
+            // of the other side trading at or through their price.
+            OrderType::Limit(price)
+                if order.time_in_force == TimeInForce::GoodTilCancelled && trades.is_some() =>
+            {
+                let traded: Decimal = trades
+                    .iter()
+                    .flatten()
+                    .filter(|trade| trade.side != order.side)
+                    .filter(|trade| match order.side {
+                        Side::Buy => trade.price <= price,
+                        Side::Sell => trade.price >= price,
+                    })
+                    .map(|trade| trade.size)
+                    .sum();
+                let size = order.size.min(traded);
+                if size.is_zero() {
+                    (Decimal::ZERO, price)
+                } else {
+                    let price = if order.side == Side::Buy {
+                        price * (Decimal::ONE + fees.maker)
+                    } else {
+                        price * (Decimal::ONE - fees.maker)
+                    };
+                    (size, price.round_dp(DEFAULT_DECIMALS))
+                }
+            }
+            // Good til cancelled limit orders joining or improving the best price, i.e. that would
+            // cross a book with bid and ask swapped, are assumed to be filled within the step.
+            // Without a spread, bid and ask are the current price, so limit orders either cross
+            // or stay unfilled.
+            OrderType::Limit(price)
+                if order.time_in_force == TimeInForce::GoodTilCancelled
+                    && order.crosses(ask, bid) =>
+            {
+                let price = if order.side == Side::Buy {
+                    price * (Decimal::ONE + fees.maker)
+                } else {
+                    price * (Decimal::ONE - fees.maker)
+                };
+                (order.size, price.round_dp(DEFAULT_DECIMALS))
             }
-            .round_dp(8),
+            _ if order.time_in_force == TimeInForce::FillOrKill => {
+                return Err(ApiError::Rejected);
+            }
+            OrderType::Limit(price) => (Decimal::ZERO, price),
+            OrderType::Market => unreachable!("market orders always cross"),
+        };
+
+        self.volume
+            .lock()
+            .await
+            .push_back((order.time, self.value(order.market, size, price).await));
+        *self
+            .net_sizes
+            .lock()
+            .await
+            .entry((order.market, order.position_side))
+            .or_default() += signed(size);
+
+        let info = OrderInfo {
+            order_id: order.order_id,
+            size,
+            price,
             time: order.time,
             side: order.side,
             market: order.market,
-        })
+            status: OrderStatus::settled(order.size, size),
+        };
+        self.settled
+            .lock()
+            .await
+            .insert(order.order_id, info.clone());
+        Ok(info)
+    }
+
+    async fn order_status(&self, _market: Symbol, order_id: Uuid) -> Result<OrderInfo, ApiError> {
+        self.settled
+            .lock()
+            .await
+            .get(&order_id)
+            .cloned()
+            .ok_or(ApiError::Api)
+    }
+
+    // Simulated orders never rest, so there is nothing left to cancel.
+    async fn cancel_order(&self, market: Symbol, order_id: Uuid) -> Result<OrderInfo, ApiError> {
+        self.order_status(market, order_id).await
+    }
+
+    // Settled orders cannot be changed anymore.
+    async fn amend_order(
+        &self,
+        market: Symbol,
+        order_id: Uuid,
+        _price: Decimal,
+        _size: Decimal,
+    ) -> Result<OrderInfo, ApiError> {
+        self.order_status(market, order_id).await?;
+        Err(ApiError::Rejected)
     }
     /*
     async fn order_update(&self, asset: Asset) -> Pin<Box<dyn Stream<Item = OrderUpdate>>> {
@@ -111,33 +654,299 @@ impl<A: Api> Api for Simulate<A> {
         */
         if markets.is_fresh() {
             self.api.update_markets(markets).await?;
+            *self.daily_volumes.lock().await = markets
+                .markets()
+                .map(|(&symbol, info)| (symbol, info.daily_quote_volume))
+                .collect();
+            *self.multipliers.lock().await = markets
+                .markets()
+                .map(|(&symbol, info)| (symbol, info.contract_multiplier))
+                .collect();
         }
 
         Ok(())
     }
 
     fn quote_asset(&self) -> Asset {
-        self.api.quote_asset()
+        self.quote_asset.unwrap_or_else(|| self.api.quote_asset())
     }
 
     async fn order_fee(&self) -> Decimal {
         self.api.order_fee().await
     }
+
+    async fn fee_schedule(&self) -> FeeSchedule {
+        self.api.fee_schedule().await
+    }
+
+    async fn borrow_rate(&self, market: Symbol) -> Option<Decimal> {
+        self.api.borrow_rate(market).await
+    }
+
+    // Interest accrues on the short sizes held since the last step, valued at the last close.
+    async fn borrow_costs(&self, time: DateTime<Utc>) -> Result<Decimal, ApiError> {
+        let since = match self.borrowed_until.lock().await.replace(time) {
+            Some(since) if since < time => since,
+            _ => return Ok(Decimal::ZERO),
+        };
+        let years = Decimal::from((time - since).num_seconds()) / Decimal::from(365 * 24 * 60 * 60);
+
+        let shorts: Vec<(Symbol, Decimal)> = self
+            .net_sizes
+            .lock()
+            .await
+            .iter()
+            .filter(|(_, size)| **size < Decimal::ZERO)
+            .map(|(&(symbol, _), &size)| (symbol, -size))
+            .collect();
+        let mut costs = Decimal::ZERO;
+        for (symbol, size) in shorts {
+            let rate = self
+                .api
+                .borrow_rate(symbol)
+                .await
+                .unwrap_or(self.borrow_rate);
+            let price = self
+                .closes
+                .lock()
+                .await
+                .get(&symbol)
+                .and_then(|closes| closes.range(..=time).next_back().map(|(_, &close)| close));
+            match price {
+                Some(price) => costs += self.value(symbol, size, price).await * rate * years,
+                None => tracing::warn!(market = %symbol, "No price to charge borrowing at."),
+            }
+        }
+
+        // Closes before the last one are not needed anymore.
+        for closes in self.closes.lock().await.values_mut() {
+            if let Some((&last, _)) = closes.range(..=time).next_back() {
+                *closes = closes.split_off(&last);
+            }
+        }
+
+        Ok(costs.round_dp(DEFAULT_DECIMALS))
+    }
+
+    // The exchange converts the dust in its own wallet.
+    async fn convert_dust(&self, _assets: Vec<Asset>) -> Result<(), ApiError> {
+        Ok(())
+    }
+
+    // Other accounts are not simulated, transfers are always accepted.
+    async fn transfer(&self, _transfer: &Transfer) -> Result<(), ApiError> {
+        Ok(())
+    }
+
+    async fn save_state(&self, strategy_name: &str, state: String) -> Result<(), ApiError> {
+        self.api.save_state(strategy_name, state).await
+    }
+
+    async fn load_state(&self, strategy_name: &str) -> Result<Option<String>, ApiError> {
+        self.api.load_state(strategy_name).await
+    }
+
+    fn margin(&self) -> Option<Margin> {
+        self.margin.clone()
+    }
+
+    async fn shutdown(&self) {
+        self.api.shutdown().await
+    }
+
+    fn execution_gap(&self) -> Option<ExecutionGap> {
+        self.api.execution_gap()
+    }
+
+    async fn commands(&self) -> Vec<Command> {
+        self.api.commands().await
+    }
+}
+
+// Fill an order taking liquidity from the book, up to its limit price.
+// The part that is not filled immediately is cancelled, fill or kill orders are rejected instead.
+fn book_fill(
+    order: &Order,
+    book: &Orderbook,
+    fee: Decimal,
+) -> Result<(Decimal, Decimal), ApiError> {
+    let limit = match order.order_type {
+        OrderType::Limit(price) => Some(price),
+        OrderType::Market => None,
+    };
+    let (size, price) = book.fill(order.size, order.side, limit);
+    if order.time_in_force == TimeInForce::FillOrKill && size < order.size {
+        return Err(ApiError::Rejected);
+    }
+
+    let price = match price {
+        Some(price) if order.side == Side::Buy => price * (Decimal::ONE + fee),
+        Some(price) => price * (Decimal::ONE - fee),
+        None => order.current_price,
+    };
+    Ok((size, price.round_dp(DEFAULT_DECIMALS)))
+}
+
+// The fill price of an order in the simulation,
+// buying at the ask and selling at the bid, including the fee.
+pub(crate) fn simulated_price(order: &Order, fee: Decimal, half_spread: Decimal) -> Decimal {
+    if order.side == Side::Buy {
+        order.current_price * (Decimal::one() + half_spread) * (Decimal::one() + fee)
+    } else {
+        order.current_price * (Decimal::one() - half_spread) * (Decimal::one() - fee)
+    }
+    .round_dp(DEFAULT_DECIMALS)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{apis::Ftx, OrderType, Side};
-    use chrono::Utc;
+    use crate::{
+        apis::{CandleGen, Mock, MockSettings},
+        Side,
+    };
+    use chrono::{TimeZone, Utc};
     use rust_decimal_macros::dec;
     use uuid::Uuid;
 
+    // A venue with a fee, the candles are never requested.
+    fn venue() -> Mock<impl CandleGen> {
+        let mut settings = MockSettings::new(|_key: CandleKey| -> Candle { unreachable!() });
+        settings.fee = dec!(0.0007);
+        Mock::new(settings)
+    }
+
+    #[tokio::test]
+    async fn reject_forbidden_direction() {
+        let api = Simulate::new(venue(), Wallet::new()).with_direction(Direction::LongOnly);
+        let order = |side| Order {
+            order_id: Uuid::new_v4(),
+            market: Symbol::perp("BTC"),
+            side,
+            size: dec!(0.01),
+            order_type: OrderType::Market,
+            reduce_only: false,
+            time_in_force: TimeInForce::ImmediateOrCancel,
+            post_only: false,
+            position_side: None,
+            time: Utc::now(),
+            current_price: dec!(10000),
+        };
+
+        assert!(matches!(
+            api.place_order(order(Side::Sell)).await,
+            Err(ApiError::Rejected)
+        ));
+        assert!(api.place_order(order(Side::Buy)).await.is_ok());
+        assert!(api.place_order(order(Side::Sell)).await.is_ok());
+        assert!(api.place_order(order(Side::Sell)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn enforce_reduce_only() {
+        let api = Simulate::new(venue(), Wallet::new());
+        let order = |side, size, reduce_only| Order {
+            order_id: Uuid::new_v4(),
+            market: Symbol::perp("BTC"),
+            side,
+            size,
+            order_type: OrderType::Market,
+            reduce_only,
+            time_in_force: TimeInForce::ImmediateOrCancel,
+            post_only: false,
+            position_side: None,
+            time: Utc::now(),
+            current_price: dec!(10000),
+        };
+
+        // Nothing to reduce.
+        assert!(matches!(
+            api.place_order(order(Side::Sell, dec!(1), true)).await,
+            Err(ApiError::Rejected)
+        ));
+        api.place_order(order(Side::Buy, dec!(1), false))
+            .await
+            .unwrap();
+        assert!(matches!(
+            api.place_order(order(Side::Buy, dec!(1), true)).await,
+            Err(ApiError::Rejected)
+        ));
+        // Reduced to the net size instead of flipping the position.
+        let info = api
+            .place_order(order(Side::Sell, dec!(3), true))
+            .await
+            .unwrap();
+        assert_eq!(info.size, dec!(1));
+        assert_eq!(info.status, OrderStatus::Filled);
+    }
+
+    #[tokio::test]
+    async fn accrue_borrow_costs() {
+        let settings = MockSettings::new(|_key: CandleKey| Candle {
+            open: dec!(100),
+            high: dec!(100),
+            low: dec!(100),
+            close: dec!(100),
+            volume: dec!(1),
+        });
+        let api = Simulate::new(Mock::new(settings), Wallet::new()).with_borrow_rate(dec!(0.365));
+        let btc = Symbol::perp("BTC");
+        let start = Utc::now();
+        let order = |side, size| Order {
+            order_id: Uuid::new_v4(),
+            market: btc,
+            side,
+            size,
+            order_type: OrderType::Market,
+            reduce_only: false,
+            time_in_force: TimeInForce::ImmediateOrCancel,
+            post_only: false,
+            position_side: None,
+            time: start,
+            current_price: dec!(100),
+        };
+        api.get_candles(CandleKey {
+            market: btc,
+            time: start,
+            interval: Duration::days(1),
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(api.borrow_costs(start).await.unwrap(), Decimal::ZERO);
+        api.place_order(order(Side::Buy, dec!(1))).await.unwrap();
+        let day = start + Duration::days(1);
+        // Long sizes are not borrowed.
+        assert_eq!(api.borrow_costs(day).await.unwrap(), Decimal::ZERO);
+        api.place_order(order(Side::Sell, dec!(3))).await.unwrap();
+        // A tenth of a percent per day on a short of 2 at 100.
+        assert_eq!(
+            api.borrow_costs(day + Duration::days(1)).await.unwrap(),
+            dec!(0.2)
+        );
+    }
+
+    #[tokio::test]
+    async fn custom_quote_asset() {
+        let usdt = Asset::new("USDT");
+        let bazaar = crate::Bazaar {
+            quote_asset: Some(usdt),
+            ..Default::default()
+        };
+        let api = bazaar.simulate(venue());
+        let mut wallet = Wallet::new();
+        api.update_wallet(&mut wallet).await.unwrap();
+
+        assert_eq!(api.quote_asset(), usdt);
+        assert_eq!(wallet.total(usdt), bazaar.start_capital);
+        assert_eq!(wallet.total(Asset::new("USD")), Decimal::ZERO);
+    }
+
     #[tokio::test]
     async fn deduct_fee_long() {
         let mut wallet = Wallet::new();
         wallet.deposit(dec!(1000), Asset::new("USD"));
-        let api = Simulate::new(Ftx::from_env(), wallet);
+        let api = Simulate::new(venue(), wallet);
         let order = Order {
             order_id: Uuid::new_v4(),
             market: Symbol::perp("BTC"),
@@ -145,6 +954,9 @@ mod tests {
             size: dec!(0.01),
             order_type: OrderType::Market,
             reduce_only: false,
+            time_in_force: TimeInForce::ImmediateOrCancel,
+            post_only: false,
+            position_side: None,
             time: Utc::now(),
             current_price: dec!(10000),
         };
@@ -160,6 +972,9 @@ mod tests {
             size: dec!(0.01),
             order_type: OrderType::Market,
             reduce_only: false,
+            time_in_force: TimeInForce::ImmediateOrCancel,
+            post_only: false,
+            position_side: None,
             time: Utc::now(),
             current_price: dec!(10000),
         };
@@ -173,7 +988,7 @@ mod tests {
     async fn deduct_fee_short() {
         let mut wallet = Wallet::new();
         wallet.deposit(dec!(1000), Asset::new("USD"));
-        let api = Simulate::new(Ftx::from_env(), wallet);
+        let api = Simulate::new(venue(), wallet);
         let order = Order {
             order_id: Uuid::new_v4(),
             market: Symbol::perp("BTC"),
@@ -181,6 +996,9 @@ mod tests {
             size: dec!(0.01),
             order_type: OrderType::Market,
             reduce_only: false,
+            time_in_force: TimeInForce::ImmediateOrCancel,
+            post_only: false,
+            position_side: None,
             time: Utc::now(),
             current_price: dec!(10000),
         };
@@ -196,6 +1014,9 @@ mod tests {
             size: dec!(0.01),
             order_type: OrderType::Market,
             reduce_only: false,
+            time_in_force: TimeInForce::ImmediateOrCancel,
+            post_only: false,
+            position_side: None,
             time: Utc::now(),
             current_price: dec!(10000),
         };
@@ -204,4 +1025,370 @@ mod tests {
 
         assert!(price > dec!(10000));
     }
+
+    #[tokio::test]
+    async fn reject_crossing_post_only() {
+        let api = Simulate::new(venue(), Wallet::new());
+        let order = |price| Order {
+            order_id: Uuid::new_v4(),
+            market: Symbol::perp("BTC"),
+            side: Side::Buy,
+            size: dec!(0.01),
+            order_type: OrderType::Limit(price),
+            reduce_only: false,
+            time_in_force: TimeInForce::GoodTilCancelled,
+            post_only: true,
+            position_side: None,
+            time: Utc::now(),
+            current_price: dec!(10000),
+        };
+
+        assert!(matches!(
+            api.place_order(order(dec!(10001))).await,
+            Err(ApiError::Rejected)
+        ));
+
+        let OrderInfo { size, .. } = api.place_order(order(dec!(9999))).await.unwrap();
+
+        assert_eq!(size, Decimal::ZERO);
+    }
+
+    #[tokio::test]
+    async fn maker_fee_for_passive_orders() {
+        let mut settings = MockSettings::new(|_key: CandleKey| Candle {
+            open: dec!(100),
+            high: dec!(101),
+            low: dec!(99),
+            close: dec!(100),
+            volume: dec!(1),
+        });
+        settings.fee = dec!(0.001);
+        let api = Simulate::new(Mock::new(settings), Wallet::new()).with_spread_estimation(10);
+        let time = Utc::now();
+        for minutes in [2, 1] {
+            let key = CandleKey {
+                market: Symbol::perp("BTC"),
+                time: time - chrono::Duration::minutes(minutes),
+                interval: chrono::Duration::minutes(1),
+            };
+            api.get_candles(key).await.unwrap();
+        }
+        let order = |price| Order {
+            order_id: Uuid::new_v4(),
+            market: Symbol::perp("BTC"),
+            side: Side::Buy,
+            size: dec!(0.01),
+            order_type: OrderType::Limit(price),
+            reduce_only: false,
+            time_in_force: TimeInForce::GoodTilCancelled,
+            post_only: true,
+            position_side: None,
+            time,
+            current_price: dec!(100),
+        };
+
+        // Inside the spread, filled at the limit price without the taker fee.
+        let OrderInfo { size, price, .. } = api.place_order(order(dec!(100))).await.unwrap();
+
+        assert_eq!(size, dec!(0.01));
+        assert_eq!(price, dec!(100));
+
+        // Through the ask.
+        assert!(matches!(
+            api.place_order(order(dec!(105))).await,
+            Err(ApiError::Rejected)
+        ));
+    }
+
+    #[tokio::test]
+    async fn walk_book_depth() {
+        let btc = Symbol::perp("BTC");
+        let mut settings = MockSettings::new(|_key: CandleKey| -> Candle { unreachable!() });
+        settings.fee = dec!(0.001);
+        settings.orderbooks.insert(
+            btc,
+            Orderbook {
+                bids: [(dec!(99), dec!(1))].into(),
+                asks: [(dec!(101), dec!(1)), (dec!(103), dec!(1))].into(),
+            },
+        );
+        let api = Simulate::new(Mock::new(settings), Wallet::new()).with_book_depth();
+        let order = |size, order_type, time_in_force| Order {
+            order_id: Uuid::new_v4(),
+            market: btc,
+            side: Side::Buy,
+            size,
+            order_type,
+            reduce_only: false,
+            time_in_force,
+            post_only: false,
+            position_side: None,
+            time: Utc::now(),
+            current_price: dec!(100),
+        };
+
+        // Walks two levels.
+        let OrderInfo { size, price, .. } = api
+            .place_order(order(
+                dec!(2),
+                OrderType::Market,
+                TimeInForce::ImmediateOrCancel,
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(size, dec!(2));
+        assert_eq!(price, dec!(102.102));
+
+        // Only the first level is within the limit.
+        let OrderInfo { size, .. } = api
+            .place_order(order(
+                dec!(2),
+                OrderType::Limit(dec!(102)),
+                TimeInForce::ImmediateOrCancel,
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(size, dec!(1));
+
+        // More than the book.
+        assert!(matches!(
+            api.place_order(order(dec!(3), OrderType::Market, TimeInForce::FillOrKill))
+                .await,
+            Err(ApiError::Rejected)
+        ));
+    }
+
+    #[tokio::test]
+    async fn fill_across_spread() {
+        let (btc, eth) = (Symbol::perp("BTC"), Symbol::perp("ETH"));
+        let api = Simulate::new(venue(), Wallet::new())
+            .with_spread(SpreadModel::Fixed(dec!(0.002)))
+            .with_market_spread(eth, SpreadModel::Fixed(dec!(0.01)));
+        let order = |market, side| Order {
+            order_id: Uuid::new_v4(),
+            market,
+            side,
+            size: dec!(1),
+            order_type: OrderType::Market,
+            reduce_only: false,
+            time_in_force: TimeInForce::ImmediateOrCancel,
+            post_only: false,
+            position_side: None,
+            time: Utc::now(),
+            current_price: dec!(100),
+        };
+
+        // Half the spread is paid before the fee.
+        let buy = api.place_order(order(btc, Side::Buy)).await.unwrap();
+        assert_eq!(buy.price, dec!(100.170070));
+        let sell = api.place_order(order(btc, Side::Sell)).await.unwrap();
+        assert_eq!(sell.price, dec!(99.830070));
+        let buy = api.place_order(order(eth, Side::Buy)).await.unwrap();
+        assert_eq!(buy.price, dec!(100.570350));
+    }
+
+    #[tokio::test]
+    async fn market_impact_of_large_orders() {
+        let btc = Symbol::perp("BTC");
+        // Alternating closes, about 1% volatility per minute.
+        let mut settings = MockSettings::new(|key: CandleKey| {
+            let close = if key.time.timestamp() / 60 % 2 == 0 {
+                dec!(100)
+            } else {
+                dec!(101)
+            };
+            Candle {
+                open: close,
+                high: close,
+                low: close,
+                close,
+                volume: dec!(1),
+            }
+        });
+        settings.markets = vec![crate::MarketInfo {
+            symbol: btc,
+            min_size: Decimal::ZERO,
+            size_increment: Decimal::ZERO,
+            price_increment: Decimal::ZERO,
+            daily_quote_volume: dec!(1_000_000),
+            market_type: crate::MarketType::Perpetual,
+            contract_multiplier: Decimal::ONE,
+            expiry: None,
+        }];
+        let api = Simulate::new(Mock::new(settings), Wallet::new())
+            .with_market_impact(ImpactModel::default());
+        api.update_markets(&mut Markets::default()).await.unwrap();
+        let time = Utc::now();
+        for minutes in 1..=10 {
+            api.get_candles(CandleKey {
+                market: btc,
+                time: time - chrono::Duration::minutes(minutes),
+                interval: chrono::Duration::minutes(1),
+            })
+            .await
+            .unwrap();
+        }
+        let order = |size| Order {
+            order_id: Uuid::new_v4(),
+            market: btc,
+            side: Side::Buy,
+            size,
+            order_type: OrderType::Market,
+            reduce_only: false,
+            time_in_force: TimeInForce::ImmediateOrCancel,
+            post_only: false,
+            position_side: None,
+            time,
+            current_price: dec!(100),
+        };
+
+        let small = api.place_order(order(dec!(1))).await.unwrap().price;
+        let large = api.place_order(order(dec!(1000))).await.unwrap().price;
+
+        assert!(small > dec!(100));
+        assert!(large > small);
+    }
+
+    // Rising by one every minute, from the open to the close.
+    fn rising() -> Mock<impl CandleGen> {
+        Mock::new(MockSettings::new(|key: CandleKey| {
+            let open = Decimal::from(100 + key.time.timestamp() / 60 % 60);
+            Candle {
+                open,
+                high: open + Decimal::ONE,
+                low: open,
+                close: open + Decimal::ONE,
+                volume: dec!(1),
+            }
+        }))
+    }
+
+    #[tokio::test]
+    async fn execute_after_latency() {
+        let btc = Symbol::perp("BTC");
+        let latency = Latency {
+            delay: Duration::seconds(90),
+            jitter: Duration::seconds(30),
+            seed: 7,
+        };
+        let time = Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap();
+        let key = CandleKey {
+            market: btc,
+            time,
+            interval: Duration::minutes(1),
+        };
+        // Decided at the close of 101, executed within the second candle after it.
+        let order = Order {
+            order_id: Uuid::new_v4(),
+            market: btc,
+            side: Side::Buy,
+            size: dec!(1),
+            order_type: OrderType::Market,
+            reduce_only: false,
+            time_in_force: TimeInForce::ImmediateOrCancel,
+            post_only: false,
+            position_side: None,
+            time,
+            current_price: dec!(101),
+        };
+
+        let mut prices = Vec::new();
+        for _ in 0..2 {
+            let api = Simulate::new(rising(), Wallet::new()).with_latency(latency);
+            api.get_candles(key).await.unwrap();
+            prices.push(api.place_order(order.clone()).await.unwrap().price);
+        }
+        assert!(
+            prices[0] >= dec!(102.5) && prices[0] <= dec!(103),
+            "{}",
+            prices[0]
+        );
+        // The same seed gives the same delays.
+        assert_eq!(prices[0], prices[1]);
+
+        // Without the candles of the market, the order executes at the close.
+        let api = Simulate::new(rising(), Wallet::new()).with_latency(latency);
+        assert_eq!(api.place_order(order).await.unwrap().price, dec!(101));
+    }
+
+    #[tokio::test]
+    async fn replay_trades() {
+        let btc = Symbol::perp("BTC");
+        let time = Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap();
+        let key = CandleKey {
+            market: btc,
+            time,
+            interval: Duration::minutes(1),
+        };
+        let trade = |seconds: i64, price, size, side| Trade {
+            time: time + Duration::seconds(60 + seconds),
+            price,
+            size,
+            side,
+        };
+        let mut settings = MockSettings::new(|key: CandleKey| {
+            let open = Decimal::from(100 + key.time.timestamp() / 60 % 60);
+            Candle {
+                open,
+                high: open + Decimal::ONE,
+                low: open,
+                close: open + Decimal::ONE,
+                volume: dec!(1),
+            }
+        });
+        settings.trades.insert(
+            btc,
+            vec![
+                trade(1, dec!(100.5), dec!(1), Side::Buy),
+                trade(10, dec!(99.5), dec!(0.5), Side::Sell),
+                trade(20, dec!(99), dec!(0.25), Side::Sell),
+                trade(30, dec!(98), dec!(1), Side::Buy),
+                // After the candle the order executes in.
+                trade(70, dec!(90), dec!(10), Side::Sell),
+            ],
+        );
+        let api = Simulate::new(Mock::new(settings), Wallet::new()).with_tick_replay();
+        api.get_candles(key).await.unwrap();
+
+        let order = |order_type, time_in_force| Order {
+            order_id: Uuid::new_v4(),
+            market: btc,
+            side: Side::Buy,
+            size: dec!(1),
+            order_type,
+            reduce_only: false,
+            time_in_force,
+            post_only: false,
+            position_side: None,
+            time,
+            current_price: dec!(101),
+        };
+
+        // Market orders execute at the first trade instead of the close.
+        let info = api
+            .place_order(order(OrderType::Market, TimeInForce::ImmediateOrCancel))
+            .await
+            .unwrap();
+        assert_eq!(info.price, dec!(100.5));
+
+        // Limit orders fill by the size sellers traded at or below their price.
+        let info = api
+            .place_order(order(
+                OrderType::Limit(dec!(99.5)),
+                TimeInForce::GoodTilCancelled,
+            ))
+            .await
+            .unwrap();
+        assert_eq!((info.size, info.price), (dec!(0.75), dec!(99.5)));
+        let info = api
+            .place_order(order(
+                OrderType::Limit(dec!(95)),
+                TimeInForce::GoodTilCancelled,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(info.size, dec!(0));
+    }
 }
@@ -0,0 +1,187 @@
+use super::CandleStore;
+use crate::{Candle, CandleKey, PriceSource, Symbol, Trade};
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::Range,
+    sync::Mutex,
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use rust_decimal::Decimal;
+
+/// Stores candles in memory, mostly useful for tests.
+#[derive(Default)]
+pub struct MemoryCandleStore {
+    #[allow(clippy::type_complexity)]
+    candles: Mutex<HashMap<(Symbol, Duration), BTreeMap<DateTime<Utc>, Option<Candle>>>>,
+    #[allow(clippy::type_complexity)]
+    trades: Mutex<HashMap<(Symbol, DateTime<Utc>), Vec<Trade>>>,
+    prices: Mutex<HashMap<(CandleKey, PriceSource), Option<Decimal>>>,
+}
+
+impl MemoryCandleStore {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+#[async_trait]
+impl CandleStore for MemoryCandleStore {
+    async fn load(
+        &self,
+        key: CandleKey,
+        limit: usize,
+    ) -> Result<Vec<(CandleKey, Option<Candle>)>, sqlx::Error> {
+        let candles = self.candles.lock().unwrap();
+        Ok(candles
+            .get(&(key.market, key.interval))
+            .map(|candles| {
+                candles
+                    .range(key.time..)
+                    .take(limit)
+                    .map(|(&time, &candle)| (CandleKey { time, ..key }, candle))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    async fn save(&self, candles: &[(CandleKey, Option<Candle>)]) -> Result<(), sqlx::Error> {
+        let mut stored = self.candles.lock().unwrap();
+        for (key, candle) in candles {
+            stored
+                .entry((key.market, key.interval))
+                .or_default()
+                .entry(key.time)
+                .or_insert(*candle);
+        }
+
+        Ok(())
+    }
+
+    async fn remove(
+        &self,
+        market: Symbol,
+        interval: Duration,
+        range: Range<DateTime<Utc>>,
+    ) -> Result<(), sqlx::Error> {
+        if let Some(candles) = self.candles.lock().unwrap().get_mut(&(market, interval)) {
+            candles.retain(|time, _| !range.contains(time));
+        }
+
+        Ok(())
+    }
+
+    async fn remove_before(
+        &self,
+        interval: Duration,
+        before: DateTime<Utc>,
+    ) -> Result<u64, sqlx::Error> {
+        let mut removed = 0;
+        for ((_, stored), candles) in self.candles.lock().unwrap().iter_mut() {
+            if *stored == interval {
+                let count = candles.len();
+                candles.retain(|&time, _| time >= before);
+                removed += (count - candles.len()) as u64;
+            }
+        }
+
+        Ok(removed)
+    }
+
+    async fn intervals(&self) -> Result<Vec<Duration>, sqlx::Error> {
+        let candles = self.candles.lock().unwrap();
+        let mut intervals: Vec<_> = candles
+            .iter()
+            .filter(|(_, candles)| !candles.is_empty())
+            .map(|(&(_, interval), _)| interval)
+            .collect();
+        intervals.sort();
+        intervals.dedup();
+
+        Ok(intervals)
+    }
+
+    async fn load_trades(
+        &self,
+        market: Symbol,
+        page: DateTime<Utc>,
+    ) -> Result<Option<Vec<Trade>>, sqlx::Error> {
+        Ok(self.trades.lock().unwrap().get(&(market, page)).cloned())
+    }
+
+    async fn save_trades(
+        &self,
+        market: Symbol,
+        page: DateTime<Utc>,
+        trades: &[Trade],
+    ) -> Result<(), sqlx::Error> {
+        self.trades
+            .lock()
+            .unwrap()
+            .insert((market, page), trades.to_vec());
+
+        Ok(())
+    }
+
+    async fn load_price(
+        &self,
+        key: CandleKey,
+        source: PriceSource,
+    ) -> Result<Option<Option<Decimal>>, sqlx::Error> {
+        Ok(self.prices.lock().unwrap().get(&(key, source)).copied())
+    }
+
+    async fn save_prices(
+        &self,
+        source: PriceSource,
+        prices: &[(CandleKey, Option<Decimal>)],
+    ) -> Result<(), sqlx::Error> {
+        let mut stored = self.prices.lock().unwrap();
+        for &(key, price) in prices {
+            stored.entry((key, source)).or_insert(price);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use rust_decimal_macros::dec;
+
+    #[tokio::test]
+    async fn save_load() {
+        let store = MemoryCandleStore::new();
+        let key = CandleKey {
+            market: Symbol::perp("BTC"),
+            time: Utc.with_ymd_and_hms(2021, 8, 1, 0, 0, 0).unwrap(),
+            interval: Duration::minutes(1),
+        };
+        let candle = Candle {
+            open: dec!(1),
+            high: dec!(2),
+            low: dec!(1),
+            close: dec!(2),
+            volume: dec!(10),
+        };
+        let next_key = CandleKey {
+            time: key.time + key.interval,
+            ..key
+        };
+
+        store
+            .save(&[(key, Some(candle)), (next_key, None)])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            store.load(key, 5000).await.unwrap(),
+            vec![(key, Some(candle)), (next_key, None)]
+        );
+        assert_eq!(store.load(next_key, 5000).await.unwrap().len(), 1);
+        assert_eq!(store.load(key, 1).await.unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,1385 @@
+mod memory;
+mod postgres;
+mod sqlite;
+
+pub use memory::*;
+pub use postgres::*;
+pub use sqlite::*;
+
+use crate::{
+    apis::{Api, ApiError, ExecutionGap, FeeSchedule, Margin, Order, OrderInfo},
+    Asset, Candle, CandleKey, Command, Interval, Markets, Orderbook, PriceSource, Symbol, Trade,
+    Transfer, Wallet,
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use futures_util::{stream, StreamExt};
+use rust_decimal::prelude::*;
+use std::{
+    collections::HashMap,
+    future::Future,
+    ops::Range,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::Duration as StdDuration,
+};
+use uuid::Uuid;
+
+// Number of candles fetched per request.
+const PAGE_SIZE: usize = 5000;
+// Trades are fetched and stored in pages of this many seconds.
+const TRADE_PAGE: i64 = 60 * 60;
+// Number of concurrent requests while backfilling.
+const BACKFILL_CONCURRENCY: usize = 8;
+// Number of attempts of a store operation while the database is locked.
+const LOCKED_ATTEMPTS: u32 = 6;
+// Wait before the first retry, doubled after every attempt.
+const LOCKED_BACKOFF: StdDuration = StdDuration::from_millis(100);
+
+/// A backend to store candles in.
+#[async_trait]
+pub trait CandleStore: Send + Sync {
+    /// Load at most `limit` stored candles in increasing order of time, starting at the key.
+    async fn load(
+        &self,
+        key: CandleKey,
+        limit: usize,
+    ) -> Result<Vec<(CandleKey, Option<Candle>)>, sqlx::Error>;
+    /// Save candles, candles that are already stored are kept.
+    async fn save(&self, candles: &[(CandleKey, Option<Candle>)]) -> Result<(), sqlx::Error>;
+    /// Remove all stored candles of the market and interval in the time range.
+    async fn remove(
+        &self,
+        market: Symbol,
+        interval: Duration,
+        range: Range<DateTime<Utc>>,
+    ) -> Result<(), sqlx::Error>;
+    /// Remove all stored candles of the interval before the time, in all markets.
+    /// Returns the number of removed candles.
+    async fn remove_before(
+        &self,
+        interval: Duration,
+        before: DateTime<Utc>,
+    ) -> Result<u64, sqlx::Error>;
+    /// The intervals of the stored candles.
+    async fn intervals(&self) -> Result<Vec<Duration>, sqlx::Error>;
+    /// Reclaim the space of removed candles.
+    async fn vacuum(&self) -> Result<(), sqlx::Error> {
+        Ok(())
+    }
+    /// Load the stored trades of a market in the page starting at the time,
+    /// None if the page was not stored. Stores without trades never store any.
+    async fn load_trades(
+        &self,
+        _market: Symbol,
+        _page: DateTime<Utc>,
+    ) -> Result<Option<Vec<Trade>>, sqlx::Error> {
+        Ok(None)
+    }
+    /// Save all trades of a market in the page starting at the time, in the order they happened.
+    async fn save_trades(
+        &self,
+        _market: Symbol,
+        _page: DateTime<Utc>,
+        _trades: &[Trade],
+    ) -> Result<(), sqlx::Error> {
+        Ok(())
+    }
+    /// Load the stored price of a market from the source as of the close of the candle of the key,
+    /// None if it was not stored. Stores without prices never store any.
+    async fn load_price(
+        &self,
+        _key: CandleKey,
+        _source: PriceSource,
+    ) -> Result<Option<Option<Decimal>>, sqlx::Error> {
+        Ok(None)
+    }
+    /// Save prices from the source, prices that are already stored are kept.
+    async fn save_prices(
+        &self,
+        _source: PriceSource,
+        _prices: &[(CandleKey, Option<Decimal>)],
+    ) -> Result<(), sqlx::Error> {
+        Ok(())
+    }
+    /// Save candles, replacing candles that are already stored.
+    async fn replace(&self, candles: &[(CandleKey, Option<Candle>)]) -> Result<(), sqlx::Error> {
+        for (key, _) in candles {
+            self.remove(key.market, key.interval, key.time..key.time + key.interval)
+                .await?;
+        }
+        self.save(candles).await
+    }
+}
+
+/// What to do when fetched candles differ from the stored candles of the same time,
+/// e.g. because pagination windows overlap and the venue returned a different close.
+/// Conflicts are recorded in any case, see `Store::conflicts`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnConflict {
+    /// Replace the stored candles with the fetched ones.
+    PreferLatest,
+    /// Fail the request, keeping the stored candles.
+    Error,
+    /// Keep the stored candles.
+    #[default]
+    Flag,
+}
+
+/// A fetched candle that differs from the stored candle with the same key.
+/// Candles that are missing in one of them are gaps, not conflicts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CandleConflict {
+    pub key: CandleKey,
+    pub stored: Candle,
+    pub fetched: Candle,
+    /// How the conflict was resolved.
+    pub resolution: OnConflict,
+    pub detected: DateTime<Utc>,
+}
+
+/// Which stored candles are kept, see `Store::prune`.
+/// By default, all candles are kept.
+#[derive(Debug, Clone, Default)]
+pub struct Retention {
+    /// Candles older than this are removed.
+    pub max_age: Option<Duration>,
+    /// Overrides `max_age` for single intervals,
+    /// e.g. to keep daily candles longer than minute candles.
+    pub interval_max_age: HashMap<Duration, Duration>,
+    /// Candles of other intervals are removed entirely, all intervals are kept if empty.
+    pub intervals: Vec<Duration>,
+}
+
+impl Retention {
+    // The time before which candles of the interval are removed, None if all are kept.
+    fn cutoff(&self, interval: Duration, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        if !self.intervals.is_empty() && !self.intervals.contains(&interval) {
+            return Some(DateTime::<Utc>::MAX_UTC);
+        }
+        self.interval_max_age
+            .get(&interval)
+            .or(self.max_age.as_ref())
+            .map(|&max_age| now - max_age)
+    }
+}
+
+/// Candles to download before a backtest starts.
+#[derive(Debug, Clone)]
+pub struct Backfill {
+    pub symbols: Vec<Symbol>,
+    pub interval: Duration,
+}
+
+/// Problems found in the stored candles of a market.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GapReport {
+    pub symbol: Symbol,
+    pub interval: Duration,
+    /// Time ranges without any stored candles.
+    pub missing: Vec<Range<DateTime<Utc>>>,
+    /// Times of candles that are stored more than once.
+    pub duplicates: Vec<DateTime<Utc>>,
+    /// Times of candles that are not a multiple of the interval.
+    pub misaligned: Vec<DateTime<Utc>>,
+}
+
+impl GapReport {
+    fn new(
+        symbol: Symbol,
+        interval: Duration,
+        range: Range<DateTime<Utc>>,
+        stored: &[DateTime<Utc>],
+    ) -> Self {
+        let mut report = GapReport {
+            symbol,
+            interval,
+            missing: Vec::new(),
+            duplicates: Vec::new(),
+            misaligned: Vec::new(),
+        };
+
+        let mut expected = align(range.start, interval);
+        let mut last = None;
+        for &time in stored {
+            if last == Some(time) {
+                report.duplicates.push(time);
+                continue;
+            }
+            last = Some(time);
+            if time != align(time, interval) {
+                report.misaligned.push(time);
+                continue;
+            }
+            if time > expected {
+                report.missing.push(expected..time);
+            }
+            expected = expected.max(time + interval);
+        }
+        if expected < range.end {
+            report.missing.push(expected..range.end);
+        }
+
+        report
+    }
+
+    /// Returns true if no problems were found.
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.duplicates.is_empty() && self.misaligned.is_empty()
+    }
+
+    // Aligned time ranges that have to be fetched again to fix all problems.
+    fn corrupted(&self) -> Vec<Range<DateTime<Utc>>> {
+        self.missing
+            .iter()
+            .cloned()
+            .chain(self.duplicates.iter().chain(&self.misaligned).map(|&time| {
+                align(time, self.interval)..align(time, self.interval) + self.interval
+            }))
+            .collect()
+    }
+}
+
+fn align(time: DateTime<Utc>, interval: Duration) -> DateTime<Utc> {
+    Interval::Fixed(interval).align(time)
+}
+
+// The primary SQLite result code of an error, see https://www.sqlite.org/rescode.html.
+fn sqlite_code(err: &sqlx::Error) -> Option<i32> {
+    match err {
+        sqlx::Error::Database(err) => err.code()?.parse::<i32>().ok().map(|code| code & 0xff),
+        _ => None,
+    }
+}
+
+// Returns true if the database is used by another process, e.g. a concurrent backtest.
+fn is_locked(err: &sqlx::Error) -> bool {
+    matches!(err, sqlx::Error::PoolTimedOut) || matches!(sqlite_code(err), Some(5 | 6))
+}
+
+// Returns true if the database file is damaged, e.g. by a crashed run.
+fn is_corrupted(err: &sqlx::Error) -> bool {
+    matches!(sqlite_code(err), Some(11 | 26))
+}
+
+// Run a store operation, retrying with exponential backoff while the database is locked.
+async fn retry<T, F, Fut>(mut operation: F) -> Result<T, sqlx::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, sqlx::Error>>,
+{
+    let mut backoff = LOCKED_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        match operation().await {
+            Err(err) if is_locked(&err) && attempt < LOCKED_ATTEMPTS => {
+                tracing::debug!("Candle store is locked, retrying in {:?}.", backoff);
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// The Store API is a middleware that stores fetched data in a candle store,
+/// by default a SQLite database.
+/// This is very useful for backtesting, as backtests are usually run many times.
+///
+/// Operations on a locked database are retried with backoff. If the store stays
+/// unavailable or turns out to be corrupted, candles are fetched from the API
+/// without storing them, see `Store::rebuild` to start over with an empty database.
+pub struct Store<A, C = SqliteCandleStore>
+where
+    A: Api,
+    C: CandleStore,
+{
+    api: A,
+    store: Option<C>,
+    corrupted: AtomicBool,
+    on_conflict: OnConflict,
+    conflicts: Mutex<Vec<CandleConflict>>,
+    retention: Retention,
+}
+
+impl<A> Store<A>
+where
+    A: Api,
+{
+    /// Store candles in the SQLite database ./.store/{API name}.db.
+    pub async fn new(api: A) -> Self {
+        match retry(|| SqliteCandleStore::new(A::NAME)).await {
+            Ok(store) => Store::with_store(api, store),
+            Err(err) => {
+                tracing::warn!(
+                    "Could not open the candle store ({}), fetching candles without storing them.",
+                    err
+                );
+                if is_corrupted(&err) {
+                    tracing::warn!(
+                        "The candle store is corrupted, use `Store::rebuild` to replace it."
+                    );
+                }
+                Store::pass_through(api)
+            }
+        }
+    }
+
+    /// Move the SQLite database aside and store candles in a new, empty one.
+    /// Use this if the database was corrupted, e.g. by a crashed run.
+    pub async fn rebuild(api: A) -> Result<Self, ApiError> {
+        let store = SqliteCandleStore::rebuild(A::NAME).await.map_err(|err| {
+            tracing::error!("Could not rebuild the candle store: {}", err);
+            ApiError::Store
+        })?;
+        Ok(Store::with_store(api, store))
+    }
+}
+
+impl<A, C> Store<A, C>
+where
+    A: Api,
+    C: CandleStore,
+{
+    /// Store candles in a custom candle store.
+    pub fn with_store(api: A, store: C) -> Self {
+        Store {
+            api,
+            store: Some(store),
+            corrupted: AtomicBool::new(false),
+            on_conflict: OnConflict::default(),
+            conflicts: Mutex::new(Vec::new()),
+            retention: Retention::default(),
+        }
+    }
+
+    /// Fetch candles from the API without storing them.
+    pub fn pass_through(api: A) -> Self {
+        Store {
+            api,
+            store: None,
+            corrupted: AtomicBool::new(false),
+            on_conflict: OnConflict::default(),
+            conflicts: Mutex::new(Vec::new()),
+            retention: Retention::default(),
+        }
+    }
+
+    /// How fetched candles that differ from the stored ones are handled.
+    pub fn with_conflict_policy(mut self, on_conflict: OnConflict) -> Self {
+        self.on_conflict = on_conflict;
+        self
+    }
+
+    /// Which stored candles are kept when pruning the store.
+    pub fn with_retention(mut self, retention: Retention) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    /// The conflicts between fetched and stored candles found so far.
+    pub fn conflicts(&self) -> Vec<CandleConflict> {
+        self.conflicts.lock().unwrap().clone()
+    }
+
+    /// Returns false if candles are fetched without storing them.
+    pub fn is_available(&self) -> bool {
+        self.backend().is_some()
+    }
+
+    /// A SHA-256 digest of the stored candles of the markets and interval in the time range,
+    /// which only changes when these candles change.
+    pub async fn digest(
+        &self,
+        symbols: &[Symbol],
+        range: Range<DateTime<Utc>>,
+        interval: Duration,
+    ) -> Result<String, ApiError> {
+        let mut hash = hmac_sha256::Hash::new();
+        for &market in symbols {
+            let mut key = CandleKey {
+                market,
+                time: range.start,
+                interval,
+            };
+            loop {
+                let data = self.load(key, PAGE_SIZE).await?;
+                let last = match data.last() {
+                    Some((last, _)) => last.time,
+                    None => break,
+                };
+                for (key, candle) in data.iter().filter(|(key, _)| key.time < range.end) {
+                    let json = serde_json::to_vec(&(key.market, key.time, candle))
+                        .expect("candles can be serialized");
+                    hash.update(&json);
+                }
+                if last >= range.end {
+                    break;
+                }
+                key.time = last + Duration::seconds(1);
+            }
+        }
+        Ok(crate::cache::to_hex(&hash.finalize()))
+    }
+
+    /// Download all candles in the time range that are not stored yet,
+    /// fetching multiple pages concurrently.
+    /// Returns the number of downloaded pages.
+    pub async fn backfill(
+        &self,
+        symbols: &[Symbol],
+        range: Range<DateTime<Utc>>,
+        interval: Duration,
+    ) -> Result<usize, ApiError> {
+        if !self.is_available() {
+            tracing::warn!("Skipping the backfill, the candle store is not available.");
+            return Ok(0);
+        }
+
+        let mut keys = Vec::new();
+        for &market in symbols {
+            let mut time = range.start;
+            while time < range.end {
+                keys.push(CandleKey {
+                    market,
+                    time,
+                    interval,
+                });
+                time += interval * PAGE_SIZE as i32;
+            }
+        }
+
+        // Find pages that are not completely stored yet.
+        let mut missing = Vec::new();
+        for key in keys {
+            let expected = ((range.end - key.time).num_seconds() / interval.num_seconds())
+                .min(PAGE_SIZE as i64) as usize;
+            if self.stored(key, expected).await? < expected {
+                missing.push(key);
+            }
+        }
+
+        let total = missing.len();
+        tracing::info!("Backfilling {} pages of candles.", total);
+
+        let mut fetches = stream::iter(missing)
+            .map(|key| async move {
+                let candles = self.api.get_candles(key).await?;
+                self.save_fetched(&candles).await?;
+                Ok::<(), ApiError>(())
+            })
+            .buffer_unordered(BACKFILL_CONCURRENCY);
+
+        let mut done = 0;
+        while let Some(result) = fetches.next().await {
+            result?;
+            done += 1;
+            tracing::info!("Backfilled {}/{} pages of candles.", done, total);
+        }
+
+        Ok(total)
+    }
+
+    /// Check the stored candles of a market in the time range for gaps,
+    /// duplicate keys and timestamps that are not aligned to the interval.
+    pub async fn verify(
+        &self,
+        symbol: Symbol,
+        range: Range<DateTime<Utc>>,
+        interval: Duration,
+    ) -> Result<GapReport, ApiError> {
+        let mut stored = Vec::new();
+        let mut key = CandleKey {
+            market: symbol,
+            time: range.start,
+            interval,
+        };
+        loop {
+            let data = self.load(key, PAGE_SIZE).await?;
+            let last = match data.last() {
+                Some((last, _)) => last.time,
+                None => break,
+            };
+            stored.extend(
+                data.into_iter()
+                    .map(|(key, _)| key.time)
+                    .filter(|&time| time < range.end),
+            );
+            if last >= range.end {
+                break;
+            }
+            key.time = last + Duration::seconds(1);
+        }
+
+        Ok(GapReport::new(symbol, interval, range, &stored))
+    }
+
+    /// Remove the corrupted candles found by `verify` and fetch them again from the API.
+    pub async fn repair(&self, report: &GapReport) -> Result<(), ApiError> {
+        for range in report.corrupted() {
+            tracing::info!(
+                "Refetching {} candles from {} to {}.",
+                report.symbol,
+                range.start,
+                range.end
+            );
+            let store = self.backend().ok_or(ApiError::Store)?;
+            retry(|| store.remove(report.symbol, report.interval, range.clone()))
+                .await
+                .map_err(|err| self.failed(&err))?;
+
+            let mut key = CandleKey {
+                market: report.symbol,
+                time: range.start,
+                interval: report.interval,
+            };
+            while key.time < range.end {
+                let candles = self.api.get_candles(key).await?;
+                let last = match candles.last() {
+                    Some((last, _)) => last.time,
+                    None => break,
+                };
+                self.save(&candles).await?;
+                key.time = last + key.interval;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remove the stored candles that are not retained at the given time.
+    /// Returns the number of removed candles.
+    pub async fn prune(&self, now: DateTime<Utc>) -> Result<u64, ApiError> {
+        let store = self.backend().ok_or(ApiError::Store)?;
+        let intervals = retry(|| store.intervals())
+            .await
+            .map_err(|err| self.failed(&err))?;
+
+        let mut removed = 0;
+        for interval in intervals {
+            if let Some(before) = self.retention.cutoff(interval, now) {
+                removed += retry(|| store.remove_before(interval, before))
+                    .await
+                    .map_err(|err| self.failed(&err))?;
+            }
+        }
+        if removed > 0 {
+            tracing::info!("Pruned {} candles from the candle store.", removed);
+        }
+
+        Ok(removed)
+    }
+
+    /// Prune the store and reclaim the space of the removed candles.
+    /// This may take a while on large databases, as SQLite rewrites the whole file.
+    /// Returns the number of removed candles.
+    pub async fn vacuum(&self) -> Result<u64, ApiError> {
+        let removed = self.prune(Utc::now()).await?;
+        let store = self.backend().ok_or(ApiError::Store)?;
+        retry(|| store.vacuum())
+            .await
+            .map_err(|err| self.failed(&err))?;
+
+        Ok(removed)
+    }
+
+    // The number of consecutive stored candles starting at the key, up to the limit.
+    async fn stored(&self, key: CandleKey, limit: usize) -> Result<usize, ApiError> {
+        let data = self.load(key, limit).await?;
+        let mut next_key = key;
+        let mut count = 0;
+        for (curr_key, _) in data {
+            if curr_key != next_key {
+                break;
+            }
+            count += 1;
+            next_key.time += next_key.interval;
+        }
+        Ok(count)
+    }
+
+    // The candle store, None if it is not available anymore.
+    fn backend(&self) -> Option<&C> {
+        self.store
+            .as_ref()
+            .filter(|_| !self.corrupted.load(Ordering::Relaxed))
+    }
+
+    async fn load(
+        &self,
+        key: CandleKey,
+        limit: usize,
+    ) -> Result<Vec<(CandleKey, Option<Candle>)>, ApiError> {
+        let store = self.backend().ok_or(ApiError::Store)?;
+        retry(|| store.load(key, limit))
+            .await
+            .map_err(|err| self.failed(&err))
+    }
+
+    async fn save(&self, candles: &[(CandleKey, Option<Candle>)]) -> Result<(), ApiError> {
+        let store = self.backend().ok_or(ApiError::Store)?;
+        retry(|| store.save(candles))
+            .await
+            .map_err(|err| self.failed(&err))
+    }
+
+    // The trades of a market in the page starting at the time, from the store if it was stored.
+    // Pages that ended are stored once fetched, None if the API does not provide trades.
+    async fn page_trades(
+        &self,
+        market: Symbol,
+        page: DateTime<Utc>,
+    ) -> Result<Option<Vec<Trade>>, ApiError> {
+        if let Some(store) = self.backend() {
+            match retry(|| store.load_trades(market, page)).await {
+                Ok(Some(trades)) => return Ok(Some(trades)),
+                Ok(None) => {}
+                Err(err) => {
+                    self.failed(&err);
+                }
+            }
+        }
+
+        let end = page + Duration::seconds(TRADE_PAGE);
+        let trades = self.api.get_trades(market, page..end).await?;
+        if let (Some(trades), Some(store)) = (&trades, self.backend()) {
+            // The trades of the current page are not complete yet.
+            if end <= Utc::now() {
+                if let Err(err) = retry(|| store.save_trades(market, page, trades)).await {
+                    self.failed(&err);
+                }
+            }
+        }
+        Ok(trades)
+    }
+
+    // Save candles fetched from the API, checking them against the stored candles
+    // of the same keys first and resolving conflicts according to the policy.
+    async fn save_fetched(&self, candles: &[(CandleKey, Option<Candle>)]) -> Result<(), ApiError> {
+        let (Some((first, _)), Some((last, _))) = (candles.first(), candles.last()) else {
+            return Ok(());
+        };
+        let stored: HashMap<_, _> = self
+            .load(*first, PAGE_SIZE)
+            .await?
+            .into_iter()
+            .filter(|(key, _)| key.time <= last.time)
+            .filter_map(|(key, candle)| Some((key.time, candle?)))
+            .collect();
+
+        let detected = Utc::now();
+        let conflicts: Vec<_> = candles
+            .iter()
+            .filter_map(|&(key, fetched)| {
+                let (stored, fetched) = (*stored.get(&key.time)?, fetched?);
+                (stored != fetched).then_some(CandleConflict {
+                    key,
+                    stored,
+                    fetched,
+                    resolution: self.on_conflict,
+                    detected,
+                })
+            })
+            .collect();
+
+        if !conflicts.is_empty() {
+            tracing::warn!(
+                "{} fetched candles of {} from {} conflict with the stored candles ({:?}).",
+                conflicts.len(),
+                first.market,
+                conflicts[0].key.time,
+                self.on_conflict
+            );
+            let replaced: Vec<_> = conflicts
+                .iter()
+                .map(|conflict| (conflict.key, Some(conflict.fetched)))
+                .collect();
+            self.conflicts.lock().unwrap().extend(conflicts);
+
+            match self.on_conflict {
+                OnConflict::PreferLatest => {
+                    let store = self.backend().ok_or(ApiError::Store)?;
+                    retry(|| store.replace(&replaced))
+                        .await
+                        .map_err(|err| self.failed(&err))?;
+                }
+                OnConflict::Error => return Err(ApiError::CandleConflict),
+                OnConflict::Flag => {}
+            }
+        }
+
+        self.save(candles).await
+    }
+
+    // Log a failed store operation, a corrupted store is not used anymore.
+    fn failed(&self, err: &sqlx::Error) -> ApiError {
+        if is_corrupted(err) {
+            if !self.corrupted.swap(true, Ordering::Relaxed) {
+                tracing::error!(
+                    "The candle store is corrupted ({}), fetching candles without storing them. \
+                    Use `Store::rebuild` to replace it.",
+                    err
+                );
+            }
+        } else {
+            tracing::warn!("Candle store error: {}", err);
+        }
+        ApiError::Store
+    }
+}
+
+#[async_trait]
+impl<A: Api, C: CandleStore> Api for Store<A, C> {
+    const NAME: &'static str = A::NAME;
+    const LIVE_TRADING_ENABLED: bool = A::LIVE_TRADING_ENABLED;
+
+    async fn get_candles(
+        &self,
+        key: CandleKey,
+    ) -> Result<Vec<(CandleKey, Option<Candle>)>, ApiError> {
+        // Fall back to fetching from the API if the store is not available.
+        let data = self.load(key, PAGE_SIZE).await.unwrap_or_default();
+
+        // Only use the stored candles up to the first missing one.
+        let mut out = Vec::new();
+        let mut next_key = key;
+        for (curr_key, candle) in data {
+            if curr_key != next_key {
+                break;
+            }
+            out.push((curr_key, candle));
+            next_key.time += next_key.interval;
+        }
+
+        tracing::warn!("out length: {}", out.len());
+
+        if out.is_empty() {
+            let candles = self.api.get_candles(key).await?;
+            tracing::trace!("Got candles!");
+
+            if self.is_available() {
+                if let Err(err @ ApiError::CandleConflict) = self.save_fetched(&candles).await {
+                    return Err(err);
+                }
+            }
+
+            Ok(candles)
+        } else {
+            Ok(out)
+        }
+    }
+
+    // Trades are fetched and stored in whole pages, so any range can be answered from the store.
+    async fn get_trades(
+        &self,
+        market: Symbol,
+        range: Range<DateTime<Utc>>,
+    ) -> Result<Option<Vec<Trade>>, ApiError> {
+        let mut trades = Vec::new();
+        let mut page = align(range.start, Duration::seconds(TRADE_PAGE));
+        while page < range.end {
+            let Some(page_trades) = self.page_trades(market, page).await? else {
+                return Ok(None);
+            };
+            trades.extend(
+                page_trades
+                    .into_iter()
+                    .filter(|trade| range.contains(&trade.time)),
+            );
+            page += Duration::seconds(TRADE_PAGE);
+        }
+        Ok(Some(trades))
+    }
+
+    async fn place_order(&self, order: Order) -> Result<OrderInfo, ApiError> {
+        self.api.place_order(order).await
+    }
+
+    async fn place_orders(&self, orders: Vec<Order>) -> Vec<Result<OrderInfo, ApiError>> {
+        self.api.place_orders(orders).await
+    }
+
+    async fn order_status(&self, market: Symbol, order_id: Uuid) -> Result<OrderInfo, ApiError> {
+        self.api.order_status(market, order_id).await
+    }
+
+    async fn cancel_order(&self, market: Symbol, order_id: Uuid) -> Result<OrderInfo, ApiError> {
+        self.api.cancel_order(market, order_id).await
+    }
+
+    async fn amend_order(
+        &self,
+        market: Symbol,
+        order_id: Uuid,
+        price: Decimal,
+        size: Decimal,
+    ) -> Result<OrderInfo, ApiError> {
+        self.api.amend_order(market, order_id, price, size).await
+    }
+    /*
+    async fn order_update(&self, asset: Asset) -> Pin<Box<dyn Stream<Item = OrderUpdate>>> {
+        todo!()
+    }
+    */
+    fn format_market(&self, symbol: Symbol) -> String {
+        self.api.format_market(symbol)
+    }
+
+    async fn update_wallet(&self, wallet: &mut Wallet) -> Result<(), ApiError> {
+        self.api.update_wallet(wallet).await
+    }
+
+    async fn update_markets(&self, markets: &mut Markets) -> Result<(), ApiError> {
+        self.api.update_markets(markets).await
+    }
+
+    fn quote_asset(&self) -> Asset {
+        self.api.quote_asset()
+    }
+
+    async fn order_fee(&self) -> Decimal {
+        self.api.order_fee().await
+    }
+
+    async fn get_orderbook(
+        &self,
+        market: Symbol,
+        time: DateTime<Utc>,
+    ) -> Result<Option<Orderbook>, ApiError> {
+        self.api.get_orderbook(market, time).await
+    }
+
+    async fn get_price(
+        &self,
+        key: CandleKey,
+        source: PriceSource,
+    ) -> Result<Option<Decimal>, ApiError> {
+        // Mid prices of the current order book can not be stored for later.
+        if !matches!(source, PriceSource::Mark | PriceSource::Index) {
+            return self.api.get_price(key, source).await;
+        }
+        if let Some(store) = self.backend() {
+            match retry(|| store.load_price(key, source)).await {
+                Ok(Some(price)) => return Ok(price),
+                Ok(None) => {}
+                Err(err) => {
+                    self.failed(&err);
+                }
+            }
+        }
+
+        // Fetch and store a whole page of prices, like candles.
+        let prices = self.api.get_prices(key, source).await?;
+        if let Some(store) = self.backend() {
+            // Prices of candles that did not close yet may still change.
+            let closed: Vec<_> = prices
+                .iter()
+                .copied()
+                .filter(|(key, _)| key.time + key.interval <= Utc::now())
+                .collect();
+            if let Err(err) = retry(|| store.save_prices(source, &closed)).await {
+                self.failed(&err);
+            }
+        }
+        Ok(prices
+            .into_iter()
+            .find(|(curr_key, _)| *curr_key == key)
+            .and_then(|(_, price)| price))
+    }
+
+    async fn fee_schedule(&self) -> FeeSchedule {
+        self.api.fee_schedule().await
+    }
+
+    async fn borrow_rate(&self, market: Symbol) -> Option<Decimal> {
+        self.api.borrow_rate(market).await
+    }
+
+    async fn borrow_costs(&self, time: DateTime<Utc>) -> Result<Decimal, ApiError> {
+        self.api.borrow_costs(time).await
+    }
+
+    async fn convert_dust(&self, assets: Vec<Asset>) -> Result<(), ApiError> {
+        self.api.convert_dust(assets).await
+    }
+
+    async fn transfer(&self, transfer: &Transfer) -> Result<(), ApiError> {
+        self.api.transfer(transfer).await
+    }
+
+    async fn save_state(&self, strategy_name: &str, state: String) -> Result<(), ApiError> {
+        self.api.save_state(strategy_name, state).await
+    }
+
+    async fn load_state(&self, strategy_name: &str) -> Result<Option<String>, ApiError> {
+        self.api.load_state(strategy_name).await
+    }
+
+    fn margin(&self) -> Option<Margin> {
+        self.api.margin()
+    }
+
+    async fn shutdown(&self) {
+        self.api.shutdown().await
+    }
+
+    fn execution_gap(&self) -> Option<ExecutionGap> {
+        self.api.execution_gap()
+    }
+
+    async fn commands(&self) -> Vec<Command> {
+        self.api.commands().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::{Mock, MockSettings};
+    use crate::Side;
+    use chrono::TimeZone;
+    use rust_decimal_macros::dec;
+    use std::sync::atomic::AtomicUsize;
+
+    // A store that is locked for a number of operations, and broken after that if `broken` is set.
+    struct FlakyStore {
+        locked: AtomicUsize,
+        broken: bool,
+        store: MemoryCandleStore,
+    }
+
+    impl FlakyStore {
+        fn check(&self) -> Result<(), sqlx::Error> {
+            if self
+                .locked
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+                .is_ok()
+            {
+                Err(sqlx::Error::PoolTimedOut)
+            } else if self.broken {
+                Err(sqlx::Error::Protocol("broken".into()))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    #[async_trait]
+    impl CandleStore for FlakyStore {
+        async fn load(
+            &self,
+            key: CandleKey,
+            limit: usize,
+        ) -> Result<Vec<(CandleKey, Option<Candle>)>, sqlx::Error> {
+            self.check()?;
+            self.store.load(key, limit).await
+        }
+
+        async fn save(&self, candles: &[(CandleKey, Option<Candle>)]) -> Result<(), sqlx::Error> {
+            self.check()?;
+            self.store.save(candles).await
+        }
+
+        async fn remove(
+            &self,
+            market: Symbol,
+            interval: Duration,
+            range: Range<DateTime<Utc>>,
+        ) -> Result<(), sqlx::Error> {
+            self.check()?;
+            self.store.remove(market, interval, range).await
+        }
+
+        async fn remove_before(
+            &self,
+            interval: Duration,
+            before: DateTime<Utc>,
+        ) -> Result<u64, sqlx::Error> {
+            self.check()?;
+            self.store.remove_before(interval, before).await
+        }
+
+        async fn intervals(&self) -> Result<Vec<Duration>, sqlx::Error> {
+            self.check()?;
+            self.store.intervals().await
+        }
+    }
+
+    #[test]
+    fn gap_report() {
+        let symbol = Symbol::perp("BTC");
+        let interval = Duration::minutes(1);
+        let start = Utc.with_ymd_and_hms(2021, 8, 1, 0, 0, 0).unwrap();
+        let minute = |i: i64| start + interval * i as i32;
+
+        let stored = [
+            minute(0),
+            minute(1),
+            minute(1),
+            minute(4),
+            minute(4) + Duration::seconds(30),
+            minute(5),
+        ];
+        let report = GapReport::new(symbol, interval, start..minute(8), &stored);
+
+        assert!(!report.is_ok());
+        assert_eq!(
+            report.missing,
+            vec![minute(2)..minute(4), minute(6)..minute(8)]
+        );
+        assert_eq!(report.duplicates, vec![minute(1)]);
+        assert_eq!(report.misaligned, vec![minute(4) + Duration::seconds(30)]);
+        assert_eq!(
+            report.corrupted(),
+            vec![
+                minute(2)..minute(4),
+                minute(6)..minute(8),
+                minute(1)..minute(2),
+                minute(4)..minute(5)
+            ]
+        );
+
+        let stored: Vec<_> = (0..8).map(minute).collect();
+        assert!(GapReport::new(symbol, interval, start..minute(8), &stored).is_ok());
+    }
+
+    #[tokio::test]
+    async fn retention() {
+        let candle = Candle {
+            open: dec!(1),
+            high: dec!(1),
+            low: dec!(1),
+            close: dec!(1),
+            volume: dec!(1),
+        };
+        let start = Utc.with_ymd_and_hms(2021, 8, 1, 0, 0, 0).unwrap();
+        let now = start + Duration::days(2);
+        let store = Store::with_store(
+            Mock::new(MockSettings::new(move |_key: CandleKey| candle)),
+            MemoryCandleStore::new(),
+        )
+        .with_retention(Retention {
+            max_age: Some(Duration::days(1)),
+            interval_max_age: HashMap::from([(Duration::hours(1), Duration::days(30))]),
+            intervals: vec![Duration::minutes(1), Duration::hours(1)],
+        });
+
+        for interval in [
+            Duration::minutes(1),
+            Duration::minutes(5),
+            Duration::hours(1),
+        ] {
+            let candles: Vec<_> = (0..(Duration::days(2).num_seconds() / interval.num_seconds()))
+                .map(|i| {
+                    let key = CandleKey {
+                        market: Symbol::perp("BTC"),
+                        time: start + interval * i as i32,
+                        interval,
+                    };
+                    (key, Some(candle))
+                })
+                .collect();
+            store.save(&candles).await.unwrap();
+        }
+
+        // Minute candles older than a day and all five minute candles are removed.
+        assert_eq!(store.prune(now).await.unwrap(), 24 * 60 + 2 * 24 * 12);
+        assert_eq!(store.prune(now).await.unwrap(), 0);
+        let intervals = store.store.as_ref().unwrap().intervals().await.unwrap();
+        assert_eq!(intervals, vec![Duration::minutes(1), Duration::hours(1)]);
+
+        let key = |interval| CandleKey {
+            market: Symbol::perp("BTC"),
+            time: start,
+            interval,
+        };
+        assert_eq!(
+            store
+                .load(key(Duration::hours(1)), PAGE_SIZE)
+                .await
+                .unwrap()
+                .len(),
+            48
+        );
+        let minutes = store
+            .load(key(Duration::minutes(1)), PAGE_SIZE)
+            .await
+            .unwrap();
+        assert_eq!(minutes.len(), 24 * 60);
+        assert_eq!(minutes[0].0.time, start + Duration::days(1));
+
+        // Vacuuming prunes at the current time, so all remaining candles are too old.
+        assert_eq!(store.vacuum().await.unwrap(), 24 * 60 + 48);
+    }
+
+    #[tokio::test]
+    async fn digest_of_range() {
+        let candle = |close| Candle {
+            open: dec!(1),
+            high: dec!(1),
+            low: dec!(1),
+            close,
+            volume: dec!(1),
+        };
+        let (btc, eth) = (Symbol::perp("BTC"), Symbol::perp("ETH"));
+        let interval = Duration::minutes(1);
+        let start = Utc.with_ymd_and_hms(2021, 8, 1, 0, 0, 0).unwrap();
+        let range = start..start + interval * 10;
+        let key = |market, minute| CandleKey {
+            market,
+            time: start + interval * minute,
+            interval,
+        };
+        let store = Store::with_store(
+            Mock::new(MockSettings::new(move |_key: CandleKey| candle(dec!(1)))),
+            MemoryCandleStore::new(),
+        );
+        store
+            .backfill(&[btc], range.clone(), interval)
+            .await
+            .unwrap();
+        let digest = store.digest(&[btc], range.clone(), interval).await.unwrap();
+
+        // Candles of other markets or after the range don't change the digest.
+        store
+            .save(&[
+                (key(eth, 0), Some(candle(dec!(1)))),
+                (key(btc, 10), Some(candle(dec!(1)))),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(
+            store.digest(&[btc], range.clone(), interval).await.unwrap(),
+            digest
+        );
+
+        store
+            .backend()
+            .unwrap()
+            .replace(&[(key(btc, 5), Some(candle(dec!(2))))])
+            .await
+            .unwrap();
+        assert_ne!(store.digest(&[btc], range, interval).await.unwrap(), digest);
+    }
+
+    #[tokio::test]
+    async fn store_prices() {
+        let btc = Symbol::perp("BTC");
+        let key = CandleKey {
+            market: btc,
+            time: Utc.with_ymd_and_hms(2021, 8, 1, 0, 0, 0).unwrap(),
+            interval: Duration::minutes(1),
+        };
+        let mut settings = MockSettings::new(|_key: CandleKey| Candle {
+            open: dec!(1),
+            high: dec!(1),
+            low: dec!(1),
+            close: dec!(1),
+            volume: dec!(1),
+        });
+        settings.prices.insert((btc, PriceSource::Mark), dec!(2));
+        let store = Store::with_store(Mock::new(settings), MemoryCandleStore::new());
+
+        assert_eq!(
+            store.get_price(key, PriceSource::Mark).await.unwrap(),
+            Some(dec!(2))
+        );
+        assert_eq!(
+            store
+                .backend()
+                .unwrap()
+                .load_price(key, PriceSource::Mark)
+                .await
+                .unwrap(),
+            Some(Some(dec!(2)))
+        );
+        // Prices the API does not provide are stored as unknown.
+        assert_eq!(
+            store.get_price(key, PriceSource::Index).await.unwrap(),
+            None
+        );
+        assert_eq!(
+            store
+                .backend()
+                .unwrap()
+                .load_price(key, PriceSource::Index)
+                .await
+                .unwrap(),
+            Some(None)
+        );
+    }
+
+    #[tokio::test]
+    async fn unavailable_store() {
+        let api = || {
+            Mock::new(MockSettings::new(|_key: CandleKey| Candle {
+                open: dec!(1),
+                high: dec!(1),
+                low: dec!(1),
+                close: dec!(1),
+                volume: dec!(1),
+            }))
+        };
+        let key = CandleKey {
+            market: Symbol::perp("BTC"),
+            time: Utc.with_ymd_and_hms(2021, 8, 1, 0, 0, 0).unwrap(),
+            interval: Duration::minutes(1),
+        };
+
+        // Locked operations are retried until the lock is released.
+        let locked = Store::with_store(
+            api(),
+            FlakyStore {
+                locked: AtomicUsize::new(3),
+                broken: false,
+                store: MemoryCandleStore::new(),
+            },
+        );
+        assert_eq!(locked.get_candles(key).await.unwrap().len(), 1);
+        assert_eq!(locked.stored(key, 1).await.unwrap(), 1);
+
+        // Candles are fetched from the API if the store keeps failing.
+        let broken = Store::with_store(
+            api(),
+            FlakyStore {
+                locked: AtomicUsize::new(0),
+                broken: true,
+                store: MemoryCandleStore::new(),
+            },
+        );
+        assert_eq!(broken.get_candles(key).await.unwrap().len(), 1);
+        assert!(broken.stored(key, 1).await.is_err());
+
+        let pass_through: Store<_, MemoryCandleStore> = Store::pass_through(api());
+        assert!(!pass_through.is_available());
+        assert_eq!(pass_through.get_candles(key).await.unwrap().len(), 1);
+        assert_eq!(
+            pass_through
+                .backfill(
+                    &[key.market],
+                    key.time..key.time + Duration::hours(1),
+                    key.interval
+                )
+                .await
+                .unwrap(),
+            0
+        );
+    }
+
+    #[tokio::test]
+    async fn candle_conflicts() {
+        let candle = |close| Candle {
+            open: dec!(1),
+            high: dec!(3),
+            low: dec!(1),
+            close,
+            volume: dec!(1),
+        };
+        let key = CandleKey {
+            market: Symbol::perp("BTC"),
+            time: Utc.with_ymd_and_hms(2021, 8, 1, 0, 0, 0).unwrap(),
+            interval: Duration::minutes(1),
+        };
+        let next_key = CandleKey {
+            time: key.time + key.interval,
+            ..key
+        };
+        let store = |on_conflict| async move {
+            let store = Store::with_store(
+                Mock::new(MockSettings::new(move |_key: CandleKey| candle(dec!(1)))),
+                MemoryCandleStore::new(),
+            )
+            .with_conflict_policy(on_conflict);
+            store.save(&[(key, Some(candle(dec!(2))))]).await.unwrap();
+            store
+        };
+        // The fetched window overlaps the stored candle with a different close.
+        let fetched = [
+            (key, Some(candle(dec!(1)))),
+            (next_key, Some(candle(dec!(1)))),
+        ];
+
+        let flag = store(OnConflict::Flag).await;
+        flag.save_fetched(&fetched).await.unwrap();
+        assert_eq!(flag.conflicts().len(), 1);
+        assert_eq!(flag.conflicts()[0].stored.close, dec!(2));
+        assert_eq!(flag.load(key, 2).await.unwrap()[0].1, Some(candle(dec!(2))));
+        assert_eq!(flag.stored(key, 2).await.unwrap(), 2);
+
+        let latest = store(OnConflict::PreferLatest).await;
+        latest.save_fetched(&fetched).await.unwrap();
+        assert_eq!(latest.load(key, 2).await.unwrap(), fetched.to_vec());
+
+        let error = store(OnConflict::Error).await;
+        assert!(matches!(
+            error.save_fetched(&fetched).await,
+            Err(ApiError::CandleConflict)
+        ));
+        assert_eq!(error.conflicts()[0].resolution, OnConflict::Error);
+        assert_eq!(error.stored(key, 2).await.unwrap(), 1);
+
+        // Consistent candles are no conflicts.
+        flag.save_fetched(&fetched[1..]).await.unwrap();
+        assert_eq!(flag.conflicts().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn stored_trades() {
+        let market = Symbol::perp("BTC");
+        let start = Utc.with_ymd_and_hms(2021, 8, 1, 0, 0, 0).unwrap();
+        let trade = |minutes: i64, price| Trade {
+            time: start + Duration::minutes(minutes),
+            price,
+            size: dec!(1),
+            side: Side::Buy,
+        };
+        let trades = vec![
+            trade(10, dec!(100)),
+            trade(50, dec!(101)),
+            trade(70, dec!(102)),
+        ];
+        let mut settings = MockSettings::new(|_key: CandleKey| Candle {
+            open: dec!(1),
+            high: dec!(1),
+            low: dec!(1),
+            close: dec!(1),
+            volume: dec!(1),
+        });
+        settings.trades.insert(market, trades.clone());
+        let store = Store::with_store(Mock::new(settings), MemoryCandleStore::new());
+
+        let range = start + Duration::minutes(30)..start + Duration::minutes(90);
+        assert_eq!(
+            store.get_trades(market, range).await.unwrap(),
+            Some(trades[1..].to_vec())
+        );
+        // Both pages are stored in full, even though only a part of the first was asked for.
+        let backend = store.store.as_ref().unwrap();
+        assert_eq!(
+            backend.load_trades(market, start).await.unwrap(),
+            Some(trades[..2].to_vec())
+        );
+        assert_eq!(
+            backend
+                .load_trades(market, start + Duration::hours(1))
+                .await
+                .unwrap(),
+            Some(trades[2..].to_vec())
+        );
+
+        // Markets without trades are not stored.
+        let other = Symbol::perp("ETH");
+        assert_eq!(
+            store
+                .get_trades(other, start..start + Duration::hours(1))
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(backend.load_trades(other, start).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,380 @@
+use super::CandleStore;
+use crate::{Candle, CandleKey, PriceSource, Side, Symbol, Trade};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use rust_decimal::Decimal;
+use sqlx::{postgres::PgPoolOptions, PgPool};
+use std::ops::Range;
+
+#[allow(clippy::type_complexity)]
+type Row = (
+    String,
+    i64,
+    i64,
+    Option<Decimal>,
+    Option<Decimal>,
+    Option<Decimal>,
+    Option<Decimal>,
+    Option<Decimal>,
+);
+
+/// Stores candles in a Postgres database,
+/// which allows sharing a candle cache between machines.
+pub struct PostgresCandleStore {
+    pool: PgPool,
+}
+
+impl PostgresCandleStore {
+    /// Connect to the database and create the candles table if it doesn't exist.
+    pub async fn connect(url: &str) -> Result<Self, sqlx::Error> {
+        let pool = PgPoolOptions::new().connect(url).await?;
+
+        sqlx::query(
+            "
+                CREATE TABLE IF NOT EXISTS candles (
+                    market TEXT,
+                    timestamp BIGINT,
+                    interval BIGINT,
+                    open NUMERIC,
+                    high NUMERIC,
+                    low NUMERIC,
+                    close NUMERIC,
+                    volume NUMERIC,
+                    PRIMARY KEY(market, timestamp, interval)
+                )
+            ",
+        )
+        .execute(&pool)
+        .await?;
+
+        // Trades are stored in whole pages, which are marked once all their trades are stored.
+        sqlx::query(
+            "
+                CREATE TABLE IF NOT EXISTS trades (
+                    market TEXT,
+                    page BIGINT,
+                    seq BIGINT,
+                    timestamp BIGINT,
+                    price NUMERIC,
+                    size NUMERIC,
+                    buy BOOLEAN,
+                    PRIMARY KEY(market, page, seq)
+                )
+            ",
+        )
+        .execute(&pool)
+        .await?;
+        sqlx::query(
+            "
+                CREATE TABLE IF NOT EXISTS trade_pages (
+                    market TEXT,
+                    page BIGINT,
+                    PRIMARY KEY(market, page)
+                )
+            ",
+        )
+        .execute(&pool)
+        .await?;
+
+        // Prices of other sources than the candles, e.g. mark prices, stored per candle.
+        sqlx::query(
+            "
+                CREATE TABLE IF NOT EXISTS prices (
+                    market TEXT,
+                    source TEXT,
+                    timestamp BIGINT,
+                    interval BIGINT,
+                    price NUMERIC,
+                    PRIMARY KEY(market, source, timestamp, interval)
+                )
+            ",
+        )
+        .execute(&pool)
+        .await?;
+
+        Ok(PostgresCandleStore { pool })
+    }
+}
+
+#[async_trait]
+impl CandleStore for PostgresCandleStore {
+    async fn load(
+        &self,
+        key: CandleKey,
+        limit: usize,
+    ) -> Result<Vec<(CandleKey, Option<Candle>)>, sqlx::Error> {
+        let data: Vec<Row> = sqlx::query_as(
+            "
+                    SELECT market, timestamp, interval, open, high, low, close, volume
+                    FROM candles
+                    WHERE market = $1
+                    AND timestamp >= $2
+                    AND interval = $3
+                    ORDER BY timestamp ASC
+                    LIMIT $4
+                ",
+        )
+        .bind(key.market.to_string())
+        .bind(key.time.timestamp())
+        .bind(key.interval.num_seconds())
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(data
+            .into_iter()
+            .map(|(market, time, interval, open, high, low, close, volume)| {
+                let key = CandleKey {
+                    market: Symbol::new(market),
+                    time: Utc.timestamp_opt(time, 0).unwrap(),
+                    interval: Duration::seconds(interval),
+                };
+                match (open, high, low, close, volume) {
+                    (Some(open), Some(high), Some(low), Some(close), Some(volume)) => (
+                        key,
+                        Some(Candle {
+                            open,
+                            high,
+                            low,
+                            close,
+                            volume,
+                        }),
+                    ),
+                    _ => (key, None),
+                }
+            })
+            .collect())
+    }
+
+    async fn save(&self, candles: &[(CandleKey, Option<Candle>)]) -> Result<(), sqlx::Error> {
+        const CHUNK_SIZE: usize = 100;
+        for chunk in candles.chunks(CHUNK_SIZE) {
+            let mut query_string = String::from(
+                "INSERT INTO candles (market, timestamp, interval, open, high, low, close, volume) VALUES ",
+            );
+            for i in 0..chunk.len() {
+                let params: Vec<String> = (1..=8).map(|j| format!("${}", i * 8 + j)).collect();
+                query_string += &format!("({}),", params.join(","));
+            }
+            query_string.pop();
+            query_string += " ON CONFLICT DO NOTHING";
+            let mut query = sqlx::query(&query_string);
+
+            for (key, candle) in chunk.iter() {
+                query = query
+                    .bind(key.market.to_string())
+                    .bind(key.time.timestamp())
+                    .bind(key.interval.num_seconds())
+                    .bind(candle.map(|candle| candle.open))
+                    .bind(candle.map(|candle| candle.high))
+                    .bind(candle.map(|candle| candle.low))
+                    .bind(candle.map(|candle| candle.close))
+                    .bind(candle.map(|candle| candle.volume));
+            }
+
+            query.execute(&self.pool).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn remove(
+        &self,
+        market: Symbol,
+        interval: Duration,
+        range: Range<DateTime<Utc>>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "
+                    DELETE FROM candles
+                    WHERE market = $1
+                    AND interval = $2
+                    AND timestamp >= $3
+                    AND timestamp < $4
+                ",
+        )
+        .bind(market.to_string())
+        .bind(interval.num_seconds())
+        .bind(range.start.timestamp())
+        .bind(range.end.timestamp())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn remove_before(
+        &self,
+        interval: Duration,
+        before: DateTime<Utc>,
+    ) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM candles WHERE interval = $1 AND timestamp < $2")
+            .bind(interval.num_seconds())
+            .bind(before.timestamp())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn intervals(&self) -> Result<Vec<Duration>, sqlx::Error> {
+        let intervals: Vec<(i64,)> = sqlx::query_as("SELECT DISTINCT interval FROM candles")
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(intervals
+            .into_iter()
+            .map(|(interval,)| Duration::seconds(interval))
+            .collect())
+    }
+
+    async fn load_trades(
+        &self,
+        market: Symbol,
+        page: DateTime<Utc>,
+    ) -> Result<Option<Vec<Trade>>, sqlx::Error> {
+        let stored: Option<(i64,)> =
+            sqlx::query_as("SELECT page FROM trade_pages WHERE market = $1 AND page = $2")
+                .bind(market.to_string())
+                .bind(page.timestamp())
+                .fetch_optional(&self.pool)
+                .await?;
+        if stored.is_none() {
+            return Ok(None);
+        }
+
+        let data: Vec<(i64, Decimal, Decimal, bool)> = sqlx::query_as(
+            "
+                    SELECT timestamp, price, size, buy
+                    FROM trades
+                    WHERE market = $1
+                    AND page = $2
+                    ORDER BY seq ASC
+                ",
+        )
+        .bind(market.to_string())
+        .bind(page.timestamp())
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(Some(
+            data.into_iter()
+                .map(|(time, price, size, buy)| Trade {
+                    time: Utc.timestamp_millis_opt(time).unwrap(),
+                    price,
+                    size,
+                    side: if buy { Side::Buy } else { Side::Sell },
+                })
+                .collect(),
+        ))
+    }
+
+    async fn save_trades(
+        &self,
+        market: Symbol,
+        page: DateTime<Utc>,
+        trades: &[Trade],
+    ) -> Result<(), sqlx::Error> {
+        const CHUNK_SIZE: usize = 100;
+        for (index, chunk) in trades.chunks(CHUNK_SIZE).enumerate() {
+            let mut query_string = String::from(
+                "INSERT INTO trades (market, page, seq, timestamp, price, size, buy) VALUES ",
+            );
+            for i in 0..chunk.len() {
+                let params: Vec<String> = (1..=7).map(|j| format!("${}", i * 7 + j)).collect();
+                query_string += &format!("({}),", params.join(","));
+            }
+            query_string.pop();
+            query_string += " ON CONFLICT DO NOTHING";
+            let mut query = sqlx::query(&query_string);
+
+            for (i, trade) in chunk.iter().enumerate() {
+                query = query
+                    .bind(market.to_string())
+                    .bind(page.timestamp())
+                    .bind((index * CHUNK_SIZE + i) as i64)
+                    .bind(trade.time.timestamp_millis())
+                    .bind(trade.price)
+                    .bind(trade.size)
+                    .bind(trade.side == Side::Buy);
+            }
+
+            query.execute(&self.pool).await?;
+        }
+
+        sqlx::query(
+            "INSERT INTO trade_pages (market, page) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        )
+        .bind(market.to_string())
+        .bind(page.timestamp())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn load_price(
+        &self,
+        key: CandleKey,
+        source: PriceSource,
+    ) -> Result<Option<Option<Decimal>>, sqlx::Error> {
+        let price: Option<(Option<Decimal>,)> = sqlx::query_as(
+            "
+                    SELECT price
+                    FROM prices
+                    WHERE market = $1
+                    AND source = $2
+                    AND timestamp = $3
+                    AND interval = $4
+                ",
+        )
+        .bind(key.market.to_string())
+        .bind(format!("{:?}", source))
+        .bind(key.time.timestamp())
+        .bind(key.interval.num_seconds())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(price.map(|(price,)| price))
+    }
+
+    async fn save_prices(
+        &self,
+        source: PriceSource,
+        prices: &[(CandleKey, Option<Decimal>)],
+    ) -> Result<(), sqlx::Error> {
+        const CHUNK_SIZE: usize = 100;
+        for chunk in prices.chunks(CHUNK_SIZE) {
+            let mut query_string = String::from(
+                "INSERT INTO prices (market, source, timestamp, interval, price) VALUES ",
+            );
+            for i in 0..chunk.len() {
+                let params: Vec<String> = (1..=5).map(|j| format!("${}", i * 5 + j)).collect();
+                query_string += &format!("({}),", params.join(","));
+            }
+            query_string.pop();
+            query_string += " ON CONFLICT DO NOTHING";
+            let mut query = sqlx::query(&query_string);
+
+            for (key, price) in chunk {
+                query = query
+                    .bind(key.market.to_string())
+                    .bind(format!("{:?}", source))
+                    .bind(key.time.timestamp())
+                    .bind(key.interval.num_seconds())
+                    .bind(*price);
+            }
+
+            query.execute(&self.pool).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn vacuum(&self) -> Result<(), sqlx::Error> {
+        sqlx::query("VACUUM candles").execute(&self.pool).await?;
+
+        Ok(())
+    }
+}
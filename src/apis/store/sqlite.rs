@@ -0,0 +1,529 @@
+use super::CandleStore;
+use crate::{Candle, CandleKey, PriceSource, Side, Symbol, Trade};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use rust_decimal::prelude::*;
+use sqlx::{sqlite::SqliteConnectOptions, ConnectOptions, SqlitePool};
+use std::ops::Range;
+
+#[allow(clippy::type_complexity)]
+type Row = (
+    String,
+    i64,
+    i64,
+    Option<Vec<u8>>,
+    Option<Vec<u8>>,
+    Option<Vec<u8>>,
+    Option<Vec<u8>>,
+    Option<Vec<u8>>,
+);
+
+/// Stores candles in a local SQLite database file in ./.store.
+pub struct SqliteCandleStore {
+    pool: SqlitePool,
+    //conn: Mutex<SqliteConnection>,
+    compact: bool,
+}
+
+impl SqliteCandleStore {
+    /// Open or create the database ./.store/{name}.db.
+    pub async fn new(name: &str) -> Result<Self, sqlx::Error> {
+        std::fs::create_dir_all("./.store")?;
+
+        let mut options = SqliteConnectOptions::new()
+            .filename(Self::path(name))
+            .create_if_missing(true);
+
+        options.disable_statement_logging();
+
+        //let conn = Mutex::new(SqliteConnection::connect_with(&options).await.unwrap());
+
+        let pool = SqlitePool::connect_with(options).await?;
+
+        sqlx::query(
+            "
+                CREATE TABLE IF NOT EXISTS data (
+                    market TEXT,
+                    timestamp INTEGER,
+                    open BLOB,
+                    high BLOB,
+                    low BLOB,
+                    close BLOB,
+                    volume BLOB,
+                    interval INTEGER,
+                    PRIMARY KEY(market, timestamp, interval)
+                )
+            ",
+        )
+        .execute(/*&mut *conn.lock().await*/ &pool)
+        .await?;
+
+        // Trades are stored in whole pages, which are marked once all their trades are stored.
+        sqlx::query(
+            "
+                CREATE TABLE IF NOT EXISTS trades (
+                    market TEXT,
+                    page INTEGER,
+                    seq INTEGER,
+                    timestamp INTEGER,
+                    price BLOB,
+                    size BLOB,
+                    buy INTEGER,
+                    PRIMARY KEY(market, page, seq)
+                )
+            ",
+        )
+        .execute(&pool)
+        .await?;
+        sqlx::query(
+            "
+                CREATE TABLE IF NOT EXISTS trade_pages (
+                    market TEXT,
+                    page INTEGER,
+                    PRIMARY KEY(market, page)
+                )
+            ",
+        )
+        .execute(&pool)
+        .await?;
+
+        // Prices of other sources than the candles, e.g. mark prices, stored per candle.
+        sqlx::query(
+            "
+                CREATE TABLE IF NOT EXISTS prices (
+                    market TEXT,
+                    source TEXT,
+                    timestamp INTEGER,
+                    interval INTEGER,
+                    price BLOB,
+                    PRIMARY KEY(market, source, timestamp, interval)
+                )
+            ",
+        )
+        .execute(&pool)
+        .await?;
+
+        // Databases created by older versions only store close and volume.
+        for column in ["open", "high", "low"] {
+            sqlx::query(&format!("ALTER TABLE data ADD COLUMN {} BLOB", column))
+                .execute(&pool)
+                .await
+                .ok();
+        }
+
+        Ok(SqliteCandleStore {
+            pool,
+            compact: false,
+        })
+    }
+
+    /// Move the database ./.store/{name}.db aside and create a new, empty one.
+    /// The old database is kept as ./.store/{name}.db.{timestamp}.bak.
+    pub async fn rebuild(name: &str) -> Result<Self, sqlx::Error> {
+        let path = Self::path(name);
+        let backup = format!("{}.{}.bak", path, Utc::now().timestamp());
+        // Journal files belong to the old database and are moved along with it.
+        for suffix in ["", "-wal", "-shm", "-journal"] {
+            match std::fs::rename(
+                format!("{}{}", path, suffix),
+                format!("{}{}", backup, suffix),
+            ) {
+                Ok(()) => {}
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        tracing::warn!("Moved the candle store {} to {}.", path, backup);
+
+        Self::new(name).await
+    }
+
+    /// Store decimals without their leading zero bytes, which roughly halves the size of new rows.
+    /// Both encodings can be read, but older versions can't read compact rows.
+    pub fn with_compact_encoding(mut self) -> Self {
+        self.compact = true;
+        self
+    }
+
+    fn path(name: &str) -> String {
+        format!("./.store/{}.db", name)
+    }
+}
+
+#[async_trait]
+impl CandleStore for SqliteCandleStore {
+    async fn load(
+        &self,
+        key: CandleKey,
+        limit: usize,
+    ) -> Result<Vec<(CandleKey, Option<Candle>)>, sqlx::Error> {
+        let data: Vec<Row> = sqlx::query_as(
+            "
+                    SELECT market, timestamp, interval, open, high, low, close, volume
+                    FROM data
+                    WHERE market = $1
+                    AND timestamp >= $2
+                    AND interval = $3
+                    ORDER BY timestamp ASC
+                    LIMIT $4
+                ",
+        )
+        .bind(key.market.to_string())
+        .bind(key.time.timestamp())
+        .bind(key.interval.num_seconds())
+        .bind(limit as i64)
+        .fetch_all(/*&mut *self.conn.lock().await*/ &self.pool)
+        .await?;
+
+        Ok(data
+            .into_iter()
+            .map(|(market, time, interval, open, high, low, close, volume)| {
+                let key = CandleKey {
+                    market: Symbol::new(market),
+                    time: Utc.timestamp_opt(time, 0).unwrap(),
+                    interval: Duration::seconds(interval),
+                };
+                match (close, volume) {
+                    (Some(close), Some(volume)) => {
+                        let close = blob_to_dec(close);
+                        (
+                            key,
+                            Some(Candle {
+                                open: open.map(blob_to_dec).unwrap_or(close),
+                                high: high.map(blob_to_dec).unwrap_or(close),
+                                low: low.map(blob_to_dec).unwrap_or(close),
+                                close,
+                                volume: blob_to_dec(volume),
+                            }),
+                        )
+                    }
+                    (None, None) => (key, None),
+                    _ => unreachable!(),
+                }
+            })
+            .collect())
+    }
+
+    async fn save(&self, candles: &[(CandleKey, Option<Candle>)]) -> Result<(), sqlx::Error> {
+        const CHUNK_SIZE: usize = 100;
+        for chunk in candles.chunks(CHUNK_SIZE) {
+            let mut query_string = String::from(
+                "INSERT OR IGNORE INTO data (market, timestamp, open, high, low, close, volume, interval) VALUES ",
+            );
+            for (i, _candle) in chunk.iter().enumerate() {
+                query_string += &format!(
+                    "(${},${},${},${},${},${},${},${}),",
+                    i * 8 + 1,
+                    i * 8 + 2,
+                    i * 8 + 3,
+                    i * 8 + 4,
+                    i * 8 + 5,
+                    i * 8 + 6,
+                    i * 8 + 7,
+                    i * 8 + 8,
+                );
+            }
+            query_string.pop();
+            let mut query = sqlx::query(&query_string);
+
+            let dec_to_blob = |decimal| {
+                if self.compact {
+                    dec_to_compact_blob(decimal)
+                } else {
+                    dec_to_blob(decimal)
+                }
+            };
+            for (curr_key, candle) in chunk.iter() {
+                query = query
+                    .bind(curr_key.market.to_string())
+                    .bind(curr_key.time.timestamp())
+                    .bind(candle.as_ref().map(|candle| dec_to_blob(candle.open)))
+                    .bind(candle.as_ref().map(|candle| dec_to_blob(candle.high)))
+                    .bind(candle.as_ref().map(|candle| dec_to_blob(candle.low)))
+                    .bind(candle.as_ref().map(|candle| dec_to_blob(candle.close)))
+                    .bind(candle.as_ref().map(|candle| dec_to_blob(candle.volume)))
+                    .bind(curr_key.interval.num_seconds());
+            }
+
+            query
+                .execute(/*&mut *self.conn.lock().await*/ &self.pool)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn remove(
+        &self,
+        market: Symbol,
+        interval: Duration,
+        range: Range<DateTime<Utc>>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "
+                    DELETE FROM data
+                    WHERE market = $1
+                    AND interval = $2
+                    AND timestamp >= $3
+                    AND timestamp < $4
+                ",
+        )
+        .bind(market.to_string())
+        .bind(interval.num_seconds())
+        .bind(range.start.timestamp())
+        .bind(range.end.timestamp())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn remove_before(
+        &self,
+        interval: Duration,
+        before: DateTime<Utc>,
+    ) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM data WHERE interval = $1 AND timestamp < $2")
+            .bind(interval.num_seconds())
+            .bind(before.timestamp())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn intervals(&self) -> Result<Vec<Duration>, sqlx::Error> {
+        let intervals: Vec<(i64,)> = sqlx::query_as("SELECT DISTINCT interval FROM data")
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(intervals
+            .into_iter()
+            .map(|(interval,)| Duration::seconds(interval))
+            .collect())
+    }
+
+    async fn load_trades(
+        &self,
+        market: Symbol,
+        page: DateTime<Utc>,
+    ) -> Result<Option<Vec<Trade>>, sqlx::Error> {
+        let stored: Option<(i64,)> =
+            sqlx::query_as("SELECT page FROM trade_pages WHERE market = $1 AND page = $2")
+                .bind(market.to_string())
+                .bind(page.timestamp())
+                .fetch_optional(&self.pool)
+                .await?;
+        if stored.is_none() {
+            return Ok(None);
+        }
+
+        let data: Vec<(i64, Vec<u8>, Vec<u8>, bool)> = sqlx::query_as(
+            "
+                    SELECT timestamp, price, size, buy
+                    FROM trades
+                    WHERE market = $1
+                    AND page = $2
+                    ORDER BY seq ASC
+                ",
+        )
+        .bind(market.to_string())
+        .bind(page.timestamp())
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(Some(
+            data.into_iter()
+                .map(|(time, price, size, buy)| Trade {
+                    time: Utc.timestamp_millis_opt(time).unwrap(),
+                    price: blob_to_dec(price),
+                    size: blob_to_dec(size),
+                    side: if buy { Side::Buy } else { Side::Sell },
+                })
+                .collect(),
+        ))
+    }
+
+    async fn save_trades(
+        &self,
+        market: Symbol,
+        page: DateTime<Utc>,
+        trades: &[Trade],
+    ) -> Result<(), sqlx::Error> {
+        const CHUNK_SIZE: usize = 100;
+        let dec_to_blob = |decimal| {
+            if self.compact {
+                dec_to_compact_blob(decimal)
+            } else {
+                dec_to_blob(decimal)
+            }
+        };
+        for (index, chunk) in trades.chunks(CHUNK_SIZE).enumerate() {
+            let mut query_string = String::from(
+                "INSERT OR IGNORE INTO trades (market, page, seq, timestamp, price, size, buy) VALUES ",
+            );
+            for i in 0..chunk.len() {
+                let params: Vec<String> = (1..=7).map(|j| format!("${}", i * 7 + j)).collect();
+                query_string += &format!("({}),", params.join(","));
+            }
+            query_string.pop();
+            let mut query = sqlx::query(&query_string);
+
+            for (i, trade) in chunk.iter().enumerate() {
+                query = query
+                    .bind(market.to_string())
+                    .bind(page.timestamp())
+                    .bind((index * CHUNK_SIZE + i) as i64)
+                    .bind(trade.time.timestamp_millis())
+                    .bind(dec_to_blob(trade.price))
+                    .bind(dec_to_blob(trade.size))
+                    .bind(trade.side == Side::Buy);
+            }
+
+            query.execute(&self.pool).await?;
+        }
+
+        sqlx::query("INSERT OR IGNORE INTO trade_pages (market, page) VALUES ($1, $2)")
+            .bind(market.to_string())
+            .bind(page.timestamp())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn load_price(
+        &self,
+        key: CandleKey,
+        source: PriceSource,
+    ) -> Result<Option<Option<Decimal>>, sqlx::Error> {
+        let price: Option<(Option<Vec<u8>>,)> = sqlx::query_as(
+            "
+                    SELECT price
+                    FROM prices
+                    WHERE market = $1
+                    AND source = $2
+                    AND timestamp = $3
+                    AND interval = $4
+                ",
+        )
+        .bind(key.market.to_string())
+        .bind(format!("{:?}", source))
+        .bind(key.time.timestamp())
+        .bind(key.interval.num_seconds())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(price.map(|(price,)| price.map(blob_to_dec)))
+    }
+
+    async fn save_prices(
+        &self,
+        source: PriceSource,
+        prices: &[(CandleKey, Option<Decimal>)],
+    ) -> Result<(), sqlx::Error> {
+        const CHUNK_SIZE: usize = 100;
+        let dec_to_blob = |decimal| {
+            if self.compact {
+                dec_to_compact_blob(decimal)
+            } else {
+                dec_to_blob(decimal)
+            }
+        };
+        for chunk in prices.chunks(CHUNK_SIZE) {
+            let mut query_string = String::from(
+                "INSERT OR IGNORE INTO prices (market, source, timestamp, interval, price) VALUES ",
+            );
+            for i in 0..chunk.len() {
+                let params: Vec<String> = (1..=5).map(|j| format!("${}", i * 5 + j)).collect();
+                query_string += &format!("({}),", params.join(","));
+            }
+            query_string.pop();
+            let mut query = sqlx::query(&query_string);
+
+            for (key, price) in chunk {
+                query = query
+                    .bind(key.market.to_string())
+                    .bind(format!("{:?}", source))
+                    .bind(key.time.timestamp())
+                    .bind(key.interval.num_seconds())
+                    .bind(price.map(dec_to_blob));
+            }
+
+            query.execute(&self.pool).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn vacuum(&self) -> Result<(), sqlx::Error> {
+        sqlx::query("VACUUM").execute(&self.pool).await?;
+
+        Ok(())
+    }
+}
+
+fn blob_to_dec(vec: Vec<u8>) -> Decimal {
+    // Compact blobs are always shorter than serialized decimals.
+    if vec.len() < 16 {
+        return compact_blob_to_dec(vec);
+    }
+    let mut buf = [0; 16];
+    buf.clone_from_slice(&vec[..]);
+    Decimal::deserialize(buf)
+}
+
+// The first byte holds the sign and scale, followed by the
+// little endian mantissa without its trailing zero bytes.
+fn compact_blob_to_dec(vec: Vec<u8>) -> Decimal {
+    let mut mantissa = [0; 16];
+    mantissa[..vec.len() - 1].copy_from_slice(&vec[1..]);
+    let mut decimal =
+        Decimal::from_i128_with_scale(i128::from_le_bytes(mantissa), (vec[0] & 0x7f) as u32);
+    decimal.set_sign_negative(vec[0] & 0x80 != 0);
+    decimal
+}
+
+fn dec_to_compact_blob(decimal: Decimal) -> Vec<u8> {
+    let mantissa = decimal.mantissa().unsigned_abs().to_le_bytes();
+    let len = mantissa
+        .iter()
+        .rposition(|&byte| byte != 0)
+        .map_or(0, |i| i + 1);
+    let mut vec = Vec::with_capacity(len + 1);
+    vec.push(decimal.scale() as u8 | if decimal.is_sign_negative() { 0x80 } else { 0 });
+    vec.extend_from_slice(&mantissa[..len]);
+    vec
+}
+
+fn dec_to_blob(decimal: Decimal) -> Vec<u8> {
+    decimal.serialize().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn compact_encoding() {
+        for decimal in [
+            dec!(0),
+            dec!(-0.0),
+            dec!(1),
+            dec!(43210.5),
+            dec!(-0.000001234),
+            Decimal::MAX,
+            Decimal::MIN,
+        ] {
+            let blob = dec_to_compact_blob(decimal);
+            assert!(blob.len() < 16);
+            let decoded = blob_to_dec(blob);
+            assert_eq!(decoded, decimal);
+            assert_eq!(decoded.scale(), decimal.scale());
+            assert_eq!(blob_to_dec(dec_to_blob(decimal)), decimal);
+        }
+        assert_eq!(dec_to_compact_blob(dec!(43210.5)).len(), 4);
+    }
+}
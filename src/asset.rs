@@ -2,7 +2,7 @@ use once_cell::sync::Lazy;
 use serde::{Deserialize, Deserializer, Serialize};
 use std::{collections::HashSet, fmt, sync::Mutex};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
 pub struct Asset(&'static str);
 
 impl<'de> Deserialize<'de> for Asset {
@@ -30,6 +30,10 @@ impl Asset {
 
         Asset(set.get(name.as_ref()).unwrap())
     }
+
+    pub fn name(&self) -> &'static str {
+        self.0
+    }
 }
 
 impl fmt::Display for Asset {
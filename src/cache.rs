@@ -0,0 +1,88 @@
+use serde::Serialize;
+use std::{fmt::Write, fs, path::PathBuf};
+
+use crate::Report;
+
+/// Caches reports of backtests on disk, keyed by a fingerprint of everything
+/// that influences the result, so identical backtests don't need to be run again.
+pub struct ReportCache {
+    directory: PathBuf,
+}
+
+impl Default for ReportCache {
+    fn default() -> Self {
+        ReportCache::new("./.store/reports")
+    }
+}
+
+impl ReportCache {
+    pub fn new<P: Into<PathBuf>>(directory: P) -> Self {
+        ReportCache {
+            directory: directory.into(),
+        }
+    }
+
+    /// A fingerprint of the inputs of a backtest, the SHA-256 digest of their serialization,
+    /// which is stable across runs and versions.
+    pub fn fingerprint<T: Serialize>(inputs: &T) -> String {
+        let json = serde_json::to_vec(inputs).expect("inputs can be serialized");
+        to_hex(&hmac_sha256::Hash::hash(&json))
+    }
+
+    pub fn get(&self, fingerprint: &str) -> Option<Report> {
+        let json = fs::read(self.path(fingerprint)).ok()?;
+        match serde_json::from_slice(&json) {
+            Ok(report) => Some(report),
+            Err(err) => {
+                tracing::warn!("Ignoring unreadable cached report: {}", err);
+                None
+            }
+        }
+    }
+
+    pub fn insert(&self, fingerprint: &str, report: &Report) -> std::io::Result<()> {
+        fs::create_dir_all(&self.directory)?;
+        fs::write(self.path(fingerprint), serde_json::to_vec_pretty(report)?)
+    }
+
+    fn path(&self, fingerprint: &str) -> PathBuf {
+        self.directory.join(format!("{}.json", fingerprint))
+    }
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{:02x}", byte);
+        hex
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn insert_get() {
+        let cache = ReportCache::new(std::env::temp_dir().join("bazaar-report-cache-test"));
+        let fingerprint = ReportCache::fingerprint(&("Strategy", dec!(1000), 20));
+        let report = Report::new(Utc::now(), dec!(1000));
+
+        cache.insert(&fingerprint, &report).unwrap();
+
+        assert_eq!(cache.get(&fingerprint), Some(report));
+        assert_eq!(
+            cache.get(&ReportCache::fingerprint(&("Strategy", dec!(1000), 21))),
+            None
+        );
+    }
+
+    #[test]
+    fn stable_fingerprint() {
+        assert_eq!(
+            ReportCache::fingerprint(&("Strategy", dec!(1000), 20)),
+            "49ae5af28a6448c08c1e90c1a84e197ed7a4d9a725d5a75267ce6fdbffd08886"
+        );
+    }
+}
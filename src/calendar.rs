@@ -0,0 +1,160 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, TimeZone, Utc, Weekday};
+use std::collections::HashSet;
+
+// How far ahead the next session is searched.
+const MAX_SEARCH_DAYS: i64 = 400;
+
+/// The trading hours of a venue in UTC, for markets that do not trade around the clock.
+/// Steps outside of the sessions are skipped, or with a [Schedule](crate::strategies::Schedule)
+/// only new entries are.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Calendar {
+    sessions: Vec<(Weekday, NaiveTime, NaiveTime)>,
+    holidays: HashSet<NaiveDate>,
+}
+
+impl Calendar {
+    /// A calendar without sessions, add them with `with_session`.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Trade on a weekday from the open until the close.
+    /// A close at or before the open ends the session on the next day.
+    pub fn with_session(mut self, day: Weekday, open: NaiveTime, close: NaiveTime) -> Self {
+        self.sessions.push((day, open, close));
+        self
+    }
+
+    /// Trade from Monday to Friday, with the same hours every day.
+    pub fn weekdays(open: NaiveTime, close: NaiveTime) -> Self {
+        [
+            Weekday::Mon,
+            Weekday::Tue,
+            Weekday::Wed,
+            Weekday::Thu,
+            Weekday::Fri,
+        ]
+        .into_iter()
+        .fold(Self::new(), |calendar, day| {
+            calendar.with_session(day, open, close)
+        })
+    }
+
+    /// Do not trade the sessions starting on this date.
+    pub fn with_holiday(mut self, date: NaiveDate) -> Self {
+        self.holidays.insert(date);
+        self
+    }
+
+    pub fn is_open(&self, time: DateTime<Utc>) -> bool {
+        // Sessions starting on the day before may still be open.
+        [time.date_naive().pred_opt(), Some(time.date_naive())]
+            .into_iter()
+            .flatten()
+            .flat_map(|date| self.sessions_on(date))
+            .any(|(open, close)| open <= time && time < close)
+    }
+
+    /// The first time at or after the time at which the venue is open,
+    /// None if there is no session within about a year.
+    pub fn next_open(&self, time: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        if self.is_open(time) {
+            return Some(time);
+        }
+
+        (0..MAX_SEARCH_DAYS)
+            .map(|days| time.date_naive() + Duration::days(days))
+            .find_map(|date| {
+                self.sessions_on(date)
+                    .map(|(open, _)| open)
+                    .filter(|open| *open > time)
+                    .min()
+            })
+    }
+
+    /// When the session open at the time closes, None if the venue is closed.
+    /// Sessions that open right as the previous one closes are treated as one.
+    pub fn next_close(&self, time: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut close = self.session_close(time)?;
+        for _ in 0..MAX_SEARCH_DAYS {
+            match self.session_close(close) {
+                Some(next) if next > close => close = next,
+                _ => break,
+            }
+        }
+        Some(close)
+    }
+
+    // The latest close of the sessions open at the time.
+    fn session_close(&self, time: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        [time.date_naive().pred_opt(), Some(time.date_naive())]
+            .into_iter()
+            .flatten()
+            .flat_map(|date| self.sessions_on(date))
+            .filter(|(open, close)| *open <= time && time < *close)
+            .map(|(_, close)| close)
+            .max()
+    }
+
+    // The sessions starting on a date, as open and close times.
+    fn sessions_on(
+        &self,
+        date: NaiveDate,
+    ) -> impl Iterator<Item = (DateTime<Utc>, DateTime<Utc>)> + '_ {
+        let holiday = self.holidays.contains(&date);
+        self.sessions
+            .iter()
+            .filter(move |(day, _, _)| !holiday && *day == date.weekday())
+            .map(move |&(_, open, close)| {
+                let open_time = Utc.from_utc_datetime(&date.and_time(open));
+                let mut close_time = Utc.from_utc_datetime(&date.and_time(close));
+                if close <= open {
+                    close_time += Duration::days(1);
+                }
+                (open_time, close_time)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2021, 1, day, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn sessions_and_holidays() {
+        // 2021-01-01 was a friday.
+        let calendar = Calendar::weekdays(
+            NaiveTime::from_hms_opt(14, 30, 0).unwrap(),
+            NaiveTime::from_hms_opt(21, 0, 0).unwrap(),
+        )
+        .with_holiday(NaiveDate::from_ymd_opt(2021, 1, 1).unwrap());
+
+        assert!(!calendar.is_open(time(1, 15, 0)));
+        assert!(calendar.is_open(time(4, 14, 30)));
+        assert!(!calendar.is_open(time(4, 21, 0)));
+        assert_eq!(calendar.next_open(time(1, 15, 0)), Some(time(4, 14, 30)));
+        assert_eq!(calendar.next_open(time(4, 16, 0)), Some(time(4, 16, 0)));
+        assert_eq!(Calendar::new().next_open(time(4, 16, 0)), None);
+        assert_eq!(calendar.next_close(time(4, 16, 0)), Some(time(4, 21, 0)));
+        assert_eq!(calendar.next_close(time(4, 22, 0)), None);
+
+        // Sunday evening until monday morning.
+        let overnight = Calendar::new().with_session(
+            Weekday::Sun,
+            NaiveTime::from_hms_opt(22, 0, 0).unwrap(),
+            NaiveTime::from_hms_opt(6, 0, 0).unwrap(),
+        );
+        assert!(overnight.is_open(time(4, 5, 59)));
+        assert!(!overnight.is_open(time(4, 6, 0)));
+
+        // Around the clock during the week, the sessions join up until the weekend.
+        let midnight = NaiveTime::from_hms_opt(0, 0, 0).unwrap();
+        let week = Calendar::weekdays(midnight, midnight);
+        assert_eq!(week.next_close(time(4, 12, 0)), Some(time(9, 0, 0)));
+    }
+}
@@ -1,17 +1,50 @@
 use chrono::{DateTime, Duration, Utc};
 use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
 
-use crate::Symbol;
+use crate::{Side, Symbol};
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Candle {
+    pub open: Decimal,
+    pub high: Decimal,
+    pub low: Decimal,
     pub close: Decimal,
     pub volume: Decimal,
 }
 
+impl Candle {
+    /// Merge with the following candle into one candle spanning both.
+    pub fn merge(self, next: Candle) -> Candle {
+        Candle {
+            open: self.open,
+            high: self.high.max(next.high),
+            low: self.low.min(next.low),
+            close: next.close,
+            volume: self.volume + next.volume,
+        }
+    }
+
+    /// The average of the high, the low and the close.
+    pub fn typical_price(&self) -> Decimal {
+        (self.high + self.low + self.close) / Decimal::from(3)
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct CandleKey {
     pub market: Symbol,
     pub time: DateTime<Utc>,
     pub interval: Duration,
 }
+
+/// A trade of a market as reported by the venue, e.g. to simulate fills within a candle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Trade {
+    pub time: DateTime<Utc>,
+    pub price: Decimal,
+    /// The traded size in contracts.
+    pub size: Decimal,
+    /// The side of the taker.
+    pub side: Side,
+}
@@ -0,0 +1,168 @@
+use crate::{
+    apis::{Api, Simulate},
+    strategies::Strategy,
+    AnyError, Bazaar, Exchange, ImpactModel, Report, Wallet,
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::prelude::*;
+use rust_decimal_macros::dec;
+use serde::{Deserialize, Serialize};
+
+/// Backtests a strategy with growing capital to find out when it stops fitting the market.
+/// Orders are filled with the estimated spread and market impact, so larger orders get worse prices.
+pub struct CapacityAnalysis {
+    /// The multiples of the start capital to backtest with.
+    pub multiples: Vec<Decimal>,
+    pub impact: ImpactModel,
+    /// The number of candles spreads are estimated from.
+    pub spread_window: usize,
+    /// The capacity is the largest capital that keeps at least this fraction
+    /// of the return at the smallest capital.
+    pub min_relative_return: Decimal,
+    /// The end of the backtests.
+    pub end_time: DateTime<Utc>,
+}
+
+impl Default for CapacityAnalysis {
+    fn default() -> Self {
+        CapacityAnalysis {
+            multiples: vec![dec!(1), dec!(10), dec!(100), dec!(1000)],
+            impact: ImpactModel::default(),
+            spread_window: 20,
+            min_relative_return: dec!(0.5),
+            end_time: Utc::now(),
+        }
+    }
+}
+
+impl CapacityAnalysis {
+    /// Run one backtest per multiple, from the start time and capital of the Bazaar.
+    /// The API and strategy are created anew for every backtest.
+    pub async fn run<A, S>(
+        &self,
+        bazaar: &Bazaar,
+        api: impl Fn() -> A,
+        strategy: impl Fn() -> S,
+    ) -> Result<Capacity, AnyError>
+    where
+        A: Api,
+        S: Strategy<Simulate<A>>,
+    {
+        let mut runs = Vec::new();
+        for &multiple in &self.multiples {
+            let capital = bazaar.start_capital * multiple;
+            tracing::info!("Backtesting with a capital of {}.", capital);
+
+            let api = api();
+            let quote_asset = bazaar.quote_asset(&api);
+            let mut wallet = Wallet::new();
+            wallet.deposit(capital, quote_asset);
+            let api = Simulate::new(api, wallet)
+                .with_quote_asset(quote_asset)
+                .with_spread_estimation(self.spread_window)
+                .with_market_impact(self.impact);
+            let report = Exchange::new(api, bazaar.start_time)
+                .until(self.end_time)
+                .run(strategy())
+                .await?;
+
+            runs.push(CapacityRun { capital, report });
+        }
+
+        Ok(Capacity::new(runs, self.min_relative_return))
+    }
+}
+
+/// A backtest of the capacity analysis.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CapacityRun {
+    pub capital: Decimal,
+    pub report: Report,
+}
+
+/// How the returns of a strategy degrade with its capital.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capacity {
+    /// The backtests, by increasing capital.
+    pub runs: Vec<CapacityRun>,
+    /// The largest capital that kept enough of the return, None if even the smallest did not,
+    /// e.g. because the strategy lost money.
+    pub capacity: Option<Decimal>,
+}
+
+impl Capacity {
+    pub(crate) fn new(mut runs: Vec<CapacityRun>, min_relative_return: Decimal) -> Self {
+        runs.sort_by_key(|run| run.capital);
+        let base = runs
+            .first()
+            .map(|run| run.report.total_return())
+            .filter(|base| *base > Decimal::ZERO);
+        let capacity = base.and_then(|base| {
+            runs.iter()
+                .take_while(|run| run.report.total_return() / base >= min_relative_return)
+                .last()
+                .map(|run| run.capital)
+        });
+
+        Capacity { runs, capacity }
+    }
+
+    /// The return of every run relative to the return at the smallest capital, by capital.
+    pub fn relative_returns(&self) -> Vec<(Decimal, Decimal)> {
+        let base = self
+            .runs
+            .first()
+            .map(|run| run.report.total_return())
+            .unwrap_or_default();
+        self.runs
+            .iter()
+            .map(|run| {
+                let relative = if base.is_zero() {
+                    Decimal::ZERO
+                } else {
+                    run.report.total_return() / base
+                };
+                (run.capital, relative)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capacity_from_degrading_returns() {
+        let time = Utc::now();
+        let run = |capital, end_total| {
+            let mut report = Report::new(time, capital);
+            report.update(time, end_total);
+            CapacityRun { capital, report }
+        };
+
+        let capacity = Capacity::new(
+            vec![
+                run(dec!(100_000), dec!(106_000)),
+                run(dec!(1_000), dec!(1_100)),
+                run(dec!(10_000), dec!(10_800)),
+                run(dec!(1_000_000), dec!(1_020_000)),
+            ],
+            dec!(0.5),
+        );
+
+        assert_eq!(capacity.capacity, Some(dec!(100_000)));
+        assert_eq!(
+            capacity.relative_returns(),
+            vec![
+                (dec!(1_000), dec!(1)),
+                (dec!(10_000), dec!(0.8)),
+                (dec!(100_000), dec!(0.6)),
+                (dec!(1_000_000), dec!(0.2)),
+            ]
+        );
+
+        let losing = Capacity::new(vec![run(dec!(1_000), dec!(900))], dec!(0.5));
+        assert_eq!(losing.capacity, None);
+    }
+}
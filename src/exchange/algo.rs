@@ -0,0 +1,106 @@
+use crate::MarketInfo;
+use rust_decimal::{prelude::Signed, Decimal};
+use serde::{Deserialize, Serialize};
+
+/// How the orders of positions are split over steps, for the whole strategy with
+/// `Settings::algo` or per position with [Position::with_algo](crate::Position::with_algo).
+/// The remainder of a split order is ordered in the next steps until the target size is
+/// reached, or the target size changes and the order is split anew.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExecutionAlgo {
+    /// Order the whole size in one step.
+    #[default]
+    Immediate,
+    /// Split the order into equal child orders over this many steps.
+    Twap { steps: u32 },
+    /// Order at most the child size per step.
+    Iceberg { child_size: Decimal },
+}
+
+// The progress of a split order of a symbol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Slice {
+    // The target size the order was split for.
+    pub target: Decimal,
+    // Steps left to order the remainder in.
+    pub steps: u32,
+}
+
+impl ExecutionAlgo {
+    // The child order of a symbol ordering `size` on the way to `target` in this step.
+    // The slice is started when the target changes, and is kept until the target is reached.
+    pub(crate) fn child(
+        &self,
+        size: Decimal,
+        target: Decimal,
+        slice: &mut Option<Slice>,
+        market: Option<&MarketInfo>,
+    ) -> Decimal {
+        let steps = match *self {
+            ExecutionAlgo::Immediate => {
+                *slice = None;
+                return size;
+            }
+            ExecutionAlgo::Twap { steps } => steps,
+            ExecutionAlgo::Iceberg { .. } => 1,
+        };
+        let current = match slice {
+            Some(slice) if slice.target == target => slice,
+            _ => slice.insert(Slice { target, steps }),
+        };
+        let child = match *self {
+            ExecutionAlgo::Twap { .. } => size / Decimal::from(current.steps.max(1)),
+            ExecutionAlgo::Iceberg { child_size } => size.signum() * size.abs().min(child_size),
+            ExecutionAlgo::Immediate => size,
+        };
+        current.steps = current.steps.saturating_sub(1);
+        let child = match market {
+            Some(market) => market.round_size(child),
+            None => child,
+        };
+
+        // Children too small to order complete the order at once.
+        let min_size = market.map(|market| market.min_size).unwrap_or_default();
+        if child.is_zero() || child.abs() < min_size || child.abs() > size.abs() {
+            size
+        } else {
+            child
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn split_orders() {
+        let twap = ExecutionAlgo::Twap { steps: 3 };
+        let mut slice = None;
+        assert_eq!(twap.child(dec!(9), dec!(9), &mut slice, None), dec!(3));
+        // Partially filled, the rest is spread over the steps left.
+        assert_eq!(twap.child(dec!(5), dec!(9), &mut slice, None), dec!(2.5));
+        assert_eq!(twap.child(dec!(2), dec!(9), &mut slice, None), dec!(2));
+        // Unfilled remainders are ordered at once.
+        assert_eq!(twap.child(dec!(1), dec!(9), &mut slice, None), dec!(1));
+        // A new target starts over.
+        assert_eq!(twap.child(dec!(-6), dec!(0), &mut slice, None), dec!(-2));
+
+        let iceberg = ExecutionAlgo::Iceberg {
+            child_size: dec!(4),
+        };
+        let mut slice = None;
+        assert_eq!(
+            iceberg.child(dec!(-10), dec!(0), &mut slice, None),
+            dec!(-4)
+        );
+        assert_eq!(iceberg.child(dec!(-6), dec!(0), &mut slice, None), dec!(-4));
+        assert_eq!(iceberg.child(dec!(-2), dec!(0), &mut slice, None), dec!(-2));
+        assert!(slice.is_some());
+
+        let immediate = ExecutionAlgo::Immediate;
+        assert_eq!(immediate.child(dec!(7), dec!(7), &mut slice, None), dec!(7));
+        assert_eq!(slice, None);
+    }
+}
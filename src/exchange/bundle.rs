@@ -2,12 +2,16 @@ use super::Valuation;
 use crate::Symbol;
 use fxhash::{FxHashMap, FxHasher};
 use rust_decimal::{prelude::Signed, Decimal};
+use serde::{Deserialize, Serialize};
 use std::{
     hash::BuildHasherDefault,
     ops::{Add, Mul, Neg, Sub},
 };
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// Sizes by symbol, serialized as a map from symbol names to decimal strings,
+/// e.g. `{"BTC-PERP": "1.5"}`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct Bundle(pub(crate) FxHashMap<Symbol, Decimal>);
 
 impl Default for Bundle {
@@ -0,0 +1,103 @@
+use std::sync::Arc;
+use tokio::{sync::watch, task::JoinHandle};
+
+/// Stops a running strategy from outside, e.g. from a GUI or a service manager.
+/// When cancelled, all positions are closed unless the strategy returns on errors
+/// without exiting positions, and the run returns its report.
+/// Clones share the same state, so any clone can cancel the run.
+#[derive(Debug, Clone)]
+pub struct CancellationToken {
+    sender: Arc<watch::Sender<bool>>,
+    receiver: watch::Receiver<bool>,
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        CancellationToken::new()
+    }
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        let (sender, receiver) = watch::channel(false);
+        CancellationToken {
+            sender: Arc::new(sender),
+            receiver,
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.sender.send_replace(true);
+    }
+
+    /// Cancel when the process receives Ctrl+C, until the returned handler is dropped.
+    /// Note that tokio keeps its signal handler installed once it listened for Ctrl+C,
+    /// so Ctrl+C does not terminate the process anymore, even after the handler was dropped.
+    pub fn cancel_on_ctrl_c(&self) -> CtrlCHandler {
+        let token = self.clone();
+        CtrlCHandler(tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                tracing::warn!("Received Ctrl+C, shutting down.");
+                token.cancel();
+            }
+        }))
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.receiver.borrow()
+    }
+
+    /// Completes once the token is cancelled.
+    pub async fn cancelled(&self) {
+        let mut receiver = self.receiver.clone();
+        while !*receiver.borrow_and_update() {
+            if receiver.changed().await.is_err() {
+                // The sender lives as long as any clone, so this can't happen.
+                return;
+            }
+        }
+    }
+}
+
+/// Listens for Ctrl+C to cancel a token, stops listening when dropped.
+#[must_use = "stops listening for Ctrl+C when dropped"]
+#[derive(Debug)]
+pub struct CtrlCHandler(JoinHandle<()>);
+
+impl Drop for CtrlCHandler {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn cancel_from_clone() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert!(!token.is_cancelled());
+        assert!(
+            tokio::time::timeout(Duration::from_millis(10), token.cancelled())
+                .await
+                .is_err()
+        );
+
+        tokio::spawn(async move { clone.cancel() });
+        token.cancelled().await;
+        assert!(token.is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn stop_listening_for_ctrl_c_when_dropped() {
+        let handler = CancellationToken::new().cancel_on_ctrl_c();
+        let task = handler.0.abort_handle();
+        assert!(!task.is_finished());
+        drop(handler);
+        tokio::task::yield_now().await;
+        assert!(task.is_finished());
+    }
+}
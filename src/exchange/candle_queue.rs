@@ -0,0 +1,106 @@
+use crate::{Candle, CandleKey};
+use chrono::{DateTime, Utc};
+use std::collections::VecDeque;
+
+/// The candles of a market fetched ahead of the current step.
+/// APIs return many candles at once, but only the candle of the current step is released.
+#[derive(Debug, Default)]
+pub(crate) struct CandleQueue {
+    candles: VecDeque<(CandleKey, Option<Candle>)>,
+}
+
+impl CandleQueue {
+    // Queue fetched candles, which have to be newer than the queued ones.
+    pub(crate) fn extend(
+        &mut self,
+        candles: impl IntoIterator<Item = (CandleKey, Option<Candle>)>,
+    ) {
+        for (key, candle) in candles {
+            debug_assert!(
+                self.candles
+                    .back()
+                    .is_none_or(|(last, _)| last.time < key.time),
+                "The candle of {} at {} is not newer than the queued candles.",
+                key.market,
+                key.time
+            );
+            self.candles.push_back((key, candle));
+        }
+    }
+
+    // Whether no candle is queued, so the candles have to be fetched.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.candles.is_empty()
+    }
+
+    // Drop the candles before the time, once the steps moved past them.
+    pub(crate) fn advance(&mut self, time: DateTime<Utc>) {
+        while matches!(self.candles.front(), Some((key, _)) if key.time < time) {
+            self.candles.pop_front();
+        }
+    }
+
+    // The candle released at the step of the time. This is the first queued candle,
+    // which is newer than the step if the API skipped it. If guarded, only the candle
+    // of the step itself is released, see `Settings::look_ahead_guard`.
+    pub(crate) fn current(
+        &self,
+        time: DateTime<Utc>,
+        guarded: bool,
+    ) -> Option<&(CandleKey, Option<Candle>)> {
+        let front = self.candles.front()?;
+        if guarded {
+            debug_assert!(
+                front.0.time >= time,
+                "The candle of {} at {} was not dropped at {}.",
+                front.0.market,
+                front.0.time,
+                time
+            );
+            if front.0.time != time {
+                return None;
+            }
+        }
+        Some(front)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Symbol;
+    use chrono::{Duration, TimeZone};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn release_current_candle() {
+        let start = Utc.with_ymd_and_hms(2021, 8, 1, 0, 0, 0).unwrap();
+        let key = |minutes| CandleKey {
+            market: Symbol::perp("BTC"),
+            time: start + Duration::minutes(minutes),
+            interval: Duration::minutes(1),
+        };
+        let candle = Candle {
+            open: dec!(1),
+            high: dec!(1),
+            low: dec!(1),
+            close: dec!(1),
+            volume: dec!(1),
+        };
+        // The API skipped the first two steps.
+        let mut queue = CandleQueue::default();
+        queue.extend([(key(2), Some(candle)), (key(3), None)]);
+
+        assert_eq!(queue.current(start, false), Some(&(key(2), Some(candle))));
+        assert_eq!(queue.current(start, true), None);
+        queue.advance(key(2).time);
+        assert_eq!(
+            queue.current(key(2).time, true),
+            Some(&(key(2), Some(candle)))
+        );
+        queue.advance(key(3).time);
+        assert_eq!(queue.current(key(3).time, true), Some(&(key(3), None)));
+        queue.advance(key(4).time);
+        assert!(queue.is_empty());
+    }
+}
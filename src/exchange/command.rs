@@ -0,0 +1,34 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// Operator commands to control a running strategy from outside the process,
+/// applied before the next evaluation of the strategy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Command {
+    /// Close all positions and quit.
+    Stop,
+    /// Close all positions and continue.
+    CloseAll,
+    /// Stop evaluating the strategy, open positions are kept.
+    Pause,
+    /// Continue evaluating the strategy after a pause, or after it panicked.
+    Resume,
+    /// Reduce the gross value of the open positions by a fraction, e.g. 0.5 for half.
+    /// Since positions are opened and closed as a whole, the largest positions are closed
+    /// until at least this fraction is closed.
+    ReducePositionsBy(Decimal),
+}
+
+impl Command {
+    /// Parse a command as stored in the commands table of the monitor database.
+    pub(crate) fn parse(name: &str, value: Option<Decimal>) -> Option<Self> {
+        match (name, value) {
+            ("stop", _) => Some(Command::Stop),
+            ("close_all", _) => Some(Command::CloseAll),
+            ("pause", _) => Some(Command::Pause),
+            ("resume", _) => Some(Command::Resume),
+            ("reduce_positions_by", Some(fraction)) => Some(Command::ReducePositionsBy(fraction)),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use super::Exposure;
+use crate::Order;
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// Checks every outgoing order against rules that apply regardless of the strategy,
+/// e.g. jurisdiction or venue specific restrictions, after the strategy modified its orders.
+pub trait Compliance: Send + Sync {
+    fn check(&self, order: &Order, context: &ComplianceContext) -> Verdict;
+}
+
+impl<F> Compliance for F
+where
+    F: Fn(&Order, &ComplianceContext) -> Verdict + Send + Sync,
+{
+    fn check(&self, order: &Order, context: &ComplianceContext) -> Verdict {
+        self(order, context)
+    }
+}
+
+/// What is known about the account when an order is checked.
+pub struct ComplianceContext<'a> {
+    /// The name of the API the order is sent to.
+    pub venue: &'static str,
+    /// The name of the strategy sending the order.
+    pub strategy: &'static str,
+    pub time: DateTime<Utc>,
+    /// The total value of the account.
+    pub total: Decimal,
+    /// The exposure before the order is executed.
+    pub exposure: &'a Exposure,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Verdict {
+    Approve,
+    /// Send this order instead, with the reason. It must be in the same market, direction and leg.
+    Modify(Order, String),
+    /// Do not send the order, with the reason.
+    Veto(String),
+}
+
+/// An order as it was before it was modified or vetoed, recorded in the report.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ComplianceRecord {
+    pub time: DateTime<Utc>,
+    pub order: Order,
+    pub verdict: Verdict,
+}
+
+// Check the orders, removing vetoed ones and replacing modified ones.
+// Returns the records of all orders that were not approved as they are.
+pub(crate) fn enforce(
+    compliance: &dyn Compliance,
+    orders: &mut Vec<Order>,
+    context: &ComplianceContext,
+) -> Vec<ComplianceRecord> {
+    let mut records = Vec::new();
+    orders.retain_mut(|order| {
+        let mut verdict = compliance.check(order, context);
+        if let Verdict::Modify(modified, _) = &verdict {
+            if modified.market != order.market
+                || modified.side != order.side
+                || modified.position_side != order.position_side
+            {
+                verdict = Verdict::Veto(
+                    "Modified the market, direction or leg of the order, which is not allowed."
+                        .to_owned(),
+                );
+            }
+        }
+
+        let original = order.clone();
+        let keep = match &verdict {
+            Verdict::Approve => return true,
+            Verdict::Modify(modified, reason) => {
+                tracing::warn!(market = %order.market, reason, "Modified order.");
+                *order = modified.clone();
+                true
+            }
+            Verdict::Veto(reason) => {
+                tracing::warn!(market = %order.market, reason, "Vetoed order.");
+                false
+            }
+        };
+        records.push(ComplianceRecord {
+            time: context.time,
+            order: original,
+            verdict,
+        });
+        keep
+    });
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{OrderType, PositionSide, Side, Symbol, TimeInForce};
+    use rust_decimal_macros::dec;
+    use uuid::Uuid;
+
+    fn order(market: Symbol, side: Side) -> Order {
+        Order {
+            order_id: Uuid::new_v4(),
+            market,
+            side,
+            size: dec!(2),
+            order_type: OrderType::Market,
+            reduce_only: false,
+            time_in_force: TimeInForce::ImmediateOrCancel,
+            post_only: false,
+            position_side: None,
+            time: Utc::now(),
+            current_price: dec!(100),
+        }
+    }
+
+    #[test]
+    fn veto_and_modify() {
+        let (btc, doge) = (Symbol::perp("BTC"), Symbol::perp("DOGE"));
+        // No shorting DOGE, at most one BTC per order.
+        let rules = move |order: &Order, _: &ComplianceContext| match order.market {
+            market if market == doge && order.side == Side::Sell => {
+                Verdict::Veto("No shorting DOGE.".to_owned())
+            }
+            market if market == btc && order.size > dec!(1) => Verdict::Modify(
+                Order {
+                    size: dec!(1),
+                    ..order.clone()
+                },
+                "At most one BTC per order.".to_owned(),
+            ),
+            _ => Verdict::Approve,
+        };
+        let exposure = Exposure::default();
+        let context = ComplianceContext {
+            venue: "Mock",
+            strategy: "Test",
+            time: Utc::now(),
+            total: dec!(1000),
+            exposure: &exposure,
+        };
+
+        let mut orders = vec![
+            order(btc, Side::Buy),
+            order(doge, Side::Sell),
+            order(doge, Side::Buy),
+        ];
+        let records = enforce(&rules, &mut orders, &context);
+
+        assert_eq!(orders.len(), 2);
+        assert_eq!(orders[0].size, dec!(1));
+        assert_eq!(orders[1].market, doge);
+        assert_eq!(records.len(), 2);
+        assert!(matches!(records[1].verdict, Verdict::Veto(_)));
+
+        let reverse = |order: &Order, _: &ComplianceContext| {
+            Verdict::Modify(
+                Order {
+                    side: Side::Sell,
+                    ..order.clone()
+                },
+                "Reverse.".to_owned(),
+            )
+        };
+        let mut orders = vec![order(btc, Side::Buy)];
+        let records = enforce(&reverse, &mut orders, &context);
+        assert!(orders.is_empty());
+        assert!(matches!(records[0].verdict, Verdict::Veto(_)));
+    }
+
+    #[test]
+    fn veto_modified_leg() {
+        let exposure = Exposure::default();
+        let context = ComplianceContext {
+            venue: "Mock",
+            strategy: "Test",
+            time: Utc::now(),
+            total: dec!(1000),
+            exposure: &exposure,
+        };
+        let switch_leg = |order: &Order, _: &ComplianceContext| {
+            Verdict::Modify(
+                Order {
+                    position_side: Some(PositionSide::Short),
+                    ..order.clone()
+                },
+                "Switch leg.".to_owned(),
+            )
+        };
+
+        let mut orders = vec![Order {
+            position_side: Some(PositionSide::Long),
+            ..order(Symbol::perp("BTC"), Side::Buy)
+        }];
+        let records = enforce(&switch_leg, &mut orders, &context);
+        assert!(orders.is_empty());
+        assert!(matches!(records[0].verdict, Verdict::Veto(_)));
+    }
+}
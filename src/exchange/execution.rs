@@ -0,0 +1,281 @@
+use crate::{Order, OrderInfo, OrderType, Side, Symbol, TimeInForce};
+use chrono::{DateTime, Utc};
+use rust_decimal::prelude::*;
+use rust_decimal_macros::dec;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+/// Chooses per order between a passive limit order at the current price and a market order,
+/// from how passive orders in the market were filled recently.
+/// Passive orders save the spread and fees, but are not worth it if they rarely fill
+/// or mostly fill right before the price moves against them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdaptiveExecution {
+    /// The number of recent passive orders per market the statistics are computed from.
+    pub window: usize,
+    /// Place passive orders until this many were placed in a market, to learn how they fill.
+    pub min_samples: usize,
+    /// Place market orders while less than this fraction of the size of passive orders is filled.
+    pub min_fill_rate: Decimal,
+    /// Place market orders while the price moves against passive fills by more than this
+    /// fraction until the next step, on average.
+    pub max_adverse_selection: Decimal,
+}
+
+impl Default for AdaptiveExecution {
+    fn default() -> Self {
+        AdaptiveExecution {
+            window: 50,
+            min_samples: 5,
+            min_fill_rate: dec!(0.5),
+            max_adverse_selection: dec!(0.001),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Tactic {
+    /// A post-only limit order at the current price, the unfilled rest is ordered again next step.
+    Passive,
+    /// A market order.
+    Market,
+}
+
+/// Realized fill statistics of the recent passive orders of a market.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FillStats {
+    /// The number of passive orders in the window.
+    pub orders: usize,
+    /// The mean filled fraction of the size of the orders.
+    pub fill_rate: Decimal,
+    /// The mean move of the price against the fills until the next step,
+    /// relative to the fill price. None until a fill was followed by a step.
+    pub adverse_selection: Option<Decimal>,
+}
+
+/// The tactic chosen for an order, recorded in the report.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExecutionDecision {
+    pub time: DateTime<Utc>,
+    pub market: Symbol,
+    pub tactic: Tactic,
+    /// The statistics the decision was based on.
+    pub stats: Option<FillStats>,
+    pub reason: String,
+}
+
+impl AdaptiveExecution {
+    fn decide(&self, stats: Option<FillStats>) -> (Tactic, String) {
+        let Some(stats) = stats.filter(|stats| stats.orders >= self.min_samples) else {
+            return (
+                Tactic::Passive,
+                "Too few passive orders to judge them.".to_owned(),
+            );
+        };
+        if stats.fill_rate < self.min_fill_rate {
+            return (
+                Tactic::Market,
+                format!(
+                    "Passive orders fill only {} of their size.",
+                    stats.fill_rate
+                ),
+            );
+        }
+        match stats.adverse_selection {
+            Some(adverse) if adverse > self.max_adverse_selection => (
+                Tactic::Market,
+                format!("The price moves {} against passive fills.", adverse),
+            ),
+            _ => (Tactic::Passive, "Passive orders fill well.".to_owned()),
+        }
+    }
+
+    // Turn market orders into passive ones where the statistics allow it.
+    // Orders the strategy already made limit orders are left as they are.
+    pub(crate) fn apply(
+        &self,
+        orders: &mut [Order],
+        quality: &ExecutionQuality,
+        time: DateTime<Utc>,
+    ) -> Vec<ExecutionDecision> {
+        let mut decisions = Vec::new();
+        for order in orders
+            .iter_mut()
+            .filter(|order| order.order_type == OrderType::Market)
+        {
+            let stats = quality.stats(order.market);
+            let (tactic, reason) = self.decide(stats);
+            tracing::info!(
+                "Executing the order of {} {} as {:?}: {}",
+                order.size,
+                order.market,
+                tactic,
+                reason
+            );
+            if tactic == Tactic::Passive {
+                order.order_type = OrderType::Limit(order.current_price);
+                order.time_in_force = TimeInForce::GoodTilCancelled;
+                order.post_only = true;
+            }
+            decisions.push(ExecutionDecision {
+                time,
+                market: order.market,
+                tactic,
+                stats,
+                reason,
+            });
+        }
+        decisions
+    }
+}
+
+/// Tracks how passive orders were filled per market.
+#[derive(Debug, Default)]
+pub(crate) struct ExecutionQuality {
+    markets: HashMap<Symbol, MarketFills>,
+}
+
+#[derive(Debug, Default)]
+struct MarketFills {
+    fill_rates: VecDeque<Decimal>,
+    adverse_selections: VecDeque<Decimal>,
+    // Fills whose adverse selection is known once the next price is.
+    pending: Vec<(Side, Decimal)>,
+}
+
+fn push(values: &mut VecDeque<Decimal>, value: Decimal, window: usize) {
+    values.push_back(value);
+    while values.len() > window {
+        values.pop_front();
+    }
+}
+
+fn mean(values: &VecDeque<Decimal>) -> Option<Decimal> {
+    if values.is_empty() {
+        None
+    } else {
+        Some(values.iter().sum::<Decimal>() / Decimal::from(values.len()))
+    }
+}
+
+impl ExecutionQuality {
+    pub(crate) fn stats(&self, market: Symbol) -> Option<FillStats> {
+        let fills = self.markets.get(&market)?;
+        Some(FillStats {
+            orders: fills.fill_rates.len(),
+            fill_rate: mean(&fills.fill_rates)?.round_dp(8),
+            adverse_selection: mean(&fills.adverse_selections).map(|mean| mean.round_dp(8)),
+        })
+    }
+
+    // Record the fill of a passive order, other orders are ignored.
+    pub(crate) fn record(&mut self, order: &Order, info: &OrderInfo, window: usize) {
+        if !order.post_only || order.size.is_zero() {
+            return;
+        }
+        let fills = self.markets.entry(order.market).or_default();
+        push(&mut fills.fill_rates, info.size / order.size, window);
+        if !info.size.is_zero() {
+            fills.pending.push((order.side, info.price));
+        }
+    }
+
+    // Compare the fills of the last step with the current price.
+    pub(crate) fn resolve(&mut self, market: Symbol, price: Decimal, window: usize) {
+        let Some(fills) = self.markets.get_mut(&market) else {
+            return;
+        };
+        for (side, fill_price) in std::mem::take(&mut fills.pending) {
+            if fill_price.is_zero() {
+                continue;
+            }
+            let adverse = match side {
+                Side::Buy => (fill_price - price) / fill_price,
+                Side::Sell => (price - fill_price) / fill_price,
+            };
+            push(&mut fills.adverse_selections, adverse, window);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::OrderStatus;
+    use uuid::Uuid;
+
+    fn order(market: Symbol, side: Side) -> Order {
+        Order {
+            order_id: Uuid::new_v4(),
+            market,
+            side,
+            size: dec!(2),
+            order_type: OrderType::Market,
+            reduce_only: false,
+            time_in_force: TimeInForce::ImmediateOrCancel,
+            post_only: false,
+            position_side: None,
+            time: Utc::now(),
+            current_price: dec!(100),
+        }
+    }
+
+    fn fill(order: &Order, size: Decimal) -> OrderInfo {
+        OrderInfo {
+            order_id: order.order_id,
+            market: order.market,
+            side: order.side,
+            size,
+            price: order.current_price,
+            time: order.time,
+            status: OrderStatus::settled(order.size, size),
+        }
+    }
+
+    #[test]
+    fn adapt_to_fills() {
+        let (btc, eth) = (Symbol::perp("BTC"), Symbol::perp("ETH"));
+        let execution = AdaptiveExecution {
+            min_samples: 2,
+            ..Default::default()
+        };
+        let mut quality = ExecutionQuality::default();
+        let time = Utc::now();
+
+        // Without statistics, orders are tried passively.
+        let mut orders = vec![order(btc, Side::Buy), order(eth, Side::Sell)];
+        let decisions = execution.apply(&mut orders, &quality, time);
+        assert!(decisions.iter().all(|d| d.tactic == Tactic::Passive));
+        assert_eq!(orders[0].order_type, OrderType::Limit(dec!(100)));
+        assert!(orders[0].post_only);
+
+        // BTC fills completely but the price falls after buying, ETH barely fills.
+        for _ in 0..2 {
+            quality.record(&orders[0], &fill(&orders[0], dec!(2)), execution.window);
+            quality.record(&orders[1], &fill(&orders[1], dec!(0.2)), execution.window);
+            quality.resolve(btc, dec!(99), execution.window);
+            quality.resolve(eth, dec!(100), execution.window);
+        }
+        assert_eq!(
+            quality.stats(btc),
+            Some(FillStats {
+                orders: 2,
+                fill_rate: dec!(1),
+                adverse_selection: Some(dec!(0.01)),
+            })
+        );
+        assert_eq!(quality.stats(eth).unwrap().fill_rate, dec!(0.1));
+
+        let mut orders = vec![order(btc, Side::Buy), order(eth, Side::Sell)];
+        let decisions = execution.apply(&mut orders, &quality, time);
+        assert!(decisions.iter().all(|d| d.tactic == Tactic::Market));
+        assert!(orders.iter().all(|o| o.order_type == OrderType::Market));
+
+        // Limit orders of the strategy are kept.
+        let mut orders = vec![Order {
+            order_type: OrderType::Limit(dec!(90)),
+            ..order(btc, Side::Buy)
+        }];
+        assert!(execution.apply(&mut orders, &quality, time).is_empty());
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+
+use super::Position;
+use crate::{Asset, PositionSide, Symbol};
+use rust_decimal::{prelude::Signed, Decimal};
+
+/// The quote value exposure of positions, netted across equivalent markets.
+/// Positions in markets with the same underlying, for example the same perp on
+/// different venues, offset each other for risk reporting and margin estimation,
+/// while each market keeps its own position for execution.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Exposure {
+    markets: HashMap<Symbol, Decimal>,
+    // Exposure per market and leg, before positions in opposite directions offset each other.
+    legs: HashMap<(Symbol, PositionSide), Decimal>,
+}
+
+impl Exposure {
+    pub(crate) fn new<'a, I: IntoIterator<Item = &'a Position>>(positions: I) -> Self {
+        let mut exposure = Exposure::default();
+        for position in positions {
+            let current = &position.current;
+            for (&symbol, &size) in &current.bundle.0 {
+                let price = current
+                    .valuation
+                    .0
+                    .get(&symbol)
+                    .cloned()
+                    .unwrap_or_default();
+                exposure.add(symbol, size, price * position.multiplier(symbol));
+            }
+        }
+
+        exposure
+    }
+
+    /// Exposure of the target sizes of positions, as they will be after the next execution.
+    pub(crate) fn target<'a, I: IntoIterator<Item = &'a Position>>(positions: I) -> Self {
+        let mut exposure = Exposure::default();
+        for position in positions {
+            for (&symbol, &size) in &position.next_size.0 {
+                let price = position
+                    .current
+                    .valuation
+                    .0
+                    .get(&symbol)
+                    .cloned()
+                    .unwrap_or_default();
+                exposure.add(symbol, size, price * position.multiplier(symbol));
+            }
+        }
+
+        exposure
+    }
+
+    fn add(&mut self, symbol: Symbol, size: Decimal, price: Decimal) {
+        *self.markets.entry(symbol).or_default() += size * price;
+        if !size.is_zero() {
+            *self
+                .legs
+                .entry((symbol, PositionSide::of(size)))
+                .or_default() += size * price;
+        }
+    }
+
+    /// Signed exposure of all markets.
+    pub fn markets(&self) -> impl Iterator<Item = (&Symbol, &Decimal)> {
+        self.markets.iter()
+    }
+
+    /// Signed exposure per market, positive for long and negative for short.
+    pub fn market(&self, symbol: Symbol) -> Decimal {
+        self.markets.get(&symbol).cloned().unwrap_or_default()
+    }
+
+    /// Signed exposure per underlying, with offsetting markets cancelling out.
+    pub fn net(&self) -> HashMap<Asset, Decimal> {
+        let mut net: HashMap<Asset, Decimal> = HashMap::new();
+        for (symbol, value) in &self.markets {
+            *net.entry(symbol.underlying()).or_default() += value;
+        }
+        net
+    }
+
+    /// Sum of the absolute exposures per market, without netting.
+    pub fn gross_total(&self) -> Decimal {
+        self.markets.values().map(|value| value.abs()).sum()
+    }
+
+    /// Signed exposure of a leg of a market, the long and short legs are margined
+    /// independently in hedged account mode.
+    pub fn leg(&self, symbol: Symbol, side: PositionSide) -> Decimal {
+        self.legs.get(&(symbol, side)).cloned().unwrap_or_default()
+    }
+
+    /// Sum of the absolute exposures per leg, without netting opposing positions in a market.
+    pub fn legs_total(&self) -> Decimal {
+        self.legs.values().map(|value| value.abs()).sum()
+    }
+
+    /// Sum of the absolute net exposures per underlying.
+    pub fn net_total(&self) -> Decimal {
+        self.net().values().map(|value| value.abs()).sum()
+    }
+}
+
+impl FromIterator<(Symbol, Decimal)> for Exposure {
+    fn from_iter<I: IntoIterator<Item = (Symbol, Decimal)>>(iter: I) -> Self {
+        let mut exposure = Exposure::default();
+        for (symbol, value) in iter {
+            exposure.add(symbol, value.signum(), value.abs());
+        }
+        exposure
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn position(symbol: Symbol, size: Decimal, price: Decimal) -> Position {
+        let mut position = Position::default();
+        position.current.valuation.0.insert(symbol, price);
+        *position.size(symbol) = size;
+        let order = position.order();
+        position.resize(order);
+        position
+    }
+
+    #[test]
+    fn offsetting_positions_cancel() {
+        let btc = Symbol::perp("BTC");
+        let eth = Symbol::perp("ETH");
+        let positions = vec![
+            position(btc, dec!(1), dec!(10000)),
+            position(btc, dec!(-0.5), dec!(10000)),
+            position(eth, dec!(-2), dec!(1000)),
+        ];
+
+        let exposure = Exposure::new(&positions);
+
+        assert_eq!(exposure.market(btc), dec!(5000));
+        assert_eq!(exposure.net().get(&Asset::new("BTC")), Some(&dec!(5000)));
+        assert_eq!(exposure.net().get(&Asset::new("ETH")), Some(&dec!(-2000)));
+        assert_eq!(exposure.net_total(), dec!(7000));
+        assert_eq!(exposure.gross_total(), dec!(7000));
+        assert_eq!(exposure.leg(btc, PositionSide::Short), dec!(-5000));
+        assert_eq!(exposure.legs_total(), dec!(17000));
+    }
+}
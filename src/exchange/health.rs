@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+
+use crate::Symbol;
+use chrono::{DateTime, Duration, Utc};
+
+/// How long watched markets may go without candles before their data is stale,
+/// e.g. because the venue is down, and how trading is protected while it is.
+#[derive(Debug, Clone, Default)]
+pub struct HealthChecks {
+    /// Markets without a candle for longer than this are stale, never if None.
+    pub max_staleness: Option<Duration>,
+    /// Thresholds of single markets, e.g. longer ones for markets that rarely trade.
+    pub market_staleness: HashMap<Symbol, Duration>,
+    /// What happens while any market is stale.
+    pub on_stale: OnStale,
+}
+
+impl HealthChecks {
+    /// How long the market may go without a candle.
+    pub fn max_staleness(&self, market: Symbol) -> Option<Duration> {
+        self.market_staleness
+            .get(&market)
+            .copied()
+            .or(self.max_staleness)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OnStale {
+    /// Only log the stale markets and report them to the API.
+    #[default]
+    Warn,
+    /// Do not evaluate the strategy until all markets are healthy again.
+    Pause,
+    /// Like pause, but also close the positions in stale markets.
+    Flatten,
+}
+
+/// The health of the data of a watched market.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MarketHealth {
+    pub market: Symbol,
+    /// The time of the last candle, None if there was none since the market was watched.
+    pub last_candle: Option<DateTime<Utc>>,
+    /// Whether the market had no candle for longer than allowed.
+    pub stale: bool,
+    // Staleness is measured from here until the first candle.
+    watched: DateTime<Utc>,
+}
+
+// The health of all watched markets.
+#[derive(Debug, Default)]
+pub(crate) struct Health {
+    markets: HashMap<Symbol, MarketHealth>,
+}
+
+impl Health {
+    pub fn watch(&mut self, market: Symbol, time: DateTime<Utc>) {
+        self.markets.entry(market).or_insert(MarketHealth {
+            market,
+            last_candle: None,
+            stale: false,
+            watched: time,
+        });
+    }
+
+    pub fn unwatch(&mut self, market: Symbol) {
+        self.markets.remove(&market);
+    }
+
+    pub fn get(&self, market: Symbol) -> Option<&MarketHealth> {
+        self.markets.get(&market)
+    }
+
+    pub fn stale(&self) -> impl Iterator<Item = Symbol> + '_ {
+        self.markets
+            .values()
+            .filter(|health| health.stale)
+            .map(|health| health.market)
+    }
+
+    // Record whether the market has a candle at the time, returning its health if it turned
+    // stale or healthy.
+    pub fn update(
+        &mut self,
+        market: Symbol,
+        time: DateTime<Utc>,
+        candle: bool,
+        checks: &HealthChecks,
+    ) -> Option<MarketHealth> {
+        let health = self.markets.get_mut(&market)?;
+        if candle {
+            health.last_candle = Some(time);
+        }
+        let since = health.last_candle.unwrap_or(health.watched);
+        let stale = checks
+            .max_staleness(market)
+            .is_some_and(|max_staleness| time - since > max_staleness);
+        if stale == health.stale {
+            return None;
+        }
+        health.stale = stale;
+        Some(*health)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn stale_without_candles() {
+        let (btc, eth) = (Symbol::perp("BTC"), Symbol::perp("ETH"));
+        let time = |minute| Utc.with_ymd_and_hms(2022, 1, 1, 0, minute, 0).unwrap();
+        let checks = HealthChecks {
+            max_staleness: Some(Duration::minutes(2)),
+            market_staleness: [(eth, Duration::minutes(10))].into_iter().collect(),
+            ..Default::default()
+        };
+        let mut health = Health::default();
+        health.watch(btc, time(0));
+        health.watch(eth, time(0));
+
+        assert_eq!(health.update(btc, time(1), true, &checks), None);
+        assert_eq!(health.update(btc, time(3), false, &checks), None);
+        let stale = health.update(btc, time(4), false, &checks).unwrap();
+        assert!(stale.stale);
+        assert_eq!(stale.last_candle, Some(time(1)));
+        assert_eq!(health.update(eth, time(4), false, &checks), None);
+        assert_eq!(health.stale().collect::<Vec<_>>(), vec![btc]);
+
+        let healthy = health.update(btc, time(5), true, &checks).unwrap();
+        assert!(!healthy.stale);
+        assert_eq!(health.stale().count(), 0);
+    }
+}
@@ -0,0 +1,47 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use uuid::{Builder, Uuid, Variant, Version};
+
+// Generates the ids of orders and positions, random by default and reproducible if seeded.
+#[derive(Default)]
+pub(crate) struct Ids {
+    rng: Option<ChaCha8Rng>,
+}
+
+impl Ids {
+    pub fn seeded(seed: u64) -> Self {
+        Ids {
+            rng: Some(ChaCha8Rng::seed_from_u64(seed)),
+        }
+    }
+
+    pub fn is_seeded(&self) -> bool {
+        self.rng.is_some()
+    }
+
+    pub fn next(&mut self) -> Uuid {
+        match &mut self.rng {
+            Some(rng) => Builder::from_bytes(rng.gen())
+                .set_variant(Variant::RFC4122)
+                .set_version(Version::Random)
+                .build(),
+            None => Uuid::new_v4(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeded_ids_repeat() {
+        let mut a = Ids::seeded(7);
+        let mut b = Ids::seeded(7);
+        let ids: Vec<Uuid> = (0..3).map(|_| a.next()).collect();
+        assert_eq!(ids, (0..3).map(|_| b.next()).collect::<Vec<_>>());
+        assert_ne!(ids[0], ids[1]);
+        assert_eq!(ids[0].get_version(), Some(Version::Random));
+        assert_ne!(Ids::default().next(), Ids::default().next());
+    }
+}
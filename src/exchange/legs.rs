@@ -0,0 +1,149 @@
+use super::{Bundle, PositionId};
+use crate::{Markets, Symbol};
+use chrono::{DateTime, Utc};
+use rust_decimal::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// How the orders of the symbols of a multi-leg position are executed, see `Position::with_leg_policy`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LegPolicy {
+    /// Every leg is ordered on its own, legs that fill less than others leave the position unbalanced.
+    #[default]
+    Independent,
+    /// The legs fill in proportion to their orders or not at all. If a leg fills less than the others,
+    /// the others are unwound at the market in the same step, down to the smallest filled fraction.
+    /// Whatever the position still had to order in this case is dropped.
+    AllOrNothing,
+}
+
+/// The legs of a position that did not fill in proportion to their orders in a step.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LegImbalance {
+    pub position: PositionId,
+    pub time: Option<DateTime<Utc>>,
+    /// The size ordered of each leg, negative for sells.
+    pub ordered: HashMap<Symbol, Decimal>,
+    /// The size filled of each leg, before unwinding.
+    pub filled: HashMap<Symbol, Decimal>,
+    /// The size filled by unwinding each leg, empty for independent legs.
+    pub unwound: HashMap<Symbol, Decimal>,
+}
+
+impl LegImbalance {
+    // The imbalance of the order of a position with the sizes that were filled,
+    // None if the order has less than two legs or all legs filled in proportion.
+    pub(crate) fn new(
+        position: PositionId,
+        time: Option<DateTime<Utc>>,
+        order: &Bundle,
+        filled: &Bundle,
+    ) -> Option<Self> {
+        let ordered: HashMap<Symbol, Decimal> = order
+            .0
+            .iter()
+            .filter(|(_, size)| !size.is_zero())
+            .map(|(&symbol, &size)| (symbol, size))
+            .collect();
+        if ordered.len() < 2 {
+            return None;
+        }
+        let filled: HashMap<Symbol, Decimal> = ordered
+            .keys()
+            .map(|&symbol| (symbol, filled.0.get(&symbol).cloned().unwrap_or_default()))
+            .collect();
+        let imbalance = LegImbalance {
+            position,
+            time,
+            ordered,
+            filled,
+            unwound: HashMap::new(),
+        };
+        let fraction = imbalance.filled_fraction();
+        imbalance
+            .ordered
+            .keys()
+            .any(|&symbol| imbalance.fraction(symbol) != fraction)
+            .then_some(imbalance)
+    }
+
+    fn fraction(&self, symbol: Symbol) -> Decimal {
+        self.filled[&symbol] / self.ordered[&symbol]
+    }
+
+    /// The smallest fraction of its order any leg filled.
+    pub fn filled_fraction(&self) -> Decimal {
+        self.ordered
+            .keys()
+            .map(|&symbol| self.fraction(symbol))
+            .min()
+            .unwrap_or_default()
+    }
+
+    /// The size of each leg beyond the smallest filled fraction, after unwinding.
+    /// Zero for all legs if the position is balanced again.
+    pub fn excess(&self) -> HashMap<Symbol, Decimal> {
+        let fraction = self.filled_fraction();
+        self.ordered
+            .iter()
+            .map(|(&symbol, &size)| {
+                let unwound = self.unwound.get(&symbol).cloned().unwrap_or_default();
+                (symbol, self.filled[&symbol] + unwound - size * fraction)
+            })
+            .collect()
+    }
+
+    // The order that unwinds the excess of the legs, rounded to the size increments of the markets.
+    pub(crate) fn unwind(&self, markets: &Markets) -> Bundle {
+        let mut unwind = Bundle::default();
+        for (symbol, excess) in self.excess() {
+            let size = markets
+                .market(symbol)
+                .map_or(excess, |market| market.round_size(excess));
+            if !size.is_zero() {
+                unwind.0.insert(symbol, -size);
+            }
+        }
+        unwind
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use uuid::Uuid;
+
+    fn bundle(sizes: &[(Symbol, Decimal)]) -> Bundle {
+        let mut bundle = Bundle::default();
+        bundle.0.extend(sizes.iter().cloned());
+        bundle
+    }
+
+    #[test]
+    fn unwind_to_smallest_fraction() {
+        let (btc, eth) = (Symbol::perp("BTC"), Symbol::perp("ETH"));
+        let order = bundle(&[(btc, dec!(1)), (eth, dec!(-10))]);
+
+        // Balanced legs and single legs are never imbalanced.
+        let balanced = bundle(&[(btc, dec!(0.5)), (eth, dec!(-5))]);
+        assert_eq!(
+            LegImbalance::new(Uuid::nil(), None, &order, &balanced),
+            None
+        );
+        let single = bundle(&[(btc, dec!(1))]);
+        assert_eq!(
+            LegImbalance::new(Uuid::nil(), None, &single, &bundle(&[])),
+            None
+        );
+
+        let filled = bundle(&[(btc, dec!(1)), (eth, dec!(-2.5))]);
+        let mut imbalance = LegImbalance::new(Uuid::nil(), None, &order, &filled).unwrap();
+        assert_eq!(imbalance.filled_fraction(), dec!(0.25));
+        let unwind = imbalance.unwind(&Markets::default());
+        assert_eq!(unwind, bundle(&[(btc, dec!(-0.75))]));
+
+        imbalance.unwound.extend(unwind.0);
+        assert!(imbalance.excess().values().all(|size| size.is_zero()));
+    }
+}
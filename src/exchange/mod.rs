@@ -1,33 +1,133 @@
+mod algo;
 mod bundle;
+mod cancellation;
+mod candle_queue;
+mod command;
+mod compliance;
+mod execution;
+mod exposure;
+mod health;
+mod ids;
+mod legs;
+mod netting;
 mod position;
+mod progress;
+mod reconciliation;
+mod session;
+mod step_log;
+mod timing;
+mod transfer;
+mod universe;
 mod valuation;
 mod valued_bundle;
 
+pub use algo::ExecutionAlgo;
 use bundle::Bundle;
-pub use position::Position;
+pub use cancellation::{CancellationToken, CtrlCHandler};
+use candle_queue::CandleQueue;
+pub use command::Command;
+pub use compliance::{Compliance, ComplianceContext, ComplianceRecord, Verdict};
+use execution::ExecutionQuality;
+pub use execution::{AdaptiveExecution, ExecutionDecision, FillStats, Tactic};
+pub use exposure::Exposure;
+use health::Health;
+pub use health::{HealthChecks, MarketHealth, OnStale};
+use ids::Ids;
+pub use legs::{LegImbalance, LegPolicy};
+pub use netting::Netting;
+pub use position::{Fill, Position, PositionId, Unfilled};
+#[cfg(feature = "progress-bar")]
+pub use progress::ProgressBar;
+use progress::ProgressTracker;
+pub use progress::{Progress, ProgressReporter};
+pub use reconciliation::{Reconciliation, WalletDiscrepancy};
+pub use session::{Session, SessionEvent, Snapshot};
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::{HashMap, HashSet},
     fmt::Debug,
+    panic::{self, AssertUnwindSafe},
+    sync::Arc,
     time::Instant,
 };
+pub use step_log::{PositionRecord, StepLog, StepRecord};
+pub use timing::StepTiming;
+pub use transfer::{Account, Transfer, TransferError};
+pub use universe::{LookAheadError, UniverseChange};
 use valuation::Valuation;
 use valued_bundle::ValuedBundle;
 
 use super::Wallet;
 use crate::{
-    apis::{Api, ApiError},
-    strategies::{OnError, Settings, Strategy},
-    Candle, CandleKey, MarketInfo, Markets, Order, Symbol,
+    apis::{Api, ApiError, ExecutionGap},
+    strategies::{OnError, OnForbiddenDirection, OnOverrun, Settings, StateStorage, Strategy},
+    AccountMode, Alert, AlertFilter, Alerter, Alerts, Asset, Candle, CandleKey, ClosedPosition,
+    CustomMetric, Direction, DustPolicy, ImpactModel, Interval, MarketInfo, Markets, Order,
+    PriceSource, Report, Risk, SpreadEstimator, Symbol, Vwap, VwapAnchor,
+};
+use crate::{
+    indicators::{Indicator, IndicatorHandle, Indicators},
+    OrderInfo, OrderStatus, OrderType, PendingOrder, PositionSide, PrecisionPolicy, Residuals,
+    RoundingSite, Side,
 };
-use crate::{OrderInfo, Side, WalletError};
 use chrono::{DateTime, Duration, Utc};
 use futures_util::{future::join_all, try_join};
 use rust_decimal::prelude::*;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tracing::Instrument;
 
 pub type AnyError = Box<dyn std::error::Error>;
 use thiserror::Error;
 
-type Candles = HashMap<Symbol, VecDeque<(CandleKey, Option<Candle>)>>;
+type Candles = HashMap<Symbol, CandleQueue>;
+// The leg each symbol of a position is held in.
+type Legs = HashMap<Symbol, PositionSide>;
+
+// The changes of the wallet and positions caused by the fills of a step.
+#[derive(Default)]
+struct Settlement {
+    value_diff: Decimal,
+    closed: Vec<Position>,
+    opened: Vec<Alert>,
+}
+
+impl Settlement {
+    // Adapt a position to the result of its order.
+    fn resize(&mut self, position: &mut Position, order_result: ValuedBundle, time: DateTime<Utc>) {
+        let opened = position.open.is_some();
+        let value = position.value();
+
+        position.resize(order_result.clone());
+
+        assert_ne!(
+            position.symbols().count(),
+            0,
+            "order result: {:?}, position: {:?}",
+            order_result,
+            position
+        );
+
+        tracing::error!("resized position has value {}", position.value());
+
+        // Change wallet value.
+        if position.closed() {
+            // Position gets closed.
+            self.value_diff += position.value();
+            self.closed.push(position.clone());
+        } else if opened {
+            // Position gets scaled.
+            self.value_diff -= position.value() - value;
+        } else {
+            // Position gets opened.
+            self.value_diff -= position.value();
+            self.opened.push(Alert::Opened {
+                time,
+                position: position.id(),
+                symbols: position.symbols().collect(),
+                value: position.value(),
+            });
+        }
+    }
+}
 
 #[derive(Error, Debug)]
 pub enum PrepareError {
@@ -35,8 +135,19 @@ pub enum PrepareError {
     InsufficientAssets,
     #[error("Market closed.")]
     MarketClosed,
+    #[error("Leverage exceeds the maximum leverage.")]
+    LeverageTooHigh,
+    #[error("Positions in this direction are not allowed.")]
+    DirectionNotAllowed,
+    #[error("Another position holds {0} in the opposite direction, which netting does not allow.")]
+    OpposingPosition(Symbol),
 }
 
+/// The strategy panicked while it was evaluated, with the panic message.
+#[derive(Error, Debug)]
+#[error("The strategy panicked: {0}")]
+pub struct StrategyPanic(pub String);
+
 /// This struct keeps track of the state of the exchange, your positions, your wallet etc.
 pub struct Exchange<A: Api> {
     api: A,
@@ -45,13 +156,104 @@ pub struct Exchange<A: Api> {
     // Current candles of all subscribed tickers.
     // TODO: Add this to markets?
     candles: Candles,
+    // Release only the candles of the current step, see `Settings::look_ahead_guard`.
+    look_ahead_guard: bool,
+    // Spread estimates of all subscribed tickers.
+    spreads: HashMap<Symbol, SpreadEstimator>,
+    vwaps: HashMap<Symbol, Vwap>,
+    vwap_anchor: VwapAnchor,
+    indicators: Indicators,
+    // Number of known candles of all subscribed tickers since they were subscribed.
+    candles_seen: HashMap<Symbol, usize>,
+    // The time of the last candle of all subscribed tickers the estimators were updated with,
+    // as the same candle stays current for several steps of a gap.
+    candles_applied: HashMap<Symbol, DateTime<Utc>>,
+    // The markets selected by `select_universe`.
+    universe: HashSet<Symbol>,
+    // Return history of all subscribed tickers.
+    risk: Risk,
+    // Whether the candles of all subscribed tickers are recent.
+    health: Health,
+    impact: ImpactModel,
+    // The trading interval, known once the strategy is initialized.
+    interval: Option<Interval>,
+    dust: DustPolicy,
     markets: Markets,
+    // When the market metadata was refreshed last.
+    markets_updated: Option<DateTime<Utc>>,
+    // Whether the strategy requested to refresh the market metadata in the next step.
+    refresh_markets: bool,
     current_time: DateTime<Utc>,
     real_time: bool,
     open_positions: Vec<Position>,
     //next_open_positions: Vec<Position>,
     debug_msg: Option<Box<dyn Debug>>,
     quit: bool,
+    cancellation: Option<CancellationToken>,
+    // Cancels on Ctrl+C as long as the exchange lives.
+    ctrl_c: Option<CtrlCHandler>,
+    commands: Option<UnboundedReceiver<Command>>,
+    paused: bool,
+    // Overrides the total, for strategies that only trade with a part of the capital.
+    budget: Option<Decimal>,
+    report: Option<Report>,
+    timing: StepTiming,
+    step_log: Option<StepLog>,
+    // Positions closed since the last record, added to the report.
+    closed_positions: Vec<ClosedPosition>,
+    // When the strategy state was saved last.
+    state_saved: Option<DateTime<Utc>>,
+    // Stop running once this time is reached.
+    end_time: Option<DateTime<Utc>>,
+    // Whether to close all positions at the end time.
+    close_at_end: bool,
+    netting: Option<Netting>,
+    execution: Option<AdaptiveExecution>,
+    execution_quality: ExecutionQuality,
+    // Execution tactics chosen since the last record, added to the report.
+    execution_decisions: Vec<ExecutionDecision>,
+    // The multi-leg positions that did not fill in proportion in the last execution.
+    leg_imbalances: Vec<LegImbalance>,
+    direction: Direction,
+    on_forbidden_direction: OnForbiddenDirection,
+    account_mode: AccountMode,
+    fill_timeout: Duration,
+    fill_poll: Duration,
+    algo: ExecutionAlgo,
+    // Since when the orders of symbols are held back for netting.
+    held: HashMap<Symbol, DateTime<Utc>>,
+    session: Option<Session>,
+    compliance: Option<Arc<dyn Compliance>>,
+    // Orders modified or vetoed since the last record, added to the report.
+    compliance_records: Vec<ComplianceRecord>,
+    strategy_name: &'static str,
+    // The serialized parameters of the strategy, added to the report.
+    parameters: Option<serde_json::Value>,
+    alerts: Option<Alerts>,
+    progress: Option<ProgressTracker>,
+    // The quote value of the fills since the last record, added to the report.
+    traded_value: Decimal,
+    // The panic message once the strategy panicked, it is not evaluated anymore until resumed.
+    poisoned: Option<String>,
+    // Interest paid for borrowing since the last record, added to the report.
+    borrow_costs: Decimal,
+    // The prices of the current step from the price source, if it is not the close.
+    prices: HashMap<Symbol, Decimal>,
+    // Orders not settled after the fill timeout, whose rest could not be cancelled yet.
+    pending: Vec<PendingOrder>,
+    // Transfers requested by the strategy, executed before the orders of the step.
+    transfers: Vec<Transfer>,
+    precision: PrecisionPolicy,
+    // Rounding residuals since the last record, added to the report.
+    residuals: Residuals,
+    reconciliation: Option<Reconciliation>,
+    // When the wallet was reconciled last, and the discrepancies since the last record.
+    reconciled: Option<DateTime<Utc>>,
+    wallet_discrepancies: Vec<WalletDiscrepancy>,
+    // Values of custom series since the last record, added to the report.
+    metrics: Vec<CustomMetric>,
+    // Behind a lock since orders are placed with a shared reference.
+    ids: std::sync::Mutex<Ids>,
 }
 
 impl<A: Api> Exchange<A> {
@@ -63,16 +265,163 @@ impl<A: Api> Exchange<A> {
             //open_positions: Vec::new(),
             //closed_positions: Vec::new(),
             candles: HashMap::new(),
+            look_ahead_guard: false,
+            candles_seen: HashMap::new(),
+            candles_applied: HashMap::new(),
+            universe: HashSet::new(),
+            spreads: HashMap::new(),
+            vwaps: HashMap::new(),
+            vwap_anchor: VwapAnchor::default(),
+            indicators: Indicators::default(),
+            risk: Risk::default(),
+            health: Health::default(),
+            impact: ImpactModel::default(),
+            interval: None,
+            dust: DustPolicy::default(),
             markets: Markets::default(),
+            markets_updated: None,
+            refresh_markets: false,
             api,
             real_time: false,
             open_positions: Vec::new(),
             //next_open_positions: Vec::new(),
             debug_msg: None,
             quit: false,
+            cancellation: None,
+            ctrl_c: None,
+            commands: None,
+            paused: false,
+            budget: None,
+            report: None,
+            timing: StepTiming::default(),
+            step_log: None,
+            closed_positions: Vec::new(),
+            state_saved: None,
+            end_time: None,
+            close_at_end: false,
+            netting: None,
+            execution: None,
+            execution_quality: ExecutionQuality::default(),
+            execution_decisions: Vec::new(),
+            leg_imbalances: Vec::new(),
+            direction: Direction::Both,
+            on_forbidden_direction: OnForbiddenDirection::Reject,
+            account_mode: AccountMode::Netting,
+            fill_timeout: Duration::zero(),
+            fill_poll: Duration::zero(),
+            algo: ExecutionAlgo::Immediate,
+            held: HashMap::new(),
+            session: None,
+            compliance: None,
+            compliance_records: Vec::new(),
+            strategy_name: "",
+            parameters: None,
+            alerts: None,
+            progress: None,
+            traded_value: Decimal::ZERO,
+            poisoned: None,
+            borrow_costs: Decimal::ZERO,
+            prices: HashMap::new(),
+            pending: Vec::new(),
+            transfers: Vec::new(),
+            precision: PrecisionPolicy::default(),
+            residuals: Residuals::default(),
+            reconciliation: None,
+            reconciled: None,
+            wallet_discrepancies: Vec::new(),
+            metrics: Vec::new(),
+            ids: std::sync::Mutex::default(),
         }
     }
 
+    /// Stop running once the token is cancelled, closing all positions.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Stop running the same way when the process receives Ctrl+C during the run.
+    pub fn with_ctrl_c(mut self) -> Self {
+        let token = self.cancellation.get_or_insert_with(CancellationToken::new);
+        self.ctrl_c = Some(token.cancel_on_ctrl_c());
+        self
+    }
+
+    /// Publish snapshots and events of the run to the subscribers of the session.
+    pub fn with_session(mut self, session: Session) -> Self {
+        self.session = Some(session);
+        self
+    }
+
+    /// Check every outgoing order for compliance, after the strategy modified its orders.
+    pub fn with_compliance(mut self, compliance: Arc<dyn Compliance>) -> Self {
+        self.compliance = Some(compliance);
+        self
+    }
+
+    /// Push notifications about opened and closed positions, triggers and errors.
+    pub fn with_alerter(mut self, alerter: Arc<dyn Alerter>, filter: AlertFilter) -> Self {
+        self.alerts = Some(Alerts::new(alerter, filter));
+        self
+    }
+
+    /// Report the progress while catching up to the end time or to now, e.g. in backtests.
+    pub fn with_progress(mut self, reporter: Arc<dyn ProgressReporter>) -> Self {
+        self.progress = Some(ProgressTracker::new(reporter));
+        self
+    }
+
+    /// Send an alert, if an alerter is set and its filter allows it.
+    pub fn alert(&self, alert: Alert) {
+        if let Some(alerts) = &self.alerts {
+            alerts.send(alert);
+        }
+    }
+
+    /// Log a value of a custom series at the current time, e.g. the strength of a signal.
+    /// The values are passed to the API, e.g. to be stored by the monitor next to the equity,
+    /// and are included in the report.
+    pub fn metric(&mut self, name: impl Into<String>, value: Decimal) {
+        let name = name.into();
+        self.api.metric(self.current_time, &name, value);
+        self.metrics.push(CustomMetric {
+            name,
+            time: self.current_time,
+            value,
+        });
+    }
+
+    /// Use another model to estimate the market impact of orders.
+    pub fn with_impact_model(mut self, impact: ImpactModel) -> Self {
+        self.impact = impact;
+        self
+    }
+
+    /// Append the inputs and outputs of every step to a log, to replay steps exactly.
+    pub fn with_step_log(mut self, log: StepLog) -> Self {
+        self.step_log = Some(log);
+        self
+    }
+
+    /// Stop running at the time and return the report, keeping positions open
+    /// unless closing them at the end is enabled.
+    pub fn until(mut self, end_time: DateTime<Utc>) -> Self {
+        self.end_time = Some(end_time);
+        self
+    }
+
+    /// Close all positions once the end time is reached, so the report contains their results.
+    pub fn with_close_at_end(mut self, close_at_end: bool) -> Self {
+        self.close_at_end = close_at_end;
+        self
+    }
+
+    /// Receive operator commands from a channel, in addition to the commands of the API.
+    pub fn with_commands(mut self, commands: UnboundedReceiver<Command>) -> Self {
+        self.commands = Some(commands);
+        self
+    }
+
     pub fn current_time(&self) -> DateTime<Utc> {
         self.current_time
     }
@@ -86,29 +435,186 @@ impl<A: Api> Exchange<A> {
         self.markets.markets().map(|(_, info)| info)
     }
 
+    /// Refresh the market metadata in the next step, also in backtests
+    /// where it is otherwise only loaded once.
+    pub fn refresh_markets(&mut self) {
+        self.refresh_markets = true;
+    }
+
     pub fn market(&self, symbol: Symbol) -> &MarketInfo {
         self.markets.market(symbol).unwrap()
     }
 
     /// Fetch the current candle of a market.
     pub fn candle(&self, market: Symbol) -> Option<&Candle> {
-        let front = self.candles.get(&market)?.front()?;
+        let front = self.current_candle(market)?;
         assert_eq!(front.0.time, self.current_time);
         front.1.as_ref()
     }
 
+    // The candle released at the current step, see `CandleQueue::current`.
+    fn current_candle(&self, market: Symbol) -> Option<&(CandleKey, Option<Candle>)> {
+        self.candles
+            .get(&market)?
+            .current(self.current_time, self.look_ahead_guard)
+    }
+
     // Fetch the current price for a market.
     pub fn price(&self, market: Symbol) -> Option<Decimal> {
         self.candle(market).map(|candle| candle.close)
     }
-    /// Begin watching a market.
+
+    /// The bid/ask spread of a market relative to its price, estimated from past candles.
+    pub fn spread(&self, market: Symbol) -> Option<Decimal> {
+        self.spreads.get(&market)?.spread()
+    }
+
+    /// The volume-weighted average price of a market since the anchor in the settings,
+    /// including the current candle.
+    pub fn vwap(&self, market: Symbol) -> Option<Decimal> {
+        self.vwaps.get(&market)?.vwap()
+    }
+
+    /// Attach an indicator to a market, which is watched if it is not already.
+    /// The indicator is updated with every candle of the market from the next step on,
+    /// until the market is unwatched.
+    pub fn attach<I: Indicator>(&mut self, market: Symbol, indicator: I) -> IndicatorHandle<I> {
+        self.watch(market);
+        self.indicators.attach(market, indicator)
+    }
+
+    /// The current value of an attached indicator, None until it is warmed up
+    /// or if its market was unwatched.
+    pub fn indicator<I: Indicator>(&self, handle: IndicatorHandle<I>) -> Option<I::Output> {
+        self.indicators.get(handle)?.value()
+    }
+
+    /// Begin watching a market, also mid-run. Watching a watched market again has no effect.
     pub fn watch(&mut self, market: Symbol) {
-        self.candles.insert(market, VecDeque::new());
+        if self.candles.contains_key(&market) {
+            return;
+        }
+        self.candles.insert(market, CandleQueue::default());
+        self.spreads.insert(market, SpreadEstimator::default());
+        self.vwaps.insert(market, Vwap::new(self.vwap_anchor));
+        self.candles_seen.insert(market, 0);
+        self.health.watch(market, self.current_time);
+    }
+
+    /// Whether at least this many candles of the market are known since it was watched,
+    /// e.g. to let indicators and risk estimates of newly added markets warm up before trading them.
+    pub fn warmed_up(&self, market: Symbol, candles: usize) -> bool {
+        self.candles_seen
+            .get(&market)
+            .map(|&seen| seen >= candles)
+            .unwrap_or(false)
+    }
+
+    /// Watch the `n` markets with the highest daily quote volume among those passing the filter,
+    /// and stop watching the markets selected before that dropped out. Markets held by positions
+    /// stay watched until the positions are closed, markets watched with `watch` are not touched.
+    /// Call it again whenever the universe should be recomputed, e.g. once a day.
+    ///
+    /// Only the current daily quote volume of the markets is known, so this fails before the
+    /// exchange runs in real time, as a backtest would select markets by their future volume.
+    pub fn select_universe(
+        &mut self,
+        filter: impl Fn(&MarketInfo) -> bool,
+        n: usize,
+    ) -> Result<UniverseChange, LookAheadError> {
+        if !self.real_time {
+            return Err(LookAheadError);
+        }
+        let selected = universe::top_by_volume(&self.markets, filter, n);
+        let held: HashSet<Symbol> = self
+            .positions()
+            .flat_map(|position| {
+                position
+                    .symbols()
+                    .chain(position.next_size.0.keys().copied())
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+
+        let mut change = UniverseChange::default();
+        let mut previous: Vec<Symbol> = self.universe.iter().copied().collect();
+        previous.sort_by_key(|symbol| symbol.to_string());
+        for market in previous {
+            if selected.contains(&market) {
+                continue;
+            }
+            if held.contains(&market) {
+                change.held.push(market);
+            } else {
+                self.universe.remove(&market);
+                self.unwatch(market);
+                change.removed.push(market);
+            }
+        }
+        for market in selected {
+            if !self.candles.contains_key(&market) {
+                self.universe.insert(market);
+                self.watch(market);
+                change.added.push(market);
+            }
+        }
+
+        if !change.added.is_empty() || !change.removed.is_empty() {
+            tracing::info!(
+                "Universe changed, added: {:?}, removed: {:?}, held: {:?}",
+                change.added,
+                change.removed,
+                change.held
+            );
+        }
+        Ok(change)
+    }
+
+    /// The markets selected by `select_universe`, including those still held by positions.
+    pub fn universe(&self) -> impl Iterator<Item = Symbol> + '_ {
+        self.universe.iter().copied()
+    }
+
+    /// Whether the candles of a watched market are recent, see `Settings::health`.
+    pub fn health(&self, market: Symbol) -> Option<&MarketHealth> {
+        self.health.get(market)
+    }
+
+    /// The watched markets without recent candles.
+    pub fn stale_markets(&self) -> impl Iterator<Item = Symbol> + '_ {
+        self.health.stale()
+    }
+
+    /// Volatilities, correlations and value at risk estimated from past candles.
+    pub fn risk(&self) -> &Risk {
+        &self.risk
+    }
+
+    /// The largest order size of a market that is expected to move the price by at most
+    /// `max_impact`, e.g. 0.001 for 10 bps, given its daily volume and volatility.
+    /// Returns None if the market is not watched long enough to estimate its volatility.
+    pub fn max_order_size(&self, market: Symbol, max_impact: Decimal) -> Option<Decimal> {
+        let price = self.price(market)?;
+        let length = self.interval?.length(self.current_time).num_seconds() as f64;
+        let daily_volatility = self.risk.volatility(market)?
+            * (Duration::days(1).num_seconds() as f64 / length).sqrt();
+        let info = self.markets.market(market)?;
+        let max_value =
+            self.impact
+                .max_value(max_impact, info.daily_quote_volume, daily_volatility)?;
+        Some(max_value / info.contract_value(price))
     }
 
     /// Stop watching a market.
     pub fn unwatch(&mut self, market: Symbol) {
         self.candles.remove(&market);
+        self.spreads.remove(&market);
+        self.vwaps.remove(&market);
+        self.indicators.remove(market);
+        self.candles_seen.remove(&market);
+        self.candles_applied.remove(&market);
+        self.risk.remove(market);
+        self.health.unwatch(market);
     }
 
     /// Quit trading.
@@ -116,11 +622,70 @@ impl<A: Api> Exchange<A> {
         self.quit = true;
     }
 
-    /// Enter a new position.
-    pub fn open(&mut self, mut position: Position) -> Result<&Position, WalletError> {
-        position.fit(self);
+    /// Enter a new position, returning its id to look it up in later steps.
+    pub fn open(&mut self, mut position: Position) -> Result<PositionId, PrepareError> {
+        let max_leverage = self
+            .api
+            .margin()
+            .map(|margin| margin.max_leverage)
+            .unwrap_or(Decimal::ONE);
+        if position.leverage() > max_leverage {
+            return Err(PrepareError::LeverageTooHigh);
+        }
+        if self.api.margin().is_some() {
+            // Positions that were not filled yet need their margin too.
+            let pending: Decimal = self
+                .open_positions
+                .iter()
+                .filter(|open| open.open.is_none())
+                .map(|open| self.required_margin(open))
+                .sum();
+            if pending + self.required_margin(&position) > self.available_margin() {
+                return Err(PrepareError::InsufficientAssets);
+            }
+        }
+
+        let forbidden: Vec<Symbol> = position
+            .next_size
+            .0
+            .iter()
+            .filter(|(_, size)| !self.direction.allows(**size))
+            .map(|(symbol, _)| *symbol)
+            .collect();
+        if !forbidden.is_empty() {
+            if self.on_forbidden_direction == OnForbiddenDirection::Reject {
+                return Err(PrepareError::DirectionNotAllowed);
+            }
+            for symbol in forbidden {
+                position.next_size.0.remove(&symbol);
+            }
+            if position.removable() {
+                return Err(PrepareError::DirectionNotAllowed);
+            }
+        }
+
+        if self.account_mode == AccountMode::Netting {
+            for (&symbol, &size) in &position.next_size.0 {
+                let opposing = self.open_positions.iter().any(|open| {
+                    open.leg(symbol)
+                        .is_some_and(|leg| leg != PositionSide::of(size))
+                });
+                if !size.is_zero() && opposing {
+                    return Err(PrepareError::OpposingPosition(symbol));
+                }
+            }
+        }
+
+        let residual = position.fit(self);
+        self.residuals.record(RoundingSite::Fit, residual);
+        // Strategies create positions with random ids.
+        let ids = self.ids.get_mut().unwrap();
+        if ids.is_seeded() {
+            position.id = ids.next();
+        }
+        let id = position.id();
         self.open_positions.push(position);
-        Ok(self.open_positions.last().unwrap())
+        Ok(id)
     }
 
     /*
@@ -156,19 +721,155 @@ impl<A: Api> Exchange<A> {
         self.open_positions.iter()
     }
 
+    /// The open position with the id, None once it was closed.
+    pub fn position(&self, id: PositionId) -> Option<&Position> {
+        self.open_positions
+            .iter()
+            .find(|position| position.id() == id)
+    }
+
+    pub fn position_mut(&mut self, id: PositionId) -> Option<&mut Position> {
+        self.open_positions
+            .iter_mut()
+            .find(|position| position.id() == id)
+    }
+
     pub fn close_all(&mut self) {
         for position in self.positions_mut() {
             position.close();
         }
     }
 
+    // Replace the open positions, used to give strategies a partial view of the exchange.
+    pub(crate) fn swap_positions(&mut self, positions: Vec<Position>) -> Vec<Position> {
+        std::mem::replace(&mut self.open_positions, positions)
+    }
+
+    pub(crate) fn set_budget(&mut self, budget: Option<Decimal>) {
+        self.budget = budget;
+    }
+
+    /// Exposure of the open positions, netted across equivalent markets.
+    pub fn exposure(&self) -> Exposure {
+        Exposure::new(&self.open_positions)
+    }
+
+    /// Exposure of the positions as they will be after the next execution.
+    pub fn target_exposure(&self) -> Exposure {
+        Exposure::target(&self.open_positions)
+    }
+
+    /// How much worse recent fills were than the simulation predicted, if monitored.
+    pub fn execution_gap(&self) -> Option<ExecutionGap> {
+        self.api.execution_gap()
+    }
+
+    /// Fill statistics of the recent passive orders of a market, tracked with adaptive execution.
+    pub fn fill_stats(&self, market: Symbol) -> Option<FillStats> {
+        self.execution_quality.stats(market)
+    }
+
+    /// Move funds into or out of the trading account, e.g. to rebalance margin between strategies.
+    /// The transfer is executed after the evaluation, before the orders of the step.
+    /// Outgoing transfers are limited to the free balance, less the transfers requested before.
+    pub fn transfer(&mut self, transfer: Transfer) -> Result<(), TransferError> {
+        if transfer.amount <= Decimal::ZERO {
+            return Err(TransferError::InvalidAmount);
+        }
+        if (transfer.from == Account::Trading) == (transfer.to == Account::Trading) {
+            return Err(TransferError::NotTradingAccount);
+        }
+        if transfer.from == Account::Trading {
+            let requested: Decimal = self
+                .transfers
+                .iter()
+                .filter(|requested| requested.asset == transfer.asset)
+                .map(Transfer::change)
+                .sum();
+            let available = self.wallet.free(transfer.asset) + requested;
+            if transfer.amount > available {
+                return Err(TransferError::NotEnoughFree {
+                    asset: transfer.asset,
+                    available: available.max(Decimal::ZERO),
+                });
+            }
+        }
+        self.transfers.push(transfer);
+        Ok(())
+    }
+
+    /// Orders that were not settled within the fill timeout and are still open at the venue.
+    /// Cancelling them is retried every step, fills after booking are not attributed to positions.
+    pub fn pending_orders(&self) -> &[PendingOrder] {
+        &self.pending
+    }
+
+    /// The multi-leg positions whose legs did not fill in proportion to their orders
+    /// in the last execution, with what was unwound of all-or-nothing positions.
+    pub fn leg_imbalances(&self) -> &[LegImbalance] {
+        &self.leg_imbalances
+    }
+
+    /// Timing of the last step and overrun statistics.
+    pub fn timing(&self) -> &StepTiming {
+        &self.timing
+    }
+
     /// Get wallet.
     pub fn wallet(&self) -> &Wallet {
         &self.wallet
     }
 
+    /// The value of the wallet backing positions, in the quote asset. With margin trading,
+    /// other assets count with their collateral weight, see [Margin](crate::apis::Margin).
+    pub fn collateral(&self) -> Decimal {
+        let weights = self
+            .api
+            .margin()
+            .map(|margin| margin.collateral)
+            .unwrap_or_default();
+        self.wallet.collateral(
+            self.api.quote_asset(),
+            |asset| self.asset_price(asset),
+            &weights,
+        )
+    }
+
+    /// The collateral plus the unrealized profit and loss of the open positions,
+    /// the margin left for new positions when trading with margin.
+    pub fn available_margin(&self) -> Decimal {
+        self.collateral()
+            + self
+                .open_positions
+                .iter()
+                .map(Position::unrealized_pnl)
+                .sum::<Decimal>()
+    }
+
+    // The margin a position needs for its next size at the current prices.
+    fn required_margin(&self, position: &Position) -> Decimal {
+        let notional: Decimal = position
+            .next_size
+            .0
+            .iter()
+            .filter_map(|(&symbol, size)| {
+                let (_, candle) = self.current_candle(symbol)?;
+                Some(size.abs() * candle.as_ref()?.close * self.markets.multiplier(symbol))
+            })
+            .sum();
+        notional / position.leverage()
+    }
+
     pub fn total(&self) -> Decimal {
-        let wallet_total = self.wallet.total(self.api.quote_asset());
+        if let Some(budget) = self.budget {
+            return budget;
+        }
+
+        let wallet_total = self.wallet.value(
+            self.api.quote_asset(),
+            |asset| self.asset_price(asset),
+            &self.dust,
+        );
         let positions_total: Decimal = self
             .open_positions
             .iter()
@@ -177,6 +878,46 @@ impl<A: Api> Exchange<A> {
 
         wallet_total + positions_total
     }
+    // The latest price of an asset in the quote asset, from its perpetual if watched.
+    fn asset_price(&self, asset: Asset) -> Option<Decimal> {
+        let (_, candle) = self.current_candle(Symbol::Perp(asset))?;
+        candle.map(|candle| candle.close)
+    }
+
+    // Convert dust to the quote asset, if the policy asks for it.
+    async fn consolidate_dust(&mut self) {
+        if !self.dust.consolidate {
+            return;
+        }
+        let quote = self.api.quote_asset();
+        let dust = self
+            .wallet
+            .dust(quote, |asset| self.asset_price(asset), &self.dust);
+        if dust.is_empty() {
+            return;
+        }
+
+        match self
+            .api
+            .convert_dust(dust.iter().map(|(asset, _)| *asset).collect())
+            .await
+        {
+            Ok(()) => {
+                for (asset, qty) in dust {
+                    let value = qty * self.asset_price(asset).unwrap_or_default();
+                    tracing::info!("Converted {} {} to {} {}.", qty, asset, value, quote);
+                    self.wallet.total.remove(&asset);
+                    self.wallet.free.remove(&asset);
+                    self.wallet.deposit(value, quote);
+                }
+            }
+            Err(ApiError::Unsupported) => {
+                tracing::warn!("{} does not support converting dust.", A::NAME);
+                self.dust.consolidate = false;
+            }
+            Err(err) => tracing::error!("Could not convert dust: {}", err),
+        }
+    }
     /*
     pub fn round_size(&self, symbol: Symbol, size: Decimal) -> Decimal {
         let increment = self.markets.market(symbol).unwrap().size_increment;
@@ -209,69 +950,364 @@ impl<A: Api> Exchange<A> {
         S: Strategy<A>,
     {
         loop {
+            if self.is_cancelled() {
+                tracing::info!("Strategy cancelled.");
+                if !matches!(settings.on_error, OnError::Return) {
+                    tracing::info!("Exiting all positions.");
+                    self.close_all();
+                    self.execute().await?;
+                }
+                self.record();
+                return Ok(());
+            }
+
+            if matches!(self.end_time, Some(end_time) if self.current_time >= end_time) {
+                tracing::info!("Reached the end time.");
+                if self.close_at_end && !self.open_positions.is_empty() {
+                    tracing::info!("Exiting all positions.");
+                    self.close_all();
+                    self.execute().await?;
+                    self.record();
+                }
+                return Ok(());
+            }
+
             // Duration to wait until next candle is available,
             // if less than zero, the candle should be available.
-            let mut wait_duration = self.current_time + settings.interval - Utc::now();
+            let mut wait_duration =
+                settings.interval.next(self.current_time) + settings.clock_skew - Utc::now();
             if wait_duration <= Duration::zero() {
-
-                let start_instant = Instant::now();
-                // Update wallet and market info.
-                self.update(settings, &mut wait_duration).await?;
-                let update_duration = start_instant.elapsed();
-
-
-                // Update position value.
-                self.valuate();
-
-                let start_instant = Instant::now();
-                strategy.eval(self)?;
-                let strategy_eval_duration = start_instant.elapsed();
-
-                // Update position value again for potential new positions.
-                self.valuate();
-
-                /*
-                log::trace!("Exiting positions.");
-                self.exit_many().await?;
-                log::trace!("Entering positions.");
-                self.enter_many().await?;
-                */
-                let start_instant = Instant::now();
-                self.execute().await?;
-                let execute_duration = start_instant.elapsed();
-
-                // Evaluate strategy and handle errors.
-                log::info!(
-                    "Ran strategy for time {}, total value: {}, open positions: {}, update: {}ms, evaluation: {}ms, execution: {}ms",
-                    self.current_time,
-                    self.total(),
-                    self.open_positions.len(),
-                    update_duration.as_millis(),
-                    strategy_eval_duration.as_millis(),
-                    execute_duration.as_millis()
+                let span = tracing::info_span!(
+                    "step",
+                    strategy = S::NAME,
+                    time = %self.current_time,
+                    session_id = tracing::field::Empty,
                 );
+                if let Some(session_id) = self.api.session_id() {
+                    span.record("session_id", tracing::field::display(session_id));
+                }
+                self.run_step(strategy, settings, &mut wait_duration)
+                    .instrument(span)
+                    .await?;
 
-                self.api.status(self.current_time, self.total());
-                self.step(settings);
+                if self.quit {
+                    tracing::info!("Quitting strategy.");
+                    return Ok(());
+                }
             } else {
                 /*
                 for (_, candles) in &self.candles {
                     assert!(candles.is_empty(), "{:?}", candles);
                 }
                 */
-                log::trace!("Waiting {} for new candles.", wait_duration);
+                tracing::trace!("Waiting {} for new candles.", wait_duration);
                 // Wait until next candles should be available.
+                if let Some(progress) = &mut self.progress {
+                    progress.finish(self.current_time, self.end_time);
+                }
                 self.real_time = true;
-                tokio::time::sleep(wait_duration.to_std().expect("Converting to std")).await;
+                let wait_duration = wait_duration.to_std().expect("Converting to std");
+                match &self.cancellation {
+                    // Stop waiting early if cancelled.
+                    Some(token) => {
+                        tokio::time::timeout(wait_duration, token.cancelled())
+                            .await
+                            .ok();
+                    }
+                    None => tokio::time::sleep(wait_duration).await,
+                }
+            }
+        }
+    }
+
+    // Update, evaluate and execute one step, returning the submitted orders with their fills.
+    pub(crate) async fn run_step<S>(
+        &mut self,
+        strategy: &mut S,
+        settings: &Settings,
+        wait_duration: &mut Duration,
+    ) -> Result<Vec<(Order, OrderInfo)>, AnyError>
+    where
+        S: Strategy<A>,
+    {
+        let start_instant = Instant::now();
+        // Update wallet and market info.
+        self.update(settings, wait_duration).await?;
+        let update_duration = start_instant.elapsed();
+        let mut step_record = self.step_log.is_some().then(|| StepRecord::inputs(self));
+        let healthy = self.check_health(settings);
+
+        // Update spread and risk estimates with the current candles.
+        self.estimate_spreads();
+
+        // Update position value.
+        self.valuate();
+        self.liquidate();
+        self.consolidate_dust().await;
+
+        self.apply_commands().await;
+
+        let start_instant = Instant::now();
+        if !self.paused && !self.quit && self.poisoned.is_none() && healthy {
+            self.eval_guarded(strategy)?;
+        }
+        let strategy_eval_duration = start_instant.elapsed();
+
+        // Update position value again for potential new positions.
+        self.valuate();
+        if let Some(record) = &mut step_record {
+            record.targets(self.open_positions.iter());
+        }
+
+        /*
+        tracing::trace!("Exiting positions.");
+        self.exit_many().await?;
+        tracing::trace!("Entering positions.");
+        self.enter_many().await?;
+        */
+        self.execute_transfers().await;
+
+        let start_instant = Instant::now();
+        let fills = self
+            .execute_with(&mut |orders| strategy.before_execute(orders))
+            .await?;
+        let execute_duration = start_instant.elapsed();
+
+        if let Some(session) = &self.session {
+            for (order, info) in &fills {
+                session.send(SessionEvent::Fill(order.clone(), info.clone()));
+            }
+        }
+
+        if let (Some(log), Some(mut record)) = (&mut self.step_log, step_record) {
+            (record.orders, record.fills) = fills.iter().cloned().unzip();
+            log.append(&record)?;
+        }
+
+        // Evaluate strategy and handle errors.
+        tracing::info!(
+            total = %self.total(),
+            open_positions = self.open_positions.len(),
+            update_ms = update_duration.as_millis() as u64,
+            evaluation_ms = strategy_eval_duration.as_millis() as u64,
+            execution_ms = execute_duration.as_millis() as u64,
+            "Ran strategy."
+        );
+
+        self.api
+            .status(self.current_time, self.total(), &self.exposure());
+        self.api.positions(self.current_time, &self.open_positions);
+        self.record();
+        if let Some(session) = &self.session {
+            session.send(SessionEvent::Snapshot(Snapshot {
+                time: self.current_time,
+                total: self.total(),
+                wallet: self.wallet.clone(),
+                positions: self.open_positions.clone(),
+            }));
+        }
+        self.save_state(strategy, settings, false).await;
+
+        let length = settings.interval.length(self.current_time);
+        self.timing.update = update_duration;
+        self.timing.eval = strategy_eval_duration;
+        self.timing.execute = execute_duration;
+        self.timing.steps += 1;
+        let overrun = self.real_time && self.timing.total() > length.to_std().unwrap_or_default();
+        self.step(settings);
+
+        if overrun {
+            self.timing.overruns += 1;
+            let missed = self.missed_steps(settings);
+            tracing::warn!(
+                "Step took {}ms, longer than the interval, {} steps behind.",
+                self.timing.total().as_millis(),
+                missed
+            );
+            self.overrun(settings, missed).await?;
+        }
+
+        Ok(fills)
+    }
+
+    // Update the health of the markets, flattening positions in stale markets if configured.
+    // Returns whether the strategy may be evaluated.
+    fn check_health(&mut self, settings: &Settings) -> bool {
+        let mut markets: Vec<Symbol> = self.candles.keys().copied().collect();
+        markets.sort();
+        for market in markets {
+            let candle = matches!(self.current_candle(market), Some((_, Some(_))));
+            let Some(health) =
+                self.health
+                    .update(market, self.current_time, candle, &settings.health)
+            else {
+                continue;
+            };
+            if health.stale {
+                tracing::warn!(
+                    "No candles of {} since {:?}, the market is stale.",
+                    market,
+                    health.last_candle
+                );
+            } else {
+                tracing::info!("Candles of {} are received again.", market);
+            }
+            self.api.health(self.current_time, &health);
+        }
+
+        let stale: HashSet<Symbol> = self.health.stale().collect();
+        if stale.is_empty() {
+            return true;
+        }
+        match settings.health.on_stale {
+            OnStale::Warn => true,
+            OnStale::Pause => false,
+            OnStale::Flatten => {
+                for position in &mut self.open_positions {
+                    let held = position
+                        .symbols()
+                        .chain(position.next_size.0.keys().copied())
+                        .any(|symbol| stale.contains(&symbol));
+                    if held && !position.removable() {
+                        tracing::warn!(
+                            "Closing position {}, it holds a stale market.",
+                            position.id()
+                        );
+                        position.close();
+                    }
+                }
+                false
+            }
+        }
+    }
+
+    async fn apply_commands(&mut self) {
+        let mut commands = self.api.commands().await;
+        if let Some(receiver) = &mut self.commands {
+            while let Ok(command) = receiver.try_recv() {
+                commands.push(command);
+            }
+        }
+
+        for command in commands {
+            tracing::warn!("Applying command {:?}.", command);
+            match command {
+                Command::Stop => {
+                    self.close_all();
+                    self.quit();
+                }
+                Command::CloseAll => self.close_all(),
+                Command::Pause => self.paused = true,
+                Command::Resume => {
+                    self.paused = false;
+                    if let Some(message) = self.poisoned.take() {
+                        tracing::warn!("Resuming the strategy, which panicked: {}", message);
+                    }
+                }
+                Command::ReducePositionsBy(fraction) => self.reduce_positions_by(fraction),
+            }
+        }
+    }
+
+    // Evaluate the strategy, turning a panic into an error so the account is still protected
+    // according to `OnError`. The strategy is poisoned and not evaluated anymore until resumed.
+    fn eval_guarded<S>(&mut self, strategy: &mut S) -> Result<(), AnyError>
+    where
+        S: Strategy<A>,
+    {
+        let payload = match panic::catch_unwind(AssertUnwindSafe(|| strategy.eval(self))) {
+            Ok(result) => return result,
+            Err(payload) => payload,
+        };
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_owned());
+
+        tracing::error!("The strategy panicked and is poisoned: {}", message);
+        self.poisoned = Some(message.clone());
+        Err(StrategyPanic(message).into())
+    }
+
+    /// The panic message if the strategy panicked. A poisoned strategy is not evaluated
+    /// anymore, until the operator resumes it with `Command::Resume`.
+    pub fn poisoned(&self) -> Option<&str> {
+        self.poisoned.as_deref()
+    }
+
+    // Close the largest positions until the fraction of the gross value is closed.
+    fn reduce_positions_by(&mut self, fraction: Decimal) {
+        let gross: Decimal = self
+            .open_positions
+            .iter()
+            .map(|position| position.notional())
+            .sum();
+        let target = gross * fraction.max(Decimal::ZERO).min(Decimal::ONE);
+
+        let mut positions: Vec<&mut Position> = self.open_positions.iter_mut().collect();
+        positions.sort_by_key(|position| std::cmp::Reverse(position.notional()));
+        let mut closed = Decimal::ZERO;
+        for position in positions {
+            if closed >= target || target.is_zero() {
+                break;
+            }
+            closed += position.notional();
+            position.close();
+        }
+    }
+
+    // Number of steps that are already complete after the current one.
+    fn missed_steps(&self, settings: &Settings) -> usize {
+        let now = Utc::now();
+        let mut time = settings.interval.next(self.current_time);
+        let mut missed = 0;
+        while settings.interval.next(time) <= now {
+            time = settings.interval.next(time);
+            missed += 1;
+        }
+        missed
+    }
+
+    // Handle missed steps according to the overrun policy.
+    async fn overrun(&mut self, settings: &Settings, missed: usize) -> Result<(), AnyError> {
+        for _ in 0..missed {
+            match settings.on_overrun {
+                OnOverrun::CatchUp => return Ok(()),
+                OnOverrun::Skip => {}
+                OnOverrun::FastForward => {
+                    let mut wait_duration = Duration::zero();
+                    self.update(settings, &mut wait_duration).await?;
+                    self.estimate_spreads();
+                }
             }
+            self.timing.skipped += 1;
+            self.step(settings);
         }
+
+        Ok(())
     }
 
     fn step(&mut self, settings: &Settings) {
-        log::trace!("Advancing time!");
-        self.current_time = self.current_time + settings.interval;
+        tracing::trace!("Advancing time!");
+        self.current_time = settings.interval.next(self.current_time);
+        self.skip_closed(settings);
+        let time = self.current_time;
         for candles in self.candles.values_mut() {
-            candles.pop_front();
+            candles.advance(time);
+        }
+    }
+
+    // Move the time to the first step within the trading hours.
+    fn skip_closed(&mut self, settings: &Settings) {
+        let Some(calendar) = &settings.calendar else {
+            return;
+        };
+        if !calendar.is_open(self.current_time) {
+            match calendar.next_open(self.current_time) {
+                Some(open) => self.current_time = settings.interval.start(open),
+                None => tracing::warn!("The calendar has no more sessions."),
+            }
         }
     }
 
@@ -280,43 +1316,59 @@ impl<A: Api> Exchange<A> {
         settings: &Settings,
         wait_duration: &mut Duration,
     ) -> Result<(), AnyError> {
+        // Market metadata rarely changes, so it is only refreshed periodically when trading live.
+        let refresh_markets = self.refresh_markets
+            || match self.markets_updated {
+                None => true,
+                Some(updated) => {
+                    self.real_time && self.current_time - updated >= settings.markets_refresh
+                }
+            };
+        // Markets that are stale already are not waited for.
+        let stale: HashSet<Symbol> = self.health.stale().collect();
+        let local_wallet = self.wallet.clone();
         try_join!(
             async {
-                log::trace!("Update markets.");
-                self.api.update_markets(&mut self.markets).await?;
+                if refresh_markets {
+                    tracing::trace!("Update markets.");
+                    self.api.update_markets(&mut self.markets).await?;
+                }
                 Ok::<(), AnyError>(())
             },
             async {
-                log::trace!("Update markets.");
+                tracing::trace!("Update markets.");
                 self.api.update_wallet(&mut self.wallet).await?;
                 Ok::<(), AnyError>(())
             },
             async {
-                log::trace!("Update candles.");
+                tracing::trace!("Update candles.");
                 let mut candles_missing: Vec<Symbol> = self
                     .candles
                     .iter()
-                    .filter(|(_asset, candles)| candles.is_empty() || candles.front().is_none())
+                    .filter(|(_asset, candles)| candles.is_empty())
                     .map(|(asset, _)| *asset)
                     .collect();
+                // Request in the same order every run.
+                candles_missing.sort();
 
                 // While the next candle is not already available
                 // and we don't have all candles, fetch candles.
                 while !candles_missing.is_empty() {
-                    log::trace!("Some candles are missing, fetching them.");
+                    tracing::trace!("Some candles are missing, fetching them.");
                     // Fetch all candles concurrently.
                     let mut futures = Vec::new();
                     for &market in candles_missing.iter() {
                         futures.push(self.api.get_candles(CandleKey {
                             market,
                             time: self.current_time,
-                            interval: settings.interval,
+                            interval: settings.interval.resolution(),
                         }));
                     }
                     let candles = join_all(futures).await;
                     for (asset, new_candles) in candles_missing.iter().zip(candles) {
                         if let Some(candles) = self.candles.get_mut(asset) {
-                            candles.append(&mut VecDeque::from_iter(new_candles?.into_iter()));
+                            let new_candles = settings.interval.resample(new_candles?);
+                            candles.extend(new_candles);
                         }
                     }
 
@@ -324,13 +1376,10 @@ impl<A: Api> Exchange<A> {
                     let mut i = 0;
                     while i < candles_missing.len() {
                         // Remove present candles from missing list.
-                        if self.candles.contains_key(&candles_missing[i])
-                            && self
-                                .candles
-                                .get(&candles_missing[i])
-                                .unwrap()
-                                .front()
-                                .is_some()
+                        if self
+                            .candles
+                            .get(&candles_missing[i])
+                            .is_some_and(|candles| !candles.is_empty())
                         {
                             candles_missing.remove(i);
                         } else {
@@ -338,71 +1387,240 @@ impl<A: Api> Exchange<A> {
                         }
                     }
 
-                    if *wait_duration <= -settings.interval {
-                        log::trace!("Stop waiting for new candles.");
+                    if *wait_duration <= -settings.interval.length(self.current_time) {
+                        tracing::trace!("Stop waiting for new candles.");
+                        break;
+                    } else if candles_missing.iter().all(|market| stale.contains(market)) {
+                        tracing::trace!("Only stale markets are missing candles, stop waiting.");
                         break;
                     } else if !candles_missing.is_empty() {
-                        log::trace!("Waiting for new candles.");
+                        tracing::trace!("Waiting for new candles.");
                         // There still are some candles that could not be fetched.
                         // Wait a bit and try again.
                         tokio::time::sleep(
                             Duration::seconds(3).to_std().expect("Converting to std"),
                         )
                         .await;
-                        *wait_duration = self.current_time + settings.interval - Utc::now();
+                        *wait_duration = settings.interval.next(self.current_time)
+                            + settings.clock_skew
+                            - Utc::now();
                     }
                 }
 
                 Ok::<(), AnyError>(())
             }
         )?;
+        // Failed updates are retried in the next step.
+        if refresh_markets {
+            self.refresh_markets = false;
+            self.markets_updated = Some(self.current_time);
+        }
+        self.reconcile(local_wallet);
+
+        self.settle_pending().await;
+
+        let borrow_costs = self.api.borrow_costs(self.current_time).await?;
+        if borrow_costs > Decimal::ZERO {
+            self.pay_borrow_costs(borrow_costs);
+        }
+
+        self.prices.clear();
+        if settings.price_source != PriceSource::Close {
+            tracing::trace!("Update prices.");
+            let mut symbols: Vec<Symbol> = self.candles.keys().copied().collect();
+            symbols.sort();
+            let prices = join_all(symbols.iter().map(|&market| {
+                self.api.get_price(
+                    CandleKey {
+                        market,
+                        time: self.current_time,
+                        interval: settings.interval.resolution(),
+                    },
+                    settings.price_source,
+                )
+            }))
+            .await;
+            // Markets without a price are valued at the close.
+            for (symbol, price) in symbols.into_iter().zip(prices) {
+                match price {
+                    Ok(Some(price)) => {
+                        self.prices.insert(symbol, price);
+                    }
+                    Ok(None) => {}
+                    Err(err) => {
+                        tracing::error!(
+                            "Could not get the {:?} price of {}, valuing at the close: {}",
+                            settings.price_source,
+                            symbol,
+                            err
+                        );
+                    }
+                }
+            }
+        }
 
         Ok(())
     }
 
+    // Compare the wallet booked until this step with the wallet of the venue when trading live,
+    // keeping the booked wallet if the reconciliation does not correct it.
+    fn reconcile(&mut self, local: Wallet) {
+        let Some(reconciliation) = self.reconciliation else {
+            return;
+        };
+        if !self.real_time || local.is_fresh() {
+            return;
+        }
+        let due = self
+            .reconciled
+            .is_none_or(|reconciled| self.current_time - reconciled >= reconciliation.every);
+        if due {
+            self.reconciled = Some(self.current_time);
+            for discrepancy in reconciliation.compare(self.current_time, &local, &self.wallet) {
+                tracing::warn!(
+                    "The wallet holds {} {} at the venue, but {} were booked.",
+                    discrepancy.venue,
+                    discrepancy.asset,
+                    discrepancy.local
+                );
+                self.api.reconciled(&discrepancy);
+                self.wallet_discrepancies.push(discrepancy);
+            }
+        }
+        if !reconciliation.correct {
+            self.wallet = local;
+        }
+    }
+
+    // Execute the requested transfers, booking them to the wallet once the venue accepted them.
+    async fn execute_transfers(&mut self) {
+        for transfer in std::mem::take(&mut self.transfers) {
+            // The free balance may have changed since the transfer was requested.
+            let change = transfer.change();
+            if self.wallet.free(transfer.asset) + change < Decimal::ZERO {
+                tracing::error!(
+                    "Not enough {} free to transfer {}.",
+                    transfer.asset,
+                    transfer.amount
+                );
+                continue;
+            }
+            match self.api.transfer(&transfer).await {
+                Ok(()) => {
+                    tracing::info!(
+                        "Transferred {} {} from {:?} to {:?}.",
+                        transfer.amount,
+                        transfer.asset,
+                        transfer.from,
+                        transfer.to
+                    );
+                    if change < Decimal::ZERO {
+                        self.wallet
+                            .reserve(-change, transfer.asset)
+                            .expect("reservation failed");
+                        self.wallet
+                            .withdraw(-change, transfer.asset)
+                            .expect("withdrawal failed");
+                    } else {
+                        self.wallet.deposit(change, transfer.asset);
+                    }
+                }
+                Err(ApiError::Unsupported) => {
+                    tracing::warn!("{} does not support transfers.", A::NAME);
+                }
+                Err(err) => tracing::error!("Could not transfer: {}", err),
+            }
+        }
+    }
+
+    // Retry cancelling the rest of the pending orders, dropping them once they are settled.
+    async fn settle_pending(&mut self) {
+        for pending in std::mem::take(&mut self.pending) {
+            let (order, booked) = (&pending.order, pending.booked);
+            let result = match self.api.cancel_order(order.market, order.order_id).await {
+                Err(ApiError::Unsupported) => {
+                    self.api.order_status(order.market, order.order_id).await
+                }
+                result => result,
+            };
+            match result {
+                Ok(info) if info.status.is_final() => {
+                    if info.size != booked {
+                        tracing::warn!(
+                            "Order {} settled with a fill of {} instead of the booked {}, the difference is not attributed to any position.",
+                            order.order_id,
+                            info.size,
+                            booked
+                        );
+                    }
+                }
+                Ok(info) => self.pending.push(PendingOrder { info, ..pending }),
+                Err(err) => {
+                    tracing::warn!("Could not cancel order {}: {}", order.order_id, err);
+                    self.pending.push(pending);
+                }
+            }
+        }
+    }
+
+    // Pay the interest from the free quote asset, like the venue debiting it from the wallet.
+    fn pay_borrow_costs(&mut self, costs: Decimal) {
+        let quote = self.api.quote_asset();
+        let paid = costs.min(self.wallet.free(quote));
+        if paid < costs {
+            tracing::warn!(
+                "Not enough free funds to pay borrowing costs of {}, paying {}.",
+                costs,
+                paid
+            );
+        }
+        self.wallet
+            .reserve(paid, quote)
+            .expect("reservation failed");
+        self.wallet
+            .withdraw(paid, quote)
+            .expect("withdrawal failed");
+        self.borrow_costs += paid;
+    }
+
     /// Start running a strategy on an exchange.
-    pub async fn run<S>(mut self, mut strategy: S) -> Result<(), AnyError>
+    pub async fn run<S>(mut self, mut strategy: S) -> Result<Report, AnyError>
     where
         S: Strategy<A>,
     {
-        self.api.hello(S::NAME);
+        let options = self.init(&mut strategy).await?;
 
-        try_join!(
-            async {
-                log::trace!("Update markets.");
-                self.api.update_markets(&mut self.markets).await?;
-                Ok::<(), AnyError>(())
-            },
-            async {
-                log::trace!("Update markets.");
-                self.api.update_wallet(&mut self.wallet).await?;
-                Ok::<(), AnyError>(())
-            },
-        )?;
-        let options = strategy.init(&mut self)?;
-
-        if A::LIVE_TRADING_ENABLED {
-            log::warn!("Trading live on exchange!");
-        }
-
-        loop {
+        let result = loop {
             match self.run_internal(&mut strategy, &options).await {
-                Ok(()) => return Ok(()),
+                Ok(()) => {
+                    if self.report.is_none() {
+                        self.record();
+                    }
+                    break Ok(self.report.take().unwrap());
+                }
                 Err(err) => {
-                    log::error!("An error occured: {}", err);
+                    tracing::error!("An error occured: {}", err);
+                    self.alert(Alert::Error {
+                        time: self.current_time,
+                        error: err.to_string(),
+                    });
                     match options.on_error {
                         OnError::Return => {
-                            return Err(err);
+                            break Err(err);
                         }
                         OnError::ExitAllPositionsAndReturn => {
                             self.close_all();
-                            self.execute().await?;
+                            if let Err(exit_err) = self.execute().await {
+                                break Err(exit_err.into());
+                            }
 
-                            return Err(err);
+                            break Err(err);
                         }
                         OnError::ExitAllPositionsAndResume => {
                             self.close_all();
-                            self.execute().await?;
+                            if let Err(exit_err) = self.execute().await {
+                                break Err(exit_err.into());
+                            }
 
                             // Go to next step and try again.
                             self.step(&options);
@@ -410,6 +1628,182 @@ impl<A: Api> Exchange<A> {
                     }
                 }
             }
+        };
+
+        if let Some(progress) = &mut self.progress {
+            progress.finish(self.current_time, self.end_time);
+        }
+        self.save_state(&strategy, &options, true).await;
+
+        if let Ok(report) = &result {
+            self.api.finished(report);
+            if let Some(session) = &self.session {
+                session.send(SessionEvent::Finished(report.clone()));
+            }
+        }
+
+        // Flush logs and alerts before returning.
+        self.api.shutdown().await;
+        if let Some(alerts) = &self.alerts {
+            alerts.flush().await;
+        }
+
+        result
+    }
+
+    // Initialize the strategy and apply its settings.
+    pub(crate) async fn init<S>(&mut self, strategy: &mut S) -> Result<Settings, AnyError>
+    where
+        S: Strategy<A>,
+    {
+        self.api.hello(S::NAME);
+        self.strategy_name = S::NAME;
+        if let Some(parameters) = strategy.parameters() {
+            match serde_json::to_value(parameters) {
+                Ok(parameters) => {
+                    self.api.parameters(&parameters);
+                    self.parameters = Some(parameters);
+                }
+                Err(err) => tracing::warn!("Could not serialize the strategy parameters: {}", err),
+            }
+        }
+
+        try_join!(
+            async {
+                tracing::trace!("Update markets.");
+                self.api.update_markets(&mut self.markets).await?;
+                Ok::<(), AnyError>(())
+            },
+            async {
+                tracing::trace!("Update markets.");
+                self.api.update_wallet(&mut self.wallet).await?;
+                Ok::<(), AnyError>(())
+            },
+        )?;
+        self.markets_updated = Some(self.current_time);
+        let options = strategy.init(self)?;
+        self.current_time = options.interval.start(self.current_time);
+        self.skip_closed(&options);
+        self.interval = Some(options.interval);
+        self.dust = options.dust;
+        self.netting = options.netting;
+        self.execution = options.execution;
+        self.direction = options.direction;
+        self.on_forbidden_direction = options.on_forbidden_direction;
+        self.account_mode = options.account_mode;
+        self.fill_timeout = options.fill_timeout;
+        self.algo = options.algo;
+        self.fill_poll = options.fill_poll;
+        self.precision = options.precision;
+        self.look_ahead_guard = options.look_ahead_guard;
+        self.reconciliation = options.reconciliation;
+        if let Some(seed) = options.seed {
+            self.ids = std::sync::Mutex::new(Ids::seeded(seed));
+        }
+        // Markets watched in init were watched before the anchor was known.
+        self.vwap_anchor = options.vwap_anchor;
+        for vwap in self.vwaps.values_mut() {
+            *vwap = Vwap::new(self.vwap_anchor);
+        }
+        match options.state.load(&self.api, S::NAME).await {
+            Ok(Some(state)) => {
+                strategy.load_state(state)?;
+                tracing::info!("Resuming with the saved strategy state.");
+            }
+            Ok(None) => {}
+            Err(err) => tracing::error!("Failed to load the strategy state: {}", err),
+        }
+
+        if A::LIVE_TRADING_ENABLED {
+            tracing::warn!("Trading live on exchange!");
+        }
+
+        Ok(options)
+    }
+
+    // Save the state of the strategy once it is due, or in any case if forced.
+    // Failing to save is logged, but does not stop the strategy.
+    async fn save_state<S>(&mut self, strategy: &S, settings: &Settings, force: bool)
+    where
+        S: Strategy<A>,
+    {
+        let due = match self.state_saved {
+            Some(saved) => self.current_time >= saved + settings.save_state_every,
+            None => true,
+        };
+        if settings.state == StateStorage::None || !(due || force) {
+            return;
+        }
+        let Some(state) = strategy.save_state() else {
+            return;
+        };
+        match settings.state.save(&self.api, S::NAME, &state).await {
+            Ok(()) => self.state_saved = Some(self.current_time),
+            Err(err) => tracing::error!("Failed to save the strategy state: {}", err),
+        }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancellation
+            .as_ref()
+            .map(|token| token.is_cancelled())
+            .unwrap_or(false)
+    }
+
+    // Record the result of the current step in the report.
+    fn record(&mut self) {
+        let (time, total) = (self.current_time, self.total());
+        let report = self.report.get_or_insert_with(|| Report::new(time, total));
+        if report.steps == 0 {
+            report.parameters.clone_from(&self.parameters);
+        }
+        report.update(time, total);
+        report.update_positions(&mut self.closed_positions, &self.open_positions);
+        report.compliance.append(&mut self.compliance_records);
+        report.execution.append(&mut self.execution_decisions);
+        report.traded_value += std::mem::take(&mut self.traded_value);
+        report.borrow_costs += std::mem::take(&mut self.borrow_costs);
+        report.rounding.append(&mut self.residuals);
+        report.metrics.append(&mut self.metrics);
+        report
+            .wallet_discrepancies
+            .append(&mut self.wallet_discrepancies);
+    }
+
+    fn estimate_spreads(&mut self) {
+        let (time, guarded) = (self.current_time, self.look_ahead_guard);
+        let mut processed = 0;
+        for (&symbol, candles) in &self.candles {
+            let current = candles.current(time, guarded);
+            let close = current.and_then(|(_, candle)| candle.as_ref());
+            self.risk.update(symbol, close.map(|candle| candle.close));
+            if let (Some(execution), Some(close)) = (&self.execution, close) {
+                self.execution_quality
+                    .resolve(symbol, close.close, execution.window);
+            }
+
+            // Only update the estimators once per candle.
+            let Some((key, Some(candle))) = current else {
+                continue;
+            };
+            if self.candles_applied.get(&symbol) >= Some(&key.time) {
+                continue;
+            }
+            self.candles_applied.insert(symbol, key.time);
+            if let Some(estimator) = self.spreads.get_mut(&symbol) {
+                estimator.update(candle);
+            }
+            if let Some(vwap) = self.vwaps.get_mut(&symbol) {
+                vwap.update(key.time, candle);
+            }
+            *self.candles_seen.entry(symbol).or_default() += 1;
+            self.indicators.update(symbol, candle);
+            processed += 1;
+        }
+        if let Some(progress) = &mut self.progress {
+            if !self.real_time {
+                progress.step(self.current_time, self.end_time, processed);
+            }
         }
     }
 
@@ -417,18 +1811,68 @@ impl<A: Api> Exchange<A> {
         let valuation = Valuation(
             self.candles
                 .iter()
-                .filter_map(|(&symbol, candle)| Some((symbol, candle.front()?.1?.close)))
+                .filter_map(|(&symbol, candles)| {
+                    let price = match self.prices.get(&symbol) {
+                        Some(&price) => price,
+                        None => {
+                            candles
+                                .current(self.current_time, self.look_ahead_guard)?
+                                .1?
+                                .close
+                        }
+                    };
+                    Some((symbol, price))
+                })
                 .collect(),
         );
 
         let time = self.current_time();
 
-        for position in self.positions_mut() {
+        for position in &mut self.open_positions {
+            position.set_multipliers(&self.markets);
             position.valuate(valuation.clone(), time);
         }
     }
 
+    // Close positions whose value fell below the maintenance margin,
+    // as the exchange would liquidate them.
+    fn liquidate(&mut self) {
+        if let Some(margin) = self.api.margin() {
+            let mut liquidated = Vec::new();
+            for position in self.positions_mut() {
+                let notional = position.notional();
+                if notional > Decimal::ZERO
+                    && position.value() < notional * margin.maintenance_margin
+                {
+                    tracing::warn!(
+                        "Liquidating position {} with value {}.",
+                        position.id(),
+                        position.value()
+                    );
+                    position.close();
+                    liquidated.push(position.id());
+                }
+            }
+            for position in liquidated {
+                self.alert(Alert::Triggered {
+                    time: self.current_time,
+                    position,
+                    reason: "liquidated below the maintenance margin".to_owned(),
+                });
+            }
+        }
+    }
+
     async fn execute(&mut self) -> Result<(), ApiError> {
+        self.execute_with(&mut |_| {}).await.map(|_| ())
+    }
+
+    // Execute the orders of all positions, letting the hook modify the coalesced orders first.
+    // Returns the submitted orders with their fills.
+    async fn execute_with(
+        &mut self,
+        before_execute: &mut dyn FnMut(&mut Vec<Order>),
+    ) -> Result<Vec<(Order, OrderInfo)>, ApiError> {
         assert!(
             self.open_positions
                 .iter()
@@ -437,79 +1881,448 @@ impl<A: Api> Exchange<A> {
                 <= self.total()
         );
 
-        // Get all orders.
-        let orders: Vec<ValuedBundle> = self.positions().map(|position| position.order()).collect();
+        // Fit positions whose size was adjusted relative to their size.
+        let mut positions = std::mem::take(&mut self.open_positions);
+        for position in positions.iter_mut().filter(|position| position.refit) {
+            let residual = position.fit(self);
+            self.residuals.record(RoundingSite::Fit, residual);
+        }
+        self.open_positions = positions;
+
+        // Get the orders of this step.
+        let (algo, markets) = (self.algo, &self.markets);
+        let orders: Vec<ValuedBundle> = self
+            .open_positions
+            .iter_mut()
+            .map(|position| position.child_order(algo, markets))
+            .collect();
         for order in &orders {
             assert!(order.time.is_some());
         }
 
-        // Order and get order results.
-        let order_results = self.order(orders.clone()).await?;
-
-
-        let mut value_diff_sum = Decimal::ZERO;
-        for (position, (order_result, order)) in self.positions_mut().zip(order_results.into_iter().zip(orders)) {            
+        // Order and get order results, holding back small orders to net them if enabled.
+        let closing: HashSet<Symbol> = self
+            .positions()
+            .filter(|position| position.removable())
+            .flat_map(|position| position.symbols())
+            .collect();
+        let mut held = std::mem::take(&mut self.held);
+        let mut records = Vec::new();
+        let exposure = self.exposure();
+        let context = ComplianceContext {
+            venue: A::NAME,
+            strategy: self.strategy_name,
+            time: self.current_time,
+            total: self.total(),
+            exposure: &exposure,
+        };
+        let mut decisions = Vec::new();
+        let legs: Vec<Legs> = match self.account_mode {
+            AccountMode::Netting => Vec::new(),
+            AccountMode::Hedged => self.positions().map(|position| position.legs()).collect(),
+        };
+        let limits = self.limits(&orders, &legs);
+        let (netting, execution, compliance, markets) = (
+            self.netting,
+            self.execution,
+            &self.compliance,
+            &self.markets,
+        );
+        let quality = &self.execution_quality;
+        let result = self
+            .order_with(orders.clone(), &legs, &mut |orders| {
+                for order in orders.iter_mut() {
+                    if let Some(Some(price)) =
+                        limits.get(&(order.market, order.position_side, order.side))
+                    {
+                        order.order_type = OrderType::Limit(*price);
+                    }
+                }
+                if let Some(netting) = &netting {
+                    netting.hold(orders, &mut held, markets, &closing, context.time);
+                }
+                before_execute(orders);
+                if let Some(execution) = &execution {
+                    decisions = execution.apply(orders, quality, context.time);
+                }
+                if let Some(compliance) = compliance {
+                    records.extend(compliance::enforce(compliance.as_ref(), orders, &context));
+                }
+            })
+            .await;
+        self.held = held;
+        self.compliance_records.append(&mut records);
+        self.execution_decisions.append(&mut decisions);
+        let (order_results, mut fills) = result?;
+        self.book_fills(&fills);
+
+        let mut settlement = Settlement::default();
+        let time = self.current_time;
+        let mut imbalances = Vec::new();
+        for (position, (order_result, order)) in self
+            .positions_mut()
+            .zip(order_results.into_iter().zip(&orders))
+        {
             if order_result.abs_value() != Decimal::ZERO {
-                // Adapt positions to order results.
-                position.resize(order_result.clone());
-
-                assert_ne!(position.symbols().count(), 0, "order: {:?}, order result: {:?}, position: {:?}", order, order_result, position);
-
-                log::error!("resized position has value {}", position.value());
+                imbalances.extend(LegImbalance::new(
+                    position.id(),
+                    order.time,
+                    &order.bundle,
+                    &order_result.bundle,
+                ));
+                settlement.resize(position, order_result, time);
+            } else if order.abs_value() != Decimal::ZERO {
+                tracing::warn!("Order of position {} was not filled.", position.id());
+            }
+        }
 
-                // Change wallet value.
-                if position.closed() {
-                    // Position gets closed.
-                    value_diff_sum += position.value();
-                } else {
-                    // Position gets opened.
-                    value_diff_sum -= position.value();
+        // Unwind the legs of all-or-nothing positions that filled more than the others.
+        let mut unwinds: Vec<ValuedBundle> = orders
+            .iter()
+            .map(|order| ValuedBundle {
+                bundle: Bundle::default(),
+                ..order.clone()
+            })
+            .collect();
+        for imbalance in &imbalances {
+            let index = self
+                .open_positions
+                .iter()
+                .position(|position| position.id() == imbalance.position)
+                .expect("imbalanced position is open");
+            let position = &mut self.open_positions[index];
+            tracing::warn!(
+                "The legs of position {} filled {:?} of {:?}.",
+                imbalance.position,
+                imbalance.filled,
+                imbalance.ordered
+            );
+            if position.leg_policy() == LegPolicy::AllOrNothing {
+                position.drop_remaining();
+                unwinds[index].bundle = imbalance.unwind(&self.markets);
+            }
+        }
+        if unwinds.iter().any(|unwind| !unwind.bundle.0.is_empty()) {
+            let legs: Vec<Legs> = match self.account_mode {
+                AccountMode::Netting => Vec::new(),
+                AccountMode::Hedged => self.positions().map(|position| position.legs()).collect(),
+            };
+            match self.order_with(unwinds, &legs, &mut |_| {}).await {
+                Ok((unwind_results, mut unwind_fills)) => {
+                    self.book_fills(&unwind_fills);
+                    fills.append(&mut unwind_fills);
+                    for (position, unwind_result) in self.positions_mut().zip(unwind_results) {
+                        if unwind_result.abs_value() == Decimal::ZERO {
+                            continue;
+                        }
+                        if let Some(imbalance) = imbalances
+                            .iter_mut()
+                            .find(|imbalance| imbalance.position == position.id())
+                        {
+                            imbalance.unwound.extend(
+                                unwind_result
+                                    .bundle
+                                    .0
+                                    .iter()
+                                    .map(|(&symbol, &size)| (symbol, size)),
+                            );
+                        }
+                        settlement.resize(position, unwind_result, time);
+                    }
                 }
-            } else {
-                assert_eq!(order.abs_value(), Decimal::ZERO, "order: {:?}, order result: {:?}", order, order_result);
+                Err(err) => tracing::error!("Could not unwind the legs of positions: {}", err),
             }
         }
+        self.leg_imbalances = imbalances;
+        let Settlement {
+            value_diff: value_diff_sum,
+            closed,
+            opened: opened_positions,
+        } = settlement;
+
+        for alert in opened_positions {
+            self.alert(alert);
+        }
+        for position in &closed {
+            self.api.closed(self.current_time, position);
+            let closed_position = ClosedPosition::from(position);
+            self.alert(Alert::Closed {
+                time: self.current_time,
+                position: closed_position.id,
+                symbols: closed_position.symbols.clone(),
+                pnl: closed_position.pnl,
+            });
+            if let Some(session) = &self.session {
+                session.send(SessionEvent::Closed(closed_position.clone()));
+            }
+            self.closed_positions.push(closed_position);
+        }
 
-        debug_assert!(self.open_positions.first().is_none() || self.open_positions.first().unwrap().value() == Decimal::ZERO);
-
-        if value_diff_sum < Decimal::ZERO {
-            self.wallet.reserve(value_diff_sum.abs(), self.api.quote_asset())
+        // Settle the wallet with the precision of the quote asset, auditing the difference.
+        let quote = self.api.quote_asset();
+        let decimals = self.precision.decimals(quote, quote, &self.markets);
+        let rounded = self.precision.round(value_diff_sum, decimals);
+        self.residuals
+            .record(RoundingSite::Wallet, rounded - value_diff_sum);
+        let value_diff_sum = rounded;
+
+        let cross_collateral = self
+            .api
+            .margin()
+            .is_some_and(|margin| !margin.collateral.is_empty());
+        if value_diff_sum < Decimal::ZERO && cross_collateral {
+            // The quote asset is borrowed against the collateral if there is not enough of it.
+            self.wallet.settle(value_diff_sum, self.api.quote_asset());
+        } else if value_diff_sum < Decimal::ZERO {
+            self.wallet
+                .reserve(value_diff_sum.abs(), self.api.quote_asset())
                 .expect("reservation failed");
-            self.wallet.withdraw(value_diff_sum.abs(), self.api.quote_asset())
+            self.wallet
+                .withdraw(value_diff_sum.abs(), self.api.quote_asset())
                 .expect("withdrawal failed");
         } else if value_diff_sum > Decimal::ZERO {
-            self.wallet.deposit(value_diff_sum.abs(), self.api.quote_asset());
+            self.wallet
+                .deposit(value_diff_sum.abs(), self.api.quote_asset());
         }
 
-        // Remove closed positions.
-        self.open_positions.retain(|position| !position.removable());
+        // Remove closed positions, positions whose close was not filled are kept.
+        self.open_positions.retain(|position| {
+            !position.removable()
+                || position
+                    .current
+                    .bundle
+                    .0
+                    .values()
+                    .any(|size| !size.is_zero())
+        });
 
         for position in &self.open_positions {
             assert_ne!(position.symbols().count(), 0);
         }
 
-        // TODO: Remove for general strategies.
-        assert!(self.open_positions.len() <= 1);
+        Ok(fills)
+    }
 
-        Ok(())
+    // Keep track of the fills of submitted orders, orders that did not settle are pending.
+    fn book_fills(&mut self, fills: &[(Order, OrderInfo)]) {
+        self.pending.extend(
+            fills
+                .iter()
+                .filter(|(_, info)| !info.status.is_final())
+                .map(|(order, info)| PendingOrder {
+                    order: order.clone(),
+                    info: info.clone(),
+                    booked: info.size,
+                }),
+        );
+        if let Some(execution) = &self.execution {
+            for (order, info) in fills {
+                self.execution_quality.record(order, info, execution.window);
+            }
+        }
+        self.traded_value += fills
+            .iter()
+            .map(|(_, info)| info.size.abs() * info.price)
+            .sum::<Decimal>();
     }
 
+    // The limit prices of the orders of each symbol, leg and side. An order is only limited if all
+    // positions trading in its direction order at a limit, at the least aggressive of their prices.
+    fn limits(
+        &mut self,
+        orders: &[ValuedBundle],
+        legs: &[Legs],
+    ) -> HashMap<(Symbol, Option<PositionSide>, Side), Option<Decimal>> {
+        let time = self.current_time;
+        let prices: Vec<HashMap<Symbol, Decimal>> = self
+            .positions_mut()
+            .map(|position| position.limit_prices(time))
+            .collect();
+        let mut limits = HashMap::new();
+        for (index, order) in orders.iter().enumerate() {
+            for (&symbol, &size) in &order.bundle.0 {
+                if size.is_zero() {
+                    continue;
+                }
+                let side = if size > Decimal::ZERO {
+                    Side::Buy
+                } else {
+                    Side::Sell
+                };
+                let leg = legs.get(index).and_then(|legs| legs.get(&symbol)).copied();
+                let price = prices[index].get(&symbol).map(|&price| {
+                    self.markets
+                        .market(symbol)
+                        .map_or(price, |market| market.round_price(price))
+                });
+                limits
+                    .entry((symbol, leg, side))
+                    .and_modify(|limit: &mut Option<Decimal>| {
+                        *limit = match (*limit, price) {
+                            (Some(limit), Some(price)) if side == Side::Buy => {
+                                Some(limit.min(price))
+                            }
+                            (Some(limit), Some(price)) => Some(limit.max(price)),
+                            _ => None,
+                        }
+                    })
+                    .or_insert(price);
+            }
+        }
+        limits
+    }
+
+    #[cfg(test)]
     async fn order(&self, orders: Vec<ValuedBundle>) -> Result<Vec<ValuedBundle>, ApiError> {
-        log::trace!("issue order");
+        self.order_with(orders, &[], &mut |_| {})
+            .await
+            .map(|(orders, _)| orders)
+    }
 
-        // Coalesce orders to issue only one order per symbol.
-        let actual_orders: Vec<Order> = Self::coalesce_orders(&orders).into();
-        let mut actual_order_futures = Vec::new();
-        for actual_order in actual_orders.iter() {
-            actual_order_futures.push(self.api.place_order(actual_order.clone()));
+    // The net size of the open positions in a market, only of the leg in hedged account mode.
+    fn held_size(&self, market: Symbol, leg: Option<PositionSide>) -> Decimal {
+        self.open_positions
+            .iter()
+            .filter_map(|position| position.current.bundle.0.get(&market))
+            .filter(|size| leg.is_none_or(|leg| !size.is_zero() && PositionSide::of(**size) == leg))
+            .sum()
+    }
+
+    // Place the orders of positions. With the legs of each position in hedged account mode,
+    // orders are coalesced per symbol and leg, otherwise per symbol.
+    async fn order_with(
+        &self,
+        orders: Vec<ValuedBundle>,
+        legs: &[Legs],
+        before_execute: &mut dyn FnMut(&mut Vec<Order>),
+    ) -> Result<(Vec<ValuedBundle>, Vec<(Order, OrderInfo)>), ApiError> {
+        tracing::trace!("issue order");
+
+        // Split the orders of positions into the parts trading each leg.
+        let parts = Self::split_legs(&orders, legs);
+
+        // Coalesce orders to issue only one order per symbol and leg.
+        let mut actual_orders: Vec<Order> = Vec::new();
+        for leg in [None, Some(PositionSide::Long), Some(PositionSide::Short)] {
+            let group: Vec<ValuedBundle> = parts
+                .iter()
+                .filter(|(_, part_leg, _)| *part_leg == leg)
+                .map(|(_, _, part)| part.clone())
+                .collect();
+            if group.is_empty() {
+                continue;
+            }
+            let mut coalesced: Vec<Order> = Self::coalesce_orders(&group).into();
+            // Submit in the same order with the same ids every run, if the ids are seeded.
+            coalesced.sort_by_key(|order| order.market);
+            for order in &mut coalesced {
+                order.order_id = self.ids.lock().unwrap().next();
+                order.position_side = leg;
+                // Orders that only shrink the held size can never open or flip a position.
+                let held = self.held_size(order.market, leg);
+                order.reduce_only = match order.side {
+                    Side::Buy => held < Decimal::ZERO && order.size <= -held,
+                    Side::Sell => held > Decimal::ZERO && order.size <= held,
+                };
+            }
+            actual_orders.append(&mut coalesced);
+        }
+        let mut submitted_orders = actual_orders.clone();
+        before_execute(&mut submitted_orders);
+        // Orders may only be removed or modified, but not added or reversed.
+        submitted_orders.retain(|submitted| {
+            let allowed = actual_orders.iter().any(|actual| {
+                actual.market == submitted.market
+                    && actual.side == submitted.side
+                    && actual.position_side == submitted.position_side
+            });
+            if !allowed {
+                tracing::error!(
+                    "Dropped order {} in {}, orders may only be removed or modified, but not added or reversed.",
+                    submitted.order_id,
+                    submitted.market
+                );
+            }
+            allowed
+        });
+
+        for submitted_order in submitted_orders.iter() {
+            tracing::debug!(
+                order_id = %submitted_order.order_id,
+                market = %submitted_order.market,
+                side = ?submitted_order.side,
+                size = %submitted_order.size,
+                "Placing order."
+            );
         }
-        let actual_order_results: Result<Vec<OrderInfo>, ApiError> =
-            join_all(actual_order_futures).await.into_iter().collect();
-        let actual_order_results = actual_order_results?;
+        // Placed in a single batch where the venue supports it.
+        let span = tracing::info_span!("orders", count = submitted_orders.len());
+        let submitted_order_results: Vec<OrderInfo> = self
+            .api
+            .place_orders(submitted_orders.clone())
+            .instrument(span)
+            .await
+            .into_iter()
+            .zip(submitted_orders.iter())
+            .map(|(result, order)| {
+                // Other orders of the batch may have been filled already, so a failed order
+                // is not filled at all instead of failing the batch, and legs can be unwound.
+                // Orders that may have reached the venue anyway stay pending until confirmed.
+                result.unwrap_or_else(|err| {
+                    tracing::error!("Order {} failed: {}", order.order_id, err);
+                    let status = match err {
+                        ApiError::Rejected | ApiError::Unsupported | ApiError::UnknownVenue(_) => {
+                            OrderStatus::Rejected
+                        }
+                        _ => OrderStatus::Submitted,
+                    };
+                    OrderInfo {
+                        order_id: order.order_id,
+                        market: order.market,
+                        size: Decimal::ZERO,
+                        price: order.current_price,
+                        time: order.time,
+                        side: order.side,
+                        status,
+                    }
+                })
+            })
+            .collect();
+        let submitted_order_results: Vec<OrderInfo> = join_all(
+            submitted_orders
+                .iter()
+                .zip(submitted_order_results)
+                .map(|(order, info)| self.confirm(order, info)),
+        )
+        .await;
 
-        log::trace!("issue order joined");
+        tracing::trace!("issue order joined");
 
-        let mut adjusted_orders = orders.clone();
+        // Removed orders are not filled at all.
+        let actual_order_results: Vec<OrderInfo> = actual_orders
+            .iter()
+            .map(|actual_order| {
+                submitted_orders
+                    .iter()
+                    .zip(submitted_order_results.iter())
+                    .find(|(submitted, _)| {
+                        submitted.market == actual_order.market
+                            && submitted.position_side == actual_order.position_side
+                    })
+                    .map(|(_, result)| result.clone())
+                    .unwrap_or_else(|| OrderInfo {
+                        order_id: actual_order.order_id,
+                        market: actual_order.market,
+                        size: Decimal::ZERO,
+                        price: actual_order.current_price,
+                        time: actual_order.time,
+                        side: actual_order.side,
+                        status: OrderStatus::Created,
+                    })
+            })
+            .collect();
+
+        let mut adjusted_parts = parts.clone();
         for (actual_order, actual_order_result) in
             actual_orders.iter().zip(actual_order_results.iter())
         {
@@ -526,16 +2339,18 @@ impl<A: Api> Exchange<A> {
 
             //println!("order: {}, price: {}, missing: {}", symbol, price, missing);
 
-            let same_side_order_size_sum: Decimal = orders
+            let same_side_order_size_sum: Decimal = parts
                 .iter()
-                .filter_map(|order| order.bundle.0.get(&symbol).cloned())
+                .filter(|(_, leg, _)| *leg == actual_order.position_side)
+                .filter_map(|(_, _, order)| order.bundle.0.get(&symbol).cloned())
                 .filter(|order| order.signum() == missing.signum())
                 .sum();
 
-            for (adjusted_order, order_size) in adjusted_orders
+            for (adjusted_order, order_size) in adjusted_parts
                 .iter_mut()
-                .zip(orders.iter())
-                .filter_map(|(adjusted_order, order)| {
+                .zip(parts.iter())
+                .filter(|(_, (_, leg, _))| *leg == actual_order.position_side)
+                .filter_map(|((_, _, adjusted_order), (_, _, order))| {
                     Some((adjusted_order, order.bundle.0.get(&symbol).cloned()?))
                 })
             {
@@ -556,7 +2371,102 @@ impl<A: Api> Exchange<A> {
             }
         }
 
-        Ok(adjusted_orders)
+        // Join the parts of each position again.
+        let mut adjusted_orders = orders.clone();
+        for (index, _, part) in adjusted_parts {
+            let adjusted_order = &mut adjusted_orders[index];
+            for (&symbol, &size) in &part.bundle.0 {
+                adjusted_order.bundle.0.insert(symbol, size);
+                if let Some(&price) = part.valuation.0.get(&symbol) {
+                    adjusted_order.valuation.0.insert(symbol, price);
+                }
+            }
+        }
+
+        let fills = submitted_orders
+            .into_iter()
+            .zip(submitted_order_results)
+            .collect();
+
+        Ok((adjusted_orders, fills))
+    }
+
+    // Wait until the venue confirms the fill of an order or the fill timeout passes,
+    // then cancel the rest. Returns the last known fill, which is not final if querying
+    // and cancelling failed.
+    async fn confirm(&self, order: &Order, mut info: OrderInfo) -> OrderInfo {
+        let deadline = Instant::now() + self.fill_timeout.to_std().unwrap_or_default();
+        while !info.status.is_final() {
+            if Instant::now() >= deadline {
+                tracing::warn!(
+                    "The fill of order {} was not confirmed in time, it is {:?}, cancelling the rest.",
+                    order.order_id,
+                    info.status
+                );
+                match self.api.cancel_order(order.market, order.order_id).await {
+                    Ok(cancelled) => info = cancelled,
+                    Err(err) => {
+                        tracing::warn!("Could not cancel order {}: {}", order.order_id, err);
+                    }
+                }
+                break;
+            }
+            tokio::time::sleep(self.fill_poll.to_std().unwrap_or_default()).await;
+            match self.api.order_status(order.market, order.order_id).await {
+                Ok(status) => info = status,
+                Err(ApiError::Network) => {
+                    tracing::warn!("Could not query the status of order {}.", order.order_id);
+                }
+                Err(ApiError::Unsupported) => break,
+                Err(err) => {
+                    tracing::warn!(
+                        "Could not query the status of order {}: {}",
+                        order.order_id,
+                        err
+                    );
+                }
+            }
+        }
+        // Unfilled orders have no fill price.
+        if info.size.is_zero() {
+            info.price = order.current_price;
+        }
+        info
+    }
+
+    // The parts of the orders of positions trading each leg, with the index of their position.
+    // Without legs, each order is a single part without a leg.
+    fn split_legs(
+        orders: &[ValuedBundle],
+        legs: &[Legs],
+    ) -> Vec<(usize, Option<PositionSide>, ValuedBundle)> {
+        if legs.is_empty() {
+            return orders
+                .iter()
+                .cloned()
+                .enumerate()
+                .map(|(index, order)| (index, None, order))
+                .collect();
+        }
+        let mut parts = Vec::new();
+        for (index, (order, legs)) in orders.iter().zip(legs).enumerate() {
+            for leg in [PositionSide::Long, PositionSide::Short] {
+                let mut part = ValuedBundle {
+                    bundle: Bundle::default(),
+                    valuation: order.valuation.clone(),
+                    time: order.time,
+                };
+                for (&symbol, &size) in &order.bundle.0 {
+                    if legs.get(&symbol) == Some(&leg) {
+                        part.bundle.0.insert(symbol, size);
+                    }
+                }
+                if !part.bundle.0.is_empty() {
+                    parts.push((index, Some(leg), part));
+                }
+            }
+        }
+        parts
     }
 
     fn coalesce_orders(orders: &[ValuedBundle]) -> ValuedBundle {
@@ -571,14 +2481,18 @@ impl<A: Api> Exchange<A> {
 
 #[cfg(test)]
 mod tests {
-    use crate::apis::{Ftx, Simulate};
+    use crate::apis::{
+        CandleGen, FillBehavior, Margin, Mock, MockSettings, SeededCandles, Simulate,
+    };
+    use crate::{Calendar, CollateralWeights};
+    use chrono::{NaiveTime, TimeZone};
     use rust_decimal_macros::dec;
 
     use super::*;
 
     #[test]
     fn coalesce_orders_none() {
-        let result = Exchange::<Ftx>::coalesce_orders(&Vec::new());
+        let result = Exchange::<Mock<fn(CandleKey) -> Candle>>::coalesce_orders(&Vec::new());
         assert_eq!(result.value(), dec!(0));
     }
 
@@ -589,7 +2503,7 @@ mod tests {
         let mut vb1 = ValuedBundle::default();
         vb1.bundle.0.insert(symbol, dec!(10));
 
-        let result = Exchange::<Ftx>::coalesce_orders(&[vb1]);
+        let result = Exchange::<Mock<fn(CandleKey) -> Candle>>::coalesce_orders(&[vb1]);
 
         assert_eq!(result.bundle.0.get(&symbol), Some(&dec!(10)));
     }
@@ -601,7 +2515,7 @@ mod tests {
         let mut vb1 = ValuedBundle::default();
         vb1.bundle.0.insert(symbol, dec!(-10));
 
-        let result = Exchange::<Ftx>::coalesce_orders(&[vb1]);
+        let result = Exchange::<Mock<fn(CandleKey) -> Candle>>::coalesce_orders(&[vb1]);
 
         assert_eq!(result.bundle.0.get(&symbol), Some(&dec!(-10)));
     }
@@ -617,14 +2531,14 @@ mod tests {
         let mut vb3 = ValuedBundle::default();
         vb3.bundle.0.insert(symbol, dec!(5));
 
-        let result = Exchange::<Ftx>::coalesce_orders(&[vb1, vb2, vb3]);
+        let result = Exchange::<Mock<fn(CandleKey) -> Candle>>::coalesce_orders(&[vb1, vb2, vb3]);
 
         assert_eq!(result.bundle.0.get(&symbol), Some(&dec!(0)));
     }
 
     #[tokio::test]
     async fn order_bundles_single_unvalued() {
-        let api = Simulate::new(Ftx::from_env(), Wallet::default());
+        let api = Simulate::new(venue(), Wallet::default());
         let exchange = Exchange::new(api, Utc::now());
         let symbol = Symbol::perp("BTC");
         let time = Utc::now();
@@ -640,7 +2554,7 @@ mod tests {
 
     #[tokio::test]
     async fn order_bundles_multiple_unvalued() {
-        let api = Simulate::new(Ftx::from_env(), Wallet::default());
+        let api = Simulate::new(venue(), Wallet::default());
         let exchange = Exchange::new(api, Utc::now());
         let symbol = Symbol::perp("BTC");
         let time = Utc::now();
@@ -666,7 +2580,7 @@ mod tests {
 
     #[tokio::test]
     async fn order_bundles_single_valued() {
-        let api = Simulate::new(Ftx::from_env(), Wallet::default());
+        let api = Simulate::new(venue(), Wallet::default());
         let fee = api.order_fee().await;
         let exchange = Exchange::new(api, Utc::now());
         let symbol = Symbol::perp("BTC");
@@ -685,4 +2599,1366 @@ mod tests {
             Some(&(dec!(10000) * (dec!(1) + fee)))
         );
     }
+
+    fn settings() -> MockSettings<impl CandleGen> {
+        MockSettings::new(|_key: CandleKey| Candle {
+            open: dec!(100),
+            high: dec!(100),
+            low: dec!(100),
+            close: dec!(100),
+            volume: dec!(1),
+        })
+    }
+
+    // A venue with a fee, to be wrapped by Simulate.
+    fn venue() -> Mock<impl CandleGen> {
+        let mut settings = settings();
+        settings.fee = dec!(0.0007);
+        Mock::new(settings)
+    }
+
+    fn mock(fills: FillBehavior) -> Exchange<Mock<impl CandleGen>> {
+        let mut settings = settings();
+        settings.fills = fills;
+        Exchange::new(Mock::new(settings), Utc::now())
+    }
+
+    fn buy(symbol: Symbol, size: Decimal) -> ValuedBundle {
+        let mut vb = ValuedBundle::default();
+        vb.bundle.0.insert(symbol, size);
+        vb.valuation.0.insert(symbol, dec!(100));
+        vb.time = Some(Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap());
+        vb
+    }
+
+    #[test]
+    fn update_indicators_once_per_candle() {
+        let mut exchange = mock(FillBehavior::Fill);
+        let btc = Symbol::perp("BTC");
+        let sma = exchange.attach(btc, crate::indicators::Sma::new(2));
+        let start = exchange.current_time;
+        let key = |minutes| CandleKey {
+            market: btc,
+            time: start + Duration::minutes(minutes),
+            interval: Duration::minutes(1),
+        };
+        let candle = |close| Candle {
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: dec!(1),
+        };
+        // The candle of the second step is missing, so the third stays current for two steps.
+        exchange.candles.get_mut(&btc).unwrap().extend([
+            (key(0), Some(candle(dec!(1)))),
+            (key(2), Some(candle(dec!(3)))),
+        ]);
+
+        for _ in 0..3 {
+            exchange.estimate_spreads();
+            exchange.current_time += Duration::minutes(1);
+            let time = exchange.current_time;
+            exchange.candles.get_mut(&btc).unwrap().advance(time);
+        }
+
+        assert_eq!(exchange.indicator(sma), Some(dec!(2)));
+        assert!(exchange.warmed_up(btc, 2));
+        assert!(!exchange.warmed_up(btc, 3));
+    }
+
+    #[tokio::test]
+    async fn order_partial_fill() {
+        let exchange = mock(FillBehavior::PartialFill(dec!(0.5)));
+        let symbol = Symbol::perp("BTC");
+
+        let result = exchange
+            .order(vec![buy(symbol, dec!(10)), buy(symbol, dec!(6))])
+            .await
+            .unwrap();
+
+        assert_eq!(result[0].bundle.0.get(&symbol), Some(&dec!(5)));
+        assert_eq!(result[1].bundle.0.get(&symbol), Some(&dec!(3)));
+    }
+
+    #[tokio::test]
+    async fn order_confirmed_fills() {
+        let symbol = Symbol::perp("BTC");
+
+        // Filled once the status was queried twice.
+        let mut exchange = mock(FillBehavior::Confirm(2));
+        exchange.fill_timeout = Duration::seconds(5);
+        exchange.fill_poll = Duration::milliseconds(1);
+        let result = exchange.order(vec![buy(symbol, dec!(10))]).await.unwrap();
+        assert_eq!(result[0].bundle.0.get(&symbol), Some(&dec!(10)));
+
+        // Not filled without waiting.
+        let exchange = mock(FillBehavior::Confirm(2));
+        let result = exchange.order(vec![buy(symbol, dec!(10))]).await.unwrap();
+        assert_eq!(result[0].bundle.0.get(&symbol), Some(&dec!(0)));
+        assert_eq!(result[0].valuation.0.get(&symbol), Some(&dec!(100)));
+    }
+
+    #[tokio::test]
+    async fn confirm_keeps_last_status_on_errors() {
+        let symbol = Symbol::perp("BTC");
+        let mut exchange = mock(FillBehavior::Confirm(2));
+        exchange.fill_timeout = Duration::milliseconds(10);
+        exchange.fill_poll = Duration::milliseconds(1);
+
+        // The venue does not know the order, so querying and cancelling it fails.
+        let orders: Vec<Order> = buy(symbol, dec!(10)).into();
+        let info = OrderInfo {
+            order_id: orders[0].order_id,
+            market: symbol,
+            size: dec!(0),
+            price: dec!(100),
+            time: orders[0].time,
+            side: Side::Buy,
+            status: OrderStatus::Submitted,
+        };
+        let confirmed = exchange.confirm(&orders[0], info).await;
+        assert_eq!(confirmed.status, OrderStatus::Submitted);
+        assert_eq!(confirmed.size, dec!(0));
+    }
+
+    #[tokio::test]
+    async fn unconfirmed_orders_are_cancelled() {
+        let symbol = Symbol::perp("BTC");
+
+        let mut exchange = mock(FillBehavior::Confirm(100));
+        let (_, fills) = exchange
+            .order_with(vec![buy(symbol, dec!(10))], &[], &mut |_| {})
+            .await
+            .unwrap();
+        assert_eq!(fills[0].1.status, OrderStatus::Cancelled);
+        assert_eq!(fills[0].1.size, dec!(0));
+
+        // Orders that could not be cancelled are pending until cancelling succeeds.
+        let (order, _) = fills[0].clone();
+        let order = Order {
+            order_id: uuid::Uuid::new_v4(),
+            ..order
+        };
+        let info = exchange.api.place_order(order.clone()).await.unwrap();
+        exchange.pending.push(PendingOrder {
+            order,
+            info,
+            booked: dec!(0),
+        });
+        assert_eq!(exchange.pending_orders().len(), 1);
+        exchange.settle_pending().await;
+        assert!(exchange.pending_orders().is_empty());
+    }
+
+    #[tokio::test]
+    async fn guarded_transfers() {
+        let usd = Asset::new("USD");
+        let mut exchange = mock(FillBehavior::Fill);
+        exchange.wallet.deposit(dec!(100), usd);
+        let other = Account::Subaccount("other".to_owned());
+
+        exchange
+            .transfer(Transfer::to(other.clone(), usd, dec!(60)))
+            .unwrap();
+        assert_eq!(
+            exchange.transfer(Transfer::to(other.clone(), usd, dec!(50))),
+            Err(TransferError::NotEnoughFree {
+                asset: usd,
+                available: dec!(40)
+            })
+        );
+        assert_eq!(
+            exchange.transfer(Transfer::to(other.clone(), usd, dec!(0))),
+            Err(TransferError::InvalidAmount)
+        );
+        assert_eq!(
+            exchange.transfer(Transfer {
+                asset: usd,
+                amount: dec!(1),
+                from: Account::Main,
+                to: other.clone(),
+            }),
+            Err(TransferError::NotTradingAccount)
+        );
+        exchange
+            .transfer(Transfer::from(Account::Main, usd, dec!(5)))
+            .unwrap();
+
+        exchange.execute_transfers().await;
+        assert_eq!(exchange.wallet.free(usd), dec!(45));
+        assert_eq!(exchange.wallet.total(usd), dec!(45));
+        assert!(exchange.transfers.is_empty());
+    }
+
+    #[tokio::test]
+    async fn rejected_orders() {
+        let symbol = Symbol::perp("BTC");
+
+        // Rejected orders are not filled.
+        let mut exchange = mock(FillBehavior::RejectEveryNth(2));
+        let filled = exchange.order(vec![buy(symbol, dec!(1))]).await.unwrap();
+        assert_eq!(filled[0].bundle.0.get(&symbol), Some(&dec!(1)));
+        let (rejected, fills) = exchange
+            .order_with(vec![buy(symbol, dec!(1))], &[], &mut |_| {})
+            .await
+            .unwrap();
+        assert_eq!(rejected[0].bundle.0.get(&symbol), Some(&dec!(0)));
+        assert_eq!(fills[0].1.status, OrderStatus::Rejected);
+        exchange.book_fills(&fills);
+        assert!(exchange.pending_orders().is_empty());
+    }
+
+    #[tokio::test]
+    async fn network_errors_keep_orders_pending() {
+        let symbol = Symbol::perp("BTC");
+
+        // The venue may have received the order, so it is not booked as a final zero fill.
+        let mut exchange = mock(FillBehavior::NetworkErrorEveryNth(1));
+        let (failed, fills) = exchange
+            .order_with(vec![buy(symbol, dec!(1))], &[], &mut |_| {})
+            .await
+            .unwrap();
+        assert_eq!(failed[0].bundle.0.get(&symbol), Some(&dec!(0)));
+        assert_eq!(fills[0].1.status, OrderStatus::Submitted);
+        exchange.book_fills(&fills);
+        assert_eq!(exchange.pending_orders().len(), 1);
+        assert_eq!(exchange.pending_orders()[0].info.size, dec!(0));
+    }
+
+    #[tokio::test]
+    async fn skip_missed_steps() {
+        let mut exchange = mock(FillBehavior::Fill);
+        let start = Utc::now() - Duration::minutes(10) + Duration::seconds(1);
+        exchange.current_time = start;
+        let settings = Settings {
+            on_overrun: OnOverrun::Skip,
+            ..Default::default()
+        };
+
+        let missed = exchange.missed_steps(&settings);
+        assert_eq!(missed, 8);
+        exchange.overrun(&settings, missed).await.unwrap();
+        assert_eq!(exchange.current_time, start + Duration::minutes(8));
+        assert_eq!(exchange.timing().skipped, 8);
+
+        let settings = Settings::default();
+        exchange.overrun(&settings, 1).await.unwrap();
+        assert_eq!(exchange.current_time, start + Duration::minutes(8));
+    }
+
+    #[test]
+    fn step_skips_closed_hours() {
+        let mut exchange = mock(FillBehavior::Fill);
+        // 2021-01-01 was a friday.
+        exchange.current_time = Utc.with_ymd_and_hms(2021, 1, 1, 20, 0, 0).unwrap();
+        let settings = Settings {
+            interval: Interval::Fixed(Duration::hours(1)),
+            calendar: Some(Calendar::weekdays(
+                NaiveTime::from_hms_opt(14, 30, 0).unwrap(),
+                NaiveTime::from_hms_opt(21, 0, 0).unwrap(),
+            )),
+            ..Default::default()
+        };
+
+        exchange.step(&settings);
+
+        // The first hour starting within the monday session.
+        assert_eq!(
+            exchange.current_time,
+            Utc.with_ymd_and_hms(2021, 1, 4, 15, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn open_in_allowed_direction() {
+        let mut exchange = mock(FillBehavior::Fill);
+        let (btc, eth) = (Symbol::perp("BTC"), Symbol::perp("ETH"));
+        for symbol in [btc, eth] {
+            exchange
+                .markets
+                .markets
+                .insert(symbol, MarketInfo::unconstrained(symbol));
+        }
+        exchange.direction = Direction::LongOnly;
+        let pair = || Position::default().long(btc, dec!(1)).short(eth, dec!(10));
+
+        assert!(matches!(
+            exchange.open(pair()),
+            Err(PrepareError::DirectionNotAllowed)
+        ));
+        assert!(exchange
+            .open(Position::default().long(btc, dec!(1)))
+            .is_ok());
+
+        exchange.on_forbidden_direction = OnForbiddenDirection::Clamp;
+        let id = exchange.open(pair()).unwrap();
+        let position = exchange.position(id).unwrap();
+        assert_eq!(position.next_size.0.keys().collect::<Vec<_>>(), vec![&btc]);
+        assert!(matches!(
+            exchange.open(Position::default().short(eth, dec!(10))),
+            Err(PrepareError::DirectionNotAllowed)
+        ));
+    }
+
+    #[tokio::test]
+    async fn unwind_unbalanced_legs() {
+        let (btc, eth) = (Symbol::perp("BTC"), Symbol::perp("ETH"));
+        let usd = Asset::new("USD");
+        let mut settings = settings();
+        settings.fills = FillBehavior::Illiquid(eth);
+        settings.markets = [btc, eth].map(MarketInfo::unconstrained).to_vec();
+        let mut exchange = Exchange::new(
+            Mock::new(settings),
+            Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap(),
+        );
+        exchange.watch(btc);
+        exchange.watch(eth);
+        exchange
+            .update(&Settings::default(), &mut Duration::zero())
+            .await
+            .unwrap();
+        exchange.wallet.deposit(dec!(10000), usd);
+        let pair = || Position::default().long(btc, dec!(1)).short(eth, dec!(10));
+
+        // Independent legs keep what was filled.
+        let independent = exchange.open(pair()).unwrap();
+        exchange.valuate();
+        exchange.execute().await.unwrap();
+        let imbalance = &exchange.leg_imbalances()[0];
+        assert_eq!(imbalance.position, independent);
+        assert_eq!(imbalance.filled_fraction(), dec!(0));
+        assert_eq!(imbalance.excess().get(&btc), Some(&dec!(1)));
+        assert!(imbalance.unwound.is_empty());
+        let position = exchange.position(independent).unwrap();
+        assert_eq!(position.current.bundle.0.get(&btc), Some(&dec!(1)));
+        exchange.close_all();
+        exchange.execute().await.unwrap();
+        assert!(exchange.leg_imbalances().is_empty());
+
+        // All-or-nothing legs are unwound, which closes the position.
+        let atomic = exchange
+            .open(pair().with_leg_policy(LegPolicy::AllOrNothing))
+            .unwrap();
+        exchange.valuate();
+        let fills = exchange.execute_with(&mut |_| {}).await.unwrap();
+        assert_eq!(fills.len(), 3);
+        let imbalance = &exchange.leg_imbalances()[0];
+        assert_eq!(imbalance.position, atomic);
+        assert_eq!(imbalance.unwound.get(&btc), Some(&dec!(-1)));
+        assert!(imbalance.excess().values().all(|size| size.is_zero()));
+        assert!(exchange.position(atomic).is_none());
+        assert_eq!(exchange.closed_positions.last().unwrap().id, atomic);
+        assert_eq!(exchange.wallet.total(usd), dec!(10000));
+    }
+
+    #[tokio::test]
+    async fn unwind_rejected_legs() {
+        let (btc, eth) = (Symbol::perp("BTC"), Symbol::perp("ETH"));
+        let usd = Asset::new("USD");
+        let mut settings = settings();
+        settings.fills = FillBehavior::RejectMarket(eth);
+        settings.markets = [btc, eth].map(MarketInfo::unconstrained).to_vec();
+        let mut exchange = Exchange::new(
+            Mock::new(settings),
+            Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap(),
+        );
+        exchange.watch(btc);
+        exchange.watch(eth);
+        exchange
+            .update(&Settings::default(), &mut Duration::zero())
+            .await
+            .unwrap();
+        exchange.wallet.deposit(dec!(10000), usd);
+
+        // The filled leg is unwound instead of failing the execution.
+        let atomic = exchange
+            .open(
+                Position::default()
+                    .long(btc, dec!(1))
+                    .short(eth, dec!(10))
+                    .with_leg_policy(LegPolicy::AllOrNothing),
+            )
+            .unwrap();
+        exchange.valuate();
+        let fills = exchange.execute_with(&mut |_| {}).await.unwrap();
+        assert!(fills
+            .iter()
+            .any(|(order, info)| order.market == eth && info.status == OrderStatus::Rejected));
+        let imbalance = &exchange.leg_imbalances()[0];
+        assert_eq!(imbalance.position, atomic);
+        assert_eq!(imbalance.unwound.get(&btc), Some(&dec!(-1)));
+        assert!(exchange.position(atomic).is_none());
+        assert_eq!(exchange.wallet.total(usd), dec!(10000));
+    }
+
+    #[test]
+    fn look_up_opened_positions() {
+        let mut exchange = mock(FillBehavior::Fill);
+        let (btc, eth) = (Symbol::perp("BTC"), Symbol::perp("ETH"));
+        for symbol in [btc, eth] {
+            exchange
+                .markets
+                .markets
+                .insert(symbol, MarketInfo::unconstrained(symbol));
+        }
+
+        let long = exchange
+            .open(Position::default().long(btc, dec!(1)))
+            .unwrap();
+        let short = exchange
+            .open(Position::default().short(eth, dec!(2)))
+            .unwrap();
+        assert_ne!(long, short);
+        assert_eq!(exchange.position(long).unwrap().remaining(btc), dec!(1));
+        exchange.position_mut(short).unwrap().close();
+        assert_eq!(exchange.position(short).unwrap().remaining(eth), dec!(0));
+        assert!(exchange.position(PositionId::new_v4()).is_none());
+    }
+
+    #[tokio::test]
+    async fn account_modes() {
+        let mut exchange = mock(FillBehavior::Fill);
+        let btc = Symbol::perp("BTC");
+        exchange
+            .markets
+            .markets
+            .insert(btc, MarketInfo::unconstrained(btc));
+
+        // Netting holds one net position per symbol.
+        assert!(exchange
+            .open(Position::default().long(btc, dec!(1)))
+            .is_ok());
+        assert!(matches!(
+            exchange.open(Position::default().short(btc, dec!(1))),
+            Err(PrepareError::OpposingPosition(symbol)) if symbol == btc
+        ));
+        let (_, fills) = exchange
+            .order_with(
+                vec![buy(btc, dec!(1)), buy(btc, dec!(-1))],
+                &[],
+                &mut |_| {},
+            )
+            .await
+            .unwrap();
+        assert!(fills.is_empty());
+
+        // Hedged legs are ordered independently.
+        exchange.account_mode = AccountMode::Hedged;
+        assert!(exchange
+            .open(Position::default().short(btc, dec!(1)))
+            .is_ok());
+        let legs: Vec<Legs> = vec![
+            [(btc, PositionSide::Long)].into(),
+            [(btc, PositionSide::Short)].into(),
+        ];
+        let (result, fills) = exchange
+            .order_with(
+                vec![buy(btc, dec!(1)), buy(btc, dec!(-1))],
+                &legs,
+                &mut |_| {},
+            )
+            .await
+            .unwrap();
+        assert_eq!(result[0].bundle.0.get(&btc), Some(&dec!(1)));
+        assert_eq!(result[1].bundle.0.get(&btc), Some(&dec!(-1)));
+        let mut sides: Vec<_> = fills
+            .iter()
+            .map(|(order, _)| (order.side, order.position_side))
+            .collect();
+        sides.sort_by_key(|(side, _)| *side == Side::Sell);
+        assert_eq!(
+            sides,
+            vec![
+                (Side::Buy, Some(PositionSide::Long)),
+                (Side::Sell, Some(PositionSide::Short))
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn refresh_markets_periodically() {
+        let btc = Symbol::perp("BTC");
+        let mut settings = settings();
+        settings.markets = vec![MarketInfo::unconstrained(btc)];
+        let mut exchange = Exchange::new(Mock::new(settings), Utc::now());
+        async fn update<A: Api>(exchange: &mut Exchange<A>) -> usize {
+            exchange.markets = Markets::default();
+            exchange
+                .update(&Settings::default(), &mut Duration::zero())
+                .await
+                .unwrap();
+            exchange.markets().count()
+        }
+
+        // Backtests load the markets once, unless requested.
+        assert_eq!(update(&mut exchange).await, 1);
+        assert_eq!(update(&mut exchange).await, 0);
+        exchange.refresh_markets();
+        assert_eq!(update(&mut exchange).await, 1);
+
+        // Live, they are refreshed once they are older than the refresh interval.
+        exchange.real_time = true;
+        assert_eq!(update(&mut exchange).await, 0);
+        exchange.current_time += Duration::hours(1);
+        assert_eq!(update(&mut exchange).await, 1);
+    }
+
+    #[tokio::test]
+    async fn retry_failed_market_updates() {
+        let mut settings = settings();
+        settings.markets = vec![MarketInfo::unconstrained(Symbol::perp("BTC"))];
+        settings.market_failures = 1;
+        let mut exchange = Exchange::new(Mock::new(settings), Utc::now());
+
+        assert!(exchange
+            .update(&Settings::default(), &mut Duration::zero())
+            .await
+            .is_err());
+        assert_eq!(exchange.markets_updated, None);
+        exchange
+            .update(&Settings::default(), &mut Duration::zero())
+            .await
+            .unwrap();
+        assert_eq!(exchange.markets().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn reconcile_wallet() {
+        let usd = Asset::new("USD");
+        let mut booked = Wallet::new();
+        booked.deposit(dec!(1000), usd);
+        let mut venue = Wallet::new();
+        venue.deposit(dec!(990), usd);
+        let exchange = |correct| {
+            let mut settings = settings();
+            settings.venue_wallet = Some(venue.clone());
+            let mut exchange = Exchange::new(
+                Mock::new(settings),
+                Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap(),
+            );
+            exchange.reconciliation = Some(Reconciliation {
+                correct,
+                ..Default::default()
+            });
+            exchange.wallet = booked.clone();
+            exchange.real_time = true;
+            exchange
+        };
+        async fn update<A: Api>(exchange: &mut Exchange<A>) {
+            exchange
+                .update(&Settings::default(), &mut Duration::zero())
+                .await
+                .unwrap();
+        }
+
+        let mut corrected = exchange(true);
+        update(&mut corrected).await;
+        assert_eq!(corrected.wallet.total(usd), dec!(990));
+        assert_eq!(corrected.wallet_discrepancies.len(), 1);
+        assert_eq!(corrected.wallet_discrepancies[0].difference(), dec!(-10));
+        assert!(corrected.wallet_discrepancies[0].corrected);
+        // The wallets are compared again once the reconciliation is due.
+        for (minutes, discrepancies) in [(30, 1), (30, 2)] {
+            corrected.wallet = booked.clone();
+            corrected.current_time += Duration::minutes(minutes);
+            update(&mut corrected).await;
+            assert_eq!(corrected.wallet_discrepancies.len(), discrepancies);
+        }
+
+        // Without correcting, the booked wallet is kept.
+        let mut kept = exchange(false);
+        update(&mut kept).await;
+        assert_eq!(kept.wallet.total(usd), dec!(1000));
+        assert!(!kept.wallet_discrepancies[0].corrected);
+
+        // Backtests are not reconciled.
+        let mut backtest = exchange(true);
+        backtest.real_time = false;
+        update(&mut backtest).await;
+        assert!(backtest.wallet_discrepancies.is_empty());
+    }
+
+    #[tokio::test]
+    async fn valuate_at_price_source() {
+        let (btc, eth) = (Symbol::perp("BTC"), Symbol::perp("ETH"));
+        let mut settings = settings();
+        settings.prices.insert((btc, PriceSource::Mark), dec!(110));
+        settings.markets = [btc, eth].map(MarketInfo::unconstrained).to_vec();
+        let mut exchange = Exchange::new(
+            Mock::new(settings),
+            Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap(),
+        );
+        exchange.watch(btc);
+        exchange.watch(eth);
+        let settings = Settings {
+            price_source: PriceSource::Mark,
+            ..Default::default()
+        };
+
+        exchange
+            .update(&settings, &mut Duration::zero())
+            .await
+            .unwrap();
+        exchange
+            .open(Position::default().long(btc, dec!(1)).long(eth, dec!(1)))
+            .unwrap();
+        exchange.valuate();
+
+        // The mark price of ETH is unknown, it is valued at the close.
+        let position = exchange.positions().next().unwrap();
+        assert_eq!(position.current.valuation.0.get(&btc), Some(&dec!(110)));
+        assert_eq!(position.current.valuation.0.get(&eth), Some(&dec!(100)));
+    }
+
+    #[tokio::test]
+    async fn value_contracts_with_multiplier() {
+        let btc = Symbol::perp("BTC");
+        let usd = Asset::new("USD");
+        let mut settings = settings();
+        settings.markets = vec![MarketInfo {
+            price_increment: dec!(0.5),
+            contract_multiplier: dec!(5),
+            ..MarketInfo::unconstrained(btc)
+        }];
+        let mut exchange = Exchange::new(
+            Mock::new(settings),
+            Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap(),
+        );
+        exchange.watch(btc);
+        exchange
+            .update(&Settings::default(), &mut Duration::zero())
+            .await
+            .unwrap();
+        exchange.wallet.deposit(dec!(10000), usd);
+        exchange
+            .open(Position::default().long(btc, dec!(2)))
+            .unwrap();
+        exchange.valuate();
+        assert_eq!(exchange.market(btc).tick_value(), dec!(2.5));
+
+        // Prices are per unit of the underlying, values apply the multiplier.
+        let fills = exchange.execute_with(&mut |_| {}).await.unwrap();
+        assert_eq!(fills[0].0.current_price, dec!(100));
+        let position = exchange.positions().next().unwrap();
+        assert_eq!(position.fills()[0].price, dec!(100));
+        assert_eq!(position.average_entry_price(btc), Some(dec!(100)));
+        assert_eq!(position.entry_value(), dec!(1000));
+        assert_eq!(position.notional(), dec!(1000));
+        assert_eq!(position.value(), dec!(1000));
+        assert_eq!(exchange.wallet.total(usd), dec!(9000));
+        assert_eq!(exchange.total(), dec!(10000));
+    }
+
+    #[tokio::test]
+    async fn select_universe_by_volume() {
+        let symbols = ["BTC", "ETH", "SOL", "DOGE"].map(Symbol::perp);
+        let mut settings = settings();
+        settings.markets = symbols
+            .iter()
+            .zip([dec!(400), dec!(300), dec!(200), dec!(100)])
+            .map(|(&symbol, daily_quote_volume)| MarketInfo {
+                daily_quote_volume,
+                ..MarketInfo::unconstrained(symbol)
+            })
+            .collect();
+        let [btc, eth, sol, doge] = symbols;
+        let mut exchange = Exchange::new(
+            Mock::new(settings),
+            Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap(),
+        );
+        let settings = Settings::default();
+        exchange
+            .update(&settings, &mut Duration::zero())
+            .await
+            .unwrap();
+
+        // Backtests would rank the markets by their future volume.
+        assert_eq!(exchange.select_universe(|_| true, 2), Err(LookAheadError));
+        exchange.real_time = true;
+
+        // Manually watched markets are left alone.
+        exchange.watch(btc);
+        let change = exchange
+            .select_universe(|info| info.symbol != sol, 2)
+            .unwrap();
+        assert_eq!(change.added, vec![eth]);
+        assert!(!exchange.warmed_up(eth, 1));
+
+        exchange
+            .update(&settings, &mut Duration::zero())
+            .await
+            .unwrap();
+        exchange.estimate_spreads();
+        assert!(exchange.warmed_up(eth, 1));
+        exchange
+            .open(Position::default().long(eth, dec!(1)))
+            .unwrap();
+
+        // The held market stays watched until the position is closed.
+        let change = exchange
+            .select_universe(|info| info.symbol == doge, 2)
+            .unwrap();
+        assert_eq!(change.added, vec![doge]);
+        assert_eq!(change.held, vec![eth]);
+        assert!(exchange.candle(eth).is_some());
+
+        // As if the position was closed.
+        exchange.open_positions.clear();
+        let change = exchange
+            .select_universe(|info| info.symbol == doge, 2)
+            .unwrap();
+        assert_eq!(change.removed, vec![eth]);
+        assert_eq!(exchange.universe().collect::<Vec<_>>(), vec![doge]);
+        assert!(exchange.candle(btc).is_some());
+    }
+
+    struct Idle;
+
+    impl<A: Api> Strategy<A> for Idle {
+        const NAME: &'static str = "Idle";
+        type Parameters = ();
+
+        fn init(&mut self, _exchange: &mut Exchange<A>) -> Result<Settings, AnyError> {
+            Ok(Settings::default())
+        }
+
+        fn eval(&mut self, _exchange: &mut Exchange<A>) -> Result<(), AnyError> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn cancelled_run_returns_report() {
+        let token = CancellationToken::new();
+        let exchange = mock(FillBehavior::Fill).with_cancellation(token.clone());
+        token.cancel();
+
+        let report = exchange.run(Idle).await.unwrap();
+        assert_eq!(report.steps, 1);
+    }
+
+    struct Opening;
+
+    impl<A: Api> Strategy<A> for Opening {
+        const NAME: &'static str = "Opening";
+        type Parameters = ();
+
+        fn init(&mut self, exchange: &mut Exchange<A>) -> Result<Settings, AnyError> {
+            exchange.watch(Symbol::perp("BTC"));
+            Ok(Settings::default())
+        }
+
+        fn eval(&mut self, exchange: &mut Exchange<A>) -> Result<(), AnyError> {
+            if exchange.positions().next().is_none() {
+                exchange.open(Position::default().long(Symbol::perp("BTC"), dec!(1)))?;
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn stop_at_end_time() {
+        let start = Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap();
+        let exchange = || {
+            let mut settings = settings();
+            settings.markets = vec![MarketInfo::unconstrained(Symbol::perp("BTC"))];
+            let mut wallet = Wallet::new();
+            wallet.deposit(dec!(1000), Asset::new("USD"));
+            Exchange::new(Simulate::new(Mock::new(settings), wallet), start)
+                .until(start + Duration::minutes(5))
+        };
+
+        let report = exchange().run(Opening).await.unwrap();
+        assert_eq!(report.end_time, start + Duration::minutes(4));
+        assert!(report.closed_positions.is_empty());
+
+        let report = exchange()
+            .with_close_at_end(true)
+            .run(Opening)
+            .await
+            .unwrap();
+        assert_eq!(report.end_time, start + Duration::minutes(5));
+        assert_eq!(report.closed_positions.len(), 1);
+    }
+
+    // Logs the number of evaluations as a custom metric.
+    struct Logging(u32);
+
+    impl<A: Api> Strategy<A> for Logging {
+        const NAME: &'static str = "Logging";
+        type Parameters = ();
+
+        fn init(&mut self, _exchange: &mut Exchange<A>) -> Result<Settings, AnyError> {
+            Ok(Settings::default())
+        }
+
+        fn eval(&mut self, exchange: &mut Exchange<A>) -> Result<(), AnyError> {
+            self.0 += 1;
+            exchange.metric("evals", Decimal::from(self.0));
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn metrics_in_report() {
+        let start = Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap();
+        let report = Exchange::new(Simulate::new(Mock::new(settings()), Wallet::new()), start)
+            .until(start + Duration::minutes(3))
+            .run(Logging(0))
+            .await
+            .unwrap();
+
+        assert_eq!(report.metrics.len() as u64, report.steps);
+        assert_eq!(
+            report.metrics.last(),
+            Some(&CustomMetric {
+                name: "evals".to_owned(),
+                time: report.end_time,
+                value: Decimal::from(report.steps),
+            })
+        );
+    }
+
+    // Does nothing, but has parameters.
+    #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct Tuned {
+        period: u32,
+        threshold: Decimal,
+    }
+
+    impl<A: Api> Strategy<A> for Tuned {
+        const NAME: &'static str = "Tuned";
+        type Parameters = Tuned;
+
+        fn parameters(&self) -> Option<Tuned> {
+            Some(self.clone())
+        }
+
+        fn init(&mut self, _exchange: &mut Exchange<A>) -> Result<Settings, AnyError> {
+            Ok(Settings::default())
+        }
+
+        fn eval(&mut self, _exchange: &mut Exchange<A>) -> Result<(), AnyError> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn parameters_in_report() {
+        let start = Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap();
+        let run = |strategy: Tuned| {
+            Exchange::new(Simulate::new(Mock::new(settings()), Wallet::new()), start)
+                .until(start + Duration::minutes(3))
+                .run(strategy)
+        };
+        let tuned = Tuned {
+            period: 20,
+            threshold: dec!(0.5),
+        };
+        let report = run(tuned.clone()).await.unwrap();
+        assert_eq!(report.parameters::<Tuned>().unwrap(), Some(tuned));
+
+        // A run can be repeated from the parameters of its serialized report.
+        let stored: Report =
+            serde_json::from_str(&serde_json::to_string(&report).unwrap()).unwrap();
+        let repeated = run(stored.parameters().unwrap().unwrap()).await.unwrap();
+        assert_eq!(repeated, report);
+
+        let report = Exchange::new(Simulate::new(Mock::new(settings()), Wallet::new()), start)
+            .until(start + Duration::minutes(3))
+            .run(Logging(0))
+            .await
+            .unwrap();
+        assert_eq!(report.parameters::<()>().unwrap(), None);
+    }
+
+    // Opens a long ETH position backed by BTC, recording the results and the margin left.
+    #[derive(Default)]
+    struct Collateralized {
+        results: Arc<std::sync::Mutex<Vec<Result<(), String>>>>,
+        // The available margin, the USD balance and the total after every step.
+        steps: Arc<std::sync::Mutex<Vec<(Decimal, Decimal, Decimal)>>>,
+    }
+
+    impl<A: Api> Strategy<A> for Collateralized {
+        const NAME: &'static str = "Collateralized";
+        type Parameters = ();
+
+        fn init(&mut self, exchange: &mut Exchange<A>) -> Result<Settings, AnyError> {
+            exchange.watch(Symbol::perp("BTC"));
+            exchange.watch(Symbol::perp("ETH"));
+            Ok(Settings::default())
+        }
+
+        fn eval(&mut self, exchange: &mut Exchange<A>) -> Result<(), AnyError> {
+            let mut results = self.results.lock().unwrap();
+            if results.is_empty() {
+                for size in [dec!(2), dec!(1)] {
+                    let result = exchange
+                        .open(Position::default().long(Symbol::perp("ETH"), size))
+                        .map(|_| ())
+                        .map_err(|err| err.to_string());
+                    results.push(result);
+                }
+            }
+            self.steps.lock().unwrap().push((
+                exchange.available_margin(),
+                exchange.wallet().total(Asset::new("USD")),
+                exchange.total(),
+            ));
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn borrow_against_collateral() {
+        let start = Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap();
+        let btc = Asset::new("BTC");
+        let mut wallet = Wallet::new();
+        wallet.deposit(dec!(2), btc);
+        let margin = Margin::new(dec!(1), dec!(0.05))
+            .with_collateral(CollateralWeights::new().with_weight(btc, dec!(0.5)));
+        let mut settings = settings();
+        settings.markets = ["BTC", "ETH"]
+            .map(|asset| MarketInfo::unconstrained(Symbol::perp(asset)))
+            .into();
+        let strategy = Collateralized::default();
+        let (results, steps) = (strategy.results.clone(), strategy.steps.clone());
+        Exchange::new(
+            Simulate::new(Mock::new(settings), wallet).with_margin(margin),
+            start,
+        )
+        .until(start + Duration::minutes(2))
+        .run(strategy)
+        .await
+        .unwrap();
+
+        // The 2 BTC at 100 back 100 USD of positions, which are borrowed.
+        assert_eq!(
+            *results.lock().unwrap(),
+            vec![Err(PrepareError::InsufficientAssets.to_string()), Ok(())]
+        );
+        assert_eq!(
+            *steps.lock().unwrap(),
+            vec![
+                (dec!(100), dec!(0), dec!(200)),
+                (dec!(0), dec!(-100), dec!(200))
+            ]
+        );
+    }
+
+    // Rotates between markets every step, closing the last position and opening the next.
+    struct Rotating(usize);
+
+    impl<A: Api> Strategy<A> for Rotating {
+        const NAME: &'static str = "Rotating";
+        type Parameters = ();
+
+        fn init(&mut self, exchange: &mut Exchange<A>) -> Result<Settings, AnyError> {
+            for name in ["BTC", "ETH", "SOL"] {
+                exchange.watch(Symbol::perp(name));
+            }
+            Ok(Settings {
+                seed: Some(42),
+                ..Default::default()
+            })
+        }
+
+        fn eval(&mut self, exchange: &mut Exchange<A>) -> Result<(), AnyError> {
+            exchange.close_all();
+            let names = ["BTC", "ETH", "SOL"];
+            let (first, second) = (names[self.0 % 3], names[(self.0 + 1) % 3]);
+            self.0 += 1;
+            exchange.open(
+                Position::default()
+                    .long(Symbol::perp(first), dec!(0.1))
+                    .long(Symbol::perp(second), dec!(0.2)),
+            )?;
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn seeded_backtests_are_identical() {
+        let start = Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap();
+        let run = || async move {
+            let mut settings = MockSettings::new(SeededCandles::random_walk(7, start));
+            settings.markets = ["BTC", "ETH", "SOL"]
+                .into_iter()
+                .map(|name| MarketInfo::unconstrained(Symbol::perp(name)))
+                .collect();
+            let mut wallet = Wallet::new();
+            wallet.deposit(dec!(1000), Asset::new("USD"));
+            let report = Exchange::new(Simulate::new(Mock::new(settings), wallet), start)
+                .until(start + Duration::minutes(30))
+                .with_close_at_end(true)
+                .run(Rotating(0))
+                .await
+                .unwrap();
+            serde_json::to_string(&report).unwrap()
+        };
+
+        let report = run().await;
+        assert!(report.contains("closed_positions"));
+        assert_eq!(report, run().await);
+    }
+
+    struct Counting(std::sync::Arc<std::sync::atomic::AtomicUsize>);
+
+    impl<A: Api> Strategy<A> for Counting {
+        const NAME: &'static str = "Counting";
+        type Parameters = ();
+
+        fn init(&mut self, _exchange: &mut Exchange<A>) -> Result<Settings, AnyError> {
+            Ok(Settings::default())
+        }
+
+        fn eval(&mut self, _exchange: &mut Exchange<A>) -> Result<(), AnyError> {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn commands_pause_and_stop() {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut exchange = mock(FillBehavior::Fill).with_commands(rx);
+        exchange.current_time = Utc::now() - Duration::minutes(5);
+        tx.send(Command::Pause).unwrap();
+        tx.send(Command::Stop).unwrap();
+
+        let evals = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let report = exchange.run(Counting(evals.clone())).await.unwrap();
+
+        assert_eq!(report.steps, 1);
+        assert_eq!(evals.load(std::sync::atomic::Ordering::SeqCst), 0);
+    }
+
+    struct Panicking(std::sync::Arc<std::sync::atomic::AtomicUsize>);
+
+    impl<A: Api> Strategy<A> for Panicking {
+        const NAME: &'static str = "Panicking";
+        type Parameters = ();
+
+        fn init(&mut self, _exchange: &mut Exchange<A>) -> Result<Settings, AnyError> {
+            Ok(Settings {
+                on_error: OnError::ExitAllPositionsAndResume,
+                ..Settings::default()
+            })
+        }
+
+        fn eval(&mut self, _exchange: &mut Exchange<A>) -> Result<(), AnyError> {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            panic!("boom");
+        }
+    }
+
+    #[tokio::test]
+    async fn panicking_strategy_is_poisoned() {
+        let mut exchange = mock(FillBehavior::Fill);
+        exchange.current_time = Utc::now() - Duration::minutes(5);
+        let exchange = exchange.until(Utc::now() - Duration::minutes(1));
+
+        let evals = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let report = exchange.run(Panicking(evals.clone())).await.unwrap();
+
+        // The run resumes without evaluating the poisoned strategy again.
+        assert_eq!(evals.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert!(report.steps >= 1);
+
+        let mut exchange = mock(FillBehavior::Fill);
+        exchange.current_time = Utc::now() - Duration::minutes(5);
+        let mut strategy = Panicking(evals);
+        let err = exchange.eval_guarded(&mut strategy).unwrap_err();
+        assert_eq!(err.to_string(), "The strategy panicked: boom");
+        assert_eq!(exchange.poisoned(), Some("boom"));
+    }
+
+    #[tokio::test]
+    async fn before_execute_modifies_orders() {
+        let exchange = mock(FillBehavior::Fill);
+        let btc = Symbol::perp("BTC");
+        let eth = Symbol::perp("ETH");
+        let mut orders = buy(btc, dec!(10));
+        orders.bundle.0.insert(eth, dec!(4));
+        orders.valuation.0.insert(eth, dec!(100));
+
+        let (result, fills) = exchange
+            .order_with(vec![orders], &[], &mut |orders| {
+                orders.retain(|order| order.market == btc);
+                orders[0].size /= dec!(2);
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(result[0].bundle.0.get(&btc), Some(&dec!(5)));
+        assert_eq!(result[0].bundle.0.get(&eth), Some(&dec!(0)));
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].1.size, dec!(5));
+    }
+
+    #[tokio::test]
+    async fn before_execute_drops_reversed_orders() {
+        let exchange = mock(FillBehavior::Fill);
+        let btc = Symbol::perp("BTC");
+
+        let (result, fills) = exchange
+            .order_with(vec![buy(btc, dec!(10))], &[], &mut |orders| {
+                orders[0].side = Side::Sell;
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(result[0].bundle.0.get(&btc), Some(&dec!(0)));
+        assert!(fills.is_empty());
+    }
+
+    #[tokio::test]
+    async fn reduce_only_from_held_size() {
+        let mut exchange = mock(FillBehavior::Fill);
+        let btc = Symbol::perp("BTC");
+        let mut position = Position::default();
+        position.current.bundle.0.insert(btc, dec!(10));
+        exchange.open_positions.push(position);
+
+        // Only orders shrinking the held size without flipping it are reduce only.
+        for (size, reduce_only) in [
+            (dec!(-4), true),
+            (dec!(-10), true),
+            (dec!(-12), false),
+            (dec!(1), false),
+        ] {
+            let mut seen = None;
+            exchange
+                .order_with(vec![buy(btc, size)], &[], &mut |orders| {
+                    seen = Some(orders[0].reduce_only);
+                })
+                .await
+                .unwrap();
+            assert_eq!(seen, Some(reduce_only));
+        }
+    }
+
+    #[tokio::test]
+    async fn limit_entries() {
+        let mut exchange = mock(FillBehavior::PartialFill(dec!(0.5)));
+        exchange.wallet.deposit(dec!(10000), Asset::new("USD"));
+        let btc = Symbol::perp("BTC");
+        let start = exchange.current_time;
+        let mut position = Position::default()
+            .long_at(btc, dec!(4), dec!(95))
+            .on_unfilled(Unfilled::MarketAfter(Duration::minutes(2)));
+        position.valuate(buy(btc, dec!(0)).valuation, start);
+        exchange.open_positions.push(position);
+
+        // The remainder is ordered at the limit again, until the timeout converts it to market.
+        let mut orders = Vec::new();
+        for minute in 0..3 {
+            exchange.current_time = start + Duration::minutes(minute);
+            let valuation = buy(btc, dec!(0)).valuation;
+            for position in exchange.positions_mut() {
+                position.valuate(valuation.clone(), start + Duration::minutes(minute));
+            }
+            let fills = exchange.execute_with(&mut |_| {}).await.unwrap();
+            orders.push((fills[0].0.order_type.clone(), fills[0].0.size));
+        }
+        assert_eq!(
+            orders,
+            [
+                (OrderType::Limit(dec!(95)), dec!(4)),
+                (OrderType::Limit(dec!(95)), dec!(2)),
+                (OrderType::Market, dec!(1)),
+            ]
+        );
+        let position = exchange.positions().next().unwrap();
+        assert_eq!(position.current.bundle.0[&btc], dec!(3.5));
+        assert_eq!(position.next_size.0[&btc], dec!(4));
+
+        // Coalesced orders are only limited if every position trading in their direction is.
+        exchange.open_positions.clear();
+        for position in [
+            Position::default().long_at(btc, dec!(1), dec!(95)),
+            Position::default().long_at(btc, dec!(1), dec!(90)),
+            Position::default().short_at(btc, dec!(1), dec!(120)),
+        ] {
+            exchange.open_positions.push(position);
+        }
+        let orders: Vec<ValuedBundle> = exchange.positions().map(Position::order).collect();
+        let limits = exchange.limits(&orders, &[]);
+        assert_eq!(limits[&(btc, None, Side::Buy)], Some(dec!(90)));
+        assert_eq!(limits[&(btc, None, Side::Sell)], Some(dec!(120)));
+        exchange
+            .open_positions
+            .push(Position::default().long(btc, dec!(1)));
+        let orders: Vec<ValuedBundle> = exchange.positions().map(Position::order).collect();
+        assert_eq!(exchange.limits(&orders, &[])[&(btc, None, Side::Buy)], None);
+    }
+
+    #[tokio::test]
+    async fn split_orders_over_steps() {
+        let mut exchange = mock(FillBehavior::PartialFill(dec!(0.5)));
+        exchange.wallet.deposit(dec!(10000), Asset::new("USD"));
+        exchange.algo = ExecutionAlgo::Twap { steps: 2 };
+        let (btc, eth) = (Symbol::perp("BTC"), Symbol::perp("ETH"));
+        let start = exchange.current_time;
+        let mut twap = Position::default().long(btc, dec!(8));
+        twap.valuate(buy(btc, dec!(0)).valuation, start);
+        let mut iceberg =
+            Position::default()
+                .long(eth, dec!(3))
+                .with_algo(ExecutionAlgo::Iceberg {
+                    child_size: dec!(2),
+                });
+        iceberg.valuate(buy(eth, dec!(0)).valuation, start);
+        exchange.open_positions.extend([twap, iceberg]);
+
+        // Half of every child is filled, the rest is spread over the remaining steps
+        // and ordered at once when there are none left.
+        let mut sizes = Vec::new();
+        for minute in 0..4 {
+            let time = start + Duration::minutes(minute);
+            exchange.current_time = time;
+            let valuation = Valuation([(btc, dec!(100)), (eth, dec!(100))].into_iter().collect());
+            for position in exchange.positions_mut() {
+                position.valuate(valuation.clone(), time);
+            }
+            let fills = exchange.execute_with(&mut |_| {}).await.unwrap();
+            let ordered = |market| {
+                fills
+                    .iter()
+                    .find(|(order, _)| order.market == market)
+                    .map(|(order, _)| order.size)
+            };
+            sizes.push((ordered(btc), ordered(eth)));
+        }
+        assert_eq!(
+            sizes,
+            [
+                (Some(dec!(4)), Some(dec!(2))),
+                (Some(dec!(6)), Some(dec!(2))),
+                (Some(dec!(3)), Some(dec!(1))),
+                (Some(dec!(1.5)), Some(dec!(0.5))),
+            ]
+        );
+        let twap = exchange.positions().next().unwrap();
+        assert_eq!(twap.remaining(btc), dec!(0.75));
+        assert_eq!(twap.average_entry_price(btc), Some(dec!(100)));
+    }
+
+    #[test]
+    fn flatten_stale_markets() {
+        let mut exchange = mock(FillBehavior::Fill);
+        let start = Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap();
+        exchange.current_time = start;
+        let (btc, eth) = (Symbol::perp("BTC"), Symbol::perp("ETH"));
+        exchange.watch(btc);
+        exchange.watch(eth);
+        let mut position = Position::default().long(btc, dec!(1));
+        position.current = buy(btc, dec!(1));
+        exchange.open_positions.push(position);
+        exchange
+            .open_positions
+            .push(Position::default().long(eth, dec!(1)));
+
+        let settings = Settings {
+            health: HealthChecks {
+                max_staleness: Some(Duration::minutes(2)),
+                on_stale: OnStale::Flatten,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let candle = Candle {
+            open: dec!(100),
+            high: dec!(100),
+            low: dec!(100),
+            close: dec!(100),
+            volume: dec!(1),
+        };
+        // Only the first candle of BTC arrives.
+        let mut healthy = Vec::new();
+        for minute in 0..4 {
+            exchange.current_time = start + Duration::minutes(minute);
+            for market in [btc, eth] {
+                let key = CandleKey {
+                    market,
+                    time: exchange.current_time,
+                    interval: Duration::minutes(1),
+                };
+                let candle = (market == eth || minute == 0).then_some(candle);
+                let mut candles = CandleQueue::default();
+                candles.extend([(key, candle)]);
+                exchange.candles.insert(market, candles);
+            }
+            healthy.push(exchange.check_health(&settings));
+        }
+
+        assert_eq!(healthy, [true, true, true, false]);
+        assert_eq!(exchange.stale_markets().collect::<Vec<_>>(), [btc]);
+        assert_eq!(exchange.health(btc).unwrap().last_candle, Some(start));
+        assert_eq!(exchange.open_positions[0].next_size.0[&btc], dec!(0));
+        assert_eq!(exchange.open_positions[1].next_size.0[&eth], dec!(1));
+    }
+
+    // Opens a long position of one BTC with a leverage of 5 on the first step and holds it.
+    #[derive(Default)]
+    struct Leveraged {
+        opened: bool,
+    }
+
+    impl Strategy<Mock<crate::testing::Script>> for Leveraged {
+        const NAME: &'static str = "Leveraged";
+        type Parameters = ();
+
+        fn init(
+            &mut self,
+            exchange: &mut Exchange<Mock<crate::testing::Script>>,
+        ) -> Result<Settings, AnyError> {
+            exchange.watch(Symbol::perp("BTC"));
+            Ok(Settings::default())
+        }
+
+        fn eval(
+            &mut self,
+            exchange: &mut Exchange<Mock<crate::testing::Script>>,
+        ) -> Result<(), AnyError> {
+            if !self.opened {
+                self.opened = true;
+                exchange.open(
+                    Position::default()
+                        .long(Symbol::perp("BTC"), dec!(1))
+                        .leveraged(dec!(5)),
+                )?;
+            }
+            Ok(())
+        }
+    }
+
+    // With a margin of 20 on a position opened at 100, a maintenance margin of 5% is
+    // breached once the price falls below 84.21.
+    async fn liquidation(price: Decimal) -> crate::testing::StrategyTester<Leveraged> {
+        let btc = Symbol::perp("BTC");
+        let mut tester = crate::testing::StrategyTester::new(Leveraged::default())
+            .with_closes(btc, [dec!(100), price])
+            .with_margin(Margin::new(dec!(5), dec!(0.05)));
+        tester.run(3).await.unwrap();
+        tester
+    }
+
+    #[tokio::test]
+    async fn liquidate_below_maintenance_margin() {
+        let tester = liquidation(dec!(84)).await;
+
+        tester.assert_flat();
+        tester.assert_balance_change(dec!(-16));
+    }
+
+    #[tokio::test]
+    async fn keep_positions_above_maintenance_margin() {
+        let tester = liquidation(dec!(85)).await;
+
+        tester.assert_size(Symbol::perp("BTC"), dec!(1));
+        tester.assert_balance_change(dec!(-20));
+    }
 }
@@ -0,0 +1,139 @@
+use crate::{Markets, Order, Symbol};
+use chrono::{DateTime, Duration, Utc};
+use rust_decimal::prelude::*;
+use rust_decimal_macros::dec;
+use std::collections::{HashMap, HashSet};
+
+/// Holds back small orders so they net with the adjustments of the following steps,
+/// which saves the fees of churn that cancels out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Netting {
+    /// Hold back orders worth less than this quote value.
+    pub min_value: Decimal,
+    /// Hold back orders smaller than this multiple of the minimum size of the market.
+    pub min_size_multiple: Decimal,
+    /// Send held back orders at the latest after this long, so positions still reach their size.
+    pub window: Duration,
+}
+
+impl Default for Netting {
+    fn default() -> Self {
+        Netting {
+            min_value: dec!(10),
+            min_size_multiple: Decimal::ZERO,
+            window: Duration::hours(1),
+        }
+    }
+}
+
+impl Netting {
+    // Remove the orders to hold back. `held` keeps track of since when the order of a symbol
+    // was held back, orders closing a position are never held back.
+    pub(crate) fn hold(
+        &self,
+        orders: &mut Vec<Order>,
+        held: &mut HashMap<Symbol, DateTime<Utc>>,
+        markets: &Markets,
+        closing: &HashSet<Symbol>,
+        time: DateTime<Utc>,
+    ) {
+        // Adjustments that netted out completely are not held anymore.
+        held.retain(|symbol, _| orders.iter().any(|order| order.market == *symbol));
+
+        orders.retain(|order| {
+            let min_size = markets
+                .market(order.market)
+                .map(|market| market.min_size * self.min_size_multiple)
+                .unwrap_or_default();
+            let value = order.size * order.current_price * markets.multiplier(order.market);
+            let small = value < self.min_value || order.size < min_size;
+            if !small || closing.contains(&order.market) {
+                held.remove(&order.market);
+                return true;
+            }
+
+            let since = *held.entry(order.market).or_insert(time);
+            if time - since >= self.window {
+                held.remove(&order.market);
+                true
+            } else {
+                tracing::debug!(
+                    "Holding back the order of {} {} to net it.",
+                    order.size,
+                    order.market
+                );
+                false
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{OrderType, Side, TimeInForce};
+    use uuid::Uuid;
+
+    fn order(market: Symbol, size: Decimal) -> Order {
+        Order {
+            order_id: Uuid::new_v4(),
+            market,
+            side: Side::Buy,
+            size,
+            order_type: OrderType::Market,
+            reduce_only: false,
+            time_in_force: TimeInForce::ImmediateOrCancel,
+            post_only: false,
+            position_side: None,
+            time: Utc::now(),
+            current_price: dec!(100),
+        }
+    }
+
+    #[test]
+    fn hold_small_orders() {
+        let netting = Netting {
+            min_value: dec!(10),
+            min_size_multiple: Decimal::ZERO,
+            window: Duration::minutes(2),
+        };
+        let (btc, eth) = (Symbol::perp("BTC"), Symbol::perp("ETH"));
+        let markets = Markets::default();
+        let start = Utc::now();
+        let minute = |i: i32| start + Duration::minutes(1) * i;
+        let mut held = HashMap::new();
+
+        let mut orders = vec![order(btc, dec!(0.05)), order(eth, dec!(1))];
+        netting.hold(&mut orders, &mut held, &markets, &HashSet::new(), minute(0));
+        assert_eq!(orders.len(), 1);
+        assert_eq!(orders[0].market, eth);
+
+        // Orders closing a position are sent.
+        let mut orders = vec![order(btc, dec!(0.05))];
+        netting.hold(&mut orders, &mut held, &markets, &[btc].into(), minute(1));
+        assert_eq!(orders.len(), 1);
+        assert!(held.is_empty());
+
+        // Held back orders are sent once the window passed.
+        let mut orders = vec![order(btc, dec!(0.05))];
+        netting.hold(&mut orders, &mut held, &markets, &HashSet::new(), minute(1));
+        assert!(orders.is_empty());
+        let mut orders = vec![order(btc, dec!(0.05))];
+        netting.hold(&mut orders, &mut held, &markets, &HashSet::new(), minute(3));
+        assert_eq!(orders.len(), 1);
+
+        // The window restarts once the adjustments netted out.
+        let mut orders = vec![order(btc, dec!(0.05))];
+        netting.hold(&mut orders, &mut held, &markets, &HashSet::new(), minute(4));
+        netting.hold(
+            &mut Vec::new(),
+            &mut held,
+            &markets,
+            &HashSet::new(),
+            minute(5),
+        );
+        let mut orders = vec![order(btc, dec!(0.05))];
+        netting.hold(&mut orders, &mut held, &markets, &HashSet::new(), minute(6));
+        assert!(orders.is_empty());
+    }
+}
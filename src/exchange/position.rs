@@ -1,17 +1,83 @@
-use chrono::{DateTime, Utc};
-use rust_decimal::Decimal;
+use chrono::{DateTime, Duration, Utc};
+use rust_decimal::{prelude::Signed, Decimal};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use uuid::Uuid;
 
-use super::{Bundle, Valuation, ValuedBundle};
-use crate::{apis::Api, Exchange, Symbol};
+use super::{algo::Slice, Bundle, ExecutionAlgo, LegPolicy, Valuation, ValuedBundle};
+use crate::{apis::Api, Exchange, Markets, PositionSide, Side, Symbol};
 
-#[derive(Debug, Clone)]
+/// Identifies a position across steps, returned by `Exchange::open` to look the position up again.
+pub type PositionId = Uuid;
+
+/// Positions serialize to a stable format, so they can be persisted and inspected:
+/// symbols as names like "BTC-PERP" and decimals as strings, to keep their precision.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Position {
-    id: Uuid,
+    pub(crate) id: PositionId,
     pub(crate) current: ValuedBundle,
     pub(crate) open: Option<ValuedBundle>,
     pub(crate) close: Option<ValuedBundle>,
     pub(crate) next_size: Bundle,
+    leverage: Decimal,
+    // Profit and loss of the parts that were already reduced.
+    realized: Decimal,
+    // Whether the next size needs to be fitted to the exchange before the next execution.
+    pub(crate) refit: bool,
+    tags: HashMap<String, String>,
+    // Worst and best unrealized profit and loss while open.
+    mae: Decimal,
+    mfe: Decimal,
+    fills: Vec<Fill>,
+    // Limit prices of the symbols that did not reach their target size yet.
+    #[serde(default)]
+    limits: HashMap<Symbol, Limit>,
+    #[serde(default)]
+    unfilled: Unfilled,
+    // Overrides the execution algorithm of the strategy.
+    #[serde(default)]
+    algo: Option<ExecutionAlgo>,
+    // The split orders of the symbols that did not reach their target size yet.
+    #[serde(default)]
+    slices: HashMap<Symbol, Slice>,
+    #[serde(default)]
+    leg_policy: LegPolicy,
+    // Contract multipliers of the symbols that are not one, as prices are per unit of the underlying.
+    #[serde(default)]
+    multipliers: HashMap<Symbol, Decimal>,
+}
+
+/// What happens to the part of a limit order that was not filled within a step.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Unfilled {
+    /// Order the remainder at the same limit price again in the next steps.
+    #[default]
+    Keep,
+    /// Order the remainder at the current price in the next steps.
+    Repost,
+    /// Like keep, but order the remainder at the market once the duration has passed
+    /// since the limit was first ordered.
+    MarketAfter(#[serde(with = "seconds")] Duration),
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Limit {
+    price: Decimal,
+    // Only orders in this direction are limited, e.g. not the exit of a partially filled entry.
+    side: Side,
+    // When the limit was first ordered.
+    since: Option<DateTime<Utc>>,
+}
+
+/// A filled change of the size of a symbol in a position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fill {
+    pub time: Option<DateTime<Utc>>,
+    pub symbol: Symbol,
+    /// The filled size, negative for sells.
+    pub size: Decimal,
+    /// The price per unit of the underlying.
+    pub price: Decimal,
 }
 
 impl Default for Position {
@@ -26,12 +92,25 @@ impl Default for Position {
                 time: None,
             },
             next_size: Bundle::default(),
+            leverage: Decimal::ONE,
+            realized: Decimal::ZERO,
+            refit: false,
+            tags: HashMap::new(),
+            mae: Decimal::ZERO,
+            mfe: Decimal::ZERO,
+            fills: Vec::new(),
+            limits: HashMap::new(),
+            unfilled: Unfilled::Keep,
+            algo: None,
+            slices: HashMap::new(),
+            leg_policy: LegPolicy::Independent,
+            multipliers: HashMap::new(),
         }
     }
 }
 
 impl Position {
-    pub fn id(&self) -> Uuid {
+    pub fn id(&self) -> PositionId {
         self.id
     }
 
@@ -47,6 +126,89 @@ impl Position {
         self
     }
 
+    /// Go long a symbol with a limit order at `price`, see [Position::on_unfilled].
+    pub fn long_at(self, symbol: Symbol, qty: Decimal, price: Decimal) -> Self {
+        let mut position = self.long(symbol, qty);
+        position.set_limit(symbol, Side::Buy, price);
+        position
+    }
+
+    /// Go short a symbol with a limit order at `price`, see [Position::on_unfilled].
+    pub fn short_at(self, symbol: Symbol, qty: Decimal, price: Decimal) -> Self {
+        let mut position = self.short(symbol, qty);
+        position.set_limit(symbol, Side::Sell, price);
+        position
+    }
+
+    /// How the unfilled remainders of limit orders are handled, they are kept by default.
+    pub fn on_unfilled(mut self, unfilled: Unfilled) -> Self {
+        self.unfilled = unfilled;
+        self
+    }
+
+    /// Split the orders of this position with another algorithm than the one of the strategy.
+    pub fn with_algo(mut self, algo: ExecutionAlgo) -> Self {
+        self.algo = Some(algo);
+        self
+    }
+
+    /// How the orders of the symbols of this position are executed together,
+    /// they are independent by default.
+    pub fn with_leg_policy(mut self, policy: LegPolicy) -> Self {
+        self.leg_policy = policy;
+        self
+    }
+
+    pub fn leg_policy(&self) -> LegPolicy {
+        self.leg_policy
+    }
+
+    fn set_limit(&mut self, symbol: Symbol, side: Side, price: Decimal) {
+        assert!(price > Decimal::ZERO);
+        self.limits.insert(
+            symbol,
+            Limit {
+                price,
+                side,
+                since: None,
+            },
+        );
+    }
+
+    /// The limit price a symbol is ordered at until it reaches its target size, if any.
+    pub fn limit_price(&self, symbol: Symbol) -> Option<Decimal> {
+        self.limits.get(&symbol).map(|limit| limit.price)
+    }
+
+    /// Open this position with leverage, only the margin is taken from the wallet.
+    pub fn leveraged(mut self, leverage: Decimal) -> Self {
+        assert!(leverage >= Decimal::ONE);
+        self.leverage = leverage;
+        self
+    }
+
+    pub fn leverage(&self) -> Decimal {
+        self.leverage
+    }
+
+    /// Attach strategy specific data to this position, e.g. the entry reason or a target price.
+    pub fn with_tag(mut self, key: impl Into<String>, value: impl ToString) -> Self {
+        self.set_tag(key, value);
+        self
+    }
+
+    pub fn set_tag(&mut self, key: impl Into<String>, value: impl ToString) {
+        self.tags.insert(key.into(), value.to_string());
+    }
+
+    pub fn tag(&self, key: &str) -> Option<&str> {
+        self.tags.get(key).map(String::as_str)
+    }
+
+    pub fn tags(&self) -> &HashMap<String, String> {
+        &self.tags
+    }
+
     pub fn symbols(&self) -> impl Iterator<Item = Symbol> {
         self.open
             .as_ref()
@@ -64,14 +226,38 @@ impl Position {
             .flatten()
     }
 
+    /// The leg a symbol is held in, from the current size or the target size if not held yet.
+    pub fn leg(&self, symbol: Symbol) -> Option<PositionSide> {
+        [&self.current.bundle, &self.next_size]
+            .into_iter()
+            .filter_map(|bundle| bundle.0.get(&symbol))
+            .find(|size| !size.is_zero())
+            .map(|&size| PositionSide::of(size))
+    }
+
+    // The legs of all symbols the position holds or is about to hold.
+    pub(crate) fn legs(&self) -> HashMap<Symbol, PositionSide> {
+        self.current
+            .bundle
+            .0
+            .keys()
+            .chain(self.next_size.0.keys())
+            .filter_map(|&symbol| Some((symbol, self.leg(symbol)?)))
+            .collect()
+    }
+
     // Fits this position to the exchange constrants, for example minimum order size, minimum size increment, ...
-    // Returns the difference from the initial position caused by rounding as quote value.
+    // Returns the difference from the initial position caused by rounding as signed quote value.
     pub fn fit<A: Api>(&mut self, exchange: &Exchange<A>) -> Decimal {
         let mut rounded_size = self.next_size.clone();
         let order_bundle = &self.next_size - &self.current.bundle;
+        let closes = |symbol| self.next_size.0.get(&symbol).is_none_or(Decimal::is_zero);
 
-        // Round by size increment.
+        // Round by size increment, closing a symbol is never rounded.
         for (&symbol, size) in &order_bundle.0 {
+            if closes(symbol) {
+                continue;
+            }
             let rounded_order_bundle = exchange.market(symbol).round_size(*size);
             rounded_size.0.insert(
                 symbol,
@@ -88,7 +274,7 @@ impl Position {
         // Round by min size requirement.
         for (&symbol, size) in &order_bundle.0 {
             let min_size = exchange.market(symbol).min_size;
-            if size.abs() < min_size {
+            if size.abs() < min_size && !closes(symbol) {
                 rounded_size.0.insert(
                     symbol,
                     self.current
@@ -101,10 +287,11 @@ impl Position {
             }
         }
 
-        let rounding_diff = (&rounded_size - &self.next_size).abs();
-        let rounding_value = &rounding_diff * &self.current.valuation;
+        let rounding_diff = &rounded_size - &self.next_size;
+        let rounding_value = self.value_of(&rounding_diff, &self.current.valuation);
 
         self.next_size = rounded_size;
+        self.refit = false;
 
         rounding_value
     }
@@ -112,6 +299,11 @@ impl Position {
     pub(crate) fn valuate(&mut self, valuation: Valuation, time: DateTime<Utc>) {
         self.current.valuation = valuation;
         self.current.time = Some(time);
+        if self.open.is_some() && self.close.is_none() {
+            let unrealized = self.pnl() - self.realized;
+            self.mae = self.mae.min(unrealized);
+            self.mfe = self.mfe.max(unrealized);
+        }
     }
 
     /// Modify the position size.
@@ -124,6 +316,59 @@ impl Position {
         for size in self.next_size.0.values_mut() {
             *size = Decimal::ZERO;
         }
+        self.limits.clear();
+    }
+
+    /// Close a symbol of this position with a limit order at `price`, see [Position::on_unfilled].
+    pub fn exit_at(&mut self, symbol: Symbol, price: Decimal) {
+        let held = self
+            .current
+            .bundle
+            .0
+            .get(&symbol)
+            .cloned()
+            .unwrap_or_default();
+        *self.size(symbol) = Decimal::ZERO;
+        self.limits.remove(&symbol);
+        if !held.is_zero() {
+            let side = if held > Decimal::ZERO {
+                Side::Sell
+            } else {
+                Side::Buy
+            };
+            self.set_limit(symbol, side, price);
+        }
+    }
+
+    /// Scale the size of every symbol, e.g. by 0.5 to halve the position.
+    /// Like the other relative adjustments, this is relative to the size the position
+    /// will have after the next execution and fitted to the exchange before executing.
+    pub fn scale(&mut self, factor: Decimal) {
+        assert!(factor >= Decimal::ZERO);
+        for size in self.next_size.0.values_mut() {
+            *size *= factor;
+        }
+        self.refit = true;
+    }
+
+    /// Reduce the absolute size of a symbol by `qty`, at most down to zero.
+    pub fn reduce_by(&mut self, symbol: Symbol, qty: Decimal) {
+        assert!(qty >= Decimal::ZERO);
+        let size = self.size(symbol);
+        *size = size.signum() * (size.abs() - qty).max(Decimal::ZERO);
+        self.refit = true;
+    }
+
+    /// Add `qty` to the size of a symbol, a negative `qty` adds to a short.
+    /// Panics if this would reverse the direction of the symbol.
+    pub fn add(&mut self, symbol: Symbol, qty: Decimal) {
+        let size = self.size(symbol);
+        assert!(
+            size.is_zero() || (*size + qty).is_zero() || (*size + qty).signum() == size.signum(),
+            "cannot reverse a position, close it and open a new one"
+        );
+        *size += qty;
+        self.refit = true;
     }
 
     pub(crate) fn order(&self) -> ValuedBundle {
@@ -137,45 +382,295 @@ impl Position {
         }
     }
 
+    // The part of the order executed in this step, split by the execution algorithm.
+    pub(crate) fn child_order(&mut self, algo: ExecutionAlgo, markets: &Markets) -> ValuedBundle {
+        let algo = self.algo.unwrap_or(algo);
+        let mut order = self.order();
+        for (&symbol, size) in order.bundle.0.iter_mut() {
+            if size.is_zero() {
+                continue;
+            }
+            let target = self.next_size.0.get(&symbol).cloned().unwrap_or_default();
+            let mut slice = self.slices.remove(&symbol);
+            *size = algo.child(*size, target, &mut slice, markets.market(symbol));
+            if let Some(slice) = slice {
+                self.slices.insert(symbol, slice);
+            }
+        }
+        order
+    }
+
+    /// The size of a symbol still to be ordered until the target size is reached,
+    /// e.g. the rest of an order split over several steps.
+    pub fn remaining(&self, symbol: Symbol) -> Decimal {
+        let size = |bundle: &Bundle| bundle.0.get(&symbol).cloned().unwrap_or_default();
+        size(&self.next_size) - size(&self.current.bundle)
+    }
+
+    // Stop ordering towards the target size, keeping the current size.
+    pub(crate) fn drop_remaining(&mut self) {
+        self.next_size = self.current.bundle.clone();
+        self.limits.clear();
+        self.slices.clear();
+    }
+
+    // The limit prices of the symbols ordered in this step, applying the policy for unfilled remainders.
+    // Symbols that are not in the returned map are ordered at the market.
+    pub(crate) fn limit_prices(&mut self, time: DateTime<Utc>) -> HashMap<Symbol, Decimal> {
+        let order = &self.next_size - &self.current.bundle;
+        let mut prices = HashMap::new();
+        for (&symbol, &size) in &order.0 {
+            let limit = match self.limits.get_mut(&symbol) {
+                Some(limit) if !size.is_zero() => limit,
+                _ => continue,
+            };
+            let side = if size > Decimal::ZERO {
+                Side::Buy
+            } else {
+                Side::Sell
+            };
+            if side != limit.side {
+                continue;
+            }
+            let since = *limit.since.get_or_insert(time);
+            match self.unfilled {
+                Unfilled::Keep => {}
+                Unfilled::Repost if since < time => {
+                    if let Some(&price) = self.current.valuation.0.get(&symbol) {
+                        limit.price = price;
+                    }
+                }
+                Unfilled::Repost => {}
+                Unfilled::MarketAfter(timeout) if time - since >= timeout => continue,
+                Unfilled::MarketAfter(_) => {}
+            }
+            prices.insert(symbol, limit.price);
+        }
+        prices
+    }
+
     pub(crate) fn resize<O: Into<ValuedBundle>>(&mut self, order: O) {
         let order: ValuedBundle = order.into();
         //self.current.valuation = order.valuation.clone();
+        let previous = self.current.bundle.clone();
+        for (&symbol, &size) in &order.bundle.0 {
+            if !size.is_zero() {
+                self.fills.push(Fill {
+                    time: order.time,
+                    symbol,
+                    size,
+                    price: order.valuation.0.get(&symbol).cloned().unwrap_or_default(),
+                });
+            }
+        }
         self.current.bundle = &self.current.bundle + &order.bundle;
-        self.next_size = self.current.bundle.clone();
-        match (&self.open, &self.close) {
+        let target = std::mem::replace(&mut self.next_size, self.current.bundle.clone());
+        // Limit and split orders keep working on the remainder until the target size is reached.
+        let current = &self.current.bundle;
+        let size =
+            |bundle: &Bundle, symbol: &Symbol| bundle.0.get(symbol).cloned().unwrap_or_default();
+        self.limits
+            .retain(|symbol, _| size(&target, symbol) != size(current, symbol));
+        self.slices
+            .retain(|symbol, _| size(&target, symbol) != size(current, symbol));
+        for symbol in self.limits.keys().chain(self.slices.keys()) {
+            if let Some(&size) = target.0.get(symbol) {
+                self.next_size.0.insert(*symbol, size);
+            }
+        }
+        let flat = self.current.bundle.0.values().all(|size| size.is_zero());
+        match (&mut self.open, &self.close) {
             (None, None) => {
                 self.open = Some(order);
             }
             (None, Some(_)) => panic!("cannot close before open"),
-            (Some(_), None) => {
+            (Some(_), None) if flat => {
                 self.close = Some(order);
                 assert!(self.closed(), "position not fully closed");
             }
+            (Some(open), None) => {
+                // The open bundle holds the remaining size at the average entry price.
+                for (&symbol, &qty) in &order.bundle.0 {
+                    if qty.is_zero() {
+                        continue;
+                    }
+                    let size = previous.0.get(&symbol).cloned().unwrap_or_default();
+                    let price = order.valuation.0.get(&symbol).cloned().unwrap_or_default();
+                    let multiplier = self
+                        .multipliers
+                        .get(&symbol)
+                        .cloned()
+                        .unwrap_or(Decimal::ONE);
+                    let entry = open.valuation.0.entry(symbol).or_default();
+                    if size.is_zero() || size.signum() == qty.signum() {
+                        *entry = (size * *entry + qty * price) / (size + qty);
+                    } else {
+                        assert!(qty.abs() <= size.abs(), "cannot reverse a position");
+                        self.realized -= qty * (price - *entry) * multiplier;
+                    }
+                    *open.bundle.0.entry(symbol).or_default() += qty;
+                }
+            }
             (Some(_), Some(_)) => panic!("cannot close twice"),
         }
     }
 
+    /// Profit and loss locked in by the parts of the position that were reduced or closed.
+    pub fn realized_pnl(&self) -> Decimal {
+        if self.close.is_some() {
+            self.pnl()
+        } else {
+            self.realized
+        }
+    }
+
+    /// Profit and loss of the size still held, at the current valuation.
+    pub fn unrealized_pnl(&self) -> Decimal {
+        self.pnl() - self.realized_pnl()
+    }
+
     // Total pnl of this position.
     pub fn pnl(&self) -> Decimal {
-        if let Some(close) = &self.close {
-            -(self.open.as_ref().expect("open before close").value() + close.value())
+        self.realized
+            + if let Some(close) = &self.close {
+                -(self.bundle_value(self.open.as_ref().expect("open before close"))
+                    + self.bundle_value(close))
+            } else {
+                -(self
+                    .open
+                    .as_ref()
+                    .map(|open| self.bundle_value(open))
+                    .unwrap_or_default()
+                    - self.bundle_value(&self.current))
+            }
+    }
+
+    /// The time of the first fill, None while nothing was filled.
+    pub fn opened_at(&self) -> Option<DateTime<Utc>> {
+        self.fills.first().and_then(|fill| fill.time)
+    }
+
+    /// The fills of this position, in the order they were executed.
+    pub fn fills(&self) -> &[Fill] {
+        &self.fills
+    }
+
+    /// The size weighted average price of the fills that increased the size of the symbol,
+    /// None if there are none. Of a fill flipping the side, only the size beyond zero counts.
+    pub fn average_entry_price(&self, symbol: Symbol) -> Option<Decimal> {
+        self.average_price(symbol, true)
+    }
+
+    /// The size weighted average price of the fills that reduced or closed the size of the symbol,
+    /// None if there are none. Of a fill flipping the side, only the size down to zero counts.
+    pub fn average_exit_price(&self, symbol: Symbol) -> Option<Decimal> {
+        self.average_price(symbol, false)
+    }
+
+    /// The value of the fills that increased the size of the position, over all symbols.
+    pub fn entry_value(&self) -> Decimal {
+        let mut sizes: HashMap<Symbol, Decimal> = HashMap::new();
+        let mut value = Decimal::ZERO;
+        for fill in &self.fills {
+            let size = sizes.entry(fill.symbol).or_default();
+            let (_, entry) = Self::split_fill(*size, fill.size);
+            value += entry * fill.price * self.multiplier(fill.symbol);
+            *size += fill.size;
+        }
+        value
+    }
+
+    fn average_price(&self, symbol: Symbol, entries: bool) -> Option<Decimal> {
+        let mut size = Decimal::ZERO;
+        let (mut quantity, mut cost) = (Decimal::ZERO, Decimal::ZERO);
+        for fill in self.fills.iter().filter(|fill| fill.symbol == symbol) {
+            let (exit, entry) = Self::split_fill(size, fill.size);
+            let part = if entries { entry } else { exit };
+            quantity += part;
+            cost += part * fill.price;
+            size += fill.size;
+        }
+
+        if quantity.is_zero() {
+            None
         } else {
-            -(self
-                .open
-                .as_ref()
-                .map(|open| open.value())
-                .unwrap_or_default()
-                - self.current.value())
+            Some(cost / quantity)
+        }
+    }
+
+    // The absolute sizes of a fill that reduce the held size and that increase it.
+    // A fill that flips the sign of the size first closes it and then opens the rest.
+    fn split_fill(size: Decimal, fill: Decimal) -> (Decimal, Decimal) {
+        if size.is_zero() || size.signum() == fill.signum() {
+            (Decimal::ZERO, fill.abs())
+        } else {
+            let exit = fill.abs().min(size.abs());
+            (exit, fill.abs() - exit)
+        }
+    }
+
+    /// Maximum adverse excursion, the worst unrealized profit and loss while the position was open.
+    /// Zero or negative, sampled at every step.
+    pub fn mae(&self) -> Decimal {
+        self.mae
+    }
+
+    /// Maximum favorable excursion, the best unrealized profit and loss while the position was open.
+    /// Zero or positive, sampled at every step.
+    pub fn mfe(&self) -> Decimal {
+        self.mfe
+    }
+
+    // The contract multiplier of a symbol, the units of the underlying one contract is worth.
+    pub(crate) fn multiplier(&self, symbol: Symbol) -> Decimal {
+        self.multipliers
+            .get(&symbol)
+            .cloned()
+            .unwrap_or(Decimal::ONE)
+    }
+
+    // Remember the contract multipliers of the symbols held or ordered, to value them.
+    pub(crate) fn set_multipliers(&mut self, markets: &Markets) {
+        let symbols = self.current.bundle.0.keys().chain(self.next_size.0.keys());
+        for &symbol in symbols {
+            let multiplier = markets.multiplier(symbol);
+            if multiplier != Decimal::ONE {
+                self.multipliers.insert(symbol, multiplier);
+            }
         }
     }
 
+    // The value of sizes in contracts at prices per unit of the underlying.
+    fn value_of(&self, bundle: &Bundle, valuation: &Valuation) -> Decimal {
+        let mut value = Decimal::ZERO;
+        for (&symbol, price) in &valuation.0 {
+            let size = bundle.0.get(&symbol).cloned().unwrap_or_default();
+            value += size * price * self.multiplier(symbol);
+        }
+        value
+    }
+
+    fn bundle_value(&self, bundle: &ValuedBundle) -> Decimal {
+        self.value_of(&bundle.bundle, &bundle.valuation)
+    }
+
+    /// The gross value of the size currently held, at the current valuation.
+    pub fn notional(&self) -> Decimal {
+        self.value_of(&self.current.bundle.abs(), &self.current.valuation)
+    }
+
     // Total value of this position.
     pub fn value(&self) -> Decimal {
+        self.margin() + self.pnl()
+    }
+
+    // The value put up to open this position.
+    pub fn margin(&self) -> Decimal {
         self.open
             .as_ref()
-            .map(|open| open.abs_value())
+            .map(|open| self.value_of(&open.bundle.abs(), &open.valuation))
             .unwrap_or_default()
-            + self.pnl()
+            / self.leverage
     }
 
     // Profit and loss relative to the open value.
@@ -184,7 +679,7 @@ impl Position {
         let value = self
             .open
             .as_ref()
-            .map(|open| open.abs_value())
+            .map(|open| self.value_of(&open.bundle.abs(), &open.valuation))
             .unwrap_or_default();
         if value == Decimal::ZERO {
             Decimal::ZERO
@@ -202,7 +697,24 @@ impl Position {
     }
 
     pub(crate) fn removable(&self) -> bool {
-        self.next_size.0.iter().all(|(_s, qty)| *qty == Decimal::ZERO)
+        self.next_size
+            .0
+            .iter()
+            .all(|(_s, qty)| *qty == Decimal::ZERO)
+    }
+}
+
+// Durations as whole seconds, for the policy of unfilled limit orders.
+mod seconds {
+    use chrono::Duration;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i64(duration.num_seconds())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        i64::deserialize(deserializer).map(Duration::seconds)
     }
 }
 
@@ -443,7 +955,6 @@ mod tests {
         assert_eq!(position.value(), dec!(0));
     }
 
-    
     #[test]
     fn long_close() {
         let mut position = Position::default();
@@ -504,7 +1015,32 @@ mod tests {
         assert_eq!(position.value(), dec!(15000));
     }
 
-    /* 
+    #[test]
+    fn leveraged_long_value() {
+        let mut position = Position::default().leveraged(dec!(5));
+
+        position
+            .current
+            .valuation
+            .0
+            .insert(Symbol::perp("BTC"), dec!(10000));
+
+        *position.size(Symbol::perp("BTC")) = dec!(1);
+        let order = position.order();
+        position.resize(order);
+        assert_eq!(position.margin(), dec!(2000));
+        assert_eq!(position.value(), dec!(2000));
+
+        position
+            .current
+            .valuation
+            .0
+            .insert(Symbol::perp("BTC"), dec!(9000));
+        assert_eq!(position.pnl(), dec!(-1000));
+        assert_eq!(position.value(), dec!(1000));
+    }
+
+    /*
     #[test]
     fn close_value_to_zero() {
         for _ in 0..100 {
@@ -551,4 +1087,185 @@ mod tests {
         }
     }
     */
+
+    #[test]
+    fn position_scale_in_and_out() {
+        let btc = Symbol::perp("BTC");
+        let mut position = Position::default().long(btc, dec!(1));
+        let fill = |position: &mut Position, price| {
+            position.current.valuation.0.insert(btc, price);
+            let order = position.order();
+            position.resize(order);
+        };
+
+        fill(&mut position, dec!(100));
+        position.add(btc, dec!(1));
+        assert_eq!(position.next_size.0[&btc], dec!(2));
+        fill(&mut position, dec!(110));
+
+        assert_eq!(position.open.as_ref().unwrap().valuation.0[&btc], dec!(105));
+        assert_eq!(position.pnl(), dec!(10));
+        assert_eq!(position.value(), dec!(220));
+
+        assert_eq!(position.average_exit_price(btc), None);
+        position.reduce_by(btc, dec!(1));
+        fill(&mut position, dec!(120));
+
+        assert_eq!(position.pnl(), dec!(30));
+        assert_eq!(position.realized_pnl(), dec!(15));
+        assert_eq!(position.unrealized_pnl(), dec!(15));
+        assert_eq!(position.value(), dec!(135));
+        assert!(!position.closed());
+
+        position.scale(dec!(0));
+        fill(&mut position, dec!(120));
+
+        assert!(position.closed());
+        assert_eq!(position.pnl(), dec!(30));
+        assert_eq!(position.realized_pnl(), dec!(30));
+        assert_eq!(position.unrealized_pnl(), dec!(0));
+        assert_eq!(position.value(), dec!(135));
+        assert_eq!(position.fills().len(), 4);
+        assert_eq!(position.average_entry_price(btc), Some(dec!(105)));
+        assert_eq!(position.average_exit_price(btc), Some(dec!(120)));
+    }
+
+    #[test]
+    fn average_prices_of_flipping_fill() {
+        let btc = Symbol::perp("BTC");
+        let mut position = Position::default();
+        let fill = |size, price| Fill {
+            time: None,
+            symbol: btc,
+            size,
+            price,
+        };
+        // The sell closes the long of 1 at 110 and opens a short of 2 at 110.
+        position.fills = vec![
+            fill(dec!(1), dec!(100)),
+            fill(dec!(-3), dec!(110)),
+            fill(dec!(2), dec!(90)),
+        ];
+
+        assert_eq!(position.average_entry_price(btc), Some(dec!(320) / dec!(3)));
+        assert_eq!(position.average_exit_price(btc), Some(dec!(290) / dec!(3)));
+        assert_eq!(position.entry_value(), dec!(320));
+    }
+
+    #[test]
+    fn position_tags() {
+        let btc = Symbol::perp("BTC");
+        let mut position = Position::default()
+            .long(btc, dec!(1))
+            .with_tag("reason", "breakout")
+            .with_tag("target", dec!(120));
+
+        let mut valuation = Valuation::default();
+        valuation.0.insert(btc, dec!(100));
+        position.valuate(valuation, Utc::now());
+        position.set_tag("reason", "retest");
+
+        assert_eq!(position.tag("reason"), Some("retest"));
+        assert_eq!(position.tag("target"), Some("120"));
+        assert_eq!(position.tag("stop"), None);
+    }
+
+    #[test]
+    fn position_excursions() {
+        let btc = Symbol::perp("BTC");
+        let mut position = Position::default().long(btc, dec!(2));
+        let valuate = |position: &mut Position, price| {
+            let mut valuation = Valuation::default();
+            valuation.0.insert(btc, price);
+            position.valuate(valuation, Utc::now());
+        };
+
+        valuate(&mut position, dec!(100));
+        let order = position.order();
+        position.resize(order);
+        for price in [dec!(95), dec!(110), dec!(104)] {
+            valuate(&mut position, price);
+        }
+        position.close();
+        let order = position.order();
+        position.resize(order);
+        valuate(&mut position, dec!(80));
+
+        assert_eq!(position.mae(), dec!(-10));
+        assert_eq!(position.mfe(), dec!(20));
+        assert_eq!(position.pnl(), dec!(8));
+    }
+
+    #[test]
+    fn position_serde_format() {
+        let btc = Symbol::perp("BTC");
+        let mut position = Position::default().long(btc, dec!(1.5));
+        let mut valuation = Valuation::default();
+        valuation.0.insert(btc, dec!(100.25));
+        position.valuate(valuation, Utc::now());
+        let order = position.order();
+        position.resize(order);
+
+        let json = serde_json::to_value(&position).unwrap();
+        assert_eq!(
+            json["current"]["bundle"],
+            serde_json::json!({"BTC-PERP": "1.5"})
+        );
+        assert_eq!(
+            json["current"]["valuation"],
+            serde_json::json!({"BTC-PERP": "100.25"})
+        );
+        assert_eq!(json["fills"][0]["symbol"], "BTC-PERP");
+
+        let restored: Position = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&restored).unwrap(), json);
+        assert_eq!(restored.pnl(), position.pnl());
+
+        // Symbols serialized by earlier versions are still read.
+        let legacy: Symbol = serde_json::from_str(r#"{"Perp": "BTC"}"#).unwrap();
+        assert_eq!(legacy, btc);
+        assert!(serde_json::from_str::<Symbol>(r#""BTC""#).is_err());
+    }
+
+    #[test]
+    fn position_limit_exit() {
+        let btc = Symbol::perp("BTC");
+        let start = Utc::now();
+        let mut position = Position::default()
+            .long(btc, dec!(2))
+            .on_unfilled(Unfilled::Repost);
+        let valuate = |position: &mut Position, price, minutes| {
+            let mut valuation = Valuation::default();
+            valuation.0.insert(btc, price);
+            position.valuate(valuation, start + Duration::minutes(minutes));
+        };
+        valuate(&mut position, dec!(100), 0);
+        assert!(position.limit_prices(start).is_empty());
+        let order = position.order();
+        position.resize(order);
+
+        position.exit_at(btc, dec!(110));
+        assert_eq!(
+            position.limit_prices(start),
+            HashMap::from([(btc, dec!(110))])
+        );
+
+        // The unfilled remainder stays at the target size and is reposted at the current price.
+        let mut order = position.order();
+        order.bundle.0.insert(btc, dec!(-1));
+        position.resize(order);
+        assert_eq!(position.next_size.0[&btc], dec!(0));
+        valuate(&mut position, dec!(105), 1);
+        let time = start + Duration::minutes(1);
+        assert_eq!(
+            position.limit_prices(time),
+            HashMap::from([(btc, dec!(105))])
+        );
+
+        let order = position.order();
+        position.resize(order);
+        assert!(position.closed());
+        assert_eq!(position.limit_price(btc), None);
+        assert_eq!(position.average_exit_price(btc), Some(dec!(102.5)));
+    }
 }
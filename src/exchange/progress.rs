@@ -0,0 +1,229 @@
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::UnboundedSender;
+
+// Reports are sent at most this often, except the last one.
+const REPORT_EVERY: Duration = Duration::from_millis(200);
+
+/// How far a backtest got, reported while it catches up to the end time or to now.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    pub start_time: DateTime<Utc>,
+    pub current_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    /// The fraction of the time range from the start to the end time that is done.
+    pub fraction: f64,
+    /// Number of steps run.
+    pub steps: u64,
+    /// Number of candles processed, summed over all markets.
+    pub candles: u64,
+    /// Candles processed per second of wall time.
+    pub candles_per_second: f64,
+    /// Wall time since the start.
+    pub elapsed: Duration,
+    /// Estimated wall time until the end, None before the first step.
+    pub eta: Option<Duration>,
+    /// Whether this is the last report, sent once the end is reached.
+    pub done: bool,
+}
+
+/// Receives the progress of a backtest, e.g. to draw a progress bar.
+/// Implemented for closures and for channels.
+pub trait ProgressReporter: Send + Sync {
+    fn report(&self, progress: &Progress);
+}
+
+impl<F> ProgressReporter for F
+where
+    F: Fn(&Progress) + Send + Sync,
+{
+    fn report(&self, progress: &Progress) {
+        self(progress)
+    }
+}
+
+impl ProgressReporter for UnboundedSender<Progress> {
+    fn report(&self, progress: &Progress) {
+        // Sending only fails once the receiver is dropped.
+        self.send(*progress).ok();
+    }
+}
+
+/// Draws the progress in a terminal progress bar.
+#[cfg(feature = "progress-bar")]
+pub struct ProgressBar {
+    bar: indicatif::ProgressBar,
+}
+
+#[cfg(feature = "progress-bar")]
+impl Default for ProgressBar {
+    fn default() -> Self {
+        ProgressBar::new()
+    }
+}
+
+#[cfg(feature = "progress-bar")]
+impl ProgressBar {
+    // The resolution of the bar.
+    const LENGTH: u64 = 1000;
+
+    pub fn new() -> Self {
+        let bar = indicatif::ProgressBar::new(Self::LENGTH);
+        bar.set_style(
+            indicatif::ProgressStyle::with_template(
+                "{bar:40} {percent:>3}% {msg} [{elapsed_precise}]",
+            )
+            .expect("Valid template"),
+        );
+        ProgressBar { bar }
+    }
+}
+
+#[cfg(feature = "progress-bar")]
+impl ProgressReporter for ProgressBar {
+    fn report(&self, progress: &Progress) {
+        self.bar
+            .set_position((progress.fraction * Self::LENGTH as f64) as u64);
+        let eta = progress
+            .eta
+            .map(|eta| format!(", ETA {}s", eta.as_secs()))
+            .unwrap_or_default();
+        self.bar.set_message(format!(
+            "{} ({:.0} candles/s{})",
+            progress.current_time.format("%Y-%m-%d %H:%M"),
+            progress.candles_per_second,
+            eta
+        ));
+        if progress.done {
+            self.bar.finish();
+        }
+    }
+}
+
+// Tracks the progress of a run and reports it periodically.
+pub(crate) struct ProgressTracker {
+    reporter: Arc<dyn ProgressReporter>,
+    started: Instant,
+    start_time: Option<DateTime<Utc>>,
+    // The end time of runs without one, i.e. the time they catch up to live trading.
+    now: DateTime<Utc>,
+    steps: u64,
+    candles: u64,
+    reported: Option<Instant>,
+    done: bool,
+}
+
+impl ProgressTracker {
+    pub fn new(reporter: Arc<dyn ProgressReporter>) -> Self {
+        ProgressTracker {
+            reporter,
+            started: Instant::now(),
+            start_time: None,
+            now: Utc::now(),
+            steps: 0,
+            candles: 0,
+            reported: None,
+            done: false,
+        }
+    }
+
+    // Count a step with the number of candles it processed, reporting if due.
+    pub fn step(
+        &mut self,
+        current_time: DateTime<Utc>,
+        end_time: Option<DateTime<Utc>>,
+        candles: u64,
+    ) {
+        self.start_time.get_or_insert(current_time);
+        self.steps += 1;
+        self.candles += candles;
+        if !matches!(self.reported, Some(reported) if reported.elapsed() < REPORT_EVERY) {
+            self.report(current_time, end_time, false);
+        }
+    }
+
+    // Send the last report, once.
+    pub fn finish(&mut self, current_time: DateTime<Utc>, end_time: Option<DateTime<Utc>>) {
+        if !self.done {
+            self.report(current_time, end_time, true);
+        }
+    }
+
+    fn report(&mut self, current_time: DateTime<Utc>, end_time: Option<DateTime<Utc>>, done: bool) {
+        let start_time = *self.start_time.get_or_insert(current_time);
+        let end_time = end_time.unwrap_or(self.now);
+        let elapsed = self.started.elapsed();
+        let fraction = if done {
+            1.0
+        } else {
+            let total = (end_time - start_time).num_seconds();
+            let passed = (current_time - start_time).num_seconds();
+            if total > 0 {
+                (passed as f64 / total as f64).clamp(0.0, 1.0)
+            } else {
+                0.0
+            }
+        };
+        let eta = if done {
+            Some(Duration::ZERO)
+        } else if fraction > 0.0 {
+            Some(elapsed.mul_f64((1.0 - fraction) / fraction))
+        } else {
+            None
+        };
+        let seconds = elapsed.as_secs_f64();
+        self.reporter.report(&Progress {
+            start_time,
+            current_time,
+            end_time,
+            fraction,
+            steps: self.steps,
+            candles: self.candles,
+            candles_per_second: if seconds > 0.0 {
+                self.candles as f64 / seconds
+            } else {
+                0.0
+            },
+            elapsed,
+            eta,
+            done,
+        });
+        self.reported = Some(Instant::now());
+        self.done = done;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::sync::Mutex;
+
+    #[test]
+    fn reports_fraction_and_finishes_once() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let collect = reports.clone();
+        let mut tracker = ProgressTracker::new(Arc::new(move |progress: &Progress| {
+            collect.lock().unwrap().push(*progress)
+        }));
+        let start = Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap();
+        let end = Utc.with_ymd_and_hms(2022, 1, 5, 0, 0, 0).unwrap();
+
+        tracker.step(start, Some(end), 2);
+        // Not due yet.
+        tracker.step(start + chrono::Duration::days(1), Some(end), 2);
+        tracker.finish(start + chrono::Duration::days(1), Some(end));
+        tracker.finish(end, Some(end));
+
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].fraction, 0.0);
+        assert_eq!(reports[0].eta, None);
+        assert!(!reports[0].done);
+        assert_eq!(reports[1].fraction, 1.0);
+        assert_eq!(reports[1].steps, 2);
+        assert_eq!(reports[1].candles, 4);
+        assert!(reports[1].done);
+    }
+}
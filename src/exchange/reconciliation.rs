@@ -0,0 +1,109 @@
+use crate::{Asset, Wallet};
+use chrono::{DateTime, Duration, Utc};
+use rust_decimal::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// How the wallet booked locally is compared with the wallet of the venue when trading live,
+/// to find what the bookkeeping missed, e.g. funding payments, fee rebates or manual trades.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reconciliation {
+    /// How often the wallets are compared.
+    pub every: Duration,
+    /// Differences up to this fraction of the balance are tolerated, e.g. 0.001 for 0.1%.
+    pub tolerance: Decimal,
+    /// Whether the wallet of the venue replaces the local wallet in every step, as without
+    /// reconciliation. Otherwise the local wallet is kept and only compared with the venue.
+    pub correct: bool,
+}
+
+impl Default for Reconciliation {
+    fn default() -> Self {
+        Reconciliation {
+            every: Duration::hours(1),
+            tolerance: Decimal::new(1, 3),
+            correct: true,
+        }
+    }
+}
+
+impl Reconciliation {
+    // The balances that differ by more than the tolerance, ordered by asset.
+    pub(crate) fn compare(
+        &self,
+        time: DateTime<Utc>,
+        local: &Wallet,
+        venue: &Wallet,
+    ) -> Vec<WalletDiscrepancy> {
+        let mut assets: Vec<Asset> = local
+            .total
+            .keys()
+            .chain(venue.total.keys())
+            .copied()
+            .collect();
+        assets.sort();
+        assets.dedup();
+        assets
+            .into_iter()
+            .map(|asset| WalletDiscrepancy {
+                time,
+                asset,
+                local: local.total(asset),
+                venue: venue.total(asset),
+                corrected: self.correct,
+            })
+            .filter(|discrepancy| {
+                let balance = discrepancy.local.abs().max(discrepancy.venue.abs());
+                discrepancy.difference().abs() > balance * self.tolerance
+            })
+            .collect()
+    }
+}
+
+/// The total balance of an asset that differs between the local wallet and the wallet of the venue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WalletDiscrepancy {
+    pub time: DateTime<Utc>,
+    pub asset: Asset,
+    /// The balance booked locally.
+    pub local: Decimal,
+    /// The balance at the venue.
+    pub venue: Decimal,
+    /// Whether the local balance was corrected to the balance at the venue.
+    pub corrected: bool,
+}
+
+impl WalletDiscrepancy {
+    /// How much more the venue holds than was booked locally.
+    pub fn difference(&self) -> Decimal {
+        self.venue - self.local
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn compare_within_tolerance() {
+        let (usd, btc, eth) = (Asset::new("USD"), Asset::new("BTC"), Asset::new("ETH"));
+        let mut local = Wallet::new();
+        local.deposit(dec!(1000), usd);
+        local.deposit(dec!(1), btc);
+        let mut venue = Wallet::new();
+        venue.deposit(dec!(999.5), usd);
+        venue.deposit(dec!(0.9), btc);
+        venue.deposit(dec!(2), eth);
+
+        let time = Utc::now();
+        let discrepancies = Reconciliation::default().compare(time, &local, &venue);
+        // USD is off by 0.05%, within the tolerance.
+        assert_eq!(
+            discrepancies
+                .iter()
+                .map(|discrepancy| (discrepancy.asset, discrepancy.difference()))
+                .collect::<Vec<_>>(),
+            vec![(btc, dec!(-0.1)), (eth, dec!(2))]
+        );
+    }
+}
@@ -0,0 +1,80 @@
+use crate::{ClosedPosition, Order, OrderInfo, Position, Report, Wallet};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use tokio::sync::broadcast;
+
+// Events buffered per subscriber, slower subscribers miss the oldest events.
+const CAPACITY: usize = 1024;
+
+/// Observes a running strategy in-process, e.g. from a custom TUI or GUI,
+/// without going through the Monitor database.
+/// Clones share the same channel, so subscribing to any clone receives the events of the run.
+#[derive(Debug, Clone)]
+pub struct Session {
+    sender: broadcast::Sender<SessionEvent>,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Session::new()
+    }
+}
+
+impl Session {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CAPACITY);
+        Session { sender }
+    }
+
+    /// Receive the events from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<SessionEvent> {
+        self.sender.subscribe()
+    }
+
+    pub(crate) fn send(&self, event: SessionEvent) {
+        // Sending only fails without subscribers.
+        self.sender.send(event).ok();
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum SessionEvent {
+    /// The state after a step.
+    Snapshot(Snapshot),
+    /// A submitted order with its fill.
+    Fill(Order, OrderInfo),
+    /// A position was closed.
+    Closed(ClosedPosition),
+    /// The run ended with this report.
+    Finished(Report),
+}
+
+/// The state of the exchange after a step.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub time: DateTime<Utc>,
+    pub total: Decimal,
+    pub wallet: Wallet,
+    pub positions: Vec<Position>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subscribe_to_clone() {
+        let session = Session::new();
+        session.send(SessionEvent::Finished(Report::new(
+            Utc::now(),
+            Decimal::ZERO,
+        )));
+
+        let mut receiver = session.clone().subscribe();
+        let report = Report::new(Utc::now(), Decimal::ONE);
+        session.send(SessionEvent::Finished(report.clone()));
+
+        assert!(matches!(receiver.try_recv(), Ok(SessionEvent::Finished(r)) if r == report));
+        assert!(receiver.try_recv().is_err());
+    }
+}
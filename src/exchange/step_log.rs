@@ -0,0 +1,178 @@
+use super::{Bundle, Exchange, Position};
+use crate::{apis::Api, Candle, MarketInfo, Order, OrderInfo, Symbol, Wallet};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+use uuid::Uuid;
+
+/// Everything a step of the exchange used and produced, enough to execute the step again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StepRecord {
+    pub time: DateTime<Utc>,
+    /// The newest candle of every watched market.
+    pub candles: Vec<(Symbol, Option<Candle>)>,
+    pub wallet: Wallet,
+    pub markets: Vec<MarketInfo>,
+    /// The positions with their sizes before and their target sizes after the step.
+    pub positions: Vec<PositionRecord>,
+    /// The orders submitted to the API, after the strategy modified them.
+    pub orders: Vec<Order>,
+    pub fills: Vec<OrderInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PositionRecord {
+    pub id: Uuid,
+    pub current: Vec<(Symbol, Decimal)>,
+    pub target: Vec<(Symbol, Decimal)>,
+}
+
+fn sizes(bundle: &Bundle) -> Vec<(Symbol, Decimal)> {
+    let mut sizes: Vec<_> = bundle
+        .0
+        .iter()
+        .map(|(&symbol, &size)| (symbol, size))
+        .collect();
+    sizes.sort_by_key(|(symbol, _)| symbol.to_string());
+    sizes
+}
+
+impl StepRecord {
+    // Record the inputs of the step, once the wallet, markets and candles are updated.
+    pub(crate) fn inputs<A: Api>(exchange: &Exchange<A>) -> Self {
+        let mut candles: Vec<_> = exchange
+            .candles
+            .keys()
+            .map(|&symbol| {
+                let candle = exchange.current_candle(symbol);
+                (symbol, candle.and_then(|(_, candle)| *candle))
+            })
+            .collect();
+        candles.sort_by_key(|(symbol, _)| symbol.to_string());
+        let mut markets: Vec<_> = exchange.markets().cloned().collect();
+        markets.sort_by_key(|market| market.symbol.to_string());
+
+        StepRecord {
+            time: exchange.current_time,
+            candles,
+            wallet: exchange.wallet.clone(),
+            markets,
+            positions: Vec::new(),
+            orders: Vec::new(),
+            fills: Vec::new(),
+        }
+    }
+
+    // Record the positions targeted by the strategy.
+    pub(crate) fn targets<'a>(&mut self, positions: impl Iterator<Item = &'a Position>) {
+        self.positions = positions
+            .map(|position| PositionRecord {
+                id: position.id(),
+                current: sizes(&position.current.bundle),
+                target: sizes(&position.next_size),
+            })
+            .collect();
+    }
+}
+
+/// An append-only log of steps with one JSON record per line,
+/// flushed after every step so the log is complete up to the last step even after a crash.
+pub struct StepLog {
+    writer: BufWriter<File>,
+}
+
+impl StepLog {
+    /// Open the log at the path, appending to it if it exists.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(StepLog {
+            writer: BufWriter::new(file),
+        })
+    }
+
+    pub fn append(&mut self, record: &StepRecord) -> io::Result<()> {
+        serde_json::to_writer(&mut self.writer, record)?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()
+    }
+
+    /// Read all records of a log, in the order the steps were run.
+    pub fn read<P: AsRef<Path>>(path: P) -> io::Result<Vec<StepRecord>> {
+        BufReader::new(File::open(path)?)
+            .lines()
+            .filter(|line| !matches!(line, Ok(line) if line.is_empty()))
+            .map(|line| Ok(serde_json::from_str(&line?)?))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Asset, OrderStatus, OrderType, Side, TimeInForce};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn append_and_read() {
+        let path = std::env::temp_dir().join(format!("bazaar-step-log-{}.jsonl", Uuid::new_v4()));
+        let btc = Symbol::perp("BTC");
+        let time = Utc::now();
+        let mut wallet = Wallet::new();
+        wallet.deposit(dec!(1000), Asset::new("USD"));
+        let order = Order {
+            order_id: Uuid::new_v4(),
+            market: btc,
+            side: Side::Buy,
+            size: dec!(0.1),
+            order_type: OrderType::Limit(dec!(100)),
+            reduce_only: false,
+            time_in_force: TimeInForce::ImmediateOrCancel,
+            post_only: false,
+            position_side: None,
+            time,
+            current_price: dec!(100),
+        };
+        let record = StepRecord {
+            time,
+            candles: vec![(btc, None)],
+            wallet,
+            markets: Vec::new(),
+            positions: vec![PositionRecord {
+                id: Uuid::new_v4(),
+                current: Vec::new(),
+                target: vec![(btc, dec!(0.1))],
+            }],
+            orders: vec![order.clone()],
+            fills: vec![OrderInfo {
+                order_id: order.order_id,
+                market: btc,
+                size: dec!(0.1),
+                price: dec!(100.05),
+                time,
+                side: Side::Buy,
+                status: OrderStatus::Filled,
+            }],
+        };
+
+        let mut log = StepLog::create(&path).unwrap();
+        log.append(&record).unwrap();
+        log.append(&record).unwrap();
+        let records = StepLog::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].time, time);
+        assert_eq!(records[1].positions, record.positions);
+        assert_eq!(records[1].orders[0].order_type, OrderType::Limit(dec!(100)));
+        assert_eq!(records[1].fills[0].price, dec!(100.05));
+        assert_eq!(
+            records[1].wallet.assets().collect::<Vec<_>>(),
+            vec![(&Asset::new("USD"), &dec!(1000))]
+        );
+    }
+}
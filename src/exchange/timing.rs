@@ -0,0 +1,34 @@
+use std::time::Duration;
+
+/// Timing of the last step and how often steps took longer than the interval.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StepTiming {
+    /// Time spent fetching candles, markets and the wallet in the last step.
+    pub update: Duration,
+    /// Time spent evaluating the strategy in the last step.
+    pub eval: Duration,
+    /// Time spent placing orders in the last step.
+    pub execute: Duration,
+    /// Number of steps run.
+    pub steps: u64,
+    /// Number of real time steps that took longer than the interval.
+    pub overruns: u64,
+    /// Number of steps that were skipped or fast-forwarded after overruns.
+    pub skipped: u64,
+}
+
+impl StepTiming {
+    /// Total time spent in the last step.
+    pub fn total(&self) -> Duration {
+        self.update + self.eval + self.execute
+    }
+
+    /// Fraction of steps that overran the interval.
+    pub fn overrun_rate(&self) -> f64 {
+        if self.steps == 0 {
+            0.0
+        } else {
+            self.overruns as f64 / self.steps as f64
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use crate::Asset;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// An account at the venue that funds are moved between.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Account {
+    /// The account the exchange trades with.
+    Trading,
+    /// The main account of the venue.
+    Main,
+    /// A subaccount by name.
+    Subaccount(String),
+}
+
+/// Moves an amount of an asset between two accounts.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Transfer {
+    pub asset: Asset,
+    pub amount: Decimal,
+    pub from: Account,
+    pub to: Account,
+}
+
+impl Transfer {
+    /// Move funds out of the trading account.
+    pub fn to(to: Account, asset: Asset, amount: Decimal) -> Self {
+        Transfer {
+            asset,
+            amount,
+            from: Account::Trading,
+            to,
+        }
+    }
+
+    /// Move funds into the trading account.
+    pub fn from(from: Account, asset: Asset, amount: Decimal) -> Self {
+        Transfer {
+            asset,
+            amount,
+            from,
+            to: Account::Trading,
+        }
+    }
+
+    // The signed change of the balance of the trading account.
+    pub(crate) fn change(&self) -> Decimal {
+        match (&self.from, &self.to) {
+            (Account::Trading, _) => -self.amount,
+            (_, Account::Trading) => self.amount,
+            _ => Decimal::ZERO,
+        }
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum TransferError {
+    #[error("The amount to transfer must be positive.")]
+    InvalidAmount,
+    #[error("Funds can only be moved into or out of the trading account.")]
+    NotTradingAccount,
+    #[error("Only {available} {asset} are free to transfer.")]
+    NotEnoughFree { asset: Asset, available: Decimal },
+}
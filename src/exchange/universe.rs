@@ -0,0 +1,45 @@
+use crate::{MarketInfo, Markets, Symbol};
+use thiserror::Error;
+
+/// The markets added to and removed from the universe by `Exchange::select_universe`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UniverseChange {
+    /// Newly watched markets, their first candle arrives with the next step.
+    pub added: Vec<Symbol>,
+    /// Markets that are not watched anymore.
+    pub removed: Vec<Symbol>,
+    /// Markets that dropped out of the selection, but are still watched
+    /// because positions hold them. They are removed once the positions are closed.
+    pub held: Vec<Symbol>,
+}
+
+/// Markets are ranked by the daily quote volume the venue reports now, not as of the time
+/// of a backtest, so selecting them in a backtest would pick them by their future volume
+/// and skip delisted markets.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("The universe can only be selected in real time, backtests would rank markets by their future volume.")]
+pub struct LookAheadError;
+
+// The n markets with the highest daily quote volume that pass the filter,
+// ties are broken by the symbol for reproducible selections.
+pub(crate) fn top_by_volume(
+    markets: &Markets,
+    filter: impl Fn(&MarketInfo) -> bool,
+    n: usize,
+) -> Vec<Symbol> {
+    let mut candidates: Vec<&MarketInfo> = markets
+        .markets()
+        .map(|(_, info)| info)
+        .filter(|info| filter(info))
+        .collect();
+    candidates.sort_by(|a, b| {
+        b.daily_quote_volume
+            .cmp(&a.daily_quote_volume)
+            .then_with(|| a.symbol.to_string().cmp(&b.symbol.to_string()))
+    });
+    candidates
+        .into_iter()
+        .take(n)
+        .map(|info| info.symbol)
+        .collect()
+}
@@ -3,8 +3,11 @@ use std::hash::BuildHasherDefault;
 use crate::Symbol;
 use fxhash::{FxHashMap, FxHasher};
 use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// Prices by symbol, serialized like a [`Bundle`](super::Bundle).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct Valuation(pub(crate) FxHashMap<Symbol, Decimal>);
 
 impl Default for Valuation {
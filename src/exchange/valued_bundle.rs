@@ -1,14 +1,15 @@
 use super::{Bundle, Valuation};
-use crate::{Order, OrderInfo, OrderType, Side};
+use crate::{Order, OrderInfo, OrderType, Side, TimeInForce};
 use chrono::{DateTime, Utc};
 use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
 use std::{
     fmt::Debug,
     ops::{Add, Neg},
 };
 use uuid::Uuid;
 
-#[derive(Clone, Default)]
+#[derive(Clone, Default, Serialize, Deserialize)]
 pub struct ValuedBundle {
     pub(crate) bundle: Bundle,
     pub(crate) valuation: Valuation,
@@ -136,6 +137,9 @@ impl From<ValuedBundle> for Vec<Order> {
                     size: qty.abs(),
                     order_type: OrderType::Market,
                     reduce_only: false,
+                    time_in_force: TimeInForce::ImmediateOrCancel,
+                    post_only: false,
+                    position_side: None,
                     time: valued_bundle
                         .time
                         .expect("Cannot order valued bundle without associated time"),
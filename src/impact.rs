@@ -0,0 +1,71 @@
+use rust_decimal::prelude::*;
+
+/// Square root market impact model, trading a quote value `Q` in a market with a daily
+/// quote volume `V` and a daily volatility `σ` moves the price by about `coefficient * σ * sqrt(Q / V)`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImpactModel {
+    /// Around 1 for most liquid markets, higher for markets that react stronger to flow.
+    pub coefficient: f64,
+}
+
+impl Default for ImpactModel {
+    fn default() -> Self {
+        ImpactModel { coefficient: 1.0 }
+    }
+}
+
+impl ImpactModel {
+    /// The expected impact of trading a quote value, relative to the price.
+    /// Returns None without volume.
+    pub fn impact(
+        &self,
+        value: Decimal,
+        daily_volume: Decimal,
+        daily_volatility: f64,
+    ) -> Option<Decimal> {
+        if daily_volume <= Decimal::ZERO {
+            return None;
+        }
+        let participation = (value.abs() / daily_volume).to_f64()?;
+        Decimal::from_f64(self.coefficient * daily_volatility * participation.sqrt())
+            .map(|impact| impact.round_dp(8))
+    }
+
+    /// The largest quote value that can be traded with an expected impact of at most `max_impact`,
+    /// e.g. 0.001 for 10 bps. Returns None without volume or volatility.
+    pub fn max_value(
+        &self,
+        max_impact: Decimal,
+        daily_volume: Decimal,
+        daily_volatility: f64,
+    ) -> Option<Decimal> {
+        let scale = self.coefficient * daily_volatility;
+        if daily_volume <= Decimal::ZERO || scale <= 0.0 {
+            return None;
+        }
+        let participation = (max_impact.to_f64()? / scale).powi(2);
+        Some((daily_volume * Decimal::from_f64(participation)?).round_dp(8))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn max_value_inverts_impact() {
+        let model = ImpactModel::default();
+
+        // 1% of the daily volume with 4% daily volatility moves the price by 40 bps.
+        assert_eq!(
+            model.impact(dec!(1_000_000), dec!(100_000_000), 0.04),
+            Some(dec!(0.004))
+        );
+        assert_eq!(
+            model.max_value(dec!(0.004), dec!(100_000_000), 0.04),
+            Some(dec!(1_000_000))
+        );
+        assert_eq!(model.max_value(dec!(0.004), dec!(0), 0.04), None);
+    }
+}
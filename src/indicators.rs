@@ -0,0 +1,518 @@
+//! Incremental technical indicators, updated with one candle at a time.
+//!
+//! Indicators can be used standalone, or attached to a market with `Exchange::attach`
+//! to be updated with every candle of the market:
+//!
+//! ```ignore
+//! let sma = exchange.attach(btc, Sma::new(20));
+//! // Later, in eval.
+//! if let Some(sma) = exchange.indicator(sma) { .. }
+//! ```
+
+use crate::{Candle, Symbol};
+use rust_decimal::prelude::*;
+use std::any::Any;
+use std::collections::{BTreeMap, VecDeque};
+use std::marker::PhantomData;
+
+/// An indicator computed incrementally from candles.
+pub trait Indicator: 'static {
+    type Output;
+
+    fn update(&mut self, candle: &Candle);
+
+    /// The current value, None until enough candles were seen.
+    fn value(&self) -> Option<Self::Output>;
+}
+
+/// Simple moving average of the closes.
+#[derive(Debug, Clone)]
+pub struct Sma {
+    period: usize,
+    window: VecDeque<Decimal>,
+    sum: Decimal,
+}
+
+impl Sma {
+    pub fn new(period: usize) -> Self {
+        assert!(period > 0);
+        Sma {
+            period,
+            window: VecDeque::with_capacity(period),
+            sum: Decimal::ZERO,
+        }
+    }
+
+    fn push(&mut self, value: Decimal) {
+        if self.window.len() == self.period {
+            self.sum -= self.window.pop_front().unwrap_or_default();
+        }
+        self.window.push_back(value);
+        self.sum += value;
+    }
+
+    fn mean(&self) -> Option<Decimal> {
+        if self.window.len() < self.period {
+            return None;
+        }
+        Some(self.sum / Decimal::from(self.period))
+    }
+}
+
+impl Indicator for Sma {
+    type Output = Decimal;
+
+    fn update(&mut self, candle: &Candle) {
+        self.push(candle.close);
+    }
+
+    fn value(&self) -> Option<Decimal> {
+        self.mean()
+    }
+}
+
+/// Exponential moving average of the closes, seeded with the simple moving average
+/// of the first `period` closes.
+#[derive(Debug, Clone)]
+pub struct Ema {
+    alpha: Decimal,
+    seed: Sma,
+    value: Option<Decimal>,
+}
+
+impl Ema {
+    pub fn new(period: usize) -> Self {
+        Ema {
+            alpha: Decimal::TWO / Decimal::from(period + 1),
+            seed: Sma::new(period),
+            value: None,
+        }
+    }
+
+    fn push(&mut self, value: Decimal) {
+        self.value = match self.value {
+            Some(ema) => Some(ema + self.alpha * (value - ema)),
+            None => {
+                self.seed.push(value);
+                self.seed.mean()
+            }
+        };
+    }
+}
+
+impl Indicator for Ema {
+    type Output = Decimal;
+
+    fn update(&mut self, candle: &Candle) {
+        self.push(candle.close);
+    }
+
+    fn value(&self) -> Option<Decimal> {
+        self.value
+    }
+}
+
+// Wilder's smoothing, a moving average seeded with the simple moving average
+// of the first `period` values.
+#[derive(Debug, Clone)]
+struct Wilder {
+    period: Decimal,
+    seed: Sma,
+    value: Option<Decimal>,
+}
+
+impl Wilder {
+    fn new(period: usize) -> Self {
+        Wilder {
+            period: Decimal::from(period),
+            seed: Sma::new(period),
+            value: None,
+        }
+    }
+
+    fn push(&mut self, value: Decimal) {
+        self.value = match self.value {
+            Some(average) => Some((average * (self.period - Decimal::ONE) + value) / self.period),
+            None => {
+                self.seed.push(value);
+                self.seed.mean()
+            }
+        };
+    }
+}
+
+/// Relative strength index of the closes between 0 and 100, with Wilder's smoothing.
+#[derive(Debug, Clone)]
+pub struct Rsi {
+    last: Option<Decimal>,
+    gains: Wilder,
+    losses: Wilder,
+}
+
+impl Rsi {
+    pub fn new(period: usize) -> Self {
+        assert!(period > 0);
+        Rsi {
+            last: None,
+            gains: Wilder::new(period),
+            losses: Wilder::new(period),
+        }
+    }
+}
+
+impl Indicator for Rsi {
+    type Output = Decimal;
+
+    fn update(&mut self, candle: &Candle) {
+        if let Some(last) = self.last.replace(candle.close) {
+            let change = candle.close - last;
+            self.gains.push(change.max(Decimal::ZERO));
+            self.losses.push((-change).max(Decimal::ZERO));
+        }
+    }
+
+    fn value(&self) -> Option<Decimal> {
+        let (gains, losses) = (self.gains.value?, self.losses.value?);
+        if losses.is_zero() {
+            return Some(if gains.is_zero() {
+                Decimal::from(50)
+            } else {
+                Decimal::ONE_HUNDRED
+            });
+        }
+        Some(Decimal::ONE_HUNDRED - Decimal::ONE_HUNDRED / (Decimal::ONE + gains / losses))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MacdValue {
+    /// The fast minus the slow moving average.
+    pub macd: Decimal,
+    /// The moving average of the MACD line.
+    pub signal: Decimal,
+    /// The MACD line minus the signal line.
+    pub histogram: Decimal,
+}
+
+/// Moving average convergence divergence of the closes.
+#[derive(Debug, Clone)]
+pub struct Macd {
+    fast: Ema,
+    slow: Ema,
+    signal: Ema,
+    macd: Option<Decimal>,
+}
+
+impl Macd {
+    pub fn new(fast: usize, slow: usize, signal: usize) -> Self {
+        Macd {
+            fast: Ema::new(fast),
+            slow: Ema::new(slow),
+            signal: Ema::new(signal),
+            macd: None,
+        }
+    }
+}
+
+impl Default for Macd {
+    /// The common periods of 12, 26 and 9.
+    fn default() -> Self {
+        Macd::new(12, 26, 9)
+    }
+}
+
+impl Indicator for Macd {
+    type Output = MacdValue;
+
+    fn update(&mut self, candle: &Candle) {
+        self.fast.push(candle.close);
+        self.slow.push(candle.close);
+        self.macd = self.fast.value.zip(self.slow.value).map(|(f, s)| f - s);
+        if let Some(macd) = self.macd {
+            self.signal.push(macd);
+        }
+    }
+
+    fn value(&self) -> Option<MacdValue> {
+        let (macd, signal) = (self.macd?, self.signal.value?);
+        Some(MacdValue {
+            macd,
+            signal,
+            histogram: macd - signal,
+        })
+    }
+}
+
+/// Average true range with Wilder's smoothing.
+#[derive(Debug, Clone)]
+pub struct Atr {
+    last_close: Option<Decimal>,
+    ranges: Wilder,
+}
+
+impl Atr {
+    pub fn new(period: usize) -> Self {
+        assert!(period > 0);
+        Atr {
+            last_close: None,
+            ranges: Wilder::new(period),
+        }
+    }
+}
+
+impl Indicator for Atr {
+    type Output = Decimal;
+
+    fn update(&mut self, candle: &Candle) {
+        let range = match self.last_close.replace(candle.close) {
+            Some(close) => (candle.high - candle.low)
+                .max((candle.high - close).abs())
+                .max((candle.low - close).abs()),
+            None => candle.high - candle.low,
+        };
+        self.ranges.push(range);
+    }
+
+    fn value(&self) -> Option<Decimal> {
+        self.ranges.value
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bands {
+    pub lower: Decimal,
+    pub middle: Decimal,
+    pub upper: Decimal,
+}
+
+/// Bollinger bands of the closes, the simple moving average plus and minus
+/// a multiple of the standard deviation.
+#[derive(Debug, Clone)]
+pub struct Bollinger {
+    sma: Sma,
+    width: Decimal,
+}
+
+impl Bollinger {
+    pub fn new(period: usize, width: Decimal) -> Self {
+        Bollinger {
+            sma: Sma::new(period),
+            width,
+        }
+    }
+}
+
+impl Default for Bollinger {
+    /// The common period of 20 and a width of two standard deviations.
+    fn default() -> Self {
+        Bollinger::new(20, Decimal::TWO)
+    }
+}
+
+impl Indicator for Bollinger {
+    type Output = Bands;
+
+    fn update(&mut self, candle: &Candle) {
+        self.sma.push(candle.close);
+    }
+
+    fn value(&self) -> Option<Bands> {
+        let middle = self.sma.mean()?;
+        let variance = self
+            .sma
+            .window
+            .iter()
+            .map(|close| (close - middle) * (close - middle))
+            .sum::<Decimal>()
+            / Decimal::from(self.sma.period);
+        let deviation = Decimal::from_f64(variance.to_f64()?.sqrt())? * self.width;
+        Some(Bands {
+            lower: middle - deviation,
+            middle,
+            upper: middle + deviation,
+        })
+    }
+}
+
+/// Refers to an indicator attached to a market, see `Exchange::attach`.
+#[derive(Debug)]
+pub struct IndicatorHandle<I> {
+    id: usize,
+    symbol: Symbol,
+    indicator: PhantomData<fn() -> I>,
+}
+
+impl<I> Clone for IndicatorHandle<I> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<I> Copy for IndicatorHandle<I> {}
+
+impl<I> IndicatorHandle<I> {
+    /// The market the indicator is attached to.
+    pub fn symbol(&self) -> Symbol {
+        self.symbol
+    }
+}
+
+trait AnyIndicator {
+    fn update_any(&mut self, candle: &Candle);
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<I: Indicator> AnyIndicator for I {
+    fn update_any(&mut self, candle: &Candle) {
+        Indicator::update(self, candle);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+// The indicators attached to markets.
+#[derive(Default)]
+pub(crate) struct Indicators {
+    next_id: usize,
+    attached: BTreeMap<usize, (Symbol, Box<dyn AnyIndicator>)>,
+}
+
+impl Indicators {
+    pub fn attach<I: Indicator>(&mut self, symbol: Symbol, indicator: I) -> IndicatorHandle<I> {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.attached.insert(id, (symbol, Box::new(indicator)));
+        IndicatorHandle {
+            id,
+            symbol,
+            indicator: PhantomData,
+        }
+    }
+
+    pub fn get<I: Indicator>(&self, handle: IndicatorHandle<I>) -> Option<&I> {
+        self.attached.get(&handle.id)?.1.as_any().downcast_ref()
+    }
+
+    pub fn update(&mut self, symbol: Symbol, candle: &Candle) {
+        for (_, indicator) in self
+            .attached
+            .values_mut()
+            .filter(|(attached, _)| *attached == symbol)
+        {
+            indicator.update_any(candle);
+        }
+    }
+
+    // Detach the indicators of a market.
+    pub fn remove(&mut self, symbol: Symbol) {
+        self.attached.retain(|_, (attached, _)| *attached != symbol);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn close(close: Decimal) -> Candle {
+        Candle {
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: dec!(1),
+        }
+    }
+
+    fn run<I: Indicator>(mut indicator: I, closes: &[Decimal]) -> Option<I::Output> {
+        for &c in closes {
+            indicator.update(&close(c));
+        }
+        indicator.value()
+    }
+
+    #[test]
+    fn moving_averages() {
+        assert_eq!(run(Sma::new(3), &[dec!(1), dec!(2)]), None);
+        assert_eq!(
+            run(Sma::new(3), &[dec!(1), dec!(2), dec!(3), dec!(7)]),
+            Some(dec!(4))
+        );
+        assert_eq!(run(Ema::new(3), &[dec!(1), dec!(2)]), None);
+        // Seeded with 2, then moves half way to 6.
+        assert_eq!(
+            run(Ema::new(3), &[dec!(1), dec!(2), dec!(3), dec!(6)]),
+            Some(dec!(4))
+        );
+    }
+
+    #[test]
+    fn rsi() {
+        assert_eq!(run(Rsi::new(2), &[dec!(1), dec!(2)]), None);
+        assert_eq!(
+            run(Rsi::new(2), &[dec!(1), dec!(2), dec!(3)]),
+            Some(dec!(100))
+        );
+        // Average gain of 1 and average loss of 1.
+        assert_eq!(
+            run(Rsi::new(2), &[dec!(1), dec!(3), dec!(1)]),
+            Some(dec!(50))
+        );
+    }
+
+    #[test]
+    fn macd() {
+        let closes: Vec<Decimal> = (1..=4).map(Decimal::from).collect();
+        assert_eq!(run(Macd::new(1, 2, 2), &closes[..2]), None);
+        // The fast average is the close, the slow one lags by a half.
+        let value = run(Macd::new(1, 2, 2), &closes).unwrap();
+        assert_eq!(value.macd, dec!(0.5));
+        assert_eq!(value.histogram, value.macd - value.signal);
+    }
+
+    #[test]
+    fn atr() {
+        let mut atr = Atr::new(2);
+        let candle = |high, low, close| Candle {
+            open: close,
+            high,
+            low,
+            close,
+            volume: dec!(1),
+        };
+        atr.update(&candle(dec!(11), dec!(9), dec!(10)));
+        assert_eq!(atr.value(), None);
+        // The gap from the last close widens the range to 4.
+        atr.update(&candle(dec!(14), dec!(13), dec!(13)));
+        assert_eq!(atr.value(), Some(dec!(3)));
+    }
+
+    #[test]
+    fn bollinger() {
+        let bands = run(Bollinger::new(2, dec!(2)), &[dec!(9), dec!(11)]).unwrap();
+        assert_eq!(
+            bands,
+            Bands {
+                lower: dec!(8),
+                middle: dec!(10),
+                upper: dec!(12),
+            }
+        );
+    }
+
+    #[test]
+    fn attached_indicators() {
+        let (btc, eth) = (Symbol::perp("BTC"), Symbol::perp("ETH"));
+        let mut indicators = Indicators::default();
+        let sma = indicators.attach(btc, Sma::new(1));
+        let ema = indicators.attach(eth, Ema::new(1));
+        indicators.update(btc, &close(dec!(5)));
+        assert_eq!(indicators.get(sma).and_then(Sma::value), Some(dec!(5)));
+        assert_eq!(indicators.get(ema).and_then(Ema::value), None);
+        indicators.remove(btc);
+        assert!(indicators.get(sma).is_none());
+        assert!(indicators.get(ema).is_some());
+    }
+}
@@ -0,0 +1,226 @@
+use crate::{Candle, CandleKey};
+use chrono::{DateTime, Datelike, Duration, TimeZone, Utc};
+
+/// The interval on which a strategy trades.
+///
+/// Calendar intervals are aligned in UTC: days start at midnight, weeks on Monday
+/// and months on the first day of the month. Since UTC has no daylight saving time,
+/// every day is exactly 24 hours long, while months and years vary in length.
+/// Their candles are resampled from daily candles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Interval {
+    /// A fixed duration, aligned to multiples of the duration since the unix epoch,
+    /// which is the candle grid of most venues.
+    Fixed(Duration),
+    Day,
+    Week,
+    Month,
+}
+
+impl From<Duration> for Interval {
+    fn from(duration: Duration) -> Self {
+        Interval::Fixed(duration)
+    }
+}
+
+impl Interval {
+    /// The resolution of the candles fetched from the API.
+    pub fn resolution(&self) -> Duration {
+        match self {
+            Interval::Fixed(duration) => *duration,
+            Interval::Day | Interval::Week | Interval::Month => Duration::days(1),
+        }
+    }
+
+    /// The start of the interval that contains the time.
+    /// Fixed intervals are aligned to multiples of their duration since the unix epoch.
+    pub fn align(&self, time: DateTime<Utc>) -> DateTime<Utc> {
+        match self {
+            Interval::Fixed(duration) => {
+                time - Duration::seconds(time.timestamp().rem_euclid(duration.num_seconds()))
+            }
+            Interval::Day => midnight(time),
+            Interval::Week => {
+                midnight(time) - Duration::days(time.weekday().num_days_from_monday() as i64)
+            }
+            Interval::Month => Utc
+                .with_ymd_and_hms(time.year(), time.month(), 1, 0, 0, 0)
+                .unwrap(),
+        }
+    }
+
+    /// The time of the next step.
+    /// Calendar intervals return the start of the following interval.
+    pub fn next(&self, time: DateTime<Utc>) -> DateTime<Utc> {
+        match self {
+            Interval::Fixed(duration) => time + *duration,
+            Interval::Day => self.align(time) + Duration::days(1),
+            Interval::Week => self.align(time) + Duration::weeks(1),
+            Interval::Month => {
+                let (year, month) = if time.month() == 12 {
+                    (time.year() + 1, 1)
+                } else {
+                    (time.year(), time.month() + 1)
+                };
+                Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0).unwrap()
+            }
+        }
+    }
+
+    /// The time of the first step at or after the time, which is the next interval boundary
+    /// unless the time already is aligned. Otherwise the candles of the venue would never match.
+    pub fn start(&self, time: DateTime<Utc>) -> DateTime<Utc> {
+        let aligned = self.align(time);
+        if aligned == time {
+            time
+        } else {
+            self.next(aligned)
+        }
+    }
+
+    /// The duration of the step at the time.
+    pub fn length(&self, time: DateTime<Utc>) -> Duration {
+        self.next(time) - time
+    }
+
+    /// Combine candles of the fetch resolution into candles of this interval.
+    /// Only complete intervals are returned, keyed by their start and actual length.
+    pub fn resample(
+        &self,
+        candles: Vec<(CandleKey, Option<Candle>)>,
+    ) -> Vec<(CandleKey, Option<Candle>)> {
+        if let Interval::Fixed(_) = self {
+            return candles;
+        }
+
+        let mut out: Vec<(CandleKey, Option<Candle>)> = Vec::new();
+        let mut complete = false;
+        for (key, candle) in candles {
+            let start = self.align(key.time);
+            let end = self.next(start);
+            match out.last_mut() {
+                Some((last, merged)) if last.time == start => {
+                    *merged = match (*merged, candle) {
+                        (Some(merged), Some(candle)) => Some(merged.merge(candle)),
+                        (merged, candle) => merged.or(candle),
+                    };
+                }
+                _ => out.push((
+                    CandleKey {
+                        market: key.market,
+                        time: start,
+                        interval: end - start,
+                    },
+                    candle,
+                )),
+            }
+            complete = key.time + key.interval >= end;
+        }
+
+        if !complete {
+            out.pop();
+        }
+
+        out
+    }
+}
+
+fn midnight(time: DateTime<Utc>) -> DateTime<Utc> {
+    Utc.from_utc_datetime(&time.date_naive().and_hms_opt(0, 0, 0).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Symbol;
+    use rust_decimal_macros::dec;
+
+    fn time(year: i32, month: u32, day: u32, hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, hour, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn month_boundaries() {
+        let month = Interval::Month;
+        assert_eq!(month.align(time(2020, 2, 29, 13)), time(2020, 2, 1, 0));
+        assert_eq!(month.next(time(2020, 1, 31, 0)), time(2020, 2, 1, 0));
+        assert_eq!(month.next(time(2020, 12, 1, 0)), time(2021, 1, 1, 0));
+        assert_eq!(month.length(time(2020, 2, 1, 0)), Duration::days(29));
+        assert_eq!(month.length(time(2021, 2, 1, 0)), Duration::days(28));
+        assert_eq!(month.start(time(2021, 1, 15, 0)), time(2021, 2, 1, 0));
+        assert_eq!(month.start(time(2021, 1, 1, 0)), time(2021, 1, 1, 0));
+    }
+
+    #[test]
+    fn week_and_day_boundaries() {
+        // 2021-01-01 was a friday, the week started on monday 2020-12-28.
+        assert_eq!(
+            Interval::Week.align(time(2021, 1, 1, 5)),
+            time(2020, 12, 28, 0)
+        );
+        assert_eq!(
+            Interval::Week.next(time(2021, 1, 3, 23)),
+            time(2021, 1, 4, 0)
+        );
+        // Daylight saving time started in Europe and ended in the US on these days.
+        for day in [time(2021, 3, 28, 0), time(2021, 11, 7, 0)] {
+            assert_eq!(Interval::Day.length(day), Duration::days(1));
+            assert_eq!(Interval::Day.align(day + Duration::hours(23)), day);
+        }
+        assert_eq!(
+            Interval::Fixed(Duration::hours(4)).align(time(2021, 1, 1, 7)),
+            time(2021, 1, 1, 4)
+        );
+        assert_eq!(
+            Interval::Fixed(Duration::minutes(1)).start(time(2021, 1, 1, 7) + Duration::seconds(5)),
+            time(2021, 1, 1, 7) + Duration::minutes(1)
+        );
+        assert_eq!(
+            Interval::Fixed(Duration::hours(4)).start(time(2021, 1, 1, 8)),
+            time(2021, 1, 1, 8)
+        );
+    }
+
+    #[test]
+    fn resample_months() {
+        let market = Symbol::perp("BTC");
+        let candles: Vec<_> = (0..40)
+            .map(|day| {
+                let key = CandleKey {
+                    market,
+                    time: time(2021, 2, 1, 0) + Duration::days(day),
+                    interval: Duration::days(1),
+                };
+                let price = dec!(100) + rust_decimal::Decimal::from(day);
+                let candle = Candle {
+                    open: price,
+                    high: price + dec!(1),
+                    low: price - dec!(1),
+                    close: price,
+                    volume: dec!(1),
+                };
+                (key, if day == 3 { None } else { Some(candle) })
+            })
+            .collect();
+
+        // March is incomplete, so only February is returned.
+        let bars = Interval::Month.resample(candles);
+        assert_eq!(
+            bars,
+            vec![(
+                CandleKey {
+                    market,
+                    time: time(2021, 2, 1, 0),
+                    interval: Duration::days(28),
+                },
+                Some(Candle {
+                    open: dec!(100),
+                    high: dec!(128),
+                    low: dec!(99),
+                    close: dec!(127),
+                    volume: dec!(27),
+                })
+            )]
+        );
+    }
+}
@@ -2,108 +2,392 @@
 #![deny(unsafe_code)]
 #![allow(clippy::comparison_chain)]
 
+mod alert;
 pub mod apis;
 mod asset;
+mod cache;
+mod calendar;
 mod candle;
+mod capacity;
 mod exchange;
+mod impact;
+pub mod indicators;
+mod interval;
+#[cfg(feature = "json-logs")]
+mod logging;
 mod market;
+mod monte_carlo;
+mod optimize;
 mod order;
+mod orderbook;
+mod precision;
+mod report;
+mod risk;
+mod spread;
 pub mod strategies;
+pub mod testing;
+mod vwap;
 mod wallet;
 
+pub use alert::*;
 pub use asset::*;
+pub use cache::*;
+pub use calendar::*;
 pub use candle::*;
+pub use capacity::*;
 use chrono::{DateTime, Duration, TimeZone, Utc};
 pub use exchange::*;
+pub use impact::*;
+pub use interval::*;
+#[cfg(feature = "json-logs")]
+pub use logging::*;
 pub use market::*;
+pub use monte_carlo::*;
+pub use optimize::*;
 pub use order::*;
+pub use orderbook::*;
+pub use precision::*;
+pub use report::*;
+pub use risk::*;
 use rust_decimal_macros::dec;
+pub use spread::*;
+pub use vwap::*;
 pub use wallet::*;
 
-use apis::{Api, ForwardFill, Monitor, Simulate, Store};
+use apis::{Api, Backfill, ForwardFill, Latency, Monitor, OnGap, Simulate, Store};
+use futures_util::future::join_all;
 use rust_decimal::Decimal;
+use std::sync::Arc;
 use strategies::Strategy;
 
 pub struct Bazaar {
-    /// The start capital for simulated backtesting in USD.
+    /// The start capital for simulated backtesting in the quote asset.
     pub start_capital: Decimal,
+    /// The asset the start capital and all totals are denominated in,
+    /// e.g. USDT on USDT-margined venues. Defaults to the quote asset of the API.
+    pub quote_asset: Option<Asset>,
+    /// The annual interest rate charged on short sizes in simulated runs,
+    /// for markets the API does not know the borrow rate of.
+    pub borrow_rate: Decimal,
+    /// The delay of orders in simulated runs, executed at the close if None.
+    pub latency: Option<Latency>,
     /// The start time for backtesting.
     pub start_time: DateTime<Utc>,
+    /// Stop at this time and return the report instead of continuing live once caught up.
+    pub end_time: Option<DateTime<Utc>>,
+    /// Close all positions at the end time, so the report contains their results.
+    pub close_at_end: bool,
     /// The maximum forward fill duration for backtesting.
     pub forward_fill: Duration,
+    /// How gaps longer than the maximum forward fill duration are handled in backtests.
+    pub on_gap: OnGap,
+    /// Cache backtest reports, keyed by the strategy, its parameters, the settings and the stored
+    /// candles of the backfill, which therefore is required. Cached reports are reused as long as these are unchanged.
+    pub cache_reports: bool,
+    /// Download the candles for the whole backtest before it starts.
+    pub backfill: Option<Backfill>,
+    /// Stop the run from outside, closing all positions and returning the report.
+    pub cancellation: Option<CancellationToken>,
+    /// Check every outgoing order for compliance, see `Compliance`.
+    pub compliance: Option<Arc<dyn Compliance>>,
+    /// Publish snapshots and events of the run to the subscribers of this session.
+    pub session: Option<Session>,
+    /// Push notifications about the run, e.g. a `Webhook` with the `alerts` feature.
+    pub alerter: Option<Arc<dyn Alerter>>,
+    /// Which alerts are pushed.
+    pub alert_filter: AlertFilter,
+    /// The version of the strategy code, e.g. a commit hash, recorded with backtests.
+    pub version: Option<String>,
+    /// Stop the run the same way when the process receives Ctrl+C.
+    /// Ctrl+C does not terminate the process anymore once enabled, see `CancellationToken::cancel_on_ctrl_c`.
+    pub shutdown_on_ctrl_c: bool,
+    /// Report the progress of backtests, e.g. to a `ProgressBar` with the `progress-bar` feature.
+    /// Not reported by `run_per_symbol`.
+    pub progress: Option<Arc<dyn ProgressReporter>>,
 }
 
 impl Default for Bazaar {
     fn default() -> Self {
         Bazaar {
             start_capital: dec!(1000),
+            quote_asset: None,
+            borrow_rate: Decimal::ZERO,
+            latency: None,
             start_time: if cfg!(feature = "backtest") {
-                Utc.ymd(2021, 1, 1).and_hms(0, 0, 0)
+                Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap()
             } else {
                 Utc::now()
             },
+            end_time: None,
+            close_at_end: false,
             forward_fill: Duration::days(1),
+            on_gap: OnGap::Error,
+            cache_reports: false,
+            backfill: None,
+            cancellation: None,
+            compliance: None,
+            session: None,
+            alerter: None,
+            alert_filter: AlertFilter::default(),
+            version: None,
+            shutdown_on_ctrl_c: false,
+            progress: None,
         }
     }
 }
 
 impl Bazaar {
+    /// The quote asset of simulated runs on the API.
+    pub fn quote_asset<A: Api>(&self, api: &A) -> Asset {
+        self.quote_asset.unwrap_or_else(|| api.quote_asset())
+    }
+
+    // A simulation of the API, starting with the start capital.
+    pub(crate) fn simulate<A: Api>(&self, api: A) -> Simulate<A> {
+        let quote_asset = self.quote_asset(&api);
+        let mut wallet = Wallet::new();
+        wallet.deposit(self.start_capital, quote_asset);
+        let simulate = Simulate::new(api, wallet)
+            .with_quote_asset(quote_asset)
+            .with_borrow_rate(self.borrow_rate);
+        match self.latency {
+            Some(latency) => simulate.with_latency(latency),
+            None => simulate,
+        }
+    }
+
+    fn exchange<A: Api>(&self, api: A) -> Exchange<A> {
+        let mut exchange = Exchange::new(api, self.start_time).with_close_at_end(self.close_at_end);
+        if let Some(end_time) = self.end_time {
+            exchange = exchange.until(end_time);
+        }
+        if let Some(session) = &self.session {
+            exchange = exchange.with_session(session.clone());
+        }
+        if let Some(compliance) = &self.compliance {
+            exchange = exchange.with_compliance(compliance.clone());
+        }
+        if let Some(alerter) = &self.alerter {
+            exchange = exchange.with_alerter(alerter.clone(), self.alert_filter);
+        }
+        if let Some(progress) = &self.progress {
+            exchange = exchange.with_progress(progress.clone());
+        }
+        if let Some(token) = &self.cancellation {
+            exchange = exchange.with_cancellation(token.clone());
+        }
+        if self.shutdown_on_ctrl_c {
+            exchange = exchange.with_ctrl_c();
+        }
+        exchange
+    }
+
+    // A fingerprint of everything that influences the report of a backtest,
+    // the candles are given by the digest of the backfilled ones.
+    #[cfg(any(test, feature = "backtest"))]
+    fn report_fingerprint(
+        &self,
+        api: &str,
+        strategy: &str,
+        parameters: serde_json::Value,
+        quote_asset: Asset,
+        candles: &str,
+    ) -> String {
+        ReportCache::fingerprint(&serde_json::json!({
+            "api": api,
+            "strategy": strategy,
+            "parameters": parameters,
+            "start_capital": self.start_capital,
+            "quote_asset": quote_asset,
+            "borrow_rate": self.borrow_rate,
+            "latency": self.latency.map(|latency| {
+                (latency.delay.num_milliseconds(), latency.jitter.num_milliseconds(), latency.seed)
+            }),
+            "start_time": self.start_time,
+            "end_time": self.end_time,
+            "close_at_end": self.close_at_end,
+            "forward_fill": self.forward_fill.num_seconds(),
+            "on_gap": format!("{:?}", self.on_gap),
+            "candles": candles,
+        }))
+    }
+
     /// Runs your strategy hot on a simulated exchange.
     #[cfg(all(not(feature = "backtest"), not(feature = "hot")))]
-    pub async fn run<A, S>(self, api: A, strategy: S) -> Result<(), AnyError>
+    pub async fn run<A, S>(self, api: A, strategy: S) -> Result<Report, AnyError>
     where
         A: Api,
         S: Strategy<Monitor<Simulate<A>>>,
     {
-        log::warn!("Running cold, live.");
+        tracing::warn!("Running cold, live.");
 
-        let mut wallet = Wallet::new();
-        wallet.deposit(self.start_capital, Asset::new("USD"));
-
-        let api = Monitor::new(Simulate::new(api, wallet));
-        let exchange = Exchange::new(api, self.start_time);
-        exchange.run(strategy).await?;
-
-        Ok(())
+        let api = Monitor::new(self.simulate(api));
+        let exchange = self.exchange(api);
+        exchange.run(strategy).await
     }
 
     /// Runs your strategy hot on the real exchange.
     #[cfg(all(not(feature = "backtest"), feature = "hot"))]
-    pub async fn run<A, S>(self, api: A, strategy: S) -> Result<(), AnyError>
+    pub async fn run<A, S>(self, api: A, strategy: S) -> Result<Report, AnyError>
     where
         A: Api,
         S: Strategy<Monitor<A>>,
     {
-        log::warn!("Running hot, live.");
+        tracing::warn!("Running hot, live.");
+        if self.quote_asset(&api) != api.quote_asset() {
+            tracing::warn!(
+                "Running hot, totals are in {} as reported by the API.",
+                api.quote_asset()
+            );
+        }
 
         let api = Monitor::new(api);
-        let exchange = Exchange::new(api, self.start_time);
-        exchange.run(strategy).await?;
-
-        Ok(())
+        let exchange = self.exchange(api);
+        exchange.run(strategy).await
     }
 
     /// Runs your strategy in backtest mode.
     /// Exchange data is stored locally to speed up backtesting.
     /// Missing candles are forward filled.
     #[cfg(feature = "backtest")]
-    pub async fn run<A, S>(self, api: A, strategy: S) -> Result<(), AnyError>
+    pub async fn run<A, S>(self, api: A, strategy: S) -> Result<Report, AnyError>
     where
         A: Api,
         S: Strategy<Monitor<Simulate<ForwardFill<Store<A>>>>>,
     {
-        log::warn!("Running cold, backtest.");
+        tracing::warn!("Running cold, backtest.");
 
-        let mut wallet = Wallet::new();
-        wallet.deposit(self.start_capital, Asset::new("USD"));
+        let store = Store::new(api).await;
+        let range = self.start_time..self.end_time.unwrap_or_else(Utc::now);
+        if let Some(backfill) = &self.backfill {
+            store
+                .backfill(&backfill.symbols, range.clone(), backfill.interval)
+                .await?;
+        }
+
+        let cache = ReportCache::default();
+        let fingerprint = match (self.cache_reports, &self.backfill) {
+            (true, Some(backfill)) => {
+                let candles = store
+                    .digest(&backfill.symbols, range, backfill.interval)
+                    .await?;
+                Some(self.report_fingerprint(
+                    A::NAME,
+                    S::NAME,
+                    serde_json::to_value(strategy.parameters())?,
+                    self.quote_asset(&store),
+                    &candles,
+                ))
+            }
+            (true, None) => {
+                tracing::warn!("Not caching the backtest report, caching requires a backfill.");
+                None
+            }
+            (false, _) => None,
+        };
+        if let Some(report) = fingerprint
+            .as_ref()
+            .and_then(|fingerprint| cache.get(fingerprint))
+        {
+            tracing::warn!("Using cached backtest report.");
+            return Ok(report);
+        }
+
+        let mut api =
+            Monitor::new(self.simulate(ForwardFill::new(store, self.forward_fill, self.on_gap)))
+                .with_kind(apis::SessionKind::Backtest);
+        if let Some(version) = &self.version {
+            api = api.with_version(version.clone());
+        }
+        let exchange = self.exchange(api);
+        let report = exchange.run(strategy).await?;
+
+        if let Some(fingerprint) = fingerprint {
+            cache.insert(&fingerprint, &report)?;
+        }
+
+        Ok(report)
+    }
+
+    /// Backtests a strategy on every symbol separately, all backtests run concurrently
+    /// from the start time until the end time or now and share one store. Each backtest starts with the full
+    /// start capital, the strategy for a symbol is created by the factory.
+    pub async fn run_per_symbol<A, S, F>(
+        mut self,
+        api: A,
+        factory: F,
+        symbols: &[Symbol],
+    ) -> Result<SymbolReports, AnyError>
+    where
+        A: Api,
+        S: Strategy<Simulate<ForwardFill<Arc<Store<A>>>>>,
+        F: Fn(Symbol) -> S,
+    {
+        tracing::warn!("Running cold, backtest per symbol.");
+        // The backtests run concurrently, so their progress would interleave.
+        self.progress = None;
+
+        let end_time = self.end_time.unwrap_or_else(Utc::now);
+        let store = Store::new(api).await;
+        if let Some(backfill) = &self.backfill {
+            store
+                .backfill(symbols, self.start_time..end_time, backfill.interval)
+                .await?;
+        }
+        let store = Arc::new(store);
+
+        let runs = symbols.iter().map(|&symbol| {
+            let api = self.simulate(ForwardFill::new(
+                store.clone(),
+                self.forward_fill,
+                self.on_gap,
+            ));
+            let exchange = self.exchange(api).until(end_time);
+            let strategy = factory(symbol);
+            async move { (symbol, exchange.run(strategy).await) }
+        });
+
+        let mut reports = Vec::new();
+        let mut failed = Vec::new();
+        for (symbol, result) in join_all(runs).await {
+            match result {
+                Ok(report) => reports.push((symbol, report)),
+                Err(err) => {
+                    tracing::error!("Backtest on {} failed: {}", symbol, err);
+                    failed.push((symbol, err.to_string()));
+                }
+            }
+        }
+
+        Ok(SymbolReports::new(reports, failed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
 
-        let api = Monitor::new(Simulate::new(
-            ForwardFill::new(Store::new(api).await, self.forward_fill),
-            wallet,
-        ));
-        let exchange = Exchange::new(api, self.start_time);
-        exchange.run(strategy).await?;
+    #[test]
+    fn cache_reports_by_parameters() {
+        let bazaar = Bazaar::default();
+        let usd = Asset::new("USD");
+        let fingerprint =
+            |parameters| bazaar.report_fingerprint("Mock", "Crossover", parameters, usd, "candles");
+        let cache = ReportCache::new(std::env::temp_dir().join("bazaar-parameters-cache-test"));
+        let report = Report::new(bazaar.start_time, bazaar.start_capital);
+        cache
+            .insert(&fingerprint(json!({ "fast": 20, "slow": 40 })), &report)
+            .unwrap();
 
-        Ok(())
+        assert_eq!(
+            cache.get(&fingerprint(json!({ "fast": 20, "slow": 40 }))),
+            Some(report)
+        );
+        assert_eq!(
+            cache.get(&fingerprint(json!({ "fast": 10, "slow": 40 }))),
+            None
+        );
+        assert_eq!(cache.get(&fingerprint(json!(null))), None);
     }
 }
@@ -0,0 +1,114 @@
+use tracing::Subscriber;
+use tracing_subscriber::{fmt, fmt::MakeWriter, util::SubscriberInitExt, EnvFilter};
+
+/// Log JSON lines to stdout, e.g. to ingest them into Loki or Elasticsearch.
+/// Each line holds the fields of the event and of its spans, like the session, the time
+/// of the step and the id of the order. The level is read from `RUST_LOG`, info by default.
+pub fn init_json_logs() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    json_logs(filter, std::io::stdout).try_init()?;
+    Ok(())
+}
+
+// Log JSON lines with the fields of the current span and of all its parents.
+fn json_logs<W>(filter: EnvFilter, writer: W) -> impl Subscriber + Send + Sync
+where
+    W: for<'writer> MakeWriter<'writer> + Send + Sync + 'static,
+{
+    fmt()
+        .json()
+        .with_env_filter(filter)
+        .with_current_span(true)
+        .with_span_list(true)
+        .with_writer(writer)
+        .finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        apis::{Mock, MockSettings},
+        strategies::Settings,
+        AnyError, Api, Asset, Candle, CandleKey, Exchange, MarketInfo, Position, Strategy, Symbol,
+    };
+    use chrono::{Duration, TimeZone, Utc};
+    use rust_decimal_macros::dec;
+    use serde_json::Value;
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+    };
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    struct Buying;
+
+    impl<A: Api> Strategy<A> for Buying {
+        const NAME: &'static str = "Buying";
+        type Parameters = ();
+
+        fn init(&mut self, exchange: &mut Exchange<A>) -> Result<Settings, AnyError> {
+            exchange.watch(Symbol::perp("BTC"));
+            Ok(Settings::default())
+        }
+
+        fn eval(&mut self, exchange: &mut Exchange<A>) -> Result<(), AnyError> {
+            if exchange.positions().next().is_none() {
+                exchange.open(Position::default().long(Symbol::perp("BTC"), dec!(1)))?;
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn log_step_and_order_fields() {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = json_logs(EnvFilter::new("debug"), move || writer.clone());
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let btc = Symbol::perp("BTC");
+        let mut settings = MockSettings::new(|_key: CandleKey| Candle {
+            open: dec!(100),
+            high: dec!(100),
+            low: dec!(100),
+            close: dec!(100),
+            volume: dec!(1),
+        });
+        settings.markets = vec![MarketInfo::unconstrained(btc)];
+        settings.wallet.deposit(dec!(1000), Asset::new("USD"));
+        let start = Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap();
+        Exchange::new(Mock::new(settings), start)
+            .until(start + Duration::minutes(2))
+            .run(Buying)
+            .await
+            .unwrap();
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let placed = lines
+            .iter()
+            .find(|line| line["fields"]["message"] == "Placing order.")
+            .expect("the order is logged");
+        assert_eq!(placed["fields"]["market"], "BTC-PERP");
+        assert!(placed["fields"]["order_id"].is_string());
+        assert_eq!(placed["spans"][0]["name"], "step");
+        assert_eq!(placed["spans"][0]["strategy"], "Buying");
+        assert_eq!(placed["spans"][0]["time"], start.to_string());
+    }
+}
@@ -1,7 +1,9 @@
 use crate::Asset;
+use chrono::{DateTime, NaiveDate, Utc};
 use rust_decimal::prelude::*;
-use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, fmt};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{collections::HashMap, fmt, str::FromStr};
+use thiserror::Error;
 
 #[derive(Default)]
 pub struct Markets {
@@ -20,6 +22,12 @@ impl Markets {
     pub fn markets(&self) -> impl Iterator<Item = (&Symbol, &MarketInfo)> {
         self.markets.iter()
     }
+
+    // The contract multiplier of a market, one for unknown markets.
+    pub(crate) fn multiplier(&self, symbol: Symbol) -> Decimal {
+        self.market(symbol)
+            .map_or(Decimal::ONE, |market| market.contract_multiplier)
+    }
 }
 
 /*
@@ -59,23 +67,25 @@ impl Market {
 }
 */
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+// Separates the venue from the rest of markets namespaced by a `Router`.
+const VENUE_SEPARATOR: char = ':';
+
+// Format of the expiry in the names of futures.
+const EXPIRY_FORMAT: &str = "%Y%m%d";
+
+/// A market, serialized as its name, e.g. "BTC-PERP", "BTC-20240329" or "BTC-SPOT".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Symbol {
-    //Spot(Asset, Asset),
     Perp(Asset),
+    /// A future of an underlying that expires at a date.
+    Future(Asset, NaiveDate),
+    /// The asset itself, bought and sold for the quote asset of the venue.
+    Spot(Asset),
 }
 
 impl Symbol {
     pub(crate) fn new<T: AsRef<str>>(string: T) -> Self {
-        match string.as_ref().split_once('-') {
-            None => unreachable!(),
-            /*match string.as_ref().split_once("/") {
-                None => unreachable!(),
-                Some((base, quote)) => Symbol::Spot(Asset::new(base), Asset::new(quote)),
-            },*/
-            Some((underlying, "PERP")) => Symbol::Perp(Asset::new(underlying)),
-            _ => unreachable!(),
-        }
+        string.as_ref().parse().unwrap()
     }
     /*
     pub fn spot<T: AsRef<str>>(base: T, quote: T) -> Self {
@@ -85,6 +95,78 @@ impl Symbol {
     pub fn perp<T: AsRef<str>>(underlying: T) -> Self {
         Symbol::Perp(Asset::new(underlying))
     }
+
+    pub fn future<T: AsRef<str>>(underlying: T, expiry: NaiveDate) -> Self {
+        Symbol::Future(Asset::new(underlying), expiry)
+    }
+
+    pub fn spot<T: AsRef<str>>(asset: T) -> Self {
+        Symbol::Spot(Asset::new(asset))
+    }
+
+    /// The asset whose price this market tracks, without the venue.
+    /// Markets with the same underlying are equivalent exposures, also on different venues.
+    pub fn underlying(&self) -> Asset {
+        match self.local() {
+            Self::Perp(asset) | Self::Future(asset, _) | Self::Spot(asset) => asset,
+        }
+    }
+
+    /// The date a future expires at, None for perpetuals and spot markets.
+    pub fn expiry(&self) -> Option<NaiveDate> {
+        match self {
+            Self::Perp(_) | Self::Spot(_) => None,
+            Self::Future(_, expiry) => Some(*expiry),
+        }
+    }
+
+    // The asset of the market, including the venue.
+    fn asset(&self) -> Asset {
+        match self {
+            Self::Perp(asset) | Self::Future(asset, _) | Self::Spot(asset) => *asset,
+        }
+    }
+
+    // The same kind of market with another asset.
+    fn with_asset(&self, asset: Asset) -> Symbol {
+        match self {
+            Self::Perp(_) => Self::Perp(asset),
+            Self::Future(_, expiry) => Self::Future(asset, *expiry),
+            Self::Spot(_) => Self::Spot(asset),
+        }
+    }
+
+    /// The market on a venue of a `Router`, e.g. "binance:BTC-PERP".
+    pub fn on(&self, venue: &str) -> Symbol {
+        assert!(
+            !venue.is_empty() && !venue.contains([VENUE_SEPARATOR, '-']),
+            "Invalid venue {}.",
+            venue
+        );
+        let local = self.local();
+        local.with_asset(Asset::new(format!(
+            "{}{}{}",
+            venue,
+            VENUE_SEPARATOR,
+            local.asset()
+        )))
+    }
+
+    /// The venue of a market namespaced by a `Router`.
+    pub fn venue(&self) -> Option<&'static str> {
+        self.asset()
+            .name()
+            .split_once(VENUE_SEPARATOR)
+            .map(|(venue, _)| venue)
+    }
+
+    /// The market without the venue, as it is known to the venue.
+    pub fn local(&self) -> Symbol {
+        match self.asset().name().split_once(VENUE_SEPARATOR) {
+            Some((_, local)) => self.with_asset(Asset::new(local)),
+            None => *self,
+        }
+    }
     /*
     pub fn base_asset(&self) -> Asset {
         match self {
@@ -107,76 +189,62 @@ impl fmt::Display for Symbol {
         match self {
             //Self::Spot(base, quote) => write!(f, "{}/{}", base, quote),
             Self::Perp(asset) => write!(f, "{}-PERP", asset),
+            Self::Future(asset, expiry) => write!(f, "{}-{}", asset, expiry.format(EXPIRY_FORMAT)),
+            Self::Spot(asset) => write!(f, "{}-SPOT", asset),
         }
     }
 }
 
-/*
-#[derive(Debug, Clone)]
-pub struct Orderbook {
-    pub bids: BTreeMap<Decimal, Decimal>,
-    pub asks: BTreeMap<Decimal, Decimal>,
-}
-impl Orderbook {
-    pub fn new() -> Orderbook {
-        Orderbook {
-            bids: BTreeMap::new(),
-            asks: BTreeMap::new(),
-        }
-    }
+#[derive(Error, Debug)]
+#[error("Invalid symbol {0}.")]
+pub struct ParseSymbolError(String);
 
-    /// Returns the price of the best bid
-    pub fn bid_price(&self) -> Option<Decimal> {
-        self.bids.keys().rev().next().cloned()
-    }
+impl FromStr for Symbol {
+    type Err = ParseSymbolError;
 
-    /// Returns the price of the best ask
-    pub fn ask_price(&self) -> Option<Decimal> {
-        self.asks.keys().next().cloned()
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        match string.split_once('-') {
+            /*None => match string.split_once("/") {
+                Some((base, quote)) => Ok(Symbol::Spot(Asset::new(base), Asset::new(quote))),
+                None => Err(ParseSymbolError(string.to_owned())),
+            },*/
+            Some((underlying, "PERP")) => Ok(Symbol::Perp(Asset::new(underlying))),
+            Some((asset, "SPOT")) => Ok(Symbol::Spot(Asset::new(asset))),
+            Some((underlying, expiry)) if expiry.len() == 8 => {
+                NaiveDate::parse_from_str(expiry, EXPIRY_FORMAT)
+                    .map(|expiry| Symbol::Future(Asset::new(underlying), expiry))
+                    .map_err(|_| ParseSymbolError(string.to_owned()))
+            }
+            _ => Err(ParseSymbolError(string.to_owned())),
+        }
     }
+}
 
-    /// Returns the midpoint between the best bid price and best ask price.
-    /// Output is not rounded to the smallest price increment.
-    pub fn mid_price(&self) -> Option<Decimal> {
-        Some((self.bid_price()? + self.ask_price()?) / Decimal::new(2, 0))
+impl Serialize for Symbol {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
     }
+}
 
-    /// Returns the expected execution price of a market order given the current
-    /// orders in the order book. Returns None if the order size exceeds the
-    /// liquidity available on that side of the order book.
-    pub fn execution_price(&self, size: Decimal, side: Side) -> Option<Decimal> {
-        // Match with orders in the book
-        let mut bids_iter = self.bids.iter().rev();
-        let mut asks_iter = self.asks.iter();
-
-        let mut fills: Vec<(Decimal, Decimal)> = Vec::new(); // (price, quantity)
-        let mut remaining = size;
-
-        while remaining > Decimal::ZERO {
-            let (price, quantity) = match side {
-                Side::Buy => asks_iter.next()?,
-                Side::Sell => bids_iter.next()?,
-            };
-
-            if *quantity <= remaining {
-                remaining -= quantity;
-                fills.push((*price, *quantity));
-            } else {
-                fills.push((*price, remaining));
-                remaining = Decimal::ZERO;
-            }
+impl<'de> Deserialize<'de> for Symbol {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // Earlier versions serialized the enum, e.g. {"Perp": "BTC"}.
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Name(String),
+            Perp {
+                #[serde(rename = "Perp")]
+                underlying: Asset,
+            },
         }
 
-        // Compute the weighted average
-        let mut weighted_avg = Decimal::ZERO;
-        for (fill_price, fill_quantity) in fills.iter() {
-            weighted_avg += fill_price * fill_quantity;
+        match Repr::deserialize(deserializer)? {
+            Repr::Name(name) => name.parse().map_err(de::Error::custom),
+            Repr::Perp { underlying } => Ok(Symbol::Perp(underlying)),
         }
-
-        Some(weighted_avg / size)
     }
 }
-*/
 
 /*
 pub struct Market {
@@ -186,16 +254,97 @@ pub struct Market {
 }
 */
 
-#[derive(Clone, Copy, Debug)]
+/// The directions positions may be held in, e.g. long only on venues that disallow shorting.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Direction {
+    #[default]
+    Both,
+    LongOnly,
+    ShortOnly,
+}
+
+impl Direction {
+    /// Returns true if a size, negative for shorts, may be held.
+    pub fn allows(&self, size: Decimal) -> bool {
+        match self {
+            Direction::Both => true,
+            Direction::LongOnly => size >= Decimal::ZERO,
+            Direction::ShortOnly => size <= Decimal::ZERO,
+        }
+    }
+}
+
+/// How positions in the same symbol are held at the venue.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AccountMode {
+    /// The venue holds one net position per symbol, so all positions in a symbol must hold it
+    /// in the same direction and their orders are netted into one order per symbol.
+    #[default]
+    Netting,
+    /// The venue holds a long and a short leg per symbol independently. Positions may hold
+    /// opposing sizes in a symbol, their orders are netted per leg and margined per leg.
+    Hedged,
+}
+
+/// The price positions are valued at. Sources the API does not provide fall back to the close.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PriceSource {
+    /// The close of the candle.
+    #[default]
+    Close,
+    /// The mark price of the venue, which it also uses for liquidations.
+    Mark,
+    /// The index price, the spot price of the underlying over several venues.
+    Index,
+    /// The middle between the best bid and ask of the order book.
+    Mid,
+}
+
+/// The kind of contract a market trades.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MarketType {
+    /// A future without expiry, kept close to the index by funding payments.
+    #[default]
+    Perpetual,
+    /// A future that settles at its expiry.
+    Future,
+    /// The asset itself, without leverage or funding, it can only be held long.
+    Spot,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct MarketInfo {
     pub symbol: Symbol,
     pub min_size: Decimal,
     pub size_increment: Decimal,
     pub price_increment: Decimal,
     pub daily_quote_volume: Decimal,
+    #[serde(default)]
+    pub market_type: MarketType,
+    /// Units of the underlying one contract is worth. Sizes are in contracts and prices
+    /// per unit of the underlying, so positions are valued at the price times the multiplier.
+    #[serde(default = "one")]
+    pub contract_multiplier: Decimal,
+    /// When the contract settles, None for perpetuals.
+    #[serde(default)]
+    pub expiry: Option<DateTime<Utc>>,
+}
+
+fn one() -> Decimal {
+    Decimal::ONE
 }
 
 impl MarketInfo {
+    /// The value of one contract at a price.
+    pub fn contract_value(&self, price: Decimal) -> Decimal {
+        price * self.contract_multiplier
+    }
+
+    /// The change of the value of one contract when the price moves by one increment.
+    pub fn tick_value(&self) -> Decimal {
+        self.contract_value(self.price_increment)
+    }
+
     pub fn round_size(&self, size: Decimal) -> Decimal {
         let increment = self.size_increment;
         if increment.is_zero() {
@@ -205,6 +354,15 @@ impl MarketInfo {
         }
     }
 
+    /// The decimals of sizes, None without a size increment.
+    pub fn size_decimals(&self) -> Option<u32> {
+        if self.size_increment.is_zero() {
+            None
+        } else {
+            Some(self.size_increment.normalize().scale())
+        }
+    }
+
     pub fn round_price(&self, price: Decimal) -> Decimal {
         let increment = self.price_increment;
         if increment.is_zero() {
@@ -213,4 +371,51 @@ impl MarketInfo {
             (price / increment).round() * increment
         }
     }
+
+    // A perpetual without size or price constraints.
+    #[cfg(test)]
+    pub(crate) fn unconstrained(symbol: Symbol) -> Self {
+        MarketInfo {
+            symbol,
+            min_size: Decimal::ZERO,
+            size_increment: Decimal::ZERO,
+            price_increment: Decimal::ZERO,
+            daily_quote_volume: Decimal::ZERO,
+            market_type: MarketType::Perpetual,
+            contract_multiplier: Decimal::ONE,
+            expiry: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn future_names() {
+        let expiry = NaiveDate::from_ymd_opt(2024, 3, 29).unwrap();
+        let future = Symbol::future("BTC", expiry);
+        assert_eq!(future.to_string(), "BTC-20240329");
+        assert_eq!("BTC-20240329".parse::<Symbol>().unwrap(), future);
+        assert!("BTC-20241329".parse::<Symbol>().is_err());
+        assert_eq!(future.expiry(), Some(expiry));
+        assert_eq!(Symbol::perp("BTC").expiry(), None);
+
+        let routed = future.on("binance");
+        assert_eq!(routed.to_string(), "binance:BTC-20240329");
+        assert_eq!(routed.venue(), Some("binance"));
+        assert_eq!(routed.local(), future);
+        assert_eq!(routed.underlying(), Asset::new("BTC"));
+    }
+
+    #[test]
+    fn spot_names() {
+        let spot = Symbol::spot("ETH");
+        assert_eq!(spot.to_string(), "ETH-SPOT");
+        assert_eq!("ETH-SPOT".parse::<Symbol>().unwrap(), spot);
+        assert_eq!(spot.expiry(), None);
+        assert_eq!(spot.on("coinbase").local(), spot);
+        assert_eq!(spot.on("coinbase").to_string(), "coinbase:ETH-SPOT");
+    }
 }
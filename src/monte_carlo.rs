@@ -0,0 +1,230 @@
+use crate::Report;
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rust_decimal::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// How the closed positions of a report are reordered in each Monte Carlo run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Resample {
+    /// Draw as many positions as were closed, with replacement.
+    #[default]
+    Bootstrap,
+    /// Shuffle the closed positions, which only changes the path of the equity.
+    Shuffle,
+}
+
+/// Settings of a Monte Carlo analysis, see `Report::monte_carlo`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MonteCarlo {
+    pub resample: Resample,
+    /// The maximum slippage of entries relative to their value, e.g. 0.001 for 10 basis points.
+    /// The slippage of every position is drawn uniformly between zero and the maximum.
+    pub slippage: Decimal,
+    /// Seeds the random number generator, so analyses can be repeated. Random if None.
+    pub seed: Option<u64>,
+}
+
+impl Default for MonteCarlo {
+    fn default() -> Self {
+        MonteCarlo {
+            resample: Resample::default(),
+            slippage: Decimal::ZERO,
+            seed: None,
+        }
+    }
+}
+
+impl MonteCarlo {
+    pub(crate) fn run(&self, report: &Report, n: usize) -> MonteCarloReport {
+        let mut rng = match self.seed {
+            Some(seed) => ChaCha8Rng::seed_from_u64(seed),
+            None => ChaCha8Rng::from_entropy(),
+        };
+        let trades: Vec<(Decimal, Decimal)> = report
+            .closed_positions
+            .iter()
+            .map(|position| (position.pnl, position.entry_value))
+            .collect();
+        // Profit and loss that is not attributed to closed positions, e.g. of positions
+        // still open at the end, is added after the resampled positions.
+        let rest = report.end_total
+            - report.start_total
+            - trades.iter().map(|(pnl, _)| pnl).sum::<Decimal>();
+
+        let mut final_equity = Vec::with_capacity(n);
+        let mut max_drawdown = Vec::with_capacity(n);
+        for _ in 0..n {
+            let sample: Vec<(Decimal, Decimal)> = match self.resample {
+                Resample::Bootstrap if trades.is_empty() => Vec::new(),
+                Resample::Bootstrap => (0..trades.len())
+                    .map(|_| trades[rng.gen_range(0..trades.len())])
+                    .collect(),
+                Resample::Shuffle => {
+                    let mut sample = trades.clone();
+                    sample.shuffle(&mut rng);
+                    sample
+                }
+            };
+
+            let mut equity = report.start_total;
+            let mut high = equity;
+            let mut drawdown = Decimal::ZERO;
+            let pnls = sample
+                .into_iter()
+                .map(|(pnl, entry_value)| pnl - entry_value * self.draw_slippage(&mut rng))
+                .chain(std::iter::once(rest));
+            for pnl in pnls {
+                equity += pnl;
+                high = high.max(equity);
+                if high > Decimal::ZERO {
+                    drawdown = drawdown.max((high - equity) / high);
+                }
+            }
+            final_equity.push(equity);
+            max_drawdown.push(drawdown);
+        }
+
+        MonteCarloReport {
+            final_equity: Distribution::new(final_equity),
+            max_drawdown: Distribution::new(max_drawdown),
+        }
+    }
+
+    fn draw_slippage(&self, rng: &mut ChaCha8Rng) -> Decimal {
+        if self.slippage.is_zero() {
+            Decimal::ZERO
+        } else {
+            self.slippage * Decimal::from_f64(rng.gen::<f64>()).unwrap_or_default()
+        }
+    }
+}
+
+/// The distributions of the results of a Monte Carlo analysis, one sample per run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MonteCarloReport {
+    /// The total at the end, in the quote asset.
+    pub final_equity: Distribution,
+    /// The largest drop of the total relative to its previous high.
+    pub max_drawdown: Distribution,
+}
+
+/// Samples of a value, in increasing order.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Distribution {
+    samples: Vec<Decimal>,
+}
+
+impl Distribution {
+    fn new(mut samples: Vec<Decimal>) -> Self {
+        samples.sort();
+        Distribution { samples }
+    }
+
+    pub fn samples(&self) -> &[Decimal] {
+        &self.samples
+    }
+
+    pub fn mean(&self) -> Option<Decimal> {
+        (!self.samples.is_empty())
+            .then(|| self.samples.iter().sum::<Decimal>() / Decimal::from(self.samples.len()))
+    }
+
+    /// The smallest sample that is at least as large as the fraction of samples,
+    /// e.g. the median for 0.5. None if there are no samples.
+    pub fn percentile(&self, fraction: Decimal) -> Option<Decimal> {
+        let rank = (fraction * Decimal::from(self.samples.len()))
+            .ceil()
+            .to_usize()
+            .unwrap_or(0);
+        let last = self.samples.len().checked_sub(1)?;
+        Some(self.samples[rank.saturating_sub(1).min(last)])
+    }
+
+    /// The central interval containing the fraction of samples,
+    /// e.g. the 5th and 95th percentile for 0.9. None if there are no samples.
+    pub fn interval(&self, confidence: Decimal) -> Option<(Decimal, Decimal)> {
+        let tail = (Decimal::ONE - confidence) / Decimal::TWO;
+        Some((
+            self.percentile(tail)?,
+            self.percentile(Decimal::ONE - tail)?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ClosedPosition;
+    use chrono::{TimeZone, Utc};
+    use rust_decimal_macros::dec;
+    use uuid::Uuid;
+
+    fn report(pnls: &[Decimal]) -> Report {
+        let time = Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap();
+        let mut report = Report::new(time, dec!(1000));
+        let mut closed: Vec<ClosedPosition> = pnls
+            .iter()
+            .map(|&pnl| ClosedPosition {
+                id: Uuid::new_v4(),
+                symbols: Vec::new(),
+                open_time: None,
+                close_time: None,
+                pnl,
+                mae: Decimal::ZERO,
+                mfe: Decimal::ZERO,
+                tags: Default::default(),
+                entry_value: dec!(100),
+            })
+            .collect();
+        report.update_positions(&mut closed, &[]);
+        // Another 5 from positions still open.
+        report.update(time, dec!(1005) + pnls.iter().sum::<Decimal>());
+        report
+    }
+
+    #[test]
+    fn shuffle_keeps_final_equity() {
+        let report = report(&[dec!(50), dec!(-100), dec!(20), dec!(-30), dec!(60)]);
+        let config = MonteCarlo {
+            resample: Resample::Shuffle,
+            seed: Some(1),
+            ..Default::default()
+        };
+        let analysis = report.monte_carlo(200, &config);
+
+        assert_eq!(analysis.final_equity.samples().len(), 200);
+        assert_eq!(analysis.final_equity.percentile(dec!(0)), Some(dec!(1005)));
+        assert_eq!(analysis.final_equity.percentile(dec!(1)), Some(dec!(1005)));
+        // The worst path loses 130 from the start,
+        // the best one loses 30 first and 100 from a high of 1100.
+        assert_eq!(analysis.max_drawdown.percentile(dec!(1)), Some(dec!(0.13)));
+        assert_eq!(
+            analysis.max_drawdown.percentile(dec!(0)),
+            Some(dec!(100) / dec!(1100))
+        );
+        assert_eq!(report.monte_carlo(200, &config), analysis);
+    }
+
+    #[test]
+    fn bootstrap_with_slippage() {
+        let report = report(&[dec!(50), dec!(-10)]);
+        let config = MonteCarlo {
+            slippage: dec!(0.01),
+            seed: Some(1),
+            ..Default::default()
+        };
+        let analysis = report.monte_carlo(500, &config);
+
+        // Between two losses and two wins, minus up to one per position for slippage.
+        let (low, high) = analysis.final_equity.interval(dec!(1)).unwrap();
+        assert!(low >= dec!(983) && low < dec!(985));
+        assert!(high > dec!(1103) && high <= dec!(1105));
+        let (low, high) = analysis.final_equity.interval(dec!(0.5)).unwrap();
+        assert!(low < high);
+        let mean = analysis.final_equity.mean().unwrap();
+        assert!(mean > dec!(1030) && mean < dec!(1050));
+
+        assert_eq!(report.monte_carlo(0, &config).max_drawdown.mean(), None);
+    }
+}
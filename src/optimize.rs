@@ -0,0 +1,396 @@
+use crate::{
+    apis::{Api, Simulate},
+    strategies::Strategy,
+    AnyError, Bazaar, Exchange, Objective, Report,
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Backtests a strategy with every combination of parameter values.
+pub struct GridSearch {
+    /// The names of the parameters with the values to try, in increasing order.
+    pub parameters: Vec<(String, Vec<Decimal>)>,
+    /// The end of the backtests.
+    pub end_time: DateTime<Utc>,
+    /// What the parameters are optimized for, the total return by default.
+    pub objective: Objective,
+}
+
+impl Default for GridSearch {
+    fn default() -> Self {
+        GridSearch {
+            parameters: Vec::new(),
+            end_time: Utc::now(),
+            objective: Objective::default(),
+        }
+    }
+}
+
+impl GridSearch {
+    pub fn with_parameter(mut self, name: impl Into<String>, values: Vec<Decimal>) -> Self {
+        self.parameters.push((name.into(), values));
+        self
+    }
+
+    pub fn with_objective(mut self, objective: Objective) -> Self {
+        self.objective = objective;
+        self
+    }
+
+    /// Run one backtest per combination, from the start time and capital of the Bazaar.
+    /// The strategy is created from the parameter values, in the order of the parameters.
+    pub async fn run<A, S>(
+        &self,
+        bazaar: &Bazaar,
+        api: impl Fn() -> A,
+        strategy: impl Fn(&[Decimal]) -> S,
+    ) -> Result<Optimization, AnyError>
+    where
+        A: Api,
+        S: Strategy<Simulate<A>>,
+    {
+        let grid = self.grid();
+        let mut trials = Vec::with_capacity(grid.len());
+        for (i, values) in grid.into_iter().enumerate() {
+            tracing::info!("Backtesting combination {}: {:?}.", i + 1, values);
+
+            let report = Exchange::new(bazaar.simulate(api()), bazaar.start_time)
+                .until(self.end_time)
+                .run(strategy(&values))
+                .await?;
+
+            trials.push(Trial::new(values, report, &self.objective));
+        }
+
+        Ok(Optimization {
+            parameters: self.parameters.clone(),
+            objective: self.objective.clone(),
+            trials,
+        })
+    }
+
+    // All combinations of parameter values.
+    fn grid(&self) -> Vec<Vec<Decimal>> {
+        self.parameters
+            .iter()
+            .fold(vec![Vec::new()], |grid, (_, values)| {
+                grid.into_iter()
+                    .flat_map(|point| {
+                        values.iter().map(move |&value| {
+                            let mut point = point.clone();
+                            point.push(value);
+                            point
+                        })
+                    })
+                    .collect()
+            })
+    }
+}
+
+/// A backtest of the grid search.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Trial {
+    /// The parameter values, in the order of the parameters.
+    pub values: Vec<Decimal>,
+    pub report: Report,
+    /// The score under the objective, None if the report violates one of its bounds.
+    pub score: Option<Decimal>,
+}
+
+impl Trial {
+    pub fn new(values: Vec<Decimal>, report: Report, objective: &Objective) -> Self {
+        Trial {
+            values,
+            score: report.score(objective),
+            report,
+        }
+    }
+}
+
+/// The backtests of a grid search.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Optimization {
+    pub parameters: Vec<(String, Vec<Decimal>)>,
+    pub objective: Objective,
+    pub trials: Vec<Trial>,
+}
+
+impl Optimization {
+    /// The trial with the highest score, among those satisfying the bounds of the objective.
+    pub fn best(&self) -> Option<&Trial> {
+        self.trials
+            .iter()
+            .filter(|trial| trial.score.is_some())
+            .max_by_key(|trial| trial.score)
+    }
+
+    /// How the score depends on the parameters, to tell robust optima from lucky ones.
+    pub fn sensitivity(&self) -> Sensitivity {
+        let marginals = self
+            .parameters
+            .iter()
+            .enumerate()
+            .map(|(i, (parameter, values))| Marginal {
+                parameter: parameter.clone(),
+                points: values
+                    .iter()
+                    .filter_map(|&value| {
+                        let scores: Vec<_> = self
+                            .trials
+                            .iter()
+                            .filter(|trial| trial.values[i] == value)
+                            .filter_map(|trial| trial.score)
+                            .collect();
+                        Some(MarginalPoint {
+                            value,
+                            mean: mean(&scores)?,
+                            best: scores.iter().max().copied()?,
+                        })
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        let mut heatmaps = Vec::new();
+        for (i, (x, x_values)) in self.parameters.iter().enumerate() {
+            for (j, (y, y_values)) in self.parameters.iter().enumerate().skip(i + 1) {
+                let scores = y_values
+                    .iter()
+                    .map(|&y_value| {
+                        x_values
+                            .iter()
+                            .map(|&x_value| {
+                                self.trials
+                                    .iter()
+                                    .filter(|trial| {
+                                        trial.values[i] == x_value && trial.values[j] == y_value
+                                    })
+                                    .filter_map(|trial| trial.score)
+                                    .max()
+                            })
+                            .collect()
+                    })
+                    .collect();
+                heatmaps.push(Heatmap {
+                    x: x.clone(),
+                    y: y.clone(),
+                    x_values: x_values.clone(),
+                    y_values: y_values.clone(),
+                    scores,
+                });
+            }
+        }
+
+        Sensitivity {
+            marginals,
+            heatmaps,
+            stability: self.stability(),
+        }
+    }
+
+    // The scores of the combinations next to the best one.
+    fn stability(&self) -> Option<Stability> {
+        let best = self.best()?;
+        let index = |trial: &Trial| -> Vec<Option<usize>> {
+            self.parameters
+                .iter()
+                .zip(&trial.values)
+                .map(|((_, values), value)| values.iter().position(|v| v == value))
+                .collect()
+        };
+        let best_index = index(best);
+        let scores: Vec<_> = self
+            .trials
+            .iter()
+            .filter(|trial| {
+                let trial_index = index(trial);
+                trial_index != best_index
+                    && trial_index
+                        .iter()
+                        .zip(&best_index)
+                        .all(|(a, b)| match (a, b) {
+                            (Some(a), Some(b)) => a.abs_diff(*b) <= 1,
+                            _ => false,
+                        })
+            })
+            .filter_map(|trial| trial.score)
+            .collect();
+
+        let neighborhood_mean = mean(&scores);
+        let neighborhood_std_dev = neighborhood_mean.and_then(|mean| {
+            let variance = scores
+                .iter()
+                .map(|score| ((score - mean) * (score - mean)).to_f64().unwrap_or(0.0))
+                .sum::<f64>()
+                / scores.len() as f64;
+            Decimal::from_f64(variance.sqrt())
+        });
+
+        Some(Stability {
+            optimum: best.values.clone(),
+            score: best.score?,
+            neighbors: scores.len(),
+            neighborhood_mean,
+            neighborhood_std_dev,
+        })
+    }
+}
+
+fn mean(scores: &[Decimal]) -> Option<Decimal> {
+    if scores.is_empty() {
+        None
+    } else {
+        Some(scores.iter().sum::<Decimal>() / Decimal::from(scores.len()))
+    }
+}
+
+/// Sensitivity of the score to the parameters of a grid search.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sensitivity {
+    /// One performance curve per parameter.
+    pub marginals: Vec<Marginal>,
+    /// One heatmap per pair of parameters.
+    pub heatmaps: Vec<Heatmap>,
+    /// The performance around the optimum, None without trials.
+    pub stability: Option<Stability>,
+}
+
+/// The score at every value of a parameter, over all values of the other parameters.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Marginal {
+    pub parameter: String,
+    pub points: Vec<MarginalPoint>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MarginalPoint {
+    pub value: Decimal,
+    pub mean: Decimal,
+    pub best: Decimal,
+}
+
+/// The best score at every pair of values of two parameters.
+/// Trials violating the bounds of the objective are left out, like in the marginals.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Heatmap {
+    pub x: String,
+    pub y: String,
+    pub x_values: Vec<Decimal>,
+    pub y_values: Vec<Decimal>,
+    /// The scores by y value, then by x value. None if no feasible trial has the pair of values.
+    pub scores: Vec<Vec<Option<Decimal>>>,
+}
+
+/// The scores of the combinations next to the optimum, i.e. at most one grid step
+/// away in every parameter. A fragile optimum performs much better than its neighborhood.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Stability {
+    /// The parameter values of the optimum.
+    pub optimum: Vec<Decimal>,
+    pub score: Decimal,
+    /// The number of combinations in the neighborhood.
+    pub neighbors: usize,
+    pub neighborhood_mean: Option<Decimal>,
+    pub neighborhood_std_dev: Option<Decimal>,
+}
+
+impl Stability {
+    /// How much worse the neighborhood performs than the optimum, relative to its score,
+    /// e.g. 0.8 if the neighborhood keeps only a fifth of the score.
+    pub fn relative_drop(&self) -> Option<Decimal> {
+        let mean = self.neighborhood_mean?;
+        if self.score.is_zero() {
+            None
+        } else {
+            Some((self.score - mean) / self.score.abs())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Metric;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn sensitivity_around_optimum() {
+        let time = Utc::now();
+        let search = GridSearch::default()
+            .with_parameter("fast", vec![dec!(5), dec!(10), dec!(20)])
+            .with_parameter("slow", vec![dec!(50), dec!(100)]);
+        let grid = search.grid();
+        assert_eq!(grid.len(), 6);
+        assert_eq!(grid[1], vec![dec!(5), dec!(100)]);
+
+        // A spike at fast = 10 and slow = 50.
+        let returns = [
+            dec!(0.01),
+            dec!(0.02),
+            dec!(0.5),
+            dec!(0.03),
+            dec!(0.02),
+            dec!(0.01),
+        ];
+        let trials = grid
+            .into_iter()
+            .zip(returns)
+            .map(|(values, ret)| {
+                let mut report = Report::new(time, dec!(100));
+                report.update(time, dec!(100) * (Decimal::ONE + ret));
+                Trial::new(values, report, &search.objective)
+            })
+            .collect();
+        let optimization = Optimization {
+            parameters: search.parameters.clone(),
+            objective: search.objective.clone(),
+            trials,
+        };
+
+        assert_eq!(
+            optimization.best().unwrap().values,
+            vec![dec!(10), dec!(50)]
+        );
+
+        let sensitivity = optimization.sensitivity();
+        assert_eq!(
+            sensitivity.marginals[0].points[1],
+            MarginalPoint {
+                value: dec!(10),
+                mean: dec!(0.265),
+                best: dec!(0.5),
+            }
+        );
+        assert_eq!(sensitivity.heatmaps.len(), 1);
+        assert_eq!(
+            sensitivity.heatmaps[0].scores,
+            vec![
+                vec![Some(dec!(0.01)), Some(dec!(0.5)), Some(dec!(0.02))],
+                vec![Some(dec!(0.02)), Some(dec!(0.03)), Some(dec!(0.01))],
+            ]
+        );
+
+        let stability = sensitivity.stability.unwrap();
+        assert_eq!(stability.neighbors, 5);
+        assert_eq!(stability.neighborhood_mean, Some(dec!(0.018)));
+        assert!(stability.relative_drop().unwrap() > dec!(0.9));
+
+        // Trials violating a bound are not considered.
+        let objective = Objective::default().with_max(Metric::TotalReturn, dec!(0.1));
+        let constrained = Optimization {
+            trials: optimization
+                .trials
+                .iter()
+                .map(|trial| Trial::new(trial.values.clone(), trial.report.clone(), &objective))
+                .collect(),
+            objective,
+            ..optimization
+        };
+        assert_eq!(
+            constrained.best().unwrap().values,
+            vec![dec!(10), dec!(100)]
+        );
+        assert_eq!(constrained.sensitivity().heatmaps[0].scores[0][1], None);
+    }
+}
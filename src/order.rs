@@ -12,8 +12,26 @@ pub enum Side {
     Sell,
 }
 
+/// The leg of a symbol an order trades in hedged account mode.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum PositionSide {
+    Long,
+    Short,
+}
+
+impl PositionSide {
+    /// The leg holding a size, negative for shorts.
+    pub fn of(size: Decimal) -> Self {
+        if size < Decimal::ZERO {
+            PositionSide::Short
+        } else {
+            PositionSide::Long
+        }
+    }
+}
+
 /// Defines an order that can be placed in an exchange.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Order {
     pub order_id: Uuid,
     pub market: Symbol,
@@ -21,22 +39,103 @@ pub struct Order {
     pub size: Decimal,
     pub order_type: OrderType,
     pub reduce_only: bool,
+    pub time_in_force: TimeInForce,
+    /// Only place the order if it rests in the book, it is rejected if it would cross.
+    pub post_only: bool,
+    /// The leg the order trades in hedged account mode, None in netting mode.
+    #[serde(default)]
+    pub position_side: Option<PositionSide>,
     pub time: DateTime<Utc>,
     pub current_price: Decimal,
 }
 
-#[derive(PartialEq, Eq, Debug, Clone)]
+impl Order {
+    /// Whether the order would take liquidity from a book with the given best bid and ask.
+    pub fn crosses(&self, bid: Decimal, ask: Decimal) -> bool {
+        match self.order_type {
+            OrderType::Market => true,
+            OrderType::Limit(price) => match self.side {
+                Side::Buy => price >= ask,
+                Side::Sell => price <= bid,
+            },
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
 pub enum OrderType {
     Limit(Decimal),
     Market,
 }
 
-#[derive(Debug, Clone)]
+/// How long an order stays active before it is cancelled.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum TimeInForce {
+    /// The order rests in the book until it is filled or cancelled.
+    GoodTilCancelled,
+    /// The part of the order that can not be filled immediately is cancelled.
+    ImmediateOrCancel,
+    /// The order is cancelled unless it can be filled immediately and completely.
+    FillOrKill,
+}
+
+/// The state of an order in its lifecycle.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum OrderStatus {
+    /// The order was created, but not submitted.
+    Created,
+    /// The order was accepted by the venue, but not filled yet.
+    Submitted,
+    /// The order was filled partially and the rest may still fill.
+    PartiallyFilled,
+    /// The order was filled completely.
+    #[default]
+    Filled,
+    /// The order was cancelled or expired, possibly after filling partially.
+    Cancelled,
+    /// The order was rejected by the venue.
+    Rejected,
+}
+
+impl OrderStatus {
+    /// Whether the fill of the order can not change anymore.
+    pub fn is_final(&self) -> bool {
+        !matches!(self, OrderStatus::Submitted | OrderStatus::PartiallyFilled)
+    }
+
+    /// The status of an order that was settled immediately, the rest of partial fills is cancelled.
+    pub fn settled(ordered: Decimal, filled: Decimal) -> Self {
+        if filled == ordered {
+            OrderStatus::Filled
+        } else {
+            OrderStatus::Cancelled
+        }
+    }
+}
+
+/// The fill of an order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OrderInfo {
     pub order_id: Uuid,
     pub market: Symbol,
+    /// The filled size.
     pub size: Decimal,
+    /// The average fill price.
     pub price: Decimal,
     pub time: DateTime<Utc>,
     pub side: Side,
+    /// Records without a status are of settled fills.
+    #[serde(default)]
+    pub status: OrderStatus,
+}
+
+/// An order that was not settled yet when its fill was booked to the positions,
+/// e.g. because cancelling its rest failed.
+#[derive(Debug, Clone)]
+pub struct PendingOrder {
+    pub order: Order,
+    /// The last known fill and status.
+    pub info: OrderInfo,
+    /// The filled size that was booked to the positions.
+    pub booked: Decimal,
 }
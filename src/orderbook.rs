@@ -0,0 +1,117 @@
+use crate::Side;
+use rust_decimal::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The depth of a market, as the size available at each price level.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Orderbook {
+    pub bids: BTreeMap<Decimal, Decimal>,
+    pub asks: BTreeMap<Decimal, Decimal>,
+}
+
+impl Orderbook {
+    pub fn new() -> Orderbook {
+        Default::default()
+    }
+
+    /// Returns the price of the best bid
+    pub fn bid_price(&self) -> Option<Decimal> {
+        self.bids.keys().next_back().cloned()
+    }
+
+    /// Returns the price of the best ask
+    pub fn ask_price(&self) -> Option<Decimal> {
+        self.asks.keys().next().cloned()
+    }
+
+    /// Returns the midpoint between the best bid price and best ask price.
+    /// Output is not rounded to the smallest price increment.
+    pub fn mid_price(&self) -> Option<Decimal> {
+        Some((self.bid_price()? + self.ask_price()?) / Decimal::TWO)
+    }
+
+    /// Returns the expected execution price of a market order given the current
+    /// orders in the order book. Returns None if the order size exceeds the
+    /// liquidity available on that side of the order book.
+    pub fn execution_price(&self, size: Decimal, side: Side) -> Option<Decimal> {
+        match self.fill(size, side, None) {
+            (filled, price) if filled == size => price,
+            _ => None,
+        }
+    }
+
+    /// Walks the book to fill an order, taking levels up to the limit price if there is one.
+    /// Returns the filled size and its average price, which is None if nothing was filled.
+    pub fn fill(
+        &self,
+        size: Decimal,
+        side: Side,
+        limit: Option<Decimal>,
+    ) -> (Decimal, Option<Decimal>) {
+        let levels: Box<dyn Iterator<Item = (&Decimal, &Decimal)>> = match side {
+            Side::Buy => Box::new(self.asks.iter()),
+            Side::Sell => Box::new(self.bids.iter().rev()),
+        };
+
+        let mut remaining = size;
+        let mut cost = Decimal::ZERO;
+        for (&price, &quantity) in levels {
+            if remaining <= Decimal::ZERO {
+                break;
+            }
+            let within_limit = match (limit, side) {
+                (None, _) => true,
+                (Some(limit), Side::Buy) => price <= limit,
+                (Some(limit), Side::Sell) => price >= limit,
+            };
+            if !within_limit {
+                break;
+            }
+            let quantity = quantity.min(remaining);
+            remaining -= quantity;
+            cost += price * quantity;
+        }
+
+        let filled = size - remaining;
+        if filled.is_zero() {
+            (filled, None)
+        } else {
+            (filled, Some(cost / filled))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn book() -> Orderbook {
+        Orderbook {
+            bids: [(dec!(99), dec!(1)), (dec!(98), dec!(2))].into(),
+            asks: [(dec!(101), dec!(1)), (dec!(102), dec!(2))].into(),
+        }
+    }
+
+    #[test]
+    fn walk_depth() {
+        let book = book();
+
+        assert_eq!(book.mid_price(), Some(dec!(100)));
+        assert_eq!(book.execution_price(dec!(2), Side::Buy), Some(dec!(101.5)));
+        assert_eq!(book.execution_price(dec!(4), Side::Buy), None);
+        assert_eq!(
+            book.fill(dec!(4), Side::Sell, None),
+            (dec!(3), Some(dec!(295) / dec!(3)))
+        );
+        assert_eq!(
+            book.fill(dec!(2), Side::Buy, Some(dec!(101))),
+            (dec!(1), Some(dec!(101)))
+        );
+        assert_eq!(
+            book.fill(dec!(2), Side::Sell, Some(dec!(100))),
+            (dec!(0), None)
+        );
+    }
+}
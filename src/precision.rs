@@ -0,0 +1,160 @@
+use crate::{Asset, Markets, Symbol};
+use rust_decimal::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Decimals of computed values without a precision of their own, e.g. simulated fill prices.
+pub const DEFAULT_DECIMALS: u32 = 8;
+
+/// How quantities of assets are rounded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrecisionPolicy {
+    /// Decimals of the quote asset, the wallet is settled with this precision.
+    pub quote_decimals: u32,
+    /// Decimals of assets whose markets have no size increment.
+    pub default_decimals: u32,
+    pub strategy: RoundingStrategy,
+}
+
+impl Default for PrecisionPolicy {
+    fn default() -> Self {
+        PrecisionPolicy {
+            quote_decimals: DEFAULT_DECIMALS,
+            default_decimals: DEFAULT_DECIMALS,
+            strategy: RoundingStrategy::MidpointNearestEven,
+        }
+    }
+}
+
+impl PrecisionPolicy {
+    /// The decimals of an asset, from the size increment of its perpetual if it has one.
+    pub fn decimals(&self, asset: Asset, quote: Asset, markets: &Markets) -> u32 {
+        if asset == quote {
+            return self.quote_decimals;
+        }
+        markets
+            .market(Symbol::Perp(asset))
+            .and_then(|info| info.size_decimals())
+            .unwrap_or(self.default_decimals)
+    }
+
+    /// Round a quantity to the decimals.
+    pub fn round(&self, qty: Decimal, decimals: u32) -> Decimal {
+        qty.round_dp_with_strategy(decimals, self.strategy)
+    }
+}
+
+/// Where values were rounded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum RoundingSite {
+    /// Order sizes fit to the size increments and minimum sizes of the markets.
+    Fit,
+    /// The value of fills settled in the wallet.
+    Wallet,
+}
+
+/// The rounding residuals accumulated at a site, in the quote asset.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Residual {
+    /// Number of roundings that changed a value.
+    pub count: u64,
+    /// Sum of the rounded minus the exact values.
+    pub net: Decimal,
+    /// Sum of the absolute differences.
+    pub gross: Decimal,
+}
+
+/// An audit trail of the roundings, to catch PnL drifting from the wallet.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Residuals(BTreeMap<RoundingSite, Residual>);
+
+impl Residuals {
+    /// The residual of a site.
+    pub fn site(&self, site: RoundingSite) -> Residual {
+        self.0.get(&site).copied().unwrap_or_default()
+    }
+
+    pub fn sites(&self) -> impl Iterator<Item = (&RoundingSite, &Residual)> {
+        self.0.iter()
+    }
+
+    /// The net residual of all sites.
+    pub fn net(&self) -> Decimal {
+        self.0.values().map(|residual| residual.net).sum()
+    }
+
+    pub(crate) fn record(&mut self, site: RoundingSite, difference: Decimal) {
+        if difference.is_zero() {
+            return;
+        }
+        let residual = self.0.entry(site).or_default();
+        residual.count += 1;
+        residual.net += difference;
+        residual.gross += difference.abs();
+    }
+
+    pub(crate) fn append(&mut self, other: &mut Residuals) {
+        for (site, residual) in std::mem::take(&mut other.0) {
+            let total = self.0.entry(site).or_default();
+            total.count += residual.count;
+            total.net += residual.net;
+            total.gross += residual.gross;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MarketInfo, MarketType};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn decimals_from_markets() {
+        let (usd, btc, eth) = (Asset::new("USD"), Asset::new("BTC"), Asset::new("ETH"));
+        let mut markets = Markets::default();
+        markets.markets.insert(
+            Symbol::Perp(btc),
+            MarketInfo {
+                symbol: Symbol::Perp(btc),
+                min_size: dec!(0.001),
+                size_increment: dec!(0.0010),
+                price_increment: dec!(0.5),
+                daily_quote_volume: Decimal::ZERO,
+                market_type: MarketType::Perpetual,
+                contract_multiplier: Decimal::ONE,
+                expiry: None,
+            },
+        );
+        let policy = PrecisionPolicy {
+            quote_decimals: 2,
+            ..Default::default()
+        };
+        assert_eq!(policy.decimals(btc, usd, &markets), 3);
+        assert_eq!(policy.decimals(eth, usd, &markets), DEFAULT_DECIMALS);
+        assert_eq!(policy.decimals(usd, usd, &markets), 2);
+        assert_eq!(policy.round(dec!(1.005), 2), dec!(1.00));
+    }
+
+    #[test]
+    fn accumulate_residuals() {
+        let mut residuals = Residuals::default();
+        residuals.record(RoundingSite::Wallet, dec!(0.01));
+        residuals.record(RoundingSite::Wallet, dec!(-0.03));
+        residuals.record(RoundingSite::Wallet, dec!(0));
+        let mut more = Residuals::default();
+        more.record(RoundingSite::Fit, dec!(0.5));
+        residuals.append(&mut more);
+
+        assert_eq!(
+            residuals.site(RoundingSite::Wallet),
+            Residual {
+                count: 2,
+                net: dec!(-0.02),
+                gross: dec!(0.04),
+            }
+        );
+        assert_eq!(residuals.net(), dec!(0.48));
+        assert_eq!(more, Residuals::default());
+    }
+}
@@ -0,0 +1,441 @@
+use crate::{
+    ComplianceRecord, ExecutionDecision, MonteCarlo, MonteCarloReport, Position, Residuals, Symbol,
+    WalletDiscrepancy,
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::prelude::*;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Summary of a strategy run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Report {
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    /// The total at the start, in the quote asset.
+    pub start_total: Decimal,
+    /// The total at the end, in the quote asset.
+    pub end_total: Decimal,
+    /// The highest total reached.
+    pub max_total: Decimal,
+    /// The largest drop of the total relative to its previous high.
+    pub max_drawdown: Decimal,
+    /// The number of evaluated intervals.
+    pub steps: u64,
+    /// The positions closed during the run, in the order they were closed.
+    #[serde(default)]
+    pub closed_positions: Vec<ClosedPosition>,
+    /// The orders that were modified or vetoed for compliance.
+    #[serde(default)]
+    pub compliance: Vec<ComplianceRecord>,
+    /// The execution tactics chosen for orders with adaptive execution.
+    #[serde(default)]
+    pub execution: Vec<ExecutionDecision>,
+    /// The quote value of all fills.
+    #[serde(default)]
+    pub traded_value: Decimal,
+    /// The interest paid for borrowing to short.
+    #[serde(default)]
+    pub borrow_costs: Decimal,
+    /// The residuals of rounding order sizes and wallet settlements.
+    #[serde(default)]
+    pub rounding: Residuals,
+    /// The profit and loss locked in by closed positions and reductions of open positions.
+    #[serde(default)]
+    pub realized_pnl: Decimal,
+    /// The profit and loss of the positions still open at the end.
+    #[serde(default)]
+    pub unrealized_pnl: Decimal,
+    /// The values of the custom series logged by the strategy, in the order they were logged.
+    #[serde(default)]
+    pub metrics: Vec<CustomMetric>,
+    /// The balances that differed between the local wallet and the wallet of the venue.
+    #[serde(default)]
+    pub wallet_discrepancies: Vec<WalletDiscrepancy>,
+    /// The serialized parameters of the strategy, see `Strategy::parameters`.
+    #[serde(default)]
+    pub parameters: Option<Value>,
+    // The profit and loss of the closed positions.
+    #[serde(default)]
+    closed_pnl: Decimal,
+    // Sums of the returns and squared returns of the steps, for the Sharpe ratio.
+    #[serde(default)]
+    return_sum: Decimal,
+    #[serde(default)]
+    return_square_sum: Decimal,
+}
+
+/// A position after it was closed, with the excursions of its profit and loss while it was open.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClosedPosition {
+    pub id: Uuid,
+    pub symbols: Vec<Symbol>,
+    pub open_time: Option<DateTime<Utc>>,
+    pub close_time: Option<DateTime<Utc>>,
+    pub pnl: Decimal,
+    /// Maximum adverse excursion, see `Position::mae`.
+    pub mae: Decimal,
+    /// Maximum favorable excursion, see `Position::mfe`.
+    pub mfe: Decimal,
+    pub tags: HashMap<String, String>,
+    /// The value of the fills that opened or increased the position, see `Position::entry_value`.
+    #[serde(default)]
+    pub entry_value: Decimal,
+}
+
+/// A value of a custom series logged by the strategy with `Exchange::metric`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CustomMetric {
+    pub name: String,
+    pub time: DateTime<Utc>,
+    pub value: Decimal,
+}
+
+impl From<&Position> for ClosedPosition {
+    fn from(position: &Position) -> Self {
+        ClosedPosition {
+            id: position.id(),
+            symbols: position.symbols().collect(),
+            open_time: position.open.as_ref().and_then(|open| open.time),
+            close_time: position.close.as_ref().and_then(|close| close.time),
+            pnl: position.pnl(),
+            mae: position.mae(),
+            mfe: position.mfe(),
+            tags: position.tags().clone(),
+            entry_value: position.entry_value(),
+        }
+    }
+}
+
+impl Report {
+    pub(crate) fn new(time: DateTime<Utc>, total: Decimal) -> Self {
+        Report {
+            start_time: time,
+            end_time: time,
+            start_total: total,
+            end_total: total,
+            max_total: total,
+            max_drawdown: Decimal::ZERO,
+            steps: 0,
+            closed_positions: Vec::new(),
+            compliance: Vec::new(),
+            execution: Vec::new(),
+            traded_value: Decimal::ZERO,
+            borrow_costs: Decimal::ZERO,
+            rounding: Residuals::default(),
+            realized_pnl: Decimal::ZERO,
+            unrealized_pnl: Decimal::ZERO,
+            metrics: Vec::new(),
+            wallet_discrepancies: Vec::new(),
+            parameters: None,
+            closed_pnl: Decimal::ZERO,
+            return_sum: Decimal::ZERO,
+            return_square_sum: Decimal::ZERO,
+        }
+    }
+
+    /// The parameters the strategy ran with, e.g. to run it again with the same parameters.
+    /// Returns None if the strategy did not provide parameters.
+    pub fn parameters<P: DeserializeOwned>(&self) -> Result<Option<P>, serde_json::Error> {
+        self.parameters
+            .clone()
+            .map(serde_json::from_value)
+            .transpose()
+    }
+
+    pub(crate) fn update(&mut self, time: DateTime<Utc>, total: Decimal) {
+        if self.end_total > Decimal::ZERO {
+            let step_return = total / self.end_total - Decimal::ONE;
+            self.return_sum += step_return;
+            self.return_square_sum += step_return * step_return;
+        }
+        self.end_time = time;
+        self.end_total = total;
+        self.max_total = self.max_total.max(total);
+        if self.max_total > Decimal::ZERO {
+            let drawdown = (self.max_total - total) / self.max_total;
+            self.max_drawdown = self.max_drawdown.max(drawdown);
+        }
+        self.steps += 1;
+    }
+
+    // Add the positions closed since the last update and the profit and loss of the open ones.
+    pub(crate) fn update_positions(&mut self, closed: &mut Vec<ClosedPosition>, open: &[Position]) {
+        self.closed_pnl += closed.iter().map(|position| position.pnl).sum::<Decimal>();
+        self.closed_positions.append(closed);
+        self.realized_pnl = self.closed_pnl
+            + open
+                .iter()
+                .map(|position| position.realized_pnl())
+                .sum::<Decimal>();
+        self.unrealized_pnl = open.iter().map(|position| position.unrealized_pnl()).sum();
+    }
+
+    /// The total return relative to the start total, e.g. 0.1 for 10% profit.
+    pub fn total_return(&self) -> Decimal {
+        if self.start_total == Decimal::ZERO {
+            Decimal::ZERO
+        } else {
+            self.end_total / self.start_total - Decimal::ONE
+        }
+    }
+
+    /// The annualized Sharpe ratio of the step returns, without a risk free rate.
+    /// Zero if the returns did not vary or the run was too short.
+    pub fn sharpe(&self) -> Decimal {
+        let years = (self.end_time - self.start_time).num_seconds() as f64 / (365.25 * 86400.0);
+        if self.steps < 2 || years <= 0.0 {
+            return Decimal::ZERO;
+        }
+        let steps = Decimal::from(self.steps);
+        let mean = self.return_sum / steps;
+        let variance = (self.return_square_sum / steps - mean * mean)
+            .to_f64()
+            .unwrap_or(0.0);
+        if variance <= 0.0 {
+            return Decimal::ZERO;
+        }
+        let steps_per_year = self.steps as f64 / years;
+        Decimal::from_f64(mean.to_f64().unwrap_or(0.0) / variance.sqrt() * steps_per_year.sqrt())
+            .unwrap_or_default()
+    }
+
+    /// The traded value relative to the start total, e.g. 2 if twice the capital was traded.
+    pub fn turnover(&self) -> Decimal {
+        if self.start_total == Decimal::ZERO {
+            Decimal::ZERO
+        } else {
+            self.traded_value / self.start_total
+        }
+    }
+
+    /// Resample the closed positions `n` times, e.g. to get confidence intervals
+    /// of the final equity and the maximum drawdown, see [MonteCarlo].
+    pub fn monte_carlo(&self, n: usize, config: &MonteCarlo) -> MonteCarloReport {
+        config.run(self, n)
+    }
+
+    /// The score of the report under the objective, None if it violates a constraint.
+    pub fn score(&self, objective: &Objective) -> Option<Decimal> {
+        objective.score(self)
+    }
+}
+
+/// A performance metric of a report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Metric {
+    TotalReturn,
+    Sharpe,
+    MaxDrawdown,
+    Turnover,
+    /// The number of closed positions.
+    ClosedPositions,
+}
+
+impl Metric {
+    pub fn of(&self, report: &Report) -> Decimal {
+        match self {
+            Metric::TotalReturn => report.total_return(),
+            Metric::Sharpe => report.sharpe(),
+            Metric::MaxDrawdown => report.max_drawdown,
+            Metric::Turnover => report.turnover(),
+            Metric::ClosedPositions => Decimal::from(report.closed_positions.len()),
+        }
+    }
+}
+
+/// What a run is scored by: a weighted sum of metrics, subject to bounds on metrics.
+/// E.g. maximize the Sharpe ratio minus 0.1 times the turnover, with at most 20% drawdown:
+/// `Objective::maximize(Metric::Sharpe).with_term(Metric::Turnover, dec!(-0.1)).with_max(Metric::MaxDrawdown, dec!(0.2))`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Objective {
+    /// The metrics with their weights, the score is their weighted sum.
+    pub terms: Vec<(Metric, Decimal)>,
+    /// Upper bounds of metrics.
+    pub max: Vec<(Metric, Decimal)>,
+    /// Lower bounds of metrics.
+    pub min: Vec<(Metric, Decimal)>,
+}
+
+impl Default for Objective {
+    fn default() -> Self {
+        Objective::maximize(Metric::TotalReturn)
+    }
+}
+
+impl Objective {
+    pub fn maximize(metric: Metric) -> Self {
+        Objective {
+            terms: vec![(metric, Decimal::ONE)],
+            max: Vec::new(),
+            min: Vec::new(),
+        }
+    }
+
+    pub fn with_term(mut self, metric: Metric, weight: Decimal) -> Self {
+        self.terms.push((metric, weight));
+        self
+    }
+
+    pub fn with_max(mut self, metric: Metric, bound: Decimal) -> Self {
+        self.max.push((metric, bound));
+        self
+    }
+
+    pub fn with_min(mut self, metric: Metric, bound: Decimal) -> Self {
+        self.min.push((metric, bound));
+        self
+    }
+
+    /// Returns true if the report satisfies all bounds.
+    pub fn feasible(&self, report: &Report) -> bool {
+        self.max
+            .iter()
+            .all(|(metric, bound)| metric.of(report) <= *bound)
+            && self
+                .min
+                .iter()
+                .all(|(metric, bound)| metric.of(report) >= *bound)
+    }
+
+    /// The weighted sum of the metrics, None if the report violates a bound.
+    pub fn score(&self, report: &Report) -> Option<Decimal> {
+        self.feasible(report).then(|| {
+            self.terms
+                .iter()
+                .map(|(metric, weight)| metric.of(report) * weight)
+                .sum()
+        })
+    }
+}
+
+/// The reports of one strategy run separately on every symbol.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SymbolReports {
+    /// The reports by symbol, best total return first.
+    pub reports: Vec<(Symbol, Report)>,
+    /// The symbols whose run failed, with the error.
+    pub failed: Vec<(Symbol, String)>,
+}
+
+impl SymbolReports {
+    pub(crate) fn new(mut reports: Vec<(Symbol, Report)>, failed: Vec<(Symbol, String)>) -> Self {
+        reports.sort_by_key(|(_, report)| std::cmp::Reverse(report.total_return()));
+        SymbolReports { reports, failed }
+    }
+
+    /// The symbols on which the strategy made a profit.
+    pub fn profitable(&self) -> impl Iterator<Item = Symbol> + '_ {
+        self.reports
+            .iter()
+            .filter(|(_, report)| report.total_return() > Decimal::ZERO)
+            .map(|(symbol, _)| *symbol)
+    }
+
+    /// One line per symbol with the return, drawdown and number of closed positions,
+    /// to compare the symbols at a glance.
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "{:<16} {:>10} {:>10} {:>10}\n",
+            "symbol", "return", "drawdown", "positions"
+        );
+        for (symbol, report) in &self.reports {
+            summary += &format!(
+                "{:<16} {:>9.2}% {:>9.2}% {:>10}\n",
+                symbol.to_string(),
+                report.total_return() * Decimal::ONE_HUNDRED,
+                report.max_drawdown * Decimal::ONE_HUNDRED,
+                report.closed_positions.len()
+            );
+        }
+        for (symbol, err) in &self.failed {
+            summary += &format!("{:<16} failed: {}\n", symbol.to_string(), err);
+        }
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn drawdown_and_return() {
+        let time = Utc::now();
+        let mut report = Report::new(time, dec!(1000));
+        report.update(time + Duration::minutes(1), dec!(1200));
+        report.update(time + Duration::minutes(2), dec!(900));
+        report.update(time + Duration::minutes(3), dec!(1100));
+
+        assert_eq!(report.steps, 3);
+        assert_eq!(report.max_total, dec!(1200));
+        assert_eq!(report.max_drawdown, dec!(0.25));
+        assert_eq!(report.total_return(), dec!(0.1));
+    }
+
+    #[test]
+    fn composite_objective() {
+        let time = Utc::now();
+        let mut report = Report::new(time, dec!(1000));
+        for (day, total) in [dec!(1010), dec!(1005), dec!(1020), dec!(1030)]
+            .into_iter()
+            .enumerate()
+        {
+            report.update(time + Duration::days(day as i64 + 1), total);
+        }
+        report.traded_value = dec!(3000);
+        assert!(report.sharpe() > Decimal::ZERO);
+        assert_eq!(report.turnover(), dec!(3));
+
+        let objective = Objective::maximize(Metric::TotalReturn)
+            .with_term(Metric::Turnover, dec!(-0.01))
+            .with_max(Metric::MaxDrawdown, dec!(0.1));
+        assert_eq!(report.score(&objective), Some(dec!(0.00)));
+        assert_eq!(report.score(&Objective::default()), Some(dec!(0.03)));
+
+        let strict = objective.with_max(Metric::MaxDrawdown, dec!(0.001));
+        assert!(!strict.feasible(&report));
+        assert_eq!(report.score(&strict), None);
+    }
+
+    #[test]
+    fn rank_symbols() {
+        let time = Utc::now();
+        let report = |end_total| {
+            let mut report = Report::new(time, dec!(1000));
+            report.update(time, end_total);
+            report
+        };
+        let (btc, eth, sol) = (
+            Symbol::perp("BTC"),
+            Symbol::perp("ETH"),
+            Symbol::perp("SOL"),
+        );
+
+        let reports = SymbolReports::new(
+            vec![
+                (btc, report(dec!(1050))),
+                (eth, report(dec!(900))),
+                (sol, report(dec!(1200))),
+            ],
+            Vec::new(),
+        );
+
+        assert_eq!(
+            reports
+                .reports
+                .iter()
+                .map(|(symbol, _)| *symbol)
+                .collect::<Vec<_>>(),
+            vec![sol, btc, eth]
+        );
+        assert_eq!(reports.profitable().collect::<Vec<_>>(), vec![sol, btc]);
+        assert!(reports
+            .summary()
+            .contains("SOL-PERP             20.00%      0.00%"));
+    }
+}
@@ -0,0 +1,297 @@
+use crate::{Asset, Exposure, Symbol};
+use rust_decimal::prelude::*;
+use std::collections::{HashMap, VecDeque};
+
+/// Rolling returns of markets, used to estimate volatilities, correlations and the value at risk.
+/// All estimates are for the trading interval, e.g. per minute when trading every minute.
+#[derive(Debug, Clone)]
+pub struct Risk {
+    window: usize,
+    last_close: HashMap<Symbol, Decimal>,
+    returns: HashMap<Symbol, VecDeque<f64>>,
+}
+
+impl Default for Risk {
+    fn default() -> Self {
+        Risk::new(500)
+    }
+}
+
+impl Risk {
+    /// Estimate from the returns of the last `window` intervals.
+    pub fn new(window: usize) -> Self {
+        assert!(window > 1);
+        Risk {
+            window,
+            last_close: HashMap::new(),
+            returns: HashMap::new(),
+        }
+    }
+
+    /// Add the close of the next interval, None if no trade happened.
+    pub(crate) fn update(&mut self, symbol: Symbol, close: Option<Decimal>) {
+        let last_close = self.last_close.get(&symbol).cloned();
+        let ret = match (last_close, close) {
+            (Some(last), Some(close)) if last > Decimal::ZERO => {
+                (close / last - Decimal::ONE).to_f64().unwrap_or_default()
+            }
+            (Some(_), _) => 0.0,
+            (None, _) => {
+                if let Some(close) = close {
+                    self.last_close.insert(symbol, close);
+                }
+                return;
+            }
+        };
+        if let Some(close) = close {
+            self.last_close.insert(symbol, close);
+        }
+
+        let returns = self.returns.entry(symbol).or_default();
+        if returns.len() == self.window {
+            returns.pop_front();
+        }
+        returns.push_back(ret);
+    }
+
+    pub(crate) fn remove(&mut self, symbol: Symbol) {
+        self.last_close.remove(&symbol);
+        self.returns.remove(&symbol);
+    }
+
+    /// Mean of the returns.
+    pub fn mean(&self, symbol: Symbol) -> Option<f64> {
+        let returns = self.returns.get(&symbol)?;
+        if returns.len() < 2 {
+            return None;
+        }
+        Some(returns.iter().sum::<f64>() / returns.len() as f64)
+    }
+
+    /// Standard deviation of the returns.
+    pub fn volatility(&self, symbol: Symbol) -> Option<f64> {
+        Some(self.covariance(symbol, symbol)?.sqrt())
+    }
+
+    pub fn correlation(&self, a: Symbol, b: Symbol) -> Option<f64> {
+        let vol = self.volatility(a)? * self.volatility(b)?;
+        if vol == 0.0 {
+            None
+        } else {
+            Some(self.covariance(a, b)? / vol)
+        }
+    }
+
+    /// Covariance of the returns of both markets over their common history.
+    pub fn covariance(&self, a: Symbol, b: Symbol) -> Option<f64> {
+        let (a, b) = (self.returns.get(&a)?, self.returns.get(&b)?);
+        let n = a.len().min(b.len());
+        if n < 2 {
+            return None;
+        }
+        let a: Vec<f64> = a.iter().skip(a.len() - n).cloned().collect();
+        let b: Vec<f64> = b.iter().skip(b.len() - n).cloned().collect();
+        let mean_a = a.iter().sum::<f64>() / n as f64;
+        let mean_b = b.iter().sum::<f64>() / n as f64;
+        let sum: f64 = a
+            .iter()
+            .zip(&b)
+            .map(|(a, b)| (a - mean_a) * (b - mean_b))
+            .sum();
+        Some(sum / (n - 1) as f64)
+    }
+
+    /// Value at risk of the exposure assuming normally distributed returns,
+    /// as a positive quote value that is not exceeded by losses with the given confidence, e.g. 0.99.
+    /// Returns None if the history of an exposed market is too short.
+    pub fn parametric_var(&self, exposure: &Exposure, confidence: f64) -> Option<Decimal> {
+        let markets: Vec<(Symbol, f64)> = exposure
+            .markets()
+            .filter(|(_, value)| !value.is_zero())
+            .map(|(&symbol, value)| (symbol, value.to_f64().unwrap_or_default()))
+            .collect();
+
+        let mut variance = 0.0;
+        for &(a, value_a) in &markets {
+            for &(b, value_b) in &markets {
+                variance += value_a * value_b * self.covariance(a, b)?;
+            }
+        }
+
+        Decimal::from_f64(variance.max(0.0).sqrt() * normal_quantile(confidence))
+            .map(|var| var.round_dp(8))
+    }
+
+    /// Value at risk of the exposure by applying the historical returns to the current exposure,
+    /// as a positive quote value that is not exceeded by losses with the given confidence, e.g. 0.99.
+    /// Returns None if the history of an exposed market is too short.
+    pub fn historical_var(&self, exposure: &Exposure, confidence: f64) -> Option<Decimal> {
+        let markets: Vec<(&VecDeque<f64>, f64)> = exposure
+            .markets()
+            .filter(|(_, value)| !value.is_zero())
+            .map(|(symbol, value)| Some((self.returns.get(symbol)?, value.to_f64()?)))
+            .collect::<Option<_>>()?;
+
+        let n = markets.iter().map(|(returns, _)| returns.len()).min();
+        let n = match n {
+            Some(n) if n > 0 => n,
+            Some(_) => return None,
+            None => return Some(Decimal::ZERO),
+        };
+
+        let mut losses: Vec<f64> = (0..n)
+            .map(|i| {
+                -markets
+                    .iter()
+                    .map(|(returns, value)| returns[returns.len() - n + i] * value)
+                    .sum::<f64>()
+            })
+            .collect();
+        losses.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let index = ((n as f64 * confidence).ceil() as usize).clamp(1, n) - 1;
+        Decimal::from_f64(losses[index].max(0.0)).map(|var| var.round_dp(8))
+    }
+}
+
+/// A hypothetical price shock, e.g. BTC -20% and everything else -35%.
+#[derive(Debug, Clone, Default)]
+pub struct StressScenario {
+    shocks: HashMap<Asset, Decimal>,
+    otherwise: Decimal,
+}
+
+impl StressScenario {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Relative price change of all markets with this underlying, e.g. -0.2 for a drop of 20%.
+    pub fn shock(mut self, underlying: Asset, change: Decimal) -> Self {
+        self.shocks.insert(underlying, change);
+        self
+    }
+
+    /// Relative price change of all markets without a specific shock.
+    pub fn otherwise(mut self, change: Decimal) -> Self {
+        self.otherwise = change;
+        self
+    }
+
+    /// Profit and loss of the exposure if the scenario happens.
+    pub fn pnl(&self, exposure: &Exposure) -> Decimal {
+        exposure
+            .markets()
+            .map(|(symbol, value)| {
+                let change = self
+                    .shocks
+                    .get(&symbol.underlying())
+                    .cloned()
+                    .unwrap_or(self.otherwise);
+                value * change
+            })
+            .sum()
+    }
+}
+
+// Quantile function of the standard normal distribution,
+// using the rational approximation by Peter Acklam.
+fn normal_quantile(p: f64) -> f64 {
+    assert!(p > 0.0 && p < 1.0);
+    const A: [f64; 6] = [
+        -3.969683028665376e1,
+        2.209460984245205e2,
+        -2.759285104469687e2,
+        1.38357751867269e2,
+        -3.066479806614716e1,
+        2.506628277459239,
+    ];
+    const B: [f64; 5] = [
+        -5.447609879822406e1,
+        1.615858368580409e2,
+        -1.556989798598866e2,
+        6.680131188771972e1,
+        -1.328068155288572e1,
+    ];
+    const C: [f64; 6] = [
+        -7.784894002430293e-3,
+        -3.223964580411365e-1,
+        -2.400758277161838,
+        -2.549732539343734,
+        4.374664141464968,
+        2.938163982698783,
+    ];
+    const D: [f64; 4] = [
+        7.784695709041462e-3,
+        3.224671290700398e-1,
+        2.445134137142996,
+        3.754408661907416,
+    ];
+    const P_LOW: f64 = 0.02425;
+
+    if p < P_LOW {
+        let q = (-2.0 * p.ln()).sqrt();
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    } else if p <= 1.0 - P_LOW {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    } else {
+        -normal_quantile(1.0 - p)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn quantile() {
+        assert!((normal_quantile(0.5)).abs() < 1e-9);
+        assert!((normal_quantile(0.99) - 2.326348).abs() < 1e-5);
+        assert!((normal_quantile(0.01) + 2.326348).abs() < 1e-5);
+    }
+
+    #[test]
+    fn stress_scenario() {
+        let exposure: Exposure = vec![
+            (Symbol::perp("BTC"), dec!(1000)),
+            (Symbol::perp("ETH"), dec!(-500)),
+            (Symbol::perp("SOL"), dec!(200)),
+        ]
+        .into_iter()
+        .collect();
+        let scenario = StressScenario::new()
+            .shock(Asset::new("BTC"), dec!(-0.2))
+            .otherwise(dec!(-0.35));
+
+        assert_eq!(scenario.pnl(&exposure), dec!(-200) + dec!(175) - dec!(70));
+    }
+
+    #[test]
+    fn hedged_exposure_has_less_risk() {
+        let btc = Symbol::perp("BTC");
+        let eth = Symbol::perp("ETH");
+        let mut risk = Risk::new(100);
+        for i in 0..100 {
+            let close = if i % 2 == 0 { dec!(100) } else { dec!(101) };
+            risk.update(btc, Some(close));
+            risk.update(eth, Some(close * dec!(10)));
+        }
+        assert!(risk.correlation(btc, eth).unwrap() > 0.99);
+
+        let long: Exposure = vec![(btc, dec!(1000))].into_iter().collect();
+        let hedged: Exposure = vec![(btc, dec!(1000)), (eth, dec!(-1000))]
+            .into_iter()
+            .collect();
+
+        let long_var = risk.parametric_var(&long, 0.99).unwrap();
+        assert!(long_var > dec!(10));
+        assert!(risk.parametric_var(&hedged, 0.99).unwrap() < dec!(0.01));
+        assert!(risk.historical_var(&long, 0.99).unwrap() > dec!(9));
+        assert!(risk.historical_var(&hedged, 0.99).unwrap() < dec!(0.01));
+    }
+}
@@ -0,0 +1,166 @@
+use crate::Candle;
+use rust_decimal::prelude::*;
+use std::collections::VecDeque;
+
+/// Estimates the bid/ask spread of a market from candle data only,
+/// using the high-low estimator of Corwin and Schultz (2012).
+/// This is useful for venues without order book access.
+#[derive(Debug, Clone)]
+pub struct SpreadEstimator {
+    window: usize,
+    last: Option<Candle>,
+    estimates: VecDeque<f64>,
+}
+
+impl Default for SpreadEstimator {
+    fn default() -> Self {
+        SpreadEstimator::new(20)
+    }
+}
+
+impl SpreadEstimator {
+    /// Create an estimator that averages the estimates of the last `window` candle pairs.
+    pub fn new(window: usize) -> Self {
+        assert!(window > 0);
+        SpreadEstimator {
+            window,
+            last: None,
+            estimates: VecDeque::with_capacity(window),
+        }
+    }
+
+    pub fn update(&mut self, candle: &Candle) {
+        if let Some(last) = &self.last {
+            if let Some(estimate) = corwin_schultz(last, candle) {
+                if self.estimates.len() == self.window {
+                    self.estimates.pop_front();
+                }
+                self.estimates.push_back(estimate);
+            }
+        }
+        self.last = Some(*candle);
+    }
+
+    /// The estimated spread relative to the price, e.g. 0.001 for a spread of 10 bps.
+    /// Returns None if not enough candles were seen yet.
+    pub fn spread(&self) -> Option<Decimal> {
+        if self.estimates.is_empty() {
+            return None;
+        }
+        let mean = self.estimates.iter().sum::<f64>() / self.estimates.len() as f64;
+        Decimal::from_f64(mean).map(|spread| spread.round_dp(8))
+    }
+}
+
+/// The spread simulated fills pay, relative to the price, see `Simulate::with_spread`.
+/// Buys are filled at the price plus half the spread and sells at the price minus half the spread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpreadModel {
+    /// The same spread at all times, e.g. 0.001 for 10 bps.
+    Fixed(Decimal),
+    /// The spread at a reference daily quote volume, scaled by the square root of the reference
+    /// over the daily quote volume of the market, so more liquid markets have tighter spreads.
+    /// Markets with unknown volume have the reference spread.
+    Volume {
+        spread: Decimal,
+        daily_volume: Decimal,
+    },
+}
+
+impl SpreadModel {
+    /// The spread of a market with the daily quote volume, if known.
+    pub fn spread(&self, daily_volume: Option<Decimal>) -> Decimal {
+        match *self {
+            SpreadModel::Fixed(spread) => spread,
+            SpreadModel::Volume {
+                spread,
+                daily_volume: reference,
+            } => match daily_volume {
+                Some(volume) if volume > Decimal::ZERO => (reference / volume)
+                    .to_f64()
+                    .and_then(|ratio| Decimal::from_f64(ratio.sqrt()))
+                    .map_or(spread, |factor| (spread * factor).round_dp(8)),
+                _ => spread,
+            },
+        }
+    }
+}
+
+// Spread estimate of two consecutive candles.
+// Negative estimates are set to zero, as is common practice.
+fn corwin_schultz(first: &Candle, second: &Candle) -> Option<f64> {
+    let (h1, l1) = (first.high.to_f64()?, first.low.to_f64()?);
+    let (h2, l2) = (second.high.to_f64()?, second.low.to_f64()?);
+    if l1 <= 0.0 || l2 <= 0.0 || h1 < l1 || h2 < l2 {
+        return None;
+    }
+
+    let beta = (h1 / l1).ln().powi(2) + (h2 / l2).ln().powi(2);
+    let gamma = (h1.max(h2) / l1.min(l2)).ln().powi(2);
+    let k = 3.0 - 2.0 * 2f64.sqrt();
+    let alpha = ((2.0 * beta).sqrt() - beta.sqrt()) / k - (gamma / k).sqrt();
+    let spread = 2.0 * (alpha.exp() - 1.0) / (1.0 + alpha.exp());
+
+    Some(spread.max(0.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn candle(high: Decimal, low: Decimal) -> Candle {
+        Candle {
+            open: low,
+            high,
+            low,
+            close: high,
+            volume: dec!(1),
+        }
+    }
+
+    #[test]
+    fn not_enough_candles() {
+        let mut estimator = SpreadEstimator::default();
+        assert_eq!(estimator.spread(), None);
+        estimator.update(&candle(dec!(101), dec!(99)));
+        assert_eq!(estimator.spread(), None);
+    }
+
+    #[test]
+    fn flat_candles_have_no_spread() {
+        let mut estimator = SpreadEstimator::default();
+        estimator.update(&candle(dec!(100), dec!(100)));
+        estimator.update(&candle(dec!(100), dec!(100)));
+        assert_eq!(estimator.spread(), Some(dec!(0)));
+    }
+
+    #[test]
+    fn bouncing_candles_have_spread() {
+        // Prices bouncing between bid and ask at a constant mid price.
+        let mut estimator = SpreadEstimator::new(10);
+        for _ in 0..20 {
+            estimator.update(&candle(dec!(100.1), dec!(99.9)));
+        }
+        let spread = estimator.spread().unwrap();
+        assert!(spread > dec!(0.001) && spread < dec!(0.003), "{}", spread);
+    }
+
+    #[test]
+    fn spread_models() {
+        assert_eq!(
+            SpreadModel::Fixed(dec!(0.001)).spread(Some(dec!(1))),
+            dec!(0.001)
+        );
+
+        let model = SpreadModel::Volume {
+            spread: dec!(0.001),
+            daily_volume: dec!(1000000),
+        };
+        assert_eq!(model.spread(Some(dec!(1000000))), dec!(0.001));
+        assert_eq!(model.spread(Some(dec!(100000000))), dec!(0.0001));
+        assert_eq!(model.spread(Some(dec!(10000))), dec!(0.01));
+        assert_eq!(model.spread(None), dec!(0.001));
+        assert_eq!(model.spread(Some(dec!(0))), dec!(0.001));
+    }
+}
@@ -1,16 +1,30 @@
 use std::{collections::HashMap, marker::PhantomData};
 
-use crate::{strategies::Settings, AnyError, Api, Exchange, Strategy};
+use crate::{strategies::Settings, Alert, AnyError, Api, Exchange, Order, PositionId, Strategy};
 use chrono::{DateTime, Duration, TimeZone, Utc};
 use rust_decimal::Decimal;
-use uuid::Uuid;
 
 #[derive(Debug, Clone, Copy)]
 pub enum Trigger {
     StopLoss(Decimal),
     TakeProfit(Decimal),
     TrailingStopLoss(Decimal),
-    TrailingStopLossAdaptive(Decimal, Decimal, Decimal)
+    TrailingStopLossAdaptive(Decimal, Decimal, Decimal),
+    /// Triggers once the position was open for this long, measured from its first fill.
+    MaxHoldingTime(Duration),
+    /// Triggers for all positions from this time on.
+    ExitAt(DateTime<Utc>),
+    /// Triggers once the profit and loss of the position in the quote asset is below
+    /// minus this value.
+    StopLossValue(Decimal),
+    /// Triggers once the profit and loss of the position in the quote asset reaches this value.
+    TakeProfitValue(Decimal),
+    /// Triggers for all positions once the total value of the account fell by this fraction
+    /// from its high since the start. Unlike the position triggers, this includes fees and
+    /// funding that were paid from the wallet.
+    MaxDrawdown(Decimal),
+    /// Like `MaxDrawdown`, but the drop is in the quote asset.
+    MaxDrawdownValue(Decimal),
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -29,16 +43,19 @@ pub struct Levels<A: Api, S: Strategy<A>> {
     _api: PhantomData<A>,
     strategy: S,
     timeout_until: DateTime<Utc>,
+    // The highest total value of the account seen so far.
+    high: Decimal,
     triggers: Vec<(Trigger, Action)>,
-    positions: HashMap<Uuid, PositionData>,
+    positions: HashMap<PositionId, PositionData>,
 }
 
 impl<A: Api, S: Strategy<A>> Levels<A, S> {
     pub fn new(strategy: S) -> Self {
         Levels {
-            _api: PhantomData::default(),
+            _api: PhantomData,
             strategy,
-            timeout_until: Utc.ymd(1970, 1, 1).and_hms(0, 0, 0),
+            timeout_until: Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(),
+            high: Decimal::ZERO,
             triggers: Vec::new(),
             positions: HashMap::new(),
         }
@@ -52,14 +69,27 @@ impl<A: Api, S: Strategy<A>> Levels<A, S> {
 
 impl<A: Api, S: Strategy<A>> Strategy<A> for Levels<A, S> {
     const NAME: &'static str = S::NAME;
+    type Parameters = S::Parameters;
+
+    fn parameters(&self) -> Option<S::Parameters> {
+        self.strategy.parameters()
+    }
 
     fn init(&mut self, exchange: &mut Exchange<A>) -> Result<Settings, AnyError> {
         self.strategy.init(exchange)
     }
 
+    fn before_execute(&mut self, orders: &mut Vec<Order>) {
+        self.strategy.before_execute(orders)
+    }
+
     fn eval(&mut self, exchange: &mut Exchange<A>) -> Result<(), AnyError> {
         self.strategy.eval(exchange)?;
 
+        let current_time = exchange.current_time();
+        let total = exchange.total();
+        self.high = self.high.max(total);
+        let drawdown = self.high - total;
         for position in exchange.positions() {
             let data = self.positions.entry(position.id()).or_insert(PositionData {
                 max_relative_pnl: Decimal::ZERO,
@@ -67,6 +97,7 @@ impl<A: Api, S: Strategy<A>> Strategy<A> for Levels<A, S> {
             });
 
             let relative_pnl = position.relative_pnl();
+            let pnl = position.pnl();
             data.max_relative_pnl = data.max_relative_pnl.max(relative_pnl);
 
             for &(trigger, action) in &self.triggers {
@@ -78,21 +109,49 @@ impl<A: Api, S: Strategy<A>> Strategy<A> for Levels<A, S> {
                     {
                         Some(action)
                     }
-                    Trigger::TrailingStopLossAdaptive(threshold_max, threshold_min, threshold_mul)
-                        if relative_pnl <= data.max_relative_pnl - (threshold_max - data.max_relative_pnl * threshold_mul).max(threshold_min) =>
+                    Trigger::TrailingStopLossAdaptive(
+                        threshold_max,
+                        threshold_min,
+                        threshold_mul,
+                    ) if relative_pnl
+                        <= data.max_relative_pnl
+                            - (threshold_max - data.max_relative_pnl * threshold_mul)
+                                .max(threshold_min) =>
                     {
                         Some(action)
                     }
+                    Trigger::MaxHoldingTime(duration)
+                        if position
+                            .opened_at()
+                            .is_some_and(|opened_at| current_time - opened_at >= duration) =>
+                    {
+                        Some(action)
+                    }
+                    Trigger::ExitAt(time) if current_time >= time => Some(action),
+                    Trigger::StopLossValue(threshold) if pnl <= -threshold => Some(action),
+                    Trigger::TakeProfitValue(threshold) if pnl >= threshold => Some(action),
+                    Trigger::MaxDrawdown(threshold)
+                        if !self.high.is_zero() && drawdown / self.high >= threshold =>
+                    {
+                        Some(action)
+                    }
+                    Trigger::MaxDrawdownValue(threshold) if drawdown >= threshold => Some(action),
                     _ => None,
                 } {
-                    log::warn!("Trigger {:?} executing action {:?}", trigger, action);
+                    tracing::warn!("Trigger {:?} executing action {:?}", trigger, action);
+                    if data.action.is_none() {
+                        exchange.alert(Alert::Triggered {
+                            time: current_time,
+                            position: position.id(),
+                            reason: format!("{:?}", trigger),
+                        });
+                    }
                     data.action = Some(action);
                 }
             }
         }
 
         let mut quit = false;
-        let current_time = exchange.current_time();
 
         for position in exchange.positions_mut() {
             let data = self.positions.get(&position.id()).unwrap();
@@ -123,3 +182,102 @@ impl<A: Api, S: Strategy<A>> Strategy<A> for Levels<A, S> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        apis::Mock,
+        testing::{Script, StrategyTester},
+        Interval, Position, Side, Symbol,
+    };
+    use rust_decimal_macros::dec;
+
+    // Enters a long position whenever it is flat.
+    struct AlwaysLong {
+        market: Symbol,
+    }
+
+    impl Strategy<Mock<Script>> for AlwaysLong {
+        const NAME: &'static str = "AlwaysLong";
+        type Parameters = ();
+
+        fn init(&mut self, exchange: &mut Exchange<Mock<Script>>) -> Result<Settings, AnyError> {
+            exchange.watch(self.market);
+            Ok(Settings {
+                interval: Interval::Fixed(Duration::hours(1)),
+                ..Default::default()
+            })
+        }
+
+        fn eval(&mut self, exchange: &mut Exchange<Mock<Script>>) -> Result<(), AnyError> {
+            if exchange.positions().next().is_none() {
+                exchange.open(Position::default().long(self.market, dec!(1)))?;
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn time_based_exits() {
+        let market = Symbol::perp("BTC");
+        let start = Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap();
+        let strategy = Levels::new(AlwaysLong { market })
+            .add(Trigger::MaxHoldingTime(Duration::hours(3)), Action::Close);
+        let mut tester = StrategyTester::new(strategy)
+            .with_closes(market, [dec!(100)])
+            .with_start_time(start);
+
+        tester.step().await.unwrap();
+        tester.assert_ordered(market, Side::Buy, dec!(1));
+        let entry = tester.orders().next().unwrap().time;
+        while tester.size(market) == dec!(1) {
+            tester.step().await.unwrap();
+        }
+        tester.assert_ordered(market, Side::Sell, dec!(1));
+        assert_eq!(
+            tester.orders().next().unwrap().time,
+            entry + Duration::hours(3)
+        );
+
+        // Positions held at the exit time are closed.
+        let exit = start + Duration::hours(10);
+        let strategy = Levels::new(AlwaysLong { market }).add(Trigger::ExitAt(exit), Action::Close);
+        let mut tester = StrategyTester::new(strategy)
+            .with_closes(market, [dec!(100)])
+            .with_start_time(start);
+        tester.step().await.unwrap();
+        while tester.size(market) == dec!(1) {
+            tester.step().await.unwrap();
+        }
+        assert_eq!(tester.orders().next().unwrap().time, exit);
+    }
+
+    #[tokio::test]
+    async fn value_triggers() {
+        let market = Symbol::perp("BTC");
+        let start = Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap();
+        let closes = [100, 100, 90, 80, 70, 60].map(Decimal::from);
+        for (trigger, exit) in [
+            (Trigger::StopLossValue(dec!(15)), 3),
+            (Trigger::MaxDrawdownValue(dec!(25)), 4),
+            (Trigger::MaxDrawdown(dec!(0.035)), 5),
+        ] {
+            let strategy = Levels::new(AlwaysLong { market }).add(trigger, Action::Close);
+            let mut tester = StrategyTester::new(strategy)
+                .with_closes(market, closes)
+                .with_capital(dec!(1000))
+                .with_start_time(start);
+            tester.step().await.unwrap();
+            while tester.size(market) == dec!(1) {
+                tester.step().await.unwrap();
+            }
+            assert_eq!(
+                tester.orders().next().unwrap().time,
+                start + Duration::hours(exit),
+                "{:?}",
+                trigger
+            );
+        }
+    }
+}
@@ -1,5 +1,17 @@
 mod levels;
+mod multi;
+mod risk_limit;
+mod risk_target;
+mod roll;
+mod schedule;
+mod state;
 mod strategy;
 
 pub use levels::*;
+pub use multi::*;
+pub use risk_limit::*;
+pub use risk_target::*;
+pub use roll::*;
+pub use schedule::*;
+pub use state::*;
 pub use strategy::*;
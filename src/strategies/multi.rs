@@ -0,0 +1,388 @@
+use std::collections::HashSet;
+
+use crate::{strategies::Settings, AnyError, Api, Exchange, Order, PositionId, Strategy};
+use rust_decimal::Decimal;
+use serde_json::Value;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum MultiStrategyError {
+    #[error("No strategies to run.")]
+    NoStrategies,
+    #[error("More than the total capital allocated.")]
+    OverAllocated,
+    #[error("All strategies must trade on the same interval.")]
+    IntervalMismatch,
+    #[error("The saved state does not belong to a multi strategy.")]
+    InvalidState,
+}
+
+// Object safe version of the strategy trait to run strategies of different types.
+trait DynStrategy<A: Api> {
+    fn name(&self) -> &'static str;
+    fn init(&mut self, exchange: &mut Exchange<A>) -> Result<Settings, AnyError>;
+    fn eval(&mut self, exchange: &mut Exchange<A>) -> Result<(), AnyError>;
+    fn before_execute(&mut self, orders: &mut Vec<Order>);
+    fn parameters(&self) -> Option<Value>;
+    fn save_state(&self) -> Option<Value>;
+    fn load_state(&mut self, state: Value) -> Result<(), AnyError>;
+}
+
+impl<A: Api, S: Strategy<A>> DynStrategy<A> for S {
+    fn name(&self) -> &'static str {
+        S::NAME
+    }
+
+    fn init(&mut self, exchange: &mut Exchange<A>) -> Result<Settings, AnyError> {
+        Strategy::init(self, exchange)
+    }
+
+    fn eval(&mut self, exchange: &mut Exchange<A>) -> Result<(), AnyError> {
+        Strategy::eval(self, exchange)
+    }
+
+    fn before_execute(&mut self, orders: &mut Vec<Order>) {
+        Strategy::before_execute(self, orders)
+    }
+
+    fn parameters(&self) -> Option<Value> {
+        serde_json::to_value(Strategy::parameters(self)?).ok()
+    }
+
+    fn save_state(&self) -> Option<Value> {
+        Strategy::save_state(self)
+    }
+
+    fn load_state(&mut self, state: Value) -> Result<(), AnyError> {
+        Strategy::load_state(self, state)
+    }
+}
+
+struct Allocation<A: Api> {
+    strategy: Box<dyn DynStrategy<A>>,
+    share: Decimal,
+    positions: HashSet<PositionId>,
+}
+
+/// Runs multiple strategies in one exchange session sharing the same wallet.
+/// Each strategy only sees its own positions, and its total is its share of the account total.
+pub struct MultiStrategy<A: Api> {
+    allocations: Vec<Allocation<A>>,
+}
+
+impl<A: Api> Default for MultiStrategy<A> {
+    fn default() -> Self {
+        MultiStrategy {
+            allocations: Vec::new(),
+        }
+    }
+}
+
+impl<A: Api> MultiStrategy<A> {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Add a strategy with a share of the capital, e.g. 0.5 for half of the capital.
+    pub fn add<S: Strategy<A> + 'static>(mut self, strategy: S, share: Decimal) -> Self {
+        assert!(share >= Decimal::ZERO);
+        self.allocations.push(Allocation {
+            strategy: Box::new(strategy),
+            share,
+            positions: HashSet::new(),
+        });
+        self
+    }
+
+    /// The name of the strategy that opened a position.
+    pub fn owner(&self, position: PositionId) -> Option<&'static str> {
+        self.allocations
+            .iter()
+            .find(|allocation| allocation.positions.contains(&position))
+            .map(|allocation| allocation.strategy.name())
+    }
+
+    // Runs a function for each strategy with a view of the exchange that only contains
+    // the positions of that strategy and a total corresponding to its share.
+    fn for_each<F, T>(&mut self, exchange: &mut Exchange<A>, mut f: F) -> Result<Vec<T>, AnyError>
+    where
+        F: FnMut(&mut dyn DynStrategy<A>, &mut Exchange<A>) -> Result<T, AnyError>,
+    {
+        let total = exchange.total();
+        let mut others = exchange.swap_positions(Vec::new());
+        let mut merged = Vec::new();
+        let mut results = Vec::new();
+
+        for allocation in &mut self.allocations {
+            let (own, rest) = others
+                .into_iter()
+                .partition(|position| allocation.positions.contains(&position.id()));
+            others = rest;
+            exchange.swap_positions(own);
+            exchange.set_budget(Some(total * allocation.share));
+
+            let result = f(allocation.strategy.as_mut(), exchange);
+
+            exchange.set_budget(None);
+            let own = exchange.swap_positions(Vec::new());
+            allocation.positions = own.iter().map(|position| position.id()).collect();
+            merged.extend(own);
+
+            match result {
+                Ok(result) => results.push(result),
+                Err(err) => {
+                    merged.extend(others);
+                    exchange.swap_positions(merged);
+                    return Err(err);
+                }
+            }
+        }
+
+        merged.extend(others);
+        exchange.swap_positions(merged);
+
+        Ok(results)
+    }
+}
+
+impl<A: Api> Strategy<A> for MultiStrategy<A> {
+    const NAME: &'static str = "Multi Strategy";
+    type Parameters = Vec<Value>;
+
+    fn init(&mut self, exchange: &mut Exchange<A>) -> Result<Settings, AnyError> {
+        if self.allocations.is_empty() {
+            return Err(MultiStrategyError::NoStrategies.into());
+        }
+        let share: Decimal = self.allocations.iter().map(|a| a.share).sum();
+        if share > Decimal::ONE {
+            return Err(MultiStrategyError::OverAllocated.into());
+        }
+
+        let mut settings = self.for_each(exchange, |strategy, exchange| strategy.init(exchange))?;
+        if settings
+            .iter()
+            .any(|other| other.interval != settings[0].interval)
+        {
+            return Err(MultiStrategyError::IntervalMismatch.into());
+        }
+
+        Ok(settings.swap_remove(0))
+    }
+
+    fn eval(&mut self, exchange: &mut Exchange<A>) -> Result<(), AnyError> {
+        self.for_each(exchange, |strategy, exchange| strategy.eval(exchange))?;
+        Ok(())
+    }
+
+    // Orders are coalesced across strategies, so every strategy sees all orders.
+    fn before_execute(&mut self, orders: &mut Vec<Order>) {
+        for allocation in &mut self.allocations {
+            allocation.strategy.before_execute(orders);
+        }
+    }
+
+    // The parameters of the strategies in the order they were added,
+    // null for strategies without parameters.
+    fn parameters(&self) -> Option<Vec<Value>> {
+        let parameters: Vec<Value> = self
+            .allocations
+            .iter()
+            .map(|allocation| allocation.strategy.parameters().unwrap_or(Value::Null))
+            .collect();
+        parameters
+            .iter()
+            .any(|parameters| !parameters.is_null())
+            .then_some(parameters)
+    }
+
+    // The states of the strategies in the order they were added, null for strategies without state.
+    fn save_state(&self) -> Option<Value> {
+        let states: Vec<Value> = self
+            .allocations
+            .iter()
+            .map(|allocation| allocation.strategy.save_state().unwrap_or(Value::Null))
+            .collect();
+        states
+            .iter()
+            .any(|state| !state.is_null())
+            .then(|| states.into())
+    }
+
+    fn load_state(&mut self, state: Value) -> Result<(), AnyError> {
+        let Value::Array(states) = state else {
+            return Err(MultiStrategyError::InvalidState.into());
+        };
+        for (allocation, state) in self.allocations.iter_mut().zip(states) {
+            if !state.is_null() {
+                allocation.strategy.load_state(state)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        apis::Mock,
+        testing::{Script, StrategyTester},
+        Interval, Position, Symbol,
+    };
+    use chrono::Duration;
+    use rust_decimal_macros::dec;
+    use std::{cell::RefCell, rc::Rc};
+
+    // The totals and the number of positions a strategy saw in each step.
+    type Seen = Rc<RefCell<Vec<(Decimal, usize)>>>;
+
+    // Puts its whole total into a market once, long or short.
+    fn invest(
+        exchange: &mut Exchange<Mock<Script>>,
+        market: Symbol,
+        long: bool,
+        seen: &Seen,
+    ) -> Result<(), AnyError> {
+        let total = exchange.total();
+        let positions = exchange.positions().count();
+        seen.borrow_mut().push((total, positions));
+        if positions == 0 {
+            let size = total / exchange.price(market).expect("scripted");
+            let position = if long {
+                Position::default().long(market, size)
+            } else {
+                Position::default().short(market, size)
+            };
+            exchange.open(position)?;
+        }
+        Ok(())
+    }
+
+    fn settings(exchange: &mut Exchange<Mock<Script>>, market: Symbol) -> Settings {
+        exchange.watch(market);
+        Settings {
+            interval: Interval::Fixed(Duration::hours(1)),
+            ..Default::default()
+        }
+    }
+
+    struct Long {
+        market: Symbol,
+        seen: Seen,
+    }
+
+    impl Strategy<Mock<Script>> for Long {
+        const NAME: &'static str = "Long";
+        type Parameters = ();
+
+        fn init(&mut self, exchange: &mut Exchange<Mock<Script>>) -> Result<Settings, AnyError> {
+            Ok(settings(exchange, self.market))
+        }
+
+        fn eval(&mut self, exchange: &mut Exchange<Mock<Script>>) -> Result<(), AnyError> {
+            invest(exchange, self.market, true, &self.seen)
+        }
+    }
+
+    struct Short {
+        market: Symbol,
+        seen: Seen,
+    }
+
+    impl Strategy<Mock<Script>> for Short {
+        const NAME: &'static str = "Short";
+        type Parameters = ();
+
+        fn init(&mut self, exchange: &mut Exchange<Mock<Script>>) -> Result<Settings, AnyError> {
+            Ok(settings(exchange, self.market))
+        }
+
+        fn eval(&mut self, exchange: &mut Exchange<Mock<Script>>) -> Result<(), AnyError> {
+            invest(exchange, self.market, false, &self.seen)
+        }
+    }
+
+    fn tester(
+        long_share: Decimal,
+        short_share: Decimal,
+    ) -> (StrategyTester<MultiStrategy<Mock<Script>>>, Seen, Seen) {
+        let (btc, eth) = (Symbol::perp("BTC"), Symbol::perp("ETH"));
+        let (long, short) = (Seen::default(), Seen::default());
+        let strategy = MultiStrategy::new()
+            .add(
+                Long {
+                    market: btc,
+                    seen: long.clone(),
+                },
+                long_share,
+            )
+            .add(
+                Short {
+                    market: eth,
+                    seen: short.clone(),
+                },
+                short_share,
+            );
+        let tester = StrategyTester::new(strategy)
+            .with_closes(btc, [dec!(100), dec!(100), dec!(110)])
+            .with_closes(eth, [dec!(50)]);
+        (tester, long, short)
+    }
+
+    #[tokio::test]
+    async fn allocate_capital_by_share() {
+        let (btc, eth) = (Symbol::perp("BTC"), Symbol::perp("ETH"));
+        let (mut tester, long, short) = tester(dec!(0.5), dec!(0.25));
+        tester.run(3).await.unwrap();
+
+        // Each strategy sizes its position with its share of the account total.
+        tester.assert_size(btc, dec!(50));
+        tester.assert_size(eth, dec!(-50));
+        tester.assert_total(dec!(10500));
+        assert_eq!(
+            *long.borrow(),
+            vec![(dec!(5000), 0), (dec!(5000), 1), (dec!(5250), 1)]
+        );
+        assert_eq!(
+            *short.borrow(),
+            vec![(dec!(2500), 0), (dec!(2500), 1), (dec!(2625), 1)]
+        );
+    }
+
+    #[tokio::test]
+    async fn attribute_positions_to_strategies() {
+        let (btc, eth) = (Symbol::perp("BTC"), Symbol::perp("ETH"));
+        let (mut tester, _, _) = tester(dec!(0.5), dec!(0.5));
+        tester.run(2).await.unwrap();
+
+        let owners: Vec<(Symbol, Option<&str>)> = tester
+            .exchange()
+            .positions()
+            .map(|position| {
+                let symbol = position.symbols().next().unwrap();
+                (symbol, tester.strategy().owner(position.id()))
+            })
+            .collect();
+        assert_eq!(owners.len(), 2);
+        assert!(owners.contains(&(btc, Some("Long"))));
+        assert!(owners.contains(&(eth, Some("Short"))));
+    }
+
+    #[tokio::test]
+    async fn reject_over_allocation() {
+        let (mut tester, long, _) = tester(dec!(0.75), dec!(0.5));
+        let err = tester.step().await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(MultiStrategyError::OverAllocated)
+        ));
+        assert!(long.borrow().is_empty());
+
+        let mut tester = StrategyTester::new(MultiStrategy::new());
+        let err = tester.step().await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(MultiStrategyError::NoStrategies)
+        ));
+    }
+}
@@ -0,0 +1,143 @@
+use std::marker::PhantomData;
+
+use crate::{strategies::Settings, AnyError, Api, Exchange, Order, Strategy, StressScenario};
+use rust_decimal::{prelude::Signed, Decimal};
+use serde_json::Value;
+
+/// Wraps a strategy and vetoes newly opened positions that would exceed the risk budget.
+/// Positions are dropped, most recent first, until the target exposure is within all limits.
+/// Orders can also be scaled down so they do not move thin markets too much.
+pub struct RiskLimit<A: Api, S: Strategy<A>> {
+    _api: PhantomData<A>,
+    strategy: S,
+    max_var: Option<(Decimal, f64)>,
+    scenarios: Vec<(StressScenario, Decimal)>,
+    max_impact: Option<Decimal>,
+}
+
+impl<A: Api, S: Strategy<A>> RiskLimit<A, S> {
+    pub fn new(strategy: S) -> Self {
+        RiskLimit {
+            _api: PhantomData,
+            strategy,
+            max_var: None,
+            scenarios: Vec::new(),
+            max_impact: None,
+        }
+    }
+
+    /// Limit the parametric value at risk over one interval at the given confidence, e.g. 0.99.
+    pub fn max_var(mut self, budget: Decimal, confidence: f64) -> Self {
+        self.max_var = Some((budget, confidence));
+        self
+    }
+
+    /// Limit the loss if the stress scenario happens.
+    pub fn max_stress_loss(mut self, scenario: StressScenario, max_loss: Decimal) -> Self {
+        self.scenarios.push((scenario, max_loss));
+        self
+    }
+
+    /// Scale down the orders of every position to an expected market impact of at most
+    /// `max_impact`, e.g. 0.001 for 10 bps. Orders in markets without a size suggestion are kept.
+    pub fn max_impact(mut self, max_impact: Decimal) -> Self {
+        self.max_impact = Some(max_impact);
+        self
+    }
+
+    fn limit_impact(&self, exchange: &mut Exchange<A>, max_impact: Decimal) {
+        let mut positions = exchange.swap_positions(Vec::new());
+        for position in &mut positions {
+            let order = position.order().bundle;
+            for (&symbol, &size) in &order.0 {
+                let max_size = match exchange.max_order_size(symbol, max_impact) {
+                    Some(max_size) if size.abs() > max_size => max_size,
+                    _ => continue,
+                };
+                tracing::warn!(
+                    "Order of position {} in {} reduced from {} to {}, it exceeds the impact limit.",
+                    position.id(),
+                    symbol,
+                    size.abs(),
+                    max_size
+                );
+                let current = position
+                    .current
+                    .bundle
+                    .0
+                    .get(&symbol)
+                    .cloned()
+                    .unwrap_or_default();
+                *position.size(symbol) = current + size.signum() * max_size;
+                position.refit = true;
+            }
+        }
+        exchange.swap_positions(positions);
+    }
+
+    fn within_limits(&self, exchange: &Exchange<A>) -> bool {
+        let exposure = exchange.target_exposure();
+        if let Some((budget, confidence)) = self.max_var {
+            // Without enough history the risk is unknown, so nothing can be opened.
+            match exchange.risk().parametric_var(&exposure, confidence) {
+                Some(var) if var <= budget => {}
+                _ => return false,
+            }
+        }
+        self.scenarios
+            .iter()
+            .all(|(scenario, max_loss)| -scenario.pnl(&exposure) <= *max_loss)
+    }
+}
+
+impl<A: Api, S: Strategy<A>> Strategy<A> for RiskLimit<A, S> {
+    const NAME: &'static str = S::NAME;
+    type Parameters = S::Parameters;
+
+    fn parameters(&self) -> Option<S::Parameters> {
+        self.strategy.parameters()
+    }
+
+    fn init(&mut self, exchange: &mut Exchange<A>) -> Result<Settings, AnyError> {
+        self.strategy.init(exchange)
+    }
+
+    fn before_execute(&mut self, orders: &mut Vec<Order>) {
+        self.strategy.before_execute(orders)
+    }
+
+    fn save_state(&self) -> Option<Value> {
+        self.strategy.save_state()
+    }
+
+    fn load_state(&mut self, state: Value) -> Result<(), AnyError> {
+        self.strategy.load_state(state)
+    }
+
+    fn eval(&mut self, exchange: &mut Exchange<A>) -> Result<(), AnyError> {
+        self.strategy.eval(exchange)?;
+
+        if let Some(max_impact) = self.max_impact {
+            self.limit_impact(exchange, max_impact);
+        }
+
+        while !self.within_limits(exchange) {
+            let mut positions = exchange.swap_positions(Vec::new());
+            let vetoed = positions
+                .iter()
+                .rposition(|position| position.open.is_none())
+                .map(|index| positions.remove(index));
+            exchange.swap_positions(positions);
+
+            match vetoed {
+                Some(position) => tracing::warn!(
+                    "Position {} vetoed, it exceeds the risk budget.",
+                    position.id()
+                ),
+                None => break,
+            }
+        }
+
+        Ok(())
+    }
+}
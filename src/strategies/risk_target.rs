@@ -0,0 +1,226 @@
+use std::{collections::HashMap, marker::PhantomData};
+
+use crate::{strategies::Settings, AnyError, Api, Exchange, Interval, Order, Strategy, Symbol};
+use chrono::Duration;
+use rust_decimal::prelude::*;
+use serde_json::Value;
+
+/// The risk the positions of a strategy are sized to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    /// An annualized volatility relative to the total value, e.g. 0.2 for 20% a year.
+    Volatility(f64),
+    /// A fraction of the Kelly optimal size given the mean returns, e.g. 0.5 for half Kelly.
+    /// Positions without an expected profit are sized to zero.
+    Kelly(f64),
+}
+
+/// Wraps a strategy and rescales the sizes of all its positions together, so the portfolio
+/// has the target risk estimated from the returns of the watched markets.
+/// The inner strategy only decides the direction and the relative sizes of its positions.
+/// Until the returns of all markets held are known, the sizes are left as they are.
+pub struct RiskTarget<A: Api, S: Strategy<A>> {
+    _api: PhantomData<A>,
+    strategy: S,
+    target: Target,
+    tolerance: f64,
+    max_leverage: Option<f64>,
+    interval: Option<Interval>,
+}
+
+impl<A: Api, S: Strategy<A>> RiskTarget<A, S> {
+    /// Rebalances once the sizes are off by more than 10%.
+    pub fn new(strategy: S, target: Target) -> Self {
+        RiskTarget {
+            _api: PhantomData,
+            strategy,
+            target,
+            tolerance: 0.1,
+            max_leverage: None,
+            interval: None,
+        }
+    }
+
+    /// Only rescale once the sizes are off by more than this fraction, to avoid small orders.
+    pub fn tolerance(mut self, tolerance: f64) -> Self {
+        assert!(tolerance >= 0.0);
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Limit the gross exposure to this multiple of the total value.
+    pub fn max_leverage(mut self, max_leverage: f64) -> Self {
+        assert!(max_leverage >= 0.0);
+        self.max_leverage = Some(max_leverage);
+        self
+    }
+
+    // The factor to multiply the target sizes with, None if the risk is unknown.
+    fn scale(&self, exchange: &Exchange<A>) -> Option<f64> {
+        let mut values: HashMap<Symbol, f64> = HashMap::new();
+        for position in exchange.positions() {
+            for (&symbol, &size) in &position.next_size.0 {
+                if size.is_zero() {
+                    continue;
+                }
+                let value = size
+                    * exchange
+                        .market(symbol)
+                        .contract_value(exchange.price(symbol)?);
+                *values.entry(symbol).or_default() += value.to_f64()?;
+            }
+        }
+
+        let risk = exchange.risk();
+        let mut variance = 0.0;
+        for (&a, value_a) in &values {
+            for (&b, value_b) in &values {
+                variance += value_a * value_b * risk.covariance(a, b)?;
+            }
+        }
+        if variance <= 0.0 {
+            return None;
+        }
+
+        let total = exchange.total().to_f64()?;
+        let scale = match self.target {
+            Target::Volatility(volatility) => {
+                let length = self.interval?.length(exchange.current_time());
+                let periods =
+                    Duration::days(365).num_seconds() as f64 / length.num_seconds() as f64;
+                volatility * total / (variance * periods).sqrt()
+            }
+            Target::Kelly(fraction) => {
+                let mut mean = 0.0;
+                for (&symbol, value) in &values {
+                    mean += value * risk.mean(symbol)?;
+                }
+                (fraction * total * mean / variance).max(0.0)
+            }
+        };
+
+        let gross: f64 = values.values().map(|value| value.abs()).sum();
+        Some(match self.max_leverage {
+            Some(max_leverage) => scale.min(max_leverage * total / gross),
+            None => scale,
+        })
+    }
+}
+
+impl<A: Api, S: Strategy<A>> Strategy<A> for RiskTarget<A, S> {
+    const NAME: &'static str = S::NAME;
+    type Parameters = S::Parameters;
+
+    fn parameters(&self) -> Option<S::Parameters> {
+        self.strategy.parameters()
+    }
+
+    fn init(&mut self, exchange: &mut Exchange<A>) -> Result<Settings, AnyError> {
+        let settings = self.strategy.init(exchange)?;
+        self.interval = Some(settings.interval);
+        Ok(settings)
+    }
+
+    fn before_execute(&mut self, orders: &mut Vec<Order>) {
+        self.strategy.before_execute(orders)
+    }
+
+    fn save_state(&self) -> Option<Value> {
+        self.strategy.save_state()
+    }
+
+    fn load_state(&mut self, state: Value) -> Result<(), AnyError> {
+        self.strategy.load_state(state)
+    }
+
+    fn eval(&mut self, exchange: &mut Exchange<A>) -> Result<(), AnyError> {
+        self.strategy.eval(exchange)?;
+
+        let Some(scale) = self.scale(exchange) else {
+            return Ok(());
+        };
+        if (scale - 1.0).abs() <= self.tolerance {
+            return Ok(());
+        }
+        let Some(scale) = Decimal::from_f64(scale) else {
+            return Ok(());
+        };
+
+        tracing::info!(
+            "Sizes of all positions scaled by {:.4} to the risk target.",
+            scale
+        );
+        for position in exchange.positions_mut() {
+            for size in position.next_size.0.values_mut() {
+                *size *= scale;
+            }
+            position.refit = true;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        apis::Mock,
+        testing::{Script, StrategyTester},
+        Position,
+    };
+    use rust_decimal_macros::dec;
+
+    // Enters a long position once the returns are known.
+    struct LongOnce {
+        market: Symbol,
+    }
+
+    impl Strategy<Mock<Script>> for LongOnce {
+        const NAME: &'static str = "LongOnce";
+        type Parameters = ();
+
+        fn init(&mut self, exchange: &mut Exchange<Mock<Script>>) -> Result<Settings, AnyError> {
+            exchange.watch(self.market);
+            Ok(Settings {
+                interval: Interval::Fixed(Duration::hours(1)),
+                ..Default::default()
+            })
+        }
+
+        fn eval(&mut self, exchange: &mut Exchange<Mock<Script>>) -> Result<(), AnyError> {
+            if exchange.warmed_up(self.market, 10) && exchange.positions().next().is_none() {
+                exchange.open(Position::default().long(self.market, dec!(1)))?;
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn scale_to_target() {
+        let btc = Symbol::perp("BTC");
+        let closes = (0..100).map(|i| if i % 2 == 0 { dec!(100) } else { dec!(101) });
+        let strategy = RiskTarget::new(LongOnce { market: btc }, Target::Volatility(0.5));
+        let mut tester = StrategyTester::new(strategy).with_closes(btc, closes);
+        tester.step().await.unwrap();
+        while tester.size(btc).is_zero() {
+            tester.step().await.unwrap();
+        }
+
+        // Hourly returns of about 1% are a volatility of about 94% a year,
+        // so about half of the total value is held.
+        let held = tester.size(btc);
+        assert!(held > dec!(50) && held < dec!(56), "held {}", held);
+
+        // Further steps are within the tolerance.
+        tester.step().await.unwrap();
+        tester.assert_no_orders();
+
+        // Falling prices have no expected profit with Kelly sizing.
+        let falling = (0..10).map(|i| dec!(100) - Decimal::from(i));
+        let strategy = RiskTarget::new(LongOnce { market: btc }, Target::Kelly(0.5));
+        let mut tester = StrategyTester::new(strategy).with_closes(btc, falling);
+        tester.run(12).await.unwrap();
+        tester.assert_size(btc, dec!(0));
+    }
+}
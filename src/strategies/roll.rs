@@ -0,0 +1,260 @@
+use std::{collections::HashMap, marker::PhantomData};
+
+use crate::{strategies::Settings, AnyError, Api, Exchange, Order, Position, Strategy, Symbol};
+use chrono::{DateTime, Duration, Utc};
+use serde_json::Value;
+
+/// Wraps a strategy and rolls positions in expiring futures into the next contract.
+/// Positions are closed `before` their expiry and reopened with the same sizes in the
+/// contract of the same underlying that expires next, which is watched ahead of the roll.
+pub struct AutoRoll<A: Api, S: Strategy<A>> {
+    _api: PhantomData<A>,
+    strategy: S,
+    before: Duration,
+}
+
+impl<A: Api, S: Strategy<A>> AutoRoll<A, S> {
+    /// Rolls three days before expiry.
+    pub fn new(strategy: S) -> Self {
+        AutoRoll {
+            _api: PhantomData,
+            strategy,
+            before: Duration::days(3),
+        }
+    }
+
+    /// Roll the duration before expiry.
+    pub fn before(mut self, before: Duration) -> Self {
+        assert!(before >= Duration::zero());
+        self.before = before;
+        self
+    }
+
+    // When a future expires, precisely if the market knows it and at the start of the day if not.
+    fn expiry(exchange: &Exchange<A>, symbol: Symbol) -> Option<DateTime<Utc>> {
+        let date = symbol.expiry()?;
+        Some(
+            exchange
+                .markets()
+                .find(|market| market.symbol == symbol)
+                .and_then(|market| market.expiry)
+                .unwrap_or_else(|| date.and_hms_opt(0, 0, 0).unwrap().and_utc()),
+        )
+    }
+
+    // The contract of the same underlying and venue that expires next after the roll.
+    fn next_contract(&self, exchange: &Exchange<A>, symbol: Symbol) -> Option<Symbol> {
+        let roll = Self::expiry(exchange, symbol)? - self.before;
+        exchange
+            .markets()
+            .map(|market| market.symbol)
+            .filter(|next| {
+                next.underlying() == symbol.underlying()
+                    && next.venue() == symbol.venue()
+                    && Self::expiry(exchange, *next).is_some_and(|expiry| expiry > roll)
+                    && *next != symbol
+            })
+            .min_by_key(|next| next.expiry())
+    }
+
+    fn roll(&self, exchange: &mut Exchange<A>) {
+        let time = exchange.current_time();
+        let mut watch = Vec::new();
+        let mut rolls = Vec::new();
+        for position in exchange
+            .positions()
+            .filter(|position| !position.removable())
+        {
+            let mut replacements = HashMap::new();
+            for symbol in position.next_size.0.keys().copied() {
+                let Some(expiry) = Self::expiry(exchange, symbol) else {
+                    continue;
+                };
+                let due = time >= expiry - self.before;
+                match self.next_contract(exchange, symbol) {
+                    Some(next) => {
+                        watch.push(next);
+                        if due {
+                            replacements.insert(symbol, next);
+                        }
+                    }
+                    None if due => tracing::warn!(
+                        "No contract to roll {} of position {} into.",
+                        symbol,
+                        position.id()
+                    ),
+                    None => {}
+                }
+            }
+            if !replacements.is_empty() {
+                rolls.push((position.id(), replacements));
+            }
+        }
+
+        for next in watch {
+            exchange.watch(next);
+        }
+        for (id, replacements) in rolls {
+            if let Some(next) = replacements
+                .values()
+                .find(|next| exchange.price(**next).is_none())
+            {
+                tracing::warn!(
+                    "Roll of position {} postponed, {} has no price yet.",
+                    id,
+                    next
+                );
+                continue;
+            }
+            let position = exchange
+                .position(id)
+                .expect("rolled position is open")
+                .clone();
+            let mut rolled = Position::default().leveraged(position.leverage());
+            for (key, value) in position.tags() {
+                rolled.set_tag(key.clone(), value);
+            }
+            rolled.set_tag("rolled_from", id);
+            for (&symbol, &size) in &position.next_size.0 {
+                *rolled.size(*replacements.get(&symbol).unwrap_or(&symbol)) = size;
+            }
+            match exchange.open(rolled) {
+                Ok(rolled) => {
+                    tracing::info!("Rolled position {} into position {}.", id, rolled);
+                    if let Some(position) = exchange.position_mut(id) {
+                        position.close();
+                    }
+                }
+                Err(err) => tracing::warn!("Position {} could not be rolled: {}", id, err),
+            }
+        }
+    }
+}
+
+impl<A: Api, S: Strategy<A>> Strategy<A> for AutoRoll<A, S> {
+    const NAME: &'static str = S::NAME;
+    type Parameters = S::Parameters;
+
+    fn parameters(&self) -> Option<S::Parameters> {
+        self.strategy.parameters()
+    }
+
+    fn init(&mut self, exchange: &mut Exchange<A>) -> Result<Settings, AnyError> {
+        self.strategy.init(exchange)
+    }
+
+    fn before_execute(&mut self, orders: &mut Vec<Order>) {
+        self.strategy.before_execute(orders)
+    }
+
+    fn save_state(&self) -> Option<Value> {
+        self.strategy.save_state()
+    }
+
+    fn load_state(&mut self, state: Value) -> Result<(), AnyError> {
+        self.strategy.load_state(state)
+    }
+
+    fn eval(&mut self, exchange: &mut Exchange<A>) -> Result<(), AnyError> {
+        self.strategy.eval(exchange)?;
+        self.roll(exchange);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        apis::Mock,
+        testing::{Script, StrategyTester},
+        Interval, MarketInfo, MarketType, Side,
+    };
+    use chrono::{NaiveDate, TimeZone};
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+
+    // Opens a long position in the near contract once.
+    struct Hold {
+        near: Symbol,
+        opened: bool,
+    }
+
+    impl Strategy<Mock<Script>> for Hold {
+        const NAME: &'static str = "Hold";
+        type Parameters = ();
+
+        fn init(&mut self, exchange: &mut Exchange<Mock<Script>>) -> Result<Settings, AnyError> {
+            exchange.watch(self.near);
+            Ok(Settings {
+                interval: Interval::Fixed(Duration::hours(1)),
+                ..Default::default()
+            })
+        }
+
+        fn eval(&mut self, exchange: &mut Exchange<Mock<Script>>) -> Result<(), AnyError> {
+            if !self.opened {
+                exchange.open(
+                    Position::default()
+                        .long(self.near, dec!(1))
+                        .with_tag("leg", "carry"),
+                )?;
+                self.opened = true;
+            }
+            Ok(())
+        }
+    }
+
+    fn future(symbol: Symbol, expiry: DateTime<Utc>) -> MarketInfo {
+        MarketInfo {
+            symbol,
+            min_size: Decimal::ZERO,
+            size_increment: Decimal::ZERO,
+            price_increment: Decimal::ZERO,
+            daily_quote_volume: Decimal::ZERO,
+            market_type: MarketType::Future,
+            contract_multiplier: Decimal::ONE,
+            expiry: Some(expiry),
+        }
+    }
+
+    #[tokio::test]
+    async fn roll_before_expiry() {
+        let date = |month, day| NaiveDate::from_ymd_opt(2022, month, day).unwrap();
+        let (near, next, far) = (
+            Symbol::future("BTC", date(1, 1)),
+            Symbol::future("BTC", date(3, 25)),
+            Symbol::future("BTC", date(6, 24)),
+        );
+        let expiry = Utc.with_ymd_and_hms(2022, 1, 1, 5, 0, 0).unwrap();
+        let strategy = AutoRoll::new(Hold {
+            near,
+            opened: false,
+        })
+        .before(Duration::hours(2));
+        let mut tester = StrategyTester::new(strategy)
+            .with_market(future(near, expiry))
+            .with_market(future(next, expiry + Duration::days(83)))
+            .with_market(future(far, expiry + Duration::days(174)))
+            .with_closes(near, [dec!(100)])
+            .with_closes(next, [dec!(105)])
+            .with_closes(far, [dec!(110)]);
+
+        tester.step().await.unwrap();
+        tester.assert_ordered(near, Side::Buy, dec!(1));
+        while tester.size(next).is_zero() {
+            tester.step().await.unwrap();
+        }
+
+        // The next contract is watched ahead, so it is priced when the roll is due.
+        let roll = tester.orders().next().unwrap().time;
+        assert_eq!(roll, expiry - Duration::hours(2));
+        tester.assert_ordered(near, Side::Sell, dec!(1));
+        tester.assert_ordered(next, Side::Buy, dec!(1));
+        tester.assert_size(near, dec!(0));
+        tester.assert_size(next, dec!(1));
+        let position = tester.exchange().positions().next().unwrap();
+        assert_eq!(position.tag("leg"), Some("carry"));
+        assert!(position.tag("rolled_from").is_some());
+    }
+}
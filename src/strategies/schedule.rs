@@ -0,0 +1,162 @@
+use std::marker::PhantomData;
+
+use crate::{strategies::Settings, AnyError, Api, Calendar, Exchange, Order, Strategy};
+use chrono::Duration;
+use serde_json::Value;
+
+/// Wraps a strategy and only lets it enter positions during the sessions of a calendar,
+/// e.g. to avoid weekends or hours with little liquidity.
+/// Unlike `Settings::calendar`, steps outside of the sessions still run, so positions can be
+/// managed and closed. Positions can also be flattened before the session closes.
+pub struct Schedule<A: Api, S: Strategy<A>> {
+    _api: PhantomData<A>,
+    strategy: S,
+    calendar: Calendar,
+    flatten_before: Option<Duration>,
+}
+
+impl<A: Api, S: Strategy<A>> Schedule<A, S> {
+    pub fn new(strategy: S, calendar: Calendar) -> Self {
+        Schedule {
+            _api: PhantomData,
+            strategy,
+            calendar,
+            flatten_before: None,
+        }
+    }
+
+    /// Close all positions the duration before the session closes, and keep them closed
+    /// until the next session opens.
+    pub fn flatten_before(mut self, before: Duration) -> Self {
+        assert!(before >= Duration::zero());
+        self.flatten_before = Some(before);
+        self
+    }
+}
+
+impl<A: Api, S: Strategy<A>> Strategy<A> for Schedule<A, S> {
+    const NAME: &'static str = S::NAME;
+    type Parameters = S::Parameters;
+
+    fn parameters(&self) -> Option<S::Parameters> {
+        self.strategy.parameters()
+    }
+
+    fn init(&mut self, exchange: &mut Exchange<A>) -> Result<Settings, AnyError> {
+        self.strategy.init(exchange)
+    }
+
+    fn before_execute(&mut self, orders: &mut Vec<Order>) {
+        self.strategy.before_execute(orders)
+    }
+
+    fn save_state(&self) -> Option<Value> {
+        self.strategy.save_state()
+    }
+
+    fn load_state(&mut self, state: Value) -> Result<(), AnyError> {
+        self.strategy.load_state(state)
+    }
+
+    fn eval(&mut self, exchange: &mut Exchange<A>) -> Result<(), AnyError> {
+        self.strategy.eval(exchange)?;
+
+        let time = exchange.current_time();
+        let flatten = match (self.flatten_before, self.calendar.next_close(time)) {
+            (Some(before), Some(close)) => close - time <= before,
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+        if !flatten && self.calendar.is_open(time) {
+            return Ok(());
+        }
+
+        let mut positions = exchange.swap_positions(Vec::new());
+        positions.retain(|position| {
+            let entry = position.open.is_none();
+            if entry {
+                tracing::info!(
+                    "Position {} dropped, entries are outside of the schedule.",
+                    position.id()
+                );
+            }
+            !entry
+        });
+        exchange.swap_positions(positions);
+
+        if flatten {
+            exchange.close_all();
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        apis::Mock,
+        testing::{Script, StrategyTester},
+        Interval, Position, Side, Symbol,
+    };
+    use chrono::{NaiveTime, TimeZone, Utc};
+    use rust_decimal_macros::dec;
+
+    // Enters a long position whenever it is flat.
+    struct AlwaysLong {
+        market: Symbol,
+    }
+
+    impl Strategy<Mock<Script>> for AlwaysLong {
+        const NAME: &'static str = "AlwaysLong";
+        type Parameters = ();
+
+        fn init(&mut self, exchange: &mut Exchange<Mock<Script>>) -> Result<Settings, AnyError> {
+            exchange.watch(self.market);
+            Ok(Settings {
+                interval: Interval::Fixed(Duration::hours(1)),
+                ..Default::default()
+            })
+        }
+
+        fn eval(&mut self, exchange: &mut Exchange<Mock<Script>>) -> Result<(), AnyError> {
+            if exchange.positions().next().is_none() {
+                exchange.open(Position::default().long(self.market, dec!(1)))?;
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn entries_within_sessions() {
+        let market = Symbol::perp("BTC");
+        let calendar = Calendar::weekdays(
+            NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+            NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+        );
+        let strategy =
+            Schedule::new(AlwaysLong { market }, calendar).flatten_before(Duration::hours(1));
+        let mut tester = StrategyTester::new(strategy).with_closes(market, [dec!(100)]);
+
+        // 2022-01-01 was a saturday, nothing is entered over the weekend.
+        tester.step().await.unwrap();
+        while tester.size(market).is_zero() {
+            tester.assert_no_orders();
+            tester.step().await.unwrap();
+        }
+        let entry = tester.orders().next().unwrap().time;
+        assert_eq!(entry, Utc.with_ymd_and_hms(2022, 1, 3, 9, 0, 0).unwrap());
+
+        // Flattened an hour before the close, without entering again.
+        while !tester.size(market).is_zero() {
+            tester.step().await.unwrap();
+        }
+        let exit = tester.orders().next().unwrap().time;
+        assert_eq!(exit, Utc.with_ymd_and_hms(2022, 1, 3, 16, 0, 0).unwrap());
+        tester.assert_ordered(market, Side::Sell, dec!(1));
+        tester.assert_ordered(market, Side::Buy, dec!(0));
+        tester.step().await.unwrap();
+        tester.assert_no_orders();
+    }
+}
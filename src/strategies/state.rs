@@ -0,0 +1,87 @@
+use crate::{apis::Api, AnyError};
+use serde_json::Value;
+use std::{fs, io, path::PathBuf};
+
+/// Where the state of a strategy is saved, see `Strategy::save_state`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum StateStorage {
+    /// The state is not saved.
+    #[default]
+    None,
+    /// Save the state through the API, the Monitor stores it in its database by strategy name.
+    Api,
+    /// Save the state as JSON in a local file.
+    File(PathBuf),
+}
+
+impl StateStorage {
+    pub(crate) async fn load<A: Api>(
+        &self,
+        api: &A,
+        name: &str,
+    ) -> Result<Option<Value>, AnyError> {
+        let json = match self {
+            StateStorage::None => return Ok(None),
+            StateStorage::Api => match api.load_state(name).await? {
+                Some(json) => json,
+                None => return Ok(None),
+            },
+            StateStorage::File(path) => match fs::read_to_string(path) {
+                Ok(json) => json,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+                Err(err) => return Err(err.into()),
+            },
+        };
+
+        Ok(Some(serde_json::from_str(&json)?))
+    }
+
+    pub(crate) async fn save<A: Api>(
+        &self,
+        api: &A,
+        name: &str,
+        state: &Value,
+    ) -> Result<(), AnyError> {
+        match self {
+            StateStorage::None => {}
+            StateStorage::Api => api.save_state(name, state.to_string()).await?,
+            StateStorage::File(path) => {
+                // Replace the file at once, so a crash never leaves a partially written state.
+                let temp = path.with_extension("tmp");
+                fs::write(&temp, state.to_string())?;
+                fs::rename(temp, path)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        apis::{Mock, MockSettings},
+        Candle, CandleKey,
+    };
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn file_storage() {
+        let api = Mock::new(MockSettings::new(|_key: CandleKey| -> Candle {
+            unreachable!()
+        }));
+        let path = std::env::temp_dir().join(format!("bazaar-state-{}.json", uuid::Uuid::new_v4()));
+        let storage = StateStorage::File(path.clone());
+
+        assert_eq!(storage.load(&api, "Strategy").await.unwrap(), None);
+
+        let state = json!({ "regime": "trending", "closes": [1, 2, 3] });
+        storage.save(&api, "Strategy", &state).await.unwrap();
+        let loaded = storage.load(&api, "Strategy").await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded, Some(state));
+        assert!(StateStorage::Api.load(&api, "Strategy").await.is_err());
+    }
+}
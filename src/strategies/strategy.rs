@@ -1,6 +1,13 @@
 use chrono::Duration;
 
-use crate::{apis::Api, AnyError, Exchange};
+use super::StateStorage;
+use crate::{
+    apis::Api, AccountMode, AdaptiveExecution, AnyError, Calendar, Direction, DustPolicy, Exchange,
+    ExecutionAlgo, HealthChecks, Interval, Netting, Order, PrecisionPolicy, PriceSource,
+    Reconciliation, VwapAnchor,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
 
 /// This trait needs to be implemented by your strategy.
 pub trait Strategy<A>
@@ -8,24 +15,120 @@ where
     A: Api,
 {
     const NAME: &'static str;
+    /// The hyperparameters of the strategy, e.g. periods and thresholds, `()` if there are none.
+    type Parameters: Serialize + DeserializeOwned;
+    /// The parameters the strategy runs with. They are recorded in the report and
+    /// the monitor session, so a run can be compared and repeated later, see `Report::parameters`.
+    fn parameters(&self) -> Option<Self::Parameters> {
+        None
+    }
     /// This method is called once at the start of the strategy.
     fn init(&mut self, manager: &mut Exchange<A>) -> Result<Settings, AnyError>;
     /// This method is called after each interval.
     fn eval(&mut self, manager: &mut Exchange<A>) -> Result<(), AnyError>;
+    /// This method is called with the coalesced orders of all positions right before they are placed.
+    /// Orders can be removed, reduced or re-priced, but not added or reversed, such orders are dropped.
+    /// Positions whose orders are removed stay unchanged and are ordered again in the next step.
+    #[allow(clippy::ptr_arg)]
+    fn before_execute(&mut self, _orders: &mut Vec<Order>) {}
+    /// The state to keep across restarts, e.g. rolling series or regime flags.
+    /// It is saved as configured by `Settings::state`, periodically and when the strategy stops.
+    fn save_state(&self) -> Option<Value> {
+        None
+    }
+    /// Restore the state saved before, called once after `init` if a saved state is found.
+    fn load_state(&mut self, _state: Value) -> Result<(), AnyError> {
+        Ok(())
+    }
 }
 
 pub struct Settings {
     /// Specifies the interval on which to trade on.
-    pub interval: Duration,
+    pub interval: Interval,
     /// Specifies how errors caused by the strategy should be handled,
     pub on_error: OnError,
+    /// Specifies how to continue when trading live and steps fall behind the interval.
+    pub on_overrun: OnOverrun,
+    /// Specifies how small balances of other assets than the quote asset are handled.
+    pub dust: DustPolicy,
+    /// Specifies the trading hours of the venue, steps outside of them are skipped.
+    pub calendar: Option<Calendar>,
+    /// Specifies how long to wait after a candle closes before requesting it,
+    /// allowing for clock differences with the venue.
+    pub clock_skew: Duration,
+    /// Specifies where the state of the strategy is saved, to resume it after a restart.
+    pub state: StateStorage,
+    /// Specifies how often the state of the strategy is saved.
+    pub save_state_every: Duration,
+    /// Specifies whether small orders are held back to net them with later adjustments.
+    pub netting: Option<Netting>,
+    /// Specifies whether orders are placed passively or as market orders depending on how
+    /// passive orders were filled recently.
+    pub execution: Option<AdaptiveExecution>,
+    /// Specifies the directions positions may be opened in.
+    pub direction: Direction,
+    /// Specifies how positions in a forbidden direction are handled.
+    pub on_forbidden_direction: OnForbiddenDirection,
+    /// Specifies the price positions are valued at, e.g. the mark price the venue liquidates at.
+    /// Falls back to the close if the API does not provide the price.
+    pub price_source: PriceSource,
+    /// Specifies how often the market metadata is refreshed when trading live.
+    /// Backtests load it once, unless the strategy calls `Exchange::refresh_markets`.
+    pub markets_refresh: Duration,
+    /// Specifies whether the venue nets positions per symbol or holds long and short legs.
+    pub account_mode: AccountMode,
+    /// Specifies the time `Exchange::vwap` is accumulated from.
+    pub vwap_anchor: VwapAnchor,
+    /// Specifies how long to wait for the fills of orders the venue did not settle immediately.
+    /// Positions are resized by the fill confirmed until then.
+    pub fill_timeout: Duration,
+    /// Specifies how often the status of unsettled orders is queried.
+    pub fill_poll: Duration,
+    /// Specifies how the orders of positions are split over steps.
+    pub algo: ExecutionAlgo,
+    /// How quantities are rounded, the residuals are added to the report.
+    pub precision: PrecisionPolicy,
+    /// Seeds the ids of orders and positions, so identical backtests produce identical reports.
+    /// Ids are random if None.
+    pub seed: Option<u64>,
+    /// Specifies when markets without recent candles are stale and how trading is protected.
+    pub health: HealthChecks,
+    /// Specifies whether only the candle of the current step is released, even if the API returned
+    /// newer ones. Markets whose next candle is newer have no candle in the step, instead of seeing
+    /// the newer candle. Debug builds also assert that no candle is released after its step.
+    pub look_ahead_guard: bool,
+    /// Specifies how the wallet booked locally is reconciled with the wallet of the venue
+    /// when trading live. Never if None.
+    pub reconciliation: Option<Reconciliation>,
 }
 
 impl Default for Settings {
     fn default() -> Self {
         Settings {
-            interval: Duration::minutes(1),
+            interval: Interval::Fixed(Duration::minutes(1)),
             on_error: OnError::ExitAllPositionsAndReturn,
+            on_overrun: OnOverrun::CatchUp,
+            dust: DustPolicy::default(),
+            calendar: None,
+            clock_skew: Duration::zero(),
+            state: StateStorage::None,
+            save_state_every: Duration::hours(1),
+            netting: None,
+            execution: None,
+            direction: Direction::Both,
+            on_forbidden_direction: OnForbiddenDirection::Reject,
+            price_source: PriceSource::Close,
+            markets_refresh: Duration::hours(1),
+            account_mode: AccountMode::Netting,
+            vwap_anchor: VwapAnchor::Day,
+            fill_timeout: Duration::seconds(30),
+            fill_poll: Duration::seconds(1),
+            algo: ExecutionAlgo::Immediate,
+            precision: PrecisionPolicy::default(),
+            seed: None,
+            health: HealthChecks::default(),
+            look_ahead_guard: false,
+            reconciliation: None,
         }
     }
 }
@@ -39,3 +142,23 @@ pub enum OnError {
     /// If an error occurs, exit all positions and return the error.
     ExitAllPositionsAndResume,
 }
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OnOverrun {
+    /// Evaluate the strategy for every missed step, one after another.
+    CatchUp,
+    /// Skip the missed steps and continue with the latest one.
+    Skip,
+    /// Skip evaluating the missed steps, but still use their candles
+    /// to update spread and risk estimates.
+    FastForward,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OnForbiddenDirection {
+    /// Reject opening the position.
+    Reject,
+    /// Drop the symbols of the position held in a forbidden direction,
+    /// rejecting the position if no symbol is left.
+    Clamp,
+}
@@ -0,0 +1,361 @@
+//! A harness to test strategies step by step on scripted candles.
+
+use crate::{
+    apis::{CandleGen, Margin, Mock, MockSettings},
+    strategies::{Settings, Strategy},
+    AnyError, Asset, Candle, CandleKey, Exchange, MarketInfo, MarketType, Order, OrderInfo, Side,
+    Symbol, Wallet,
+};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use rust_decimal::prelude::*;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Mutex;
+
+/// Candles scripted per symbol, the nth step gets the nth candle of every symbol.
+/// Once the script of a symbol ends, its last candle is repeated.
+#[derive(Default)]
+pub struct Script {
+    candles: HashMap<Symbol, Vec<Candle>>,
+    // The times candles were requested at, to know the step of a request.
+    times: Mutex<BTreeSet<DateTime<Utc>>>,
+}
+
+impl CandleGen for Script {
+    fn candle(&self, key: CandleKey) -> Candle {
+        let step = {
+            let mut times = self.times.lock().unwrap();
+            times.insert(key.time);
+            times.range(..key.time).count()
+        };
+        let candles = self
+            .candles
+            .get(&key.market)
+            .unwrap_or_else(|| panic!("No candles scripted for {}.", key.market));
+        *candles
+            .get(step)
+            .or_else(|| candles.last())
+            .unwrap_or_else(|| panic!("No candles scripted for {}.", key.market))
+    }
+}
+
+/// Runs a strategy step by step on scripted candles, to assert on its orders, positions
+/// and wallet after each step. Orders are filled completely at the close.
+///
+/// ```ignore
+/// let mut tester = StrategyTester::new(MyStrategy::default())
+///     .with_closes(btc, [dec!(100), dec!(110)]);
+/// tester.step().await?;
+/// tester.assert_no_orders();
+/// tester.step().await?;
+/// tester.assert_ordered(btc, Side::Buy, dec!(1));
+/// ```
+pub struct StrategyTester<S>
+where
+    S: Strategy<Mock<Script>>,
+{
+    strategy: S,
+    script: Script,
+    markets: HashMap<Symbol, MarketInfo>,
+    capital: Decimal,
+    fee: Decimal,
+    margin: Option<Margin>,
+    start_time: DateTime<Utc>,
+    // The exchange and the settings of the strategy, once initialized by the first step.
+    exchange: Option<(Exchange<Mock<Script>>, Settings)>,
+    // The orders submitted in the last step with their fills.
+    fills: Vec<(Order, OrderInfo)>,
+}
+
+impl<S> StrategyTester<S>
+where
+    S: Strategy<Mock<Script>>,
+{
+    /// A tester with a capital of 10000 USD and no fees, starting on 2022-01-01.
+    pub fn new(strategy: S) -> Self {
+        StrategyTester {
+            strategy,
+            script: Script::default(),
+            markets: HashMap::new(),
+            capital: Decimal::from(10_000),
+            fee: Decimal::ZERO,
+            margin: None,
+            start_time: Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap(),
+            exchange: None,
+            fills: Vec::new(),
+        }
+    }
+
+    /// Script the candles of a symbol, one per step.
+    pub fn with_candles(
+        mut self,
+        symbol: Symbol,
+        candles: impl IntoIterator<Item = Candle>,
+    ) -> Self {
+        self.script
+            .candles
+            .entry(symbol)
+            .or_default()
+            .extend(candles);
+        self
+    }
+
+    /// Script the closes of a symbol, one per step, as candles without range and volume.
+    pub fn with_closes(self, symbol: Symbol, closes: impl IntoIterator<Item = Decimal>) -> Self {
+        self.with_candles(
+            symbol,
+            closes.into_iter().map(|close| Candle {
+                open: close,
+                high: close,
+                low: close,
+                close,
+                volume: Decimal::ZERO,
+            }),
+        )
+    }
+
+    /// Set the constraints of a market, scripted markets without are unconstrained.
+    pub fn with_market(mut self, market: MarketInfo) -> Self {
+        self.markets.insert(market.symbol, market);
+        self
+    }
+
+    /// Set the capital in the quote asset.
+    pub fn with_capital(mut self, capital: Decimal) -> Self {
+        self.capital = capital;
+        self
+    }
+
+    /// Set the fee charged on fills.
+    pub fn with_fee(mut self, fee: Decimal) -> Self {
+        self.fee = fee;
+        self
+    }
+
+    /// Set the margin requirements, positions are not liquidated without.
+    pub fn with_margin(mut self, margin: Margin) -> Self {
+        self.margin = Some(margin);
+        self
+    }
+
+    pub fn with_start_time(mut self, start_time: DateTime<Utc>) -> Self {
+        self.start_time = start_time;
+        self
+    }
+
+    // Initialize the strategy on an exchange with the scripted candles.
+    async fn init(&mut self) -> Result<(), AnyError> {
+        let mut markets = std::mem::take(&mut self.markets);
+        for &symbol in self.script.candles.keys() {
+            markets.entry(symbol).or_insert(MarketInfo {
+                symbol,
+                min_size: Decimal::ZERO,
+                size_increment: Decimal::ZERO,
+                price_increment: Decimal::ZERO,
+                daily_quote_volume: Decimal::ZERO,
+                market_type: MarketType::Perpetual,
+                contract_multiplier: Decimal::ONE,
+                expiry: None,
+            });
+        }
+        let mut settings = MockSettings::new(std::mem::take(&mut self.script));
+        settings.fee = self.fee;
+        settings.margin = self.margin.clone();
+        settings.markets = markets.into_values().collect();
+        settings.wallet = Wallet::new();
+        settings.wallet.deposit(self.capital, Asset::new("USD"));
+
+        let mut exchange = Exchange::new(Mock::new(settings), self.start_time);
+        let settings = exchange.init(&mut self.strategy).await?;
+        self.exchange = Some((exchange, settings));
+        Ok(())
+    }
+
+    /// Run a step, initializing the strategy first if it is the first one.
+    pub async fn step(&mut self) -> Result<(), AnyError> {
+        if self.exchange.is_none() {
+            self.init().await?;
+        }
+        let (exchange, settings) = self.exchange.as_mut().expect("initialized");
+        self.fills = exchange
+            .run_step(&mut self.strategy, settings, &mut Duration::zero())
+            .await?;
+        Ok(())
+    }
+
+    /// Run a number of steps.
+    pub async fn run(&mut self, steps: usize) -> Result<(), AnyError> {
+        for _ in 0..steps {
+            self.step().await?;
+        }
+        Ok(())
+    }
+
+    /// The exchange the strategy runs on, available after the first step.
+    pub fn exchange(&self) -> &Exchange<Mock<Script>> {
+        &self
+            .exchange
+            .as_ref()
+            .expect("the strategy is initialized by the first step")
+            .0
+    }
+
+    pub fn strategy(&self) -> &S {
+        &self.strategy
+    }
+
+    pub fn strategy_mut(&mut self) -> &mut S {
+        &mut self.strategy
+    }
+
+    /// The orders submitted in the last step.
+    pub fn orders(&self) -> impl Iterator<Item = &Order> {
+        self.fills.iter().map(|(order, _)| order)
+    }
+
+    /// The orders submitted in the last step with their fills.
+    pub fn fills(&self) -> &[(Order, OrderInfo)] {
+        &self.fills
+    }
+
+    /// The size of a symbol summed over all open positions, negative for shorts.
+    pub fn size(&self, symbol: Symbol) -> Decimal {
+        self.exchange()
+            .positions()
+            .filter_map(|position| position.current.bundle.0.get(&symbol))
+            .sum()
+    }
+
+    /// The change of the quote balance since the start, e.g. by the value put into positions,
+    /// their profits and fees.
+    pub fn balance_change(&self) -> Decimal {
+        let exchange = self.exchange();
+        exchange.wallet().total(Asset::new("USD")) - self.capital
+    }
+
+    /// Assert that the last step ordered a size of a symbol on a side.
+    #[track_caller]
+    pub fn assert_ordered(&self, symbol: Symbol, side: Side, size: Decimal) {
+        let ordered: Decimal = self
+            .orders()
+            .filter(|order| order.market == symbol && order.side == side)
+            .map(|order| order.size)
+            .sum();
+        assert_eq!(
+            ordered, size,
+            "expected an order of {} {} on the {:?} side, the orders were {:?}",
+            size, symbol, side, self.fills
+        );
+    }
+
+    /// Assert that the last step submitted no orders.
+    #[track_caller]
+    pub fn assert_no_orders(&self) {
+        assert!(
+            self.fills.is_empty(),
+            "expected no orders, the orders were {:?}",
+            self.fills
+        );
+    }
+
+    /// Assert the size of a symbol summed over all open positions, negative for shorts.
+    #[track_caller]
+    pub fn assert_size(&self, symbol: Symbol, size: Decimal) {
+        assert_eq!(
+            self.size(symbol),
+            size,
+            "unexpected size of {} in the open positions",
+            symbol
+        );
+    }
+
+    /// Assert that no positions are open.
+    #[track_caller]
+    pub fn assert_flat(&self) {
+        let open = self.exchange().positions().count();
+        assert_eq!(open, 0, "expected no open positions, {} are open", open);
+    }
+
+    /// Assert the change of the quote balance since the start.
+    #[track_caller]
+    pub fn assert_balance_change(&self, change: Decimal) {
+        assert_eq!(
+            self.balance_change(),
+            change,
+            "unexpected change of the quote balance"
+        );
+    }
+
+    /// Assert the total value of the wallet and the open positions.
+    #[track_caller]
+    pub fn assert_total(&self, total: Decimal) {
+        assert_eq!(self.exchange().total(), total, "unexpected total value");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Interval, Position};
+    use rust_decimal_macros::dec;
+
+    // Goes long once the price rises and exits once it falls.
+    struct Momentum {
+        last: Option<Decimal>,
+    }
+
+    impl Strategy<Mock<Script>> for Momentum {
+        const NAME: &'static str = "Momentum";
+        type Parameters = ();
+
+        fn init(&mut self, exchange: &mut Exchange<Mock<Script>>) -> Result<Settings, AnyError> {
+            exchange.watch(Symbol::perp("BTC"));
+            Ok(Settings {
+                interval: Interval::Fixed(Duration::hours(1)),
+                ..Default::default()
+            })
+        }
+
+        fn eval(&mut self, exchange: &mut Exchange<Mock<Script>>) -> Result<(), AnyError> {
+            let btc = Symbol::perp("BTC");
+            let price = exchange.price(btc).unwrap();
+            match self.last.replace(price) {
+                Some(last) if price > last && exchange.positions().count() == 0 => {
+                    exchange.open(Position::default().long(btc, dec!(1)))?;
+                }
+                Some(last) if price < last => exchange.close_all(),
+                _ => {}
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_strategy_on_script() {
+        let btc = Symbol::perp("BTC");
+        let mut tester = StrategyTester::new(Momentum { last: None })
+            .with_closes(btc, [dec!(100), dec!(110), dec!(120), dec!(115)]);
+
+        tester.step().await.unwrap();
+        tester.assert_no_orders();
+        tester.assert_flat();
+
+        tester.step().await.unwrap();
+        tester.assert_ordered(btc, Side::Buy, dec!(1));
+        tester.assert_size(btc, dec!(1));
+        tester.assert_balance_change(dec!(-110));
+
+        tester.step().await.unwrap();
+        tester.assert_no_orders();
+        tester.assert_total(dec!(10010));
+
+        tester.step().await.unwrap();
+        tester.assert_ordered(btc, Side::Sell, dec!(1));
+        tester.assert_flat();
+        tester.assert_balance_change(dec!(5));
+
+        // The last candle repeats once the script ends.
+        tester.run(2).await.unwrap();
+        tester.assert_no_orders();
+        assert_eq!(tester.strategy().last, Some(dec!(115)));
+    }
+}
@@ -0,0 +1,116 @@
+use crate::Candle;
+use chrono::{DateTime, Utc};
+use rust_decimal::prelude::*;
+
+/// The time the volume-weighted average price is accumulated from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VwapAnchor {
+    /// Since the market was watched, i.e. usually since the start of the run.
+    Start,
+    /// Since midnight UTC, starting over every day.
+    #[default]
+    Day,
+}
+
+/// The volume-weighted average of the typical prices of the candles since the anchor.
+#[derive(Debug, Clone)]
+pub struct Vwap {
+    anchor: VwapAnchor,
+    since: Option<DateTime<Utc>>,
+    price_volume: Decimal,
+    volume: Decimal,
+}
+
+impl Vwap {
+    pub fn new(anchor: VwapAnchor) -> Self {
+        Vwap {
+            anchor,
+            since: None,
+            price_volume: Decimal::ZERO,
+            volume: Decimal::ZERO,
+        }
+    }
+
+    /// Add the candle starting at the time, starting over once the anchor is passed.
+    pub fn update(&mut self, time: DateTime<Utc>, candle: &Candle) {
+        let restart = match (self.anchor, self.since) {
+            (_, None) => true,
+            (VwapAnchor::Start, Some(_)) => false,
+            (VwapAnchor::Day, Some(since)) => since.date_naive() != time.date_naive(),
+        };
+        if restart {
+            self.since = Some(time);
+            self.price_volume = Decimal::ZERO;
+            self.volume = Decimal::ZERO;
+        }
+        self.price_volume += candle.typical_price() * candle.volume;
+        self.volume += candle.volume;
+    }
+
+    /// The time of the first candle since the anchor.
+    pub fn since(&self) -> Option<DateTime<Utc>> {
+        self.since
+    }
+
+    /// The volume traded since the anchor.
+    pub fn volume(&self) -> Decimal {
+        self.volume
+    }
+
+    /// The volume-weighted average price, None if nothing was traded since the anchor.
+    pub fn vwap(&self) -> Option<Decimal> {
+        if self.volume.is_zero() {
+            return None;
+        }
+        Some(self.price_volume / self.volume)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use rust_decimal_macros::dec;
+
+    fn candle(high: Decimal, low: Decimal, close: Decimal, volume: Decimal) -> Candle {
+        Candle {
+            open: close,
+            high,
+            low,
+            close,
+            volume,
+        }
+    }
+
+    #[test]
+    fn weighted_by_volume() {
+        let time = Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap();
+        let mut vwap = Vwap::new(VwapAnchor::Start);
+        assert_eq!(vwap.vwap(), None);
+        vwap.update(time, &candle(dec!(0), dec!(0), dec!(0), dec!(0)));
+        assert_eq!(vwap.vwap(), None);
+        vwap.update(time, &candle(dec!(12), dec!(9), dec!(9), dec!(1)));
+        assert_eq!(vwap.vwap(), Some(dec!(10)));
+        vwap.update(time, &candle(dec!(20), dec!(20), dec!(20), dec!(4)));
+        assert_eq!(vwap.vwap(), Some(dec!(18)));
+        assert_eq!(vwap.volume(), dec!(5));
+    }
+
+    #[test]
+    fn day_anchor_starts_over() {
+        let time = Utc.with_ymd_and_hms(2022, 1, 1, 23, 0, 0).unwrap();
+        let next_day = Utc.with_ymd_and_hms(2022, 1, 2, 0, 0, 0).unwrap();
+        let flat = |price| candle(price, price, price, dec!(1));
+
+        let mut day = Vwap::new(VwapAnchor::Day);
+        let mut start = Vwap::new(VwapAnchor::Start);
+        for vwap in [&mut day, &mut start] {
+            vwap.update(time, &flat(dec!(10)));
+            vwap.update(next_day, &flat(dec!(20)));
+        }
+        assert_eq!(day.vwap(), Some(dec!(20)));
+        assert_eq!(day.since(), Some(next_day));
+        assert_eq!(start.vwap(), Some(dec!(15)));
+        assert_eq!(start.since(), Some(time));
+    }
+}
@@ -1,4 +1,5 @@
 use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use thiserror::Error;
 
@@ -12,7 +13,45 @@ pub enum WalletError {
     NotEnoughReserved,
 }
 
-#[derive(Default, Debug, Clone)]
+/// How small balances of assets other than the quote asset are handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DustPolicy {
+    /// Balances worth less than this quote value are ignored in the valuation.
+    pub min_value: Decimal,
+    /// Convert dust to the quote asset, if the venue supports it.
+    pub consolidate: bool,
+}
+
+/// How much of the value of assets other than the quote asset backs positions, like the
+/// cross-collateral of FTX. Assets without a weight are no collateral, negative balances
+/// are borrowed and count fully against the collateral.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CollateralWeights {
+    pub weights: HashMap<Asset, Decimal>,
+}
+
+impl CollateralWeights {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Count this fraction of the value of the asset as collateral, e.g. 0.95 for BTC.
+    pub fn with_weight(mut self, asset: Asset, weight: Decimal) -> Self {
+        assert!(weight >= Decimal::ZERO && weight <= Decimal::ONE);
+        self.weights.insert(asset, weight);
+        self
+    }
+
+    pub fn weight(&self, asset: Asset) -> Decimal {
+        self.weights.get(&asset).cloned().unwrap_or_default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.weights.is_empty()
+    }
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct Wallet {
     pub(crate) total: HashMap<Asset, Decimal>,
     pub(crate) free: HashMap<Asset, Decimal>,
@@ -33,7 +72,7 @@ impl Wallet {
 
     pub fn deposit(&mut self, qty: Decimal, asset: Asset) {
         assert!(qty >= Decimal::ZERO);
-        log::debug!("Depositing {} {}", qty, asset);
+        tracing::debug!("Depositing {} {}", qty, asset);
         let mut total_qty = self.total.entry(asset).or_default();
         let mut free_qty = self.free.entry(asset).or_default();
         total_qty += qty;
@@ -43,7 +82,7 @@ impl Wallet {
     pub fn reserve(&mut self, qty: Decimal, asset: Asset) -> Result<(), WalletError> {
         assert!(qty >= Decimal::ZERO);
         let mut free_qty = self.free.entry(asset).or_default();
-        log::debug!("Reserving {} {}", qty, asset);
+        tracing::debug!("Reserving {} {}", qty, asset);
         if qty > *free_qty {
             return Err(WalletError::NotEnoughTotal);
         }
@@ -78,11 +117,71 @@ impl Wallet {
         self.total.get(&asset).cloned().unwrap_or(Decimal::ZERO)
     }
 
+    /// Value of the wallet in the quote asset, other assets are valued at their price
+    /// unless their price is unknown or they are dust.
+    pub fn value<F>(&self, quote: Asset, price: F, dust: &DustPolicy) -> Decimal
+    where
+        F: Fn(Asset) -> Option<Decimal>,
+    {
+        self.total(quote)
+            + self
+                .total
+                .iter()
+                .filter(|(&asset, _)| asset != quote)
+                .filter_map(|(&asset, &qty)| Some(qty * price(asset)?))
+                .filter(|value| value.abs() >= dust.min_value)
+                .sum::<Decimal>()
+    }
+
+    /// Value of the wallet that backs positions, in the quote asset. Other assets count with
+    /// their collateral weight, and not at all if their price is unknown.
+    pub fn collateral<F>(&self, quote: Asset, price: F, weights: &CollateralWeights) -> Decimal
+    where
+        F: Fn(Asset) -> Option<Decimal>,
+    {
+        self.total(quote)
+            + self
+                .total
+                .iter()
+                .filter(|(&asset, _)| asset != quote)
+                .filter_map(|(&asset, &qty)| Some((asset, qty * price(asset)?)))
+                .map(|(asset, value)| {
+                    if value < Decimal::ZERO {
+                        value
+                    } else {
+                        value * weights.weight(asset)
+                    }
+                })
+                .sum::<Decimal>()
+    }
+
+    // Change the balance of an asset by the signed quantity, borrowing it if the balance
+    // turns negative.
+    pub(crate) fn settle(&mut self, qty: Decimal, asset: Asset) {
+        tracing::debug!("Settling {} {}", qty, asset);
+        *self.total.entry(asset).or_default() += qty;
+        *self.free.entry(asset).or_default() += qty;
+    }
+
+    /// Assets other than the quote asset with a positive balance worth less than the dust threshold.
+    pub fn dust<F>(&self, quote: Asset, price: F, dust: &DustPolicy) -> Vec<(Asset, Decimal)>
+    where
+        F: Fn(Asset) -> Option<Decimal>,
+    {
+        self.total
+            .iter()
+            .filter(|(&asset, &qty)| asset != quote && qty > Decimal::ZERO)
+            .filter_map(|(&asset, &qty)| Some((asset, qty, qty * price(asset)?)))
+            .filter(|(_, _, value)| *value < dust.min_value)
+            .map(|(asset, qty, _)| (asset, qty))
+            .collect()
+    }
+
     /// Withdraw some quantity of an asset.
     /// Assumes that the quantity to be withdrawn was reserved beforehand.
     pub fn withdraw(&mut self, qty: Decimal, asset: Asset) -> Result<(), WalletError> {
         assert!(qty >= Decimal::ZERO);
-        log::debug!("Withdrawing {} {}", qty, asset);
+        tracing::debug!("Withdrawing {} {}", qty, asset);
         let mut total_qty = self.total.entry(asset).or_default();
         let free_qty = self.free.entry(asset).or_default();
         let reserved_qty = *total_qty - *free_qty;
@@ -108,4 +207,62 @@ mod tests {
         wallet.reserve(dec!(10), asset).unwrap();
         wallet.withdraw(dec!(10), asset).unwrap();
     }
+
+    #[test]
+    fn weighted_collateral() {
+        let mut wallet = Wallet::new();
+        let (usd, btc, eth, doge) = (
+            Asset::new("USD"),
+            Asset::new("BTC"),
+            Asset::new("ETH"),
+            Asset::new("DOGE"),
+        );
+        wallet.deposit(dec!(100), usd);
+        wallet.deposit(dec!(2), btc);
+        wallet.deposit(dec!(10), doge);
+        wallet.settle(dec!(-1), eth);
+        let weights = CollateralWeights::new().with_weight(btc, dec!(0.9));
+        let price = |asset| {
+            if asset == btc {
+                Some(dec!(1000))
+            } else if asset == eth {
+                Some(dec!(50))
+            } else {
+                None
+            }
+        };
+
+        // The borrowed ETH counts fully, DOGE without a price or weight not at all.
+        assert_eq!(wallet.collateral(usd, price, &weights), dec!(1850));
+        assert_eq!(
+            wallet.collateral(usd, price, &CollateralWeights::new()),
+            dec!(50)
+        );
+    }
+
+    #[test]
+    fn ignore_dust() {
+        let mut wallet = Wallet::new();
+        let (usd, btc, eth) = (Asset::new("USD"), Asset::new("BTC"), Asset::new("ETH"));
+        wallet.deposit(dec!(100), usd);
+        wallet.deposit(dec!(0.0001), btc);
+        wallet.deposit(dec!(1), eth);
+        let price = |asset| {
+            if asset == btc {
+                Some(dec!(20000))
+            } else if asset == eth {
+                Some(dec!(1000))
+            } else {
+                None
+            }
+        };
+        let policy = DustPolicy {
+            min_value: dec!(5),
+            consolidate: false,
+        };
+
+        assert_eq!(wallet.value(usd, price, &DustPolicy::default()), dec!(1102));
+        assert_eq!(wallet.value(usd, price, &policy), dec!(1100));
+        assert_eq!(wallet.dust(usd, price, &policy), vec![(btc, dec!(0.0001))]);
+    }
 }